@@ -0,0 +1,178 @@
+//! Throughput of the event application on synthetic workloads: streams of order
+//! events, reconstruction of a large book and mark price fan-out to positions.
+
+use std::hint::black_box;
+
+use criterion::{BatchSize, Criterion, Throughput, criterion_group, criterion_main};
+use dex_sdk::{
+    state::Exchange,
+    stream::RawBlockEvents,
+    testing::sim::{Action, Simulator},
+};
+
+/// Blocks applied in the order flow workload.
+const BLOCKS: usize = 100;
+
+/// Order actions per block in the order flow workload.
+const ACTIONS_PER_BLOCK: usize = 50;
+
+/// Orders in the reconstructed book.
+const BOOK_ORDERS: u64 = 10_000;
+
+/// Accounts with a position in the mark price fan-out workload.
+const POSITIONS: u32 = 5_000;
+
+/// Exchange state and the blocks to apply to it.
+fn workload(sim: &mut Simulator, exchange: &mut Exchange, setup: &[Vec<Action>]) {
+    for actions in setup {
+        exchange.apply_events(&sim.block(actions)).unwrap();
+    }
+}
+
+/// Deterministic mix of placements, fills, changes and cancellations, dominated
+/// by placements so the book keeps growing.
+fn order_flow(sim: &mut Simulator) -> Vec<RawBlockEvents> {
+    (0..BLOCKS)
+        .map(|block| {
+            let actions = (0..ACTIONS_PER_BLOCK)
+                .map(|i| {
+                    let n = block * ACTIONS_PER_BLOCK + i;
+                    match n % 10 {
+                        0..=5 => Action::Place {
+                            perpetual: n,
+                            account: n,
+                            is_bid: n.is_multiple_of(2),
+                            offset: (n % 97) as u64,
+                            lots: 1 + (n % 13) as u64,
+                        },
+                        6 | 7 => Action::Fill {
+                            order: n * 7,
+                            taker: n + 1,
+                            lots: 1 + (n % 5) as u64,
+                        },
+                        8 => Action::Modify {
+                            order: n * 3,
+                            offset: (n % 89) as u64,
+                            lots: 1 + (n % 11) as u64,
+                        },
+                        _ => Action::Cancel { order: n * 5 },
+                    }
+                })
+                .collect::<Vec<_>>();
+            sim.block(&actions)
+        })
+        .collect()
+}
+
+fn apply_order_flow(c: &mut Criterion) {
+    let mut sim = Simulator::new(&[1, 2, 3, 4], 100);
+    let exchange = sim.exchange();
+    let blocks = order_flow(&mut sim);
+    let events = blocks.iter().map(|b| b.events().len()).sum::<usize>();
+
+    let mut group = c.benchmark_group("apply_order_flow");
+    group.throughput(Throughput::Elements(events as u64));
+    group.bench_function(format!("{BLOCKS}x{ACTIONS_PER_BLOCK}"), |b| {
+        b.iter_batched(
+            || exchange.clone(),
+            |mut exchange| {
+                for block in &blocks {
+                    black_box(exchange.apply_events(block).unwrap());
+                }
+                exchange
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+fn book_reconstruction(c: &mut Criterion) {
+    let mut sim = Simulator::new(&[1], 100);
+    let exchange = sim.exchange();
+    let actions = (0..BOOK_ORDERS)
+        .map(|n| Action::Place {
+            perpetual: 0,
+            account: n as usize,
+            is_bid: n.is_multiple_of(2),
+            offset: n % 500,
+            lots: 1 + n % 7,
+        })
+        .collect::<Vec<_>>();
+    // Snapshot sized blocks, as applied when catching up from an empty book
+    let blocks = actions
+        .chunks(1_000)
+        .map(|chunk| sim.block(chunk))
+        .collect::<Vec<_>>();
+
+    let mut group = c.benchmark_group("book_reconstruction");
+    group.throughput(Throughput::Elements(BOOK_ORDERS));
+    group.sample_size(20);
+    group.bench_function(format!("{BOOK_ORDERS}_orders"), |b| {
+        b.iter_batched(
+            || exchange.clone(),
+            |mut exchange| {
+                for block in &blocks {
+                    black_box(exchange.apply_events(block).unwrap());
+                }
+                exchange
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+fn mark_fanout(c: &mut Criterion) {
+    let mut sim = Simulator::new(&[1, 2], POSITIONS);
+    let mut exchange = sim.exchange();
+    let opens = (0..POSITIONS as usize)
+        .map(|account| Action::Open {
+            perpetual: 0,
+            account,
+            is_long: account.is_multiple_of(2),
+            lots: 1 + (account % 10) as u64,
+        })
+        .collect::<Vec<_>>();
+    workload(&mut sim, &mut exchange, &[opens]);
+    let mark = sim.block(&[Action::Mark {
+        perpetual: 0,
+        price: 1_010,
+    }]);
+    let other_mark = sim.block(&[Action::Mark {
+        perpetual: 1,
+        price: 1_010,
+    }]);
+
+    let mut group = c.benchmark_group("mark_fanout");
+    group.throughput(Throughput::Elements(POSITIONS as u64));
+    group.bench_function(format!("{POSITIONS}_positions"), |b| {
+        b.iter_batched(
+            || exchange.clone(),
+            |mut exchange| {
+                black_box(exchange.apply_events(&mark).unwrap());
+                exchange
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    // Mark price of the perpetual contract no position is open in
+    group.bench_function("no_positions", |b| {
+        b.iter_batched(
+            || {
+                let mut exchange = exchange.clone();
+                exchange.apply_events(&mark).unwrap();
+                exchange
+            },
+            |mut exchange| {
+                black_box(exchange.apply_events(&other_mark).unwrap());
+                exchange
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, apply_order_flow, book_reconstruction, mark_fanout);
+criterion_main!(benches);
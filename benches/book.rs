@@ -0,0 +1,142 @@
+//! Latency of the hot-path order book queries, comparing the owned accessors
+//! with the borrowed `BookView` API.
+
+use std::hint::black_box;
+
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use dex_sdk::{
+    state::{Exchange, OrderBook},
+    testing::sim::{Action, Simulator},
+    types::OrderSide,
+};
+
+/// Price levels per side of the book.
+const LEVELS: u64 = 200;
+
+/// Orders per price level.
+const ORDERS_PER_LEVEL: usize = 10;
+
+/// Book of a single perpetual contract with `LEVELS` levels of `ORDERS_PER_LEVEL` orders
+/// on each side, placed in blocks of a hundred orders.
+fn deep_book() -> Exchange {
+    deep_book_sim().1
+}
+
+fn deep_book_sim() -> (Simulator, Exchange) {
+    let mut sim = Simulator::new(&[1], 10);
+    let mut exchange = sim.exchange();
+    let actions = (0..LEVELS)
+        .flat_map(|offset| {
+            (0..ORDERS_PER_LEVEL).flat_map(move |account| {
+                [true, false].map(|is_bid| Action::Place {
+                    perpetual: 0,
+                    account,
+                    is_bid,
+                    offset,
+                    lots: 1 + account as u64,
+                })
+            })
+        })
+        .collect::<Vec<_>>();
+    for block in actions.chunks(100) {
+        exchange.apply_events(&sim.block(block)).unwrap();
+    }
+    (sim, exchange)
+}
+
+fn book(exchange: &Exchange) -> &OrderBook {
+    exchange.perpetuals()[&1].l3_book()
+}
+
+fn top_of_book(c: &mut Criterion) {
+    let exchange = deep_book();
+    let book = book(&exchange);
+    let mut group = c.benchmark_group("top_of_book");
+    group.bench_function("best_bid", |b| b.iter(|| black_box(book).best_bid()));
+    group.bench_function("view_best_bid", |b| {
+        b.iter(|| {
+            black_box(book)
+                .view()
+                .best_bid()
+                .map(|l| (l.price(), l.size()))
+        })
+    });
+    group.bench_function("top_bids_10", |b| {
+        b.iter(|| black_box(book).top_bids(10).len())
+    });
+    group.bench_function("view_bids_10", |b| {
+        b.iter(|| {
+            black_box(book).view().bids().take(10).for_each(|l| {
+                black_box((l.price(), l.size()));
+            })
+        })
+    });
+    group.bench_function("view_spread", |b| {
+        b.iter(|| black_box(book).view().spread())
+    });
+    group.finish();
+}
+
+fn level_orders(c: &mut Criterion) {
+    let exchange = deep_book();
+    let book = book(&exchange);
+    let (price, _) = book.best_bid().unwrap();
+    let mut group = c.benchmark_group("level_orders");
+    group.bench_function("bid_orders_filtered", |b| {
+        b.iter(|| {
+            black_box(book)
+                .bid_orders()
+                .filter(|o| o.price() == price)
+                .collect::<Vec<_>>()
+                .len()
+        })
+    });
+    group.bench_function("view_level_orders", |b| {
+        b.iter(|| {
+            black_box(book)
+                .view()
+                .level(OrderSide::Bid, price)
+                .map(|l| l.orders().count())
+        })
+    });
+    group.finish();
+}
+
+fn order_churn(c: &mut Criterion) {
+    // Order lookups, unlinking and relinking dominate applying changes and
+    // cancellations to a deep book
+    let (mut sim, exchange) = deep_book_sim();
+    let actions = (0..500)
+        .map(|n| match n % 3 {
+            0 => Action::Modify {
+                order: n * 7,
+                offset: (n as u64) % LEVELS,
+                lots: 1 + (n as u64) % 5,
+            },
+            1 => Action::Cancel { order: n * 11 },
+            _ => Action::Place {
+                perpetual: 0,
+                account: n,
+                is_bid: n.is_multiple_of(4),
+                offset: (n as u64) % LEVELS,
+                lots: 1,
+            },
+        })
+        .collect::<Vec<_>>();
+    let block = sim.block(&actions);
+    let mut group = c.benchmark_group("order_churn");
+    group.bench_function("modify_cancel_place_500", |b| {
+        b.iter_batched(
+            || exchange.clone(),
+            |mut exchange| {
+                black_box(exchange.apply_events(&block).unwrap());
+                exchange
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, top_of_book, level_orders, order_churn);
+criterion_main!(benches);
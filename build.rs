@@ -3,5 +3,33 @@ use std::fs;
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let rev = fs::read_to_string("abi/dex/REVISION")?;
     println!("cargo::rustc-env=DEX_REVISION={rev}");
+
+    #[cfg(feature = "capi")]
+    generate_c_header()?;
+    Ok(())
+}
+
+/// Generates the C header into `OUT_DIR`, the checked-in `include/dex_sdk.h`
+/// is verified against it by the `capi` tests.
+#[cfg(feature = "capi")]
+fn generate_c_header() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo::rerun-if-changed=abi/dex/REVISION");
+    println!("cargo::rerun-if-changed=src/capi.rs");
+    let mut config = cbindgen::Config {
+        language: cbindgen::Language::C,
+        include_guard: Some("DEX_SDK_H".into()),
+        autogen_warning: Some("/* Generated from src/capi.rs by cbindgen, do not edit. */".into()),
+        cpp_compat: true,
+        usize_is_size_t: true,
+        style: cbindgen::Style::Type,
+        ..Default::default()
+    };
+    config.enumeration.prefix_with_name = true;
+    config.enumeration.rename_variants = cbindgen::RenameRule::ScreamingSnakeCase;
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src("src/capi.rs")
+        .generate()?
+        .write_to_file(std::path::Path::new(&std::env::var("OUT_DIR")?).join("dex_sdk.h"));
     Ok(())
 }
@@ -0,0 +1,178 @@
+//! Chain access: snapshot building and event streams.
+
+use std::time::Duration;
+
+use alloy::{
+    providers::{DynProvider, Provider, ProviderBuilder},
+    rpc::client::RpcClient,
+    transports::layers::RetryBackoffLayer,
+};
+use dex_sdk::{state::SnapshotBuilder, stream, types};
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+use crate::{convert, error, exchange::Exchange, stream::EventStream};
+
+/// Deployment of the exchange.
+#[pyclass(frozen, module = "dex_sdk")]
+pub struct Chain {
+    pub(crate) inner: dex_sdk::Chain,
+}
+
+#[pymethods]
+impl Chain {
+    /// Monad testnet deployment.
+    #[staticmethod]
+    fn testnet() -> Self {
+        Self {
+            inner: dex_sdk::Chain::testnet(),
+        }
+    }
+
+    /// Custom deployment, e.g. a local one.
+    #[staticmethod]
+    fn custom(
+        chain_id: u64,
+        collateral_token: &str,
+        deployed_at_block: u64,
+        exchange: &str,
+        perpetuals: Vec<types::PerpetualId>,
+    ) -> PyResult<Self> {
+        Ok(Self {
+            inner: dex_sdk::Chain::custom(
+                chain_id,
+                convert::address(collateral_token)?,
+                deployed_at_block,
+                convert::address(exchange)?,
+                perpetuals,
+            ),
+        })
+    }
+
+    #[getter]
+    fn chain_id(&self) -> u64 {
+        self.inner.chain_id()
+    }
+
+    #[getter]
+    fn exchange(&self) -> String {
+        self.inner.exchange().to_string()
+    }
+
+    #[getter]
+    fn collateral_token(&self) -> String {
+        self.inner.collateral_token().to_string()
+    }
+
+    #[getter]
+    fn deployed_at_block(&self) -> u64 {
+        self.inner.deployed_at_block()
+    }
+
+    #[getter]
+    fn perpetuals(&self) -> Vec<types::PerpetualId> {
+        self.inner.perpetuals().to_vec()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Chain(chain_id={}, exchange={})",
+            self.inner.chain_id(),
+            self.inner.exchange()
+        )
+    }
+}
+
+/// RPC connection to the exchange deployment.
+#[pyclass(frozen, module = "dex_sdk")]
+pub struct Client {
+    chain: dex_sdk::Chain,
+    provider: DynProvider,
+}
+
+#[pymethods]
+impl Client {
+    /// Connects to the HTTP RPC endpoint, retrying rate-limited requests.
+    #[new]
+    #[pyo3(signature = (rpc_url, chain, poll_interval_ms = 500))]
+    fn new(rpc_url: &str, chain: &Chain, poll_interval_ms: u64) -> PyResult<Self> {
+        let url = rpc_url
+            .parse()
+            .map_err(|err| PyValueError::new_err(format!("invalid RPC URL {rpc_url}: {err}")))?;
+        let client = RpcClient::builder()
+            .layer(RetryBackoffLayer::new(10, 100, 200))
+            .http(url);
+        client.set_poll_interval(Duration::from_millis(poll_interval_ms));
+        Ok(Self {
+            chain: chain.inner.clone(),
+            provider: ProviderBuilder::new().connect_client(client).erased(),
+        })
+    }
+
+    #[getter]
+    fn chain(&self) -> Chain {
+        Chain {
+            inner: self.chain.clone(),
+        }
+    }
+
+    /// Awaitable [`Exchange`] snapshot at the latest block, see `SnapshotBuilder`.
+    ///
+    /// Tracks the given accounts, and positions of all accounts if `all_positions` is set.
+    #[pyo3(signature = (accounts = None, perpetuals = None, all_positions = false))]
+    fn snapshot<'py>(
+        &self,
+        py: Python<'py>,
+        accounts: Option<Vec<String>>,
+        perpetuals: Option<Vec<types::PerpetualId>>,
+        all_positions: bool,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let mut builder = SnapshotBuilder::new(&self.chain, self.provider.clone());
+        if let Some(accounts) = accounts {
+            let accounts = accounts
+                .iter()
+                .map(|account| convert::address(account))
+                .collect::<PyResult<_>>()?;
+            builder = builder.with_accounts(accounts);
+        }
+        if let Some(perpetuals) = perpetuals {
+            builder = builder.with_perpetuals(perpetuals);
+        }
+        if all_positions {
+            builder = builder.with_all_positions();
+        }
+        pyo3_async_runtimes::tokio::future_into_py(py, async move {
+            builder
+                .build()
+                .await
+                .map(Exchange::new)
+                .map_err(error::to_py)
+        })
+    }
+
+    /// Async iterator of the raw block events, starting at the instant of
+    /// the [`Exchange`] snapshot or at the given block number.
+    ///
+    /// Up to `capacity` blocks are buffered ahead of the consumer, the stream
+    /// is paused until it catches up.
+    #[pyo3(signature = (start, capacity = 64))]
+    fn stream(&self, start: &Bound<'_, PyAny>, capacity: usize) -> PyResult<EventStream> {
+        if capacity == 0 {
+            return Err(PyValueError::new_err("capacity must be non-zero"));
+        }
+        let from = match start.extract::<PyRef<'_, Exchange>>() {
+            Ok(exchange) => exchange.instant(),
+            Err(_) => types::StateInstant::new(start.extract()?, 0),
+        };
+        let (tx, rx) = stream::channel(capacity, stream::Backpressure::Block);
+        let chain = self.chain.clone();
+        let provider = self.provider.clone();
+        pyo3_async_runtimes::tokio::get_runtime().spawn(async move {
+            stream::forward(stream::raw(&chain, provider, from, tokio::time::sleep), tx).await
+        });
+        Ok(EventStream::new(rx))
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Client(chain_id={})", self.chain.chain_id())
+    }
+}
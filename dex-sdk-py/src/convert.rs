@@ -0,0 +1,22 @@
+//! Conversions between SDK values and Python objects.
+
+use std::fmt::Display;
+
+use alloy::primitives::Address;
+use pyo3::{exceptions::PyValueError, prelude::*, sync::PyOnceLock, types::PyType};
+
+static DECIMAL: PyOnceLock<Py<PyType>> = PyOnceLock::new();
+
+/// Fixed-point number as an exact `decimal.Decimal`.
+pub(crate) fn decimal<'py>(py: Python<'py>, value: impl Display) -> PyResult<Bound<'py, PyAny>> {
+    DECIMAL
+        .import(py, "decimal", "Decimal")?
+        .call1((value.to_string(),))
+}
+
+/// Address from its hex string.
+pub(crate) fn address(value: &str) -> PyResult<Address> {
+    value
+        .parse()
+        .map_err(|err| PyValueError::new_err(format!("invalid address {value}: {err}")))
+}
@@ -0,0 +1,7 @@
+use pyo3::{PyErr, create_exception, exceptions::PyException};
+
+create_exception!(dex_sdk, DexError, PyException, "Error reported by the SDK.");
+
+pub(crate) fn to_py(err: dex_sdk::error::DexError) -> PyErr {
+    DexError::new_err(err.to_string())
+}
@@ -0,0 +1,283 @@
+//! Exchange state and its queries.
+//!
+//! Query results are plain dicts and lists with fixed-point numbers as `decimal.Decimal`,
+//! ready to be loaded into data frames.
+
+use std::sync::{Arc, Mutex};
+
+use dex_sdk::{
+    state::{self, ExchangeWriter, SharedExchange},
+    types,
+};
+use pyo3::{
+    exceptions::PyKeyError,
+    prelude::*,
+    types::{PyDict, PyList},
+};
+
+use crate::{convert::decimal, error, stream::BlockEvents};
+
+/// Exchange state snapshot, kept up to date by applying the streamed block events.
+///
+/// Queries read the latest applied state and can be made concurrently with applying.
+#[pyclass(frozen, module = "dex_sdk")]
+pub struct Exchange {
+    shared: SharedExchange,
+    writer: Mutex<ExchangeWriter>,
+}
+
+impl Exchange {
+    pub(crate) fn new(exchange: state::Exchange) -> Self {
+        let (shared, writer) = SharedExchange::new(exchange);
+        Self {
+            shared,
+            writer: Mutex::new(writer),
+        }
+    }
+
+    pub(crate) fn instant(&self) -> types::StateInstant {
+        self.shared.load().instant()
+    }
+}
+
+#[pymethods]
+impl Exchange {
+    #[getter]
+    fn block_number(&self) -> u64 {
+        self.instant().block_number()
+    }
+
+    #[getter]
+    fn timestamp(&self) -> u64 {
+        self.instant().block_timestamp()
+    }
+
+    /// Applies the block events, returning `False` if the block was already applied.
+    ///
+    /// Releases the GIL while applying.
+    fn apply(&self, py: Python<'_>, block: &BlockEvents) -> PyResult<bool> {
+        let events = block.inner.clone();
+        py.detach(|| {
+            self.writer
+                .lock()
+                .expect("writer lock")
+                .apply_events(&events)
+        })
+        .map(|state_events| state_events.is_some())
+        .map_err(error::to_py)
+    }
+
+    /// Hex hash of the state, equal for identical states.
+    fn state_hash(&self) -> String {
+        self.shared.load().state_hash().to_string()
+    }
+
+    /// IDs of the tracked perpetual contracts, in ascending order.
+    fn perpetual_ids(&self) -> Vec<types::PerpetualId> {
+        let mut ids = self
+            .shared
+            .load()
+            .perpetuals()
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Parameters and market data of the perpetual contract.
+    fn perpetual<'py>(
+        &self,
+        py: Python<'py>,
+        perpetual_id: types::PerpetualId,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let exchange = self.shared.load();
+        let perp = perpetual(&exchange, perpetual_id)?;
+        let dict = PyDict::new(py);
+        dict.set_item("id", perp.id())?;
+        dict.set_item("name", perp.name())?;
+        dict.set_item("symbol", perp.symbol())?;
+        dict.set_item("is_paused", perp.is_paused())?;
+        dict.set_item("mark_price", decimal(py, perp.mark_price())?)?;
+        dict.set_item("oracle_price", decimal(py, perp.oracle_price())?)?;
+        dict.set_item("last_price", decimal(py, perp.last_price())?)?;
+        dict.set_item("funding_rate", decimal(py, perp.funding_rate())?)?;
+        dict.set_item("open_interest", decimal(py, perp.open_interest())?)?;
+        dict.set_item("maker_fee", decimal(py, perp.maker_fee())?)?;
+        dict.set_item("taker_fee", decimal(py, perp.taker_fee())?)?;
+        dict.set_item("initial_margin", decimal(py, perp.initial_margin())?)?;
+        dict.set_item(
+            "maintenance_margin",
+            decimal(py, perp.maintenance_margin())?,
+        )?;
+        dict.set_item("tick_size", decimal(py, perp.tick_size())?)?;
+        dict.set_item("lot_size", decimal(py, perp.lot_size())?)?;
+        dict.set_item("total_orders", perp.total_orders())?;
+        Ok(dict)
+    }
+
+    /// Aggregated price levels of the order book, best first:
+    /// `{"bids": [(price, size, num_orders), ...], "asks": [...]}`.
+    #[pyo3(signature = (perpetual_id, depth = 10))]
+    fn book<'py>(
+        &self,
+        py: Python<'py>,
+        perpetual_id: types::PerpetualId,
+        depth: usize,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let exchange = self.shared.load();
+        let view = perpetual(&exchange, perpetual_id)?.l3_book().view();
+        let levels = |levels: &mut dyn Iterator<Item = state::LevelView<'_>>| {
+            levels
+                .take(depth)
+                .map(|level| {
+                    Ok((
+                        decimal(py, level.price())?,
+                        decimal(py, level.size())?,
+                        level.num_orders(),
+                    ))
+                })
+                .collect::<PyResult<Vec<_>>>()
+        };
+        let dict = PyDict::new(py);
+        dict.set_item("bids", levels(&mut view.bids())?)?;
+        dict.set_item("asks", levels(&mut view.asks())?)?;
+        Ok(dict)
+    }
+
+    /// Resting orders of the perpetual contract, in ascending order ID.
+    fn orders<'py>(
+        &self,
+        py: Python<'py>,
+        perpetual_id: types::PerpetualId,
+    ) -> PyResult<Bound<'py, PyList>> {
+        let exchange = self.shared.load();
+        let book = perpetual(&exchange, perpetual_id)?.l3_book();
+        let orders = PyList::empty(py);
+        for order in book.all_orders().values() {
+            let order = order.order();
+            let dict = PyDict::new(py);
+            dict.set_item("order_id", order.order_id().get())?;
+            dict.set_item("account_id", order.account_id())?;
+            dict.set_item("type", order_type(order.r#type()))?;
+            dict.set_item("price", decimal(py, order.price())?)?;
+            dict.set_item("size", decimal(py, order.size())?)?;
+            dict.set_item("expiry_block", order.expiry_block())?;
+            dict.set_item("request_id", order.request_id())?;
+            orders.append(dict)?;
+        }
+        Ok(orders)
+    }
+
+    /// IDs of the tracked accounts, in ascending order.
+    fn account_ids(&self) -> Vec<types::AccountId> {
+        let mut ids = self
+            .shared
+            .load()
+            .accounts()
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Balances and positions of the account, `None` if not tracked.
+    fn account<'py>(
+        &self,
+        py: Python<'py>,
+        account_id: types::AccountId,
+    ) -> PyResult<Option<Bound<'py, PyDict>>> {
+        let exchange = self.shared.load();
+        let Some(account) = exchange.accounts().get(&account_id) else {
+            return Ok(None);
+        };
+        let dict = PyDict::new(py);
+        dict.set_item("id", account.id())?;
+        dict.set_item("address", account.address().to_string())?;
+        dict.set_item("balance", decimal(py, account.balance())?)?;
+        dict.set_item("locked_balance", decimal(py, account.locked_balance())?)?;
+        dict.set_item("frozen", account.frozen())?;
+        dict.set_item("positions", positions(py, account.positions().values())?)?;
+        Ok(Some(dict))
+    }
+
+    /// Open positions of the tracked accounts in the perpetual contract.
+    fn positions<'py>(
+        &self,
+        py: Python<'py>,
+        perpetual_id: types::PerpetualId,
+    ) -> PyResult<Bound<'py, PyList>> {
+        let exchange = self.shared.load();
+        positions(
+            py,
+            exchange
+                .accounts()
+                .values()
+                .filter_map(|account| account.positions().get(&perpetual_id)),
+        )
+    }
+
+    fn __repr__(&self) -> String {
+        let exchange = self.shared.load();
+        format!(
+            "Exchange(block_number={}, perpetuals={}, accounts={})",
+            exchange.instant().block_number(),
+            exchange.perpetuals().len(),
+            exchange.accounts().len()
+        )
+    }
+}
+
+fn perpetual(
+    exchange: &state::Exchange,
+    perpetual_id: types::PerpetualId,
+) -> PyResult<&state::Perpetual> {
+    exchange
+        .perpetuals()
+        .get(&perpetual_id)
+        .map(Arc::as_ref)
+        .ok_or_else(|| PyKeyError::new_err(perpetual_id))
+}
+
+fn positions<'a, 'py>(
+    py: Python<'py>,
+    positions: impl Iterator<Item = &'a state::Position>,
+) -> PyResult<Bound<'py, PyList>> {
+    let mut positions = positions.collect::<Vec<_>>();
+    positions.sort_unstable_by_key(|p| (p.perpetual_id(), p.account_id()));
+    let list = PyList::empty(py);
+    for position in positions {
+        let dict = PyDict::new(py);
+        dict.set_item("perpetual_id", position.perpetual_id())?;
+        dict.set_item("account_id", position.account_id())?;
+        dict.set_item(
+            "type",
+            match position.r#type() {
+                state::PositionType::Long => "long",
+                state::PositionType::Short => "short",
+            },
+        )?;
+        dict.set_item("entry_price", decimal(py, position.entry_price())?)?;
+        dict.set_item("size", decimal(py, position.size())?)?;
+        dict.set_item("deposit", decimal(py, position.deposit())?)?;
+        dict.set_item("pnl", decimal(py, position.pnl())?)?;
+        dict.set_item("delta_pnl", decimal(py, position.delta_pnl())?)?;
+        dict.set_item("premium_pnl", decimal(py, position.premium_pnl())?)?;
+        dict.set_item(
+            "liquidation_price",
+            decimal(py, position.liquidation_price())?,
+        )?;
+        list.append(dict)?;
+    }
+    Ok(list)
+}
+
+fn order_type(r#type: types::OrderType) -> &'static str {
+    match r#type {
+        types::OrderType::OpenLong => "open_long",
+        types::OrderType::OpenShort => "open_short",
+        types::OrderType::CloseLong => "close_long",
+        types::OrderType::CloseShort => "close_short",
+    }
+}
@@ -0,0 +1,43 @@
+//! Python bindings of the read side of the SDK: snapshot building, event stream
+//! consumption and order book/position queries.
+//!
+//! Chain access methods return awaitables driven by the Tokio runtime of
+//! `pyo3-async-runtimes`, so they integrate with `asyncio` and never hold the GIL
+//! while waiting for the RPC provider. Applying events to the state releases
+//! the GIL as well.
+//!
+//! ```python
+//! import asyncio
+//! import dex_sdk
+//!
+//! async def main():
+//!     client = dex_sdk.Client("https://testnet-rpc.monad.xyz", dex_sdk.Chain.testnet())
+//!     exchange = await client.snapshot(accounts=["0x..."])
+//!     async for block in client.stream(exchange):
+//!         exchange.apply(block)
+//!         print(exchange.book(16, depth=5))
+//!
+//! asyncio.run(main())
+//! ```
+//!
+//! Build with [maturin](https://www.maturin.rs/): `maturin develop -m dex-sdk-py/Cargo.toml`.
+
+use pyo3::prelude::*;
+
+mod client;
+mod convert;
+mod error;
+mod exchange;
+mod stream;
+
+#[pymodule]
+#[pyo3(name = "dex_sdk")]
+fn dex_sdk_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<client::Chain>()?;
+    m.add_class::<client::Client>()?;
+    m.add_class::<exchange::Exchange>()?;
+    m.add_class::<stream::BlockEvents>()?;
+    m.add_class::<stream::EventStream>()?;
+    m.add("DexError", m.py().get_type::<error::DexError>())?;
+    Ok(())
+}
@@ -0,0 +1,78 @@
+//! Async iteration over the raw block events.
+
+use std::sync::Arc;
+
+use dex_sdk::{error::DexError, stream};
+use pyo3::{exceptions::PyStopAsyncIteration, prelude::*};
+
+use crate::error;
+
+type Receiver = stream::Receiver<Result<stream::RawBlockEvents, DexError>>;
+
+/// Raw exchange events of a block, to be applied with `Exchange.apply`.
+#[pyclass(frozen, module = "dex_sdk")]
+pub struct BlockEvents {
+    pub(crate) inner: Arc<stream::RawBlockEvents>,
+}
+
+#[pymethods]
+impl BlockEvents {
+    #[getter]
+    fn block_number(&self) -> u64 {
+        self.inner.instant().block_number()
+    }
+
+    #[getter]
+    fn timestamp(&self) -> u64 {
+        self.inner.instant().block_timestamp()
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.events().len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "BlockEvents(block_number={}, events={})",
+            self.inner.instant().block_number(),
+            self.inner.events().len()
+        )
+    }
+}
+
+/// Async iterator of [`BlockEvents`], raising `DexError` on stream failure.
+#[pyclass(frozen, module = "dex_sdk")]
+pub struct EventStream {
+    receiver: Arc<tokio::sync::Mutex<Receiver>>,
+}
+
+impl EventStream {
+    pub(crate) fn new(receiver: Receiver) -> Self {
+        Self {
+            receiver: Arc::new(tokio::sync::Mutex::new(receiver)),
+        }
+    }
+}
+
+#[pymethods]
+impl EventStream {
+    fn __aiter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    fn __anext__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let receiver = self.receiver.clone();
+        pyo3_async_runtimes::tokio::future_into_py(py, async move {
+            match receiver.lock().await.recv().await {
+                Some(stream::Delivery::Item(Ok(events))) => Ok(BlockEvents {
+                    inner: Arc::new(events),
+                }),
+                Some(stream::Delivery::Item(Err(err))) => Err(error::to_py(err)),
+                Some(stream::Delivery::Resync { dropped }) => Err(error::DexError::new_err(
+                    format!("stream dropped {dropped} blocks"),
+                )),
+                None => Err(PyStopAsyncIteration::new_err(())),
+            }
+        })
+    }
+}
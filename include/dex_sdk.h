@@ -0,0 +1,242 @@
+#ifndef DEX_SDK_H
+#define DEX_SDK_H
+
+/* Generated from src/capi.rs by cbindgen, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Result of the call.
+ */
+typedef enum {
+  /**
+   * Call succeeded.
+   */
+  DEX_STATUS_OK = 0,
+  /**
+   * Null pointer or malformed input, see [`dex_last_error`].
+   */
+  DEX_STATUS_INVALID_ARGUMENT = 1,
+  /**
+   * Perpetual contract, account or position is not tracked.
+   */
+  DEX_STATUS_NOT_FOUND = 2,
+  /**
+   * Applying the events failed or the call panicked, and the state is
+   * no longer consistent, see [`dex_last_error`].
+   */
+  DEX_STATUS_FAILED = 3,
+} DexStatus;
+
+/**
+ * Side of the order book.
+ */
+typedef enum {
+  DEX_SIDE_ASK = 0,
+  DEX_SIDE_BID = 1,
+} DexSide;
+
+/**
+ * Exchange state handle.
+ */
+typedef struct DexExchange DexExchange;
+
+/**
+ * Exchange log, as reported by the node for the block.
+ */
+typedef struct {
+  /**
+   * Hash of the transaction emitted the log.
+   */
+  uint8_t tx_hash[32];
+  /**
+   * Index of the transaction within the block.
+   */
+  uint64_t tx_index;
+  /**
+   * Index of the log within the block.
+   */
+  uint64_t log_index;
+  /**
+   * Address of the contract emitted the log.
+   */
+  uint8_t address[20];
+  /**
+   * Topics of the log, `topics_len` 32-byte words.
+   */
+  const uint8_t (*topics)[32];
+  size_t topics_len;
+  /**
+   * ABI-encoded non-indexed event fields, `data_len` bytes.
+   */
+  const uint8_t *data;
+  size_t data_len;
+} DexLog;
+
+/**
+ * Order fill of the applied block.
+ */
+typedef struct {
+  uint32_t perpetual_id;
+  uint32_t account_id;
+  /**
+   * ID of the filled order, zero if not known.
+   */
+  uint16_t order_id;
+  bool is_maker;
+  double price;
+  double size;
+  double fee;
+  /**
+   * Hash of the transaction emitted the fill log.
+   */
+  uint8_t tx_hash[32];
+  /**
+   * Index of the fill log within the block.
+   */
+  uint64_t log_index;
+} DexFill;
+
+/**
+ * Callback receiving fills of the applied block along with the user data pointer.
+ */
+typedef void (*DexFillCallback)(const DexFill *fill, void *user_data);
+
+/**
+ * Aggregated price level of the order book.
+ */
+typedef struct {
+  double price;
+  double size;
+  uint32_t num_orders;
+} DexLevel;
+
+/**
+ * Open position of the account.
+ */
+typedef struct {
+  /**
+   * Whether the position is long.
+   */
+  bool is_long;
+  double entry_price;
+  double size;
+  /**
+   * Collateral deposit / margin locked in the position.
+   */
+  double deposit;
+  /**
+   * Unrealized PnL, the sum of delta and premium PnL.
+   */
+  double pnl;
+  double delta_pnl;
+  double premium_pnl;
+  double liquidation_price;
+} DexPosition;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Message of the last failure on the calling thread, null if none.
+ *
+ * Pointer is valid until the next failing call on the same thread.
+ */
+const char *dex_last_error(void);
+
+/**
+ * Captures the exchange state at the latest block via the HTTP RPC endpoint, blocking
+ * until done. Returns null on failure.
+ *
+ * `exchange` is the 20-byte address of the exchange contract, or null for the testnet
+ * deployment. The given accounts, `accounts_len` 20-byte addresses, are tracked along
+ * with the order books of all perpetual contracts.
+ *
+ * # Safety
+ *
+ * `rpc_url` must be a NUL-terminated string, `exchange` null or pointing to 20 bytes,
+ * `accounts` pointing to `accounts_len` addresses or null if zero.
+ */
+DexExchange *dex_exchange_snapshot(const char *rpc_url,
+                                   const uint8_t (*exchange)[20],
+                                   const uint8_t (*accounts)[20],
+                                   size_t accounts_len);
+
+/**
+ * Releases the handle.
+ *
+ * # Safety
+ *
+ * `exchange` must be null or a handle returned by [`dex_exchange_snapshot`],
+ * not used after this call.
+ */
+void dex_exchange_free(DexExchange *exchange);
+
+/**
+ * Writes the number of the last applied block to `block_number`.
+ *
+ * # Safety
+ *
+ * `exchange` must be a valid handle, `block_number` writable.
+ */
+DexStatus dex_exchange_block_number(const DexExchange *exchange, uint64_t *block_number);
+
+/**
+ * Applies the exchange logs of the block, in the order of the log index. Logs of
+ * other contracts have to be filtered out beforehand. Fills of the block are reported
+ * to the callback, if any, before returning.
+ *
+ * Blocks up to the last applied one are ignored. On [`DexStatus::Failed`] the handle
+ * has to be released and the snapshot taken again.
+ *
+ * # Safety
+ *
+ * `exchange` must be a valid handle, `logs` pointing to `logs_len` logs with valid
+ * topics and data pointers, or null if zero.
+ */
+DexStatus dex_exchange_apply_logs(DexExchange *exchange,
+                                  uint64_t block_number,
+                                  uint64_t block_timestamp,
+                                  const DexLog *logs,
+                                  size_t logs_len,
+                                  DexFillCallback on_fill,
+                                  void *user_data);
+
+/**
+ * Writes up to `capacity` best price levels of the order book side to `levels`,
+ * storing the number written to `levels_len`.
+ *
+ * # Safety
+ *
+ * `exchange` must be a valid handle, `levels` pointing to `capacity` writable levels
+ * or null if zero, `levels_len` writable.
+ */
+DexStatus dex_exchange_book_levels(const DexExchange *exchange,
+                                   uint32_t perpetual_id,
+                                   DexSide side,
+                                   DexLevel *levels,
+                                   size_t capacity,
+                                   size_t *levels_len);
+
+/**
+ * Writes the position of the tracked account in the perpetual contract to `position`.
+ *
+ * # Safety
+ *
+ * `exchange` must be a valid handle, `position` writable.
+ */
+DexStatus dex_exchange_position(const DexExchange *exchange,
+                                uint32_t account_id,
+                                uint32_t perpetual_id,
+                                DexPosition *position);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* DEX_SDK_H */
@@ -1,10 +1,20 @@
 pub const DEX_REVISION: &str = env!("DEX_REVISION");
 
+/// Generates Solidity bindings, along with contract instances to make calls
+/// and send transactions when the `rpc` feature is enabled.
+macro_rules! sol {
+    ($($input:tt)*) => {
+        #[cfg(feature = "rpc")]
+        alloy::sol!(#[sol(rpc)] $($input)*);
+        #[cfg(not(feature = "rpc"))]
+        alloy::sol!($($input)*);
+    };
+}
+
 #[allow(clippy::too_many_arguments)]
 pub mod dex {
-    alloy::sol!(
+    sol!(
         #[derive(Debug)]
-        #[sol(rpc)]
         Exchange,
         "abi/dex/Exchange.json"
     );
@@ -12,9 +22,8 @@ pub mod dex {
 
 #[allow(clippy::too_many_arguments)]
 pub mod erc1967_proxy {
-    alloy::sol!(
+    sol!(
         #[derive(Debug)]
-        #[sol(rpc)]
         ERC1967Proxy,
         "abi/dex/ERC1967Proxy.json"
     );
@@ -22,20 +31,32 @@ pub mod erc1967_proxy {
 
 #[allow(clippy::too_many_arguments)]
 pub mod errors {
-    alloy::sol!(
+    sol!(
         #[derive(Debug)]
-        #[sol(rpc)]
         Exchange,
         "abi/dex/Errors.abi.json"
     );
 }
 
+pub mod erc20 {
+    sol!(
+        /// Subset of the ERC-20 token interface used to manage collateral approvals
+        /// and transfers.
+        #[derive(Debug)]
+        interface IERC20 {
+            function allowance(address owner, address spender) external view returns (uint256);
+            function approve(address spender, uint256 amount) external returns (bool);
+            function transfer(address to, uint256 amount) external returns (bool);
+        }
+    );
+}
+
 #[allow(clippy::too_many_arguments)]
+#[cfg(feature = "rpc")]
 pub mod testing {
-    alloy::sol!(
+    sol!(
         /// Test ERC-20 token to use as an exchange collateral token.
         #[derive(Debug)]
-        #[sol(rpc)]
         TestToken,
         "abi/testing/TestToken.json"
     );
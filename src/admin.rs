@@ -0,0 +1,255 @@
+//! Typed exchange administration operations.
+//!
+//! [`AdminClient`] wraps administrator calls of the exchange smart contract,
+//! converting decimal arguments to the contract fixed-point representation.
+//! It is used by [`crate::testing::TestExchange`] and can be used by operators
+//! against real deployments, provided the [`Provider`] signs for the administrator
+//! addresses.
+
+use alloy::{
+    network::Ethereum,
+    primitives::{Address, I256, U256},
+    providers::{PendingTransactionBuilder, Provider},
+};
+use fastnum::{D64, UD64, UD128};
+
+use crate::{
+    Chain,
+    abi::dex::Exchange::ExchangeInstance,
+    error::DexError,
+    num,
+    state::{FEE_SCALE, FUNDING_RATE_SCALE, LEVERAGE_SCALE},
+    types,
+};
+
+/// Client for exchange administrator operations.
+///
+/// Parameter updates are sent from the administrator address, price updates
+/// from the price administrator address, which defaults to the administrator.
+pub struct AdminClient<P> {
+    instance: ExchangeInstance<P>,
+    admin: Address,
+    price_admin: Address,
+    collateral_decimals: Option<u8>,
+}
+
+impl<P: Provider> AdminClient<P> {
+    /// Create a new client sending administrator transactions from the given address.
+    pub fn new(chain: &Chain, provider: P, admin: Address) -> Self {
+        Self {
+            instance: ExchangeInstance::new(chain.exchange(), provider),
+            admin,
+            price_admin: admin,
+            collateral_decimals: chain.collateral_decimals(),
+        }
+    }
+
+    /// Send price updates from the given price administrator address.
+    pub fn with_price_admin(mut self, price_admin: Address) -> Self {
+        self.price_admin = price_admin;
+        self
+    }
+
+    /// Administrator address.
+    pub fn admin(&self) -> Address {
+        self.admin
+    }
+
+    /// Price administrator address.
+    pub fn price_admin(&self) -> Address {
+        self.price_admin
+    }
+
+    /// Set taker fee of the perpetual contract, as a fraction of the notional.
+    pub async fn set_taker_fee(
+        &self,
+        perp_id: types::PerpetualId,
+        fee: UD64,
+    ) -> Result<PendingTransactionBuilder<Ethereum>, DexError> {
+        let fee = num::Converter::new(FEE_SCALE).to_unsigned(fee);
+        Ok(self
+            .instance
+            .setTakerFee(U256::from(perp_id), fee)
+            .from(self.admin)
+            .send()
+            .await?)
+    }
+
+    /// Set maker fee of the perpetual contract, as a fraction of the notional.
+    pub async fn set_maker_fee(
+        &self,
+        perp_id: types::PerpetualId,
+        fee: UD64,
+    ) -> Result<PendingTransactionBuilder<Ethereum>, DexError> {
+        let fee = num::Converter::new(FEE_SCALE).to_unsigned(fee);
+        Ok(self
+            .instance
+            .setMakerFee(U256::from(perp_id), fee)
+            .from(self.admin)
+            .send()
+            .await?)
+    }
+
+    /// Set initial margin of the perpetual contract,
+    /// see [`crate::state::Perpetual::initial_margin`].
+    pub async fn set_initial_margin(
+        &self,
+        perp_id: types::PerpetualId,
+        margin: UD64,
+    ) -> Result<PendingTransactionBuilder<Ethereum>, DexError> {
+        let margin = num::Converter::new(LEVERAGE_SCALE).to_unsigned(margin);
+        Ok(self
+            .instance
+            .setInitialMarginFraction(U256::from(perp_id), margin)
+            .from(self.admin)
+            .send()
+            .await?)
+    }
+
+    /// Set maintenance margin of the perpetual contract,
+    /// see [`crate::state::Perpetual::maintenance_margin`].
+    pub async fn set_maintenance_margin(
+        &self,
+        perp_id: types::PerpetualId,
+        margin: UD64,
+    ) -> Result<PendingTransactionBuilder<Ethereum>, DexError> {
+        let margin = num::Converter::new(LEVERAGE_SCALE).to_unsigned(margin);
+        Ok(self
+            .instance
+            .setMaintenanceMarginFraction(U256::from(perp_id), margin)
+            .from(self.admin)
+            .send()
+            .await?)
+    }
+
+    /// Pause/unpause the perpetual contract.
+    pub async fn set_paused(
+        &self,
+        perp_id: types::PerpetualId,
+        paused: bool,
+    ) -> Result<PendingTransactionBuilder<Ethereum>, DexError> {
+        Ok(self
+            .instance
+            .setContractPaused(U256::from(perp_id), paused)
+            .from(self.admin)
+            .send()
+            .await?)
+    }
+
+    /// Make the perpetual contract ignore oracle prices.
+    pub async fn set_ignore_oracle(
+        &self,
+        perp_id: types::PerpetualId,
+        ignore: bool,
+    ) -> Result<PendingTransactionBuilder<Ethereum>, DexError> {
+        Ok(self
+            .instance
+            .setIgnOracle(U256::from(perp_id), ignore)
+            .from(self.admin)
+            .send()
+            .await?)
+    }
+
+    /// Halt/resume the whole exchange.
+    pub async fn set_halted(
+        &self,
+        halted: bool,
+    ) -> Result<PendingTransactionBuilder<Ethereum>, DexError> {
+        Ok(self
+            .instance
+            .setExchangeHalted(halted)
+            .from(self.admin)
+            .send()
+            .await?)
+    }
+
+    /// Set minimal collateral amount of posted orders.
+    pub async fn set_min_post(
+        &self,
+        amount: UD128,
+    ) -> Result<PendingTransactionBuilder<Ethereum>, DexError> {
+        let amount = self.collateral_converter().await?.to_unsigned(amount);
+        Ok(self
+            .instance
+            .setMinPost(amount)
+            .from(self.admin)
+            .send()
+            .await?)
+    }
+
+    /// Set minimal collateral amount of settlements.
+    pub async fn set_min_settle(
+        &self,
+        amount: UD128,
+    ) -> Result<PendingTransactionBuilder<Ethereum>, DexError> {
+        let amount = self.collateral_converter().await?.to_unsigned(amount);
+        Ok(self
+            .instance
+            .setMinSettle(amount)
+            .from(self.admin)
+            .send()
+            .await?)
+    }
+
+    /// Set mark price of the perpetual contract.
+    pub async fn set_mark_price(
+        &self,
+        perp_id: types::PerpetualId,
+        price: UD64,
+    ) -> Result<PendingTransactionBuilder<Ethereum>, DexError> {
+        let price = self.price_converter(perp_id).await?.to_unsigned(price);
+        Ok(self
+            .instance
+            .updateMarkPricePNS(U256::from(perp_id), price)
+            .from(self.price_admin)
+            .send()
+            .await?)
+    }
+
+    /// Set funding rate of the next funding event of the perpetual contract,
+    /// at the given funding price, overwriting the already set one.
+    pub async fn set_funding_sum(
+        &self,
+        perp_id: types::PerpetualId,
+        rate: D64,
+        price: UD64,
+    ) -> Result<PendingTransactionBuilder<Ethereum>, DexError> {
+        let rate: I256 = num::Converter::new(FUNDING_RATE_SCALE).to_signed(rate);
+        let price = self.price_converter(perp_id).await?.to_unsigned(price);
+        let price = u32::try_from(price).map_err(|_| {
+            DexError::InvalidRequest(format!("funding price out of range: {price}"))
+        })?;
+        Ok(self
+            .instance
+            .setFundingSum(U256::from(perp_id), rate, price, true, true)
+            .from(self.price_admin)
+            .send()
+            .await?)
+    }
+
+    async fn price_converter(
+        &self,
+        perp_id: types::PerpetualId,
+    ) -> Result<num::Converter, DexError> {
+        let info = self
+            .instance
+            .getPerpetualInfo(U256::from(perp_id))
+            .call()
+            .await?;
+        Ok(num::Converter::new(info.priceDecimals.to()))
+    }
+
+    async fn collateral_converter(&self) -> Result<num::Converter, DexError> {
+        let decimals = match self.collateral_decimals {
+            Some(decimals) => decimals,
+            None => self
+                .instance
+                .getExchangeInfo()
+                .call()
+                .await?
+                .collateralDecimals
+                .to(),
+        };
+        Ok(num::Converter::new(decimals))
+    }
+}
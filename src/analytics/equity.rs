@@ -0,0 +1,397 @@
+//! Account equity curves with drawdown and return statistics.
+//!
+//! [`EquityTracker`] samples total equity of the tracked accounts after applied blocks,
+//! keeping the curve along with the running maximum drawdown, and answers windowed
+//! queries of the PnL, drawdown and per-sample return statistics.
+//!
+//! Deposits and withdrawals change the equity without being a gain or loss, so they
+//! are accounted as flows: drawdowns and returns are measured on the equity net of
+//! the cumulative flows, i.e. the trading performance of the account.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use dex_sdk::analytics::equity::{EquityConfig, EquityTracker};
+//!
+//! let mut tracker = EquityTracker::new(EquityConfig::default().with_interval_blocks(10));
+//! while let Some(block_events) = stream.next().await {
+//!     if let Some(events) = exchange.apply_events(&block_events?)? {
+//!         tracker.apply(&exchange, &events);
+//!     }
+//! }
+//! let stats = tracker.stats(account_id, Duration::from_secs(86_400));
+//! ```
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+
+use fastnum::{D64, D256, UD128};
+
+use crate::{
+    state::{
+        AccountEvent, AccountEventType, AccountTracking, BalanceChangeReason, Exchange,
+        StateBlockEvents, StateEvents,
+    },
+    types,
+};
+
+/// Default number of samples kept per account.
+const DEFAULT_MAX_SAMPLES: usize = 10_000;
+
+/// Sampling and retention configuration of the [`EquityTracker`].
+#[derive(Clone, Copy, Debug)]
+pub struct EquityConfig {
+    interval_blocks: u64,
+    max_samples: usize,
+}
+
+impl Default for EquityConfig {
+    fn default() -> Self {
+        Self {
+            interval_blocks: 1,
+            max_samples: DEFAULT_MAX_SAMPLES,
+        }
+    }
+}
+
+impl EquityConfig {
+    /// Sample at most once per given number of blocks (default: every block).
+    pub fn with_interval_blocks(mut self, interval_blocks: u64) -> Self {
+        self.interval_blocks = interval_blocks.max(1);
+        self
+    }
+
+    /// Keep up to the given number of the most recent samples per account.
+    pub fn with_max_samples(mut self, max_samples: usize) -> Self {
+        self.max_samples = max_samples.max(1);
+        self
+    }
+}
+
+/// Equity of the account as of the end of the block.
+#[derive(Clone, Copy, derive_more::Debug)]
+pub struct EquitySample {
+    /// Instant of the block the sample got taken at.
+    pub instant: types::StateInstant,
+
+    /// Collateral balance, including the collateral locked by orders.
+    #[debug("{balance}")]
+    pub balance: UD128,
+
+    /// Collateral locked by orders.
+    #[debug("{locked_balance}")]
+    pub locked_balance: UD128,
+
+    /// Total unrealized PnL of all positions.
+    #[debug("{unrealized_pnl}")]
+    pub unrealized_pnl: D256,
+
+    /// Balance plus deposits and unrealized PnL of all positions.
+    #[debug("{equity}")]
+    pub equity: D256,
+
+    /// Deposits minus withdrawals since the tracking start.
+    #[debug("{net_flows}")]
+    pub net_flows: D256,
+}
+
+impl EquitySample {
+    /// Equity net of the deposits and withdrawals since the tracking start.
+    pub fn performance(&self) -> D256 {
+        self.equity - self.net_flows
+    }
+}
+
+/// Statistics of the equity curve within the window.
+#[derive(Clone, Copy, derive_more::Debug)]
+pub struct EquityStats {
+    /// Number of samples within the window.
+    pub samples: usize,
+
+    /// Equity of the first sample.
+    #[debug("{start_equity}")]
+    pub start_equity: D256,
+
+    /// Equity of the last sample.
+    #[debug("{end_equity}")]
+    pub end_equity: D256,
+
+    /// Change of the equity net of deposits and withdrawals.
+    #[debug("{pnl}")]
+    pub pnl: D256,
+
+    /// The largest decline of the equity net of flows from its preceding peak.
+    #[debug("{max_drawdown}")]
+    pub max_drawdown: D256,
+
+    /// Mean of the returns between consecutive samples, as a fraction of the equity.
+    #[debug("{:?}", mean_return.map(|v| format!("{v}")))]
+    pub mean_return: Option<D64>,
+
+    /// Standard deviation of the returns between consecutive samples.
+    #[debug("{:?}", return_volatility.map(|v| format!("{v}")))]
+    pub return_volatility: Option<D64>,
+
+    /// Mean return per unit of volatility, not annualized and with no risk-free rate.
+    #[debug("{:?}", sharpe.map(|v| format!("{v}")))]
+    pub sharpe: Option<D64>,
+}
+
+/// Equity curve of a single account.
+#[derive(Clone, Debug, Default)]
+struct Curve {
+    samples: VecDeque<EquitySample>,
+    /// Flows since the tracking start, including the ones not sampled yet.
+    net_flows: D256,
+    peak: Option<D256>,
+    max_drawdown: D256,
+}
+
+/// Equity curves of the tracked accounts, fed with the exchange state and events
+/// of each applied block.
+///
+/// Accounts tracked with [`AccountTracking::PositionsOnly`] are not sampled, as their
+/// balances are not known. Windows of the queries are measured in block time back from
+/// the latest sample, inclusive.
+#[derive(Clone, Debug, Default)]
+pub struct EquityTracker {
+    config: EquityConfig,
+    instant: types::StateInstant,
+    last_sample_block: Option<u64>,
+    curves: HashMap<types::AccountId, Curve>,
+}
+
+impl EquityTracker {
+    pub fn new(config: EquityConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    /// Instant of the latest applied block.
+    pub fn instant(&self) -> types::StateInstant {
+        self.instant
+    }
+
+    /// Accounts deposits and withdrawals of the block and samples equity of all
+    /// fully tracked accounts if the sampling interval elapsed.
+    ///
+    /// Returns the number of accounts sampled.
+    pub fn apply(&mut self, exchange: &Exchange, events: &StateBlockEvents) -> usize {
+        for (_, event) in events.flat_events() {
+            if let StateEvents::Account(AccountEvent {
+                account_id,
+                r#type:
+                    AccountEventType::BalanceChanged {
+                        delta,
+                        reason: BalanceChangeReason::Deposit | BalanceChangeReason::Withdrawal,
+                    },
+                ..
+            }) = event
+            {
+                self.curves.entry(*account_id).or_default().net_flows += delta.resize();
+            }
+        }
+
+        let instant = exchange.instant();
+        self.instant = self.instant.max(instant);
+        if self
+            .last_sample_block
+            .is_some_and(|b| instant.block_number() < b + self.config.interval_blocks)
+        {
+            return 0;
+        }
+        self.last_sample_block = Some(instant.block_number());
+
+        let mut sampled = 0;
+        for account in exchange.accounts().values() {
+            if account.tracking() != AccountTracking::Full {
+                continue;
+            }
+            let unrealized_pnl = account
+                .positions()
+                .values()
+                .fold(D256::ZERO, |acc, pos| acc + pos.pnl());
+            let deposits = account
+                .positions()
+                .values()
+                .fold(UD128::ZERO, |acc, pos| acc + pos.deposit());
+            let curve = self.curves.entry(account.id()).or_default();
+            let sample = EquitySample {
+                instant,
+                balance: account.balance(),
+                locked_balance: account.locked_balance(),
+                unrealized_pnl,
+                equity: (account.balance() + deposits).to_signed().resize() + unrealized_pnl,
+                net_flows: curve.net_flows,
+            };
+            let performance = sample.performance();
+            let peak = curve.peak.map_or(performance, |peak| peak.max(performance));
+            curve.peak = Some(peak);
+            curve.max_drawdown = curve.max_drawdown.max(peak - performance);
+            if curve.samples.len() == self.config.max_samples {
+                curve.samples.pop_front();
+            }
+            curve.samples.push_back(sample);
+            sampled += 1;
+        }
+        sampled
+    }
+
+    /// Retained samples of the account, oldest first.
+    pub fn samples(
+        &self,
+        account_id: types::AccountId,
+    ) -> impl DoubleEndedIterator<Item = &EquitySample> {
+        self.curves
+            .get(&account_id)
+            .into_iter()
+            .flat_map(|c| c.samples.iter())
+    }
+
+    /// The most recent sample of the account.
+    pub fn latest(&self, account_id: types::AccountId) -> Option<&EquitySample> {
+        self.curves.get(&account_id)?.samples.back()
+    }
+
+    /// Decline of the latest equity net of flows from its peak since the tracking start.
+    pub fn drawdown(&self, account_id: types::AccountId) -> Option<D256> {
+        let curve = self.curves.get(&account_id)?;
+        Some(curve.peak? - curve.samples.back()?.performance())
+    }
+
+    /// The largest drawdown since the tracking start, including the samples
+    /// no longer retained.
+    pub fn max_drawdown(&self, account_id: types::AccountId) -> Option<D256> {
+        let curve = self.curves.get(&account_id)?;
+        curve.peak.map(|_| curve.max_drawdown)
+    }
+
+    /// Statistics of the samples of the account within the window.
+    ///
+    /// Returns are measured between consecutive samples with positive preceding equity,
+    /// statistics of them are `None` unless there are at least two.
+    pub fn stats(&self, account_id: types::AccountId, window: Duration) -> Option<EquityStats> {
+        let since = self
+            .instant
+            .block_timestamp()
+            .saturating_sub(window.as_secs());
+        let samples = self
+            .samples(account_id)
+            .rev()
+            .take_while(|s| s.instant.block_timestamp() >= since)
+            .collect::<Vec<_>>();
+        let (last, first) = (*samples.first()?, *samples.last()?);
+
+        let mut peak = first.performance();
+        let mut max_drawdown = D256::ZERO;
+        let mut returns = vec![];
+        for pair in samples.windows(2).rev() {
+            let (prev, next) = (pair[1], pair[0]);
+            peak = peak.max(next.performance());
+            max_drawdown = max_drawdown.max(peak - next.performance());
+            if prev.equity > D256::ZERO {
+                returns.push((next.performance() - prev.performance()) / prev.equity);
+            }
+        }
+
+        let (mean_return, return_volatility, sharpe) = if returns.len() >= 2 {
+            let n = D256::from(returns.len() as u64);
+            let mean = returns.iter().fold(D256::ZERO, |acc, r| acc + *r) / n;
+            let variance = returns
+                .iter()
+                .fold(D256::ZERO, |acc, r| acc + (*r - mean) * (*r - mean))
+                / (n - D256::ONE);
+            let volatility = variance.sqrt();
+            let sharpe = (volatility > D256::ZERO).then(|| (mean / volatility).resize());
+            (Some(mean.resize()), Some(volatility.resize()), sharpe)
+        } else {
+            (None, None, None)
+        };
+
+        Some(EquityStats {
+            samples: samples.len(),
+            start_equity: first.equity,
+            end_equity: last.equity,
+            pnl: last.performance() - first.performance(),
+            max_drawdown,
+            mean_return,
+            return_volatility,
+            sharpe,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::sim::{Action, Simulator};
+
+    #[test]
+    fn drawdown_and_stats() {
+        let mut sim = Simulator::new(&[1], 2);
+        let mut exchange = sim.exchange_with_accounts(&[1]);
+        let mut tracker = EquityTracker::new(EquityConfig::default().with_max_samples(3));
+        let mut step = |tracker: &mut EquityTracker, actions: &[Action]| {
+            let events = exchange.apply_events(&sim.block(actions)).unwrap().unwrap();
+            tracker.apply(&exchange, &events)
+        };
+        let mark = |price| Action::Mark {
+            perpetual: 0,
+            price,
+        };
+
+        assert_eq!(
+            step(
+                &mut tracker,
+                &[Action::Open {
+                    perpetual: 0,
+                    account: 0,
+                    is_long: true,
+                    lots: 10,
+                }]
+            ),
+            1
+        );
+        step(&mut tracker, &[mark(1_100)]);
+        let peak = tracker.latest(1).unwrap().equity;
+        step(&mut tracker, &[mark(900)]);
+        let trough = tracker.latest(1).unwrap().equity;
+        assert!(trough < peak);
+        assert_eq!(tracker.drawdown(1), Some(peak - trough));
+        assert_eq!(tracker.max_drawdown(1), Some(peak - trough));
+        assert!(tracker.latest(2).is_none());
+
+        // Recovery keeps the maximum drawdown beyond the retained samples
+        step(&mut tracker, &[mark(1_000)]);
+        assert_eq!(tracker.samples(1).count(), 3);
+        assert_eq!(tracker.max_drawdown(1), Some(peak - trough));
+        assert!(tracker.drawdown(1).unwrap() < peak - trough);
+
+        let stats = tracker.stats(1, Duration::from_secs(3600)).unwrap();
+        assert_eq!(stats.samples, 3);
+        assert_eq!(stats.start_equity, peak);
+        assert_eq!(stats.max_drawdown, peak - trough);
+        assert_eq!(stats.pnl, stats.end_equity - peak);
+        assert!(stats.return_volatility.unwrap() > D64::ZERO);
+        assert!(stats.sharpe.is_some());
+    }
+
+    #[test]
+    fn samples_at_interval() {
+        let mut sim = Simulator::new(&[1], 1);
+        let mut exchange = sim.exchange();
+        let mut tracker = EquityTracker::new(EquityConfig::default().with_interval_blocks(3));
+        let sampled = (0..6)
+            .map(|_| {
+                let events = exchange.apply_events(&sim.block(&[])).unwrap().unwrap();
+                tracker.apply(&exchange, &events)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(sampled, vec![1, 0, 0, 1, 0, 0]);
+        assert_eq!(tracker.instant(), exchange.instant());
+    }
+}
@@ -0,0 +1,6 @@
+//! Performance analytics of the tracked accounts.
+//!
+//! [`equity::EquityTracker`] samples equity of the tracked accounts block by block and
+//! reports the drawdown and return statistics of their equity curves.
+
+pub mod equity;
@@ -0,0 +1,124 @@
+//! Balance sweeper binary - withdraws free collateral above the target buffer
+//! to a cold wallet on a schedule.
+//!
+//! Transactions are sent from the account address via `eth_sendTransaction`,
+//! so the RPC node is expected to sign for it.
+
+use std::time::Duration;
+
+use alloy::{
+    primitives::Address, providers::ProviderBuilder, rpc::client::RpcClient,
+    transports::layers::RetryBackoffLayer,
+};
+use clap::Parser;
+use dex_sdk::{
+    Chain,
+    client::DexClient,
+    sweep::{self, SweepConfig, SweepReport},
+};
+use fastnum::UD128;
+
+#[derive(Parser, Debug)]
+#[command(name = "balance_sweeper")]
+#[command(about = "Sweep free collateral above the target buffer to a cold wallet")]
+struct Args {
+    /// Chain to connect to (testnet or custom chain ID)
+    #[arg(short, long, default_value = "testnet")]
+    chain: String,
+
+    /// RPC URL to connect to
+    #[arg(short, long)]
+    rpc_url: String,
+
+    /// Address of the swept account, the RPC node has to sign for it
+    #[arg(short, long)]
+    account: Address,
+
+    /// Cold wallet address to transfer the swept collateral to
+    #[arg(long)]
+    cold_wallet: Address,
+
+    /// Free collateral to keep in the account
+    #[arg(short, long)]
+    buffer: UD128,
+
+    /// Minimal amount worth a sweep
+    #[arg(long, default_value = "0")]
+    min_sweep: UD128,
+
+    /// Sweep interval in seconds
+    #[arg(short, long, default_value = "60")]
+    interval: u64,
+
+    /// Poll interval in milliseconds
+    #[arg(short, long, default_value = "500")]
+    poll_interval: u64,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+
+    let chain = match args.chain.as_str() {
+        "testnet" => Chain::testnet(),
+        _ => {
+            eprintln!("Only 'testnet' is currently supported for chain");
+            std::process::exit(1);
+        }
+    };
+
+    println!("Connecting to {} ...", args.rpc_url);
+
+    let client = RpcClient::builder()
+        .layer(RetryBackoffLayer::new(10, 100, 200))
+        .connect(&args.rpc_url)
+        .await?;
+    client.set_poll_interval(Duration::from_millis(args.poll_interval));
+    let provider = ProviderBuilder::new().connect_client(client);
+
+    println!("Tracking account {} ...", args.account);
+
+    let mut client = DexClient::signing(chain, provider, args.account);
+    let tracking = client
+        .track(
+            client.snapshot().with_accounts(vec![args.account]),
+            tokio::time::sleep,
+        )
+        .await?;
+
+    let config = SweepConfig::new(args.cold_wallet, args.buffer)
+        .with_min_sweep(args.min_sweep)
+        .with_interval(Duration::from_secs(args.interval));
+    let (mut reports, _) = sweep::run(client, config, tokio::time::sleep);
+
+    println!(
+        "Sweeping free collateral above {} to {} every {}s",
+        args.buffer, args.cold_wallet, args.interval
+    );
+
+    while let Some(report) = reports.recv().await {
+        match report {
+            Ok(SweepReport::Idle { .. }) => {}
+            Ok(SweepReport::RateLimited {
+                sweepable,
+                until_block,
+            }) => {
+                println!("Withdrawals rate limited until block {until_block}, {sweepable} pending")
+            }
+            Ok(SweepReport::Swept {
+                amount,
+                withdraw_tx,
+                transfer_tx,
+            }) => println!("Swept {amount} (withdrawal: {withdraw_tx}, transfer: {transfer_tx})"),
+            Err(e) => eprintln!("Sweep failed: {:?}", e),
+        }
+        if tracking.is_finished() {
+            break;
+        }
+    }
+
+    match tracking.await? {
+        Ok(()) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -1,6 +1,6 @@
 //! Book listener binary - listens to an order book on testnet and prints it.
 
-use std::time::Duration;
+use std::{io::Write, time::Duration};
 
 use alloy::{
     providers::ProviderBuilder, rpc::client::RpcClient, transports::layers::RetryBackoffLayer,
@@ -8,12 +8,16 @@ use alloy::{
 use clap::{Parser, ValueEnum};
 use dex_sdk::{
     Chain,
+    render::{BookRenderer, RenderConfig},
     state::{BookOrder, OrderBook, Perpetual, SnapshotBuilder},
     stream,
-    types::{OrderType, PerpetualId, StateInstant},
+    types::{OrderSide, OrderType, PerpetualId, StateInstant},
 };
 use futures::StreamExt;
 
+/// Number of price levels to render in live mode when all levels are requested.
+const DEFAULT_LIVE_DEPTH: usize = 20;
+
 #[derive(Debug, Clone, Copy, ValueEnum, Default)]
 enum DisplayMode {
     /// L2 view: aggregated price levels only
@@ -23,6 +27,8 @@ enum DisplayMode {
     L3,
     /// Compact L3: show orders in a condensed format
     Compact,
+    /// Live L2: redraw changed levels in place
+    Live,
 }
 
 #[derive(Parser, Debug)]
@@ -49,7 +55,7 @@ struct Args {
     #[arg(short, long, default_value = "500")]
     poll_interval: u64,
 
-    /// Display mode: l2, l3, or compact
+    /// Display mode: l2, l3, compact or live
     #[arg(long, value_enum, default_value = "l3")]
     mode: DisplayMode,
 
@@ -191,24 +197,24 @@ fn print_l3_book(book: &OrderBook, depth: usize, orders_per_level: usize, curren
             level.num_orders(),
         );
 
-        // Get orders at this level via the book's ask_orders iterator filtered by price
-        let level_orders: Vec<_> = book.ask_orders().filter(|o| o.price() == **price).collect();
-
+        // Get orders at this level following its queue
+        let level = book
+            .view()
+            .level(OrderSide::Ask, **price)
+            .expect("level exists");
+        let num_orders = level.num_orders() as usize;
         let show_count = if orders_per_level == 0 {
-            level_orders.len()
+            num_orders
         } else {
-            orders_per_level.min(level_orders.len())
+            orders_per_level.min(num_orders)
         };
 
-        for order in level_orders.iter().take(show_count) {
+        for order in level.orders().take(show_count) {
             print_order_detailed(order, current_block);
         }
 
-        if level_orders.len() > show_count {
-            println!(
-                "       │ ... and {} more orders",
-                level_orders.len() - show_count
-            );
+        if num_orders > show_count {
+            println!("       │ ... and {} more orders", num_orders - show_count);
         }
         println!("  └{:─^96}", "");
     }
@@ -236,23 +242,23 @@ fn print_l3_book(book: &OrderBook, depth: usize, orders_per_level: usize, curren
         );
 
         // Get orders at this level
-        let level_orders: Vec<_> = book.bid_orders().filter(|o| o.price() == price.0).collect();
-
+        let level = book
+            .view()
+            .level(OrderSide::Bid, price.0)
+            .expect("level exists");
+        let num_orders = level.num_orders() as usize;
         let show_count = if orders_per_level == 0 {
-            level_orders.len()
+            num_orders
         } else {
-            orders_per_level.min(level_orders.len())
+            orders_per_level.min(num_orders)
         };
 
-        for order in level_orders.iter().take(show_count) {
+        for order in level.orders().take(show_count) {
             print_order_detailed(order, current_block);
         }
 
-        if level_orders.len() > show_count {
-            println!(
-                "       │ ... and {} more orders",
-                level_orders.len() - show_count
-            );
+        if num_orders > show_count {
+            println!("       │ ... and {} more orders", num_orders - show_count);
         }
         println!("  └{:─^96}", "");
     }
@@ -407,6 +413,7 @@ fn print_book(
         DisplayMode::L2 => print_l2_book(book, depth),
         DisplayMode::L3 => print_l3_book(book, depth, orders_per_level, current_block),
         DisplayMode::Compact => print_compact_book(book, depth, orders_per_level),
+        DisplayMode::Live => unreachable!("live mode is rendered by BookRenderer"),
     }
 }
 
@@ -474,6 +481,41 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         instant.block_timestamp()
     );
 
+    // Live mode redraws only changed rows of the book view in place
+    if let DisplayMode::Live = args.mode {
+        let depth = if args.depth == 0 {
+            DEFAULT_LIVE_DEPTH
+        } else {
+            args.depth
+        };
+        let mut renderer = BookRenderer::new(RenderConfig::default().with_depth(depth));
+        let mut event_stream = Box::pin(stream::raw(
+            &chain,
+            provider,
+            StateInstant::new(instant.block_number() + 1, 0),
+            tokio::time::sleep,
+        ));
+        loop {
+            if let Some(perp) = exchange.perpetuals().get(&args.market) {
+                print!("{}", renderer.render(perp));
+                std::io::stdout().flush()?;
+            }
+            match event_stream.next().await {
+                Some(Ok(block_events)) => {
+                    if let Err(e) = exchange.apply_events(&block_events) {
+                        eprintln!("Error applying events: {:?}", e);
+                        renderer.invalidate();
+                    }
+                }
+                Some(Err(e)) => {
+                    eprintln!("Error fetching events: {:?}", e);
+                    renderer.invalidate();
+                }
+                None => return Ok(()),
+            }
+        }
+    }
+
     // Print initial book state
     if let Some(perp) = exchange.perpetuals().get(&args.market) {
         print_market_info(perp);
@@ -0,0 +1,624 @@
+//! C ABI for embedding the exchange state tracking into C/C++ systems.
+//!
+//! The state is held by an opaque [`DexExchange`] handle: captured with
+//! [`dex_exchange_snapshot`], kept up to date with the exchange logs of each block
+//! passed to [`dex_exchange_apply_logs`], and queried with [`dex_exchange_book_levels`]
+//! and [`dex_exchange_position`]. The header is checked in as `include/dex_sdk.h`,
+//! tests verify it matches the one generated by the build.
+//!
+//! Fixed-point values are converted to `double`, exact on-chain values can be recovered
+//! by rounding to the tick/lot size of the perpetual contract. Functions report failures
+//! with [`DexStatus`] or a null handle, panics included, the message of the last failure
+//! on the calling thread is available via [`dex_last_error`].
+//!
+//! Handles are not thread-safe: calls with the same handle must not overlap.
+
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString, c_char, c_void},
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+};
+
+use alloy::{
+    primitives::{Address, B256, Bytes, Log, TxHash},
+    providers::ProviderBuilder,
+};
+
+use crate::{
+    Chain,
+    state::{self, OrderEventType, StateEvents},
+    stream::{self, DecodedLog, RawBlockEvents, RawEvent, UnknownRawEvent},
+    types,
+};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Result of the call.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DexStatus {
+    /// Call succeeded.
+    Ok = 0,
+    /// Null pointer or malformed input, see [`dex_last_error`].
+    InvalidArgument = 1,
+    /// Perpetual contract, account or position is not tracked.
+    NotFound = 2,
+    /// Applying the events failed or the call panicked, and the state is
+    /// no longer consistent, see [`dex_last_error`].
+    Failed = 3,
+}
+
+/// Side of the order book.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DexSide {
+    Ask = 0,
+    Bid = 1,
+}
+
+/// Exchange state handle.
+pub struct DexExchange {
+    exchange: state::Exchange,
+}
+
+/// Exchange log, as reported by the node for the block.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct DexLog {
+    /// Hash of the transaction emitted the log.
+    pub tx_hash: [u8; 32],
+    /// Index of the transaction within the block.
+    pub tx_index: u64,
+    /// Index of the log within the block.
+    pub log_index: u64,
+    /// Address of the contract emitted the log.
+    pub address: [u8; 20],
+    /// Topics of the log, `topics_len` 32-byte words.
+    pub topics: *const [u8; 32],
+    pub topics_len: usize,
+    /// ABI-encoded non-indexed event fields, `data_len` bytes.
+    pub data: *const u8,
+    pub data_len: usize,
+}
+
+/// Aggregated price level of the order book.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DexLevel {
+    pub price: f64,
+    pub size: f64,
+    pub num_orders: u32,
+}
+
+/// Open position of the account.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DexPosition {
+    /// Whether the position is long.
+    pub is_long: bool,
+    pub entry_price: f64,
+    pub size: f64,
+    /// Collateral deposit / margin locked in the position.
+    pub deposit: f64,
+    /// Unrealized PnL, the sum of delta and premium PnL.
+    pub pnl: f64,
+    pub delta_pnl: f64,
+    pub premium_pnl: f64,
+    pub liquidation_price: f64,
+}
+
+/// Order fill of the applied block.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct DexFill {
+    pub perpetual_id: u32,
+    pub account_id: u32,
+    /// ID of the filled order, zero if not known.
+    pub order_id: u16,
+    pub is_maker: bool,
+    pub price: f64,
+    pub size: f64,
+    pub fee: f64,
+    /// Hash of the transaction emitted the fill log.
+    pub tx_hash: [u8; 32],
+    /// Index of the fill log within the block.
+    pub log_index: u64,
+}
+
+/// Callback receiving fills of the applied block along with the user data pointer.
+pub type DexFillCallback = Option<extern "C" fn(fill: *const DexFill, user_data: *mut c_void)>;
+
+/// Message of the last failure on the calling thread, null if none.
+///
+/// Pointer is valid until the next failing call on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn dex_last_error() -> *const c_char {
+    LAST_ERROR.with_borrow(|err| err.as_ref().map_or(ptr::null(), |err| err.as_ptr()))
+}
+
+/// Captures the exchange state at the latest block via the HTTP RPC endpoint, blocking
+/// until done. Returns null on failure.
+///
+/// `exchange` is the 20-byte address of the exchange contract, or null for the testnet
+/// deployment. The given accounts, `accounts_len` 20-byte addresses, are tracked along
+/// with the order books of all perpetual contracts.
+///
+/// # Safety
+///
+/// `rpc_url` must be a NUL-terminated string, `exchange` null or pointing to 20 bytes,
+/// `accounts` pointing to `accounts_len` addresses or null if zero.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dex_exchange_snapshot(
+    rpc_url: *const c_char,
+    exchange: *const [u8; 20],
+    accounts: *const [u8; 20],
+    accounts_len: usize,
+) -> *mut DexExchange {
+    catch_panic(ptr::null_mut(), || {
+        if rpc_url.is_null() {
+            set_error("rpc_url is null");
+            return ptr::null_mut();
+        }
+        let Ok(url) = unsafe { CStr::from_ptr(rpc_url) }.to_str() else {
+            set_error("rpc_url is not UTF-8");
+            return ptr::null_mut();
+        };
+        let Ok(url) = url.parse() else {
+            set_error(&format!("invalid RPC URL: {url}"));
+            return ptr::null_mut();
+        };
+        let exchange = (!exchange.is_null()).then(|| Address::from(unsafe { *exchange }));
+        let accounts = unsafe { slice_or_empty(accounts, accounts_len) }
+            .iter()
+            .map(|address| Address::from(*address))
+            .collect();
+
+        let runtime = match tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        {
+            Ok(runtime) => runtime,
+            Err(err) => {
+                set_error(&err.to_string());
+                return ptr::null_mut();
+            }
+        };
+        let provider = ProviderBuilder::new().connect_http(url);
+        let result = runtime.block_on(async {
+            let chain = match exchange {
+                Some(exchange) => Chain::discover(provider.clone(), exchange).await?,
+                None => Chain::testnet(),
+            };
+            state::SnapshotBuilder::new(&chain, provider)
+                .with_accounts(accounts)
+                .build()
+                .await
+        });
+        match result {
+            Ok(exchange) => Box::into_raw(Box::new(DexExchange { exchange })),
+            Err(err) => {
+                set_error(&err.to_string());
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Releases the handle.
+///
+/// # Safety
+///
+/// `exchange` must be null or a handle returned by [`dex_exchange_snapshot`],
+/// not used after this call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dex_exchange_free(exchange: *mut DexExchange) {
+    catch_panic((), || {
+        if !exchange.is_null() {
+            drop(unsafe { Box::from_raw(exchange) });
+        }
+    })
+}
+
+/// Writes the number of the last applied block to `block_number`.
+///
+/// # Safety
+///
+/// `exchange` must be a valid handle, `block_number` writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dex_exchange_block_number(
+    exchange: *const DexExchange,
+    block_number: *mut u64,
+) -> DexStatus {
+    catch_panic(DexStatus::Failed, || {
+        let (Some(handle), Some(out)) = (unsafe { exchange.as_ref() }, unsafe {
+            block_number.as_mut()
+        }) else {
+            set_error("exchange or block_number is null");
+            return DexStatus::InvalidArgument;
+        };
+        *out = handle.exchange.instant().block_number();
+        DexStatus::Ok
+    })
+}
+
+/// Applies the exchange logs of the block, in the order of the log index. Logs of
+/// other contracts have to be filtered out beforehand. Fills of the block are reported
+/// to the callback, if any, before returning.
+///
+/// Blocks up to the last applied one are ignored. On [`DexStatus::Failed`] the handle
+/// has to be released and the snapshot taken again.
+///
+/// # Safety
+///
+/// `exchange` must be a valid handle, `logs` pointing to `logs_len` logs with valid
+/// topics and data pointers, or null if zero.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dex_exchange_apply_logs(
+    exchange: *mut DexExchange,
+    block_number: u64,
+    block_timestamp: u64,
+    logs: *const DexLog,
+    logs_len: usize,
+    on_fill: DexFillCallback,
+    user_data: *mut c_void,
+) -> DexStatus {
+    catch_panic(DexStatus::Failed, || {
+        let Some(handle) = (unsafe { exchange.as_mut() }) else {
+            set_error("exchange is null");
+            return DexStatus::InvalidArgument;
+        };
+        let logs = unsafe { slice_or_empty(logs, logs_len) };
+        let block_events = match unsafe { decode_logs(block_number, block_timestamp, logs) } {
+            Ok(block_events) => block_events,
+            Err(err) => {
+                set_error(&err);
+                return DexStatus::InvalidArgument;
+            }
+        };
+        let state_events = match handle.exchange.apply_events(&block_events) {
+            Ok(state_events) => state_events,
+            Err(err) => {
+                set_error(&err.to_string());
+                return DexStatus::Failed;
+            }
+        };
+        if let (Some(on_fill), Some(state_events)) = (on_fill, state_events) {
+            for (_, event) in state_events.flat_events() {
+                let StateEvents::Order(order) = event else {
+                    continue;
+                };
+                let OrderEventType::Filled {
+                    fill_price,
+                    fill_size,
+                    fee,
+                    is_maker,
+                    fill_id,
+                } = order.r#type
+                else {
+                    continue;
+                };
+                let fill = DexFill {
+                    perpetual_id: order.perpetual_id,
+                    account_id: order.account_id,
+                    order_id: order.order_id.map_or(0, |id| id.get()),
+                    is_maker,
+                    price: fill_price.to_f64(),
+                    size: fill_size.to_f64(),
+                    fee: fee.to_f64(),
+                    tx_hash: fill_id.tx_hash.0,
+                    log_index: fill_id.log_index,
+                };
+                on_fill(&fill, user_data);
+            }
+        }
+        DexStatus::Ok
+    })
+}
+
+/// Writes up to `capacity` best price levels of the order book side to `levels`,
+/// storing the number written to `levels_len`.
+///
+/// # Safety
+///
+/// `exchange` must be a valid handle, `levels` pointing to `capacity` writable levels
+/// or null if zero, `levels_len` writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dex_exchange_book_levels(
+    exchange: *const DexExchange,
+    perpetual_id: u32,
+    side: DexSide,
+    levels: *mut DexLevel,
+    capacity: usize,
+    levels_len: *mut usize,
+) -> DexStatus {
+    catch_panic(DexStatus::Failed, || {
+        let (Some(handle), Some(levels_len)) =
+            (unsafe { exchange.as_ref() }, unsafe { levels_len.as_mut() })
+        else {
+            set_error("exchange or levels_len is null");
+            return DexStatus::InvalidArgument;
+        };
+        *levels_len = 0;
+        let Some(perpetual) = handle.exchange.perpetuals().get(&perpetual_id) else {
+            return DexStatus::NotFound;
+        };
+        if capacity == 0 {
+            return DexStatus::Ok;
+        }
+        if levels.is_null() {
+            set_error("levels is null");
+            return DexStatus::InvalidArgument;
+        }
+        let out = unsafe { slice::from_raw_parts_mut(levels, capacity) };
+        let side = match side {
+            DexSide::Ask => types::OrderSide::Ask,
+            DexSide::Bid => types::OrderSide::Bid,
+        };
+        for (out, level) in out.iter_mut().zip(perpetual.l3_book().view().levels(side)) {
+            *out = DexLevel {
+                price: level.price().to_f64(),
+                size: level.size().to_f64(),
+                num_orders: level.num_orders(),
+            };
+            *levels_len += 1;
+        }
+        DexStatus::Ok
+    })
+}
+
+/// Writes the position of the tracked account in the perpetual contract to `position`.
+///
+/// # Safety
+///
+/// `exchange` must be a valid handle, `position` writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dex_exchange_position(
+    exchange: *const DexExchange,
+    account_id: u32,
+    perpetual_id: u32,
+    position: *mut DexPosition,
+) -> DexStatus {
+    catch_panic(DexStatus::Failed, || {
+        let (Some(handle), Some(out)) =
+            (unsafe { exchange.as_ref() }, unsafe { position.as_mut() })
+        else {
+            set_error("exchange or position is null");
+            return DexStatus::InvalidArgument;
+        };
+        let Some(position) = handle
+            .exchange
+            .accounts()
+            .get(&account_id)
+            .and_then(|account| account.positions().get(&perpetual_id))
+        else {
+            return DexStatus::NotFound;
+        };
+        *out = DexPosition {
+            is_long: position.r#type() == state::PositionType::Long,
+            entry_price: position.entry_price().to_f64(),
+            size: position.size().to_f64(),
+            deposit: position.deposit().to_f64(),
+            pnl: position.pnl().to_f64(),
+            delta_pnl: position.delta_pnl().to_f64(),
+            premium_pnl: position.premium_pnl().to_f64(),
+            liquidation_price: position.liquidation_price().to_f64(),
+        };
+        DexStatus::Ok
+    })
+}
+
+/// Runs the body of the entry point, reporting a panic as the failure with the given
+/// result instead of unwinding into the caller.
+fn catch_panic<T>(on_panic: T, body: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown");
+        set_error(&format!("panicked: {message}"));
+        on_panic
+    })
+}
+
+fn set_error(message: &str) {
+    let message = CString::new(message.replace('\0', " ")).expect("no interior NUL");
+    LAST_ERROR.set(Some(message));
+}
+
+/// # Safety
+///
+/// `ptr` must point to `len` items or be null if zero.
+unsafe fn slice_or_empty<'a, T>(ptr: *const T, len: usize) -> &'a [T] {
+    if ptr.is_null() || len == 0 {
+        &[]
+    } else {
+        unsafe { slice::from_raw_parts(ptr, len) }
+    }
+}
+
+/// # Safety
+///
+/// Topics and data pointers of the logs must be valid for their lengths.
+unsafe fn decode_logs(
+    block_number: u64,
+    block_timestamp: u64,
+    logs: &[DexLog],
+) -> Result<RawBlockEvents, String> {
+    let mut events = Vec::with_capacity(logs.len());
+    let mut unknown = vec![];
+    for log in logs {
+        let tx_hash = TxHash::from(log.tx_hash);
+        let address = Address::from(log.address);
+        let topics = unsafe { slice_or_empty(log.topics, log.topics_len) }
+            .iter()
+            .map(|topic| B256::from(*topic))
+            .collect();
+        let data = Bytes::copy_from_slice(unsafe { slice_or_empty(log.data, log.data_len) });
+        let decoded = Log::new(address, topics, data)
+            .ok_or_else(|| format!("log {} has too many topics", log.log_index))
+            .and_then(|log| stream::decode_log(&log, false).map_err(|err| err.to_string()))
+            .map_err(|err| format!("invalid log {}: {err}", log.log_index))?;
+        match decoded {
+            DecodedLog::Known(event) => events.push(RawEvent::new(
+                tx_hash,
+                log.tx_index,
+                log.log_index,
+                address,
+                event,
+            )),
+            DecodedLog::Unknown(event) => unknown.push(UnknownRawEvent::new(
+                tx_hash,
+                log.tx_index,
+                log.log_index,
+                address,
+                event,
+            )),
+        }
+    }
+    Ok(RawBlockEvents::new(
+        types::StateInstant::new(block_number, block_timestamp),
+        events,
+    )
+    .with_unknown_events(unknown))
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::IntoLogData;
+
+    use super::*;
+    use crate::testing::sim::{Action, Simulator};
+
+    #[test]
+    fn header_is_up_to_date() {
+        assert_eq!(
+            include_str!("../include/dex_sdk.h"),
+            include_str!(concat!(env!("OUT_DIR"), "/dex_sdk.h")),
+            "include/dex_sdk.h is outdated, replace it with the generated one"
+        );
+    }
+
+    extern "C" fn count_fill(_fill: *const DexFill, user_data: *mut c_void) {
+        unsafe { *user_data.cast::<usize>() += 1 };
+    }
+
+    #[test]
+    fn apply_logs_and_query() {
+        let mut sim = Simulator::new(&[1], 2);
+        let mut expected = sim.exchange();
+        let handle = Box::into_raw(Box::new(DexExchange {
+            exchange: sim.exchange(),
+        }));
+        let block = sim.block(&[
+            Action::Place {
+                perpetual: 0,
+                account: 0,
+                is_bid: true,
+                offset: 1,
+                lots: 10,
+            },
+            Action::Fill {
+                order: 0,
+                taker: 1,
+                lots: 4,
+            },
+            Action::Open {
+                perpetual: 0,
+                account: 1,
+                is_long: false,
+                lots: 3,
+            },
+        ]);
+        expected.apply_events(&block).unwrap();
+
+        let log_data = block
+            .events()
+            .iter()
+            .map(|e| e.event.to_log_data())
+            .collect::<Vec<_>>();
+        let logs = block
+            .events()
+            .iter()
+            .zip(&log_data)
+            .map(|(e, data)| DexLog {
+                tx_hash: e.tx_hash.0,
+                tx_index: e.tx_index,
+                log_index: e.log_index,
+                address: e.address.into_array(),
+                topics: data.topics().as_ptr().cast(),
+                topics_len: data.topics().len(),
+                data: data.data.as_ptr(),
+                data_len: data.data.len(),
+            })
+            .collect::<Vec<_>>();
+        let mut fills = 0usize;
+        let status = unsafe {
+            dex_exchange_apply_logs(
+                handle,
+                block.instant().block_number(),
+                block.instant().block_timestamp(),
+                logs.as_ptr(),
+                logs.len(),
+                Some(count_fill),
+                (&raw mut fills).cast(),
+            )
+        };
+        assert_eq!(status, DexStatus::Ok);
+        assert_eq!(
+            unsafe { &*handle }.exchange.state_hash(),
+            expected.state_hash()
+        );
+        assert_eq!(fills, 2);
+        let mut block_number = 0;
+        let status = unsafe { dex_exchange_block_number(handle, &mut block_number) };
+        assert_eq!(status, DexStatus::Ok);
+        assert_eq!(block_number, block.instant().block_number());
+
+        let mut levels = [DexLevel::default(); 4];
+        let mut levels_len = 0;
+        let status = unsafe {
+            dex_exchange_book_levels(
+                handle,
+                1,
+                DexSide::Bid,
+                levels.as_mut_ptr(),
+                levels.len(),
+                &mut levels_len,
+            )
+        };
+        assert_eq!(status, DexStatus::Ok);
+        let (price, size) = expected.perpetuals()[&1].l3_book().best_bid().unwrap();
+        assert_eq!(levels_len, 1);
+        assert_eq!(levels[0].price, price.to_f64());
+        assert_eq!(levels[0].size, size.to_f64());
+
+        let mut position = DexPosition::default();
+        let status = unsafe { dex_exchange_position(handle, 2, 1, &mut position) };
+        assert_eq!(status, DexStatus::Ok);
+        assert!(!position.is_long);
+        assert_eq!(
+            position.size,
+            expected.accounts()[&2].positions()[&1].size().to_f64()
+        );
+        let status = unsafe { dex_exchange_position(handle, 2, 2, &mut position) };
+        assert_eq!(status, DexStatus::NotFound);
+
+        unsafe { dex_exchange_free(handle) };
+    }
+
+    #[test]
+    fn null_handle_and_panic_are_reported() {
+        let mut block_number = 0;
+        let status = unsafe { dex_exchange_block_number(ptr::null(), &mut block_number) };
+        assert_eq!(status, DexStatus::InvalidArgument);
+
+        let status = catch_panic(DexStatus::Failed, || panic!("inconsistent state"));
+        assert_eq!(status, DexStatus::Failed);
+        let message = unsafe { CStr::from_ptr(dex_last_error()) };
+        assert_eq!(message.to_str().unwrap(), "panicked: inconsistent state");
+    }
+}
@@ -0,0 +1,573 @@
+//! Entry point bundling chain configuration with a provider.
+//!
+//! [`DexClient`] is parameterized by its access mode: a client constructed with
+//! [`DexClient::read_only`] exposes only snapshot, stream and state APIs, while
+//! mutating methods exist only on clients constructed with [`DexClient::signing`].
+//! Indexer deployments can rely on the compiler to guarantee they never
+//! require signing keys:
+//!
+//! ```compile_fail
+//! # use dex_sdk::{Chain, client::DexClient};
+//! # async fn f(provider: alloy::providers::DynProvider, exchange: dex_sdk::state::Exchange) {
+//! let client = DexClient::read_only(Chain::testnet(), provider);
+//! client.send_orders(&exchange, &[]).await; // not available in read-only mode
+//! # }
+//! ```
+//!
+//! For the common case the client can also keep the exchange state up to date by itself,
+//! see [`DexClient::track`]:
+//!
+//! ```ignore
+//! let mut client = DexClient::signing(Chain::testnet(), provider, address);
+//! let handle = client
+//!     .track(client.snapshot().with_accounts(vec![address]), tokio::time::sleep)
+//!     .await?;
+//! let mut events = client.events()?;
+//!
+//! let book = client.book(perp_id).await?;
+//! let pending = client.submit_orders(&[request]).await?;
+//! while let Ok(block_events) = events.recv().await {
+//!     println!("{:?}", block_events);
+//! }
+//! ```
+
+use std::{
+    sync::{Arc, Weak},
+    time::Duration,
+};
+
+use alloy::{
+    network::Ethereum,
+    primitives::{Address, U256},
+    providers::{PendingTransactionBuilder, Provider},
+};
+use fastnum::{UD64, UD128};
+use futures::{Stream, StreamExt};
+use tokio::sync::{RwLock, broadcast};
+
+use crate::{
+    Chain,
+    abi::{
+        dex::Exchange::{ExchangeInstance, OrderDesc},
+        erc20::IERC20::IERC20Instance,
+    },
+    collateral,
+    error::DexError,
+    execution, num, state,
+    stream::{self, RawBlockEvents},
+    types,
+};
+
+/// Capacity of the state events channel, see [`DexClient::events`].
+const EVENTS_CHANNEL_SIZE: usize = 256;
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Access mode of the [`DexClient`].
+pub trait Mode: sealed::Sealed {}
+
+/// Watch-only access mode, no signing address is known to the client.
+#[derive(Clone, Copy, Debug)]
+pub struct ReadOnly;
+
+/// Signing access mode, transactions are sent from the given address,
+/// which the [`Provider`] is expected to sign for.
+#[derive(Clone, Copy, Debug)]
+pub struct Signing {
+    address: Address,
+}
+
+impl sealed::Sealed for ReadOnly {}
+impl sealed::Sealed for Signing {}
+impl Mode for ReadOnly {}
+impl Mode for Signing {}
+
+/// Client of the exchange deployed on the given chain.
+#[derive(Clone, Debug)]
+pub struct DexClient<P, M: Mode = ReadOnly> {
+    chain: Chain,
+    provider: P,
+    mode: M,
+    tracked: Option<Tracked>,
+    guardrails: Option<Arc<execution::Guardrails>>,
+    order_presets: Arc<types::OrderPresets>,
+    allowances: Arc<collateral::AllowanceManager>,
+}
+
+/// Exchange state kept up to date by the client, see [`DexClient::track`].
+#[derive(Clone, Debug)]
+struct Tracked {
+    exchange: Arc<RwLock<state::Exchange>>,
+    events: broadcast::Sender<Arc<state::StateBlockEvents>>,
+}
+
+impl<P: Provider + Clone> DexClient<P, ReadOnly> {
+    /// Create a watch-only client, which can not send transactions.
+    pub fn read_only(chain: Chain, provider: P) -> Self {
+        Self {
+            provider,
+            mode: ReadOnly,
+            tracked: None,
+            guardrails: None,
+            order_presets: Default::default(),
+            allowances: Arc::new(collateral::AllowanceManager::new(&chain)),
+            chain,
+        }
+    }
+}
+
+impl<P: Provider + Clone> DexClient<P, Signing> {
+    /// Create a client sending transactions from the given address.
+    pub fn signing(chain: Chain, provider: P, address: Address) -> Self {
+        Self {
+            provider,
+            mode: Signing { address },
+            tracked: None,
+            guardrails: None,
+            order_presets: Default::default(),
+            allowances: Arc::new(collateral::AllowanceManager::new(&chain)),
+            chain,
+        }
+    }
+
+    /// Address transactions are sent from.
+    pub fn address(&self) -> Address {
+        self.mode.address
+    }
+
+    /// Downgrade to the watch-only client sharing the same provider and tracked state.
+    pub fn into_read_only(self) -> DexClient<P, ReadOnly> {
+        DexClient {
+            chain: self.chain,
+            provider: self.provider,
+            mode: ReadOnly,
+            tracked: self.tracked,
+            guardrails: None,
+            order_presets: Default::default(),
+            allowances: self.allowances,
+        }
+    }
+
+    /// Check order requests against the guardrails before sending them with
+    /// [`Self::submit_orders`] and [`Self::send_orders`]. Clones of the client share
+    /// the guardrails, including the count of orders sent per block.
+    pub fn with_guardrails(mut self, guardrails: execution::Guardrails) -> Self {
+        self.guardrails = Some(Arc::new(guardrails));
+        self
+    }
+
+    /// Guardrails order requests are checked against, if any.
+    pub fn guardrails(&self) -> Option<&execution::Guardrails> {
+        self.guardrails.as_deref()
+    }
+
+    /// Fill in the shorthand order requests with the given per-perpetual defaults,
+    /// see [`Self::buy`] and [`Self::sell`].
+    pub fn with_order_presets(mut self, presets: types::OrderPresets) -> Self {
+        self.order_presets = Arc::new(presets);
+        self
+    }
+
+    /// Defaults of the shorthand order requests.
+    pub fn order_presets(&self) -> &types::OrderPresets {
+        &self.order_presets
+    }
+
+    /// Limit order request opening/increasing a long position, with leverage, expiry
+    /// and execution policy taken from the order presets as of the state block,
+    /// see [`Self::with_order_presets`].
+    pub fn buy(
+        &self,
+        exchange: &state::Exchange,
+        request_id: types::RequestId,
+        perp_id: types::PerpetualId,
+        price: UD64,
+        size: UD64,
+    ) -> types::OrderRequest {
+        let block_number = exchange.instant().block_number();
+        self.order_presets
+            .buy(request_id, perp_id, price, size, block_number)
+    }
+
+    /// Limit order request opening/increasing a short position, with leverage, expiry
+    /// and execution policy taken from the order presets as of the state block,
+    /// see [`Self::with_order_presets`].
+    pub fn sell(
+        &self,
+        exchange: &state::Exchange,
+        request_id: types::RequestId,
+        perp_id: types::PerpetualId,
+        price: UD64,
+        size: UD64,
+    ) -> types::OrderRequest {
+        let block_number = exchange.instant().block_number();
+        self.order_presets
+            .sell(request_id, perp_id, price, size, block_number)
+    }
+
+    /// Collateral approval policy used by [`Self::create_account`] and [`Self::deposit`]
+    /// (default: exact), see [`collateral::ApprovalPolicy`].
+    pub fn with_approval_policy(mut self, policy: collateral::ApprovalPolicy) -> Self {
+        self.allowances =
+            Arc::new(collateral::AllowanceManager::new(&self.chain).with_policy(policy));
+        self
+    }
+
+    /// Collateral allowance manager of the client address.
+    pub fn allowances(&self) -> &collateral::AllowanceManager {
+        &self.allowances
+    }
+
+    /// Create the exchange account of the client address with the initial deposit,
+    /// approving the collateral transfer first if needed.
+    pub async fn create_account(
+        &self,
+        amount: UD128,
+    ) -> Result<PendingTransactionBuilder<Ethereum>, DexError> {
+        let amount = self.collateral_amount(amount).await?;
+        self.allowances
+            .ensure(&self.provider, self.mode.address, amount)
+            .await?;
+        let pending = ExchangeInstance::new(self.chain.exchange(), &self.provider)
+            .createAccount(amount)
+            .from(self.mode.address)
+            .send()
+            .await?;
+        self.allowances.spent(self.mode.address, amount);
+        Ok(pending)
+    }
+
+    /// Deposit collateral to the account of the client address,
+    /// approving the collateral transfer first if needed.
+    pub async fn deposit(
+        &self,
+        amount: UD128,
+    ) -> Result<PendingTransactionBuilder<Ethereum>, DexError> {
+        let amount = self.collateral_amount(amount).await?;
+        self.allowances
+            .ensure(&self.provider, self.mode.address, amount)
+            .await?;
+        let pending = ExchangeInstance::new(self.chain.exchange(), &self.provider)
+            .depositCollateral(amount)
+            .from(self.mode.address)
+            .send()
+            .await?;
+        self.allowances.spent(self.mode.address, amount);
+        Ok(pending)
+    }
+
+    /// Withdraw collateral from the account of the client address back to the address.
+    ///
+    /// Withdrawals are subject to the exchange-wide rate limit,
+    /// see [`state::Exchange::withdraw_rate_limit`].
+    pub async fn withdraw(
+        &self,
+        amount: UD128,
+    ) -> Result<PendingTransactionBuilder<Ethereum>, DexError> {
+        let amount = self.collateral_amount(amount).await?;
+        Ok(ExchangeInstance::new(self.chain.exchange(), &self.provider)
+            .withdrawCollateral(amount)
+            .from(self.mode.address)
+            .send()
+            .await?)
+    }
+
+    /// Allow or disallow the exchange administrator to forward orders on behalf of
+    /// the account of the client address, see [`state::Account::order_forwarding`].
+    pub async fn allow_order_forwarding(
+        &self,
+        allow: bool,
+    ) -> Result<PendingTransactionBuilder<Ethereum>, DexError> {
+        Ok(ExchangeInstance::new(self.chain.exchange(), &self.provider)
+            .allowOrderForwarding(allow)
+            .from(self.mode.address)
+            .send()
+            .await?)
+    }
+
+    /// Transfer collateral tokens held by the client address to another address.
+    pub async fn transfer_collateral(
+        &self,
+        to: Address,
+        amount: UD128,
+    ) -> Result<PendingTransactionBuilder<Ethereum>, DexError> {
+        let amount = self.collateral_amount(amount).await?;
+        Ok(
+            IERC20Instance::new(self.chain.collateral_token(), &self.provider)
+                .transfer(to, amount)
+                .from(self.mode.address)
+                .send()
+                .await?,
+        )
+    }
+
+    /// Collateral amount in token units.
+    async fn collateral_amount(&self, amount: UD128) -> Result<U256, DexError> {
+        let decimals = match self.chain.collateral_decimals() {
+            Some(decimals) => decimals,
+            None => ExchangeInstance::new(self.chain.exchange(), &self.provider)
+                .getExchangeInfo()
+                .call()
+                .await?
+                .collateralDecimals
+                .to(),
+        };
+        Ok(num::Converter::new(decimals).to_unsigned(amount))
+    }
+
+    /// Send the order requests in a single `execOpsAndOrders` transaction,
+    /// preparing them against the tracked state, see [`Self::track`].
+    pub async fn submit_orders(
+        &self,
+        requests: &[types::OrderRequest],
+    ) -> Result<PendingTransactionBuilder<Ethereum>, DexError> {
+        // Not holding the state across sending, so block application is not blocked
+        let descs = {
+            let exchange = self.tracked()?.exchange.read().await;
+            self.prepare_orders(&exchange, requests)?
+        };
+        self.send_descs(descs).await
+    }
+
+    /// Send the order requests in a single `execOpsAndOrders` transaction.
+    ///
+    /// Fails with [`DexError::Guardrail`] without sending if the requests
+    /// violate the guardrails, see [`Self::with_guardrails`].
+    ///
+    /// Submissions are suspended with [`DexError::AccountFrozen`] while the account of
+    /// the client address is frozen in the given state, and resume once it gets unfrozen,
+    /// see [`state::Account::frozen_since`]. Likewise, they fail with
+    /// [`DexError::VenueUnavailable`] while the exchange is halted or the targeted
+    /// perpetual contract is paused, see [`state::Exchange::ensure_available`].
+    pub async fn send_orders(
+        &self,
+        exchange: &state::Exchange,
+        requests: &[types::OrderRequest],
+    ) -> Result<PendingTransactionBuilder<Ethereum>, DexError> {
+        let descs = self.prepare_orders(exchange, requests)?;
+        self.send_descs(descs).await
+    }
+
+    /// Checks the order requests against the state and the guardrails,
+    /// preparing them for sending, see [`Self::send_orders`].
+    fn prepare_orders(
+        &self,
+        exchange: &state::Exchange,
+        requests: &[types::OrderRequest],
+    ) -> Result<Vec<OrderDesc>, DexError> {
+        if let Some(account) = exchange.account_by_address(self.mode.address) {
+            account.ensure_not_frozen()?;
+        }
+        exchange.ensure_requests_available(requests)?;
+        if let Some(guardrails) = &self.guardrails {
+            guardrails.admit(exchange, self.mode.address, requests)?;
+        }
+        Ok(requests.iter().map(|r| r.prepare(exchange)).collect())
+    }
+
+    async fn send_descs(
+        &self,
+        descs: Vec<OrderDesc>,
+    ) -> Result<PendingTransactionBuilder<Ethereum>, DexError> {
+        Ok(ExchangeInstance::new(self.chain.exchange(), &self.provider)
+            .execOpsAndOrders(vec![], descs, false)
+            .from(self.mode.address)
+            .send()
+            .await?)
+    }
+
+    /// Cancel all resting orders and close all positions of the client account,
+    /// see [`execution::flatten_account`].
+    pub async fn flatten(
+        &self,
+        exchange: &state::Exchange,
+        max_slippage: UD64,
+        first_request_id: types::RequestId,
+    ) -> Result<execution::FlattenReport, DexError> {
+        let account_id = exchange
+            .account_id_by_address(self.mode.address)
+            .ok_or_else(|| {
+                DexError::InvalidRequest(format!("no account of {}", self.mode.address))
+            })?;
+        execution::flatten_account(
+            &self.provider,
+            exchange,
+            account_id,
+            max_slippage,
+            first_request_id,
+        )
+        .await
+    }
+
+    /// Estimate gas usage and cost of sending the order requests,
+    /// see [`execution::estimate_batch`].
+    pub async fn estimate_orders(
+        &self,
+        exchange: &state::Exchange,
+        requests: &[types::OrderRequest],
+        native_price: UD64,
+    ) -> Result<execution::GasEstimate, DexError> {
+        execution::estimate_batch(
+            &self.provider,
+            exchange,
+            self.mode.address,
+            requests,
+            native_price,
+        )
+        .await
+    }
+
+    /// Start the order [`execution::Batcher`] sending transactions via this client provider.
+    pub fn batcher<S, SFut>(
+        &self,
+        exchange: &state::Exchange,
+        config: execution::BatcherConfig,
+        sleep: S,
+    ) -> (
+        execution::Batcher,
+        tokio::task::JoinHandle<Result<(), DexError>>,
+    )
+    where
+        P: 'static,
+        S: Fn(Duration) -> SFut + Copy + Send + 'static,
+        SFut: Future<Output = ()> + Send,
+    {
+        execution::Batcher::start(exchange, self.provider.clone(), config, sleep)
+    }
+}
+
+impl<P: Provider + Clone, M: Mode> DexClient<P, M> {
+    pub fn chain(&self) -> &Chain {
+        &self.chain
+    }
+
+    pub fn provider(&self) -> &P {
+        &self.provider
+    }
+
+    /// Snapshot builder of the exchange state, see [`state::SnapshotBuilder`].
+    pub fn snapshot(&self) -> state::SnapshotBuilder<P> {
+        state::SnapshotBuilder::new(&self.chain, self.provider.clone())
+    }
+
+    /// Stream of raw exchange events starting from the given instant,
+    /// see [`stream::raw`].
+    pub fn raw_stream<S, SFut>(
+        &self,
+        from: types::StateInstant,
+        sleep: S,
+    ) -> impl Stream<Item = Result<RawBlockEvents, DexError>>
+    where
+        S: Fn(Duration) -> SFut + Copy,
+        SFut: Future<Output = ()>,
+    {
+        stream::raw(&self.chain, self.provider.clone(), from, sleep)
+    }
+
+    /// Build the snapshot and keep it up to date with the events in the background,
+    /// making [`Self::state`], [`Self::book`], [`Self::account`], [`Self::events`]
+    /// and [`Self::submit_orders`] available. Replaces the previously tracked state, if any.
+    ///
+    /// Tracking stops once the client and all its clones are dropped, or on the first
+    /// event stream or [`state::Exchange::apply_events`] failure returned by the handle.
+    pub async fn track<S, SFut>(
+        &mut self,
+        snapshot: state::SnapshotBuilder<P>,
+        sleep: S,
+    ) -> Result<tokio::task::JoinHandle<Result<(), DexError>>, DexError>
+    where
+        P: Send + Sync + 'static,
+        S: Fn(Duration) -> SFut + Copy + Send + Sync + 'static,
+        SFut: Future<Output = ()> + Send + 'static,
+    {
+        let exchange = snapshot.build().await?;
+        let from = exchange.instant();
+        let tracked = Tracked {
+            exchange: Arc::new(RwLock::new(exchange)),
+            events: broadcast::channel(EVENTS_CHANNEL_SIZE).0,
+        };
+        let handle = tokio::spawn(apply_loop(
+            self.chain.clone(),
+            self.provider.clone(),
+            from,
+            Arc::downgrade(&tracked.exchange),
+            tracked.events.clone(),
+            sleep,
+        ));
+        self.tracked = Some(tracked);
+        Ok(handle)
+    }
+
+    /// Exchange state tracked by the client, see [`Self::track`].
+    pub fn state(&self) -> Option<&Arc<RwLock<state::Exchange>>> {
+        self.tracked.as_ref().map(|t| &t.exchange)
+    }
+
+    /// Copy of the order book of the perpetual contract from the tracked state.
+    pub async fn book(&self, perp_id: types::PerpetualId) -> Result<state::OrderBook, DexError> {
+        self.tracked()?
+            .exchange
+            .read()
+            .await
+            .perpetuals()
+            .get(&perp_id)
+            .map(|perp| perp.l3_book().clone())
+            .ok_or_else(|| DexError::InvalidRequest(format!("unknown perpetual: {perp_id}")))
+    }
+
+    /// Copy of the account with the given address from the tracked state, if tracked.
+    pub async fn account(&self, address: Address) -> Result<Option<state::Account>, DexError> {
+        Ok(self
+            .tracked()?
+            .exchange
+            .read()
+            .await
+            .account_by_address(address)
+            .cloned())
+    }
+
+    /// Subscribe to the state events produced by the tracked state updates.
+    ///
+    /// Receivers falling behind by more than 256 blocks miss the oldest ones,
+    /// see [`broadcast::error::RecvError::Lagged`].
+    pub fn events(&self) -> Result<broadcast::Receiver<Arc<state::StateBlockEvents>>, DexError> {
+        Ok(self.tracked()?.events.subscribe())
+    }
+
+    fn tracked(&self) -> Result<&Tracked, DexError> {
+        self.tracked.as_ref().ok_or_else(|| {
+            DexError::InvalidRequest("exchange state is not tracked by the client".to_string())
+        })
+    }
+}
+
+async fn apply_loop<P, S, SFut>(
+    chain: Chain,
+    provider: P,
+    from: types::StateInstant,
+    exchange: Weak<RwLock<state::Exchange>>,
+    events: broadcast::Sender<Arc<state::StateBlockEvents>>,
+    sleep: S,
+) -> Result<(), DexError>
+where
+    P: Provider,
+    S: Fn(Duration) -> SFut + Copy,
+    SFut: Future<Output = ()>,
+{
+    let stream = stream::raw(&chain, provider, from, sleep);
+    futures::pin_mut!(stream);
+    while let Some(block_events) = stream.next().await {
+        let block_events = block_events?;
+        let Some(exchange) = exchange.upgrade() else {
+            // All clients dropped, graceful shutdown
+            break;
+        };
+        if let Some(state_events) = exchange.write().await.apply_events(&block_events)? {
+            // No subscribers is not an error
+            let _ = events.send(Arc::new(state_events));
+        }
+    }
+    Ok(())
+}
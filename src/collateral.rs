@@ -0,0 +1,186 @@
+//! Collateral token approvals.
+//!
+//! Deposits and account creation transfer collateral tokens from the depositing address
+//! to the exchange, which requires a sufficient ERC-20 allowance. [`AllowanceManager`]
+//! checks the allowance before such transactions and submits approvals when it falls
+//! short, according to the [`ApprovalPolicy`].
+//!
+//! Known allowances are cached per owner address, so repeated deposits do not query
+//! the token each time. The cache is updated with the approvals and deposits sent via
+//! the manager, approvals or transfers made elsewhere are picked up after
+//! [`AllowanceManager::invalidate`].
+//!
+//! # Example
+//!
+//! ```ignore
+//! use dex_sdk::{DexClient, collateral::ApprovalPolicy};
+//!
+//! let client = DexClient::signing(chain, provider, address)
+//!     .with_approval_policy(ApprovalPolicy::Infinite);
+//!
+//! // Approves the exchange first if needed
+//! client.create_account(udec128!(1000)).await?.get_receipt().await?;
+//! client.deposit(udec128!(500)).await?.get_receipt().await?;
+//! ```
+
+use std::{collections::HashMap, sync::Mutex};
+
+use alloy::{
+    primitives::{Address, U256},
+    providers::Provider,
+};
+
+use crate::{
+    Chain,
+    abi::erc20::IERC20::IERC20Instance,
+    error::{DexError, RevertReason},
+};
+
+/// Amount approved when the allowance falls short.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ApprovalPolicy {
+    /// Approve exactly the amount required by the transaction, so no collateral
+    /// is left approved afterwards.
+    #[default]
+    Exact,
+
+    /// Approve the maximal amount once, so further transactions need no approvals.
+    Infinite,
+}
+
+/// Checks and tops up the collateral token allowance of the exchange,
+/// see the [module](self) documentation.
+#[derive(Debug)]
+pub struct AllowanceManager {
+    token: Address,
+    spender: Address,
+    policy: ApprovalPolicy,
+    allowances: Mutex<HashMap<Address, U256>>,
+}
+
+impl AllowanceManager {
+    /// Create a manager of the collateral token allowance of the chain exchange.
+    pub fn new(chain: &Chain) -> Self {
+        Self {
+            token: chain.collateral_token(),
+            spender: chain.exchange(),
+            policy: ApprovalPolicy::default(),
+            allowances: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Amount to approve when the allowance falls short (default: exact).
+    pub fn with_policy(mut self, policy: ApprovalPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn policy(&self) -> ApprovalPolicy {
+        self.policy
+    }
+
+    /// Cached allowance of the owner, if known.
+    pub fn cached(&self, owner: Address) -> Option<U256> {
+        self.cache().get(&owner).copied()
+    }
+
+    /// Current allowance of the owner, queried from the token unless cached.
+    pub async fn allowance<P: Provider>(
+        &self,
+        provider: &P,
+        owner: Address,
+    ) -> Result<U256, DexError> {
+        if let Some(allowance) = self.cached(owner) {
+            return Ok(allowance);
+        }
+        let allowance = IERC20Instance::new(self.token, provider)
+            .allowance(owner, self.spender)
+            .call()
+            .await?;
+        self.cache().insert(owner, allowance);
+        Ok(allowance)
+    }
+
+    /// Make sure the owner allows the exchange to transfer at least the given amount
+    /// of collateral (in token units), approving it according to the policy
+    /// and waiting for the approval to get included otherwise.
+    ///
+    /// Returns `true` if an approval was sent.
+    pub async fn ensure<P: Provider>(
+        &self,
+        provider: &P,
+        owner: Address,
+        amount: U256,
+    ) -> Result<bool, DexError> {
+        if self.allowance(provider, owner).await? >= amount {
+            return Ok(false);
+        }
+        let approved = match self.policy {
+            ApprovalPolicy::Exact => amount,
+            ApprovalPolicy::Infinite => U256::MAX,
+        };
+        let receipt = IERC20Instance::new(self.token, provider)
+            .approve(self.spender, approved)
+            .from(owner)
+            .send()
+            .await
+            .inspect_err(|_| self.invalidate(owner))?
+            .get_receipt()
+            .await
+            .inspect_err(|_| self.invalidate(owner))?;
+        if !receipt.status() {
+            self.invalidate(owner);
+            return Err(DexError::Reverted(Box::new(RevertReason::Generic(
+                format!("approval {} failed", receipt.transaction_hash),
+            ))));
+        }
+        self.cache().insert(owner, approved);
+        Ok(true)
+    }
+
+    /// Account for the amount transferred by the exchange from the owner,
+    /// to be called once the transaction is sent.
+    pub fn spent(&self, owner: Address, amount: U256) {
+        if let Some(allowance) = self.cache().get_mut(&owner) {
+            // Infinite allowance is not decreased by the token
+            if *allowance != U256::MAX {
+                *allowance = allowance.saturating_sub(amount);
+            }
+        }
+    }
+
+    /// Forget the cached allowance of the owner, e.g. after an approval sent elsewhere.
+    pub fn invalidate(&self, owner: Address) {
+        self.cache().remove(&owner);
+    }
+
+    fn cache(&self) -> std::sync::MutexGuard<'_, HashMap<Address, U256>> {
+        self.allowances.lock().expect("allowance cache lock")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spent_allowance() {
+        let manager = AllowanceManager::new(&Chain::testnet());
+        let owner = Address::with_last_byte(1);
+        manager.spent(owner, U256::from(10));
+        assert_eq!(manager.cached(owner), None);
+
+        manager.cache().insert(owner, U256::from(100));
+        manager.spent(owner, U256::from(30));
+        assert_eq!(manager.cached(owner), Some(U256::from(70)));
+        manager.spent(owner, U256::from(100));
+        assert_eq!(manager.cached(owner), Some(U256::ZERO));
+
+        manager.cache().insert(owner, U256::MAX);
+        manager.spent(owner, U256::from(30));
+        assert_eq!(manager.cached(owner), Some(U256::MAX));
+
+        manager.invalidate(owner);
+        assert_eq!(manager.cached(owner), None);
+    }
+}
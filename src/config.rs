@@ -0,0 +1,246 @@
+//! Hot-reloadable configuration of strategy binaries.
+//!
+//! [`Watcher`] loads the configuration file with the parser provided by the binary
+//! and publishes the latest valid version via [`tokio::sync::watch`] channel, so
+//! strategies pick up changed thresholds and filters without restarting.
+//!
+//! Reloads are triggered either by the background job polling the file
+//! modification time ([`Watcher::run`]) or explicitly with [`Watcher::reload`],
+//! e.g. from the SIGHUP handler of the binary. Configuration failing to read or parse
+//! gets reported and the previous one stays in effect.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use dex_sdk::config::{DEFAULT_POLL_INTERVAL, Watcher};
+//!
+//! let watcher = Arc::new(Watcher::new("strategy.conf", parse_strategy_config)?);
+//! let mut config = watcher.subscribe();
+//! let (mut reloads, _) = watcher.clone().run(DEFAULT_POLL_INTERVAL, tokio::time::sleep);
+//! tokio::spawn(async move {
+//!     while let Some(reload) = reloads.recv().await {
+//!         if let Err(err) = reload {
+//!             eprintln!("keeping previous configuration: {err}");
+//!         }
+//!     }
+//! });
+//!
+//! loop {
+//!     let params = config.borrow_and_update().clone();
+//!     // ... run the strategy iteration with `params`
+//! }
+//! ```
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use tokio::sync::{mpsc, watch};
+
+use crate::error::DexError;
+
+/// Default interval of polling the configuration file for changes.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+const DEFAULT_CHANNEL_SIZE: usize = 16;
+
+type Parser<T> = Box<dyn Fn(&str) -> Result<T, String> + Send + Sync>;
+
+/// Configuration file watcher, see the [module docs](self).
+pub struct Watcher<T> {
+    path: PathBuf,
+    parse: Parser<T>,
+    /// Modification time and content of the last applied or rejected file.
+    seen: Mutex<(Option<SystemTime>, String)>,
+    tx: watch::Sender<Arc<T>>,
+}
+
+impl<T: Send + Sync + 'static> Watcher<T> {
+    /// Loads the initial configuration, failing if the file cannot be read or parsed.
+    pub fn new(
+        path: impl Into<PathBuf>,
+        parse: impl Fn(&str) -> Result<T, String> + Send + Sync + 'static,
+    ) -> Result<Self, DexError> {
+        let path = path.into();
+        let (modified, content) = read(&path)?;
+        let config = parse(&content).map_err(|err| config_error(&path, err))?;
+        Ok(Self {
+            path,
+            parse: Box::new(parse),
+            seen: Mutex::new((modified, content)),
+            tx: watch::channel(Arc::new(config)).0,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Configuration currently in effect.
+    pub fn current(&self) -> Arc<T> {
+        self.tx.borrow().clone()
+    }
+
+    /// Receiver notified on every applied configuration change.
+    pub fn subscribe(&self) -> watch::Receiver<Arc<T>> {
+        self.tx.subscribe()
+    }
+
+    /// Re-reads the configuration file and applies it if the content changed,
+    /// returning whether the new configuration got applied.
+    ///
+    /// Failing configuration is not applied and the previous one stays in effect.
+    pub fn reload(&self) -> Result<bool, DexError> {
+        let (modified, content) = read(&self.path)?;
+        self.apply(modified, content)
+    }
+
+    /// Same as [`Self::reload`], but reads the file only if its modification time changed
+    /// since the last reload.
+    pub fn poll(&self) -> Result<bool, DexError> {
+        let modified = std::fs::metadata(&self.path)
+            .and_then(|meta| meta.modified())
+            .map_err(|err| config_error(&self.path, err))?;
+        if self.lock().0 == Some(modified) {
+            return Ok(false);
+        }
+        self.reload()
+    }
+
+    /// Start the job polling the configuration file for changes with the given interval.
+    ///
+    /// Every applied configuration and every failed reload get reported.
+    ///
+    /// Job stops once the report receiver is dropped.
+    pub fn run<S, SFut>(
+        self: Arc<Self>,
+        interval: Duration,
+        sleep: S,
+    ) -> (
+        mpsc::Receiver<Result<Arc<T>, DexError>>,
+        tokio::task::JoinHandle<()>,
+    )
+    where
+        S: Fn(Duration) -> SFut + Send + 'static,
+        SFut: Future<Output = ()> + Send,
+    {
+        let (tx, rx) = mpsc::channel(DEFAULT_CHANNEL_SIZE);
+        let handle = tokio::spawn(async move {
+            while !tx.is_closed() {
+                sleep(interval).await;
+                let report = match self.poll() {
+                    Ok(false) => continue,
+                    Ok(true) => Ok(self.current()),
+                    Err(err) => Err(err),
+                };
+                if tx.send(report).await.is_err() {
+                    // Receiver dropped, graceful shutdown
+                    break;
+                }
+            }
+        });
+        (rx, handle)
+    }
+
+    fn apply(&self, modified: Option<SystemTime>, content: String) -> Result<bool, DexError> {
+        let mut seen = self.lock();
+        seen.0 = modified;
+        if seen.1 == content {
+            return Ok(false);
+        }
+        // Remember rejected content too, so it gets reported only once
+        let parsed = (self.parse)(&content);
+        seen.1 = content;
+        let config = parsed.map_err(|err| config_error(&self.path, err))?;
+        self.tx.send_replace(Arc::new(config));
+        Ok(true)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, (Option<SystemTime>, String)> {
+        self.seen.lock().expect("config watcher lock")
+    }
+}
+
+impl<T> std::fmt::Debug for Watcher<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Watcher")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+fn read(path: &Path) -> Result<(Option<SystemTime>, String), DexError> {
+    let content = std::fs::read_to_string(path).map_err(|err| config_error(path, err))?;
+    let modified = std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok();
+    Ok((modified, content))
+}
+
+fn config_error(path: &Path, err: impl std::fmt::Display) -> DexError {
+    DexError::Config(format!("{}: {err}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("dex-sdk-config-{}-{name}", std::process::id()))
+    }
+
+    fn write(path: &Path, content: &str, modified: SystemTime) {
+        std::fs::write(path, content).unwrap();
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+
+    fn parse(content: &str) -> Result<u32, String> {
+        content.trim().parse().map_err(|err| format!("{err}"))
+    }
+
+    #[test]
+    fn reload_keeps_last_valid() {
+        let path = temp_file("reload");
+        let t0 = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        write(&path, "5", t0);
+        assert!(Watcher::new(temp_file("missing"), parse).is_err());
+
+        let watcher = Watcher::new(&path, parse).unwrap();
+        let mut rx = watcher.subscribe();
+        assert_eq!(*watcher.current(), 5);
+        assert!(!watcher.reload().unwrap());
+        assert!(!watcher.poll().unwrap());
+
+        // Modification time unchanged, polling does not notice the change
+        write(&path, "7", t0);
+        assert!(!watcher.poll().unwrap());
+        assert!(watcher.reload().unwrap());
+        assert_eq!(*watcher.current(), 7);
+        assert!(rx.has_changed().unwrap());
+        assert_eq!(**rx.borrow_and_update(), 7);
+
+        // Invalid configuration is reported once and not applied
+        write(&path, "seven", t0 + Duration::from_secs(1));
+        assert!(matches!(watcher.poll(), Err(DexError::Config(_))));
+        assert!(!watcher.reload().unwrap());
+        assert_eq!(*watcher.current(), 7);
+        assert!(!rx.has_changed().unwrap());
+
+        write(&path, "9\n", t0 + Duration::from_secs(2));
+        assert!(watcher.poll().unwrap());
+        assert_eq!(**rx.borrow_and_update(), 9);
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(watcher.reload().is_err());
+        assert_eq!(*watcher.current(), 9);
+    }
+}
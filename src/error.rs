@@ -1,16 +1,23 @@
+#[cfg(feature = "rpc")]
 use std::fmt::Display;
 
+#[cfg(feature = "rpc")]
 use alloy::{
     contract,
-    primitives::Bytes,
     providers::{MulticallError, PendingTransactionError},
-    sol_types::{self, SolInterface},
     transports,
 };
+use alloy::{
+    primitives::{Address, Bytes},
+    sol_types::{self, SolInterface},
+};
 
+#[cfg(feature = "rpc")]
+use crate::execution::GuardrailViolation;
 use crate::{
     abi::errors::Exchange::ExchangeErrors,
-    state::{OrderBookError, OrderParseError},
+    num::ConversionError,
+    state::{IncompatibleContract, OrderBookError, OrderParseError, VenueUnavailable},
     types,
 };
 
@@ -63,13 +70,57 @@ pub enum ProviderError<R> {
     #[error("position not found, acc: {0}, perp: {1}")]
     PositionNotFound(types::AccountId, types::PerpetualId),
 
+    #[error("no checkpoint at or before block: {0}")]
+    CheckpointNotFound(u64),
+
+    #[error("operation cancelled")]
+    Cancelled,
+
+    #[error("batcher is closed")]
+    BatcherClosed,
+
+    #[error("order request {0} already submitted")]
+    DuplicateRequest(types::RequestId),
+
+    #[error("batch submission failed: {0}")]
+    BatchFailed(String),
+
+    #[error("resume token block hash mismatch at block: {0}")]
+    ResumeTokenMismatch(u64),
+
+    #[error("journal error: {0}")]
+    Journal(String),
+
+    #[error("configuration error: {0}")]
+    Config(String),
+
     #[error("order book error: {0}")]
     OrderBook(#[from] OrderBookError),
 
     #[error("order parse error: {0}")]
     OrderParse(#[from] OrderParseError),
+
+    #[error("conversion error: {0}")]
+    Conversion(#[from] ConversionError),
+
+    #[error("account {0} is frozen since block {1}")]
+    AccountFrozen(types::AccountId, u64),
+
+    #[error("venue unavailable: {0}")]
+    VenueUnavailable(#[from] VenueUnavailable),
+
+    #[cfg(feature = "rpc")]
+    #[error("guardrail violation: {0}")]
+    Guardrail(#[from] GuardrailViolation),
+
+    #[error("exchange implementation upgraded to {0}, acknowledgement required")]
+    UpgradePending(Address),
+
+    #[error("incompatible contract: {0}")]
+    IncompatibleContract(#[from] Box<IncompatibleContract>),
 }
 
+#[cfg(feature = "rpc")]
 impl<R: SolInterface> From<contract::Error> for ProviderError<R> {
     fn from(value: contract::Error) -> Self {
         match value {
@@ -85,6 +136,7 @@ impl<R: SolInterface> From<contract::Error> for ProviderError<R> {
     }
 }
 
+#[cfg(feature = "rpc")]
 impl<R: SolInterface> From<PendingTransactionError> for ProviderError<R> {
     fn from(value: PendingTransactionError) -> Self {
         match value {
@@ -104,6 +156,7 @@ impl<R: SolInterface> From<PendingTransactionError> for ProviderError<R> {
     }
 }
 
+#[cfg(feature = "rpc")]
 impl<E: Display, R: SolInterface> From<transports::RpcError<E>> for ProviderError<R> {
     fn from(value: transports::RpcError<E>) -> Self {
         match value {
@@ -137,6 +190,7 @@ impl<R: SolInterface> From<sol_types::Error> for ProviderError<R> {
     }
 }
 
+#[cfg(feature = "rpc")]
 impl<R: SolInterface> From<MulticallError> for ProviderError<R> {
     fn from(value: MulticallError) -> Self {
         match value {
@@ -151,6 +205,7 @@ impl<R: SolInterface> From<MulticallError> for ProviderError<R> {
     }
 }
 
+#[cfg(feature = "rpc")]
 impl<E: Display, R: SolInterface> From<transports::RpcError<E>> for RevertReason<R> {
     fn from(value: transports::RpcError<E>) -> Self {
         match value.as_error_resp() {
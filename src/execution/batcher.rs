@@ -0,0 +1,376 @@
+//! Order request batching.
+
+use std::{collections::HashMap, future::Future, time::Duration};
+
+use alloy::{
+    primitives::{Address, TxHash},
+    providers::Provider,
+};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    abi::dex::Exchange::{ExchangeInstance, OrderDesc},
+    error::DexError,
+    num, state, types,
+};
+
+/// Default maximal number of order requests in a single transaction.
+const DEFAULT_MAX_BATCH_SIZE: usize = 100;
+
+/// Default flush interval.
+const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Window to accumulate order requests for before sending them.
+#[derive(Clone, Copy, Debug)]
+pub enum FlushWindow {
+    /// Flush queued requests at the given interval.
+    Interval(Duration),
+    /// Flush queued requests once per block, polling the block number at the given interval.
+    Block(Duration),
+}
+
+/// Configuration of the [`Batcher`].
+#[derive(Clone, Copy, Debug)]
+pub struct BatcherConfig {
+    window: FlushWindow,
+    max_batch_size: usize,
+}
+
+impl BatcherConfig {
+    /// Window to accumulate order requests for.
+    pub fn with_window(mut self, window: FlushWindow) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Maximal number of order requests in a single transaction,
+    /// larger batches get split into several transactions.
+    pub fn with_max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.max_batch_size = max_batch_size.max(1);
+        self
+    }
+}
+
+impl Default for BatcherConfig {
+    fn default() -> Self {
+        Self {
+            window: FlushWindow::Interval(DEFAULT_FLUSH_INTERVAL),
+            max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+        }
+    }
+}
+
+/// Queued order request awaiting submission.
+struct Pending {
+    desc: OrderDesc,
+    result: oneshot::Sender<Result<TxHash, DexError>>,
+}
+
+/// Queued order requests grouped by the sending account,
+/// in the order of their submission.
+struct BatchQueue {
+    max_batch_size: usize,
+    accounts: Vec<(Address, Vec<Pending>)>,
+}
+
+impl BatchQueue {
+    fn new(max_batch_size: usize) -> Self {
+        Self {
+            max_batch_size,
+            accounts: Vec::new(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    fn push(&mut self, from: Address, pending: Pending) {
+        match self.accounts.iter_mut().find(|(addr, _)| *addr == from) {
+            Some((_, queue)) => queue.push(pending),
+            None => self.accounts.push((from, vec![pending])),
+        }
+    }
+
+    /// Takes all queued requests split into batches of at most `max_batch_size` requests.
+    fn drain(&mut self) -> Vec<(Address, Vec<Pending>)> {
+        let mut batches = Vec::new();
+        for (from, mut queue) in self.accounts.drain(..) {
+            while queue.len() > self.max_batch_size {
+                let rest = queue.split_off(self.max_batch_size);
+                batches.push((from, queue));
+                queue = rest;
+            }
+            batches.push((from, queue));
+        }
+        batches
+    }
+}
+
+/// Converters required to prepare order requests of a perpetual contract.
+#[derive(Clone, Copy)]
+struct PerpetualConverters {
+    price_converter: num::Converter,
+    size_converter: num::Converter,
+    leverage_converter: num::Converter,
+}
+
+/// Submitted order request, resolves to the hash of the transaction
+/// the request was sent with.
+pub struct Submission {
+    result: oneshot::Receiver<Result<TxHash, DexError>>,
+}
+
+impl Submission {
+    /// Waits for the batch containing the request to be sent.
+    pub async fn tx_hash(self) -> Result<TxHash, DexError> {
+        self.result.await.map_err(|_| DexError::BatcherClosed)?
+    }
+}
+
+/// Queues order requests and coalesces them into a single `execOpsAndOrders`
+/// transaction per account.
+///
+/// Requests of all perpetual contracts known at start get sent with
+/// `revertOnFail = false`, so a failure of one request does not affect
+/// the rest of the batch. Requests are not checked against the live exchange state,
+/// e.g. for the availability, see [`crate::state::Exchange::ensure_available`].
+///
+/// Dropping the batcher flushes the remaining requests and stops the background task.
+pub struct Batcher {
+    collateral_converter: num::Converter,
+    perpetuals: HashMap<types::PerpetualId, PerpetualConverters>,
+    tx: mpsc::UnboundedSender<(Address, Pending)>,
+}
+
+impl Batcher {
+    /// Start the batcher for the exchange, sending transactions via the given
+    /// [`Provider`], which must be able to sign for all submitting addresses.
+    ///
+    /// Returns the batcher and a handle to the background task.
+    pub fn start<P, S, SFut>(
+        exchange: &state::Exchange,
+        provider: P,
+        config: BatcherConfig,
+        sleep: S,
+    ) -> (Self, tokio::task::JoinHandle<Result<(), DexError>>)
+    where
+        P: Provider + Send + Sync + 'static,
+        S: Fn(Duration) -> SFut + Copy + Send + 'static,
+        SFut: Future<Output = ()> + Send,
+    {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let instance = ExchangeInstance::new(exchange.chain().exchange(), provider);
+        let handle = tokio::spawn(async move { run_batcher(instance, config, sleep, rx).await });
+
+        let batcher = Self {
+            collateral_converter: exchange.collateral_converter(),
+            perpetuals: exchange
+                .perpetuals()
+                .iter()
+                .map(|(id, perp)| {
+                    (
+                        *id,
+                        PerpetualConverters {
+                            price_converter: perp.price_converter(),
+                            size_converter: perp.size_converter(),
+                            leverage_converter: perp.leverage_converter(),
+                        },
+                    )
+                })
+                .collect(),
+            tx,
+        };
+        (batcher, handle)
+    }
+
+    /// Queue the order request to be sent from the given address with the next batch.
+    pub fn submit(
+        &self,
+        from: Address,
+        request: &types::OrderRequest,
+    ) -> Result<Submission, DexError> {
+        let converters = self.perpetuals.get(&request.perp_id()).ok_or_else(|| {
+            DexError::InvalidRequest(format!("unknown perpetual: {}", request.perp_id()))
+        })?;
+        let desc = request.to_order_desc(
+            converters.price_converter,
+            converters.size_converter,
+            converters.leverage_converter,
+            Some(self.collateral_converter),
+        );
+
+        let (result_tx, result_rx) = oneshot::channel();
+        self.tx
+            .send((
+                from,
+                Pending {
+                    desc,
+                    result: result_tx,
+                },
+            ))
+            .map_err(|_| DexError::BatcherClosed)?;
+        Ok(Submission { result: result_rx })
+    }
+}
+
+async fn run_batcher<P, S, SFut>(
+    instance: ExchangeInstance<P>,
+    config: BatcherConfig,
+    sleep: S,
+    mut rx: mpsc::UnboundedReceiver<(Address, Pending)>,
+) -> Result<(), DexError>
+where
+    P: Provider,
+    S: Fn(Duration) -> SFut + Copy,
+    SFut: Future<Output = ()>,
+{
+    let mut queue = BatchQueue::new(config.max_batch_size);
+    let mut last_block = None;
+
+    loop {
+        match config.window {
+            FlushWindow::Interval(interval) | FlushWindow::Block(interval) => sleep(interval).await,
+        }
+
+        let mut closed = false;
+        loop {
+            match rx.try_recv() {
+                Ok((from, pending)) => queue.push(from, pending),
+                Err(mpsc::error::TryRecvError::Empty) => break,
+                Err(mpsc::error::TryRecvError::Disconnected) => {
+                    closed = true;
+                    break;
+                }
+            }
+        }
+
+        if !closed && matches!(config.window, FlushWindow::Block(_)) {
+            // Transient failure of the poll only delays the flush until the next tick
+            let Ok(block) = instance.provider().get_block_number().await else {
+                continue;
+            };
+            if last_block.replace(block) == Some(block) {
+                continue;
+            }
+        }
+
+        // Batches are sent sequentially to keep nonces of the same account in order
+        for (from, batch) in queue.drain() {
+            let (descs, results): (Vec<_>, Vec<_>) =
+                batch.into_iter().map(|p| (p.desc, p.result)).unzip();
+            let result = instance
+                .execOpsAndOrders(vec![], descs, false)
+                .from(from)
+                .send()
+                .await
+                .map(|pending_tx| *pending_tx.tx_hash())
+                .map_err(DexError::from);
+            for result_tx in results {
+                // Submitter might not be interested in the result
+                let _ = result_tx.send(
+                    result
+                        .as_ref()
+                        .copied()
+                        .map_err(|err| DexError::BatchFailed(err.to_string())),
+                );
+            }
+        }
+
+        if closed && queue.is_empty() {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::{primitives::U64, providers::ProviderBuilder, transports::mock::Asserter};
+
+    use super::*;
+
+    fn pending(id: u64) -> (Pending, oneshot::Receiver<Result<TxHash, DexError>>) {
+        let (tx, rx) = oneshot::channel();
+        let conv = num::Converter::new(2);
+        let desc = types::OrderRequest::new(
+            id,
+            1,
+            types::RequestType::Cancel,
+            None,
+            Default::default(),
+            Default::default(),
+            None,
+            false,
+            false,
+            false,
+            None,
+            Default::default(),
+            None,
+            None,
+        )
+        .to_order_desc(conv, conv, conv, None);
+        (Pending { desc, result: tx }, rx)
+    }
+
+    fn ids(batch: &[Pending]) -> Vec<u64> {
+        batch.iter().map(|p| p.desc.orderDescId.to()).collect()
+    }
+
+    #[test]
+    fn requests_are_grouped_per_account() {
+        let (a, b) = (Address::repeat_byte(1), Address::repeat_byte(2));
+        let mut queue = BatchQueue::new(10);
+        for (from, id) in [(a, 1), (b, 2), (a, 3), (b, 4), (a, 5)] {
+            queue.push(from, pending(id).0);
+        }
+
+        let batches = queue.drain();
+        assert!(queue.is_empty());
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].0, a);
+        assert_eq!(ids(&batches[0].1), vec![1, 3, 5]);
+        assert_eq!(batches[1].0, b);
+        assert_eq!(ids(&batches[1].1), vec![2, 4]);
+    }
+
+    #[tokio::test]
+    async fn failed_block_poll_is_retried() {
+        let asserter = Asserter::new();
+        asserter.push_failure_msg("connection reset");
+        asserter.push_success(&U64::from(1));
+        asserter.push_success(&TxHash::repeat_byte(7));
+        let provider = ProviderBuilder::default().connect_mocked_client(asserter);
+        let instance = ExchangeInstance::new(Address::ZERO, provider);
+        let config = BatcherConfig::default().with_window(FlushWindow::Block(Duration::ZERO));
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (request, result) = pending(1);
+        tx.send((Address::repeat_byte(1), request)).unwrap();
+        let handle = tokio::spawn(run_batcher(
+            instance,
+            config,
+            |_| tokio::task::yield_now(),
+            rx,
+        ));
+
+        assert_eq!(result.await.unwrap().unwrap(), TxHash::repeat_byte(7));
+        drop(tx);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[test]
+    fn batches_respect_max_size() {
+        let a = Address::repeat_byte(1);
+        let mut queue = BatchQueue::new(2);
+        for id in 1..=5 {
+            queue.push(a, pending(id).0);
+        }
+
+        let batches = queue
+            .drain()
+            .iter()
+            .map(|(_, batch)| ids(batch))
+            .collect::<Vec<_>>();
+        assert_eq!(batches, vec![vec![1, 2], vec![3, 4], vec![5]]);
+    }
+}
@@ -0,0 +1,371 @@
+//! Kill switch cancelling all resting orders and closing all positions of an account.
+
+use alloy::{primitives::TxHash, providers::Provider};
+use fastnum::{UD64, UD128};
+
+use crate::{
+    abi::dex::Exchange::ExchangeInstance,
+    error::DexError,
+    state::{self, Perpetual},
+    types::{self, OrderRequest, OrderSide, OrderType},
+};
+
+/// Immediate-or-cancel order closing the position, see [`FlattenOutcome::close`].
+#[derive(Clone, Copy, PartialEq, Eq, derive_more::Debug)]
+pub struct CloseOrder {
+    /// ID of the close request.
+    pub request_id: types::RequestId,
+
+    /// Side of the close order, opposite to the position.
+    pub side: OrderSide,
+
+    /// Size of the position to close.
+    #[debug("{size}")]
+    pub size: UD64,
+
+    /// Worst acceptable price, the reference price moved by the maximal slippage.
+    #[debug("{limit_price}")]
+    pub limit_price: UD64,
+
+    /// Size crossable in the tracked book at or better than the limit price,
+    /// not counting the own orders of the account.
+    #[debug("{fillable_size}")]
+    pub fillable_size: UD64,
+
+    /// Expected size-averaged fill price, `None` if nothing is fillable.
+    #[debug("{:?}", avg_price.map(|v| format!("{v}")))]
+    pub avg_price: Option<UD64>,
+}
+
+/// Expected result of flattening the account on the perpetual contract.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlattenStatus {
+    /// No position to close.
+    Flat,
+
+    /// Position is expected to get closed completely.
+    Closed,
+
+    /// Book liquidity within the slippage covers only part of the position.
+    PartiallyClosed,
+
+    /// No book liquidity within the slippage, the position is left open.
+    NoLiquidity,
+}
+
+/// Requests of the account on the perpetual contract, see [`flatten_requests`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlattenOutcome {
+    /// ID of the perpetual contract.
+    pub perpetual_id: types::PerpetualId,
+
+    /// Resting orders of the account being cancelled.
+    pub cancelled_orders: Vec<types::OrderId>,
+
+    /// Order closing the position, `None` if there is no position or no liquidity.
+    pub close: Option<CloseOrder>,
+
+    /// Expected result.
+    pub status: FlattenStatus,
+}
+
+/// Batch flattening the account, see [`flatten_requests`].
+#[derive(Clone, Debug)]
+pub struct FlattenPlan {
+    /// ID of the account.
+    pub account_id: types::AccountId,
+
+    /// Cancellations followed by the close orders, to be sent in a single transaction.
+    pub requests: Vec<OrderRequest>,
+
+    /// Outcome per perpetual contract with resting orders or a position, ordered by ID.
+    pub outcomes: Vec<FlattenOutcome>,
+}
+
+/// Result of [`flatten_account`].
+#[derive(Clone, Debug)]
+pub struct FlattenReport {
+    /// Submitted batch.
+    pub plan: FlattenPlan,
+
+    /// Hash of the sent transaction, `None` if there was nothing to send.
+    pub tx_hash: Option<TxHash>,
+}
+
+/// Builds the batch cancelling all resting orders of the account and closing all its
+/// positions with immediate-or-cancel orders, with request IDs assigned sequentially.
+///
+/// Close orders are priced at the reference price moved by `max_slippage` (fraction of
+/// the price) against the position: the mark price, or the best price of the opposite side
+/// of the book if the mark price is not known. Expected fills are found by walking the
+/// tracked book, skipping the orders of the account which get cancelled first.
+///
+/// Fails if the account is not tracked or the slippage is not below 1.
+pub fn flatten_requests(
+    exchange: &state::Exchange,
+    account_id: types::AccountId,
+    max_slippage: UD64,
+    first_request_id: types::RequestId,
+) -> Result<FlattenPlan, DexError> {
+    if max_slippage >= UD64::ONE {
+        return Err(DexError::InvalidRequest(format!(
+            "max slippage out of range: {max_slippage}"
+        )));
+    }
+    let account = exchange
+        .accounts()
+        .get(&account_id)
+        .ok_or_else(|| DexError::InvalidRequest(format!("account not tracked: {account_id}")))?;
+
+    let mut perp_ids = exchange.perpetuals().keys().copied().collect::<Vec<_>>();
+    perp_ids.sort();
+    let mut request_ids = first_request_id..;
+    let mut cancels = vec![];
+    let mut closes = vec![];
+    let mut outcomes = vec![];
+    for perp_id in perp_ids {
+        let perp = &exchange.perpetuals()[&perp_id];
+        let mut cancelled_orders = perp
+            .l3_book()
+            .all_orders()
+            .values()
+            .filter(|o| o.account_id() == account_id)
+            .map(|o| o.order_id())
+            .collect::<Vec<_>>();
+        cancelled_orders.sort();
+        let position = account.positions().get(&perp_id);
+        if cancelled_orders.is_empty() && position.is_none() {
+            continue;
+        }
+        cancels.extend(cancelled_orders.iter().map(|order_id| {
+            OrderRequest::cancel(request_ids.next().expect("unbounded"), perp_id, *order_id)
+        }));
+
+        let (close, status) = match position {
+            None => (None, FlattenStatus::Flat),
+            Some(pos) => {
+                let close = close_order(perp, account_id, pos, max_slippage, &mut request_ids);
+                match close {
+                    Some(close) if close.fillable_size >= close.size => {
+                        (Some(close), FlattenStatus::Closed)
+                    }
+                    Some(close) => (Some(close), FlattenStatus::PartiallyClosed),
+                    None => (None, FlattenStatus::NoLiquidity),
+                }
+            }
+        };
+        if let (Some(close), Some(pos)) = (&close, position) {
+            let r#type = if pos.r#type().is_long() {
+                OrderType::CloseLong
+            } else {
+                OrderType::CloseShort
+            };
+            closes.push(
+                OrderRequest::ioc(
+                    close.request_id,
+                    perp_id,
+                    r#type,
+                    close.limit_price,
+                    close.size,
+                )
+                .build(),
+            );
+        }
+        outcomes.push(FlattenOutcome {
+            perpetual_id: perp_id,
+            cancelled_orders,
+            close,
+            status,
+        });
+    }
+
+    cancels.extend(closes);
+    Ok(FlattenPlan {
+        account_id,
+        requests: cancels,
+        outcomes,
+    })
+}
+
+/// Builds the batch with [`flatten_requests`] and sends it in a single `execOpsAndOrders`
+/// transaction from the address of the account, which the provider is expected to sign for.
+///
+/// Guardrails are not applied, so the kill switch can not be blocked by them.
+/// Fails with [`DexError::AccountFrozen`] while the account is frozen and with
+/// [`DexError::VenueUnavailable`] while the exchange would reject the batch.
+pub async fn flatten_account<P: Provider>(
+    provider: &P,
+    exchange: &state::Exchange,
+    account_id: types::AccountId,
+    max_slippage: UD64,
+    first_request_id: types::RequestId,
+) -> Result<FlattenReport, DexError> {
+    let plan = flatten_requests(exchange, account_id, max_slippage, first_request_id)?;
+    let account = &exchange.accounts()[&account_id];
+    account.ensure_not_frozen()?;
+    exchange.ensure_requests_available(&plan.requests)?;
+    if plan.requests.is_empty() {
+        return Ok(FlattenReport {
+            plan,
+            tx_hash: None,
+        });
+    }
+    let descs = plan
+        .requests
+        .iter()
+        .map(|r| r.prepare(exchange))
+        .collect::<Vec<_>>();
+    let pending = ExchangeInstance::new(exchange.chain().exchange(), provider)
+        .execOpsAndOrders(vec![], descs, false)
+        .from(account.address())
+        .send()
+        .await?;
+    Ok(FlattenReport {
+        plan,
+        tx_hash: Some(*pending.tx_hash()),
+    })
+}
+
+/// Close order of the position, `None` if there is no liquidity within the slippage.
+pub(super) fn close_order(
+    perp: &Perpetual,
+    account_id: types::AccountId,
+    position: &state::Position,
+    max_slippage: UD64,
+    request_ids: &mut impl Iterator<Item = types::RequestId>,
+) -> Option<CloseOrder> {
+    let book = perp.l3_book();
+    let (side, mut liquidity) = if position.r#type().is_long() {
+        (OrderSide::Ask, book.bid_orders().collect::<Vec<_>>())
+    } else {
+        (OrderSide::Bid, book.ask_orders().collect::<Vec<_>>())
+    };
+    liquidity.retain(|o| o.account_id() != account_id);
+
+    let reference = if perp.mark_price() > UD64::ZERO {
+        perp.mark_price()
+    } else {
+        liquidity.first()?.price()
+    };
+    let limit_price = match side {
+        OrderSide::Ask => perp.round_price(reference - reference * max_slippage, side),
+        OrderSide::Bid => perp.round_price(reference + reference * max_slippage, side),
+    };
+
+    let size = position.size();
+    let (mut filled, mut notional) = (UD64::ZERO, UD128::ZERO);
+    for order in liquidity {
+        let crossable = match side {
+            OrderSide::Ask => order.price() >= limit_price,
+            OrderSide::Bid => order.price() <= limit_price,
+        };
+        if !crossable || filled >= size {
+            break;
+        }
+        let fill = order.size().min(size - filled);
+        filled += fill;
+        notional += order.price().resize() * fill.resize();
+    }
+    if filled == UD64::ZERO {
+        return None;
+    }
+    Some(CloseOrder {
+        request_id: request_ids.next().expect("unbounded"),
+        side,
+        size,
+        limit_price,
+        fillable_size: filled,
+        avg_price: Some((notional / filled.resize()).resize()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use fastnum::udec64;
+
+    use super::*;
+    use crate::testing::fuzz::{Action, Simulator};
+
+    #[test]
+    fn flatten_plan() {
+        let mut sim = Simulator::new(&[1, 2], 2);
+        let mut exchange = sim.exchange();
+        let place = |perpetual, account, is_bid, offset, lots| Action::Place {
+            perpetual,
+            account,
+            is_bid,
+            offset,
+            lots,
+        };
+        let open = |perpetual, is_long, lots| Action::Open {
+            perpetual,
+            account: 0,
+            is_long,
+            lots,
+        };
+        exchange
+            .apply_events(&sim.block(&[
+                // Own orders, best bid of the first perpetual gets skipped
+                place(0, 0, true, 0, 1),
+                place(0, 0, false, 5, 1),
+                // Liquidity of the other account
+                place(0, 1, true, 1, 3),
+                place(0, 1, true, 10, 5),
+                place(1, 1, false, 0, 4),
+                open(0, true, 5),
+                open(1, false, 2),
+            ]))
+            .unwrap();
+
+        let plan = flatten_requests(&exchange, 1, udec64!(0.005), 100).unwrap();
+        assert_eq!(plan.requests.len(), 4);
+        assert_eq!(
+            plan.requests
+                .iter()
+                .map(|r| r.request_id())
+                .collect::<Vec<_>>(),
+            vec![100, 101, 102, 103]
+        );
+        assert!(matches!(
+            plan.requests[0].r#type(),
+            types::RequestType::Cancel
+        ));
+        assert!(matches!(
+            plan.requests[1].r#type(),
+            types::RequestType::Cancel
+        ));
+
+        let long = &plan.outcomes[0];
+        assert_eq!(long.cancelled_orders.len(), 2);
+        assert_eq!(long.status, FlattenStatus::PartiallyClosed);
+        let close = long.close.unwrap();
+        assert_eq!(close.side, OrderSide::Ask);
+        assert_eq!(close.limit_price, udec64!(995));
+        assert_eq!(close.fillable_size, udec64!(3));
+        assert_eq!(close.avg_price, Some(udec64!(999)));
+        assert!(matches!(
+            plan.requests[2].r#type(),
+            types::RequestType::CloseLong
+        ));
+        assert!(plan.requests[2].immediate_or_cancel());
+
+        let short = &plan.outcomes[1];
+        assert!(short.cancelled_orders.is_empty());
+        assert_eq!(short.status, FlattenStatus::Closed);
+        let close = short.close.unwrap();
+        assert_eq!((close.limit_price, close.size), (udec64!(1006), udec64!(2)));
+        assert_eq!(plan.requests[3].price(), udec64!(1006));
+
+        // Other account has no position, only orders to cancel
+        let plan = flatten_requests(&exchange, 2, udec64!(0.005), 1).unwrap();
+        assert_eq!(plan.requests.len(), 3);
+        assert!(
+            plan.outcomes
+                .iter()
+                .all(|o| o.status == FlattenStatus::Flat)
+        );
+
+        assert!(flatten_requests(&exchange, 1, UD64::ONE, 1).is_err());
+        assert!(flatten_requests(&exchange, 3, udec64!(0.01), 1).is_err());
+    }
+}
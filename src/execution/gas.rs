@@ -0,0 +1,145 @@
+//! Gas estimation of order batches.
+
+use alloy::{primitives::Address, providers::Provider};
+use fastnum::{UD64, UD128};
+
+use crate::{abi::dex::Exchange::ExchangeInstance, error::DexError, num, state, types};
+
+/// Decimals of the native token.
+const NATIVE_DECIMALS: u8 = 18;
+
+/// Predicted gas usage and cost of an order batch.
+#[derive(Clone, derive_more::Debug)]
+pub struct GasEstimate {
+    /// Gas of the whole batch transaction.
+    pub gas: u64,
+
+    /// Gas of the transaction without orders: intrinsic and call overhead.
+    pub base_gas: u64,
+
+    /// Gas attributed to each order, estimated separately
+    /// as the single-order transaction gas minus [`Self::base_gas`].
+    pub order_gas: Vec<u64>,
+
+    /// Gas price used for the cost estimation, in wei.
+    pub gas_price: u128,
+
+    /// Cost of the whole batch in the native token.
+    #[debug("{native_cost}")]
+    pub native_cost: UD128,
+
+    /// Cost of the whole batch in the collateral token, at the provided native token price.
+    #[debug("{collateral_cost}")]
+    pub collateral_cost: UD128,
+}
+
+impl GasEstimate {
+    fn new(
+        gas: u64,
+        base_gas: u64,
+        order_gas: Vec<u64>,
+        gas_price: u128,
+        native_price: UD64,
+    ) -> Self {
+        let native_cost = native_cost(gas, gas_price);
+        Self {
+            gas,
+            base_gas,
+            order_gas,
+            gas_price,
+            native_cost,
+            collateral_cost: native_cost * native_price.resize(),
+        }
+    }
+
+    /// Cost of the order in the collateral token, including its share of the base gas.
+    pub fn order_collateral_cost(&self, index: usize) -> Option<UD128> {
+        let gas = self.order_gas.get(index)?;
+        let share = self.base_gas / self.order_gas.len() as u64;
+        Some(self.collateral_cost * UD128::from(gas + share) / UD128::from(self.gas.max(1)))
+    }
+}
+
+/// Estimate gas of sending the order requests in a single `execOpsAndOrders` transaction
+/// from the given address, with the per-order breakdown.
+///
+/// Uses the current gas price of the provider and the given `native_price`
+/// (collateral tokens per native token) to express the cost in the collateral token.
+///
+/// Estimation simulates the transaction against the latest state, so orders depending
+/// on each other (e.g. placement and cancellation) might be estimated inaccurately
+/// when broken down.
+pub async fn estimate_batch<P: Provider>(
+    provider: &P,
+    exchange: &state::Exchange,
+    from: Address,
+    requests: &[types::OrderRequest],
+    native_price: UD64,
+) -> Result<GasEstimate, DexError> {
+    let instance = ExchangeInstance::new(exchange.chain().exchange(), provider);
+    let descs = requests
+        .iter()
+        .map(|r| {
+            if exchange.perpetuals().contains_key(&r.perp_id()) {
+                Ok(r.prepare(exchange))
+            } else {
+                Err(DexError::InvalidRequest(format!(
+                    "unknown perpetual: {}",
+                    r.perp_id()
+                )))
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let estimate = |descs| {
+        let call = instance.execOpsAndOrders(vec![], descs, false).from(from);
+        async move { call.estimate_gas().await }
+    };
+    let (gas, base_gas, single_gas, gas_price) = futures::try_join!(
+        estimate(descs.clone()),
+        estimate(vec![]),
+        futures::future::try_join_all(descs.iter().map(|d| estimate(vec![d.clone()]))),
+        async { provider.get_gas_price().await.map_err(Into::into) },
+    )?;
+
+    Ok(GasEstimate::new(
+        gas,
+        base_gas,
+        single_gas
+            .into_iter()
+            .map(|g| g.saturating_sub(base_gas))
+            .collect(),
+        gas_price,
+        native_price,
+    ))
+}
+
+fn native_cost(gas: u64, gas_price: u128) -> UD128 {
+    num::Converter::new(NATIVE_DECIMALS).from_unsigned(
+        alloy::primitives::U256::from(gas) * alloy::primitives::U256::from(gas_price),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use fastnum::{udec64, udec128};
+
+    use super::*;
+
+    #[test]
+    fn cost_in_collateral() {
+        // 1M gas at 100 gwei = 0.1 MON at 2.5 collateral per MON
+        let estimate = GasEstimate::new(
+            1_000_000,
+            100_000,
+            vec![600_000, 300_000],
+            100_000_000_000,
+            udec64!(2.5),
+        );
+        assert_eq!(estimate.native_cost, udec128!(0.1));
+        assert_eq!(estimate.collateral_cost, udec128!(0.25));
+        // (600K + 50K) / 1M of the cost
+        assert_eq!(estimate.order_collateral_cost(0), Some(udec128!(0.1625)));
+        assert_eq!(estimate.order_collateral_cost(2), None);
+    }
+}
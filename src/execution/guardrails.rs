@@ -0,0 +1,529 @@
+//! Pre-submission limits on order requests.
+
+use std::{collections::HashMap, sync::Mutex};
+
+use alloy::primitives::Address;
+use fastnum::{D256, UD64, UD128};
+use thiserror::Error;
+
+use crate::{
+    state,
+    types::{self, RequestType},
+};
+
+/// Order request rejected by [`Guardrails`] before submission.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error)]
+pub enum GuardrailViolation {
+    #[error("order {request_id} notional {notional} exceeds limit {limit}")]
+    OrderNotional {
+        request_id: types::RequestId,
+        notional: UD128,
+        limit: UD128,
+    },
+
+    #[error("position notional {notional} in perpetual {perpetual_id} exceeds limit {limit}")]
+    PositionNotional {
+        perpetual_id: types::PerpetualId,
+        notional: UD128,
+        limit: UD128,
+    },
+
+    #[error("total leverage {leverage} exceeds limit {limit}")]
+    TotalLeverage { leverage: UD64, limit: UD64 },
+
+    #[error("{count} orders in block {block_number} exceed limit {limit}")]
+    OrdersPerBlock {
+        block_number: u64,
+        count: usize,
+        limit: usize,
+    },
+
+    #[error("order {request_id} price {price} is outside of range [{min_price}, {max_price}]")]
+    PriceOutOfRange {
+        request_id: types::RequestId,
+        price: UD64,
+        min_price: UD64,
+        max_price: UD64,
+    },
+}
+
+/// Fat-finger protection limits checked against the tracked state before
+/// the order requests get sent, see [`crate::client::DexClient::with_guardrails`].
+///
+/// Notional values are taken at the mark price of the perpetual contract, or at
+/// the order price if the mark price is not known. Position limits assume open orders
+/// of the batch get completely filled and do not account for resting orders, requests
+/// only reducing the exposure are never rejected.
+#[derive(Debug, Default)]
+pub struct Guardrails {
+    max_order_notional: Option<UD128>,
+    max_position_notional: Option<UD128>,
+    max_position_notional_by_perp: HashMap<types::PerpetualId, UD128>,
+    max_total_leverage: Option<UD64>,
+    max_orders_per_block: Option<usize>,
+    check_price_range: bool,
+    /// Block number and number of orders admitted in it.
+    submitted: Mutex<(u64, usize)>,
+}
+
+impl Guardrails {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maximal notional value of a single order at its limit price.
+    pub fn with_max_order_notional(mut self, limit: UD128) -> Self {
+        self.max_order_notional = Some(limit);
+        self
+    }
+
+    /// Maximal notional value of a position in any perpetual contract.
+    pub fn with_max_position_notional(mut self, limit: UD128) -> Self {
+        self.max_position_notional = Some(limit);
+        self
+    }
+
+    /// Maximal notional value of a position in the perpetual contract,
+    /// overrides [`Self::with_max_position_notional`].
+    pub fn with_max_position_notional_for(
+        mut self,
+        perpetual_id: types::PerpetualId,
+        limit: UD128,
+    ) -> Self {
+        self.max_position_notional_by_perp
+            .insert(perpetual_id, limit);
+        self
+    }
+
+    /// Maximal total notional value of all account positions relative to its equity.
+    pub fn with_max_total_leverage(mut self, limit: UD64) -> Self {
+        self.max_total_leverage = Some(limit);
+        self
+    }
+
+    /// Maximal number of order requests, excluding cancellations, sent per block
+    /// of the tracked state.
+    pub fn with_max_orders_per_block(mut self, limit: usize) -> Self {
+        self.max_orders_per_block = Some(limit);
+        self
+    }
+
+    /// Reject orders priced outside of the acceptable range of the perpetual contract,
+    /// see [`price_range_warnings`].
+    pub fn with_price_range_check(mut self) -> Self {
+        self.check_price_range = true;
+        self
+    }
+
+    /// Check the order requests sent from the given address against the limits.
+    pub fn check(
+        &self,
+        exchange: &state::Exchange,
+        address: Address,
+        requests: &[types::OrderRequest],
+    ) -> Result<(), GuardrailViolation> {
+        let submitted = *self.submitted.lock().unwrap();
+        self.check_with(exchange, address, requests, submitted)
+    }
+
+    /// Check the order requests and count them against the per-block limit if admitted.
+    pub(crate) fn admit(
+        &self,
+        exchange: &state::Exchange,
+        address: Address,
+        requests: &[types::OrderRequest],
+    ) -> Result<(), GuardrailViolation> {
+        let mut submitted = self.submitted.lock().unwrap();
+        self.check_with(exchange, address, requests, *submitted)?;
+        let block_number = exchange.instant().block_number();
+        if submitted.0 != block_number {
+            *submitted = (block_number, 0);
+        }
+        submitted.1 += requests.iter().filter(|r| counts_as_order(r)).count();
+        Ok(())
+    }
+
+    fn check_with(
+        &self,
+        exchange: &state::Exchange,
+        address: Address,
+        requests: &[types::OrderRequest],
+        (submitted_block, submitted): (u64, usize),
+    ) -> Result<(), GuardrailViolation> {
+        if let Some(limit) = self.max_orders_per_block {
+            let block_number = exchange.instant().block_number();
+            let count = requests.iter().filter(|r| counts_as_order(r)).count()
+                + if submitted_block == block_number {
+                    submitted
+                } else {
+                    0
+                };
+            if count > limit {
+                return Err(GuardrailViolation::OrdersPerBlock {
+                    block_number,
+                    count,
+                    limit,
+                });
+            }
+        }
+
+        if let Some(limit) = self.max_order_notional {
+            for r in requests.iter().filter(|r| counts_as_order(r)) {
+                let notional = r.price().resize() * r.size().resize();
+                if notional > limit {
+                    return Err(GuardrailViolation::OrderNotional {
+                        request_id: r.request_id(),
+                        notional,
+                        limit,
+                    });
+                }
+            }
+        }
+
+        if self.check_price_range
+            && let Some(violation) = price_range_warnings(exchange, requests).next()
+        {
+            return Err(violation);
+        }
+
+        self.check_positions(exchange, exchange.account_by_address(address), requests)
+    }
+
+    fn check_positions(
+        &self,
+        exchange: &state::Exchange,
+        account: Option<&state::Account>,
+        requests: &[types::OrderRequest],
+    ) -> Result<(), GuardrailViolation> {
+        // Signed position sizes before and after the batch, with the valuation price
+        let mut sizes: HashMap<types::PerpetualId, (D256, D256, UD64)> = HashMap::new();
+        for r in requests {
+            let delta = match r.r#type() {
+                RequestType::OpenLong => r.size().to_signed().resize(),
+                RequestType::OpenShort => r.size().to_signed().resize().neg(),
+                _ => continue,
+            };
+            let price = exchange
+                .perpetuals()
+                .get(&r.perp_id())
+                .map(|perp| perp.mark_price())
+                .filter(|price| *price > UD64::ZERO)
+                .unwrap_or(r.price());
+            let entry = sizes.entry(r.perp_id()).or_insert_with(|| {
+                let size = account
+                    .and_then(|acc| acc.positions().get(&r.perp_id()))
+                    .map(signed_size)
+                    .unwrap_or_default();
+                (size, size, price)
+            });
+            entry.1 += delta;
+        }
+        if sizes.is_empty() {
+            return Ok(());
+        }
+
+        let notional =
+            |size: D256, price: UD64| -> UD128 { size.unsigned_abs().resize() * price.resize() };
+        for (perp_id, (before, after, price)) in &sizes {
+            let Some(limit) = self
+                .max_position_notional_by_perp
+                .get(perp_id)
+                .copied()
+                .or(self.max_position_notional)
+            else {
+                continue;
+            };
+            let notional_after = notional(*after, *price);
+            if notional_after > limit && notional_after > notional(*before, *price) {
+                return Err(GuardrailViolation::PositionNotional {
+                    perpetual_id: *perp_id,
+                    notional: notional_after,
+                    limit,
+                });
+            }
+        }
+
+        let Some(limit) = self.max_total_leverage else {
+            return Ok(());
+        };
+        let mut equity = D256::ZERO;
+        let mut total_before = UD128::ZERO;
+        let mut total_after = UD128::ZERO;
+        if let Some(acc) = account {
+            equity = acc.balance().to_signed().resize();
+            for (perp_id, pos) in acc.positions() {
+                equity += pos.deposit().to_signed().resize() + pos.pnl();
+                if sizes.contains_key(perp_id) {
+                    continue;
+                }
+                let price = exchange
+                    .perpetuals()
+                    .get(perp_id)
+                    .map(|perp| perp.mark_price())
+                    .filter(|price| *price > UD64::ZERO)
+                    .unwrap_or(pos.entry_price());
+                let pos_notional = notional(signed_size(pos), price);
+                total_before += pos_notional;
+                total_after += pos_notional;
+            }
+        }
+        for (before, after, price) in sizes.values() {
+            total_before += notional(*before, *price);
+            total_after += notional(*after, *price);
+        }
+        if total_after <= total_before {
+            return Ok(());
+        }
+        let leverage = if equity > D256::ZERO {
+            (total_after / equity.unsigned_abs().resize()).resize()
+        } else {
+            UD64::MAX
+        };
+        if leverage > limit {
+            return Err(GuardrailViolation::TotalLeverage { leverage, limit });
+        }
+        Ok(())
+    }
+}
+
+/// Order requests priced outside of [`state::Perpetual::acceptable_price_range`] as of
+/// the tracked state, which the exchange is going to reject with `PriceOutOfRange`
+/// unless the mark price moves by the time the requests get executed.
+///
+/// Yields [`GuardrailViolation::PriceOutOfRange`] per such request without rejecting
+/// the batch, see [`Guardrails::with_price_range_check`] to enforce the range.
+pub fn price_range_warnings<'a>(
+    exchange: &'a state::Exchange,
+    requests: &'a [types::OrderRequest],
+) -> impl Iterator<Item = GuardrailViolation> + 'a {
+    requests
+        .iter()
+        .filter(|r| counts_as_order(r) && r.price() > UD64::ZERO)
+        .filter_map(|r| {
+            let perp = exchange.perpetuals().get(&r.perp_id())?;
+            let (min_price, max_price) = perp.acceptable_price_range()?;
+            (!perp.is_price_acceptable(r.price())).then(|| GuardrailViolation::PriceOutOfRange {
+                request_id: r.request_id(),
+                price: r.price(),
+                min_price,
+                max_price,
+            })
+        })
+}
+
+/// Indicates the request places or changes an order, as opposed to cancellations
+/// and collateral operations.
+fn counts_as_order(request: &types::OrderRequest) -> bool {
+    !matches!(
+        request.r#type(),
+        RequestType::Cancel | RequestType::IncreasePositionCollateral
+    )
+}
+
+fn signed_size(pos: &state::Position) -> D256 {
+    if pos.r#type().is_long() {
+        pos.size().to_signed().resize()
+    } else {
+        pos.size().to_signed().resize().neg()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fastnum::{udec64, udec128};
+
+    use super::*;
+    use crate::{
+        testing::fuzz::{Action, Simulator},
+        types::OrderType,
+    };
+
+    #[test]
+    fn guardrail_limits() {
+        let mut sim = Simulator::new(&[1], 1);
+        let mut exchange = sim.exchange();
+        // Unlevered long of 2 lots at 1000
+        exchange
+            .apply_events(&sim.block(&[Action::Open {
+                perpetual: 0,
+                account: 0,
+                is_long: true,
+                lots: 2,
+            }]))
+            .unwrap();
+        let address = exchange.accounts()[&1].address();
+        let order = |id, r#type, size| {
+            types::OrderRequest::limit(id, 1, r#type, udec64!(1000), size).build()
+        };
+
+        let guardrails = Guardrails::new().with_max_order_notional(udec128!(5000));
+        assert!(
+            guardrails
+                .check(
+                    &exchange,
+                    address,
+                    &[order(1, OrderType::OpenLong, udec64!(5))]
+                )
+                .is_ok()
+        );
+        assert_eq!(
+            guardrails.check(
+                &exchange,
+                address,
+                &[order(2, OrderType::OpenLong, udec64!(6))]
+            ),
+            Err(GuardrailViolation::OrderNotional {
+                request_id: 2,
+                notional: udec128!(6000),
+                limit: udec128!(5000),
+            })
+        );
+
+        // Position of 2 lots grows to 4, reducing it is fine even above the limit
+        let guardrails = Guardrails::new()
+            .with_max_position_notional(udec128!(10000))
+            .with_max_position_notional_for(1, udec128!(3000));
+        assert_eq!(
+            guardrails.check(
+                &exchange,
+                address,
+                &[order(1, OrderType::OpenLong, udec64!(2))]
+            ),
+            Err(GuardrailViolation::PositionNotional {
+                perpetual_id: 1,
+                notional: udec128!(4000),
+                limit: udec128!(3000),
+            })
+        );
+        assert!(
+            guardrails
+                .check(
+                    &exchange,
+                    address,
+                    &[order(1, OrderType::OpenShort, udec64!(3))]
+                )
+                .is_ok()
+        );
+
+        // Equity of 1e9 balance plus 2000 deposit backs total notional of 10000 at 0.00001
+        let guardrails = Guardrails::new().with_max_total_leverage(udec64!(0.00001));
+        assert!(matches!(
+            guardrails.check(
+                &exchange,
+                address,
+                &[order(1, OrderType::OpenLong, udec64!(9))]
+            ),
+            Err(GuardrailViolation::TotalLeverage { .. })
+        ));
+        assert!(
+            guardrails
+                .check(
+                    &exchange,
+                    address,
+                    &[order(1, OrderType::OpenLong, udec64!(8))]
+                )
+                .is_ok()
+        );
+
+        // Admitted orders count towards the block limit, cancellations do not
+        let guardrails = Guardrails::new().with_max_orders_per_block(2);
+        let cancel = types::OrderRequest::cancel(3, 1, types::OrderId::new(1).unwrap());
+        let batch = [order(1, OrderType::OpenLong, udec64!(1)), cancel];
+        guardrails.admit(&exchange, address, &batch).unwrap();
+        guardrails.admit(&exchange, address, &batch).unwrap();
+        assert!(matches!(
+            guardrails.check(&exchange, address, &batch),
+            Err(GuardrailViolation::OrdersPerBlock { count: 3, .. })
+        ));
+        exchange.apply_events(&sim.block(&[])).unwrap();
+        assert!(guardrails.check(&exchange, address, &batch).is_ok());
+    }
+
+    #[test]
+    fn price_range_checks() {
+        use crate::{
+            abi::dex::Exchange::{self as Abi, ExchangeEvents},
+            state::ToleranceBreachKind,
+            stream,
+        };
+        use alloy::primitives::{TxHash, U256};
+
+        let mut exchange = Simulator::new(&[1], 1).exchange();
+        let address = exchange.accounts()[&1].address();
+        let order = |id, price| {
+            types::OrderRequest::limit(id, 1, OrderType::OpenLong, price, udec64!(1)).build()
+        };
+        let batch = [
+            order(1, udec64!(1000)),
+            order(2, udec64!(1060)),
+            order(3, udec64!(950)),
+        ];
+        // Unknown tolerance, no range to check against
+        assert_eq!(price_range_warnings(&exchange, &batch).count(), 0);
+
+        let perp_id = U256::from(1);
+        let events = [
+            ExchangeEvents::PriceTolUpdated(Abi::PriceTolUpdated {
+                perpId: perp_id,
+                tolPer100k: U256::from(5_000),
+            }),
+            ExchangeEvents::MarkUpdated(Abi::MarkUpdated {
+                perpId: perp_id,
+                pricePNS: U256::from(1_000),
+            }),
+            ExchangeEvents::MarkExceedsTol(Abi::MarkExceedsTol {
+                perpId: perp_id,
+                markPNS: U256::from(1_200),
+                synthPerpPricePNS: U256::from(1_000),
+                tolerancePer100k: U256::from(5_000),
+            }),
+        ];
+        let instant = types::StateInstant::new(2, 2);
+        exchange
+            .apply_events(&stream::RawBlockEvents::new(
+                instant,
+                events
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, e)| {
+                        stream::RawEvent::new(TxHash::ZERO, 0, i as u64, Address::ZERO, e)
+                    })
+                    .collect(),
+            ))
+            .unwrap();
+
+        let perp = &exchange.perpetuals()[&1];
+        assert_eq!(perp.price_tolerance(), udec64!(0.05));
+        assert_eq!(
+            perp.acceptable_price_range(),
+            Some((udec64!(950), udec64!(1050)))
+        );
+        let breach = perp.last_tolerance_breach().unwrap();
+        assert_eq!(breach.instant, instant);
+        assert_eq!(
+            breach.kind,
+            ToleranceBreachKind::Mark {
+                mark_price: udec64!(1200),
+                synthetic_price: udec64!(1000),
+            }
+        );
+
+        // Warnings do not reject, unless enforced
+        assert_eq!(
+            price_range_warnings(&exchange, &batch).collect::<Vec<_>>(),
+            vec![GuardrailViolation::PriceOutOfRange {
+                request_id: 2,
+                price: udec64!(1060),
+                min_price: udec64!(950),
+                max_price: udec64!(1050),
+            }]
+        );
+        assert!(Guardrails::new().check(&exchange, address, &batch).is_ok());
+        assert!(matches!(
+            Guardrails::new()
+                .with_price_range_check()
+                .check(&exchange, address, &batch),
+            Err(GuardrailViolation::PriceOutOfRange { request_id: 2, .. })
+        ));
+    }
+}
@@ -0,0 +1,206 @@
+//! Duplicate submission detection with client idempotency keys.
+
+use std::collections::HashMap;
+
+use alloy::{
+    primitives::{U256, keccak256},
+    providers::Provider,
+    rpc::types::Filter,
+    sol_types::SolEvent,
+};
+
+use crate::{
+    Chain,
+    abi::dex::Exchange::{ExchangeEvents, OrderRequest},
+    error::DexError,
+    stream, types,
+};
+
+/// Number of blocks to scan for `OrderRequest` logs with a single request.
+const BLOCKS_PER_SCAN: u64 = 1000;
+
+/// Highest bit is set in request IDs derived from idempotency keys, so they never
+/// collide with sequentially allocated ones.
+const KEYED_REQUEST_ID_BIT: types::RequestId = 1 << 63;
+
+/// Request ID derived from the client idempotency key.
+///
+/// The same key always maps to the same request ID, which the exchange echoes
+/// in the `OrderRequest` event, so submissions of the key can be recognized on-chain.
+pub fn keyed_request_id(key: impl AsRef<[u8]>) -> types::RequestId {
+    let hash = keccak256(key.as_ref());
+    let prefix = u64::from_be_bytes(hash[..8].try_into().expect("8 bytes"));
+    prefix | KEYED_REQUEST_ID_BIT
+}
+
+/// Registry of the order requests submitted by the account, rejecting repeated
+/// submissions of the same idempotency key.
+///
+/// Request IDs of the keys are derived with [`keyed_request_id`] and claimed before
+/// submission. After a crash or restart, [`Self::recover`] scans recent `OrderRequest`
+/// events of the account, so keys submitted by the previous process are rejected too,
+/// preventing double placement on recovery. Keeping the registry up to date with
+/// [`Self::observe`] records requests submitted by other processes of the account.
+///
+/// # Example
+///
+/// ```ignore
+/// use dex_sdk::execution::IdempotencyRegistry;
+///
+/// let mut registry = IdempotencyRegistry::new(account_id);
+/// registry.recover(&chain, &provider, 10_000).await?;
+///
+/// // Fails with `DexError::DuplicateRequest` if already submitted
+/// let request_id = registry.claim(format!("rebalance-{epoch}"))?;
+/// client.submit_orders(&exchange, &[client.buy(&exchange, request_id, perp_id, price, size)]).await?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct IdempotencyRegistry {
+    account_id: types::AccountId,
+    /// Submitted request IDs with the block they were seen on-chain at,
+    /// `None` if only claimed locally.
+    submitted: HashMap<types::RequestId, Option<u64>>,
+    scanned_to_block: Option<u64>,
+}
+
+impl IdempotencyRegistry {
+    pub fn new(account_id: types::AccountId) -> Self {
+        Self {
+            account_id,
+            submitted: HashMap::new(),
+            scanned_to_block: None,
+        }
+    }
+
+    pub fn account_id(&self) -> types::AccountId {
+        self.account_id
+    }
+
+    /// Claims the request ID of the idempotency key for submission, failing with
+    /// [`DexError::DuplicateRequest`] if the key was already claimed or seen on-chain.
+    ///
+    /// Claims are not released if the submission fails, use a new key to retry.
+    pub fn claim(&mut self, key: impl AsRef<[u8]>) -> Result<types::RequestId, DexError> {
+        let request_id = keyed_request_id(key);
+        if self.submitted.contains_key(&request_id) {
+            return Err(DexError::DuplicateRequest(request_id));
+        }
+        self.submitted.insert(request_id, None);
+        Ok(request_id)
+    }
+
+    /// Whether the request ID was claimed or seen on-chain.
+    pub fn contains(&self, request_id: types::RequestId) -> bool {
+        self.submitted.contains_key(&request_id)
+    }
+
+    /// Block the request was seen on-chain at, if it was.
+    pub fn seen_at_block(&self, request_id: types::RequestId) -> Option<u64> {
+        self.submitted.get(&request_id).copied().flatten()
+    }
+
+    /// Last block scanned by [`Self::recover`] or observed.
+    pub fn scanned_to_block(&self) -> Option<u64> {
+        self.scanned_to_block
+    }
+
+    /// Records order requests of the account from the block events.
+    pub fn observe(&mut self, events: &stream::RawBlockEvents) {
+        let block_number = events.instant().block_number();
+        for event in events.events() {
+            if let ExchangeEvents::OrderRequest(e) = event.event() {
+                self.record(e, block_number);
+            }
+        }
+        self.scanned_to_block = self.scanned_to_block.max(Some(block_number));
+    }
+
+    /// Scans `OrderRequest` logs of the last `lookback_blocks` blocks for requests
+    /// of the account, continuing from the last scanned block if it is more recent.
+    ///
+    /// Lookback has to cover the window the previous process could have submitted in,
+    /// e.g. the expiry of its orders.
+    pub async fn recover<P: Provider>(
+        &mut self,
+        chain: &Chain,
+        provider: &P,
+        lookback_blocks: u64,
+    ) -> Result<(), DexError> {
+        let to_block = provider.get_block_number().await?;
+        let from_block = to_block
+            .saturating_sub(lookback_blocks)
+            .max(self.scanned_to_block.map_or(0, |block| block + 1))
+            .max(chain.deployed_at_block());
+        let mut start = from_block;
+        while start <= to_block {
+            let end = (start + BLOCKS_PER_SCAN - 1).min(to_block);
+            let filter = Filter::new()
+                .address(chain.exchange())
+                .event_signature(OrderRequest::SIGNATURE_HASH)
+                .from_block(start)
+                .to_block(end);
+            for log in provider.get_logs(&filter).await? {
+                let event = OrderRequest::decode_log(&log.inner)
+                    .map_err(|err| DexError::Fatal(format!("invalid OrderRequest log: {err}")))?;
+                self.record(&event, log.block_number.unwrap_or(end));
+            }
+            self.scanned_to_block = Some(end);
+            start = end + 1;
+        }
+        Ok(())
+    }
+
+    fn record(&mut self, event: &OrderRequest, block_number: u64) {
+        if event.accountId != U256::from(self.account_id) {
+            return;
+        }
+        let seen_at = self.submitted.entry(event.orderDescId.to()).or_default();
+        seen_at.get_or_insert(block_number);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::sim::{Action, Simulator};
+
+    #[test]
+    fn claims_key_once() {
+        let mut registry = IdempotencyRegistry::new(1);
+        let request_id = registry.claim("rebalance-1").unwrap();
+        assert_eq!(request_id, keyed_request_id("rebalance-1"));
+        assert!(request_id >= KEYED_REQUEST_ID_BIT);
+        assert!(matches!(
+            registry.claim("rebalance-1"),
+            Err(DexError::DuplicateRequest(id)) if id == request_id
+        ));
+        assert!(registry.claim("rebalance-2").is_ok());
+        assert_eq!(registry.seen_at_block(request_id), None);
+    }
+
+    #[test]
+    fn observes_requests_of_account() {
+        let mut sim = Simulator::new(&[1], 2);
+        let place = |account| Action::Place {
+            perpetual: 0,
+            account,
+            is_bid: true,
+            offset: 1,
+            lots: 10,
+        };
+        let block = sim.block(&[place(0), place(1)]);
+        let mut registry = IdempotencyRegistry::new(2);
+        registry.observe(&block);
+
+        // Simulator allocates request IDs sequentially
+        assert!(!registry.contains(1));
+        assert_eq!(
+            registry.seen_at_block(2),
+            Some(block.instant().block_number())
+        );
+        assert_eq!(
+            registry.scanned_to_block(),
+            Some(block.instant().block_number())
+        );
+    }
+}
@@ -0,0 +1,346 @@
+//! Ladder (grid) order generation for liquidity provision.
+
+use fastnum::{D64, UD64};
+
+use crate::{
+    error::DexError,
+    state::{self, Perpetual},
+    types::{self, OrderPresets, OrderRequest, OrderSide, OrderType},
+};
+
+/// Single level of the [`Ladder`].
+#[derive(Clone, Copy, PartialEq, Eq, derive_more::Debug)]
+pub struct LadderLevel {
+    /// Price of the level, valid tick of the perpetual contract.
+    #[debug("{price}")]
+    pub price: UD64,
+
+    /// Size of the level, valid lot of the perpetual contract.
+    #[debug("{size}")]
+    pub size: UD64,
+}
+
+/// Post-only orders at evenly spaced price levels on one side of the book,
+/// see [`ladder`].
+#[derive(Clone, Debug)]
+pub struct Ladder {
+    perpetual_id: types::PerpetualId,
+    side: OrderSide,
+    levels: Vec<LadderLevel>,
+}
+
+impl Ladder {
+    /// ID of the perpetual contract.
+    pub fn perpetual_id(&self) -> types::PerpetualId {
+        self.perpetual_id
+    }
+
+    /// Side of the book.
+    pub fn side(&self) -> OrderSide {
+        self.side
+    }
+
+    /// Levels of the ladder, from the closest to the start price.
+    pub fn levels(&self) -> &[LadderLevel] {
+        &self.levels
+    }
+
+    /// Requests placing all levels of the ladder as post-only orders opening positions,
+    /// with request IDs assigned sequentially and defaults of the perpetual contract applied,
+    /// see [`types::OrderBuilder::build_with`].
+    pub fn requests(
+        &self,
+        first_request_id: types::RequestId,
+        presets: &OrderPresets,
+        block_number: u64,
+    ) -> Vec<OrderRequest> {
+        (first_request_id..)
+            .zip(&self.levels)
+            .map(|(request_id, level)| self.place(request_id, level, presets, block_number))
+            .collect()
+    }
+
+    fn order_type(&self) -> OrderType {
+        match self.side {
+            OrderSide::Bid => OrderType::OpenLong,
+            OrderSide::Ask => OrderType::OpenShort,
+        }
+    }
+
+    fn place(
+        &self,
+        request_id: types::RequestId,
+        level: &LadderLevel,
+        presets: &OrderPresets,
+        block_number: u64,
+    ) -> OrderRequest {
+        OrderRequest::limit_post_only(
+            request_id,
+            self.perpetual_id,
+            self.order_type(),
+            level.price,
+            level.size,
+        )
+        .build_with(presets, block_number)
+    }
+}
+
+/// Generate the ladder of `levels` price levels on the given side of the book,
+/// starting at `start_price` and moving away from the mid price by `step`.
+///
+/// Sizes are distributed linearly around `size_per_level` according to `skew` in range
+/// `[-1, 1]`: positive skew shifts size to the far levels, negative to the near ones,
+/// keeping the total size. Prices get snapped to ticks in the direction away from the mid
+/// price, sizes get rounded down to lots, levels which end up with zero size are skipped.
+///
+/// Fails if the parameters are out of range, the step is too small to produce distinct
+/// prices, or bid prices would reach zero.
+pub fn ladder(
+    perp: &Perpetual,
+    side: OrderSide,
+    start_price: UD64,
+    step: UD64,
+    levels: usize,
+    size_per_level: UD64,
+    skew: D64,
+) -> Result<Ladder, DexError> {
+    if levels == 0 || step == UD64::ZERO || size_per_level == UD64::ZERO {
+        return Err(DexError::InvalidRequest(
+            "ladder needs non-zero levels, step and size".to_string(),
+        ));
+    }
+    if skew.abs() > D64::ONE {
+        return Err(DexError::InvalidRequest(format!(
+            "ladder skew out of range: {skew}"
+        )));
+    }
+
+    let mut result = Ladder {
+        perpetual_id: perp.id(),
+        side,
+        levels: Vec::with_capacity(levels),
+    };
+    let mut prev_price = None;
+    for i in 0..levels {
+        let offset = step * UD64::from(i as u64);
+        let price = match side {
+            OrderSide::Bid if offset >= start_price => {
+                return Err(DexError::InvalidRequest(format!(
+                    "ladder bid price reaches zero at level {i}"
+                )));
+            }
+            OrderSide::Bid => perp.round_price(start_price - offset, side),
+            OrderSide::Ask => perp.round_price(start_price + offset, side),
+        };
+        if prev_price == Some(price) {
+            return Err(DexError::InvalidRequest(format!(
+                "ladder step {step} is below the tick size"
+            )));
+        }
+        prev_price = Some(price);
+
+        // Position of the level in range [-1, 1] from the nearest to the farthest one
+        let position = if levels == 1 {
+            D64::ZERO
+        } else {
+            D64::from(2 * i as u64) / D64::from(levels as u64 - 1) - D64::ONE
+        };
+        let size = perp
+            .round_size((size_per_level.to_signed() * (D64::ONE + skew * position)).unsigned_abs());
+        if size > UD64::ZERO {
+            result.levels.push(LadderLevel { price, size });
+        }
+    }
+    Ok(result)
+}
+
+/// Requests transforming the account resting orders on the ladder side into the ladder,
+/// in order: cancellations, modifications, placements.
+///
+/// Resting orders at the ladder prices get their size adjusted, other resting orders
+/// get moved to the remaining ladder levels, and excess ones get cancelled, so only
+/// levels with no order left to reuse get placed. Only opening orders of the ladder side
+/// are taken into account.
+///
+/// Request IDs are assigned sequentially, defaults of the perpetual contract are applied
+/// to placed orders, as well as the default expiry to modified ones.
+pub fn reladder(
+    ladder: &Ladder,
+    perp: &Perpetual,
+    account_id: types::AccountId,
+    first_request_id: types::RequestId,
+    presets: &OrderPresets,
+    block_number: u64,
+) -> Vec<OrderRequest> {
+    let book = perp.l3_book();
+    let mut resting: Vec<&state::Order> = match ladder.side {
+        OrderSide::Bid => itertools::Either::Left(book.bid_orders()),
+        OrderSide::Ask => itertools::Either::Right(book.ask_orders()),
+    }
+    .map(|o| o.order())
+    .filter(|o| o.account_id() == account_id && o.r#type() == ladder.order_type())
+    .collect();
+
+    // Resting orders at the ladder prices stay, possibly with adjusted sizes
+    let mut changes = Vec::new();
+    let mut missing = Vec::new();
+    for level in &ladder.levels {
+        match resting.iter().position(|o| o.price() == level.price) {
+            Some(idx) => {
+                let order = resting.remove(idx);
+                if order.size() != level.size {
+                    changes.push((order, level));
+                }
+            }
+            None => missing.push(level),
+        }
+    }
+
+    // The rest get moved to the missing levels, closest first
+    let mut missing = missing.into_iter();
+    let mut excess = Vec::new();
+    for order in resting {
+        match missing.next() {
+            Some(level) => changes.push((order, level)),
+            None => excess.push(order),
+        }
+    }
+
+    let expiry_blocks = presets.get(ladder.perpetual_id).expiry_blocks();
+    let mut request_ids = first_request_id..;
+    let mut requests = Vec::new();
+    for order in excess {
+        requests.push(OrderRequest::cancel(
+            request_ids.next().expect("request ID"),
+            ladder.perpetual_id,
+            order.order_id(),
+        ));
+    }
+    for (order, level) in changes {
+        let expiry_block = expiry_blocks
+            .map(|blocks| block_number + blocks)
+            .or((order.expiry_block() > 0).then_some(order.expiry_block()));
+        requests.push(OrderRequest::modify(
+            request_ids.next().expect("request ID"),
+            ladder.perpetual_id,
+            order.order_id(),
+            level.price,
+            level.size,
+            expiry_block,
+        ));
+    }
+    for level in missing {
+        requests.push(ladder.place(
+            request_ids.next().expect("request ID"),
+            level,
+            presets,
+            block_number,
+        ));
+    }
+    requests
+}
+
+#[cfg(test)]
+mod tests {
+    use fastnum::{dec64, udec64};
+
+    use super::*;
+    use crate::types::{OrderDefaults, RequestType};
+
+    fn oid(n: u16) -> types::OrderId {
+        types::OrderId::new(n).expect("test order id must be non-zero")
+    }
+
+    #[test]
+    fn levels() {
+        let perp = Perpetual::for_testing(1);
+        let ladder = ladder(
+            &perp,
+            OrderSide::Bid,
+            udec64!(100.5),
+            udec64!(2),
+            3,
+            udec64!(10),
+            dec64!(0.5),
+        )
+        .unwrap();
+        assert_eq!(
+            ladder
+                .levels()
+                .iter()
+                .map(|l| (l.price, l.size))
+                .collect::<Vec<_>>(),
+            vec![
+                (udec64!(100), udec64!(5)),
+                (udec64!(98), udec64!(10)),
+                (udec64!(96), udec64!(15)),
+            ]
+        );
+
+        let requests = ladder.requests(7, &OrderPresets::new(OrderDefaults::new(udec64!(3))), 0);
+        assert_eq!(requests.len(), 3);
+        assert!(requests.iter().all(|r| r.post_only()
+            && r.leverage() == udec64!(3)
+            && matches!(r.r#type(), RequestType::OpenLong)));
+        assert_eq!(requests[2].request_id(), 9);
+
+        let invalid = |side, step, levels, skew| {
+            super::ladder(&perp, side, udec64!(100), step, levels, udec64!(1), skew).is_err()
+        };
+        assert!(invalid(OrderSide::Ask, udec64!(0.5), 3, D64::ZERO));
+        assert!(invalid(OrderSide::Bid, udec64!(50), 3, D64::ZERO));
+        assert!(invalid(OrderSide::Ask, udec64!(1), 3, dec64!(1.5)));
+        assert!(invalid(OrderSide::Ask, udec64!(1), 0, D64::ZERO));
+    }
+
+    #[test]
+    fn reladder_reuses_orders() {
+        let mut perp = Perpetual::for_testing(1);
+        let rest = |id, price, size, account| {
+            state::Order::for_l3_testing(OrderType::OpenShort, price, size, 1, oid(id), account)
+        };
+        for order in [
+            rest(1, udec64!(101), udec64!(1), 1),
+            rest(2, udec64!(102), udec64!(5), 1),
+            rest(3, udec64!(110), udec64!(1), 1),
+            rest(4, udec64!(120), udec64!(1), 1),
+            rest(5, udec64!(103), udec64!(1), 2),
+        ] {
+            perp.add_order(order).unwrap();
+        }
+        let presets = OrderPresets::new(OrderDefaults::new(udec64!(1)));
+
+        let ladder = ladder(
+            &perp,
+            OrderSide::Ask,
+            udec64!(101),
+            udec64!(1),
+            3,
+            udec64!(1),
+            D64::ZERO,
+        )
+        .unwrap();
+        let requests = reladder(&ladder, &perp, 1, 1, &presets, 0);
+        // Level 101 is in place, 102 gets resized, 103 taken by the order from 110,
+        // and the order at 120 gets cancelled
+        let summary = requests
+            .iter()
+            .map(|r| (r.r#type(), r.order_id().map(|id| id.get()), r.price()))
+            .map(|(t, id, price)| (format!("{t:?}"), id, price))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("Cancel".to_string(), Some(4), UD64::ZERO),
+                ("Change".to_string(), Some(2), udec64!(102)),
+                ("Change".to_string(), Some(3), udec64!(103)),
+            ]
+        );
+        assert_eq!(requests[1].size(), udec64!(1));
+
+        // Nothing to reuse for the other account
+        let requests = reladder(&ladder, &perp, 3, 1, &presets, 0);
+        assert_eq!(requests.len(), 3);
+        assert!(requests.iter().all(|r| r.post_only()));
+    }
+}
@@ -0,0 +1,64 @@
+//! Order submission utilities.
+//!
+//! [`Batcher`] queues outgoing [`crate::types::OrderRequest`]s for a short window and
+//! coalesces them into a single `execOpsAndOrders` transaction per account, reducing gas
+//! and nonce contention for high-frequency strategies.
+//!
+//! [`estimate_batch`] predicts gas usage and cost of an order batch with the per-order
+//! breakdown, so strategies can decide whether an order is worth its gas.
+//!
+//! [`Guardrails`] reject order requests exceeding notional, leverage and rate limits
+//! before they get sent, see [`crate::client::DexClient::with_guardrails`].
+//! [`price_range_warnings`] flags orders priced outside of the exchange price tolerance band.
+//!
+//! [`AccountSigners`] routes order batches of many exchange accounts to their own signers,
+//! tracking nonces per signer, for running sub-accounts out of a single process.
+//!
+//! [`flatten_account`] is the kill switch cancelling all resting orders of the account and
+//! closing all its positions within the slippage limit in a single transaction.
+//!
+//! [`TrailingStops`] follow the favorable extreme of the mark price of the account positions
+//! and close them once the mark retraces by the configured fraction.
+//!
+//! [`IdempotencyRegistry`] derives request IDs from client idempotency keys and rejects
+//! repeated submissions of the same key, including the ones made before a restart.
+//!
+//! [`ladder`] generates grids of post-only orders for liquidity provision, and [`reladder`]
+//! produces the minimal set of requests moving resting orders to the desired grid.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use dex_sdk::execution::{Batcher, BatcherConfig, FlushWindow};
+//!
+//! let config = BatcherConfig::default().with_window(FlushWindow::Interval(Duration::from_millis(20)));
+//! let (batcher, handle) = Batcher::start(&exchange, provider, config, tokio::time::sleep);
+//!
+//! let submission = batcher.submit(address, &request)?;
+//! println!("sent in tx {}", submission.tx_hash().await?);
+//!
+//! // Flushes pending requests and stops the background task
+//! drop(batcher);
+//! handle.await??;
+//! ```
+
+mod batcher;
+mod flatten;
+mod gas;
+mod guardrails;
+mod idempotency;
+mod ladder;
+mod signers;
+mod trailing;
+
+pub use batcher::{Batcher, BatcherConfig, FlushWindow, Submission};
+pub use flatten::{
+    CloseOrder, FlattenOutcome, FlattenPlan, FlattenReport, FlattenStatus, flatten_account,
+    flatten_requests,
+};
+pub use gas::{GasEstimate, estimate_batch};
+pub use guardrails::{GuardrailViolation, Guardrails, price_range_warnings};
+pub use idempotency::{IdempotencyRegistry, keyed_request_id};
+pub use ladder::{Ladder, LadderLevel, ladder, reladder};
+pub use signers::AccountSigners;
+pub use trailing::{StopTrigger, TrailingStop, TrailingStops};
@@ -0,0 +1,173 @@
+//! Order submission on behalf of multiple exchange accounts.
+
+use std::collections::HashMap;
+
+use alloy::{
+    network::Ethereum,
+    primitives::Address,
+    providers::{PendingTransactionBuilder, Provider},
+};
+use futures::future::join_all;
+use tokio::sync::Mutex;
+
+use crate::{Chain, abi::dex::Exchange::ExchangeInstance, error::DexError, state, types};
+
+/// Signer of the single account along with its nonce.
+#[derive(Debug)]
+struct AccountSigner<P> {
+    address: Address,
+    provider: P,
+    /// Next nonce to use, `None` until fetched or after a failed send.
+    nonce: Mutex<Option<u64>>,
+}
+
+/// Routes order request batches of many exchange accounts to their signers.
+///
+/// Each account is sent from its own address via the provider signing for it, so
+/// the same process can run many sub-accounts with separate keys. Nonces are tracked
+/// per signer: batches of the same account are sent one after another with consecutive
+/// nonces, while batches of different accounts are sent concurrently.
+///
+/// Orders of the accounts frozen in the given exchange state are not sent, failing with
+/// [`DexError::AccountFrozen`] until the account gets unfrozen. Orders for perpetual
+/// contracts unavailable due to the exchange halt or the contract pause fail with
+/// [`DexError::VenueUnavailable`] until resumed.
+///
+/// Cached nonce gets re-fetched from the pending state after a failed send, as well as
+/// after [`Self::reset_nonce`], e.g. when transactions are sent from the same address
+/// by other means.
+///
+/// # Example
+///
+/// ```ignore
+/// use dex_sdk::execution::AccountSigners;
+///
+/// let signers = AccountSigners::new(chain)
+///     .with_signer(1, alice, ProviderBuilder::new().wallet(alice_key).connect_http(url))
+///     .with_signer(2, bob, ProviderBuilder::new().wallet(bob_key).connect_http(url));
+/// for (account_id, result) in signers.send_batches(&exchange, batches).await {
+///     println!("account {account_id}: {:?}", result.map(|tx| *tx.tx_hash()));
+/// }
+/// ```
+#[derive(Debug)]
+pub struct AccountSigners<P> {
+    chain: Chain,
+    signers: HashMap<types::AccountId, AccountSigner<P>>,
+}
+
+impl<P: Provider> AccountSigners<P> {
+    pub fn new(chain: Chain) -> Self {
+        Self {
+            chain,
+            signers: HashMap::new(),
+        }
+    }
+
+    /// Send orders of the account from the given address, via the provider
+    /// able to sign for it. Replaces the previous signer of the account, if any.
+    pub fn with_signer(
+        mut self,
+        account_id: types::AccountId,
+        address: Address,
+        provider: P,
+    ) -> Self {
+        self.signers.insert(
+            account_id,
+            AccountSigner {
+                address,
+                provider,
+                nonce: Mutex::new(None),
+            },
+        );
+        self
+    }
+
+    /// IDs of the accounts with signers.
+    pub fn account_ids(&self) -> impl Iterator<Item = types::AccountId> + '_ {
+        self.signers.keys().copied()
+    }
+
+    /// Address the orders of the account are sent from.
+    pub fn address(&self, account_id: types::AccountId) -> Option<Address> {
+        self.signers.get(&account_id).map(|s| s.address)
+    }
+
+    /// Nonce the next transaction of the account is going to be sent with,
+    /// if already known.
+    pub async fn nonce(&self, account_id: types::AccountId) -> Option<u64> {
+        *self.signers.get(&account_id)?.nonce.lock().await
+    }
+
+    /// Forget the cached nonce of the account, so it gets re-fetched before the next send.
+    pub async fn reset_nonce(&self, account_id: types::AccountId) {
+        if let Some(signer) = self.signers.get(&account_id) {
+            *signer.nonce.lock().await = None;
+        }
+    }
+
+    /// Send the order requests of the account in a single `execOpsAndOrders` transaction.
+    pub async fn send_orders(
+        &self,
+        exchange: &state::Exchange,
+        account_id: types::AccountId,
+        requests: &[types::OrderRequest],
+    ) -> Result<PendingTransactionBuilder<Ethereum>, DexError> {
+        let signer = self.signers.get(&account_id).ok_or_else(|| {
+            DexError::InvalidRequest(format!("no signer for account: {account_id}"))
+        })?;
+        if let Some(account) = exchange.accounts().get(&account_id) {
+            account.ensure_not_frozen()?;
+        }
+        exchange.ensure_requests_available(requests)?;
+        let descs = requests
+            .iter()
+            .map(|r| r.prepare(exchange))
+            .collect::<Vec<_>>();
+
+        // Held until the transaction is sent to keep nonces of the account in order
+        let mut nonce = signer.nonce.lock().await;
+        let next = match *nonce {
+            Some(next) => next,
+            None => {
+                signer
+                    .provider
+                    .get_transaction_count(signer.address)
+                    .pending()
+                    .await?
+            }
+        };
+        let result = ExchangeInstance::new(self.chain.exchange(), &signer.provider)
+            .execOpsAndOrders(vec![], descs, false)
+            .from(signer.address)
+            .nonce(next)
+            .send()
+            .await;
+        *nonce = result.as_ref().ok().map(|_| next + 1);
+        Ok(result?)
+    }
+
+    /// Send the order request batches, one transaction per batch, routing each one
+    /// to the signer of its account.
+    ///
+    /// Returns results in the order of the batches.
+    pub async fn send_batches(
+        &self,
+        exchange: &state::Exchange,
+        batches: impl IntoIterator<Item = (types::AccountId, Vec<types::OrderRequest>)>,
+    ) -> Vec<(
+        types::AccountId,
+        Result<PendingTransactionBuilder<Ethereum>, DexError>,
+    )> {
+        join_all(
+            batches
+                .into_iter()
+                .map(|(account_id, requests)| async move {
+                    (
+                        account_id,
+                        self.send_orders(exchange, account_id, &requests).await,
+                    )
+                }),
+        )
+        .await
+    }
+}
@@ -0,0 +1,442 @@
+//! Trailing stops of the account positions.
+//!
+//! [`TrailingStops`] follows the favorable extreme of the mark price of every position
+//! of the account since it got opened: the highest mark for long positions and the lowest
+//! for short ones. Once the mark retraces from the extreme by the configured fraction,
+//! the stop triggers with an immediate-or-cancel order closing the position.
+//!
+//! Trailing state is checkpointed to a file with [`TrailingStops::checkpoint`] in the
+//! [`crate::journal`] record format, e.g. from the [`crate::runtime::RuntimeConfig::with_on_shutdown`]
+//! hook. After restart [`TrailingStops::restore`] loads it, and updating the stops with
+//! every block replayed from the journal catches up with the marks missed in the meantime.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    path::Path,
+};
+
+use fastnum::UD64;
+
+use super::flatten::{CloseOrder, close_order};
+use crate::{
+    error::DexError,
+    journal::{self, Decoder},
+    state,
+    types::{self, OrderRequest, OrderType},
+};
+
+/// Trailing stop of the position.
+#[derive(Clone, Copy, PartialEq, Eq, derive_more::Debug)]
+pub struct TrailingStop {
+    /// ID of the perpetual contract of the position.
+    pub perpetual_id: types::PerpetualId,
+
+    /// Side of the position.
+    pub is_long: bool,
+
+    /// Favorable extreme of the mark price since the position got opened.
+    #[debug("{extreme}")]
+    pub extreme: UD64,
+
+    /// Retracement from the extreme triggering the stop, as a fraction of the extreme.
+    #[debug("{retrace}")]
+    pub retrace: UD64,
+
+    /// Instant the stop was last updated at.
+    pub instant: types::StateInstant,
+
+    /// Indicator of the stop being triggered, no more close orders get requested
+    /// until [`TrailingStops::rearm`].
+    pub triggered: bool,
+}
+
+impl TrailingStop {
+    fn new(
+        perpetual_id: types::PerpetualId,
+        is_long: bool,
+        mark_price: UD64,
+        retrace: UD64,
+        instant: types::StateInstant,
+    ) -> Self {
+        Self {
+            perpetual_id,
+            is_long,
+            extreme: mark_price,
+            retrace,
+            instant,
+            triggered: false,
+        }
+    }
+
+    /// Mark price triggering the stop.
+    pub fn stop_price(&self) -> UD64 {
+        if self.is_long {
+            self.extreme - self.extreme * self.retrace
+        } else {
+            self.extreme + self.extreme * self.retrace
+        }
+    }
+
+    /// Follows the mark price, returns `true` if it reached the stop price.
+    fn follow(&mut self, mark_price: UD64, instant: types::StateInstant) -> bool {
+        self.instant = instant;
+        if self.is_long {
+            self.extreme = self.extreme.max(mark_price);
+            mark_price <= self.stop_price()
+        } else {
+            self.extreme = self.extreme.min(mark_price);
+            mark_price >= self.stop_price()
+        }
+    }
+}
+
+/// Triggered stop with the request closing the position, see [`TrailingStops::update`].
+#[derive(Clone, Debug)]
+pub struct StopTrigger {
+    /// State of the stop at the trigger.
+    pub stop: TrailingStop,
+
+    /// Order closing the position.
+    pub close: CloseOrder,
+
+    /// Request to send.
+    pub request: OrderRequest,
+}
+
+/// Trailing stops of all positions of the account, see the [module](self) documentation.
+#[derive(Clone, derive_more::Debug)]
+pub struct TrailingStops {
+    account_id: types::AccountId,
+    #[debug("{retrace}")]
+    retrace: UD64,
+    #[debug("{max_slippage}")]
+    max_slippage: UD64,
+    retrace_overrides: HashMap<types::PerpetualId, UD64>,
+    stops: BTreeMap<types::PerpetualId, TrailingStop>,
+}
+
+impl TrailingStops {
+    /// Trailing stops of the account positions triggering on the retracement
+    /// by the given fraction, closing positions with `max_slippage` (fraction of
+    /// the mark price) at most.
+    ///
+    /// Fails if either fraction is not below 1 or the retracement is zero.
+    pub fn new(
+        account_id: types::AccountId,
+        retrace: UD64,
+        max_slippage: UD64,
+    ) -> Result<Self, DexError> {
+        validate_retrace(retrace)?;
+        if max_slippage >= UD64::ONE {
+            return Err(DexError::InvalidRequest(format!(
+                "max slippage out of range: {max_slippage}"
+            )));
+        }
+        Ok(Self {
+            account_id,
+            retrace,
+            max_slippage,
+            retrace_overrides: HashMap::new(),
+            stops: BTreeMap::new(),
+        })
+    }
+
+    /// Trigger positions in the perpetual contract on the retracement by the given
+    /// fraction instead, applied to positions opened from now on.
+    pub fn with_retrace(
+        mut self,
+        perpetual_id: types::PerpetualId,
+        retrace: UD64,
+    ) -> Result<Self, DexError> {
+        validate_retrace(retrace)?;
+        self.retrace_overrides.insert(perpetual_id, retrace);
+        Ok(self)
+    }
+
+    pub fn account_id(&self) -> types::AccountId {
+        self.account_id
+    }
+
+    /// Stops of the currently open positions, ordered by perpetual contract ID.
+    pub fn stops(&self) -> impl Iterator<Item = &TrailingStop> {
+        self.stops.values()
+    }
+
+    /// Stop of the position in the perpetual contract, if open.
+    pub fn stop(&self, perpetual_id: types::PerpetualId) -> Option<&TrailingStop> {
+        self.stops.get(&perpetual_id)
+    }
+
+    /// Follows the mark prices of the account positions in the updated state, to be called
+    /// after every applied block. Returns close orders of the triggered stops, with
+    /// request IDs assigned sequentially.
+    ///
+    /// Stops start at the mark price when the position is first seen open, and restart
+    /// when the position gets inverted. Stops of closed positions get dropped.
+    /// Positions in perpetual contracts without the mark price are not followed.
+    ///
+    /// Stop is not triggered while there is no book liquidity within the slippage,
+    /// it is retried on the next update instead.
+    pub fn update(
+        &mut self,
+        exchange: &state::Exchange,
+        first_request_id: types::RequestId,
+    ) -> Result<Vec<StopTrigger>, DexError> {
+        let account = exchange.accounts().get(&self.account_id).ok_or_else(|| {
+            DexError::InvalidRequest(format!("account not tracked: {}", self.account_id))
+        })?;
+        let instant = exchange.instant();
+        self.stops
+            .retain(|perp_id, _| account.positions().contains_key(perp_id));
+
+        let mut request_ids = first_request_id..;
+        let mut triggers = vec![];
+        let mut perp_ids = account.positions().keys().copied().collect::<Vec<_>>();
+        perp_ids.sort_unstable();
+        for perp_id in perp_ids {
+            let position = &account.positions()[&perp_id];
+            let Some(perp) = exchange.perpetuals().get(&perp_id) else {
+                continue;
+            };
+            let mark_price = perp.mark_price();
+            if mark_price == UD64::ZERO {
+                continue;
+            }
+            let is_long = position.r#type().is_long();
+            let retrace = self
+                .retrace_overrides
+                .get(&perp_id)
+                .copied()
+                .unwrap_or(self.retrace);
+            let stop = self
+                .stops
+                .entry(perp_id)
+                .and_modify(|stop| {
+                    if stop.is_long != is_long {
+                        *stop = TrailingStop::new(perp_id, is_long, mark_price, retrace, instant);
+                    }
+                })
+                .or_insert_with(|| {
+                    TrailingStop::new(perp_id, is_long, mark_price, retrace, instant)
+                });
+            if !stop.follow(mark_price, instant) || stop.triggered {
+                continue;
+            }
+            let Some(close) = close_order(
+                perp,
+                self.account_id,
+                position,
+                self.max_slippage,
+                &mut request_ids,
+            ) else {
+                continue;
+            };
+            stop.triggered = true;
+            let r#type = if is_long {
+                OrderType::CloseLong
+            } else {
+                OrderType::CloseShort
+            };
+            triggers.push(StopTrigger {
+                stop: *stop,
+                close,
+                request: OrderRequest::ioc(
+                    close.request_id,
+                    perp_id,
+                    r#type,
+                    close.limit_price,
+                    close.size,
+                )
+                .build(),
+            });
+        }
+        Ok(triggers)
+    }
+
+    /// Re-arms the triggered stop, e.g. after the close order failed or closed the position
+    /// only partially, so the next update can trigger it again.
+    pub fn rearm(&mut self, perpetual_id: types::PerpetualId) {
+        if let Some(stop) = self.stops.get_mut(&perpetual_id) {
+            stop.triggered = false;
+        }
+    }
+
+    /// Writes the trailing state to the file, replacing it atomically.
+    pub fn checkpoint(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut payload = vec![];
+        payload.extend((self.stops.len() as u32).to_le_bytes());
+        for stop in self.stops.values() {
+            payload.extend(stop.perpetual_id.to_le_bytes());
+            payload.push(stop.is_long as u8);
+            payload.push(stop.triggered as u8);
+            payload.extend(stop.instant.block_number().to_le_bytes());
+            payload.extend(stop.instant.block_timestamp().to_le_bytes());
+            for value in [stop.extreme, stop.retrace] {
+                let value = value.to_string();
+                payload.extend((value.len() as u32).to_le_bytes());
+                payload.extend(value.as_bytes());
+            }
+        }
+
+        let tmp = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        journal::write_record(&mut writer, &payload)?;
+        writer.into_inner()?.sync_all()?;
+        std::fs::rename(tmp, path)
+    }
+
+    /// Loads the trailing state written by [`Self::checkpoint`], replacing the current stops.
+    ///
+    /// Returns the latest instant the stops were updated at, so the blocks since can be
+    /// replayed, `None` if there are no stops.
+    pub fn restore(&mut self, path: impl AsRef<Path>) -> io::Result<Option<types::StateInstant>> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let payload = journal::read_record(&mut BufReader::new(file), len)?
+            .ok_or_else(|| journal::invalid_data("truncated trailing stops checkpoint"))?;
+
+        let mut decoder = Decoder(&payload);
+        let count = decoder.u32()?;
+        let mut stops = BTreeMap::new();
+        for _ in 0..count {
+            let perpetual_id = decoder.u32()?;
+            let flags = decoder.take(2)?;
+            let (is_long, triggered) = (flags[0] != 0, flags[1] != 0);
+            let instant = types::StateInstant::new(decoder.u64()?, decoder.u64()?);
+            let mut decimal = || -> io::Result<UD64> {
+                let len = decoder.u32()? as usize;
+                std::str::from_utf8(decoder.take(len)?)
+                    .ok()
+                    .and_then(|value| value.parse().ok())
+                    .ok_or_else(|| journal::invalid_data("invalid trailing stop decimal"))
+            };
+            let (extreme, retrace) = (decimal()?, decimal()?);
+            stops.insert(
+                perpetual_id,
+                TrailingStop {
+                    perpetual_id,
+                    is_long,
+                    extreme,
+                    retrace,
+                    instant,
+                    triggered,
+                },
+            );
+        }
+        self.stops = stops;
+        Ok(self.stops.values().map(|stop| stop.instant).max())
+    }
+}
+
+fn validate_retrace(retrace: UD64) -> Result<(), DexError> {
+    if retrace == UD64::ZERO || retrace >= UD64::ONE {
+        return Err(DexError::InvalidRequest(format!(
+            "trailing retracement out of range: {retrace}"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::{Address, TxHash, U256};
+    use fastnum::udec64;
+
+    use super::*;
+    use crate::{
+        abi::dex::Exchange::{self as Abi, ExchangeEvents},
+        stream,
+        testing::fuzz::{Action, Simulator},
+    };
+
+    fn mark(exchange: &mut state::Exchange, price: u64) {
+        let block_number = exchange.instant().block_number() + 1;
+        let event = ExchangeEvents::MarkUpdated(Abi::MarkUpdated {
+            perpId: U256::from(1),
+            pricePNS: U256::from(price),
+        });
+        exchange
+            .apply_events(&stream::RawBlockEvents::new(
+                types::StateInstant::new(block_number, block_number),
+                vec![stream::RawEvent::new(
+                    TxHash::ZERO,
+                    0,
+                    0,
+                    Address::ZERO,
+                    event,
+                )],
+            ))
+            .unwrap();
+    }
+
+    #[test]
+    fn trailing_long_stop() {
+        let mut sim = Simulator::new(&[1], 2);
+        let mut exchange = sim.exchange();
+        exchange
+            .apply_events(&sim.block(&[
+                Action::Place {
+                    perpetual: 0,
+                    account: 1,
+                    is_bid: true,
+                    offset: 0,
+                    lots: 10,
+                },
+                Action::Open {
+                    perpetual: 0,
+                    account: 0,
+                    is_long: true,
+                    lots: 5,
+                },
+            ]))
+            .unwrap();
+
+        let mut stops = TrailingStops::new(1, udec64!(0.05), udec64!(0.1)).unwrap();
+        // Not followed until the mark price is known
+        assert!(stops.update(&exchange, 1).unwrap().is_empty());
+        assert_eq!(stops.stops().count(), 0);
+
+        for price in [1000, 1100, 1050] {
+            mark(&mut exchange, price);
+            assert!(stops.update(&exchange, 1).unwrap().is_empty());
+        }
+        let stop = *stops.stop(1).unwrap();
+        assert_eq!(stop.extreme, udec64!(1100));
+        assert_eq!(stop.stop_price(), udec64!(1045));
+
+        // Checkpointed state survives restart
+        let path = std::env::temp_dir().join(format!(
+            "dex-sdk-trailing-{}.checkpoint",
+            std::process::id()
+        ));
+        stops.checkpoint(&path).unwrap();
+        let mut restored = TrailingStops::new(1, udec64!(0.05), udec64!(0.1)).unwrap();
+        assert_eq!(restored.restore(&path).unwrap(), Some(exchange.instant()));
+        assert_eq!(restored.stop(1), Some(&stop));
+        std::fs::remove_file(&path).unwrap();
+
+        mark(&mut exchange, 1040);
+        let triggers = restored.update(&exchange, 7).unwrap();
+        assert_eq!(triggers.len(), 1);
+        assert_eq!(triggers[0].request.request_id(), 7);
+        assert_eq!(triggers[0].close.size, udec64!(5));
+        assert_eq!(triggers[0].close.limit_price, udec64!(936));
+        assert!(matches!(
+            triggers[0].request.r#type(),
+            types::RequestType::CloseLong
+        ));
+        assert!(restored.stop(1).unwrap().triggered);
+
+        // Triggered stop waits for the position to close or the re-arm
+        mark(&mut exchange, 1030);
+        assert!(restored.update(&exchange, 8).unwrap().is_empty());
+        restored.rearm(1);
+        assert_eq!(restored.update(&exchange, 8).unwrap().len(), 1);
+
+        assert!(TrailingStops::new(1, UD64::ZERO, udec64!(0.1)).is_err());
+        assert!(TrailingStops::new(1, udec64!(0.05), UD64::ONE).is_err());
+    }
+}
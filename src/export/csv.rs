@@ -0,0 +1,82 @@
+//! CSV output.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+use super::{Column, FileWriter, Value};
+
+pub(super) struct CsvWriter {
+    writer: BufWriter<File>,
+    size: u64,
+}
+
+impl CsvWriter {
+    /// Creates the writer and writes the header row.
+    pub(super) fn new(file: File, columns: &[Column]) -> io::Result<Self> {
+        let mut writer = Self {
+            writer: BufWriter::new(file),
+            size: 0,
+        };
+        let header = columns
+            .iter()
+            .map(|c| Value::Text(c.name.to_string()))
+            .collect::<Vec<_>>();
+        writer.write_row(&header)?;
+        Ok(writer)
+    }
+
+    fn write_row(&mut self, row: &[Value]) -> io::Result<()> {
+        let mut line = String::new();
+        for (i, value) in row.iter().enumerate() {
+            if i > 0 {
+                line.push(',');
+            }
+            match value {
+                Value::Null => {}
+                Value::UInt64(v) => line.push_str(&v.to_string()),
+                Value::Text(v) => line.push_str(&escape(v)),
+            }
+        }
+        line.push('\n');
+        self.writer.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+}
+
+impl FileWriter for CsvWriter {
+    fn write_rows(&mut self, rows: &[Vec<Value>]) -> io::Result<()> {
+        rows.iter().try_for_each(|row| self.write_row(row))
+    }
+
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Quotes the field if it contains separators, quotes or line breaks (RFC 4180).
+fn escape(field: &str) -> std::borrow::Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\"")).into()
+    } else {
+        field.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_escaping() {
+        assert_eq!(escape("plain"), "plain");
+        assert_eq!(escape("a,b"), "\"a,b\"");
+        assert_eq!(escape("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
@@ -0,0 +1,392 @@
+//! Export of trades, state events, candles and state summaries to CSV or Parquet files
+//! for research data collection.
+//!
+//! [`Exporter`] writes [`Record`]s of a single kind into a sequence of files in the
+//! configured directory, starting a new file once the current one exceeds the size
+//! or age limit of the [`ExportConfig`]. Files are named `<prefix>-<sequence>.<extension>`
+//! with the sequence continuing past the files already present in the directory.
+//!
+//! Records are provided for:
+//! * [`TradeRecord`]: maker fills of [`crate::fill::BlockTrades`],
+//! * [`EventRecord`]: [`crate::state::StateEvents`] of the applied block,
+//! * [`crate::marketdata::tape::Candle`]: candles of the trade tape,
+//! * [`BlockSummary`]: top of the books, mark prices, open interest, account equities
+//!   and the state hash as of the applied block.
+//!
+//! Parquet output requires the `parquet` feature.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use dex_sdk::export::{ExportConfig, Exporter, Format, TradeRecord};
+//!
+//! let config = ExportConfig::new("data/trades", "trades", Format::Csv)
+//!     .with_max_bytes(64 << 20)
+//!     .with_max_age(Duration::from_secs(3600));
+//! let mut exporter = Exporter::new(config)?;
+//! while let Some(block_trades) = rx.recv().await {
+//!     exporter.write(TradeRecord::from_block(&block_trades))?;
+//! }
+//! exporter.finish()?;
+//! ```
+
+mod csv;
+#[cfg(feature = "parquet")]
+mod parquet;
+mod records;
+mod summary;
+
+use std::{
+    fs, io,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+pub use records::{EventRecord, TradeRecord};
+pub use summary::{BlockSummary, PerpetualSummary};
+
+/// Output file format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Comma-separated values with the header row.
+    Csv,
+
+    /// Apache Parquet, one row group per [`Exporter::write`] call.
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+impl Format {
+    fn extension(&self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            #[cfg(feature = "parquet")]
+            Format::Parquet => "parquet",
+        }
+    }
+}
+
+/// Destination and rotation configuration of the [`Exporter`].
+#[derive(Clone, Debug)]
+pub struct ExportConfig {
+    dir: PathBuf,
+    prefix: String,
+    format: Format,
+    max_bytes: Option<u64>,
+    max_age: Option<Duration>,
+}
+
+impl ExportConfig {
+    /// Writes files named by the prefix into the directory, created if missing,
+    /// without rotation.
+    pub fn new(dir: impl AsRef<Path>, prefix: impl Into<String>, format: Format) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            prefix: prefix.into(),
+            format,
+            max_bytes: None,
+            max_age: None,
+        }
+    }
+
+    /// Start a new file once the current one reaches the given size.
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Start a new file once the current one was open for the given wall clock time.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+}
+
+/// Column of the exported records.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Column {
+    pub name: &'static str,
+    pub kind: ColumnKind,
+}
+
+impl Column {
+    pub const fn new(name: &'static str, kind: ColumnKind) -> Self {
+        Self { name, kind }
+    }
+}
+
+/// Type of the column values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColumnKind {
+    UInt64,
+    /// Text, also used for decimals and hashes to keep them exact.
+    Text,
+}
+
+/// Value of the column, nullable.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    Null,
+    UInt64(u64),
+    Text(String),
+}
+
+/// Row of the exported file.
+pub trait Record {
+    /// Columns of the rows, in order of [`Self::values`].
+    fn columns() -> &'static [Column];
+
+    /// Values of the row, one per column.
+    fn values(&self) -> Vec<Value>;
+}
+
+/// Writer of a single output file.
+trait FileWriter {
+    fn write_rows(&mut self, rows: &[Vec<Value>]) -> io::Result<()>;
+
+    /// Size of the file so far, including buffered data.
+    fn size(&self) -> u64;
+
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+struct OpenFile {
+    writer: Box<dyn FileWriter>,
+    opened_at: Instant,
+}
+
+/// Writer of records into the sequence of rotated files.
+///
+/// Files get finalized on rotation, [`Self::finish`] or drop, the latter ignoring errors.
+pub struct Exporter<R> {
+    config: ExportConfig,
+    current: Option<OpenFile>,
+    next_sequence: u64,
+    files: Vec<PathBuf>,
+    _record: PhantomData<fn(&R)>,
+}
+
+impl<R: Record> Exporter<R> {
+    /// Creates the exporter, the first file gets created on the first write.
+    pub fn new(config: ExportConfig) -> io::Result<Self> {
+        fs::create_dir_all(&config.dir)?;
+        Ok(Self {
+            config,
+            current: None,
+            next_sequence: 0,
+            files: vec![],
+            _record: PhantomData,
+        })
+    }
+
+    /// Files written so far, oldest first, including the current one.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Appends records to the current file, rotating it first if it exceeds the limits.
+    ///
+    /// Returns the number of written records.
+    pub fn write(&mut self, records: impl IntoIterator<Item = R>) -> io::Result<usize> {
+        let rows = records.into_iter().map(|r| r.values()).collect::<Vec<_>>();
+        if rows.is_empty() {
+            return Ok(0);
+        }
+        if self.current.as_ref().is_some_and(|f| self.is_due(f)) {
+            self.rotate()?;
+        }
+        if self.current.is_none() {
+            self.current = Some(self.open()?);
+        }
+        let file = self.current.as_mut().expect("file is open");
+        file.writer.write_rows(&rows)?;
+        Ok(rows.len())
+    }
+
+    /// Finalizes the current file, the next write starts a new one.
+    pub fn rotate(&mut self) -> io::Result<()> {
+        match self.current.take() {
+            Some(file) => file.writer.finish(),
+            None => Ok(()),
+        }
+    }
+
+    /// Finalizes the current file and returns all written files.
+    pub fn finish(mut self) -> io::Result<Vec<PathBuf>> {
+        self.rotate()?;
+        Ok(std::mem::take(&mut self.files))
+    }
+
+    fn is_due(&self, file: &OpenFile) -> bool {
+        self.config
+            .max_bytes
+            .is_some_and(|max| file.writer.size() >= max)
+            || self
+                .config
+                .max_age
+                .is_some_and(|max| file.opened_at.elapsed() >= max)
+    }
+
+    fn open(&mut self) -> io::Result<OpenFile> {
+        // Skip sequence numbers of the files left by previous runs
+        let (path, file) = loop {
+            let path = self.config.dir.join(format!(
+                "{}-{:06}.{}",
+                self.config.prefix,
+                self.next_sequence,
+                self.config.format.extension()
+            ));
+            self.next_sequence += 1;
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(file) => break (path, file),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        };
+        let writer: Box<dyn FileWriter> = match self.config.format {
+            Format::Csv => Box::new(csv::CsvWriter::new(file, R::columns())?),
+            #[cfg(feature = "parquet")]
+            Format::Parquet => Box::new(parquet::ParquetWriter::new(file, R::columns())?),
+        };
+        self.files.push(path);
+        Ok(OpenFile {
+            writer,
+            opened_at: Instant::now(),
+        })
+    }
+}
+
+impl<R> Drop for Exporter<R> {
+    fn drop(&mut self) {
+        if let Some(file) = self.current.take() {
+            let _ = file.writer.finish();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::TxHash;
+    use fastnum::{UD64, udec64};
+
+    use super::*;
+    use crate::{
+        fill::{BlockTrades, MakerFill, TakerTrade},
+        types::{self, OrderSide},
+    };
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("dex-sdk-export-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn block(number: u64, fills: u64) -> BlockTrades {
+        BlockTrades::new(
+            types::StateInstant::new(number, number * 10),
+            vec![TakerTrade {
+                tx_hash: TxHash::ZERO,
+                tx_index: 0,
+                fill_id: types::FillId::new(TxHash::ZERO, fills),
+                perpetual_id: 1,
+                taker_account_id: 1,
+                taker_side: OrderSide::Bid,
+                taker_fee: UD64::ZERO,
+                maker_fills: (0..fills)
+                    .map(|i| MakerFill {
+                        log_index: i,
+                        fill_id: types::FillId::new(TxHash::ZERO, i),
+                        maker_account_id: 2,
+                        maker_order_id: types::OrderId::new(1).unwrap(),
+                        price: udec64!(100.5),
+                        size: udec64!(0.25),
+                        fee: udec64!(0.01),
+                    })
+                    .collect(),
+                execution: None,
+            }],
+        )
+    }
+
+    #[test]
+    fn csv_rotation_by_size() {
+        let dir = temp_dir("csv");
+        let mut exporter =
+            Exporter::new(ExportConfig::new(&dir, "trades", Format::Csv).with_max_bytes(300))
+                .unwrap();
+        assert_eq!(
+            exporter
+                .write(TradeRecord::from_block(&block(1, 2)))
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            exporter
+                .write(TradeRecord::from_block(&block(2, 0)))
+                .unwrap(),
+            0
+        );
+        exporter
+            .write(TradeRecord::from_block(&block(3, 1)))
+            .unwrap();
+        let files = exporter.finish().unwrap();
+        assert_eq!(files.len(), 2);
+        assert!(files[0].ends_with("trades-000000.csv"));
+
+        let first = fs::read_to_string(&files[0]).unwrap();
+        let lines = first.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            TradeRecord::columns()
+                .iter()
+                .map(|c| c.name)
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        assert!(lines[1].starts_with("1,10,"));
+        assert!(lines[1].contains(",bid,2,1,100.5,0.25,0.01"));
+        assert_eq!(fs::read_to_string(&files[1]).unwrap().lines().count(), 2);
+
+        // Existing files are not overwritten
+        let mut exporter = Exporter::new(ExportConfig::new(&dir, "trades", Format::Csv)).unwrap();
+        exporter
+            .write(TradeRecord::from_block(&block(4, 1)))
+            .unwrap();
+        let files = exporter.finish().unwrap();
+        assert!(files[0].ends_with("trades-000002.csv"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn parquet_output() {
+        use ::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let dir = temp_dir("parquet");
+        let mut exporter =
+            Exporter::new(ExportConfig::new(&dir, "trades", Format::Parquet)).unwrap();
+        exporter
+            .write(TradeRecord::from_block(&block(1, 2)))
+            .unwrap();
+        exporter
+            .write(TradeRecord::from_block(&block(2, 3)))
+            .unwrap();
+        let files = exporter.finish().unwrap();
+        assert_eq!(files.len(), 1);
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(fs::File::open(&files[0]).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let rows = reader.map(|batch| batch.unwrap().num_rows()).sum::<usize>();
+        assert_eq!(rows, 5);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
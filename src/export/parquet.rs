@@ -0,0 +1,77 @@
+//! Parquet output.
+
+use std::{fs::File, io, sync::Arc};
+
+use arrow_array::{ArrayRef, RecordBatch, StringArray, UInt64Array};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::arrow::ArrowWriter;
+
+use super::{Column, ColumnKind, FileWriter, Value};
+
+pub(super) struct ParquetWriter {
+    schema: SchemaRef,
+    writer: ArrowWriter<File>,
+}
+
+impl ParquetWriter {
+    pub(super) fn new(file: File, columns: &[Column]) -> io::Result<Self> {
+        let schema = Arc::new(Schema::new(
+            columns
+                .iter()
+                .map(|c| {
+                    let data_type = match c.kind {
+                        ColumnKind::UInt64 => DataType::UInt64,
+                        ColumnKind::Text => DataType::Utf8,
+                    };
+                    Field::new(c.name, data_type, true)
+                })
+                .collect::<Vec<_>>(),
+        ));
+        let writer = ArrowWriter::try_new(file, schema.clone(), None).map_err(io::Error::other)?;
+        Ok(Self { schema, writer })
+    }
+}
+
+impl FileWriter for ParquetWriter {
+    fn write_rows(&mut self, rows: &[Vec<Value>]) -> io::Result<()> {
+        let columns = self
+            .schema
+            .fields()
+            .iter()
+            .enumerate()
+            .map(|(i, field)| -> ArrayRef {
+                let values = rows.iter().map(|row| &row[i]);
+                match field.data_type() {
+                    DataType::UInt64 => Arc::new(
+                        values
+                            .map(|v| match v {
+                                Value::UInt64(v) => Some(*v),
+                                _ => None,
+                            })
+                            .collect::<UInt64Array>(),
+                    ),
+                    _ => Arc::new(
+                        values
+                            .map(|v| match v {
+                                Value::Text(v) => Some(v.as_str()),
+                                _ => None,
+                            })
+                            .collect::<StringArray>(),
+                    ),
+                }
+            })
+            .collect();
+        let batch = RecordBatch::try_new(self.schema.clone(), columns).map_err(io::Error::other)?;
+        self.writer.write(&batch).map_err(io::Error::other)?;
+        // Row group per write keeps the size estimate close to the file size
+        self.writer.flush().map_err(io::Error::other)
+    }
+
+    fn size(&self) -> u64 {
+        (self.writer.bytes_written() + self.writer.in_progress_size()) as u64
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        self.writer.close().map(|_| ()).map_err(io::Error::other)
+    }
+}
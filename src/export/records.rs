@@ -0,0 +1,230 @@
+//! Records of the exported data.
+
+use alloy::primitives::TxHash;
+use fastnum::UD64;
+
+use super::{Column, ColumnKind, Record, Value};
+use crate::{
+    fill::BlockTrades,
+    marketdata::tape::Candle,
+    state::{StateBlockEvents, StateEvents},
+    types::{self, OrderSide},
+};
+
+/// Maker fill of the taker trade, one row per maker order matched.
+///
+/// Taker fee is charged per taker trade, so it is not included.
+#[derive(Clone, Copy, Debug)]
+pub struct TradeRecord {
+    pub instant: types::StateInstant,
+    pub tx_hash: TxHash,
+    pub log_index: u64,
+    pub perpetual_id: types::PerpetualId,
+    pub taker_account_id: types::AccountId,
+    pub taker_side: OrderSide,
+    pub maker_account_id: types::AccountId,
+    pub maker_order_id: types::OrderId,
+    pub price: UD64,
+    pub size: UD64,
+    pub maker_fee: UD64,
+}
+
+impl TradeRecord {
+    /// Records of all maker fills of the block, in log order.
+    pub fn from_block(block: &BlockTrades) -> impl Iterator<Item = TradeRecord> + '_ {
+        block.trades.iter().flat_map(move |trade| {
+            trade.maker_fills.iter().map(move |fill| TradeRecord {
+                instant: block.instant,
+                tx_hash: trade.tx_hash,
+                log_index: fill.log_index,
+                perpetual_id: trade.perpetual_id,
+                taker_account_id: trade.taker_account_id,
+                taker_side: trade.taker_side,
+                maker_account_id: fill.maker_account_id,
+                maker_order_id: fill.maker_order_id,
+                price: fill.price,
+                size: fill.size,
+                maker_fee: fill.fee,
+            })
+        })
+    }
+}
+
+impl Record for TradeRecord {
+    fn columns() -> &'static [Column] {
+        const COLUMNS: &[Column] = &[
+            Column::new("block_number", ColumnKind::UInt64),
+            Column::new("block_timestamp", ColumnKind::UInt64),
+            Column::new("tx_hash", ColumnKind::Text),
+            Column::new("log_index", ColumnKind::UInt64),
+            Column::new("perpetual_id", ColumnKind::UInt64),
+            Column::new("taker_account_id", ColumnKind::UInt64),
+            Column::new("taker_side", ColumnKind::Text),
+            Column::new("maker_account_id", ColumnKind::UInt64),
+            Column::new("maker_order_id", ColumnKind::UInt64),
+            Column::new("price", ColumnKind::Text),
+            Column::new("size", ColumnKind::Text),
+            Column::new("maker_fee", ColumnKind::Text),
+        ];
+        COLUMNS
+    }
+
+    fn values(&self) -> Vec<Value> {
+        vec![
+            Value::UInt64(self.instant.block_number()),
+            Value::UInt64(self.instant.block_timestamp()),
+            Value::Text(self.tx_hash.to_string()),
+            Value::UInt64(self.log_index),
+            Value::UInt64(self.perpetual_id.into()),
+            Value::UInt64(self.taker_account_id.into()),
+            Value::Text(side(self.taker_side).to_string()),
+            Value::UInt64(self.maker_account_id.into()),
+            Value::UInt64(self.maker_order_id.get().into()),
+            Value::Text(self.price.to_string()),
+            Value::Text(self.size.to_string()),
+            Value::Text(self.maker_fee.to_string()),
+        ]
+    }
+}
+
+/// State event of the applied block, with the type and details of the event
+/// in the debug representation.
+#[derive(Clone, Debug)]
+pub struct EventRecord {
+    pub instant: types::StateInstant,
+    pub meta: types::LogMeta,
+    pub event: StateEvents,
+}
+
+impl EventRecord {
+    /// Records of all state events of the block, in order of production.
+    pub fn from_block(block: &StateBlockEvents) -> impl Iterator<Item = EventRecord> + '_ {
+        block.flat_events().map(|(meta, event)| EventRecord {
+            instant: block.instant(),
+            meta,
+            event: event.clone(),
+        })
+    }
+
+    /// Category, perpetual contract, account, order and request IDs of the event.
+    #[allow(clippy::type_complexity)]
+    fn keys(
+        &self,
+    ) -> (
+        &'static str,
+        Option<types::PerpetualId>,
+        Option<types::AccountId>,
+        Option<types::OrderId>,
+        Option<types::RequestId>,
+    ) {
+        match &self.event {
+            StateEvents::Account(e) => ("account", None, Some(e.account_id), None, e.request_id),
+            StateEvents::BookCrossed(e) => ("book", Some(e.perpetual_id), None, None, None),
+            StateEvents::Error(e) => (
+                "error",
+                Some(e.perpetual_id),
+                Some(e.account_id),
+                e.order_id,
+                Some(e.request_id),
+            ),
+            StateEvents::Exchange(_) => ("exchange", None, None, None, None),
+            StateEvents::Order(e) => (
+                "order",
+                Some(e.perpetual_id),
+                Some(e.account_id),
+                e.order_id,
+                e.request_id,
+            ),
+            StateEvents::Perpetual(e) => ("perpetual", Some(e.perpetual_id), None, None, None),
+            StateEvents::Position(e) => (
+                "position",
+                Some(e.perpetual_id),
+                Some(e.account_id),
+                None,
+                e.request_id,
+            ),
+        }
+    }
+}
+
+impl Record for EventRecord {
+    fn columns() -> &'static [Column] {
+        const COLUMNS: &[Column] = &[
+            Column::new("block_number", ColumnKind::UInt64),
+            Column::new("block_timestamp", ColumnKind::UInt64),
+            Column::new("tx_hash", ColumnKind::Text),
+            Column::new("log_index", ColumnKind::UInt64),
+            Column::new("category", ColumnKind::Text),
+            Column::new("perpetual_id", ColumnKind::UInt64),
+            Column::new("account_id", ColumnKind::UInt64),
+            Column::new("order_id", ColumnKind::UInt64),
+            Column::new("request_id", ColumnKind::UInt64),
+            Column::new("event", ColumnKind::Text),
+        ];
+        COLUMNS
+    }
+
+    fn values(&self) -> Vec<Value> {
+        let (category, perpetual_id, account_id, order_id, request_id) = self.keys();
+        let event = match &self.event {
+            StateEvents::Account(e) => format!("{:?}", e.r#type),
+            StateEvents::BookCrossed(e) => format!("{e:?}"),
+            StateEvents::Error(e) => format!("{:?}", e.r#type),
+            StateEvents::Exchange(e) => format!("{e:?}"),
+            StateEvents::Order(e) => format!("{:?}", e.r#type),
+            StateEvents::Perpetual(e) => format!("{:?}", e.r#type),
+            StateEvents::Position(e) => format!("{:?}", e.r#type),
+        };
+        let opt = |v: Option<u64>| v.map(Value::UInt64).unwrap_or(Value::Null);
+        vec![
+            Value::UInt64(self.instant.block_number()),
+            Value::UInt64(self.instant.block_timestamp()),
+            Value::Text(self.meta.tx_hash.to_string()),
+            Value::UInt64(self.meta.log_index),
+            Value::Text(category.to_string()),
+            opt(perpetual_id.map(u64::from)),
+            opt(account_id.map(u64::from)),
+            opt(order_id.map(|id| id.get().into())),
+            opt(request_id),
+            Value::Text(event),
+        ]
+    }
+}
+
+impl Record for Candle {
+    fn columns() -> &'static [Column] {
+        const COLUMNS: &[Column] = &[
+            Column::new("perpetual_id", ColumnKind::UInt64),
+            Column::new("start", ColumnKind::UInt64),
+            Column::new("interval", ColumnKind::UInt64),
+            Column::new("open", ColumnKind::Text),
+            Column::new("high", ColumnKind::Text),
+            Column::new("low", ColumnKind::Text),
+            Column::new("close", ColumnKind::Text),
+            Column::new("volume", ColumnKind::Text),
+            Column::new("trades", ColumnKind::UInt64),
+        ];
+        COLUMNS
+    }
+
+    fn values(&self) -> Vec<Value> {
+        vec![
+            Value::UInt64(self.perpetual_id.into()),
+            Value::UInt64(self.start),
+            Value::UInt64(self.interval),
+            Value::Text(self.open.to_string()),
+            Value::Text(self.high.to_string()),
+            Value::Text(self.low.to_string()),
+            Value::Text(self.close.to_string()),
+            Value::Text(self.volume.to_string()),
+            Value::UInt64(self.trades),
+        ]
+    }
+}
+
+fn side(side: OrderSide) -> &'static str {
+    match side {
+        OrderSide::Ask => "ask",
+        OrderSide::Bid => "bid",
+    }
+}
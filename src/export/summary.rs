@@ -0,0 +1,179 @@
+//! Per-block summary of the exchange state.
+
+use alloy::primitives::B256;
+use fastnum::{D256, UD64, UD128};
+
+use super::{Column, ColumnKind, Record, Value};
+use crate::{state::Exchange, types};
+
+/// Compact summary of the exchange state as of the applied block, serving as
+/// the per-block consistency marker for consumers not following the full event stream.
+///
+/// Taken with [`Self::from_exchange`] after each [`Exchange::apply_events`], then either
+/// written to files by [`super::Exporter`], one row per block, or passed over a channel.
+///
+/// In the exported rows, perpetual contracts are encoded as `;`-separated
+/// `perpetual_id:best_bid:best_ask:mark_price:open_interest` entries with missing
+/// prices left empty, and equities as `;`-separated `account_id:equity` entries.
+#[derive(Clone, derive_more::Debug)]
+pub struct BlockSummary {
+    /// Instant of the latest applied block.
+    pub instant: types::StateInstant,
+
+    /// Hash of the state, see [`Exchange::state_hash`].
+    pub state_hash: B256,
+
+    /// Tracked perpetual contracts, by ascending ID.
+    pub perpetuals: Vec<PerpetualSummary>,
+
+    /// Equity of the tracked accounts, by ascending account ID,
+    /// see [`crate::state::Account::equity`].
+    #[debug("{equities:?}")]
+    pub equities: Vec<(types::AccountId, D256)>,
+}
+
+/// Top of the book and market state of the perpetual contract within [`BlockSummary`].
+#[derive(Clone, Copy, PartialEq, Eq, derive_more::Debug)]
+pub struct PerpetualSummary {
+    pub perpetual_id: types::PerpetualId,
+
+    /// Best bid price, `None` if the side is empty.
+    #[debug("{best_bid:?}")]
+    pub best_bid: Option<UD64>,
+
+    /// Best ask price, `None` if the side is empty.
+    #[debug("{best_ask:?}")]
+    pub best_ask: Option<UD64>,
+
+    #[debug("{mark_price}")]
+    pub mark_price: UD64,
+
+    #[debug("{open_interest}")]
+    pub open_interest: UD128,
+}
+
+impl BlockSummary {
+    /// Summary of the current state of the exchange.
+    pub fn from_exchange(exchange: &Exchange) -> Self {
+        let mut perpetuals = exchange
+            .perpetuals()
+            .values()
+            .map(|perp| PerpetualSummary {
+                perpetual_id: perp.id(),
+                best_bid: perp.l3_book().best_bid().map(|(price, _)| price),
+                best_ask: perp.l3_book().best_ask().map(|(price, _)| price),
+                mark_price: perp.mark_price(),
+                open_interest: perp.open_interest(),
+            })
+            .collect::<Vec<_>>();
+        perpetuals.sort_unstable_by_key(|p| p.perpetual_id);
+        let mut equities = exchange
+            .accounts()
+            .values()
+            .map(|acc| (acc.id(), acc.equity()))
+            .collect::<Vec<_>>();
+        equities.sort_unstable_by_key(|(account_id, _)| *account_id);
+        Self {
+            instant: exchange.instant(),
+            state_hash: exchange.state_hash(),
+            perpetuals,
+            equities,
+        }
+    }
+}
+
+impl Record for BlockSummary {
+    fn columns() -> &'static [Column] {
+        const COLUMNS: &[Column] = &[
+            Column::new("block_number", ColumnKind::UInt64),
+            Column::new("block_timestamp", ColumnKind::UInt64),
+            Column::new("state_hash", ColumnKind::Text),
+            Column::new("perpetuals", ColumnKind::Text),
+            Column::new("equities", ColumnKind::Text),
+        ];
+        COLUMNS
+    }
+
+    fn values(&self) -> Vec<Value> {
+        let price = |p: Option<UD64>| p.map(|p| p.to_string()).unwrap_or_default();
+        let perpetuals = self
+            .perpetuals
+            .iter()
+            .map(|p| {
+                format!(
+                    "{}:{}:{}:{}:{}",
+                    p.perpetual_id,
+                    price(p.best_bid),
+                    price(p.best_ask),
+                    p.mark_price,
+                    p.open_interest
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(";");
+        let equities = self
+            .equities
+            .iter()
+            .map(|(account_id, equity)| format!("{account_id}:{equity}"))
+            .collect::<Vec<_>>()
+            .join(";");
+        vec![
+            Value::UInt64(self.instant.block_number()),
+            Value::UInt64(self.instant.block_timestamp()),
+            Value::Text(self.state_hash.to_string()),
+            Value::Text(perpetuals),
+            Value::Text(equities),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::sim::{Action, Simulator};
+
+    #[test]
+    fn summarizes_block_state() {
+        let mut sim = Simulator::new(&[2, 1], 2);
+        let mut exchange = sim.exchange();
+        exchange
+            .apply_events(&sim.block(&[Action::Place {
+                perpetual: 1,
+                account: 1,
+                is_bid: true,
+                offset: 0,
+                lots: 5,
+            }]))
+            .unwrap();
+
+        let summary = BlockSummary::from_exchange(&exchange);
+        assert_eq!(summary.instant, exchange.instant());
+        assert_eq!(summary.state_hash, exchange.state_hash());
+        assert_eq!(
+            summary
+                .perpetuals
+                .iter()
+                .map(|p| (p.perpetual_id, p.best_bid.is_some(), p.best_ask))
+                .collect::<Vec<_>>(),
+            vec![(1, true, None), (2, false, None)]
+        );
+        assert_eq!(
+            summary
+                .equities
+                .iter()
+                .map(|(id, _)| *id)
+                .collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+
+        let values = summary.values();
+        assert_eq!(values.len(), BlockSummary::columns().len());
+        assert_eq!(values[0], Value::UInt64(2));
+        let Value::Text(perpetuals) = &values[3] else {
+            panic!("perpetuals encoded as text");
+        };
+        let bid = summary.perpetuals[0].best_bid.unwrap();
+        assert!(perpetuals.starts_with(&format!("1:{bid}::")));
+        assert!(perpetuals.contains(";2:::"));
+    }
+}
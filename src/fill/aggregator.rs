@@ -0,0 +1,411 @@
+//! Rolling per-account fill statistics.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+
+use fastnum::{UD64, UD128};
+
+use super::BlockTrades;
+use crate::types;
+
+/// Default interval between summaries.
+const DEFAULT_SUMMARY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Default rolling windows of the summaries.
+const DEFAULT_WINDOWS: [Duration; 2] = [Duration::from_secs(3_600), Duration::from_secs(86_400)];
+
+/// Configuration of the [`FillAggregator`].
+#[derive(Clone, Debug)]
+pub struct AggregatorConfig {
+    windows: Vec<Duration>,
+    summary_interval: Duration,
+}
+
+impl Default for AggregatorConfig {
+    fn default() -> Self {
+        Self {
+            windows: DEFAULT_WINDOWS.to_vec(),
+            summary_interval: DEFAULT_SUMMARY_INTERVAL,
+        }
+    }
+}
+
+impl AggregatorConfig {
+    /// Summarize fills over the given rolling windows, replacing the default ones.
+    pub fn with_windows(mut self, windows: impl IntoIterator<Item = Duration>) -> Self {
+        self.windows = windows.into_iter().collect();
+        self.windows.sort();
+        self.windows.dedup();
+        self
+    }
+
+    /// Emit summaries once the given block time elapses since the previous ones.
+    /// Zero interval emits summaries on every applied block.
+    pub fn with_summary_interval(mut self, summary_interval: Duration) -> Self {
+        self.summary_interval = summary_interval;
+        self
+    }
+
+    /// Rolling windows of the summaries, shortest first.
+    pub fn windows(&self) -> &[Duration] {
+        &self.windows
+    }
+
+    /// Block time between summaries.
+    pub fn summary_interval(&self) -> Duration {
+        self.summary_interval
+    }
+}
+
+/// Maker and taker fill statistics of the account on the perpetual contract.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FillStats {
+    /// Number of maker order fills.
+    pub maker_fills: u64,
+
+    /// Number of taker order executions, each possibly matching many makers.
+    pub taker_fills: u64,
+
+    /// Size filled as the maker.
+    pub maker_volume: UD128,
+
+    /// Size filled as the taker.
+    pub taker_volume: UD128,
+
+    /// Notional value filled as the maker, in collateral token.
+    pub maker_notional: UD128,
+
+    /// Notional value filled as the taker, in collateral token.
+    pub taker_notional: UD128,
+
+    /// Fees paid as the maker, in collateral token.
+    pub maker_fees: UD128,
+
+    /// Fees paid as the taker, in collateral token.
+    pub taker_fees: UD128,
+}
+
+impl FillStats {
+    /// Total number of fills as both maker and taker.
+    pub fn total_fills(&self) -> u64 {
+        self.maker_fills + self.taker_fills
+    }
+
+    /// Total size filled as both maker and taker.
+    pub fn total_volume(&self) -> UD128 {
+        self.maker_volume + self.taker_volume
+    }
+
+    /// Total fees paid as both maker and taker.
+    pub fn total_fees(&self) -> UD128 {
+        self.maker_fees + self.taker_fees
+    }
+
+    /// Share of the volume filled as the maker, in range `[0, 1]`.
+    ///
+    /// Returns `None` if there were no fills.
+    pub fn maker_ratio(&self) -> Option<UD128> {
+        let total = self.total_volume();
+        (total > UD128::ZERO).then(|| self.maker_volume / total)
+    }
+
+    fn add(&mut self, fill: &Fill) {
+        let notional = fill.price.resize() * fill.size.resize();
+        if fill.is_maker {
+            self.maker_fills += 1;
+            self.maker_volume += fill.size.resize();
+            self.maker_notional += notional;
+            self.maker_fees += fill.fee.resize();
+        } else {
+            self.taker_fills += 1;
+            self.taker_volume += fill.size.resize();
+            self.taker_notional += notional;
+            self.taker_fees += fill.fee.resize();
+        }
+    }
+}
+
+/// Statistics of the account on the perpetual contract over the rolling window.
+#[derive(Clone, Copy, Debug)]
+pub struct AccountFillSummary {
+    /// Account ID.
+    pub account_id: types::AccountId,
+
+    /// Perpetual contract ID.
+    pub perpetual_id: types::PerpetualId,
+
+    /// Rolling window the statistics cover, back from the summary instant.
+    pub window: Duration,
+
+    /// Fill statistics within the window.
+    pub stats: FillStats,
+}
+
+/// Periodic summary of all accounts with fills within the longest window.
+#[derive(Clone, Debug)]
+pub struct FillSummaries {
+    /// Instant of the block the summary was emitted at.
+    pub instant: types::StateInstant,
+
+    /// Statistics per account, perpetual contract and window,
+    /// sorted by account ID, perpetual contract ID and window.
+    pub summaries: Vec<AccountFillSummary>,
+}
+
+/// Fill of the account, either as the maker, or the whole taker execution.
+#[derive(Clone, Copy, Debug)]
+struct Fill {
+    timestamp: u64,
+    is_maker: bool,
+    /// Average price for taker executions.
+    price: UD64,
+    size: UD64,
+    fee: UD64,
+}
+
+/// Streaming aggregator of maker/taker fills per account and perpetual contract,
+/// fed from [`crate::fill`] stream.
+///
+/// Keeps fills within the longest configured window and emits [`FillSummaries`]
+/// every summary interval of block time, e.g. to track maker volume for rebate
+/// programs or to monitor execution of per-account strategies.
+///
+/// Windows are measured in block time back from the latest applied block, inclusive.
+///
+/// # Example
+///
+/// ```ignore
+/// use dex_sdk::fill::{AggregatorConfig, FillAggregator};
+///
+/// let mut aggregator = FillAggregator::new(AggregatorConfig::default());
+/// while let Some(block_trades) = rx.recv().await {
+///     if let Some(summaries) = aggregator.apply(&block_trades) {
+///         for s in summaries.summaries {
+///             println!("{} on {} over {:?}: maker volume {}",
+///                 s.account_id, s.perpetual_id, s.window, s.stats.maker_volume);
+///         }
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct FillAggregator {
+    config: AggregatorConfig,
+    instant: types::StateInstant,
+    last_summary: Option<u64>,
+    fills: HashMap<(types::AccountId, types::PerpetualId), VecDeque<Fill>>,
+}
+
+impl FillAggregator {
+    pub fn new(config: AggregatorConfig) -> Self {
+        Self {
+            config,
+            instant: types::StateInstant::default(),
+            last_summary: None,
+            fills: HashMap::new(),
+        }
+    }
+
+    /// Instant of the latest applied block.
+    pub fn instant(&self) -> types::StateInstant {
+        self.instant
+    }
+
+    /// Records fills of the block, drops ones outside of the longest window and
+    /// returns summaries if the summary interval elapsed.
+    pub fn apply(&mut self, block: &BlockTrades) -> Option<FillSummaries> {
+        self.instant = self.instant.max(block.instant);
+        let timestamp = block.instant.block_timestamp();
+        for trade in &block.trades {
+            for fill in &trade.maker_fills {
+                self.fills
+                    .entry((fill.maker_account_id, trade.perpetual_id))
+                    .or_default()
+                    .push_back(Fill {
+                        timestamp,
+                        is_maker: true,
+                        price: fill.price,
+                        size: fill.size,
+                        fee: fill.fee,
+                    });
+            }
+            self.fills
+                .entry((trade.taker_account_id, trade.perpetual_id))
+                .or_default()
+                .push_back(Fill {
+                    timestamp,
+                    is_maker: false,
+                    price: trade.avg_price().unwrap_or_default(),
+                    size: trade.total_size(),
+                    fee: trade.taker_fee,
+                });
+        }
+
+        let since = self.since(self.config.windows.last().copied().unwrap_or_default());
+        self.fills.retain(|_, fills| {
+            while fills.front().is_some_and(|f| f.timestamp < since) {
+                fills.pop_front();
+            }
+            !fills.is_empty()
+        });
+
+        let now = self.instant.block_timestamp();
+        let due = self
+            .last_summary
+            .is_none_or(|last| now.saturating_sub(last) >= self.config.summary_interval.as_secs());
+        due.then(|| {
+            self.last_summary = Some(now);
+            self.summaries()
+        })
+    }
+
+    /// Statistics of the account on the perpetual contract within the window.
+    ///
+    /// Windows longer than the longest configured one only cover the retained fills.
+    pub fn stats(
+        &self,
+        account_id: types::AccountId,
+        perpetual_id: types::PerpetualId,
+        window: Duration,
+    ) -> FillStats {
+        let since = self.since(window);
+        let mut stats = FillStats::default();
+        self.fills
+            .get(&(account_id, perpetual_id))
+            .into_iter()
+            .flat_map(|fills| fills.iter().rev())
+            .take_while(|f| f.timestamp >= since)
+            .for_each(|f| stats.add(f));
+        stats
+    }
+
+    /// Summaries of all accounts with retained fills as of the latest applied block.
+    pub fn summaries(&self) -> FillSummaries {
+        let mut keys = self.fills.keys().copied().collect::<Vec<_>>();
+        keys.sort();
+        let summaries = keys
+            .into_iter()
+            .flat_map(|(account_id, perpetual_id)| {
+                self.config
+                    .windows
+                    .iter()
+                    .map(move |window| AccountFillSummary {
+                        account_id,
+                        perpetual_id,
+                        window: *window,
+                        stats: self.stats(account_id, perpetual_id, *window),
+                    })
+            })
+            .collect();
+        FillSummaries {
+            instant: self.instant,
+            summaries,
+        }
+    }
+
+    fn since(&self, window: Duration) -> u64 {
+        self.instant
+            .block_timestamp()
+            .saturating_sub(window.as_secs())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::TxHash;
+    use fastnum::{udec64, udec128};
+
+    use super::*;
+    use crate::{
+        fill::{MakerFill, TakerTrade},
+        types::OrderSide,
+    };
+
+    fn trade(taker: types::AccountId, makers: &[(types::AccountId, UD64)]) -> TakerTrade {
+        TakerTrade {
+            tx_hash: TxHash::ZERO,
+            tx_index: 0,
+            fill_id: types::FillId::new(TxHash::ZERO, 1),
+            perpetual_id: 1,
+            taker_account_id: taker,
+            taker_side: OrderSide::Bid,
+            taker_fee: udec64!(0.5),
+            maker_fills: makers
+                .iter()
+                .map(|(maker, price)| MakerFill {
+                    log_index: 0,
+                    fill_id: types::FillId::new(TxHash::ZERO, 0),
+                    maker_account_id: *maker,
+                    maker_order_id: types::OrderId::new(1).unwrap(),
+                    price: *price,
+                    size: udec64!(2),
+                    fee: udec64!(0.1),
+                })
+                .collect(),
+            execution: None,
+        }
+    }
+
+    fn block(number: u64, trades: Vec<TakerTrade>) -> BlockTrades {
+        BlockTrades::new(types::StateInstant::new(number, number * 10), trades)
+    }
+
+    #[test]
+    fn rolling_stats_and_summaries() {
+        let hour = Duration::from_secs(3_600);
+        let minute = Duration::from_secs(60);
+        let mut aggregator = FillAggregator::new(
+            AggregatorConfig::default()
+                .with_windows([hour, minute])
+                .with_summary_interval(Duration::from_secs(30)),
+        );
+
+        // First block always emits
+        let summaries = aggregator
+            .apply(&block(
+                1,
+                vec![trade(1, &[(2, udec64!(100)), (3, udec64!(110))])],
+            ))
+            .unwrap();
+        assert_eq!(summaries.summaries.len(), 6);
+        let taker = aggregator.stats(1, 1, minute);
+        assert_eq!(taker.taker_fills, 1);
+        assert_eq!(taker.taker_volume, udec128!(4));
+        assert_eq!(taker.taker_notional, udec128!(420));
+        assert_eq!(taker.taker_fees, udec128!(0.5));
+        assert_eq!(taker.maker_ratio(), Some(UD128::ZERO));
+
+        // Interval has not elapsed yet
+        assert!(
+            aggregator
+                .apply(&block(2, vec![trade(2, &[(3, udec64!(100))])]))
+                .is_none()
+        );
+        let both = aggregator.stats(2, 1, minute);
+        assert_eq!((both.maker_fills, both.taker_fills), (1, 1));
+        assert_eq!(both.total_fees(), udec128!(0.6));
+        assert_eq!(both.maker_ratio(), Some(udec128!(0.5)));
+        let maker = aggregator.stats(3, 1, minute);
+        assert_eq!(maker.maker_fills, 2);
+        assert_eq!(maker.maker_notional, udec128!(420));
+
+        // Earlier fills fall out of the short window, but not the long one
+        let summaries = aggregator.apply(&block(8, vec![])).unwrap();
+        assert_eq!(summaries.instant.block_number(), 8);
+        assert_eq!(aggregator.stats(3, 1, minute).maker_fills, 1);
+        assert_eq!(aggregator.stats(3, 1, hour).maker_fills, 2);
+        let account_3 = summaries
+            .summaries
+            .iter()
+            .filter(|s| s.account_id == 3)
+            .map(|s| (s.window, s.stats.maker_fills))
+            .collect::<Vec<_>>();
+        assert_eq!(account_3, vec![(minute, 1), (hour, 2)]);
+
+        // Fills outside of the longest window are dropped
+        aggregator.apply(&block(400, vec![]));
+        assert_eq!(aggregator.stats(3, 1, hour), FillStats::default());
+        assert!(aggregator.summaries().summaries.is_empty());
+    }
+}
@@ -1,21 +1,29 @@
 //! Fill listener implementation.
 
-use std::{collections::HashMap, future::Future, num::NonZeroU16, time::Duration};
+use std::{collections::HashMap, num::NonZeroU16};
+#[cfg(feature = "rpc")]
+use std::{future::Future, time::Duration};
 
+#[cfg(feature = "rpc")]
 use alloy::{primitives::U256, providers::Provider};
+#[cfg(feature = "rpc")]
 use futures::StreamExt;
+#[cfg(feature = "rpc")]
 use tokio::sync::mpsc;
 
-use super::types::{BlockTrades, MakerFill, TakerTrade, TradeReceiver};
+#[cfg(feature = "rpc")]
+use super::types::TradeReceiver;
+use super::types::{BlockTrades, MakerFill, TakerTrade};
+#[cfg(feature = "rpc")]
+use crate::{Chain, abi::dex::Exchange::ExchangeInstance, error::DexError};
 use crate::{
-    Chain,
-    abi::dex::Exchange::{ExchangeEvents, ExchangeInstance, MakerOrderFilled},
-    error::DexError,
+    abi::dex::Exchange::{ExchangeEvents, MakerOrderFilled},
     num, stream,
     types::{self, OrderSide, RequestType},
 };
 
 /// Default channel buffer size.
+#[cfg(feature = "rpc")]
 const DEFAULT_CHANNEL_SIZE: usize = 100;
 
 /// Configuration for normalization.
@@ -163,6 +171,7 @@ impl TradeProcessor {
         Some(TakerTrade {
             tx_hash: taker_tx_hash,
             tx_index: event.tx_index(),
+            fill_id: event.fill_id(),
             perpetual_id,
             taker_account_id: ctx.account_id,
             taker_side: ctx.side,
@@ -171,6 +180,7 @@ impl TradeProcessor {
                 .into_iter()
                 .map(|m| MakerFill {
                     log_index: m.log_index,
+                    fill_id: types::FillId::new(m.tx_hash, m.log_index),
                     maker_account_id: m.maker_account_id,
                     maker_order_id: m.maker_order_id,
                     price: m.price,
@@ -178,12 +188,51 @@ impl TradeProcessor {
                     fee: m.maker_fee,
                 })
                 .collect(),
+            execution: None,
         })
     }
 }
 
 impl NormalizationConfig {
+    #[cfg(test)]
+    pub(crate) fn for_testing(
+        collateral_decimals: u8,
+        perpetuals: impl IntoIterator<Item = (types::PerpetualId, u8, u8)>,
+    ) -> Self {
+        Self {
+            collateral_converter: num::Converter::new(collateral_decimals),
+            perpetuals: perpetuals
+                .into_iter()
+                .map(|(id, price_decimals, lot_decimals)| {
+                    (
+                        id,
+                        PerpetualConverters {
+                            price_converter: num::Converter::new(price_decimals),
+                            size_converter: num::Converter::new(lot_decimals),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Converter of collateral token amounts.
+    pub(crate) fn collateral_converter(&self) -> num::Converter {
+        self.collateral_converter
+    }
+
+    /// Price and size converters of the perpetual contract, if known.
+    pub(crate) fn perpetual_converters(
+        &self,
+        perp_id: types::PerpetualId,
+    ) -> Option<(num::Converter, num::Converter)> {
+        self.perpetuals
+            .get(&perp_id)
+            .map(|c| (c.price_converter, c.size_converter))
+    }
+
     /// Fetch normalization config from the chain.
+    #[cfg(feature = "rpc")]
     pub async fn fetch<P: Provider>(chain: &Chain, provider: &P) -> Result<Self, DexError> {
         let instance = ExchangeInstance::new(chain.exchange(), provider);
 
@@ -236,6 +285,7 @@ impl NormalizationConfig {
 ///     }
 /// }
 /// ```
+#[cfg(feature = "rpc")]
 pub async fn start<P, S, SFut>(
     chain: &Chain,
     provider: P,
@@ -261,6 +311,7 @@ where
     Ok((TradeReceiver::new(rx), handle))
 }
 
+#[cfg(feature = "rpc")]
 async fn run_listener<P, S, SFut>(
     chain: Chain,
     provider: P,
@@ -294,3 +345,56 @@ where
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        state::{OrderEvent, OrderEventType, StateEvents},
+        testing::fuzz::{Action, Simulator},
+    };
+
+    #[test]
+    fn fill_ids_join_state_events() {
+        let mut sim = Simulator::new(&[1], 2);
+        let mut exchange = sim.exchange();
+        let mut processor = TradeProcessor::new(NormalizationConfig::for_testing(0, [(1, 0, 0)]));
+        let place = Action::Place {
+            perpetual: 0,
+            account: 0,
+            is_bid: true,
+            offset: 0,
+            lots: 10,
+        };
+        exchange.apply_events(&sim.block(&[place])).unwrap();
+
+        let fill = Action::Fill {
+            order: 0,
+            taker: 1,
+            lots: 4,
+        };
+        let block = sim.block(&[fill]);
+        let trades = processor.process_block(&block);
+        let state_events = exchange.apply_events(&block).unwrap().unwrap();
+
+        let fill_ids = state_events
+            .flat_events()
+            .filter_map(|(_, e)| match e {
+                StateEvents::Order(OrderEvent {
+                    r#type:
+                        OrderEventType::Filled {
+                            is_maker, fill_id, ..
+                        },
+                    ..
+                }) => Some((*is_maker, *fill_id)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let trade = &trades.trades[0];
+        assert_eq!(
+            fill_ids,
+            vec![(true, trade.maker_fills[0].fill_id), (false, trade.fill_id)]
+        );
+        assert_ne!(trade.fill_id, trade.maker_fills[0].fill_id);
+    }
+}
@@ -11,6 +11,10 @@
 //! - [`TradeProcessor`] - Pure, synchronous trade extraction from raw events
 //! - [`NormalizationConfig`] - Configuration fetched once at startup
 //! - [`start`] - Async entry point that spawns a background listener task
+//! - [`FillAggregator`] - Optional rolling maker/taker statistics per account,
+//!   emitting periodic [`FillSummaries`]
+//! - [`BlockTrades::attach_execution_quality`] - Optional [`ExecutionQuality`] of the trades
+//!   relative to the pre-trade order book of the tracked state
 //!
 //! # Data Model
 //!
@@ -51,8 +55,16 @@
 //! handle.await??;
 //! ```
 
+mod aggregator;
 mod listener;
+mod quality;
 mod types;
 
-pub use listener::{NormalizationConfig, TradeProcessor, start};
+pub use aggregator::{
+    AccountFillSummary, AggregatorConfig, FillAggregator, FillStats, FillSummaries,
+};
+#[cfg(feature = "rpc")]
+pub use listener::start;
+pub use listener::{NormalizationConfig, TradeProcessor};
+pub use quality::ExecutionQuality;
 pub use types::{BlockTrades, MakerFill, TakerTrade, TradeReceiver};
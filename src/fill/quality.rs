@@ -0,0 +1,247 @@
+//! Execution quality of the taker trades relative to the pre-trade order book.
+
+use std::collections::{HashMap, hash_map};
+
+use fastnum::{D64, UD64};
+
+use super::{BlockTrades, TakerTrade};
+use crate::{
+    state::Exchange,
+    types::{self, OrderSide},
+};
+
+/// Execution metrics of the taker trade, see [`BlockTrades::attach_execution_quality`].
+#[derive(Clone, Copy, PartialEq, Eq, derive_more::Debug)]
+pub struct ExecutionQuality {
+    /// Best opposite price in the pre-trade book, `None` if the side was empty.
+    #[debug("{best_price:?}")]
+    pub best_price: Option<UD64>,
+
+    /// Volume-weighted average price of taking the traded size from the pre-trade book,
+    /// `None` if the side was empty.
+    #[debug("{expected_price:?}")]
+    pub expected_price: Option<UD64>,
+
+    /// Size available in the pre-trade book, less than the traded size if the trade
+    /// consumed liquidity unknown before it.
+    #[debug("{expected_size}")]
+    pub expected_size: UD64,
+
+    /// Volume-weighted average price of the maker fills.
+    #[debug("{realized_price}")]
+    pub realized_price: UD64,
+
+    /// Number of distinct price levels the trade was filled at.
+    pub levels_consumed: usize,
+
+    /// Expected minus realized price for buys, and the opposite for sells, so that
+    /// positive values are in favor of the taker. `None` if nothing was expected.
+    #[debug("{price_improvement:?}")]
+    pub price_improvement: Option<D64>,
+
+    /// Whether any fill was executed at a price worse than the worst one needed
+    /// to take the traded size from the pre-trade book.
+    pub trade_through: bool,
+}
+
+/// Price levels of the side of the book the takers of the block trade against,
+/// best first.
+type Levels = Vec<(UD64, UD64)>;
+
+impl BlockTrades {
+    /// Computes [`ExecutionQuality`] of each trade and attaches it to
+    /// [`TakerTrade::execution`].
+    ///
+    /// The exchange state has to be the one preceding the block, i.e. before applying
+    /// the block with [`Exchange::apply_events`]. Each trade is compared against
+    /// the book as of the block start, depleted by the fills of the preceding trades
+    /// of the block. Orders placed or cancelled within the block before the trade
+    /// are not accounted, showing up as price improvement or trade-through.
+    ///
+    /// Trades of the perpetual contracts unknown to the exchange are left as is.
+    pub fn attach_execution_quality(&mut self, exchange: &Exchange) {
+        let mut books: HashMap<(types::PerpetualId, OrderSide), Levels> = HashMap::new();
+        for trade in &mut self.trades {
+            let Some(perp) = exchange.perpetuals().get(&trade.perpetual_id) else {
+                continue;
+            };
+            let levels = match books.entry((trade.perpetual_id, trade.taker_side)) {
+                hash_map::Entry::Occupied(e) => e.into_mut(),
+                hash_map::Entry::Vacant(e) => {
+                    let book = perp.l3_book();
+                    e.insert(match trade.taker_side {
+                        OrderSide::Bid => book.top_asks(usize::MAX),
+                        OrderSide::Ask => book.top_bids(usize::MAX),
+                    })
+                }
+            };
+            trade.execution = execution_quality(trade, levels);
+            deplete(levels, trade);
+        }
+    }
+}
+
+/// Execution quality of the trade against the levels, `None` if the trade has no fills.
+fn execution_quality(trade: &TakerTrade, levels: &Levels) -> Option<ExecutionQuality> {
+    let realized_price = trade.avg_price()?;
+    let is_buy = trade.taker_side == OrderSide::Bid;
+
+    // Walking the book for the traded size
+    let mut remaining = trade.total_size();
+    let (mut expected_size, mut expected_value, mut worst_price) = (UD64::ZERO, UD64::ZERO, None);
+    for (price, size) in levels {
+        if remaining.is_zero() {
+            break;
+        }
+        let take = remaining.min(*size);
+        expected_size += take;
+        expected_value += take * *price;
+        worst_price = Some(*price);
+        remaining -= take;
+    }
+    let expected_price = (!expected_size.is_zero()).then(|| expected_value / expected_size);
+
+    let mut fill_prices = trade
+        .maker_fills
+        .iter()
+        .map(|f| f.price)
+        .collect::<Vec<_>>();
+    fill_prices.sort();
+    fill_prices.dedup();
+    let trade_through = worst_price.is_some_and(|worst| {
+        fill_prices.iter().any(|price| {
+            if is_buy {
+                *price > worst
+            } else {
+                *price < worst
+            }
+        })
+    });
+
+    Some(ExecutionQuality {
+        best_price: levels.first().map(|(price, _)| *price),
+        expected_price,
+        expected_size,
+        realized_price,
+        levels_consumed: fill_prices.len(),
+        price_improvement: expected_price.map(|expected| {
+            if is_buy {
+                expected.to_signed() - realized_price.to_signed()
+            } else {
+                realized_price.to_signed() - expected.to_signed()
+            }
+        }),
+        trade_through,
+    })
+}
+
+/// Removes the liquidity taken by the trade from the levels.
+fn deplete(levels: &mut Levels, trade: &TakerTrade) {
+    for fill in &trade.maker_fills {
+        if let Some((_, size)) = levels.iter_mut().find(|(price, _)| *price == fill.price) {
+            *size = if *size > fill.size {
+                *size - fill.size
+            } else {
+                UD64::ZERO
+            };
+        }
+    }
+    levels.retain(|(_, size)| !size.is_zero());
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::TxHash;
+    use fastnum::{dec64, udec64};
+
+    use super::*;
+    use crate::{
+        fill::MakerFill,
+        testing::sim::{Action, Simulator},
+    };
+
+    fn trade(side: OrderSide, fills: &[(UD64, UD64)]) -> TakerTrade {
+        TakerTrade {
+            tx_hash: TxHash::ZERO,
+            tx_index: 0,
+            fill_id: types::FillId::new(TxHash::ZERO, 0),
+            perpetual_id: 1,
+            taker_account_id: 2,
+            taker_side: side,
+            taker_fee: UD64::ZERO,
+            maker_fills: fills
+                .iter()
+                .enumerate()
+                .map(|(i, (price, size))| MakerFill {
+                    log_index: i as u64,
+                    fill_id: types::FillId::new(TxHash::ZERO, i as u64),
+                    maker_account_id: 1,
+                    maker_order_id: types::OrderId::new(i as u16 + 1).unwrap(),
+                    price: *price,
+                    size: *size,
+                    fee: UD64::ZERO,
+                })
+                .collect(),
+            execution: None,
+        }
+    }
+
+    #[test]
+    fn compares_against_pre_trade_book() {
+        let mut sim = Simulator::new(&[1], 2);
+        let mut exchange = sim.exchange();
+        let ask = |offset, lots| Action::Place {
+            perpetual: 0,
+            account: 0,
+            is_bid: false,
+            offset,
+            lots,
+        };
+        exchange
+            .apply_events(&sim.block(&[ask(1, 10), ask(3, 10)]))
+            .unwrap();
+        let asks = exchange.perpetuals()[&1].l3_book().top_asks(2);
+        let ((p1, _), (p2, _)) = (asks[0], asks[1]);
+
+        let mut block = BlockTrades::new(
+            exchange.instant(),
+            vec![
+                // Takes the first level and half of the second one
+                trade(OrderSide::Bid, &[(p1, udec64!(10)), (p2, udec64!(5))]),
+                // Skips the rest of the second level
+                trade(OrderSide::Bid, &[(p2 + udec64!(1), udec64!(1))]),
+            ],
+        );
+        block.attach_execution_quality(&exchange);
+
+        let first = block.trades[0].execution.unwrap();
+        assert_eq!(first.best_price, Some(p1));
+        assert_eq!(first.expected_size, udec64!(15));
+        assert_eq!(first.expected_price, Some(first.realized_price));
+        assert_eq!(first.price_improvement, Some(dec64!(0)));
+        assert_eq!(first.levels_consumed, 2);
+        assert!(!first.trade_through);
+
+        // Second trade sees the book depleted by the first one
+        let second = block.trades[1].execution.unwrap();
+        assert_eq!(second.best_price, Some(p2));
+        assert_eq!(second.price_improvement, Some(dec64!(-1)));
+        assert!(second.trade_through);
+    }
+
+    #[test]
+    fn sell_into_empty_book() {
+        let exchange = Simulator::new(&[1], 2).exchange();
+        let mut block = BlockTrades::new(
+            exchange.instant(),
+            vec![trade(OrderSide::Ask, &[(udec64!(100), udec64!(1))])],
+        );
+        block.attach_execution_quality(&exchange);
+
+        let execution = block.trades[0].execution.unwrap();
+        assert_eq!(execution.best_price, None);
+        assert_eq!(execution.expected_size, UD64::ZERO);
+        assert_eq!(execution.price_improvement, None);
+        assert!(!execution.trade_through);
+    }
+}
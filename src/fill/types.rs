@@ -12,6 +12,9 @@ pub struct MakerFill {
     /// Log index of this maker fill event.
     pub log_index: u64,
 
+    /// Maker fill log, matching [`crate::state::OrderEventType::Filled`] of the maker order.
+    pub fill_id: types::FillId,
+
     /// Maker account ID.
     pub maker_account_id: types::AccountId,
 
@@ -41,6 +44,9 @@ pub struct TakerTrade {
     /// Transaction index within the block.
     pub tx_index: u64,
 
+    /// Taker fill log, matching [`crate::state::OrderEventType::Filled`] of the taker order.
+    pub fill_id: types::FillId,
+
     /// Perpetual contract ID.
     pub perpetual_id: types::PerpetualId,
 
@@ -55,6 +61,10 @@ pub struct TakerTrade {
 
     /// All maker fills matched by this taker order.
     pub maker_fills: Vec<MakerFill>,
+
+    /// Execution quality relative to the pre-trade order book, if attached with
+    /// [`BlockTrades::attach_execution_quality`].
+    pub execution: Option<super::ExecutionQuality>,
 }
 
 impl TakerTrade {
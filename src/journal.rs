@@ -0,0 +1,481 @@
+//! Persistent journal of raw exchange events.
+//!
+//! [`Journal`] appends every block of raw events to an append-only file as the stream
+//! runs, so after restart [`stream`] replays journaled blocks from the snapshot instant
+//! before switching to live polling, instead of re-fetching logs from the RPC provider.
+//!
+//! # Format
+//!
+//! The file is a sequence of records, one per block, each consisting of:
+//! * payload length, `u32` little-endian
+//! * CRC-32 (ISO-HDLC) of the payload, `u32` little-endian
+//! * payload: block number and timestamp, followed by the events with their log metadata,
+//!   topics and data, including [`crate::types::BlockEvents::unknown_events`]
+//!
+//! Blocks are journaled contiguously, block index is built in memory when the journal
+//! gets opened. Incomplete trailing record, e.g. after a crash in the middle of a write,
+//! gets truncated, while a record failing the CRC check is reported as invalid data.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use dex_sdk::journal::{self, Journal};
+//!
+//! let journal = Journal::open("events.journal")?;
+//! let from = StateInstant::new(exchange.instant().block_number() + 1, 0);
+//! let events = journal::stream(&chain, provider, from, journal, tokio::time::sleep);
+//! futures::pin_mut!(events);
+//!
+//! while let Some(block_events) = events.next().await {
+//!     exchange.apply_events(&block_events?)?;
+//! }
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use alloy::{
+    primitives::{Address, B256, Bytes, IntoLogData, Log, LogData, TxHash},
+    providers::Provider,
+};
+use futures::{Stream, StreamExt};
+
+use crate::{
+    Chain,
+    error::DexError,
+    stream::{self, DecodedLog, RawBlockEvents, RawEvent, UnknownRawEvent},
+    types,
+};
+
+const CRC32: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+/// Size of the record header: payload length and CRC.
+const HEADER_SIZE: u64 = 8;
+
+/// Append-only journal of raw event blocks.
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    /// Offsets of the block records in the file.
+    index: BTreeMap<u64, u64>,
+    len: u64,
+}
+
+impl Journal {
+    /// Opens the journal file, creating it if does not exist.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+        let file_len = file.metadata()?.len();
+
+        let mut index = BTreeMap::new();
+        let mut reader = BufReader::new(&mut file);
+        let mut len = 0;
+        while let Some(record) = read_record(&mut reader, file_len - len)? {
+            index.insert(block_number(&record)?, len);
+            len += HEADER_SIZE + record.len() as u64;
+        }
+        if len < file_len {
+            // Incomplete trailing record
+            file.set_len(len)?;
+        }
+
+        Ok(Self {
+            path,
+            writer: BufWriter::new(file),
+            index,
+            len,
+        })
+    }
+
+    /// Path of the journal file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The first journaled block.
+    pub fn first_block(&self) -> Option<u64> {
+        self.index.keys().next().copied()
+    }
+
+    /// The last journaled block.
+    pub fn last_block(&self) -> Option<u64> {
+        self.index.keys().next_back().copied()
+    }
+
+    /// Appends the block to the journal and flushes it to the file.
+    ///
+    /// Blocks already in the journal are ignored, blocks not following the last
+    /// journaled one are rejected.
+    pub fn append(&mut self, block: &RawBlockEvents) -> io::Result<()> {
+        let block_number = block.instant().block_number();
+        if let Some(last) = self.last_block() {
+            if block_number <= last {
+                return Ok(());
+            }
+            if block_number != last + 1 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("journal gap: last block {last}, appended {block_number}"),
+                ));
+            }
+        }
+
+        let payload = encode(block);
+        write_record(&mut self.writer, &payload)?;
+        self.writer.flush()?;
+
+        self.index.insert(block_number, self.len);
+        self.len += HEADER_SIZE + payload.len() as u64;
+        Ok(())
+    }
+
+    /// Removes all journaled blocks.
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().set_len(0)?;
+        self.index.clear();
+        self.len = 0;
+        Ok(())
+    }
+
+    /// Journaled blocks starting from the given one.
+    pub fn replay(&self, from_block: u64) -> io::Result<Replay> {
+        let offset = self
+            .index
+            .range(from_block..)
+            .next()
+            .map_or(self.len, |(_, offset)| *offset);
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        Ok(Replay {
+            reader: BufReader::new(file),
+            remaining: self.len - offset,
+        })
+    }
+}
+
+/// Iterator over journaled blocks, see [`Journal::replay`].
+#[derive(Debug)]
+pub struct Replay {
+    reader: BufReader<File>,
+    remaining: u64,
+}
+
+impl Iterator for Replay {
+    type Item = io::Result<RawBlockEvents>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match read_record(&mut self.reader, self.remaining) {
+            Ok(Some(record)) => {
+                self.remaining -= HEADER_SIZE + record.len() as u64;
+                Some(decode(&record))
+            }
+            Ok(None) => None,
+            Err(err) => {
+                self.remaining = 0;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Stream of raw exchange events starting from the given instant, replaying blocks
+/// available in the journal and continuing with [`stream::raw`], journaling the blocks
+/// received from the latter.
+///
+/// If the journal can not be continued from the given instant, e.g. the snapshot is newer
+/// than the last journaled block, the journal gets cleared.
+pub fn stream<'a, P, S, SFut>(
+    chain: &'a Chain,
+    provider: P,
+    from: types::StateInstant,
+    mut journal: Journal,
+    sleep: S,
+) -> impl Stream<Item = Result<RawBlockEvents, DexError>> + 'a
+where
+    P: Provider + 'a,
+    S: Fn(Duration) -> SFut + Copy + 'a,
+    SFut: Future<Output = ()> + 'a,
+{
+    let from_block = from.block_number();
+    let replayable = journal
+        .first_block()
+        .zip(journal.last_block())
+        .is_some_and(|(first, last)| first <= from_block && from_block <= last + 1);
+    let replay = if replayable {
+        journal.replay(from_block)
+    } else {
+        journal.clear().and_then(|_| journal.replay(from_block))
+    };
+    let live_from = journal
+        .last_block()
+        .map_or(from, |last| types::StateInstant::new(last + 1, 0));
+
+    let replayed = futures::stream::iter(match replay {
+        Ok(replay) => itertools::Either::Left(replay.map(|r| r.map_err(journal_error))),
+        Err(err) => itertools::Either::Right(std::iter::once(Err(journal_error(err)))),
+    });
+    let live = stream::raw(chain, provider, live_from, sleep).map(move |result| {
+        let block = result?;
+        journal.append(&block).map_err(journal_error)?;
+        Ok(block)
+    });
+    replayed.chain(live)
+}
+
+fn journal_error(err: io::Error) -> DexError {
+    DexError::Journal(err.to_string())
+}
+
+/// Writes the record: payload length, CRC and payload.
+pub(crate) fn write_record(writer: &mut impl Write, payload: &[u8]) -> io::Result<()> {
+    writer.write_all(&(payload.len() as u32).to_le_bytes())?;
+    writer.write_all(&CRC32.checksum(payload).to_le_bytes())?;
+    writer.write_all(payload)
+}
+
+/// Reads the record payload, returns `None` if there is no complete record
+/// within the remaining length.
+pub(crate) fn read_record(reader: &mut impl Read, remaining: u64) -> io::Result<Option<Vec<u8>>> {
+    if remaining < HEADER_SIZE {
+        return Ok(None);
+    }
+    let mut header = [0; HEADER_SIZE as usize];
+    reader.read_exact(&mut header)?;
+    let len = u32::from_le_bytes(header[..4].try_into().expect("4 bytes"));
+    let crc = u32::from_le_bytes(header[4..].try_into().expect("4 bytes"));
+    if remaining < HEADER_SIZE + len as u64 {
+        return Ok(None);
+    }
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload)?;
+    if CRC32.checksum(&payload) != crc {
+        return Err(invalid_data("journal record CRC mismatch"));
+    }
+    Ok(Some(payload))
+}
+
+pub(crate) fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn encode(block: &RawBlockEvents) -> Vec<u8> {
+    let mut logs = block
+        .events()
+        .iter()
+        .map(|event| (event.meta(), event.event().to_log_data()))
+        .chain(block.unknown_events().iter().map(|event| {
+            let data = event.event();
+            let log = LogData::new_unchecked(data.topics.clone(), data.data.clone());
+            (event.meta(), log)
+        }))
+        .collect::<Vec<_>>();
+    logs.sort_by_key(|(meta, _)| meta.log_index);
+
+    let mut buf = Vec::new();
+    buf.extend(block.instant().block_number().to_le_bytes());
+    buf.extend(block.instant().block_timestamp().to_le_bytes());
+    buf.extend((logs.len() as u32).to_le_bytes());
+    for (meta, log) in logs {
+        buf.extend(meta.tx_hash.as_slice());
+        buf.extend(meta.tx_index.to_le_bytes());
+        buf.extend(meta.log_index.to_le_bytes());
+        buf.extend(meta.address.as_slice());
+        buf.push(log.topics().len() as u8);
+        for topic in log.topics() {
+            buf.extend(topic.as_slice());
+        }
+        buf.extend((log.data.len() as u32).to_le_bytes());
+        buf.extend(log.data.iter());
+    }
+    buf
+}
+
+fn block_number(payload: &[u8]) -> io::Result<u64> {
+    Decoder(payload).u64()
+}
+
+fn decode(payload: &[u8]) -> io::Result<RawBlockEvents> {
+    let mut decoder = Decoder(payload);
+    let instant = types::StateInstant::new(decoder.u64()?, decoder.u64()?);
+    let count = decoder.u32()?;
+    let mut events = Vec::with_capacity(count as usize);
+    let mut unknown = vec![];
+    for _ in 0..count {
+        let tx_hash = TxHash::from_slice(decoder.take(32)?);
+        let tx_index = decoder.u64()?;
+        let log_index = decoder.u64()?;
+        let address = Address::from_slice(decoder.take(20)?);
+        let topics = (0..decoder.take(1)?[0])
+            .map(|_| decoder.take(32).map(B256::from_slice))
+            .collect::<io::Result<Vec<_>>>()?;
+        let data_len = decoder.u32()? as usize;
+        let data = Bytes::copy_from_slice(decoder.take(data_len)?);
+        let log =
+            Log::new(address, topics, data).ok_or_else(|| invalid_data("invalid journaled log"))?;
+        match stream::decode_log(&log, false)
+            .map_err(|err| invalid_data(&format!("invalid journaled event: {err}")))?
+        {
+            DecodedLog::Known(event) => {
+                events.push(RawEvent::new(tx_hash, tx_index, log_index, address, event))
+            }
+            DecodedLog::Unknown(event) => unknown.push(UnknownRawEvent::new(
+                tx_hash, tx_index, log_index, address, event,
+            )),
+        }
+    }
+    Ok(RawBlockEvents::new(instant, events).with_unknown_events(unknown))
+}
+
+pub(crate) struct Decoder<'a>(pub(crate) &'a [u8]);
+
+impl<'a> Decoder<'a> {
+    pub(crate) fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(invalid_data("truncated journal record"));
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(
+            self.take(4)?.try_into().expect("4 bytes"),
+        ))
+    }
+
+    pub(crate) fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(
+            self.take(8)?.try_into().expect("8 bytes"),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fuzz::{Action, Simulator};
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("dex-sdk-{name}-{}.journal", std::process::id()))
+    }
+
+    fn logs(block: &RawBlockEvents) -> Vec<(u64, u64, alloy::primitives::LogData)> {
+        block
+            .events()
+            .iter()
+            .map(|e| (e.tx_index(), e.log_index(), e.event().to_log_data()))
+            .collect()
+    }
+
+    #[test]
+    fn append_and_replay() {
+        let path = temp_path("append-and-replay");
+        let _ = std::fs::remove_file(&path);
+
+        let mut sim = Simulator::new(&[1], 2);
+        let blocks = (0..3)
+            .map(|i| {
+                sim.block(&[Action::Place {
+                    perpetual: 0,
+                    account: i,
+                    is_bid: i % 2 == 0,
+                    offset: 1,
+                    lots: 1,
+                }])
+            })
+            .collect::<Vec<_>>();
+
+        let mut journal = Journal::open(&path).unwrap();
+        for block in &blocks {
+            journal.append(block).unwrap();
+        }
+        // Already journaled block is ignored
+        journal.append(&blocks[1]).unwrap();
+        drop(journal);
+
+        // Torn write of the next record
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[10, 0, 0, 0, 1]).unwrap();
+        drop(file);
+
+        let journal = Journal::open(&path).unwrap();
+        let first = blocks[0].instant().block_number();
+        assert_eq!(journal.first_block(), Some(first));
+        assert_eq!(journal.last_block(), Some(first + 2));
+
+        let replayed = journal
+            .replay(first + 1)
+            .unwrap()
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(replayed.len(), 2);
+        for (replayed, original) in replayed.iter().zip(&blocks[1..]) {
+            assert_eq!(replayed.instant(), original.instant());
+            assert_eq!(logs(replayed), logs(original));
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn corrupted_record() {
+        let path = temp_path("corrupted-record");
+        let mut sim = Simulator::new(&[1], 1);
+        let mut journal = Journal::open(&path).unwrap();
+        journal.clear().unwrap();
+        journal.append(&sim.block(&[])).unwrap();
+        drop(journal);
+
+        let mut bytes = std::fs::read(&path).unwrap();
+        *bytes.last_mut().unwrap() ^= 0xff;
+        std::fs::write(&path, bytes).unwrap();
+        assert_eq!(
+            Journal::open(&path).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn unknown_events() {
+        let mut block = Simulator::new(&[1], 1).block(&[Action::Place {
+            perpetual: 0,
+            account: 0,
+            is_bid: true,
+            offset: 1,
+            lots: 1,
+        }]);
+        let instant = block.instant();
+        let (events, _) = block.into_parts();
+        let unknown = types::UnknownEvent {
+            topics: vec![B256::repeat_byte(1), B256::repeat_byte(2)],
+            data: Bytes::from_static(&[1, 2, 3]),
+        };
+        block =
+            RawBlockEvents::new(instant, events).with_unknown_events(vec![UnknownRawEvent::new(
+                TxHash::ZERO,
+                0,
+                100,
+                Address::ZERO,
+                unknown.clone(),
+            )]);
+
+        let decoded = decode(&encode(&block)).unwrap();
+        assert_eq!(logs(&decoded), logs(&block));
+        assert_eq!(decoded.unknown_events().len(), 1);
+        assert_eq!(decoded.unknown_events()[0].log_index(), 100);
+        assert_eq!(decoded.unknown_events()[0].event(), &unknown);
+    }
+}
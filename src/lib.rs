@@ -11,6 +11,12 @@
 //! Use [`types::OrderRequest`] to prepare order requests to send them with
 //! [`crate::abi::dex::Exchange::ExchangeInstance::execOpsAndOrders`].
 //!
+//! [`client::DexClient`] bundles both with the chain configuration, in either
+//! watch-only or signing mode.
+//!
+//! [`runtime::Runtime`] keeps the snapshot up to date in the background and
+//! shuts down at a block boundary, flushing the final state.
+//!
 //! See `./tests` for examples.
 //!
 //! # Limitations/follow-ups
@@ -25,6 +31,17 @@
 //!
 //! * Test coverage is far below reasonable.
 //!
+//! # Features
+//!
+//! * `rpc` (default): chain access via RPC provider, i.e. snapshots, event streams,
+//!   clients, background services and the testing environment. Without it [`state`],
+//!   [`types`] and other modules free of I/O compile to `wasm32-unknown-unknown`,
+//!   so browser front-ends can apply events fetched by other means with the same logic.
+//!
+//! * `parquet`: Parquet export of state and events.
+//!
+//! * `capi`: C ABI of the state tracking, see [`capi`] for details.
+//!
 //! # Testing
 //!
 //! [`testing`] module provides a local testing environment with collateral
@@ -33,25 +50,86 @@
 //!
 //! [`execution events`]: https://docs.monad.xyz/execution-events/
 
+// State constructors and decoding helpers are used by snapshot building and streams only
+#![cfg_attr(not(feature = "rpc"), allow(dead_code))]
+
 pub mod abi;
+#[cfg(feature = "rpc")]
+pub mod admin;
+pub mod analytics;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "rpc")]
+pub mod client;
+#[cfg(feature = "rpc")]
+pub mod collateral;
+#[cfg(feature = "rpc")]
+pub mod config;
 pub mod error;
+#[cfg(feature = "rpc")]
+pub mod execution;
+pub mod export;
 pub mod fill;
+#[cfg(feature = "rpc")]
+pub mod journal;
+pub mod liquidations;
+pub mod marketdata;
+#[cfg(feature = "rpc")]
+pub mod notify;
 pub mod num;
+pub mod oracle;
+#[cfg(feature = "rpc")]
+pub mod reconcile;
+pub mod render;
+pub mod risk;
+#[cfg(feature = "rpc")]
+pub mod runtime;
 pub mod state;
 pub mod stream;
+#[cfg(feature = "rpc")]
+pub mod sweep;
 pub mod testing;
 pub mod types;
 
 use alloy::primitives::{Address, address};
+#[cfg(feature = "rpc")]
+use alloy::{eips::BlockId, primitives::U256, providers::Provider};
+#[cfg(feature = "rpc")]
+use futures::TryFutureExt;
+#[cfg(feature = "rpc")]
+use std::future::IntoFuture;
+use std::time::Duration;
+
+#[cfg(feature = "rpc")]
+use crate::error::DexError;
+
+/// Highest perpetual contract ID probed by [`Chain::discover`].
+#[cfg(feature = "rpc")]
+const MAX_DISCOVERED_PERPETUAL_ID: types::PerpetualId = 256;
+
+/// Number of perpetual contracts to probe via single multicall during discovery.
+#[cfg(feature = "rpc")]
+const PERPETUALS_PER_DISCOVERY_BATCH: usize = 64;
+
+/// Nominal block time of Monad.
+const DEFAULT_BLOCK_TIME: Duration = Duration::from_millis(400);
+
+/// Number of blocks covering the duration, rounded up.
+fn blocks_from_duration(duration: Duration, block_time: Duration) -> u64 {
+    let block_time = block_time.as_nanos().max(1);
+    duration.as_nanos().div_ceil(block_time) as u64
+}
 
 #[derive(Clone, Debug)]
 /// Chain the exchange is operating on.
 pub struct Chain {
     chain_id: u64,
     collateral_token: Address,
+    collateral_decimals: Option<u8>,
     deployed_at_block: u64,
     exchange: Address,
     perpetuals: Vec<types::PerpetualId>,
+    block_time: Duration,
 }
 
 impl Chain {
@@ -59,12 +137,79 @@ impl Chain {
         Self {
             chain_id: 10143,
             collateral_token: address!("0xdF5B718d8FcC173335185a2a1513eE8151e3c027"),
+            collateral_decimals: None,
             deployed_at_block: 62953,
             exchange: address!("0x9C216D1Ab3e0407b3d6F1d5e9EfFe6d01C326ab7"),
             perpetuals: vec![16, 32, 48, 64],
+            block_time: DEFAULT_BLOCK_TIME,
         }
     }
 
+    /// Monad mainnet deployment, `None` until the exchange is deployed there.
+    pub fn mainnet() -> Option<Self> {
+        None
+    }
+
+    /// Discovers the chain configuration from the exchange smart contract
+    /// deployed at the given address, at the latest block.
+    ///
+    /// Deployment block is found by binary search over the contract code presence,
+    /// so the provider has to serve historical state.
+    ///
+    /// Active perpetual contracts are found by probing IDs up to 256.
+    #[cfg(feature = "rpc")]
+    pub async fn discover<P: Provider>(provider: P, exchange: Address) -> Result<Self, DexError> {
+        let latest = provider.get_block_number().await?;
+        let block_id = BlockId::number(latest);
+        let instance = abi::dex::Exchange::new(exchange, &provider);
+
+        let exchange_info_call = instance.getExchangeInfo().block(block_id);
+        let (chain_id, exchange_info) = futures::try_join!(
+            IntoFuture::into_future(provider.get_chain_id()).map_err(DexError::from),
+            exchange_info_call
+                .call()
+                .into_future()
+                .map_err(DexError::from),
+        )?;
+
+        let deployed_at_block = Self::deployment_block(&provider, exchange, latest).await?;
+
+        let perpetual_ids = (1..=MAX_DISCOVERED_PERPETUAL_ID).collect::<Vec<_>>();
+        let perpetual_batch_futs =
+            perpetual_ids
+                .chunks(PERPETUALS_PER_DISCOVERY_BATCH)
+                .map(|chunk| {
+                    let multicall = provider.multicall().block(block_id).dynamic().extend(
+                        chunk
+                            .iter()
+                            .map(|pid| instance.getPerpetualInfo(U256::from(*pid))),
+                    );
+                    async move { multicall.try_aggregate(false).await }
+                });
+        let perpetuals = futures::future::try_join_all(perpetual_batch_futs)
+            .await?
+            .into_iter()
+            .flatten()
+            .zip(perpetual_ids.iter())
+            .filter_map(|(info, pid)| {
+                // Unknown perpetual contracts either revert or return empty info
+                info.ok()
+                    .filter(|info| !info.symbol.is_empty())
+                    .map(|_| *pid)
+            })
+            .collect();
+
+        Ok(Self {
+            chain_id,
+            collateral_token: exchange_info.collateralToken,
+            collateral_decimals: Some(exchange_info.collateralDecimals.to()),
+            deployed_at_block,
+            exchange,
+            perpetuals,
+            block_time: DEFAULT_BLOCK_TIME,
+        })
+    }
+
     pub fn custom(
         chain_id: u64,
         collateral_token: Address,
@@ -75,12 +220,20 @@ impl Chain {
         Self {
             chain_id,
             collateral_token,
+            collateral_decimals: None,
             deployed_at_block,
             exchange,
             perpetuals,
+            block_time: DEFAULT_BLOCK_TIME,
         }
     }
 
+    /// Average block time used to estimate timestamps of future blocks (default: 400ms).
+    pub fn with_block_time(mut self, block_time: Duration) -> Self {
+        self.block_time = block_time;
+        self
+    }
+
     pub fn chain_id(&self) -> u64 {
         self.chain_id
     }
@@ -89,6 +242,11 @@ impl Chain {
         self.collateral_token
     }
 
+    /// Decimals of the collateral token, known only for discovered chains.
+    pub fn collateral_decimals(&self) -> Option<u8> {
+        self.collateral_decimals
+    }
+
     pub fn deployed_at_block(&self) -> u64 {
         self.deployed_at_block
     }
@@ -100,4 +258,46 @@ impl Chain {
     pub fn perpetuals(&self) -> &[types::PerpetualId] {
         &self.perpetuals
     }
+
+    /// Average block time of the chain.
+    pub fn block_time(&self) -> Duration {
+        self.block_time
+    }
+
+    /// Number of blocks produced within the duration at the average block time,
+    /// rounded up, e.g. to express order expiry in wall clock time.
+    ///
+    /// See [`state::Exchange::expiry_block_in`] for the estimate based on
+    /// the observed block time.
+    pub fn blocks_from_duration(&self, duration: Duration) -> u64 {
+        blocks_from_duration(duration, self.block_time)
+    }
+
+    #[cfg(feature = "rpc")]
+    async fn deployment_block<P: Provider>(
+        provider: &P,
+        exchange: Address,
+        latest: u64,
+    ) -> Result<u64, DexError> {
+        if provider
+            .get_code_at(exchange)
+            .number(latest)
+            .await?
+            .is_empty()
+        {
+            return Err(DexError::InvalidRequest(format!(
+                "no contract deployed at {exchange}"
+            )));
+        }
+        let (mut low, mut high) = (0, latest);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if provider.get_code_at(exchange).number(mid).await?.is_empty() {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        Ok(low)
+    }
 }
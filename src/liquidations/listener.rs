@@ -0,0 +1,350 @@
+//! Liquidation listener implementation.
+
+#[cfg(feature = "rpc")]
+use std::{future::Future, time::Duration};
+
+use alloy::primitives::U256;
+#[cfg(feature = "rpc")]
+use alloy::providers::Provider;
+#[cfg(feature = "rpc")]
+use futures::StreamExt;
+#[cfg(feature = "rpc")]
+use tokio::sync::mpsc;
+
+#[cfg(feature = "rpc")]
+use super::types::LiquidationReceiver;
+use super::types::{BlockLiquidations, Liquidation, LiquidationKind};
+#[cfg(feature = "rpc")]
+use crate::{Chain, error::DexError};
+use crate::{
+    abi::dex::Exchange::ExchangeEvents, fill::NormalizationConfig, state::PositionType, stream,
+    types,
+};
+
+/// Default channel buffer size.
+#[cfg(feature = "rpc")]
+const DEFAULT_CHANNEL_SIZE: usize = 100;
+
+/// Liquidation processor - pure logic, no async.
+pub struct LiquidationProcessor {
+    config: NormalizationConfig,
+}
+
+impl LiquidationProcessor {
+    /// Create a new liquidation processor with the given normalization config.
+    pub fn new(config: NormalizationConfig) -> Self {
+        Self { config }
+    }
+
+    /// Process a block of raw events and extract liquidations.
+    ///
+    /// This is pure logic - no async, no I/O.
+    pub fn process_block(&mut self, events: &stream::RawBlockEvents) -> BlockLiquidations {
+        let mut liquidations: Vec<Liquidation> = Vec::new();
+
+        for event in events.events() {
+            match event.event() {
+                ExchangeEvents::AccountLiquidationCredit(e) => {
+                    // Credit to another account following the liquidation within the same
+                    // transaction is the penalty paid to the liquidator
+                    let account_id: types::AccountId = e.accountId.to();
+                    if let Some(liq) = liquidations.last_mut()
+                        && liq.tx_hash == event.tx_hash()
+                        && liq.kind == LiquidationKind::Liquidated
+                        && liq.liquidator_account_id.is_none()
+                        && liq.account_id != account_id
+                    {
+                        liq.liquidator_account_id = Some(account_id);
+                        liq.penalty = Some(
+                            self.config
+                                .collateral_converter()
+                                .from_unsigned(e.endBalanceCNS.saturating_sub(e.startBalanceCNS)),
+                        );
+                    }
+                }
+                _ => liquidations.extend(self.process_event(event)),
+            }
+        }
+
+        BlockLiquidations::new(events.instant(), liquidations)
+    }
+
+    /// Process a single event, potentially emitting a liquidation.
+    fn process_event(&self, event: &stream::RawEvent) -> Option<Liquidation> {
+        let (kind, perp_id, account_id, position_type, price, size, remaining_size) =
+            match event.event() {
+                ExchangeEvents::PositionLiquidated(e) => (
+                    LiquidationKind::Liquidated,
+                    e.perpId,
+                    e.posAccountId,
+                    e.positionType,
+                    e.liqPricePNS,
+                    e.liqLotLNS,
+                    e.posLotLNS,
+                ),
+                ExchangeEvents::PositionDeleveraged(e) => (
+                    LiquidationKind::Deleveraged {
+                        force_close: e.forceClose,
+                    },
+                    e.perpId,
+                    e.accountId,
+                    e.positionType,
+                    e.deleveragePricePNS,
+                    e.startLotLNS.saturating_sub(e.endLotLNS),
+                    e.endLotLNS,
+                ),
+                ExchangeEvents::PositionUnwound(e) => (
+                    LiquidationKind::Unwound { paid: true },
+                    e.perpId,
+                    e.accountId,
+                    e.positionType,
+                    e.pricePNS,
+                    e.lotLNS,
+                    U256::ZERO,
+                ),
+                ExchangeEvents::PositionUnwoundWithoutPayment(e) => (
+                    LiquidationKind::Unwound { paid: false },
+                    e.perpId,
+                    e.accountId,
+                    e.positionType,
+                    e.pricePNS,
+                    e.lotLNS,
+                    U256::ZERO,
+                ),
+                _ => return None,
+            };
+
+        let perpetual_id: types::PerpetualId = perp_id.to();
+        let (price_converter, size_converter) = self.config.perpetual_converters(perpetual_id)?;
+        Some(Liquidation {
+            tx_hash: event.tx_hash(),
+            tx_index: event.tx_index(),
+            log_index: event.log_index(),
+            kind,
+            perpetual_id,
+            account_id: account_id.to(),
+            position_type: PositionType::from(position_type),
+            price: price_converter.from_unsigned(price),
+            size: size_converter.from_unsigned(size),
+            remaining_size: size_converter.from_unsigned(remaining_size),
+            penalty: None,
+            liquidator_account_id: None,
+        })
+    }
+}
+
+/// Start the liquidation listener.
+///
+/// Returns a receiver for liquidations and a handle to the background task.
+/// The listener will stream all liquidations from the exchange starting from
+/// the specified block, normalize them, and push them to the channel.
+///
+/// # Example
+///
+/// ```ignore
+/// let (mut rx, handle) = liquidations::start(&chain, provider, from, tokio::time::sleep).await?;
+///
+/// while let Some(block_liquidations) = rx.recv().await {
+///     for liq in &block_liquidations.liquidations {
+///         println!("{:?} {:?} of account {} on perp {}: {} @ {}",
+///             liq.kind, liq.position_type, liq.account_id,
+///             liq.perpetual_id, liq.size, liq.price);
+///     }
+/// }
+/// ```
+#[cfg(feature = "rpc")]
+pub async fn start<P, S, SFut>(
+    chain: &Chain,
+    provider: P,
+    from: types::StateInstant,
+    sleep: S,
+) -> Result<
+    (
+        LiquidationReceiver,
+        tokio::task::JoinHandle<Result<(), DexError>>,
+    ),
+    DexError,
+>
+where
+    P: Provider + Clone + Send + 'static,
+    S: Fn(Duration) -> SFut + Copy + Send + 'static,
+    SFut: Future<Output = ()> + Send,
+{
+    // Fetch normalization config
+    let config = NormalizationConfig::fetch(chain, &provider).await?;
+
+    let (tx, rx) = mpsc::channel(DEFAULT_CHANNEL_SIZE);
+
+    let chain_clone = chain.clone();
+    let handle =
+        tokio::spawn(
+            async move { run_listener(chain_clone, provider, from, sleep, config, tx).await },
+        );
+
+    Ok((LiquidationReceiver::new(rx), handle))
+}
+
+#[cfg(feature = "rpc")]
+async fn run_listener<P, S, SFut>(
+    chain: Chain,
+    provider: P,
+    from: types::StateInstant,
+    sleep: S,
+    config: NormalizationConfig,
+    tx: mpsc::Sender<BlockLiquidations>,
+) -> Result<(), DexError>
+where
+    P: Provider,
+    S: Fn(Duration) -> SFut + Copy,
+    SFut: Future<Output = ()>,
+{
+    let raw_stream = stream::raw(&chain, provider, from, sleep);
+    futures::pin_mut!(raw_stream);
+
+    let mut processor = LiquidationProcessor::new(config);
+
+    while let Some(result) = raw_stream.next().await {
+        let block_events = result?;
+
+        // Pure processing - no async
+        let block_liquidations = processor.process_block(&block_events);
+
+        // Send liquidations (even if empty, for block progression tracking)
+        if tx.send(block_liquidations).await.is_err() {
+            // Receiver dropped, graceful shutdown
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::{Address, I256, TxHash};
+    use fastnum::udec64;
+
+    use super::*;
+    use crate::abi::dex::Exchange::{
+        AccountLiquidationCredit, PositionDeleveraged, PositionLiquidated,
+    };
+
+    fn processor() -> LiquidationProcessor {
+        LiquidationProcessor::new(NormalizationConfig::for_testing(6, [(1, 1, 5)]))
+    }
+
+    fn liquidated(perp_id: u64) -> ExchangeEvents {
+        ExchangeEvents::PositionLiquidated(PositionLiquidated {
+            perpId: U256::from(perp_id),
+            posAccountId: U256::from(7),
+            positionType: 0,
+            refPricePNS: U256::from(1_000_000),
+            refIsMark: true,
+            liqPricePNS: U256::from(999_000),
+            liqLotLNS: U256::from(30_000),
+            posLotLNS: U256::from(70_000),
+            deltaPnlCNS: I256::ZERO,
+            fundingCNS: I256::ZERO,
+            posAmountCNS: I256::ZERO,
+            posDepositCNS: U256::ZERO,
+            accAmountCNS: I256::ZERO,
+            accBalanceCNS: U256::ZERO,
+            onOrderBook: false,
+        })
+    }
+
+    fn credit(account_id: u64, start: u64, end: u64) -> ExchangeEvents {
+        ExchangeEvents::AccountLiquidationCredit(AccountLiquidationCredit {
+            perpId: U256::from(1),
+            accountId: U256::from(account_id),
+            startBalanceCNS: U256::from(start),
+            endBalanceCNS: U256::from(end),
+        })
+    }
+
+    fn block(events: Vec<(u8, ExchangeEvents)>) -> stream::RawBlockEvents {
+        stream::RawBlockEvents::new(
+            types::StateInstant::new(10, 100),
+            events
+                .into_iter()
+                .enumerate()
+                .map(|(log_index, (tx, e))| {
+                    stream::RawEvent::new(
+                        TxHash::repeat_byte(tx),
+                        tx as u64,
+                        log_index as u64,
+                        Address::ZERO,
+                        e,
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn liquidation_with_liquidator_credit() {
+        let block = processor().process_block(&block(vec![
+            (1, liquidated(1)),
+            (1, credit(7, 0, 1_000_000)),
+            (1, credit(9, 5_000_000, 7_500_000)),
+            // Unknown perpetual
+            (2, liquidated(2)),
+        ]));
+
+        assert_eq!(block.len(), 1);
+        let liq = &block.liquidations[0];
+        assert_eq!(liq.kind, LiquidationKind::Liquidated);
+        assert_eq!(liq.account_id, 7);
+        assert_eq!(liq.position_type, PositionType::Long);
+        assert_eq!(liq.price, udec64!(99900));
+        assert_eq!(liq.size, udec64!(0.3));
+        assert_eq!(liq.remaining_size, udec64!(0.7));
+        assert!(!liq.is_full());
+        assert_eq!(liq.liquidator_account_id, Some(9));
+        assert_eq!(liq.penalty, Some(udec64!(2.5)));
+    }
+
+    #[test]
+    fn credit_from_other_transaction_is_ignored() {
+        let block = processor().process_block(&block(vec![
+            (1, liquidated(1)),
+            (2, credit(9, 0, 1_000_000)),
+        ]));
+
+        assert_eq!(block.len(), 1);
+        assert_eq!(block.liquidations[0].liquidator_account_id, None);
+        assert_eq!(block.liquidations[0].penalty, None);
+    }
+
+    #[test]
+    fn full_deleverage() {
+        let block = processor().process_block(&block(vec![(
+            1,
+            ExchangeEvents::PositionDeleveraged(PositionDeleveraged {
+                perpId: U256::from(1),
+                accountId: U256::from(3),
+                forceClose: true,
+                positionType: 1,
+                entryPricePNS: U256::from(1_000_000),
+                refPricePNS: U256::from(1_000_000),
+                refIsMark: true,
+                deleveragePricePNS: U256::from(1_010_000),
+                deltaPnlCNS: I256::ZERO,
+                fundingCNS: I256::ZERO,
+                startDepositCNS: U256::ZERO,
+                endDepositCNS: U256::ZERO,
+                startLotLNS: U256::from(50_000),
+                endLotLNS: U256::ZERO,
+                amountCNS: U256::ZERO,
+                balanceCNS: U256::ZERO,
+            }),
+        )]));
+
+        let liq = &block.liquidations[0];
+        assert_eq!(liq.kind, LiquidationKind::Deleveraged { force_close: true });
+        assert_eq!(liq.position_type, PositionType::Short);
+        assert_eq!(liq.price, udec64!(101000));
+        assert_eq!(liq.size, udec64!(0.5));
+        assert!(liq.is_full());
+    }
+}
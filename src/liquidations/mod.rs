@@ -0,0 +1,57 @@
+//! Liquidation listener module for streaming normalized liquidation events.
+//!
+//! Listens to `PositionLiquidated`, `PositionDeleveraged`, `PositionUnwound` and
+//! `PositionUnwoundWithoutPayment` events, normalizes fixed-point values to
+//! decimals, and streams forced position reductions batched per block.
+//!
+//! Intended for liquidation-hunting and risk-monitoring consumers which do not
+//! need the complete exchange state.
+//!
+//! # Architecture
+//!
+//! The module follows the same split as [`crate::fill`]:
+//!
+//! - [`LiquidationProcessor`] - Pure, synchronous liquidation extraction from raw events
+//! - [`crate::fill::NormalizationConfig`] - Configuration fetched once at startup
+//! - [`start`] - Async entry point that spawns a background listener task
+//!
+//! # Data Model
+//!
+//! Each [`Liquidation`] represents a single forced reduction of a position, see
+//! [`LiquidationKind`]. Liquidator account and penalty are taken from the
+//! `AccountLiquidationCredit` event crediting another account within the same
+//! transaction, and are only available for [`LiquidationKind::Liquidated`].
+//!
+//! # Example
+//!
+//! ```ignore
+//! use dex_sdk::{Chain, liquidations, types::StateInstant};
+//!
+//! let chain = Chain::testnet();
+//! let provider = /* setup provider */;
+//! let from = StateInstant::new(latest_block, timestamp);
+//!
+//! let (mut rx, handle) = liquidations::start(&chain, provider, from, tokio::time::sleep).await?;
+//!
+//! while let Some(block_liquidations) = rx.recv().await {
+//!     for liq in &block_liquidations.liquidations {
+//!         println!("{:?} {:?} of account {} on perp {}: {} @ {}, {} remaining",
+//!             liq.kind, liq.position_type, liq.account_id, liq.perpetual_id,
+//!             liq.size, liq.price, liq.remaining_size);
+//!         if let (Some(liquidator), Some(penalty)) = (liq.liquidator_account_id, liq.penalty) {
+//!             println!("  liquidator {} credited {}", liquidator, penalty);
+//!         }
+//!     }
+//! }
+//!
+//! // Check for errors
+//! handle.await??;
+//! ```
+
+mod listener;
+mod types;
+
+pub use listener::LiquidationProcessor;
+#[cfg(feature = "rpc")]
+pub use listener::start;
+pub use types::{BlockLiquidations, Liquidation, LiquidationKind, LiquidationReceiver};
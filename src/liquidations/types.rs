@@ -0,0 +1,119 @@
+//! Liquidation data structures.
+
+use alloy::primitives::TxHash;
+use fastnum::UD64;
+use tokio::sync::mpsc;
+
+use crate::{state::PositionType, types};
+
+/// Kind of the forced position reduction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LiquidationKind {
+    /// Position was (partially) liquidated, `PositionLiquidated` event.
+    Liquidated,
+    /// Position was (partially) deleveraged against opposing positions,
+    /// `PositionDeleveraged` event.
+    Deleveraged {
+        /// Indicates the position was forcibly closed.
+        force_close: bool,
+    },
+    /// Position was unwound on perpetual contract wind-down, `PositionUnwound` and
+    /// `PositionUnwoundWithoutPayment` events.
+    Unwound {
+        /// Indicates the position holder received the payment.
+        paid: bool,
+    },
+}
+
+/// A single forced position reduction.
+#[derive(Clone, Debug)]
+pub struct Liquidation {
+    /// Transaction hash the liquidation occurred in.
+    pub tx_hash: TxHash,
+
+    /// Transaction index within the block.
+    pub tx_index: u64,
+
+    /// Log index of the liquidation event.
+    pub log_index: u64,
+
+    /// Kind of the liquidation.
+    pub kind: LiquidationKind,
+
+    /// Perpetual contract ID.
+    pub perpetual_id: types::PerpetualId,
+
+    /// ID of the account holding the liquidated position.
+    pub account_id: types::AccountId,
+
+    /// Type of the liquidated position.
+    pub position_type: PositionType,
+
+    /// Price the position was reduced at (normalized decimal).
+    pub price: UD64,
+
+    /// Liquidated size (normalized decimal).
+    pub size: UD64,
+
+    /// Size of the position remaining after the liquidation (normalized decimal).
+    pub remaining_size: UD64,
+
+    /// Penalty credited to the liquidator (normalized decimal, in collateral token),
+    /// if observed within the same transaction.
+    pub penalty: Option<UD64>,
+
+    /// ID of the liquidator account credited within the same transaction, if any.
+    pub liquidator_account_id: Option<types::AccountId>,
+}
+
+impl Liquidation {
+    /// Returns true if the position was completely closed.
+    pub fn is_full(&self) -> bool {
+        self.remaining_size == UD64::ZERO
+    }
+}
+
+/// Liquidations from a single block.
+#[derive(Clone, Debug)]
+pub struct BlockLiquidations {
+    /// Block instant.
+    pub instant: types::StateInstant,
+
+    /// All liquidations in this block.
+    pub liquidations: Vec<Liquidation>,
+}
+
+impl BlockLiquidations {
+    pub(crate) fn new(instant: types::StateInstant, liquidations: Vec<Liquidation>) -> Self {
+        Self {
+            instant,
+            liquidations,
+        }
+    }
+
+    /// Returns true if there are no liquidations in this block.
+    pub fn is_empty(&self) -> bool {
+        self.liquidations.is_empty()
+    }
+
+    /// Returns the number of liquidations in this block.
+    pub fn len(&self) -> usize {
+        self.liquidations.len()
+    }
+}
+
+/// Receiver for block liquidations.
+pub struct LiquidationReceiver {
+    inner: mpsc::Receiver<BlockLiquidations>,
+}
+
+impl LiquidationReceiver {
+    pub(crate) fn new(inner: mpsc::Receiver<BlockLiquidations>) -> Self {
+        Self { inner }
+    }
+
+    /// Receives the next batch of liquidations, or `None` if the channel is closed.
+    pub async fn recv(&mut self) -> Option<BlockLiquidations> {
+        self.inner.recv().await
+    }
+}
@@ -0,0 +1,589 @@
+//! Attribution of the account PnL to the sources relevant for market making.
+//!
+//! [`PnlAttribution`] follows the trades of the account from [`crate::fill`] stream and
+//! the applied exchange state, and decomposes the PnL over the window into:
+//! * spread capture: edge of the fill price against the mid price before the trade,
+//! * adverse selection: move of the mid price within the markout horizon after the fill,
+//! * fees paid as the maker and the taker,
+//! * funding payments of the open positions.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use dex_sdk::marketdata::attribution::{AttributionConfig, PnlAttribution};
+//!
+//! let mut attribution = PnlAttribution::new(
+//!     account_id,
+//!     AttributionConfig::default().with_markout(Duration::from_secs(30)),
+//! );
+//! loop {
+//!     // Trades of the block go first, so fills are measured against the mid before the trade
+//!     attribution.apply_trades(&trade_rx.recv().await?);
+//!     if let Some(state_events) = exchange.apply_events(&stream.next().await?)? {
+//!         attribution.apply(&exchange, &state_events);
+//!     }
+//!     let report = attribution.report(Duration::from_secs(3600));
+//!     println!("spread {}, adverse {}, total {}", report.spread_capture, report.adverse_selection, report.total());
+//! }
+//! ```
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+
+use fastnum::{D256, UD64, UD128};
+
+use crate::{
+    fill::BlockTrades,
+    state::{Exchange, Perpetual, PerpetualEventType, StateBlockEvents, StateEvents},
+    types::{self, OrderSide},
+};
+
+/// Default block time after the fill to measure the mid price move at.
+pub const DEFAULT_MARKOUT: Duration = Duration::from_secs(60);
+
+/// Default number of fills and funding payments kept.
+pub const DEFAULT_MAX_RECORDS: usize = 10_000;
+
+/// Configuration of the [`PnlAttribution`].
+#[derive(Clone, Copy, Debug)]
+pub struct AttributionConfig {
+    markout: Duration,
+    max_records: usize,
+}
+
+impl Default for AttributionConfig {
+    fn default() -> Self {
+        Self {
+            markout: DEFAULT_MARKOUT,
+            max_records: DEFAULT_MAX_RECORDS,
+        }
+    }
+}
+
+impl AttributionConfig {
+    /// Measure adverse selection by the mid price move over the given block time after the fill.
+    pub fn with_markout(mut self, markout: Duration) -> Self {
+        self.markout = markout;
+        self
+    }
+
+    /// Keep up to the given number of the most recent fills, and separately funding payments.
+    pub fn with_max_records(mut self, max_records: usize) -> Self {
+        self.max_records = max_records;
+        self
+    }
+
+    pub fn markout(&self) -> Duration {
+        self.markout
+    }
+
+    pub fn max_records(&self) -> usize {
+        self.max_records
+    }
+}
+
+/// Fill of the account with the reference prices it is attributed against.
+#[derive(Clone, Copy, PartialEq, Eq, derive_more::Debug)]
+pub struct AttributedFill {
+    /// Instant of the block the fill occurred in.
+    pub instant: types::StateInstant,
+
+    /// Perpetual contract ID.
+    pub perpetual_id: types::PerpetualId,
+
+    /// Whether the account was the maker of the fill.
+    pub is_maker: bool,
+
+    /// Side of the account (Bid = buying, Ask = selling).
+    pub side: OrderSide,
+
+    /// Fill price, size-averaged across makers for taker fills.
+    #[debug("{price}")]
+    pub price: UD64,
+
+    /// Fill size.
+    #[debug("{size}")]
+    pub size: UD64,
+
+    /// Fee paid, in collateral token.
+    #[debug("{fee}")]
+    pub fee: UD64,
+
+    /// Mid price before the fill, if the book had both sides or mark price was known.
+    #[debug("{:?}", reference_price.map(|v| format!("{v}")))]
+    pub reference_price: Option<UD64>,
+
+    /// Mid price after the markout horizon, once elapsed.
+    #[debug("{:?}", markout_price.map(|v| format!("{v}")))]
+    pub markout_price: Option<UD64>,
+}
+
+impl AttributedFill {
+    /// Edge of the fill price against the mid price before the fill, in collateral token.
+    /// Positive when bought below or sold above the mid.
+    pub fn spread_capture(&self) -> Option<D256> {
+        self.reference_price
+            .map(|reference| self.signed_move(self.price, reference))
+    }
+
+    /// PnL of the filled size due to the mid price move over the markout horizon,
+    /// in collateral token. Negative when the price moved against the fill.
+    pub fn adverse_selection(&self) -> Option<D256> {
+        self.reference_price
+            .zip(self.markout_price)
+            .map(|(reference, markout)| self.signed_move(reference, markout))
+    }
+
+    /// Value of the filled size moving from one price to another, from the account point of view.
+    fn signed_move(&self, from: UD64, to: UD64) -> D256 {
+        let change = to.resize().to_signed() - from.resize().to_signed();
+        let value = change * self.size.resize().to_signed();
+        match self.side {
+            OrderSide::Bid => value,
+            OrderSide::Ask => value.neg(),
+        }
+    }
+}
+
+/// Funding payment of the account position.
+#[derive(Clone, Copy, PartialEq, Eq, derive_more::Debug)]
+pub struct FundingPayment {
+    /// Instant of the block the funding event occurred in.
+    pub instant: types::StateInstant,
+
+    /// Perpetual contract ID.
+    pub perpetual_id: types::PerpetualId,
+
+    /// Payment received by the account, in collateral token. Negative if paid.
+    #[debug("{amount}")]
+    pub amount: D256,
+}
+
+/// Decomposition of the account PnL over the window, see [`PnlAttribution::report`].
+#[derive(Clone, Copy, PartialEq, Eq, derive_more::Debug)]
+pub struct PnlBreakdown {
+    /// Window of block time the breakdown covers.
+    pub window: Duration,
+
+    /// Number of fills as the maker.
+    pub maker_fills: u64,
+
+    /// Number of taker order executions.
+    pub taker_fills: u64,
+
+    /// Total filled size.
+    #[debug("{volume}")]
+    pub volume: UD128,
+
+    /// Edge of the fills against the mid price before the trade.
+    #[debug("{spread_capture}")]
+    pub spread_capture: D256,
+
+    /// Mid price move against the fills over the markout horizon,
+    /// covering only the fills with elapsed horizon.
+    #[debug("{adverse_selection}")]
+    pub adverse_selection: D256,
+
+    /// Number of fills with the markout horizon not elapsed yet.
+    pub pending_markouts: u64,
+
+    /// Number of fills without the reference price, excluded from spread capture
+    /// and adverse selection.
+    pub unreferenced_fills: u64,
+
+    /// Fees paid as the maker.
+    #[debug("{maker_fees}")]
+    pub maker_fees: UD128,
+
+    /// Fees paid as the taker.
+    #[debug("{taker_fees}")]
+    pub taker_fees: UD128,
+
+    /// Funding payments received, negative if paid.
+    #[debug("{funding}")]
+    pub funding: D256,
+}
+
+impl PnlBreakdown {
+    fn new(window: Duration) -> Self {
+        Self {
+            window,
+            maker_fills: 0,
+            taker_fills: 0,
+            volume: UD128::ZERO,
+            spread_capture: D256::ZERO,
+            adverse_selection: D256::ZERO,
+            pending_markouts: 0,
+            unreferenced_fills: 0,
+            maker_fees: UD128::ZERO,
+            taker_fees: UD128::ZERO,
+            funding: D256::ZERO,
+        }
+    }
+
+    /// Total fees paid as both maker and taker.
+    pub fn total_fees(&self) -> UD128 {
+        self.maker_fees + self.taker_fees
+    }
+
+    /// Sum of all components: spread capture and adverse selection net of fees, plus funding.
+    pub fn total(&self) -> D256 {
+        self.spread_capture + self.adverse_selection - self.total_fees().resize().to_signed()
+            + self.funding
+    }
+}
+
+/// PnL attribution of a single account, fed from [`crate::fill`] stream and
+/// the applied exchange state.
+///
+/// Windows of the reports are measured in block time back from the latest
+/// applied block, inclusive.
+#[derive(Clone, Debug)]
+pub struct PnlAttribution {
+    account_id: types::AccountId,
+    config: AttributionConfig,
+    instant: types::StateInstant,
+    mids: HashMap<types::PerpetualId, UD64>,
+    fills: VecDeque<AttributedFill>,
+    funding: VecDeque<FundingPayment>,
+}
+
+impl PnlAttribution {
+    pub fn new(account_id: types::AccountId, config: AttributionConfig) -> Self {
+        Self {
+            account_id,
+            config,
+            instant: types::StateInstant::default(),
+            mids: HashMap::new(),
+            fills: VecDeque::new(),
+            funding: VecDeque::new(),
+        }
+    }
+
+    /// ID of the account attributed.
+    pub fn account_id(&self) -> types::AccountId {
+        self.account_id
+    }
+
+    /// Instant of the latest applied block.
+    pub fn instant(&self) -> types::StateInstant {
+        self.instant
+    }
+
+    /// Records fills of the account in the block, attributed against the mid prices
+    /// of the latest applied exchange state.
+    ///
+    /// Trades of the block should be applied before the exchange state of the same block,
+    /// otherwise fills are measured against the mid price after the trade.
+    pub fn apply_trades(&mut self, block: &BlockTrades) {
+        self.instant = self.instant.max(block.instant);
+        for trade in &block.trades {
+            let reference_price = self.mids.get(&trade.perpetual_id).copied();
+            let maker_side = match trade.taker_side {
+                OrderSide::Ask => OrderSide::Bid,
+                OrderSide::Bid => OrderSide::Ask,
+            };
+            let fills = trade
+                .maker_fills
+                .iter()
+                .filter(|fill| fill.maker_account_id == self.account_id)
+                .map(|fill| AttributedFill {
+                    instant: block.instant,
+                    perpetual_id: trade.perpetual_id,
+                    is_maker: true,
+                    side: maker_side,
+                    price: fill.price,
+                    size: fill.size,
+                    fee: fill.fee,
+                    reference_price,
+                    markout_price: None,
+                });
+            self.fills.extend(fills);
+            if trade.taker_account_id == self.account_id
+                && let Some(price) = trade.avg_price()
+            {
+                self.fills.push_back(AttributedFill {
+                    instant: block.instant,
+                    perpetual_id: trade.perpetual_id,
+                    is_maker: false,
+                    side: trade.taker_side,
+                    price,
+                    size: trade.total_size(),
+                    fee: trade.taker_fee,
+                    reference_price,
+                    markout_price: None,
+                });
+            }
+        }
+        let excess = self.fills.len().saturating_sub(self.config.max_records);
+        self.fills.drain(..excess);
+    }
+
+    /// Records funding payments of the account positions in the block,
+    /// updates the mid prices and completes the markouts with elapsed horizon.
+    pub fn apply(&mut self, exchange: &Exchange, events: &StateBlockEvents) {
+        self.instant = self.instant.max(events.instant());
+        for (_, event) in events.flat_events() {
+            if let StateEvents::Perpetual(pe) = event
+                && let PerpetualEventType::FundingEvent {
+                    payment_per_unit, ..
+                } = pe.r#type
+            {
+                self.record_funding(
+                    exchange,
+                    events.instant(),
+                    pe.perpetual_id,
+                    payment_per_unit,
+                );
+            }
+        }
+
+        self.mids = exchange
+            .perpetuals()
+            .iter()
+            .filter_map(|(perp_id, perp)| mid_price(perp).map(|mid| (*perp_id, mid)))
+            .collect();
+
+        let now = self.instant.block_timestamp();
+        let markout = self.config.markout.as_secs();
+        for fill in self.fills.iter_mut().rev() {
+            if fill.markout_price.is_some() {
+                // Earlier fills were completed before
+                break;
+            }
+            if fill.instant.block_timestamp() + markout <= now {
+                fill.markout_price = self.mids.get(&fill.perpetual_id).copied();
+            }
+        }
+    }
+
+    /// Retained fills of the account, oldest first.
+    pub fn fills(&self) -> impl Iterator<Item = &AttributedFill> {
+        self.fills.iter()
+    }
+
+    /// Retained funding payments of the account, oldest first.
+    pub fn funding_payments(&self) -> impl Iterator<Item = &FundingPayment> {
+        self.funding.iter()
+    }
+
+    /// PnL decomposition over the fills and funding payments within the window.
+    pub fn report(&self, window: Duration) -> PnlBreakdown {
+        let since = self
+            .instant
+            .block_timestamp()
+            .saturating_sub(window.as_secs());
+        let mut report = PnlBreakdown::new(window);
+        for fill in self
+            .fills
+            .iter()
+            .filter(|f| f.instant.block_timestamp() >= since)
+        {
+            report.volume += fill.size.resize();
+            if fill.is_maker {
+                report.maker_fills += 1;
+                report.maker_fees += fill.fee.resize();
+            } else {
+                report.taker_fills += 1;
+                report.taker_fees += fill.fee.resize();
+            }
+            match (fill.spread_capture(), fill.adverse_selection()) {
+                (None, _) => report.unreferenced_fills += 1,
+                (Some(spread_capture), adverse_selection) => {
+                    report.spread_capture += spread_capture;
+                    match adverse_selection {
+                        Some(adverse_selection) => report.adverse_selection += adverse_selection,
+                        None => report.pending_markouts += 1,
+                    }
+                }
+            }
+        }
+        report.funding = self
+            .funding
+            .iter()
+            .filter(|p| p.instant.block_timestamp() >= since)
+            .fold(D256::ZERO, |sum, p| sum + p.amount);
+        report
+    }
+
+    fn record_funding(
+        &mut self,
+        exchange: &Exchange,
+        instant: types::StateInstant,
+        perpetual_id: types::PerpetualId,
+        payment_per_unit: D256,
+    ) {
+        let Some(pos) = exchange
+            .accounts()
+            .get(&self.account_id)
+            .and_then(|acc| acc.positions().get(&perpetual_id))
+        else {
+            return;
+        };
+        // Positive funding payment means longs pay shorts
+        let amount = payment_per_unit * pos.size().resize().to_signed();
+        self.funding.push_back(FundingPayment {
+            instant,
+            perpetual_id,
+            amount: if pos.r#type().is_long() {
+                amount.neg()
+            } else {
+                amount
+            },
+        });
+        if self.funding.len() > self.config.max_records {
+            self.funding.pop_front();
+        }
+    }
+}
+
+/// Mid price of the book, or the mark price if either side is empty.
+fn mid_price(perp: &Perpetual) -> Option<UD64> {
+    let book = perp.l3_book();
+    match (book.best_bid(), book.best_ask()) {
+        (Some((bid, _)), Some((ask, _))) => Some((bid + ask) / UD64::TWO),
+        _ => (!perp.mark_price().is_zero()).then(|| perp.mark_price()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::TxHash;
+    use fastnum::{dec256, udec64, udec128};
+
+    use super::*;
+    use crate::{
+        fill::{NormalizationConfig, TradeProcessor},
+        state::PerpetualEvent,
+        testing::fuzz::{Action, Simulator},
+    };
+
+    #[test]
+    fn attribution_breakdown() {
+        let mut sim = Simulator::new(&[1], 2);
+        let mut exchange = sim.exchange();
+        let mut processor = TradeProcessor::new(NormalizationConfig::for_testing(0, [(1, 0, 0)]));
+        let config = AttributionConfig::default().with_markout(Duration::from_secs(2));
+        let mut attributions = [
+            PnlAttribution::new(1, config),
+            PnlAttribution::new(2, config),
+        ];
+        let place = |account, is_bid, offset, lots| Action::Place {
+            perpetual: 0,
+            account,
+            is_bid,
+            offset,
+            lots,
+        };
+        let mut apply =
+            |actions: &[Action], exchange: &mut Exchange, attributions: &mut [PnlAttribution]| {
+                let block = sim.block(actions);
+                let trades = processor.process_block(&block);
+                let events = exchange.apply_events(&block).unwrap().unwrap();
+                for attribution in attributions {
+                    attribution.apply_trades(&trades);
+                    attribution.apply(exchange, &events);
+                }
+            };
+
+        // Quotes around 1000.5 mid
+        apply(
+            &[place(0, true, 0, 4), place(1, false, 0, 1)],
+            &mut exchange,
+            &mut attributions,
+        );
+        // Maker bid at 1000 gets hit, mid moves down to 1000
+        let fill = Action::Fill {
+            order: 0,
+            taker: 1,
+            lots: 4,
+        };
+        apply(
+            &[fill, place(1, true, 1, 1)],
+            &mut exchange,
+            &mut attributions,
+        );
+
+        let [maker, _] = &attributions;
+        let fill = *maker.fills().next().unwrap();
+        assert!(fill.is_maker);
+        assert_eq!(fill.side, OrderSide::Bid);
+        assert_eq!(fill.reference_price, Some(udec64!(1000.5)));
+        assert_eq!(fill.spread_capture(), Some(dec256!(2)));
+        let report = maker.report(Duration::from_secs(60));
+        assert_eq!((report.maker_fills, report.pending_markouts), (1, 1));
+        assert_eq!(report.volume, udec128!(4));
+        assert_eq!(report.adverse_selection, D256::ZERO);
+
+        // Markout horizon elapses
+        apply(&[], &mut exchange, &mut attributions);
+        assert_eq!(
+            attributions[0]
+                .report(Duration::from_secs(60))
+                .pending_markouts,
+            1
+        );
+        apply(&[], &mut exchange, &mut attributions);
+        let [maker, taker] = &attributions;
+        let report = maker.report(Duration::from_secs(60));
+        assert_eq!(report.pending_markouts, 0);
+        assert_eq!(report.spread_capture, dec256!(2));
+        assert_eq!(report.adverse_selection, dec256!(-2));
+        assert_eq!(report.total(), D256::ZERO);
+
+        // Taker sold below the mid, but the price moved its way
+        let report = taker.report(Duration::from_secs(60));
+        assert_eq!((report.maker_fills, report.taker_fills), (0, 1));
+        assert_eq!(report.spread_capture, dec256!(-2));
+        assert_eq!(report.adverse_selection, dec256!(2));
+
+        // Long position pays the funding to the short one
+        let open = |account, is_long| Action::Open {
+            perpetual: 0,
+            account,
+            is_long,
+            lots: 4,
+        };
+        apply(
+            &[open(0, true), open(1, false)],
+            &mut exchange,
+            &mut attributions,
+        );
+        let [maker, taker] = &mut attributions;
+        let instant = types::StateInstant::new(
+            exchange.instant().block_number() + 1,
+            exchange.instant().block_timestamp() + 1,
+        );
+        let funding = types::BlockEvents::with_buffer(
+            instant,
+            vec![types::EventContext::new(
+                TxHash::ZERO,
+                0,
+                0,
+                Default::default(),
+                0..1,
+            )],
+            vec![StateEvents::Perpetual(PerpetualEvent {
+                perpetual_id: 1,
+                r#type: PerpetualEventType::FundingEvent {
+                    rate: Default::default(),
+                    payment_per_unit: dec256!(0.5),
+                },
+            })],
+        );
+        maker.apply(&exchange, &funding);
+        taker.apply(&exchange, &funding);
+        assert_eq!(maker.funding_payments().count(), 1);
+        let report = maker.report(Duration::from_secs(60));
+        assert_eq!(report.funding, dec256!(-2));
+        assert_eq!(report.total(), dec256!(-2));
+        assert_eq!(taker.report(Duration::from_secs(60)).funding, dec256!(2));
+
+        // Window excludes older fills
+        let report = maker.report(Duration::from_secs(1));
+        assert_eq!(report.maker_fills, 0);
+        assert_eq!(report.funding, dec256!(-2));
+    }
+}
@@ -0,0 +1,278 @@
+//! Best bid/offer history.
+//!
+//! [`BboRecorder`] samples top of the order book of each tracked perpetual contract
+//! after every applied block and keeps the quotes which differ from the previous ones,
+//! answering point-in-time and spread distribution queries.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use dex_sdk::marketdata::bbo::{BboConfig, BboRecorder};
+//!
+//! let mut recorder = BboRecorder::new(BboConfig::default().with_max_quotes(100_000));
+//! while let Some(block_events) = stream.next().await {
+//!     exchange.apply_events(&block_events?)?;
+//!     recorder.apply(&exchange);
+//! }
+//! let quote = recorder.bbo_at(btc, instant);
+//! let spreads = recorder.spread_percentiles(btc, Duration::from_secs(3600), &[udec64!(0.5), udec64!(0.99)]);
+//! ```
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+
+use fastnum::UD64;
+
+use crate::{state::Exchange, types};
+
+/// Default number of quotes kept per perpetual contract.
+const DEFAULT_MAX_QUOTES: usize = 10_000;
+
+/// Retention configuration of the [`BboRecorder`].
+#[derive(Clone, Copy, Debug)]
+pub struct BboConfig {
+    max_quotes: usize,
+}
+
+impl Default for BboConfig {
+    fn default() -> Self {
+        Self {
+            max_quotes: DEFAULT_MAX_QUOTES,
+        }
+    }
+}
+
+impl BboConfig {
+    /// Keep up to the given number of the most recent quotes per perpetual contract.
+    pub fn with_max_quotes(mut self, max_quotes: usize) -> Self {
+        self.max_quotes = max_quotes;
+        self
+    }
+}
+
+/// Top of the order book as of the end of the block.
+#[derive(Clone, Copy, PartialEq, Eq, derive_more::Debug)]
+pub struct Bbo {
+    /// Instant of the block the quote got recorded at.
+    pub instant: types::StateInstant,
+
+    /// Best bid price and size, `None` if the bid side is empty.
+    #[debug("{:?}", bid.map(|(p, s)| format!("{s}@{p}")))]
+    pub bid: Option<(UD64, UD64)>,
+
+    /// Best ask price and size, `None` if the ask side is empty.
+    #[debug("{:?}", ask.map(|(p, s)| format!("{s}@{p}")))]
+    pub ask: Option<(UD64, UD64)>,
+}
+
+impl Bbo {
+    /// Difference between the best ask and bid prices, if both sides are present.
+    ///
+    /// Zero if the book is crossed or locked.
+    pub fn spread(&self) -> Option<UD64> {
+        let ((bid, _), (ask, _)) = self.bid.zip(self.ask)?;
+        Some(if ask > bid { ask - bid } else { UD64::ZERO })
+    }
+
+    /// Middle between the best ask and bid prices, if both sides are present.
+    pub fn mid(&self) -> Option<UD64> {
+        let ((bid, _), (ask, _)) = self.bid.zip(self.ask)?;
+        Some((bid + ask) / UD64::TWO)
+    }
+
+    fn same_quote(&self, other: &Bbo) -> bool {
+        self.bid == other.bid && self.ask == other.ask
+    }
+}
+
+/// Best bid/offer history per perpetual contract, fed with the exchange state
+/// after each applied block.
+///
+/// Windows of the queries are measured in block time back from the latest
+/// applied block, inclusive.
+#[derive(Clone, Debug, Default)]
+pub struct BboRecorder {
+    config: BboConfig,
+    instant: types::StateInstant,
+    quotes: HashMap<types::PerpetualId, VecDeque<Bbo>>,
+}
+
+impl BboRecorder {
+    pub fn new(config: BboConfig) -> Self {
+        Self {
+            config,
+            instant: types::StateInstant::default(),
+            quotes: HashMap::new(),
+        }
+    }
+
+    /// Instant of the latest applied block.
+    pub fn instant(&self) -> types::StateInstant {
+        self.instant
+    }
+
+    /// Records top of the book of each perpetual contract if it changed since
+    /// the previous quote, and drops quotes outside of the retention limit.
+    ///
+    /// Returns the number of perpetual contracts with the new quote recorded.
+    pub fn apply(&mut self, exchange: &Exchange) -> usize {
+        let instant = exchange.instant();
+        self.instant = self.instant.max(instant);
+
+        let mut recorded = 0;
+        for (perp_id, perp) in exchange.perpetuals() {
+            let book = perp.l3_book();
+            let bbo = Bbo {
+                instant,
+                bid: book.best_bid(),
+                ask: book.best_ask(),
+            };
+            let quotes = self.quotes.entry(*perp_id).or_default();
+            if quotes.back().is_some_and(|last| last.same_quote(&bbo)) {
+                continue;
+            }
+            if quotes.len() == self.config.max_quotes {
+                quotes.pop_front();
+            }
+            quotes.push_back(bbo);
+            recorded += 1;
+        }
+        recorded
+    }
+
+    /// Retained quotes of the perpetual contract, oldest first.
+    pub fn quotes(
+        &self,
+        perpetual_id: types::PerpetualId,
+    ) -> impl DoubleEndedIterator<Item = &Bbo> {
+        self.quotes.get(&perpetual_id).into_iter().flatten()
+    }
+
+    /// The most recent quote of the perpetual contract.
+    pub fn latest(&self, perpetual_id: types::PerpetualId) -> Option<&Bbo> {
+        self.quotes.get(&perpetual_id)?.back()
+    }
+
+    /// Quote of the perpetual contract in effect at the given instant: the latest one
+    /// recorded at or before its block.
+    ///
+    /// Returns `None` if the instant precedes the retained history.
+    pub fn bbo_at(
+        &self,
+        perpetual_id: types::PerpetualId,
+        instant: types::StateInstant,
+    ) -> Option<&Bbo> {
+        let quotes = self.quotes.get(&perpetual_id)?;
+        let idx = quotes.partition_point(|q| q.instant.block_number() <= instant.block_number());
+        idx.checked_sub(1).map(|idx| &quotes[idx])
+    }
+
+    /// Spread percentiles of the quotes recorded within the window, in the order of
+    /// the requested percentiles given as fractions in range `[0, 1]`, using nearest-rank
+    /// method. Quotes are weighted equally regardless of how long they were in effect.
+    ///
+    /// Returns `None` if there were no quotes with both sides present.
+    pub fn spread_percentiles(
+        &self,
+        perpetual_id: types::PerpetualId,
+        window: Duration,
+        percentiles: &[UD64],
+    ) -> Option<Vec<UD64>> {
+        let since = self
+            .instant
+            .block_timestamp()
+            .saturating_sub(window.as_secs());
+        let mut spreads = self
+            .quotes(perpetual_id)
+            .rev()
+            .take_while(|q| q.instant.block_timestamp() >= since)
+            .filter_map(Bbo::spread)
+            .collect::<Vec<_>>();
+        if spreads.is_empty() {
+            return None;
+        }
+        spreads.sort();
+        let n = UD64::from(spreads.len() as u64);
+        Some(
+            percentiles
+                .iter()
+                .map(|p| {
+                    // Smallest rank covering the percentile
+                    let target = (*p).min(UD64::ONE) * n;
+                    let rank = (1..spreads.len())
+                        .find(|rank| UD64::from(*rank as u64) >= target)
+                        .unwrap_or(spreads.len());
+                    spreads[rank - 1]
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fastnum::udec64;
+
+    use super::*;
+    use crate::testing::fuzz::{Action, Simulator};
+
+    #[test]
+    fn quotes_history() {
+        let mut sim = Simulator::new(&[1], 2);
+        let mut exchange = sim.exchange();
+        let mut recorder = BboRecorder::new(BboConfig::default().with_max_quotes(3));
+        let place = |account, is_bid, offset| Action::Place {
+            perpetual: 0,
+            account,
+            is_bid,
+            offset,
+            lots: 1,
+        };
+
+        // Empty book gets recorded once
+        recorder.apply(&exchange);
+        let start = exchange.instant();
+        assert_eq!(recorder.latest(1).unwrap().spread(), None);
+
+        exchange
+            .apply_events(&sim.block(&[place(0, true, 2), place(1, false, 2)]))
+            .unwrap();
+        assert_eq!(recorder.apply(&exchange), 1);
+        let quoted = exchange.instant();
+        // Unchanged top of the book is not recorded
+        exchange.apply_events(&sim.block(&[])).unwrap();
+        assert_eq!(recorder.apply(&exchange), 0);
+
+        exchange
+            .apply_events(&sim.block(&[place(0, true, 1)]))
+            .unwrap();
+        assert_eq!(recorder.apply(&exchange), 1);
+        assert_eq!(recorder.quotes(1).count(), 3);
+
+        assert_eq!(recorder.bbo_at(1, start).unwrap().bid, None);
+        let at_quoted = recorder.bbo_at(1, quoted).unwrap();
+        assert_eq!(at_quoted.instant, quoted);
+        let wide = at_quoted.spread().unwrap();
+        let tight = recorder.latest(1).unwrap().spread().unwrap();
+        assert!(tight < wide);
+
+        let percentiles = recorder
+            .spread_percentiles(
+                1,
+                Duration::from_secs(60),
+                &[UD64::ZERO, udec64!(0.5), UD64::ONE],
+            )
+            .unwrap();
+        assert_eq!(percentiles, vec![tight, tight, wide]);
+
+        // Retention drops the oldest quote
+        exchange
+            .apply_events(&sim.block(&[place(1, false, 1)]))
+            .unwrap();
+        recorder.apply(&exchange);
+        assert_eq!(recorder.quotes(1).count(), 3);
+        assert!(recorder.bbo_at(1, start).is_none());
+    }
+}
@@ -0,0 +1,479 @@
+//! Compact binary encoding of order book history.
+//!
+//! [`BookDeltaEncoder`] compares the aggregated levels of each tracked order book
+//! against the ones of the previous encoded block and writes the changed levels
+//! into a frame, with prices and sizes in the fixed-point units of the perpetual
+//! contract, as varint-encoded differences. [`BookDeltaDecoder`] replays the frames
+//! and reconstructs the books as of each encoded block.
+//!
+//! Frames are self-delimiting and can be appended to the same buffer or file,
+//! blocks without book changes produce no frame.
+//!
+//! # Frame layout
+//!
+//! All integers are LEB128 varints, signed ones zigzag-encoded.
+//!
+//! ```text
+//! frame   := block_delta:i timestamp_delta:i sections:u section*
+//! section := perpetual:u (perpetual_id << 1 | has_decimals)
+//!            [price_decimals:u size_decimals:u]
+//!            bid_count:u change* ask_count:u change*
+//! change  := price_delta:i size:u
+//! ```
+//!
+//! Block number and timestamp are relative to the previous frame, decimals are
+//! written with the first section of the perpetual contract. Changes of each side
+//! follow the side order, best price first, with prices relative to the previous
+//! change and zero size removing the level.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use dex_sdk::marketdata::book_delta::{BookDeltaDecoder, BookDeltaEncoder};
+//!
+//! let mut encoder = BookDeltaEncoder::new();
+//! let mut storage = Vec::new();
+//! while let Some(block_events) = stream.next().await {
+//!     exchange.apply_events(&block_events?)?;
+//!     encoder.encode(&exchange, &mut storage);
+//! }
+//!
+//! let mut decoder = BookDeltaDecoder::new();
+//! let mut bytes = storage.as_slice();
+//! while !bytes.is_empty() {
+//!     let consumed = decoder.decode(bytes)?;
+//!     bytes = &bytes[consumed..];
+//!     let book = decoder.book(btc).unwrap();
+//!     println!("{:?}: {:?} / {:?}", decoder.instant(), book.best_bid(), book.best_ask());
+//! }
+//! ```
+
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+};
+
+use fastnum::UD64;
+use thiserror::Error;
+
+use crate::{num, state::Exchange, types};
+
+/// Error decoding the book delta frame.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum BookDeltaError {
+    /// Frame ended before all of its fields were read.
+    #[error("truncated book delta frame")]
+    Truncated,
+
+    /// Varint does not fit 64 bits.
+    #[error("varint overflow in book delta frame")]
+    VarintOverflow,
+
+    /// Section of the perpetual contract which was not introduced with its decimals,
+    /// e.g. if decoding started past the first frame.
+    #[error("perpetual {0} has no decimals, decoding must start from the first frame")]
+    UnknownPerpetual(types::PerpetualId),
+}
+
+/// Aggregated levels of the order book side, keyed by price in fixed-point units.
+type Levels = BTreeMap<u64, u64>;
+
+#[derive(Clone, Debug, Default)]
+struct EncodedBook {
+    bids: Levels,
+    asks: Levels,
+}
+
+/// Encoder of the order book changes between the applied blocks.
+#[derive(Clone, Debug, Default)]
+pub struct BookDeltaEncoder {
+    instant: types::StateInstant,
+    books: HashMap<types::PerpetualId, EncodedBook>,
+}
+
+impl BookDeltaEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Instant of the last encoded frame.
+    pub fn instant(&self) -> types::StateInstant {
+        self.instant
+    }
+
+    /// Appends the frame with the book changes of all perpetual contracts since
+    /// the previous frame to the output.
+    ///
+    /// Returns the number of bytes written, zero if none of the books changed.
+    pub fn encode(&mut self, exchange: &Exchange, out: &mut Vec<u8>) -> usize {
+        let mut sections = Vec::new();
+        let mut count = 0;
+        let mut perpetuals = exchange.perpetuals().iter().collect::<Vec<_>>();
+        perpetuals.sort_by_key(|(id, _)| **id);
+        for (perp_id, perp) in perpetuals {
+            let (price_converter, size_converter) = (perp.price_converter(), perp.size_converter());
+            let book = perp.l3_book();
+            let bids = fixed_levels(
+                book.bids().iter().map(|(p, l)| (p.0, l.size())),
+                price_converter,
+                size_converter,
+            );
+            let asks = fixed_levels(
+                book.asks().iter().map(|(p, l)| (*p, l.size())),
+                price_converter,
+                size_converter,
+            );
+
+            let is_new = !self.books.contains_key(perp_id);
+            let prev = self.books.entry(*perp_id).or_default();
+            let bid_changes = changes(&prev.bids, &bids, true);
+            let ask_changes = changes(&prev.asks, &asks, false);
+            if !is_new && bid_changes.is_empty() && ask_changes.is_empty() {
+                continue;
+            }
+
+            write_varint(&mut sections, (*perp_id as u64) << 1 | is_new as u64);
+            if is_new {
+                write_varint(&mut sections, price_converter.decimals() as u64);
+                write_varint(&mut sections, size_converter.decimals() as u64);
+            }
+            write_changes(&mut sections, &bid_changes);
+            write_changes(&mut sections, &ask_changes);
+            *prev = EncodedBook { bids, asks };
+            count += 1;
+        }
+        if count == 0 {
+            return 0;
+        }
+
+        let instant = exchange.instant();
+        let start = out.len();
+        write_varint(
+            out,
+            zigzag(instant.block_number() as i64 - self.instant.block_number() as i64),
+        );
+        write_varint(
+            out,
+            zigzag(instant.block_timestamp() as i64 - self.instant.block_timestamp() as i64),
+        );
+        write_varint(out, count);
+        out.extend_from_slice(&sections);
+        self.instant = instant;
+        out.len() - start
+    }
+}
+
+/// Aggregated order book reconstructed by the [`BookDeltaDecoder`].
+#[derive(Clone, Debug)]
+pub struct DecodedBook {
+    price_converter: num::Converter,
+    size_converter: num::Converter,
+    bids: BTreeMap<Reverse<UD64>, UD64>,
+    asks: BTreeMap<UD64, UD64>,
+}
+
+impl DecodedBook {
+    /// Bid levels with their total sizes, best first.
+    pub fn bids(&self) -> impl Iterator<Item = (UD64, UD64)> + '_ {
+        self.bids.iter().map(|(p, s)| (p.0, *s))
+    }
+
+    /// Ask levels with their total sizes, best first.
+    pub fn asks(&self) -> impl Iterator<Item = (UD64, UD64)> + '_ {
+        self.asks.iter().map(|(p, s)| (*p, *s))
+    }
+
+    pub fn best_bid(&self) -> Option<(UD64, UD64)> {
+        self.bids().next()
+    }
+
+    pub fn best_ask(&self) -> Option<(UD64, UD64)> {
+        self.asks().next()
+    }
+
+    fn apply(&mut self, is_bid: bool, price: u64, size: u64) {
+        let price = self.price_converter.from_u64(price);
+        let size = self.size_converter.from_u64(size);
+        if is_bid {
+            if size.is_zero() {
+                self.bids.remove(&Reverse(price));
+            } else {
+                self.bids.insert(Reverse(price), size);
+            }
+        } else if size.is_zero() {
+            self.asks.remove(&price);
+        } else {
+            self.asks.insert(price, size);
+        }
+    }
+}
+
+/// Decoder replaying the frames of the [`BookDeltaEncoder`] from the first one.
+#[derive(Clone, Debug, Default)]
+pub struct BookDeltaDecoder {
+    instant: types::StateInstant,
+    books: HashMap<types::PerpetualId, DecodedBook>,
+}
+
+impl BookDeltaDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Instant of the last decoded frame.
+    pub fn instant(&self) -> types::StateInstant {
+        self.instant
+    }
+
+    /// Reconstructed order book of the perpetual contract as of the last decoded frame.
+    pub fn book(&self, perpetual_id: types::PerpetualId) -> Option<&DecodedBook> {
+        self.books.get(&perpetual_id)
+    }
+
+    /// Decodes the frame at the start of the input and applies it to the books.
+    ///
+    /// Returns the number of bytes consumed. The books are left partially updated
+    /// if the frame is malformed.
+    pub fn decode(&mut self, bytes: &[u8]) -> Result<usize, BookDeltaError> {
+        let mut reader = Reader { bytes, pos: 0 };
+        let block_number = self
+            .instant
+            .block_number()
+            .wrapping_add_signed(unzigzag(reader.varint()?));
+        let block_timestamp = self
+            .instant
+            .block_timestamp()
+            .wrapping_add_signed(unzigzag(reader.varint()?));
+        for _ in 0..reader.varint()? {
+            let header = reader.varint()?;
+            let perp_id = (header >> 1) as types::PerpetualId;
+            if header & 1 == 1 {
+                let price_decimals = reader.varint()? as u8;
+                let size_decimals = reader.varint()? as u8;
+                self.books.insert(
+                    perp_id,
+                    DecodedBook {
+                        price_converter: num::Converter::new(price_decimals),
+                        size_converter: num::Converter::new(size_decimals),
+                        bids: BTreeMap::new(),
+                        asks: BTreeMap::new(),
+                    },
+                );
+            }
+            let book = self
+                .books
+                .get_mut(&perp_id)
+                .ok_or(BookDeltaError::UnknownPerpetual(perp_id))?;
+            for is_bid in [true, false] {
+                let mut price = 0u64;
+                for _ in 0..reader.varint()? {
+                    price = price.wrapping_add_signed(unzigzag(reader.varint()?));
+                    book.apply(is_bid, price, reader.varint()?);
+                }
+            }
+        }
+        self.instant = types::StateInstant::new(block_number, block_timestamp);
+        Ok(reader.pos)
+    }
+}
+
+/// Aggregated levels with prices and sizes converted to the fixed-point units.
+fn fixed_levels(
+    levels: impl Iterator<Item = (UD64, UD64)>,
+    price_converter: num::Converter,
+    size_converter: num::Converter,
+) -> Levels {
+    levels
+        .map(|(price, size)| {
+            (
+                price_converter.to_unsigned(price).saturating_to::<u64>(),
+                size_converter.to_unsigned(size).saturating_to::<u64>(),
+            )
+        })
+        .collect()
+}
+
+/// Levels which differ between the previous and current side of the book, in the
+/// side order, with zero size for the removed ones.
+fn changes(prev: &Levels, curr: &Levels, is_bid: bool) -> Vec<(u64, u64)> {
+    let mut changes = prev
+        .iter()
+        .filter(|(price, _)| !curr.contains_key(price))
+        .map(|(price, _)| (*price, 0))
+        .chain(
+            curr.iter()
+                .filter(|(price, size)| prev.get(price) != Some(size))
+                .map(|(price, size)| (*price, *size)),
+        )
+        .collect::<Vec<_>>();
+    if is_bid {
+        changes.sort_by_key(|(price, _)| Reverse(*price));
+    } else {
+        changes.sort_by_key(|(price, _)| *price);
+    }
+    changes
+}
+
+fn write_changes(out: &mut Vec<u8>, changes: &[(u64, u64)]) {
+    write_varint(out, changes.len() as u64);
+    let mut prev_price = 0u64;
+    for (price, size) in changes {
+        write_varint(out, zigzag(price.wrapping_sub(prev_price) as i64));
+        write_varint(out, *size);
+        prev_price = *price;
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn varint(&mut self) -> Result<u64, BookDeltaError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.bytes.get(self.pos).ok_or(BookDeltaError::Truncated)?;
+            self.pos += 1;
+            let bits = (byte & 0x7f) as u64;
+            if shift == 63 && bits > 1 {
+                return Err(BookDeltaError::VarintOverflow);
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(BookDeltaError::VarintOverflow)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::sim::{Action, Simulator};
+
+    type Sides = (Vec<(UD64, UD64)>, Vec<(UD64, UD64)>);
+
+    fn levels(book: &DecodedBook) -> Sides {
+        (book.bids().collect(), book.asks().collect())
+    }
+
+    #[test]
+    fn roundtrip_reconstructs_books() {
+        let mut sim = Simulator::new(&[1, 2], 3);
+        let mut exchange = sim.exchange();
+        let mut encoder = BookDeltaEncoder::new();
+        let mut storage = Vec::new();
+        let place = |perpetual, account, is_bid, offset, lots| Action::Place {
+            perpetual,
+            account,
+            is_bid,
+            offset,
+            lots,
+        };
+        let blocks = [
+            vec![
+                place(0, 0, true, 1, 10),
+                place(0, 1, true, 1, 5),
+                place(0, 2, false, 2, 7),
+                place(1, 0, false, 1, 3),
+            ],
+            vec![],
+            vec![Action::Fill {
+                order: 0,
+                taker: 2,
+                lots: 4,
+            }],
+            vec![
+                Action::Cancel { order: 1 },
+                Action::Modify {
+                    order: 2,
+                    offset: 5,
+                    lots: 9,
+                },
+            ],
+        ];
+
+        let mut expected = Vec::new();
+        let mut frames = 0;
+        for actions in blocks {
+            exchange.apply_events(&sim.block(&actions)).unwrap();
+            let written = encoder.encode(&exchange, &mut storage);
+            assert_eq!(written == 0, actions.is_empty());
+            if written > 0 {
+                frames += 1;
+                let book = exchange.perpetuals()[&1].l3_book();
+                expected.push((exchange.instant(), book.top_bids(100), book.top_asks(100)));
+            }
+        }
+
+        let mut decoder = BookDeltaDecoder::new();
+        let mut bytes = storage.as_slice();
+        for (instant, bids, asks) in expected {
+            let consumed = decoder.decode(bytes).unwrap();
+            bytes = &bytes[consumed..];
+            assert_eq!(decoder.instant(), instant);
+            assert_eq!(levels(decoder.book(1).unwrap()), (bids, asks));
+            frames -= 1;
+        }
+        assert!(bytes.is_empty());
+        assert_eq!(frames, 0);
+
+        let book = exchange.perpetuals()[&2].l3_book();
+        assert_eq!(
+            levels(decoder.book(2).unwrap()),
+            (book.top_bids(100), book.top_asks(100))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_frames() {
+        let mut sim = Simulator::new(&[1], 1);
+        let mut exchange = sim.exchange();
+        exchange
+            .apply_events(&sim.block(&[Action::Place {
+                perpetual: 0,
+                account: 0,
+                is_bid: true,
+                offset: 1,
+                lots: 1,
+            }]))
+            .unwrap();
+        let mut storage = Vec::new();
+        let written = BookDeltaEncoder::new().encode(&exchange, &mut storage);
+
+        assert_eq!(
+            BookDeltaDecoder::new().decode(&storage[..written - 1]),
+            Err(BookDeltaError::Truncated)
+        );
+        assert_eq!(
+            BookDeltaDecoder::new().decode(&[0x80; 11]),
+            Err(BookDeltaError::VarintOverflow)
+        );
+        // Section without decimals of the perpetual contract
+        assert_eq!(
+            BookDeltaDecoder::new().decode(&[0, 0, 1, 2, 0, 0]),
+            Err(BookDeltaError::UnknownPerpetual(1))
+        );
+        for value in [0, 1, -1, i64::MAX, i64::MIN] {
+            assert_eq!(unzigzag(zigzag(value)), value);
+        }
+    }
+}
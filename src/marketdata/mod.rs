@@ -0,0 +1,22 @@
+//! Market data derived from the trade stream and the tracked order books.
+//!
+//! [`tape::Tape`] keeps recent trades per perpetual contract from [`crate::fill`] stream
+//! and answers rolling window queries such as VWAP, volume and buy/sell imbalance.
+//!
+//! [`bbo::BboRecorder`] keeps the history of best bid/offer quotes per perpetual contract
+//! and answers point-in-time and spread distribution queries.
+//!
+//! [`signals::SignalTracker`] follows the tracked order books and order flow and periodically
+//! reports microstructure signals such as book imbalance, trade flow and quote lifetimes.
+//!
+//! [`attribution::PnlAttribution`] follows the fills and positions of an account and
+//! decomposes its PnL into spread capture, adverse selection, fees and funding.
+//!
+//! [`book_delta::BookDeltaEncoder`] writes the per-block changes of the tracked order books
+//! in a compact binary format for storage, [`book_delta::BookDeltaDecoder`] replays them.
+
+pub mod attribution;
+pub mod bbo;
+pub mod book_delta;
+pub mod signals;
+pub mod tape;
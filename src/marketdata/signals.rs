@@ -0,0 +1,524 @@
+//! Order book and order flow microstructure signals.
+//!
+//! [`SignalTracker`] follows the order book and order lifecycle events of each tracked
+//! perpetual contract and periodically reports:
+//! * order book imbalance over the top levels,
+//! * signed taker trade flow,
+//! * cancel-to-trade ratio,
+//! * lifetime statistics of the quotes removed from the book.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use dex_sdk::marketdata::signals::{SignalTracker, SignalsConfig};
+//!
+//! let mut signals = SignalTracker::new(SignalsConfig::default().with_depth(10));
+//! while let Some(block_events) = stream.next().await {
+//!     if let Some(state_events) = exchange.apply_events(&block_events?)? {
+//!         if let Some(update) = signals.apply(&exchange, &state_events) {
+//!             for s in &update.signals {
+//!                 println!("{}: imbalance {:?}, flow {}", s.perpetual_id, s.book_imbalance, s.trade_flow);
+//!             }
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    time::Duration,
+};
+
+use fastnum::{D64, D128, UD64};
+
+use crate::{
+    state::{Exchange, OrderEventType, StateBlockEvents, StateEvents},
+    types::{self, OrderSide},
+};
+
+/// Default number of top levels of each side the book imbalance is computed over.
+pub const DEFAULT_DEPTH: usize = 5;
+
+/// Default rolling window of the flow signals.
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(60);
+
+/// Default block time between signal updates.
+pub const DEFAULT_UPDATE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Configuration of the [`SignalTracker`].
+#[derive(Clone, Copy, Debug)]
+pub struct SignalsConfig {
+    depth: usize,
+    window: Duration,
+    update_interval: Duration,
+}
+
+impl Default for SignalsConfig {
+    fn default() -> Self {
+        Self {
+            depth: DEFAULT_DEPTH,
+            window: DEFAULT_WINDOW,
+            update_interval: DEFAULT_UPDATE_INTERVAL,
+        }
+    }
+}
+
+impl SignalsConfig {
+    /// Compute the book imbalance over the given number of top levels of each side.
+    pub fn with_depth(mut self, depth: usize) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Compute the flow signals over the given rolling window of block time.
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Emit updates once the given block time elapses since the previous ones.
+    /// Zero interval emits updates on every applied block.
+    pub fn with_update_interval(mut self, update_interval: Duration) -> Self {
+        self.update_interval = update_interval;
+        self
+    }
+
+    /// Number of top levels of each side the book imbalance is computed over.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Rolling window of the flow signals.
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Block time between updates.
+    pub fn update_interval(&self) -> Duration {
+        self.update_interval
+    }
+}
+
+/// Lifetime statistics of the quotes removed from the book within the window,
+/// only covering quotes placed while being tracked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QuoteLifetimes {
+    /// Number of removed quotes.
+    pub count: u64,
+
+    /// Shortest lifetime.
+    pub min: Duration,
+
+    /// Median lifetime, the lower one for even count.
+    pub median: Duration,
+
+    /// Average lifetime.
+    pub mean: Duration,
+
+    /// Longest lifetime.
+    pub max: Duration,
+}
+
+/// Signals of the perpetual contract as of the latest applied block.
+#[derive(Clone, Copy, derive_more::Debug)]
+pub struct PerpetualSignals {
+    /// Perpetual contract ID.
+    pub perpetual_id: types::PerpetualId,
+
+    /// Imbalance of the bid and ask size over the top levels, in range `[-1, 1]`:
+    /// `(bid - ask) / (bid + ask)`, `None` if the book is empty.
+    #[debug("{:?}", book_imbalance.map(|v| format!("{v}")))]
+    pub book_imbalance: Option<D64>,
+
+    /// Taker buy volume less taker sell volume within the window.
+    #[debug("{trade_flow}")]
+    pub trade_flow: D128,
+
+    /// Number of maker order fills within the window.
+    pub trades: u64,
+
+    /// Number of quotes cancelled or repriced within the window.
+    pub cancels: u64,
+
+    /// Ratio of cancels to trades, `None` if there were no trades.
+    #[debug("{:?}", cancel_to_trade.map(|v| format!("{v}")))]
+    pub cancel_to_trade: Option<UD64>,
+
+    /// Lifetimes of the quotes removed within the window, `None` if there were none.
+    pub quote_lifetimes: Option<QuoteLifetimes>,
+}
+
+/// Periodic update of the signals of all tracked perpetual contracts,
+/// see [`SignalTracker::apply`].
+#[derive(Clone, Debug)]
+pub struct SignalUpdate {
+    /// Instant of the block the update was produced at.
+    pub instant: types::StateInstant,
+
+    /// Signals of each perpetual contract, ordered by ID.
+    pub signals: Vec<PerpetualSignals>,
+}
+
+/// Resting order as known to the tracker.
+#[derive(Clone, Copy, Debug)]
+struct Quote {
+    side: OrderSide,
+    /// Timestamp of placement, `None` if the order was in the book before tracking started.
+    placed_at: Option<u64>,
+}
+
+/// Order flow activity within the window.
+#[derive(Clone, Copy, Debug)]
+enum Activity {
+    /// Maker order fill, with the aggressor side if the maker order is known.
+    Trade {
+        taker_side: Option<OrderSide>,
+        size: UD64,
+    },
+    /// Quote cancelled or repriced.
+    Cancel,
+    /// Quote removed from the book after the given number of seconds.
+    Removed { lifetime: u64 },
+}
+
+#[derive(Clone, Debug, Default)]
+struct PerpetualFlow {
+    quotes: HashMap<types::OrderId, Quote>,
+    activity: VecDeque<(u64, Activity)>,
+    book_imbalance: Option<D64>,
+}
+
+/// Microstructure signals per perpetual contract, fed with the exchange state and
+/// the state events after each applied block.
+///
+/// Windows are measured in block time back from the latest applied block, inclusive.
+#[derive(Clone, Debug, Default)]
+pub struct SignalTracker {
+    config: SignalsConfig,
+    instant: types::StateInstant,
+    last_update: Option<u64>,
+    flows: HashMap<types::PerpetualId, PerpetualFlow>,
+}
+
+impl SignalTracker {
+    pub fn new(config: SignalsConfig) -> Self {
+        Self {
+            config,
+            instant: types::StateInstant::default(),
+            last_update: None,
+            flows: HashMap::new(),
+        }
+    }
+
+    /// Instant of the latest applied block.
+    pub fn instant(&self) -> types::StateInstant {
+        self.instant
+    }
+
+    /// Records order flow of the block and the resulting top of the book, drops activity
+    /// outside of the window and returns the update if the update interval elapsed.
+    ///
+    /// Orders resting in the book when the perpetual contract is first seen are tracked
+    /// without the placement time, so they do not contribute to the quote lifetimes.
+    pub fn apply(
+        &mut self,
+        exchange: &Exchange,
+        events: &StateBlockEvents,
+    ) -> Option<SignalUpdate> {
+        self.instant = self.instant.max(events.instant());
+        let timestamp = events.instant().block_timestamp();
+        for (perp_id, perp) in exchange.perpetuals() {
+            self.flows.entry(*perp_id).or_insert_with(|| PerpetualFlow {
+                quotes: perp
+                    .l3_book()
+                    .all_orders()
+                    .values()
+                    .map(|o| {
+                        let quote = Quote {
+                            side: o.r#type().side(),
+                            placed_at: None,
+                        };
+                        (o.order_id(), quote)
+                    })
+                    .collect(),
+                ..Default::default()
+            });
+        }
+
+        // Maker fills are reported after the removal of the fully filled order
+        let filled = events
+            .flat_events()
+            .filter_map(|(_, e)| match e {
+                StateEvents::Order(oe)
+                    if matches!(oe.r#type, OrderEventType::Filled { is_maker: true, .. }) =>
+                {
+                    Some((oe.perpetual_id, oe.order_id?))
+                }
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+        for (_, event) in events.flat_events() {
+            let StateEvents::Order(oe) = event else {
+                continue;
+            };
+            let Some(flow) = self.flows.get_mut(&oe.perpetual_id) else {
+                continue;
+            };
+            let quote = oe.order_id.and_then(|id| flow.quotes.get(&id).copied());
+            match (oe.r#type, oe.order_id) {
+                (OrderEventType::Placed { r#type, .. }, Some(order_id)) => {
+                    let quote = Quote {
+                        side: r#type.side(),
+                        placed_at: Some(timestamp),
+                    };
+                    flow.quotes.insert(order_id, quote);
+                }
+                (
+                    OrderEventType::Filled {
+                        fill_size,
+                        is_maker: true,
+                        ..
+                    },
+                    _,
+                ) => flow.activity.push_back((
+                    timestamp,
+                    Activity::Trade {
+                        taker_side: quote.map(|q| match q.side {
+                            OrderSide::Ask => OrderSide::Bid,
+                            OrderSide::Bid => OrderSide::Ask,
+                        }),
+                        size: fill_size,
+                    },
+                )),
+                (OrderEventType::Removed, Some(order_id)) => {
+                    flow.quotes.remove(&order_id);
+                    if !filled.contains(&(oe.perpetual_id, order_id)) {
+                        flow.activity.push_back((timestamp, Activity::Cancel));
+                    }
+                    if let Some(placed_at) = quote.and_then(|q| q.placed_at) {
+                        let lifetime = timestamp.saturating_sub(placed_at);
+                        flow.activity
+                            .push_back((timestamp, Activity::Removed { lifetime }));
+                    }
+                }
+                (OrderEventType::Updated { price: Some(_), .. }, Some(order_id)) => {
+                    // Repricing withdraws the quote and posts a new one
+                    flow.activity.push_back((timestamp, Activity::Cancel));
+                    if let Some(quote) = flow.quotes.get_mut(&order_id) {
+                        if let Some(placed_at) = quote.placed_at {
+                            let lifetime = timestamp.saturating_sub(placed_at);
+                            flow.activity
+                                .push_back((timestamp, Activity::Removed { lifetime }));
+                        }
+                        quote.placed_at = Some(timestamp);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let since = self.since();
+        for (perp_id, flow) in self.flows.iter_mut() {
+            if let Some(perp) = exchange.perpetuals().get(perp_id) {
+                flow.book_imbalance = book_imbalance(
+                    &perp.l3_book().top_bids(self.config.depth),
+                    &perp.l3_book().top_asks(self.config.depth),
+                );
+            }
+            while flow.activity.front().is_some_and(|(ts, _)| *ts < since) {
+                flow.activity.pop_front();
+            }
+        }
+
+        let now = self.instant.block_timestamp();
+        let due = self
+            .last_update
+            .is_none_or(|last| now.saturating_sub(last) >= self.config.update_interval.as_secs());
+        due.then(|| {
+            self.last_update = Some(now);
+            let mut signals = self
+                .flows
+                .keys()
+                .filter_map(|id| self.signals(*id))
+                .collect::<Vec<_>>();
+            signals.sort_by_key(|s| s.perpetual_id);
+            SignalUpdate {
+                instant: self.instant,
+                signals,
+            }
+        })
+    }
+
+    /// Current signals of the perpetual contract, `None` if it is not tracked.
+    pub fn signals(&self, perpetual_id: types::PerpetualId) -> Option<PerpetualSignals> {
+        let flow = self.flows.get(&perpetual_id)?;
+        let (mut trade_flow, mut trades, mut cancels) = (D128::ZERO, 0u64, 0u64);
+        let mut lifetimes = vec![];
+        for (_, activity) in &flow.activity {
+            match activity {
+                Activity::Trade { taker_side, size } => {
+                    trades += 1;
+                    let size = size.resize::<2>().to_signed();
+                    match taker_side {
+                        Some(OrderSide::Bid) => trade_flow += size,
+                        Some(OrderSide::Ask) => trade_flow -= size,
+                        None => {}
+                    }
+                }
+                Activity::Cancel => cancels += 1,
+                Activity::Removed { lifetime } => lifetimes.push(*lifetime),
+            }
+        }
+        Some(PerpetualSignals {
+            perpetual_id,
+            book_imbalance: flow.book_imbalance,
+            trade_flow,
+            trades,
+            cancels,
+            cancel_to_trade: (trades > 0).then(|| UD64::from(cancels) / UD64::from(trades)),
+            quote_lifetimes: quote_lifetimes(lifetimes),
+        })
+    }
+
+    fn since(&self) -> u64 {
+        self.instant
+            .block_timestamp()
+            .saturating_sub(self.config.window.as_secs())
+    }
+}
+
+/// Imbalance of the total size of the given bid and ask levels.
+fn book_imbalance(bids: &[(UD64, UD64)], asks: &[(UD64, UD64)]) -> Option<D64> {
+    let bid = bids.iter().map(|(_, size)| *size).sum::<UD64>();
+    let ask = asks.iter().map(|(_, size)| *size).sum::<UD64>();
+    let total = bid + ask;
+    (total > UD64::ZERO).then(|| (bid.to_signed() - ask.to_signed()) / total.to_signed())
+}
+
+fn quote_lifetimes(mut lifetimes: Vec<u64>) -> Option<QuoteLifetimes> {
+    if lifetimes.is_empty() {
+        return None;
+    }
+    lifetimes.sort();
+    let count = lifetimes.len() as u64;
+    let total = lifetimes.iter().sum::<u64>();
+    Some(QuoteLifetimes {
+        count,
+        min: Duration::from_secs(lifetimes[0]),
+        median: Duration::from_secs(lifetimes[(lifetimes.len() - 1) / 2]),
+        mean: Duration::from_millis(total * 1000 / count),
+        max: Duration::from_secs(lifetimes[lifetimes.len() - 1]),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use fastnum::{dec64, udec64};
+
+    use super::*;
+    use crate::testing::fuzz::{Action, Simulator};
+
+    #[test]
+    fn book_and_flow_signals() {
+        let mut sim = Simulator::new(&[1], 3);
+        let mut exchange = sim.exchange();
+        let mut tracker = SignalTracker::new(
+            SignalsConfig::default()
+                .with_window(Duration::from_secs(3))
+                .with_update_interval(Duration::from_secs(2)),
+        );
+        let place = |account, is_bid, offset, lots| Action::Place {
+            perpetual: 0,
+            account,
+            is_bid,
+            offset,
+            lots,
+        };
+        let mut apply = |sim: &mut Simulator, actions: &[Action]| {
+            let events = exchange.apply_events(&sim.block(actions)).unwrap().unwrap();
+            (
+                tracker.apply(&exchange, &events),
+                tracker.signals(1).unwrap(),
+            )
+        };
+
+        // Orders: 0 - bid, 1 - ask, 2 - bid
+        let (update, signals) = apply(
+            &mut sim,
+            &[
+                place(0, true, 1, 3),
+                place(1, false, 1, 1),
+                place(0, true, 2, 4),
+            ],
+        );
+        assert_eq!(update.unwrap().signals.len(), 1);
+        assert_eq!(signals.book_imbalance, Some(dec64!(0.75)));
+        assert_eq!(signals.cancel_to_trade, None);
+        assert_eq!(signals.quote_lifetimes, None);
+
+        // Taker sells into the best bid, then the ask gets cancelled
+        let (update, signals) = apply(
+            &mut sim,
+            &[Action::Fill {
+                order: 0,
+                taker: 2,
+                lots: 1,
+            }],
+        );
+        assert!(update.is_none());
+        assert_eq!(signals.trades, 1);
+        assert!(signals.trade_flow < D128::ZERO);
+
+        let (update, signals) = apply(&mut sim, &[Action::Cancel { order: 1 }]);
+        assert!(update.is_some());
+        assert_eq!(signals.book_imbalance, Some(D64::ONE));
+        assert_eq!(signals.cancels, 1);
+        assert_eq!(signals.cancel_to_trade, Some(udec64!(1)));
+        let lifetimes = signals.quote_lifetimes.unwrap();
+        assert_eq!(lifetimes.count, 1);
+        assert_eq!(lifetimes.max, Duration::from_secs(2));
+
+        // Full fill removes the quote without counting as cancel
+        let (_, signals) = apply(
+            &mut sim,
+            &[Action::Fill {
+                order: 0,
+                taker: 2,
+                lots: 10,
+            }],
+        );
+        assert_eq!(signals.trades, 2);
+        assert_eq!(signals.cancels, 1);
+        assert_eq!(signals.quote_lifetimes.unwrap().count, 2);
+
+        // Activity leaves the window
+        for _ in 0..4 {
+            apply(&mut sim, &[]);
+        }
+        let signals = tracker.signals(1).unwrap();
+        assert_eq!((signals.trades, signals.cancels), (0, 0));
+        assert_eq!(signals.trade_flow, D128::ZERO);
+        assert_eq!(signals.quote_lifetimes, None);
+        assert_eq!(signals.book_imbalance, Some(D64::ONE));
+    }
+
+    #[test]
+    fn lifetime_statistics() {
+        assert_eq!(quote_lifetimes(vec![]), None);
+        assert_eq!(
+            quote_lifetimes(vec![4, 1, 2, 10]),
+            Some(QuoteLifetimes {
+                count: 4,
+                min: Duration::from_secs(1),
+                median: Duration::from_secs(2),
+                mean: Duration::from_millis(4250),
+                max: Duration::from_secs(10),
+            })
+        );
+        assert_eq!(book_imbalance(&[], &[]), None);
+        assert_eq!(
+            book_imbalance(&[(udec64!(99), udec64!(1))], &[(udec64!(101), udec64!(3))]),
+            Some(dec64!(-0.5))
+        );
+    }
+}
@@ -0,0 +1,390 @@
+//! Trade tape with rolling window queries.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use dex_sdk::marketdata::tape::{Tape, TapeConfig};
+//!
+//! let mut tape = Tape::new(TapeConfig::default().with_max_age(Duration::from_secs(300)));
+//! let (mut rx, handle) = fill::start(&chain, provider, from, tokio::time::sleep).await?;
+//!
+//! while let Some(block_trades) = rx.recv().await {
+//!     tape.apply(&block_trades);
+//!     let window = Duration::from_secs(60);
+//!     println!("1m VWAP: {:?}, imbalance: {:?}",
+//!         tape.vwap(perp_id, window), tape.buy_sell_imbalance(perp_id, window));
+//! }
+//! ```
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+
+use alloy::primitives::TxHash;
+use fastnum::{D64, UD64, UD128};
+
+use crate::{
+    fill::BlockTrades,
+    types::{self, OrderSide},
+};
+
+/// Default number of trades kept per perpetual contract.
+const DEFAULT_MAX_TRADES: usize = 10_000;
+
+/// Retention configuration of the [`Tape`].
+#[derive(Clone, Copy, Debug)]
+pub struct TapeConfig {
+    max_trades: usize,
+    max_age: Option<Duration>,
+}
+
+impl Default for TapeConfig {
+    fn default() -> Self {
+        Self {
+            max_trades: DEFAULT_MAX_TRADES,
+            max_age: None,
+        }
+    }
+}
+
+impl TapeConfig {
+    /// Keep up to the given number of the most recent trades per perpetual contract.
+    pub fn with_max_trades(mut self, max_trades: usize) -> Self {
+        self.max_trades = max_trades;
+        self
+    }
+
+    /// Drop trades older than the given age, relative to the latest applied block.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+}
+
+/// Single trade print: fill of a maker order by the taker.
+#[derive(Clone, Copy, derive_more::Debug)]
+pub struct Trade {
+    /// Instant of the block the trade occurred in.
+    pub instant: types::StateInstant,
+
+    /// Transaction hash the trade occurred in.
+    pub tx_hash: TxHash,
+
+    /// Perpetual contract ID.
+    pub perpetual_id: types::PerpetualId,
+
+    /// Side of the taker, aggressor of the trade (Bid = buying, Ask = selling).
+    pub taker_side: OrderSide,
+
+    /// Trade price.
+    #[debug("{price}")]
+    pub price: UD64,
+
+    /// Trade size.
+    #[debug("{size}")]
+    pub size: UD64,
+}
+
+/// Open, high, low and close prices of the trades within the interval, see [`Tape::candles`].
+#[derive(Clone, Copy, PartialEq, Eq, derive_more::Debug)]
+pub struct Candle {
+    /// Perpetual contract ID.
+    pub perpetual_id: types::PerpetualId,
+
+    /// Block timestamp the interval starts at, inclusive.
+    pub start: u64,
+
+    /// Length of the interval in seconds.
+    pub interval: u64,
+
+    /// Price of the first trade.
+    #[debug("{open}")]
+    pub open: UD64,
+
+    /// Highest trade price.
+    #[debug("{high}")]
+    pub high: UD64,
+
+    /// Lowest trade price.
+    #[debug("{low}")]
+    pub low: UD64,
+
+    /// Price of the last trade.
+    #[debug("{close}")]
+    pub close: UD64,
+
+    /// Total traded size.
+    #[debug("{volume}")]
+    pub volume: UD128,
+
+    /// Number of trades.
+    pub trades: u64,
+}
+
+/// Recent trades per perpetual contract, fed from [`crate::fill`] stream.
+///
+/// Windows of the queries are measured in block time back from the latest
+/// applied block, inclusive.
+#[derive(Clone, Debug, Default)]
+pub struct Tape {
+    config: TapeConfig,
+    instant: types::StateInstant,
+    trades: HashMap<types::PerpetualId, VecDeque<Trade>>,
+}
+
+impl Tape {
+    pub fn new(config: TapeConfig) -> Self {
+        Self {
+            config,
+            instant: types::StateInstant::default(),
+            trades: HashMap::new(),
+        }
+    }
+
+    /// Instant of the latest applied block.
+    pub fn instant(&self) -> types::StateInstant {
+        self.instant
+    }
+
+    /// Appends trades of the block and drops ones outside of the retention limits.
+    pub fn apply(&mut self, block: &BlockTrades) {
+        self.instant = self.instant.max(block.instant);
+        for trade in &block.trades {
+            let trades = self.trades.entry(trade.perpetual_id).or_default();
+            trades.extend(trade.maker_fills.iter().map(|fill| Trade {
+                instant: block.instant,
+                tx_hash: trade.tx_hash,
+                perpetual_id: trade.perpetual_id,
+                taker_side: trade.taker_side,
+                price: fill.price,
+                size: fill.size,
+            }));
+        }
+
+        let since = self
+            .config
+            .max_age
+            .map(|age| self.since(age))
+            .unwrap_or_default();
+        for trades in self.trades.values_mut() {
+            let excess = trades.len().saturating_sub(self.config.max_trades);
+            trades.drain(..excess);
+            while trades
+                .front()
+                .is_some_and(|t| t.instant.block_timestamp() < since)
+            {
+                trades.pop_front();
+            }
+        }
+    }
+
+    /// Retained trades of the perpetual contract, oldest first.
+    pub fn trades(&self, perpetual_id: types::PerpetualId) -> impl Iterator<Item = &Trade> {
+        self.trades.get(&perpetual_id).into_iter().flatten()
+    }
+
+    /// The most recent trade of the perpetual contract.
+    pub fn last_trade(&self, perpetual_id: types::PerpetualId) -> Option<&Trade> {
+        self.trades.get(&perpetual_id)?.back()
+    }
+
+    /// Volume-weighted average price of trades within the window.
+    ///
+    /// Returns `None` if there were no trades.
+    pub fn vwap(&self, perpetual_id: types::PerpetualId, window: Duration) -> Option<UD64> {
+        let (notional, volume) = self.window(perpetual_id, window).fold(
+            (UD128::ZERO, UD128::ZERO),
+            |(notional, volume), t| {
+                (
+                    notional + t.price.resize() * t.size.resize(),
+                    volume + t.size.resize(),
+                )
+            },
+        );
+        (volume > UD128::ZERO).then(|| (notional / volume).resize())
+    }
+
+    /// OHLC candles of the retained trades, aggregated into intervals of block time
+    /// aligned to the multiples of the interval, oldest first.
+    /// Intervals without trades are skipped.
+    pub fn candles(&self, perpetual_id: types::PerpetualId, interval: Duration) -> Vec<Candle> {
+        let interval = interval.as_secs().max(1);
+        let mut candles: Vec<Candle> = vec![];
+        for trade in self.trades(perpetual_id) {
+            let timestamp = trade.instant.block_timestamp();
+            let start = timestamp - timestamp % interval;
+            match candles.last_mut() {
+                Some(candle) if candle.start == start => {
+                    candle.high = candle.high.max(trade.price);
+                    candle.low = candle.low.min(trade.price);
+                    candle.close = trade.price;
+                    candle.volume += trade.size.resize();
+                    candle.trades += 1;
+                }
+                _ => candles.push(Candle {
+                    perpetual_id,
+                    start,
+                    interval,
+                    open: trade.price,
+                    high: trade.price,
+                    low: trade.price,
+                    close: trade.price,
+                    volume: trade.size.resize(),
+                    trades: 1,
+                }),
+            }
+        }
+        candles
+    }
+
+    /// Total traded size within the window.
+    pub fn volume(&self, perpetual_id: types::PerpetualId, window: Duration) -> UD128 {
+        self.window(perpetual_id, window)
+            .map(|t| t.size.resize::<2>())
+            .sum()
+    }
+
+    /// Imbalance of taker buy and sell volume within the window, in range `[-1, 1]`:
+    /// `(buy - sell) / (buy + sell)`.
+    ///
+    /// Returns `None` if there were no trades.
+    pub fn buy_sell_imbalance(
+        &self,
+        perpetual_id: types::PerpetualId,
+        window: Duration,
+    ) -> Option<D64> {
+        let (buy, sell) =
+            self.window(perpetual_id, window)
+                .fold((UD64::ZERO, UD64::ZERO), |(buy, sell), t| {
+                    match t.taker_side {
+                        OrderSide::Bid => (buy + t.size, sell),
+                        OrderSide::Ask => (buy, sell + t.size),
+                    }
+                });
+        let total = buy + sell;
+        (total > UD64::ZERO).then(|| (buy.to_signed() - sell.to_signed()) / total.to_signed())
+    }
+
+    fn window(
+        &self,
+        perpetual_id: types::PerpetualId,
+        window: Duration,
+    ) -> impl Iterator<Item = &Trade> {
+        let since = self.since(window);
+        self.trades
+            .get(&perpetual_id)
+            .into_iter()
+            .flat_map(move |trades| {
+                trades
+                    .iter()
+                    .rev()
+                    .take_while(move |t| t.instant.block_timestamp() >= since)
+            })
+    }
+
+    fn since(&self, window: Duration) -> u64 {
+        self.instant
+            .block_timestamp()
+            .saturating_sub(window.as_secs())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fastnum::{dec64, udec64, udec128};
+
+    use super::*;
+    use crate::fill::{MakerFill, TakerTrade};
+
+    fn block(number: u64, trades: Vec<(OrderSide, UD64, UD64)>) -> BlockTrades {
+        BlockTrades::new(
+            types::StateInstant::new(number, number * 10),
+            trades
+                .into_iter()
+                .map(|(side, price, size)| TakerTrade {
+                    tx_hash: TxHash::ZERO,
+                    tx_index: 0,
+                    fill_id: types::FillId::new(TxHash::ZERO, 1),
+                    perpetual_id: 1,
+                    taker_account_id: 1,
+                    taker_side: side,
+                    taker_fee: UD64::ZERO,
+                    maker_fills: vec![MakerFill {
+                        log_index: 0,
+                        fill_id: types::FillId::new(TxHash::ZERO, 0),
+                        maker_account_id: 2,
+                        maker_order_id: types::OrderId::new(1).unwrap(),
+                        price,
+                        size,
+                        fee: UD64::ZERO,
+                    }],
+                    execution: None,
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn window_queries() {
+        let mut tape = Tape::new(TapeConfig::default());
+        tape.apply(&block(1, vec![(OrderSide::Bid, udec64!(100), udec64!(3))]));
+        tape.apply(&block(2, vec![(OrderSide::Ask, udec64!(110), udec64!(1))]));
+        tape.apply(&block(3, vec![(OrderSide::Bid, udec64!(120), udec64!(1))]));
+
+        let all = Duration::from_secs(100);
+        let last_two = Duration::from_secs(10);
+        assert_eq!(tape.volume(1, all), udec128!(5));
+        assert_eq!(tape.vwap(1, all), Some(udec64!(106)));
+        assert_eq!(tape.buy_sell_imbalance(1, all), Some(dec64!(0.6)));
+
+        assert_eq!(tape.volume(1, last_two), udec128!(2));
+        assert_eq!(tape.vwap(1, last_two), Some(udec64!(115)));
+        assert_eq!(tape.buy_sell_imbalance(1, last_two), Some(D64::ZERO));
+
+        assert_eq!(tape.last_trade(1).unwrap().price, udec64!(120));
+        let candles = tape.candles(1, Duration::from_secs(20));
+        assert_eq!(candles.len(), 2);
+        assert_eq!(
+            (
+                candles[0].start,
+                candles[0].open,
+                candles[0].close,
+                candles[0].trades
+            ),
+            (0, udec64!(100), udec64!(100), 1)
+        );
+        assert_eq!(
+            (
+                candles[1].start,
+                candles[1].low,
+                candles[1].high,
+                candles[1].volume
+            ),
+            (20, udec64!(110), udec64!(120), udec128!(2))
+        );
+        assert_eq!(tape.vwap(2, all), None);
+        assert_eq!(tape.buy_sell_imbalance(2, all), None);
+    }
+
+    #[test]
+    fn retention() {
+        let mut tape = Tape::new(
+            TapeConfig::default()
+                .with_max_trades(3)
+                .with_max_age(Duration::from_secs(20)),
+        );
+        for n in 1..=4 {
+            tape.apply(&block(n, vec![(OrderSide::Bid, udec64!(100), udec64!(1))]));
+        }
+        // Block 1 trade exceeds the count, block 2 one is at the age limit
+        assert_eq!(tape.trades(1).count(), 3);
+        tape.apply(&block(5, vec![]));
+        assert_eq!(
+            tape.trades(1)
+                .map(|t| t.instant.block_number())
+                .collect::<Vec<_>>(),
+            vec![3, 4]
+        );
+    }
+}
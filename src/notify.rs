@@ -0,0 +1,487 @@
+//! Account event notifications.
+//!
+//! [`Notifier`] lets users register async callbacks for specific account events,
+//! such as balance dropping below a threshold, position opening/closing, order fills,
+//! or account freezing, so alerting integrations don't have to filter the state
+//! event stream themselves.
+//!
+//! Strategies can also subscribe to venue availability changes with
+//! [`Notifier::subscribe_venue`], to pause quoting while the exchange is halted or
+//! the perpetual contract is paused, as submissions fail with
+//! [`crate::error::DexError::VenueUnavailable`] meanwhile.
+//!
+//! Conditions get evaluated synchronously on state events returned by
+//! [`crate::state::Exchange::apply_events`], while callbacks run as separate tasks
+//! with bounded concurrency, so slow integrations don't stall the event-apply loop.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use dex_sdk::notify::{Condition, Notifier};
+//!
+//! let mut notifier = Notifier::new(16);
+//! notifier.subscribe(Some(account_id), Condition::BalanceBelow(udec128!(1000)), |n| async move {
+//!     alert(format!("account {} balance is low at block {}", n.account_id, n.instant.block_number())).await;
+//! });
+//!
+//! while let Some(block_events) = stream.next().await {
+//!     if let Some(state_events) = exchange.apply_events(&block_events?)? {
+//!         notifier.notify(&state_events);
+//!     }
+//! }
+//! ```
+
+use std::{collections::HashMap, future::Future, sync::Arc};
+
+use fastnum::{UD64, UD128};
+use futures::future::BoxFuture;
+use tokio::sync::Semaphore;
+
+use crate::{
+    state::{
+        AccountEventType, ExchangeEvent, OrderEventType, PerpetualEventType, PositionEventType,
+        StateBlockEvents, StateEvents,
+    },
+    types,
+};
+
+/// ID of the subscription, see [`Notifier::subscribe`].
+pub type SubscriptionId = u64;
+
+/// Account event condition to get notified on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Condition {
+    /// Account balance dropped below the threshold.
+    /// Fires once per drop, re-arms when the balance gets back to the threshold or above.
+    BalanceBelow(UD128),
+    /// Position opened.
+    PositionOpened,
+    /// Position closed, including liquidations, deleverages and unwinds reducing it to zero.
+    PositionClosed,
+    /// Order filled, either as maker or taker.
+    OrderFilled,
+    /// Order lingered in the book past the interval,
+    /// requires [`crate::state::Exchange::watch_stale_orders`].
+    OrderStale,
+    /// Account frozen.
+    Frozen,
+    /// Account unfrozen, order submissions can be resumed.
+    Unfrozen,
+}
+
+/// Notification passed to the callback.
+#[derive(Clone, Debug)]
+pub struct Notification {
+    /// ID of the subscription the notification is for.
+    pub subscription_id: SubscriptionId,
+
+    /// Instant of the block the event occurred at.
+    pub instant: types::StateInstant,
+
+    /// Metadata of the log the event originates from.
+    pub log: types::LogMeta,
+
+    /// ID of the account the event relates to.
+    pub account_id: types::AccountId,
+
+    /// Condition met.
+    pub condition: Condition,
+
+    /// State event met the condition.
+    pub event: StateEvents,
+}
+
+/// Change of the exchange availability for order submissions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VenueChange {
+    /// Exchange halted, orders for all perpetual contracts get rejected.
+    Halted,
+    /// Exchange unhalted, submissions can be resumed unless the contract is paused.
+    Unhalted,
+    /// Perpetual contract paused or removed, its orders get rejected.
+    Paused(types::PerpetualId),
+    /// Perpetual contract unpaused, submissions can be resumed.
+    Unpaused(types::PerpetualId),
+}
+
+/// Venue notification passed to the callback, see [`Notifier::subscribe_venue`].
+#[derive(Clone, Debug)]
+pub struct VenueNotification {
+    /// ID of the subscription the notification is for.
+    pub subscription_id: SubscriptionId,
+
+    /// Instant of the block the change occurred at.
+    pub instant: types::StateInstant,
+
+    /// Metadata of the log the change originates from.
+    pub log: types::LogMeta,
+
+    /// Availability change.
+    pub change: VenueChange,
+}
+
+type Callback<N = Notification> = Arc<dyn Fn(N) -> BoxFuture<'static, ()> + Send + Sync>;
+
+struct Subscription {
+    id: SubscriptionId,
+    account_id: Option<types::AccountId>,
+    condition: Condition,
+    callback: Callback,
+    /// Accounts with balance currently below the threshold, for edge-triggering.
+    below: HashMap<types::AccountId, bool>,
+}
+
+/// Registry of account event callbacks.
+pub struct Notifier {
+    next_id: SubscriptionId,
+    subscriptions: Vec<Subscription>,
+    venue_subscriptions: Vec<(SubscriptionId, Callback<VenueNotification>)>,
+    pool: Arc<Semaphore>,
+}
+
+impl Notifier {
+    /// Create a new notifier running up to `max_concurrent_callbacks` callbacks at once.
+    pub fn new(max_concurrent_callbacks: usize) -> Self {
+        Self {
+            next_id: 1,
+            subscriptions: Vec::new(),
+            venue_subscriptions: Vec::new(),
+            pool: Arc::new(Semaphore::new(max_concurrent_callbacks.max(1))),
+        }
+    }
+
+    /// Register the callback for the condition on the given account,
+    /// or on all tracked accounts if `None`.
+    pub fn subscribe<F, Fut>(
+        &mut self,
+        account_id: Option<types::AccountId>,
+        condition: Condition,
+        callback: F,
+    ) -> SubscriptionId
+    where
+        F: Fn(Notification) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.subscriptions.push(Subscription {
+            id,
+            account_id,
+            condition,
+            callback: Arc::new(move |n| Box::pin(callback(n))),
+            below: HashMap::new(),
+        });
+        id
+    }
+
+    /// Register the callback for the exchange halts and perpetual contract pauses,
+    /// as well as their reversals.
+    pub fn subscribe_venue<F, Fut>(&mut self, callback: F) -> SubscriptionId
+    where
+        F: Fn(VenueNotification) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.venue_subscriptions
+            .push((id, Arc::new(move |n| Box::pin(callback(n)))));
+        id
+    }
+
+    /// Remove the subscription, returns `false` if it was not found.
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let len = self.subscriptions.len() + self.venue_subscriptions.len();
+        self.subscriptions.retain(|s| s.id != id);
+        self.venue_subscriptions.retain(|(sub_id, _)| *sub_id != id);
+        self.subscriptions.len() + self.venue_subscriptions.len() < len
+    }
+
+    /// Evaluate conditions on the state events of a block and spawn callbacks
+    /// for the met ones. Returns the number of notifications dispatched.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn notify(&mut self, events: &StateBlockEvents) -> usize {
+        let notifications = self.evaluate(events);
+        let venue_notifications = self.evaluate_venue(events);
+        let count = notifications.len() + venue_notifications.len();
+        for (callback, notification) in notifications {
+            self.spawn(callback, notification);
+        }
+        for (callback, notification) in venue_notifications {
+            self.spawn(callback, notification);
+        }
+        count
+    }
+
+    fn spawn<N: Send + 'static>(&self, callback: Callback<N>, notification: N) {
+        let pool = self.pool.clone();
+        tokio::spawn(async move {
+            // Semaphore is never closed
+            let _permit = pool.acquire_owned().await;
+            callback(notification).await;
+        });
+    }
+
+    fn evaluate_venue(
+        &self,
+        events: &StateBlockEvents,
+    ) -> Vec<(Callback<VenueNotification>, VenueNotification)> {
+        if self.venue_subscriptions.is_empty() {
+            return Vec::new();
+        }
+        let mut notifications = Vec::new();
+        for ctx in events.events() {
+            for event in events.events_of(ctx) {
+                let change = match event {
+                    StateEvents::Exchange(ExchangeEvent::Halted(true)) => VenueChange::Halted,
+                    StateEvents::Exchange(ExchangeEvent::Halted(false)) => VenueChange::Unhalted,
+                    StateEvents::Perpetual(e) => match e.r#type {
+                        PerpetualEventType::Paused(true) => VenueChange::Paused(e.perpetual_id),
+                        PerpetualEventType::Paused(false) => VenueChange::Unpaused(e.perpetual_id),
+                        _ => continue,
+                    },
+                    _ => continue,
+                };
+                for (id, callback) in &self.venue_subscriptions {
+                    notifications.push((
+                        callback.clone(),
+                        VenueNotification {
+                            subscription_id: *id,
+                            instant: events.instant(),
+                            log: ctx.meta(),
+                            change,
+                        },
+                    ));
+                }
+            }
+        }
+        notifications
+    }
+
+    fn evaluate(&mut self, events: &StateBlockEvents) -> Vec<(Callback, Notification)> {
+        let mut notifications = Vec::new();
+        for ctx in events.events() {
+            for event in events.events_of(ctx) {
+                for sub in self.subscriptions.iter_mut() {
+                    if let Some(account_id) = sub.matches(event) {
+                        notifications.push((
+                            sub.callback.clone(),
+                            Notification {
+                                subscription_id: sub.id,
+                                instant: events.instant(),
+                                log: ctx.meta(),
+                                account_id,
+                                condition: sub.condition,
+                                event: event.clone(),
+                            },
+                        ));
+                    }
+                }
+            }
+        }
+        notifications
+    }
+}
+
+impl Subscription {
+    /// Returns ID of the account if the event meets the subscription condition.
+    fn matches(&mut self, event: &StateEvents) -> Option<types::AccountId> {
+        let account_id = match event {
+            StateEvents::Account(e) => e.account_id,
+            StateEvents::Order(e) => e.account_id,
+            StateEvents::Position(e) => e.account_id,
+            _ => return None,
+        };
+        if self.account_id.is_some_and(|id| id != account_id) {
+            return None;
+        }
+
+        let met = match (self.condition, event) {
+            (Condition::BalanceBelow(threshold), StateEvents::Account(e)) => match e.r#type {
+                AccountEventType::BalanceUpdated(balance) => {
+                    let below = balance < threshold;
+                    let was_below = self.below.insert(account_id, below).unwrap_or(false);
+                    below && !was_below
+                }
+                _ => false,
+            },
+            (Condition::PositionOpened, StateEvents::Position(e)) => {
+                matches!(e.r#type, PositionEventType::Opened { .. })
+            }
+            (Condition::PositionClosed, StateEvents::Position(e)) => match e.r#type {
+                PositionEventType::Closed { .. } | PositionEventType::Unwound { .. } => true,
+                PositionEventType::Liquidated { new_size, .. }
+                | PositionEventType::Deleveraged { new_size, .. } => new_size == UD64::ZERO,
+                _ => false,
+            },
+            (Condition::OrderFilled, StateEvents::Order(e)) => {
+                matches!(e.r#type, OrderEventType::Filled { .. })
+            }
+            (Condition::OrderStale, StateEvents::Order(e)) => {
+                matches!(e.r#type, OrderEventType::Stale { .. })
+            }
+            (Condition::Frozen, StateEvents::Account(e)) => {
+                matches!(e.r#type, AccountEventType::Frozen(status) if status.is_frozen())
+            }
+            (Condition::Unfrozen, StateEvents::Account(e)) => {
+                matches!(e.r#type, AccountEventType::Frozen(status) if !status.is_frozen())
+            }
+            _ => false,
+        };
+        met.then_some(account_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fastnum::udec128;
+
+    use super::*;
+    use crate::state::{AccountEvent, PerpetualEvent};
+
+    fn balance(account_id: types::AccountId, balance: UD128) -> StateEvents {
+        StateEvents::Account(AccountEvent {
+            account_id,
+            request_id: None,
+            r#type: AccountEventType::BalanceUpdated(balance),
+        })
+    }
+
+    fn block(events: Vec<StateEvents>) -> StateBlockEvents {
+        StateBlockEvents::with_buffer(
+            types::StateInstant::new(1, 1),
+            vec![types::EventContext::empty(0..events.len())],
+            events,
+        )
+    }
+
+    fn conditions(
+        notifier: &mut Notifier,
+        events: Vec<StateEvents>,
+    ) -> Vec<(types::AccountId, Condition)> {
+        notifier
+            .evaluate(&block(events))
+            .into_iter()
+            .map(|(_, n)| (n.account_id, n.condition))
+            .collect()
+    }
+
+    #[test]
+    fn balance_below_is_edge_triggered() {
+        let mut notifier = Notifier::new(1);
+        let threshold = Condition::BalanceBelow(udec128!(100));
+        notifier.subscribe(None, threshold, |_| async {});
+
+        assert_eq!(
+            conditions(
+                &mut notifier,
+                vec![
+                    balance(1, udec128!(150)),
+                    balance(1, udec128!(90)),
+                    balance(1, udec128!(80)),
+                    balance(2, udec128!(50)),
+                ]
+            ),
+            vec![(1, threshold), (2, threshold)]
+        );
+        assert_eq!(
+            conditions(
+                &mut notifier,
+                vec![balance(1, udec128!(100)), balance(1, udec128!(99))]
+            ),
+            vec![(1, threshold)]
+        );
+    }
+
+    #[test]
+    fn subscriptions_filter_by_account_and_condition() {
+        let mut notifier = Notifier::new(1);
+        let id = notifier.subscribe(Some(2), Condition::Frozen, |_| async {});
+        let frozen = |account_id, frozen| {
+            StateEvents::Account(AccountEvent {
+                account_id,
+                request_id: None,
+                r#type: AccountEventType::Frozen(if frozen {
+                    types::FreezeStatus::Frozen(1)
+                } else {
+                    types::FreezeStatus::NotFrozen
+                }),
+            })
+        };
+
+        assert_eq!(
+            conditions(
+                &mut notifier,
+                vec![
+                    frozen(1, true),
+                    frozen(2, false),
+                    balance(2, UD128::ZERO),
+                    frozen(2, true)
+                ]
+            ),
+            vec![(2, Condition::Frozen)]
+        );
+
+        assert!(notifier.unsubscribe(id));
+        assert!(!notifier.unsubscribe(id));
+        assert!(conditions(&mut notifier, vec![frozen(2, true)]).is_empty());
+
+        notifier.subscribe(None, Condition::Unfrozen, |_| async {});
+        assert_eq!(
+            conditions(&mut notifier, vec![frozen(1, true), frozen(1, false)]),
+            vec![(1, Condition::Unfrozen)]
+        );
+    }
+
+    #[tokio::test]
+    async fn callbacks_get_dispatched() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut notifier = Notifier::new(2);
+        notifier.subscribe(None, Condition::BalanceBelow(udec128!(10)), move |n| {
+            let tx = tx.clone();
+            async move {
+                tx.send(n.account_id).unwrap();
+            }
+        });
+
+        assert_eq!(notifier.notify(&block(vec![balance(7, udec128!(1))])), 1);
+        assert_eq!(rx.recv().await, Some(7));
+    }
+
+    #[test]
+    fn venue_changes() {
+        let mut notifier = Notifier::new(1);
+        let id = notifier.subscribe_venue(|_| async {});
+        let paused = |paused| {
+            StateEvents::Perpetual(PerpetualEvent {
+                perpetual_id: 3,
+                r#type: PerpetualEventType::Paused(paused),
+            })
+        };
+        let events = block(vec![
+            StateEvents::Exchange(ExchangeEvent::Halted(true)),
+            paused(true),
+            balance(1, UD128::ZERO),
+            paused(false),
+            StateEvents::Exchange(ExchangeEvent::Halted(false)),
+        ]);
+
+        assert_eq!(
+            notifier
+                .evaluate_venue(&events)
+                .into_iter()
+                .map(|(_, n)| (n.subscription_id, n.change))
+                .collect::<Vec<_>>(),
+            vec![
+                (id, VenueChange::Halted),
+                (id, VenueChange::Paused(3)),
+                (id, VenueChange::Unpaused(3)),
+                (id, VenueChange::Unhalted),
+            ]
+        );
+        assert!(notifier.evaluate(&events).is_empty());
+
+        assert!(notifier.unsubscribe(id));
+        assert!(notifier.evaluate_venue(&events).is_empty());
+    }
+}
@@ -4,6 +4,38 @@ use fastnum::{
     decimal::{Context, Decimal, RoundingMode, UnsignedDecimal},
 };
 
+/// Rounding direction of decimal to fixed-point conversions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Rounding {
+    /// Towards negative infinity.
+    Floor,
+    /// Towards positive infinity.
+    Ceil,
+    /// To the nearest value, half away from zero.
+    #[default]
+    Nearest,
+}
+
+impl From<Rounding> for RoundingMode {
+    fn from(value: Rounding) -> Self {
+        match value {
+            Rounding::Floor => RoundingMode::Floor,
+            Rounding::Ceil => RoundingMode::Ceiling,
+            Rounding::Nearest => RoundingMode::HalfUp,
+        }
+    }
+}
+
+/// Error of a checked conversion.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum ConversionError {
+    #[error("precision loss converting {0} to {1} decimals")]
+    PrecisionLoss(String, u8),
+
+    #[error("overflow converting {0}")]
+    Overflow(String),
+}
+
 /// Fixed-point to decimal converter.
 #[derive(Clone, Copy, Debug, Default)]
 pub struct Converter {
@@ -21,6 +53,21 @@ impl Converter {
         self.decimals as u8
     }
 
+    /// Same as [`Self::from_unsigned`], but fails if the value does not fit
+    /// the decimal precision instead of panicking.
+    pub fn from_unsigned_checked<const N: usize>(
+        &self,
+        value: U256,
+    ) -> Result<UnsignedDecimal<N>, ConversionError> {
+        let unscaled = bint::UInt::<N>::from_le_slice(value.as_le_slice())
+            .ok_or_else(|| ConversionError::Overflow(value.to_string()))?;
+        Ok(UnsignedDecimal::<N>::from_parts(
+            unscaled,
+            -self.decimals,
+            Context::default().with_rounding_mode(RoundingMode::Floor),
+        ))
+    }
+
     pub fn from_unsigned<const N: usize>(&self, value: U256) -> UnsignedDecimal<N> {
         let unscaled = bint::UInt::<N>::from_le_slice(value.as_le_slice())
             .expect("Converter: U256 -> UInt::<N>");
@@ -71,6 +118,96 @@ impl Converter {
         U256::from_le_slice(rescaled.digits().to_radix_le(256).as_slice())
     }
 
+    /// Converts the value rounding it in the given direction if it has more
+    /// fractional digits than the converter.
+    pub fn to_unsigned_rounded<const N: usize>(
+        &self,
+        value: UnsignedDecimal<N>,
+        rounding: Rounding,
+    ) -> U256 {
+        match rounding {
+            // Ceiling of the rescale looks only at the first dropped digit,
+            // so 1.0001 would end up as 1.00
+            Rounding::Ceil => {
+                let floor = self.to_unsigned(value.with_rounding_mode(RoundingMode::Floor));
+                if self.from_unsigned::<N>(floor) < value {
+                    floor + U256::ONE
+                } else {
+                    floor
+                }
+            }
+            _ => self.to_unsigned(value.with_rounding_mode(rounding.into())),
+        }
+    }
+
+    /// Converts the value, failing if it has more fractional digits than the converter
+    /// or does not fit fixed-point representation, instead of silently rounding/truncating.
+    pub fn to_unsigned_checked<const N: usize>(
+        &self,
+        value: UnsignedDecimal<N>,
+    ) -> Result<U256, ConversionError> {
+        let rescaled = value
+            .with_ctx(Context::default().without_traps())
+            .rescale(self.decimals as i16);
+        if rescaled.is_nan()
+            || rescaled.is_op_overflow()
+            || rescaled.fractional_digits_count() != self.decimals as i16
+        {
+            return Err(ConversionError::Overflow(value.to_string()));
+        }
+        if rescaled != value {
+            return Err(ConversionError::PrecisionLoss(
+                value.to_string(),
+                self.decimals(),
+            ));
+        }
+        U256::try_from_le_slice(rescaled.digits().to_radix_le(256).as_slice())
+            .ok_or_else(|| ConversionError::Overflow(value.to_string()))
+    }
+
+    /// Converts the value rounding it in the given direction if it has more
+    /// fractional digits than the converter.
+    pub fn to_signed_rounded<const N: usize>(&self, value: Decimal<N>, rounding: Rounding) -> I256 {
+        if rounding == Rounding::Nearest {
+            return self.to_signed(value.with_rounding_mode(rounding.into()));
+        }
+        // Directed rounding of the rescale looks only at the first dropped digit,
+        // so truncate and step away from zero for any remainder instead
+        let truncated = self.to_signed(value.with_rounding_mode(RoundingMode::Down));
+        let exact = self.from_signed::<N>(truncated) == value;
+        match rounding {
+            Rounding::Floor if !exact && value.is_negative() => truncated - I256::ONE,
+            Rounding::Ceil if !exact && !value.is_negative() => truncated + I256::ONE,
+            _ => truncated,
+        }
+    }
+
+    /// Converts the value, failing if it has more fractional digits than the converter
+    /// or does not fit fixed-point representation, instead of silently rounding/saturating.
+    pub fn to_signed_checked<const N: usize>(
+        &self,
+        value: Decimal<N>,
+    ) -> Result<I256, ConversionError> {
+        let rescaled = value
+            .with_ctx(Context::default().without_traps())
+            .rescale(self.decimals as i16);
+        if rescaled.is_nan()
+            || rescaled.is_op_overflow()
+            || rescaled.fractional_digits_count() != self.decimals as i16
+        {
+            return Err(ConversionError::Overflow(value.to_string()));
+        }
+        if rescaled != value {
+            return Err(ConversionError::PrecisionLoss(
+                value.to_string(),
+                self.decimals(),
+            ));
+        }
+        let abs = I256::try_from_le_slice(rescaled.digits().to_radix_le(256).as_slice())
+            .ok_or_else(|| ConversionError::Overflow(value.to_string()))?;
+        Ok(if value.is_negative() { -abs } else { abs })
+    }
+
     pub fn to_signed<const N: usize>(&self, value: Decimal<N>) -> I256 {
         let rescaled = value.rescale(self.decimals as i16);
         let mut res = I256::try_from_le_slice(rescaled.digits().to_radix_le(256).as_slice())
@@ -84,7 +221,7 @@ impl Converter {
 
 #[cfg(test)]
 mod tests {
-    use fastnum::{dec256, udec256};
+    use fastnum::{dec64, dec256, udec64, udec256};
 
     use super::*;
 
@@ -149,6 +286,86 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_numeric_converter_rounding() {
+        let conv = Converter::new(2);
+        assert_eq!(
+            conv.to_unsigned_rounded(udec64!(1.005), Rounding::Floor),
+            U256::from(100)
+        );
+        assert_eq!(
+            conv.to_unsigned_rounded(udec64!(1.001), Rounding::Ceil),
+            U256::from(101)
+        );
+        assert_eq!(
+            conv.to_unsigned_rounded(udec64!(1.0001), Rounding::Ceil),
+            U256::from(101)
+        );
+        assert_eq!(
+            conv.to_unsigned_rounded(udec64!(1.01), Rounding::Ceil),
+            U256::from(101)
+        );
+        assert_eq!(
+            conv.to_unsigned_rounded(udec64!(1.005), Rounding::Nearest),
+            U256::from(101)
+        );
+        assert_eq!(
+            conv.to_unsigned_rounded(udec64!(1.004), Rounding::Nearest),
+            U256::from(100)
+        );
+        assert_eq!(
+            conv.to_signed_rounded(dec64!(-1.001), Rounding::Floor),
+            I256::try_from(-101).unwrap()
+        );
+        assert_eq!(
+            conv.to_signed_rounded(dec64!(-1.009), Rounding::Ceil),
+            I256::try_from(-100).unwrap()
+        );
+        assert_eq!(
+            conv.to_signed_rounded(dec64!(-1.0001), Rounding::Floor),
+            I256::try_from(-101).unwrap()
+        );
+        assert_eq!(
+            conv.to_signed_rounded(dec64!(1.0001), Rounding::Ceil),
+            I256::try_from(101).unwrap()
+        );
+        assert_eq!(
+            conv.to_signed_rounded(dec64!(-1.0001), Rounding::Ceil),
+            I256::try_from(-100).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_numeric_converter_checked() {
+        let conv = Converter::new(2);
+        assert_eq!(conv.to_unsigned_checked(udec64!(1.2)), Ok(U256::from(120)));
+        assert!(matches!(
+            conv.to_unsigned_checked(udec64!(1.234)),
+            Err(ConversionError::PrecisionLoss(_, 2))
+        ));
+        assert_eq!(
+            conv.to_signed_checked(dec64!(-1.23)),
+            Ok(I256::try_from(-123).unwrap())
+        );
+        assert!(matches!(
+            conv.to_signed_checked(dec64!(-1.234)),
+            Err(ConversionError::PrecisionLoss(_, 2))
+        ));
+        assert!(matches!(
+            Converter::new(18).to_unsigned_checked(udec64!(1000)),
+            Err(ConversionError::Overflow(_))
+        ));
+
+        assert_eq!(
+            conv.from_unsigned_checked::<1>(U256::from(123)),
+            Ok(udec64!(1.23))
+        );
+        assert!(matches!(
+            conv.from_unsigned_checked::<1>(U256::MAX),
+            Err(ConversionError::Overflow(_))
+        ));
+    }
+
     #[test]
     fn test_numeric_converter_to_signed() {
         assert_eq!(
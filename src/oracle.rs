@@ -0,0 +1,181 @@
+//! Index price sources.
+//!
+//! [`IndexPriceSource`] unifies the reference prices of perpetual contracts coming from
+//! different places: [`LinkFeedSource`] tracks the on-chain Chainlink Data Streams prices
+//! reported via [`crate::state::Exchange::apply_events`], while [`ExternalSource`] takes
+//! prices published by user code, e.g. from an HTTP or WebSocket feed of another venue.
+//!
+//! [`crate::state::Exchange::update_index_prices`] feeds the prices into
+//! [`crate::state::Perpetual::index_price`], so basis and funding estimations can use an
+//! external reference when the on-chain oracle is ignored by the contract.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use dex_sdk::oracle::ExternalSource;
+//!
+//! let source = ExternalSource::new();
+//! let publisher = source.clone();
+//! tokio::spawn(async move {
+//!     while let Some((perp_id, price, timestamp)) = venue_feed.next().await {
+//!         publisher.publish(perp_id, price, timestamp);
+//!     }
+//! });
+//!
+//! while let Some(block_events) = stream.next().await {
+//!     exchange.apply_events(&block_events?)?;
+//!     exchange.update_index_prices(&source);
+//!     println!("basis: {:?}", exchange.perpetuals()[&perp_id].basis());
+//! }
+//! ```
+
+use std::{collections::HashMap, sync::Arc};
+
+use dashmap::DashMap;
+use fastnum::UD64;
+
+use crate::{
+    state::{Exchange, PerpetualEvent, PerpetualEventType, StateBlockEvents, StateEvents},
+    types,
+};
+
+/// Index price of the perpetual contract.
+#[derive(Clone, Copy, PartialEq, Eq, derive_more::Debug)]
+pub struct IndexPrice {
+    /// Price in the units of the perpetual contract prices.
+    #[debug("{price}")]
+    pub price: UD64,
+
+    /// Unix timestamp (in seconds) the price was observed at.
+    pub timestamp: u64,
+}
+
+/// Source of index prices of perpetual contracts.
+pub trait IndexPriceSource {
+    /// The latest index price of the perpetual contract, if known to the source.
+    fn index_price(&self, perpetual_id: types::PerpetualId) -> Option<IndexPrice>;
+}
+
+/// Index prices from the on-chain Chainlink Data Streams price updates.
+#[derive(Clone, Debug, Default)]
+pub struct LinkFeedSource {
+    prices: HashMap<types::PerpetualId, IndexPrice>,
+}
+
+impl LinkFeedSource {
+    /// Create the source seeded with the oracle prices of the exchange state.
+    pub fn new(exchange: &Exchange) -> Self {
+        Self {
+            prices: exchange
+                .perpetuals()
+                .values()
+                .filter(|perp| perp.oracle_price() > UD64::ZERO)
+                .map(|perp| {
+                    (
+                        perp.id(),
+                        IndexPrice {
+                            price: perp.oracle_price(),
+                            timestamp: perp.oracle_price_timestamp(),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Records oracle price updates of the block.
+    pub fn apply(&mut self, events: &StateBlockEvents) {
+        for (_, event) in events.flat_events() {
+            if let StateEvents::Perpetual(PerpetualEvent {
+                perpetual_id,
+                r#type: PerpetualEventType::OraclePriceUpdated(price),
+            }) = event
+            {
+                self.prices.insert(
+                    *perpetual_id,
+                    IndexPrice {
+                        price: *price,
+                        timestamp: events.instant().block_timestamp(),
+                    },
+                );
+            }
+        }
+    }
+}
+
+impl IndexPriceSource for LinkFeedSource {
+    fn index_price(&self, perpetual_id: types::PerpetualId) -> Option<IndexPrice> {
+        self.prices.get(&perpetual_id).copied()
+    }
+}
+
+/// Index prices published by user code.
+///
+/// Clones share the prices, so one can be moved to the task polling
+/// the external feed, while the other is used to update the exchange state.
+#[derive(Clone, Debug, Default)]
+pub struct ExternalSource {
+    prices: Arc<DashMap<types::PerpetualId, IndexPrice>>,
+}
+
+impl ExternalSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Publishes the price of the perpetual contract observed at the given Unix timestamp
+    /// (in seconds). Prices older than the already published one are ignored.
+    pub fn publish(&self, perpetual_id: types::PerpetualId, price: UD64, timestamp: u64) {
+        self.prices
+            .entry(perpetual_id)
+            .and_modify(|current| {
+                if current.timestamp <= timestamp {
+                    *current = IndexPrice { price, timestamp };
+                }
+            })
+            .or_insert(IndexPrice { price, timestamp });
+    }
+
+    /// Removes the price of the perpetual contract, e.g. when the external feed is down.
+    pub fn remove(&self, perpetual_id: types::PerpetualId) {
+        self.prices.remove(&perpetual_id);
+    }
+}
+
+impl IndexPriceSource for ExternalSource {
+    fn index_price(&self, perpetual_id: types::PerpetualId) -> Option<IndexPrice> {
+        self.prices.get(&perpetual_id).map(|p| *p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fastnum::udec64;
+
+    use super::*;
+    use crate::testing::fuzz::Simulator;
+
+    #[test]
+    fn external_index_price() {
+        let mut exchange = Simulator::new(&[1, 2], 1).exchange();
+        let source = ExternalSource::new();
+        source.publish(1, udec64!(1010), 20);
+        source.publish(1, udec64!(1005), 10);
+        assert_eq!(exchange.update_index_prices(&source), 1);
+
+        let perp = &exchange.perpetuals()[&1];
+        assert_eq!(
+            perp.index_price(),
+            Some(IndexPrice {
+                price: udec64!(1010),
+                timestamp: 20
+            })
+        );
+        // On-chain oracle is not used by the simulated contract
+        assert_eq!(perp.reference_price(), Some(udec64!(1010)));
+        assert_eq!(exchange.perpetuals()[&2].index_price(), None);
+
+        // Same price is not updated again
+        assert_eq!(exchange.update_index_prices(&source), 0);
+    }
+}
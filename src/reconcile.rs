@@ -0,0 +1,209 @@
+//! Automatic reconciliation of the event-driven state against fresh snapshots.
+//!
+//! [`run`] periodically builds a shadow snapshot in the background at the instant of the
+//! live [`Exchange`] state, kept up to date with [`Exchange::apply_events`], and reports
+//! the differences with [`Exchange::diff`], so divergence caused by event handling issues
+//! gets noticed. With [`ReconcileConfig::with_hot_swap`], diverged live state gets replaced
+//! with the corrected one, caught up with the events to the live instant first, so
+//! consumers only hold the write lock for the swap itself.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use dex_sdk::reconcile::{self, ReconcileConfig};
+//!
+//! let exchange = Arc::new(RwLock::new(snapshot));
+//! let config = ReconcileConfig::new(Duration::from_secs(600)).with_hot_swap();
+//! let (mut reports, handle) =
+//!     reconcile::run(exchange.clone(), provider, config, tokio::time::sleep);
+//!
+//! tokio::spawn(async move {
+//!     while let Some(report) = reports.recv().await {
+//!         match report {
+//!             Ok(report) if !report.diff.is_empty() => {
+//!                 eprintln!("state diverged at {:?}: {:?}", report.instant, report.diff)
+//!             }
+//!             Ok(_) => {}
+//!             Err(err) => eprintln!("reconciliation failed: {err}"),
+//!         }
+//!     }
+//! });
+//!
+//! // Event-apply loop keeps going as usual
+//! while let Some(block_events) = stream.next().await {
+//!     exchange.write().await.apply_events(&block_events?)?;
+//! }
+//! ```
+
+use std::{sync::Arc, time::Duration};
+
+use alloy::{eips::BlockId, providers::Provider};
+use futures::StreamExt;
+use tokio::sync::{RwLock, mpsc};
+
+use crate::{
+    error::DexError,
+    state::{AccountTracking, Exchange, SnapshotBuilder, StateDiff},
+    stream, types,
+};
+
+/// Default channel buffer size.
+const DEFAULT_CHANNEL_SIZE: usize = 16;
+
+/// Configuration of the reconciliation job.
+#[derive(Clone, Copy, Debug)]
+pub struct ReconcileConfig {
+    interval: Duration,
+    hot_swap: bool,
+}
+
+impl ReconcileConfig {
+    /// Reconcile the state every `interval`, only reporting the divergence.
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            hot_swap: false,
+        }
+    }
+
+    /// Replace the diverged live state with the corrected one.
+    pub fn with_hot_swap(mut self) -> Self {
+        self.hot_swap = true;
+        self
+    }
+}
+
+/// Outcome of a single reconciliation.
+#[derive(Clone, Debug)]
+pub struct Report {
+    /// Instant the live state was compared at.
+    pub instant: types::StateInstant,
+
+    /// Differences of the shadow snapshot from the live state,
+    /// see [`Exchange::diff`].
+    pub diff: StateDiff,
+
+    /// Instant the live state got replaced at with the corrected one,
+    /// if hot swap is enabled and the state diverged.
+    pub swapped_at: Option<types::StateInstant>,
+}
+
+/// Start the reconciliation job of the live state.
+///
+/// Shadow snapshots track the same perpetual contracts and accounts as the live state.
+/// Failed reconciliations get reported as errors and retried after the interval.
+///
+/// Job stops once the report receiver is dropped.
+pub fn run<P, S, SFut>(
+    exchange: Arc<RwLock<Exchange>>,
+    provider: P,
+    config: ReconcileConfig,
+    sleep: S,
+) -> (
+    mpsc::Receiver<Result<Report, DexError>>,
+    tokio::task::JoinHandle<()>,
+)
+where
+    P: Provider + Clone + Send + Sync + 'static,
+    S: Fn(Duration) -> SFut + Copy + Send + Sync + 'static,
+    SFut: Future<Output = ()> + Send,
+{
+    let (tx, rx) = mpsc::channel(DEFAULT_CHANNEL_SIZE);
+    let handle = tokio::spawn(async move {
+        loop {
+            sleep(config.interval).await;
+            let result = reconcile(&exchange, &provider, config, sleep).await;
+            if tx.send(result).await.is_err() {
+                // Receiver dropped, graceful shutdown
+                break;
+            }
+        }
+    });
+    (rx, handle)
+}
+
+async fn reconcile<P, S, SFut>(
+    exchange: &RwLock<Exchange>,
+    provider: &P,
+    config: ReconcileConfig,
+    sleep: S,
+) -> Result<Report, DexError>
+where
+    P: Provider + Clone,
+    S: Fn(Duration) -> SFut + Copy,
+    SFut: Future<Output = ()>,
+{
+    let live = exchange.read().await.clone();
+    let shadow = shadow_snapshot(&live, provider.clone()).await?;
+    let diff = live.diff(&shadow);
+    let swapped_at = if config.hot_swap && !diff.is_empty() {
+        Some(swap(exchange, shadow, provider.clone(), sleep).await?)
+    } else {
+        None
+    };
+    Ok(Report {
+        instant: live.instant(),
+        diff,
+        swapped_at,
+    })
+}
+
+async fn shadow_snapshot<P: Provider + Clone>(
+    live: &Exchange,
+    provider: P,
+) -> Result<Exchange, DexError> {
+    let builder = SnapshotBuilder::new(live.chain(), provider)
+        .at_block(BlockId::number(live.instant().block_number()))
+        .with_perpetuals(live.perpetuals().keys().copied().collect());
+    if live.tracks_all_accounts() {
+        builder.with_all_positions()
+    } else {
+        let addresses = |tracking| {
+            live.accounts()
+                .values()
+                .filter(|acc| acc.tracking() == tracking && !acc.address().is_zero())
+                .map(|acc| acc.address())
+                .collect()
+        };
+        builder
+            .with_accounts_full(addresses(AccountTracking::Full))
+            .with_accounts_positions_only(addresses(AccountTracking::PositionsOnly))
+    }
+    .build()
+    .await
+}
+
+/// Catches the corrected state up with the live one and replaces the latter,
+/// returning the instant of the swap.
+async fn swap<P, S, SFut>(
+    exchange: &RwLock<Exchange>,
+    mut corrected: Exchange,
+    provider: P,
+    sleep: S,
+) -> Result<types::StateInstant, DexError>
+where
+    P: Provider,
+    S: Fn(Duration) -> SFut + Copy,
+    SFut: Future<Output = ()>,
+{
+    let from = types::StateInstant::new(corrected.instant().block_number() + 1, 0);
+    let chain = corrected.chain().clone();
+    let events = stream::raw(&chain, provider, from, sleep);
+    futures::pin_mut!(events);
+    loop {
+        let target = exchange.read().await.instant().block_number();
+        while corrected.instant().block_number() < target {
+            let block = events
+                .next()
+                .await
+                .ok_or_else(|| DexError::Fatal("event stream ended".to_string()))??;
+            corrected.apply_events(&block)?;
+        }
+        // Live state may have advanced while catching up
+        let mut live = exchange.write().await;
+        if live.instant().block_number() == corrected.instant().block_number() {
+            live.replace_state(corrected);
+            return Ok(live.instant());
+        }
+    }
+}
@@ -0,0 +1,281 @@
+//! Live order book rendering for terminal tools.
+//!
+//! [`BookRenderer`] keeps the terminal model of the book view: a fixed layout of text rows
+//! with the top of the book next to the spread row in the middle. Every update
+//! diffs the new frame against the previous one and only the changed (dirty) rows get
+//! redrawn with ANSI cursor positioning, so the view does not flicker and unchanged
+//! levels cost nothing to output.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use dex_sdk::render::{BookRenderer, RenderConfig};
+//!
+//! let mut renderer = BookRenderer::new(RenderConfig::default().with_depth(15));
+//! while let Some(block_events) = stream.next().await {
+//!     exchange.apply_events(&block_events?)?;
+//!     print!("{}", renderer.render(&exchange.perpetuals()[&perp_id]));
+//!     std::io::stdout().flush()?;
+//! }
+//! ```
+
+use std::fmt::Write;
+
+use fastnum::{UD64, udec64};
+
+use crate::state::{BookLevel, Perpetual};
+
+/// Default number of price levels rendered on each side of the book.
+pub const DEFAULT_DEPTH: usize = 10;
+
+/// Clears the whole screen.
+const CLEAR_SCREEN: &str = "\x1b[2J";
+
+/// Clears the line from the cursor to the end.
+const CLEAR_LINE: &str = "\x1b[K";
+
+/// Configuration of the [`BookRenderer`].
+#[derive(Clone, Copy, Debug)]
+pub struct RenderConfig {
+    depth: usize,
+    origin_row: u16,
+    clear_screen: bool,
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self {
+            depth: DEFAULT_DEPTH,
+            origin_row: 1,
+            clear_screen: true,
+        }
+    }
+}
+
+impl RenderConfig {
+    /// Render the given number of price levels on each side of the book.
+    pub fn with_depth(mut self, depth: usize) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Render the view starting at the given terminal row, 1-based (default: 1),
+    /// to embed it below other output of the tool.
+    pub fn with_origin_row(mut self, origin_row: u16) -> Self {
+        self.origin_row = origin_row.max(1);
+        self
+    }
+
+    /// Do not clear the whole screen on full redraws, only the rows of the view.
+    pub fn without_clear_screen(mut self) -> Self {
+        self.clear_screen = false;
+        self
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn origin_row(&self) -> u16 {
+        self.origin_row
+    }
+}
+
+/// Diff-based renderer of the perpetual contract order book, see the [module docs](self).
+#[derive(Clone, Debug)]
+pub struct BookRenderer {
+    config: RenderConfig,
+    rows: Vec<String>,
+    stale: bool,
+}
+
+impl BookRenderer {
+    pub fn new(config: RenderConfig) -> Self {
+        Self {
+            config,
+            rows: vec![],
+            stale: true,
+        }
+    }
+
+    /// Rows of the latest rendered frame, top to bottom.
+    pub fn rows(&self) -> &[String] {
+        &self.rows
+    }
+
+    /// Forces the full redraw on the next render, e.g. after the terminal got
+    /// resized or overwritten by other output.
+    pub fn invalidate(&mut self) {
+        self.stale = true;
+    }
+
+    /// Updates the terminal model with the current state of the perpetual contract,
+    /// returning indices of the rows that changed.
+    /// All rows are reported after [`Self::invalidate`] and on the first update.
+    pub fn update(&mut self, perp: &Perpetual) -> Vec<usize> {
+        let frame = self.frame(perp);
+        let dirty = if self.stale || frame.len() != self.rows.len() {
+            (0..frame.len()).collect()
+        } else {
+            frame
+                .iter()
+                .zip(&self.rows)
+                .enumerate()
+                .filter(|(_, (new, old))| new != old)
+                .map(|(row, _)| row)
+                .collect()
+        };
+        self.rows = frame;
+        self.stale = false;
+        dirty
+    }
+
+    /// Updates the terminal model and returns ANSI output redrawing only the dirty rows,
+    /// leaving the cursor on the row below the view.
+    pub fn render(&mut self, perp: &Perpetual) -> String {
+        let full = self.stale;
+        let dirty = self.update(perp);
+        let mut out = String::new();
+        if full && self.config.clear_screen {
+            out.push_str(CLEAR_SCREEN);
+        }
+        let origin = self.config.origin_row as usize;
+        for row in dirty {
+            let _ = write!(
+                out,
+                "\x1b[{};1H{}{CLEAR_LINE}",
+                origin + row,
+                self.rows[row]
+            );
+        }
+        let _ = write!(out, "\x1b[{};1H", origin + self.rows.len());
+        out
+    }
+
+    /// Text rows of the view: header, asks with the best one at the bottom, spread,
+    /// bids with the best one at the top and summary.
+    /// Missing levels are rendered as empty rows to keep the layout stable.
+    fn frame(&self, perp: &Perpetual) -> Vec<String> {
+        let book = perp.l3_book();
+        let depth = self.config.depth;
+        let mut rows = Vec::with_capacity(2 * depth + 4);
+        rows.push(format!(
+            "{} ({}) │ Block: {} │ Last: {} │ Mark: {}",
+            perp.name(),
+            perp.symbol(),
+            perp.instant().block_number(),
+            perp.last_price(),
+            perp.mark_price(),
+        ));
+        rows.push(format!(
+            "{:>20} │ {:>20} │ {:>8} │ {:>20}",
+            "Price", "Size", "Orders", "Cumulative"
+        ));
+
+        let asks = levels(book.asks().iter().map(|(p, l)| (*p, l)), depth);
+        rows.extend(std::iter::repeat_n(String::new(), depth - asks.len()));
+        rows.extend(asks.into_iter().rev());
+
+        rows.push(match (book.best_bid(), book.best_ask()) {
+            (Some((bid, _)), Some((ask, _))) => {
+                let spread = ask - bid;
+                let mid = (ask + bid) / UD64::TWO;
+                format!(
+                    "{:─^78}",
+                    format!(
+                        " Spread: {} ({:.2} bps) ",
+                        spread,
+                        spread / mid * udec64!(10000)
+                    )
+                )
+            }
+            _ => format!("{:─^78}", " No spread "),
+        });
+
+        let bids = levels(book.bids().iter().map(|(p, l)| (p.0, l)), depth);
+        let bid_count = bids.len();
+        rows.extend(bids);
+        rows.extend(std::iter::repeat_n(String::new(), depth - bid_count));
+
+        rows.push(format!(
+            "Asks: {} levels │ Bids: {} levels │ Orders: {}",
+            book.asks().len(),
+            book.bids().len(),
+            book.total_orders(),
+        ));
+        rows
+    }
+}
+
+/// Rows of up to `depth` levels of one side of the book, best first.
+fn levels<'a>(side: impl Iterator<Item = (UD64, &'a BookLevel)>, depth: usize) -> Vec<String> {
+    let mut cumulative = UD64::ZERO;
+    side.take(depth)
+        .map(|(price, level)| {
+            cumulative += level.size();
+            format!(
+                "{:>20} │ {:>20} │ {:>8} │ {:>20}",
+                format!("{price}"),
+                format!("{}", level.size()),
+                level.num_orders(),
+                format!("{cumulative}"),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use fastnum::udec64;
+
+    use super::*;
+    use crate::{
+        state::Order,
+        types::{OrderId, OrderType},
+    };
+
+    fn order(r#type: OrderType, price: UD64, oid: u16) -> Order {
+        Order::for_l3_testing(r#type, price, udec64!(1), 1, OrderId::new(oid).unwrap(), 1)
+    }
+
+    #[test]
+    fn dirty_rows_only() {
+        let mut perp = Perpetual::for_testing(1);
+        let mut renderer = BookRenderer::new(RenderConfig::default().with_depth(2));
+
+        // Header, column names, 2 ask rows, spread, 2 bid rows and summary, all drawn initially
+        let out = renderer.render(&perp);
+        assert_eq!(renderer.rows().len(), 8);
+        assert!(out.starts_with(CLEAR_SCREEN));
+        assert!(out.ends_with("\x1b[9;1H"));
+        assert!(renderer.rows()[4].contains("No spread"));
+
+        // Nothing changed
+        assert!(renderer.update(&perp).is_empty());
+
+        // Best ask goes right above the spread, best bid right below it
+        perp.add_order(order(OrderType::OpenShort, udec64!(101), 1))
+            .unwrap();
+        perp.add_order(order(OrderType::OpenLong, udec64!(99), 2))
+            .unwrap();
+        assert_eq!(renderer.update(&perp), vec![3, 4, 5, 7]);
+        assert!(renderer.rows()[1].contains("Price"));
+        assert!(renderer.rows()[2].is_empty());
+        assert!(renderer.rows()[3].contains("101"));
+        assert!(renderer.rows()[4].contains("Spread: 2"));
+        assert!(renderer.rows()[5].contains("99"));
+        assert!(renderer.rows()[6].is_empty());
+
+        // Deeper bid changes only its own row and the summary
+        perp.add_order(order(OrderType::OpenLong, udec64!(98), 3))
+            .unwrap();
+        let out = renderer.render(&perp);
+        assert!(!out.contains(CLEAR_SCREEN));
+        assert_eq!(out.matches(CLEAR_LINE).count(), 2);
+        assert!(out.contains("\x1b[7;1H"));
+        assert!(out.contains("\x1b[8;1H"));
+
+        renderer.invalidate();
+        assert_eq!(renderer.update(&perp).len(), 8);
+    }
+}
@@ -0,0 +1,7 @@
+//! Risk analysis of the tracked exchange state.
+//!
+//! [`simulator`] replays maintenance margin and liquidation rules locally against
+//! hypothetical mark price paths, to backtest how tracked positions would fare
+//! without touching the chain, or against current prices for margin monitoring.
+
+pub mod simulator;
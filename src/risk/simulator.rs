@@ -0,0 +1,448 @@
+//! Simulated liquidation engine.
+//!
+//! [`simulate`] walks the hypothetical mark price path of each perpetual contract in the
+//! [`Scenario`] step by step, checking tracked positions against their maintenance margin
+//! requirement, the same way the exchange does:
+//! * Position reaching its liquidation price gets liquidated in full.
+//! * If the mark price is already past its bankruptcy price, its deposit does not cover
+//!   the loss, so the position gets deleveraged against opposing positions of the same
+//!   perpetual contract instead, in the order of their unrealized PnL relative to deposit,
+//!   most profitable first. Deleveraged positions get reduced at the bankruptcy price.
+//!
+//! Funding payments, order book liquidity and liquidator behavior are not modeled,
+//! and only tracked accounts are taken into account.
+//!
+//! [`Scenario::current`] checks positions at the current prices instead, optionally using
+//! the depth-weighted fair price of the order book in place of the mark price, see
+//! [`PriceSource`].
+//!
+//! # Example
+//!
+//! ```ignore
+//! use dex_sdk::risk::simulator::{self, Scenario};
+//!
+//! // Mark price of BTC drops by 1% per step
+//! let path = (0..30).map(|i| start * (UD64::ONE - udec64!(0.01) * UD64::from(i as u64)));
+//! let report = simulator::simulate(&exchange, &Scenario::new().with_path(btc, path))?;
+//! for liq in &report.liquidations {
+//!     println!("step {}: account {} liquidated at {}", liq.step, liq.account_id, liq.mark_price);
+//! }
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+
+use fastnum::{D256, UD64, UD128};
+
+use crate::{
+    error::DexError,
+    state::{Exchange, Perpetual, Position, PositionType},
+    types,
+};
+
+/// Hypothetical mark price paths of perpetual contracts.
+///
+/// Paths are aligned by step, a path shorter than the others keeps its last price
+/// for the remaining steps.
+#[derive(Clone, Debug, Default)]
+pub struct Scenario {
+    paths: HashMap<types::PerpetualId, Vec<UD64>>,
+}
+
+impl Scenario {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the mark price path of the perpetual contract.
+    pub fn with_path(
+        mut self,
+        perpetual_id: types::PerpetualId,
+        prices: impl IntoIterator<Item = UD64>,
+    ) -> Self {
+        self.paths
+            .insert(perpetual_id, prices.into_iter().collect());
+        self
+    }
+
+    /// Single step scenario at the current prices of all perpetual contracts,
+    /// taken from the given source, to check which tracked positions are
+    /// liquidatable right now.
+    ///
+    /// Perpetual contracts without a known price are left out.
+    pub fn current(exchange: &Exchange, source: PriceSource) -> Self {
+        Self {
+            paths: exchange
+                .perpetuals()
+                .iter()
+                .filter_map(|(id, perp)| source.price(perp).map(|price| (*id, vec![price])))
+                .collect(),
+        }
+    }
+
+    /// Number of steps in the scenario.
+    pub fn steps(&self) -> usize {
+        self.paths.values().map(Vec::len).max().unwrap_or_default()
+    }
+
+    fn price(&self, perpetual_id: types::PerpetualId, step: usize) -> Option<UD64> {
+        let path = self.paths.get(&perpetual_id)?;
+        path.get(step).or(path.last()).copied()
+    }
+}
+
+/// Source of the current price positions get checked against.
+#[derive(Clone, Copy, derive_more::Debug, PartialEq, Eq)]
+pub enum PriceSource {
+    /// Mark price of the perpetual contract, as used by the exchange.
+    Mark,
+    /// Price of the last trade.
+    Last,
+    /// Depth-weighted fair price of the order book up to the notional on each side,
+    /// see [`Perpetual::fair_price`]. More robust than the last or mark price on thin books.
+    #[debug("Fair({_0})")]
+    Fair(UD128),
+}
+
+impl PriceSource {
+    /// Current price of the perpetual contract, if known.
+    pub fn price(&self, perpetual: &Perpetual) -> Option<UD64> {
+        let price = match self {
+            Self::Mark => perpetual.mark_price(),
+            Self::Last => perpetual.last_price(),
+            Self::Fair(depth_notional) => perpetual.fair_price(*depth_notional)?,
+        };
+        (price > UD64::ZERO).then_some(price)
+    }
+}
+
+/// Reduction of the opposing position to cover the bankrupt one.
+#[derive(Clone, Copy, derive_more::Debug)]
+pub struct Deleveraging {
+    /// ID of the account holding the deleveraged position.
+    pub account_id: types::AccountId,
+
+    /// Size the position gets reduced by.
+    #[debug("{size}")]
+    pub size: UD64,
+
+    /// Size of the position left after the reduction.
+    #[debug("{remaining_size}")]
+    pub remaining_size: UD64,
+}
+
+/// Simulated forced closure of the position.
+#[derive(Clone, derive_more::Debug)]
+pub struct SimulatedLiquidation {
+    /// Step of the scenario the position gets liquidated at.
+    pub step: usize,
+
+    /// ID of the account holding the position.
+    pub account_id: types::AccountId,
+
+    /// ID of the perpetual contract.
+    pub perpetual_id: types::PerpetualId,
+
+    /// Type of the position.
+    pub position_type: PositionType,
+
+    /// Size of the position.
+    #[debug("{size}")]
+    pub size: UD64,
+
+    /// Mark price triggered the liquidation.
+    #[debug("{mark_price}")]
+    pub mark_price: UD64,
+
+    /// Liquidation price of the position before the step.
+    #[debug("{liquidation_price}")]
+    pub liquidation_price: UD64,
+
+    /// Bankruptcy price of the position before the step.
+    #[debug("{bankruptcy_price}")]
+    pub bankruptcy_price: UD64,
+
+    /// Loss not covered by the position deposit at the mark price,
+    /// non-zero for bankrupt positions.
+    #[debug("{shortfall}")]
+    pub shortfall: UD128,
+
+    /// Opposing positions reduced to cover the bankrupt position, in order.
+    /// Empty unless the position is bankrupt.
+    pub deleveraged: Vec<Deleveraging>,
+}
+
+impl SimulatedLiquidation {
+    /// Indicates if the mark price went past the bankruptcy price of the position.
+    pub fn is_bankrupt(&self) -> bool {
+        self.shortfall > UD128::ZERO
+    }
+}
+
+/// Outcome of the simulation.
+#[derive(Clone, Debug, Default)]
+pub struct SimulationReport {
+    /// Liquidated positions, in order of the scenario steps.
+    pub liquidations: Vec<SimulatedLiquidation>,
+
+    /// Positions surviving the scenario, reduced by deleveraging if any.
+    pub surviving: Vec<Position>,
+}
+
+impl SimulationReport {
+    /// Liquidations of the account positions.
+    pub fn account_liquidations(
+        &self,
+        account_id: types::AccountId,
+    ) -> impl Iterator<Item = &SimulatedLiquidation> {
+        self.liquidations
+            .iter()
+            .filter(move |liq| liq.account_id == account_id)
+    }
+}
+
+/// Simulate liquidations of the tracked positions along the scenario mark price paths.
+///
+/// Only positions in perpetual contracts with a path are checked. Fails if the scenario
+/// refers unknown perpetual contracts.
+pub fn simulate(exchange: &Exchange, scenario: &Scenario) -> Result<SimulationReport, DexError> {
+    if let Some(id) = scenario
+        .paths
+        .keys()
+        .find(|id| !exchange.perpetuals().contains_key(id))
+    {
+        return Err(DexError::InvalidRequest(format!("unknown perpetual: {id}")));
+    }
+
+    // Positions by perpetual contract, in a stable order for reproducible results
+    let mut positions: BTreeMap<types::PerpetualId, Vec<Position>> = BTreeMap::new();
+    for acc in exchange.accounts().values() {
+        for pos in acc.positions().values() {
+            if scenario.paths.contains_key(&pos.perpetual_id()) {
+                positions
+                    .entry(pos.perpetual_id())
+                    .or_default()
+                    .push(pos.clone());
+            }
+        }
+    }
+    for perp_positions in positions.values_mut() {
+        perp_positions.sort_by_key(|pos| pos.account_id());
+    }
+
+    let mut report = SimulationReport::default();
+    for step in 0..scenario.steps() {
+        for (perp_id, perp_positions) in positions.iter_mut() {
+            let Some(mark_price) = scenario.price(*perp_id, step) else {
+                continue;
+            };
+            let maintenance_margin = exchange.perpetuals()[perp_id].maintenance_margin();
+            while let Some(idx) = perp_positions
+                .iter()
+                .position(|pos| is_liquidatable(pos, mark_price))
+            {
+                let pos = perp_positions.remove(idx);
+                let liquidation = liquidate(step, pos, mark_price, perp_positions);
+                for reduced in perp_positions.iter_mut() {
+                    reduced.apply_maintenance_margin(reduced.instant(), maintenance_margin);
+                }
+                perp_positions.retain(|pos| pos.size() > UD64::ZERO);
+                report.liquidations.push(liquidation);
+            }
+        }
+    }
+    report.surviving = positions.into_values().flatten().collect();
+    report
+        .surviving
+        .sort_by_key(|pos| (pos.account_id(), pos.perpetual_id()));
+    Ok(report)
+}
+
+fn is_liquidatable(pos: &Position, mark_price: UD64) -> bool {
+    match pos.r#type() {
+        PositionType::Long => mark_price <= pos.liquidation_price(),
+        PositionType::Short => mark_price >= pos.liquidation_price(),
+    }
+}
+
+/// Loss of the position beyond its deposit at the given price.
+fn shortfall(pos: &Position, mark_price: UD64) -> UD128 {
+    let bankruptcy_price = pos.bankruptcy_price();
+    let gap = match pos.r#type() {
+        PositionType::Long if mark_price < bankruptcy_price => bankruptcy_price - mark_price,
+        PositionType::Short if mark_price > bankruptcy_price => mark_price - bankruptcy_price,
+        _ => UD64::ZERO,
+    };
+    gap.resize() * pos.size().resize()
+}
+
+/// Unrealized PnL of the position at the given price relative to its deposit.
+fn pnl_ratio(pos: &Position, price: UD64) -> D256 {
+    let side = if pos.r#type().is_long() {
+        D256::ONE
+    } else {
+        D256::ONE.neg()
+    };
+    let pnl = side
+        * (price.to_signed().resize() - pos.entry_price().to_signed().resize())
+        * pos.size().to_signed().resize()
+        + pos.premium_pnl();
+    if pos.deposit() == UD128::ZERO {
+        return pnl;
+    }
+    pnl / pos.deposit().to_signed().resize()
+}
+
+fn liquidate(
+    step: usize,
+    pos: Position,
+    mark_price: UD64,
+    opposing: &mut [Position],
+) -> SimulatedLiquidation {
+    let shortfall = shortfall(&pos, mark_price);
+    let bankruptcy_price = pos.bankruptcy_price();
+    let mut liquidation = SimulatedLiquidation {
+        step,
+        account_id: pos.account_id(),
+        perpetual_id: pos.perpetual_id(),
+        position_type: pos.r#type(),
+        size: pos.size(),
+        mark_price,
+        liquidation_price: pos.liquidation_price(),
+        bankruptcy_price,
+        shortfall,
+        deleveraged: vec![],
+    };
+    if shortfall == UD128::ZERO {
+        return liquidation;
+    }
+
+    let mut ranked = opposing
+        .iter_mut()
+        .filter(|other| other.r#type() != pos.r#type())
+        .collect::<Vec<_>>();
+    ranked.sort_by(|a, b| {
+        pnl_ratio(b, bankruptcy_price)
+            .cmp(&pnl_ratio(a, bankruptcy_price))
+            .then(a.account_id().cmp(&b.account_id()))
+    });
+
+    let mut remaining = pos.size();
+    for other in ranked {
+        if remaining == UD64::ZERO {
+            break;
+        }
+        let size = remaining.min(other.size());
+        let remaining_size = other.size() - size;
+        let deposit = other.deposit() * remaining_size.resize() / other.size().resize();
+        let instant = other.instant();
+        other.update_deposit(instant, deposit);
+        other.update_size(instant, remaining_size);
+        remaining -= size;
+        liquidation.deleveraged.push(Deleveraging {
+            account_id: other.account_id(),
+            size,
+            remaining_size,
+        });
+    }
+    liquidation
+}
+
+#[cfg(test)]
+mod tests {
+    use fastnum::{udec64, udec128};
+
+    use super::*;
+    use crate::testing::fuzz::{Action, Simulator};
+
+    #[test]
+    fn liquidations_along_path() {
+        let mut sim = Simulator::new(&[1], 3);
+        let mut exchange = sim.exchange();
+        let open = |account, is_long, lots| Action::Open {
+            perpetual: 0,
+            account,
+            is_long,
+            lots,
+        };
+        // Unlevered positions entered at 1000 with maintenance margin of 5%:
+        // longs liquidated at 50 and bankrupt at 0, shorts at 1950 and 2000
+        exchange
+            .apply_events(&sim.block(&[open(0, true, 4), open(1, false, 3), open(2, false, 1)]))
+            .unwrap();
+
+        let scenario = Scenario::new().with_path(1, [udec64!(1000), udec64!(1960), udec64!(2100)]);
+        let report = simulate(&exchange, &scenario).unwrap();
+        let summary = report
+            .liquidations
+            .iter()
+            .map(|liq| (liq.step, liq.account_id, liq.is_bankrupt()))
+            .collect::<Vec<_>>();
+        // Both shorts liquidated at the second step before bankruptcy
+        assert_eq!(summary, vec![(1, 2, false), (1, 3, false)]);
+        assert_eq!(report.liquidations[0].liquidation_price, udec64!(1950));
+        assert_eq!(report.surviving.len(), 1);
+
+        // Gap straight past the bankruptcy price deleverages the long position
+        let scenario = Scenario::new().with_path(1, [udec64!(2100)]);
+        let report = simulate(&exchange, &scenario).unwrap();
+        let first = &report.liquidations[0];
+        assert_eq!(first.account_id, 2);
+        assert_eq!(first.shortfall, udec64!(300).resize());
+        assert_eq!(first.deleveraged.len(), 1);
+        assert_eq!(first.deleveraged[0].account_id, 1);
+        assert_eq!(first.deleveraged[0].size, udec64!(3));
+        assert_eq!(first.deleveraged[0].remaining_size, udec64!(1));
+        // Reduced long covers the remaining short
+        let second = &report.liquidations[1];
+        assert_eq!(second.deleveraged[0].remaining_size, UD64::ZERO);
+        assert!(report.surviving.is_empty());
+
+        assert!(simulate(&exchange, &Scenario::new().with_path(2, [udec64!(1)])).is_err());
+    }
+
+    #[test]
+    fn current_fair_prices() {
+        let mut sim = Simulator::new(&[1], 3);
+        let mut exchange = sim.exchange();
+        let open = |account, is_long, lots| Action::Open {
+            perpetual: 0,
+            account,
+            is_long,
+            lots,
+        };
+        let place = |is_bid, offset| Action::Place {
+            perpetual: 0,
+            account: 0,
+            is_bid,
+            offset,
+            lots: 1,
+        };
+        exchange
+            .apply_events(&sim.block(&[open(0, true, 4), open(1, false, 3), open(2, false, 1)]))
+            .unwrap();
+
+        // Mark price is not known yet
+        let report = simulate(&exchange, &Scenario::current(&exchange, PriceSource::Mark)).unwrap();
+        assert!(report.liquidations.is_empty());
+        assert!(report.surviving.is_empty());
+
+        // Thin book: single lot bid at 1000 and ask at 4000, fair price of 2500
+        // is past the liquidation price of the shorts
+        exchange
+            .apply_events(&sim.block(&[place(true, 0), place(false, 2999)]))
+            .unwrap();
+        let source = PriceSource::Fair(udec128!(100000));
+        assert_eq!(
+            source.price(&exchange.perpetuals()[&1]),
+            Some(udec64!(2500))
+        );
+        let report = simulate(&exchange, &Scenario::current(&exchange, source)).unwrap();
+        let liquidated = report
+            .liquidations
+            .iter()
+            .map(|liq| liq.account_id)
+            .collect::<Vec<_>>();
+        assert_eq!(liquidated, vec![2, 3]);
+        assert_eq!(report.liquidations[0].mark_price, udec64!(2500));
+    }
+}
@@ -0,0 +1,314 @@
+//! Managed event-apply loop with graceful shutdown.
+//!
+//! [`Runtime`] keeps the [`Exchange`] state up to date with [`crate::stream::raw`]
+//! (or [`crate::journal::stream`]) in the background. [`Runtime::shutdown`] stops polling
+//! at the block boundary: the block being fetched is given [`RuntimeConfig::with_drain_timeout`]
+//! to arrive and gets applied, after which the optional shutdown hook can persist the final
+//! state, so services can terminate on SIGTERM without leaving a torn state behind.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use dex_sdk::runtime::{Runtime, RuntimeConfig};
+//!
+//! let config = RuntimeConfig::new()
+//!     .with_journal(Journal::open("events.journal")?)
+//!     .with_on_shutdown(|exchange| save_checkpoint(exchange));
+//! let runtime = Runtime::start(snapshot, provider, config, tokio::time::sleep);
+//! let mut events = runtime.subscribe();
+//!
+//! tokio::signal::ctrl_c().await?;
+//! let instant = runtime.shutdown().await?;
+//! println!("stopped at block {}", instant.block_number());
+//! ```
+
+use std::{sync::Arc, time::Duration};
+
+use alloy::providers::Provider;
+use futures::{Stream, StreamExt};
+use tokio::sync::{RwLock, broadcast};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    error::DexError,
+    journal::{self, Journal},
+    state::{Exchange, StateBlockEvents},
+    stream::{self, RawBlockEvents},
+    types,
+};
+
+/// Capacity of the state events channel, see [`Runtime::subscribe`].
+const EVENTS_CHANNEL_SIZE: usize = 256;
+
+/// Default time to wait for the block being fetched on shutdown.
+const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+type ShutdownHook = Box<dyn FnOnce(&Exchange) -> Result<(), DexError> + Send>;
+
+/// Configuration of the [`Runtime`].
+#[derive(derive_more::Debug)]
+pub struct RuntimeConfig {
+    journal: Option<Journal>,
+    drain_timeout: Duration,
+    #[debug(skip)]
+    on_shutdown: Option<ShutdownHook>,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        Self {
+            journal: None,
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+            on_shutdown: None,
+        }
+    }
+}
+
+impl RuntimeConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replay and record events with the journal, see [`journal::stream`].
+    pub fn with_journal(mut self, journal: Journal) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Time to wait for the block being fetched when shutting down (default: 5s).
+    /// The block is dropped if it does not arrive in time, and gets fetched again
+    /// after restart.
+    pub fn with_drain_timeout(mut self, drain_timeout: Duration) -> Self {
+        self.drain_timeout = drain_timeout;
+        self
+    }
+
+    /// Sets the hook called with the final state on shutdown, e.g. to persist it.
+    /// Hook failure is returned by [`Runtime::shutdown`].
+    pub fn with_on_shutdown(
+        mut self,
+        hook: impl FnOnce(&Exchange) -> Result<(), DexError> + Send + 'static,
+    ) -> Self {
+        self.on_shutdown = Some(Box::new(hook));
+        self
+    }
+}
+
+/// Background event-apply loop of the exchange state.
+#[derive(Debug)]
+pub struct Runtime {
+    exchange: Arc<RwLock<Exchange>>,
+    events: broadcast::Sender<Arc<StateBlockEvents>>,
+    shutdown: CancellationToken,
+    handle: tokio::task::JoinHandle<Result<types::StateInstant, DexError>>,
+}
+
+impl Runtime {
+    /// Start applying events to the state, starting from the block following its instant.
+    pub fn start<P, S, SFut>(
+        exchange: Exchange,
+        provider: P,
+        config: RuntimeConfig,
+        sleep: S,
+    ) -> Self
+    where
+        P: Provider + Send + Sync + 'static,
+        S: Fn(Duration) -> SFut + Copy + Send + Sync + 'static,
+        SFut: Future<Output = ()> + Send + 'static,
+    {
+        let chain = exchange.chain().clone();
+        let from = exchange.instant();
+        let RuntimeConfig {
+            journal,
+            drain_timeout,
+            on_shutdown,
+        } = config;
+        let loop_config = LoopConfig {
+            drain_timeout,
+            on_shutdown,
+        };
+        Self::spawn(exchange, move |state| async move {
+            match journal {
+                Some(journal) => {
+                    let stream = journal::stream(&chain, provider, from, journal, sleep);
+                    run(state, stream, loop_config, sleep).await
+                }
+                None => {
+                    let stream = stream::raw(&chain, provider, from, sleep);
+                    run(state, stream, loop_config, sleep).await
+                }
+            }
+        })
+    }
+
+    fn spawn<F, Fut>(exchange: Exchange, run: F) -> Self
+    where
+        F: FnOnce(LoopState) -> Fut,
+        Fut: Future<Output = Result<types::StateInstant, DexError>> + Send + 'static,
+    {
+        let exchange = Arc::new(RwLock::new(exchange));
+        let (events, _) = broadcast::channel(EVENTS_CHANNEL_SIZE);
+        let shutdown = CancellationToken::new();
+        let state = LoopState {
+            exchange: exchange.clone(),
+            events: events.clone(),
+            shutdown: shutdown.clone(),
+        };
+        let handle = tokio::spawn(run(state));
+        Self {
+            exchange,
+            events,
+            shutdown,
+            handle,
+        }
+    }
+
+    /// Tracked exchange state, kept up to date until shutdown.
+    pub fn state(&self) -> &Arc<RwLock<Exchange>> {
+        &self.exchange
+    }
+
+    /// Subscribe to the state events of the applied blocks.
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<StateBlockEvents>> {
+        self.events.subscribe()
+    }
+
+    /// Indicates if the apply loop has stopped, either on shutdown or failure.
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Stop polling, apply the block in flight if it arrives within the drain timeout,
+    /// run the shutdown hook and return the final state instant.
+    ///
+    /// Returns the error the apply loop failed with, if any, in which case the shutdown
+    /// hook is not called.
+    pub async fn shutdown(self) -> Result<types::StateInstant, DexError> {
+        self.shutdown.cancel();
+        self.handle
+            .await
+            .map_err(|err| DexError::Fatal(format!("runtime task failed: {err}")))?
+    }
+}
+
+/// Shared state of the runtime and its apply loop.
+struct LoopState {
+    exchange: Arc<RwLock<Exchange>>,
+    events: broadcast::Sender<Arc<StateBlockEvents>>,
+    shutdown: CancellationToken,
+}
+
+struct LoopConfig {
+    drain_timeout: Duration,
+    on_shutdown: Option<ShutdownHook>,
+}
+
+async fn run<St, S, SFut>(
+    state: LoopState,
+    stream: St,
+    config: LoopConfig,
+    sleep: S,
+) -> Result<types::StateInstant, DexError>
+where
+    St: Stream<Item = Result<RawBlockEvents, DexError>>,
+    S: Fn(Duration) -> SFut + Copy,
+    SFut: Future<Output = ()>,
+{
+    futures::pin_mut!(stream);
+    loop {
+        let block_events = tokio::select! {
+            biased;
+            block_events = stream.next() => block_events,
+            _ = state.shutdown.cancelled() => break,
+        };
+        let Some(block_events) = block_events else {
+            break;
+        };
+        apply(&state, block_events?).await?;
+    }
+
+    // Drain the block in flight, not polling for new ones
+    if state.shutdown.is_cancelled() {
+        tokio::select! {
+            biased;
+            Some(block_events) = stream.next() => apply(&state, block_events?).await?,
+            _ = sleep(config.drain_timeout) => (),
+        }
+    }
+
+    let exchange = state.exchange.read().await;
+    if let Some(hook) = config.on_shutdown {
+        hook(&exchange)?;
+    }
+    Ok(exchange.instant())
+}
+
+async fn apply(state: &LoopState, block_events: RawBlockEvents) -> Result<(), DexError> {
+    if let Some(state_events) = state.exchange.write().await.apply_events(&block_events)? {
+        // No subscribers is not an error
+        let _ = state.events.send(Arc::new(state_events));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::testing::fuzz::{Action, Simulator};
+
+    #[tokio::test]
+    async fn shutdown_drains_block_in_flight() {
+        let mut sim = Simulator::new(&[1], 2);
+        let exchange = sim.exchange();
+        let open = Action::Open {
+            perpetual: 0,
+            account: 0,
+            is_long: true,
+            lots: 1,
+        };
+        let first = sim.block(&[open]);
+        let in_flight = sim.block(&[]);
+        let in_flight_block = in_flight.instant().block_number();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        tx.send(first).unwrap();
+        let blocks = futures::stream::poll_fn(move |cx| rx.poll_recv(cx)).map(Ok);
+        let (release, released) = tokio::sync::oneshot::channel::<()>();
+        let persisted = Arc::new(Mutex::new(None));
+        let config = LoopConfig {
+            drain_timeout: Duration::from_secs(10),
+            on_shutdown: Some(Box::new({
+                let persisted = persisted.clone();
+                move |exchange: &Exchange| {
+                    *persisted.lock().unwrap() = Some(exchange.instant().block_number());
+                    Ok(())
+                }
+            })),
+        };
+        let runtime = Runtime::spawn(exchange, move |state| {
+            run(state, blocks, config, tokio::time::sleep)
+        });
+        let mut events = runtime.subscribe();
+        events.recv().await.unwrap();
+        assert_eq!(
+            runtime.state().read().await.accounts()[&1]
+                .positions()
+                .len(),
+            1
+        );
+
+        tokio::spawn(async move {
+            let _ = released.await;
+            tx.send(in_flight).unwrap();
+        });
+        // Release the block in flight once the loop stopped polling
+        let shutdown = tokio::spawn(runtime.shutdown());
+        tokio::task::yield_now().await;
+        release.send(()).unwrap();
+        let instant = shutdown.await.unwrap().unwrap();
+        assert_eq!(instant.block_number(), in_flight_block);
+        assert_eq!(*persisted.lock().unwrap(), Some(in_flight_block));
+    }
+}
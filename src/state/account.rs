@@ -4,7 +4,20 @@ use crate::{
     types,
 };
 use alloy::primitives::{Address, U256};
-use fastnum::UD128;
+use fastnum::{D128, D256, UD128};
+
+/// Granularity of the account state tracking, see [`SnapshotBuilder::with_accounts_full`]
+/// and [`SnapshotBuilder::with_accounts_positions_only`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AccountTracking {
+    /// Balances and positions are tracked.
+    #[default]
+    Full,
+
+    /// Only positions are tracked, balances stay zero and their updates are ignored,
+    /// along with the corresponding state events.
+    PositionsOnly,
+}
 
 /// Exchange account.
 #[derive(Clone, derive_more::Debug)]
@@ -12,12 +25,55 @@ pub struct Account {
     instant: types::StateInstant,
     id: types::AccountId,
     address: Address,
+    tracking: AccountTracking,
     #[debug("{balance}")]
     balance: UD128, // SC allocates 80 bits
     #[debug("{locked_balance}")]
     locked_balance: UD128, // SC allocates 80 bits
-    frozen: bool,
+    #[debug(skip)]
+    last_balance_change: D128,
+    frozen_since: Option<types::StateInstant>,
+    freeze_status: types::FreezeStatus,
+    order_forwarding: Option<bool>,
     positions: HashMap<types::PerpetualId, Position>,
+    #[debug(skip)]
+    margin: MarginAggregates,
+}
+
+/// Maintenance margin requirement and equity of the positions, along with the
+/// contribution of each position, so that a single position change is accounted
+/// without iterating the rest.
+#[derive(Clone, Debug, Default)]
+struct MarginAggregates {
+    maintenance_margin: UD128,
+    position_equity: D256,
+    contributions: HashMap<types::PerpetualId, (UD128, D256)>,
+}
+
+impl MarginAggregates {
+    fn from_positions(positions: &HashMap<types::PerpetualId, Position>) -> Self {
+        let mut margin = Self::default();
+        for (perp_id, pos) in positions {
+            margin.update(*perp_id, Some(pos));
+        }
+        margin
+    }
+
+    fn update(&mut self, perpetual_id: types::PerpetualId, position: Option<&Position>) {
+        if let Some((maintenance_margin, equity)) = self.contributions.remove(&perpetual_id) {
+            self.maintenance_margin -= maintenance_margin;
+            self.position_equity -= equity;
+        }
+        if let Some(pos) = position {
+            let contribution = (
+                pos.maintenance_margin_requirement(),
+                pos.deposit().to_signed().resize() + pos.pnl(),
+            );
+            self.maintenance_margin += contribution.0;
+            self.position_equity += contribution.1;
+            self.contributions.insert(perpetual_id, contribution);
+        }
+    }
 }
 
 impl Account {
@@ -27,14 +83,30 @@ impl Account {
         info: &AccountInfo,
         positions: HashMap<types::PerpetualId, Position>,
         collateral_converter: num::Converter,
+        tracking: AccountTracking,
     ) -> Self {
+        let is_full = tracking == AccountTracking::Full;
         Self {
             instant,
             id,
             address: info.accountAddr,
-            balance: collateral_converter.from_unsigned(info.balanceCNS),
-            locked_balance: collateral_converter.from_unsigned(info.lockedBalanceCNS),
-            frozen: info.frozen != 0,
+            tracking,
+            balance: if is_full {
+                collateral_converter.from_unsigned(info.balanceCNS)
+            } else {
+                UD128::ZERO
+            },
+            locked_balance: if is_full {
+                collateral_converter.from_unsigned(info.lockedBalanceCNS)
+            } else {
+                UD128::ZERO
+            },
+            // Actual freeze instant is not available from the snapshot
+            last_balance_change: D128::ZERO,
+            frozen_since: (info.frozen != 0).then_some(instant),
+            freeze_status: info.frozen.into(),
+            order_forwarding: None,
+            margin: MarginAggregates::from_positions(&positions),
             positions,
         }
     }
@@ -48,10 +120,15 @@ impl Account {
             instant,
             id,
             address,
+            tracking: AccountTracking::Full,
             balance: UD128::ZERO,
             locked_balance: UD128::ZERO,
-            frozen: false,
+            last_balance_change: D128::ZERO,
+            frozen_since: None,
+            freeze_status: types::FreezeStatus::NotFrozen,
+            order_forwarding: None,
             positions: HashMap::new(),
+            margin: MarginAggregates::default(),
         }
     }
 
@@ -63,9 +140,14 @@ impl Account {
             instant,
             id: account_id,
             address: Address::ZERO,
+            tracking: AccountTracking::Full,
             balance: UD128::ZERO,
             locked_balance: UD128::ZERO,
-            frozen: false,
+            last_balance_change: D128::ZERO,
+            frozen_since: None,
+            freeze_status: types::FreezeStatus::NotFrozen,
+            order_forwarding: None,
+            margin: MarginAggregates::from_positions(&positions),
             positions,
         }
     }
@@ -85,8 +167,14 @@ impl Account {
         self.address
     }
 
+    /// Granularity of the account state tracking.
+    pub fn tracking(&self) -> AccountTracking {
+        self.tracking
+    }
+
     /// The current balance of collateral tokens in this account,
     /// not including any open positions.
+    /// Always zero for accounts with [`AccountTracking::PositionsOnly`].
     pub fn balance(&self) -> UD128 {
         self.balance
     }
@@ -101,7 +189,25 @@ impl Account {
 
     /// Indicator of the account being frozen.
     pub fn frozen(&self) -> bool {
-        self.frozen
+        self.frozen_since.is_some()
+    }
+
+    /// Freeze status of the account as set by the administrator.
+    pub fn freeze_status(&self) -> types::FreezeStatus {
+        self.freeze_status
+    }
+
+    /// Instant the account got frozen at, if currently frozen.
+    /// For accounts already frozen at the snapshot, it is the snapshot instant.
+    pub fn frozen_since(&self) -> Option<types::StateInstant> {
+        self.frozen_since
+    }
+
+    /// Whether the account permits the exchange administrator to forward orders
+    /// on its behalf, `None` until the first `OrderForwardingUpdated` event,
+    /// as the snapshot does not provide it.
+    pub fn order_forwarding(&self) -> Option<bool> {
+        self.order_forwarding
     }
 
     /// Positions the account has, up to one per each perpetual contract.
@@ -109,28 +215,111 @@ impl Account {
         &self.positions
     }
 
-    pub(crate) fn update_frozen(&mut self, instant: types::StateInstant, frozen: bool) {
-        self.frozen = frozen;
+    /// Total maintenance margin requirement of the positions, kept up to date
+    /// with the position changes and maintenance margin updates.
+    pub fn maintenance_margin(&self) -> UD128 {
+        self.margin.maintenance_margin
+    }
+
+    /// Balance plus deposits and unrealized PnL of the positions at the mark price.
+    /// Includes only the positions for accounts with [`AccountTracking::PositionsOnly`].
+    pub fn equity(&self) -> D256 {
+        self.balance.to_signed().resize() + self.margin.position_equity
+    }
+
+    /// Ratio of the equity to the maintenance margin requirement, the account
+    /// is subject to liquidation once it falls to 1 or below.
+    ///
+    /// Returns `None` if the account has no maintenance margin requirement.
+    pub fn health_ratio(&self) -> Option<D256> {
+        let maintenance_margin = self.margin.maintenance_margin;
+        (maintenance_margin > UD128::ZERO)
+            .then(|| self.equity() / maintenance_margin.to_signed().resize())
+    }
+
+    pub(crate) fn update_address(&mut self, address: Address) {
+        self.address = address;
+    }
+
+    pub(crate) fn update_freeze_status(
+        &mut self,
+        instant: types::StateInstant,
+        status: types::FreezeStatus,
+    ) {
+        self.freeze_status = status;
+        self.frozen_since = if status.is_frozen() {
+            self.frozen_since.or(Some(instant))
+        } else {
+            None
+        };
         self.instant = instant;
     }
 
-    pub(crate) fn update_balance(&mut self, instant: types::StateInstant, balance: UD128) {
-        self.balance = balance;
+    pub(crate) fn update_order_forwarding(&mut self, instant: types::StateInstant, allowed: bool) {
+        self.order_forwarding = Some(allowed);
         self.instant = instant;
     }
 
+    /// Fails if the account is frozen, as the exchange rejects its orders.
+    pub(crate) fn ensure_not_frozen(&self) -> Result<(), DexError> {
+        match self.frozen_since {
+            Some(since) => Err(DexError::AccountFrozen(self.id, since.block_number())),
+            None => Ok(()),
+        }
+    }
+
+    /// Indicates if balance updates are tracked.
+    pub(crate) fn tracks_balances(&self) -> bool {
+        self.tracking == AccountTracking::Full
+    }
+
+    pub(crate) fn update_balance(&mut self, instant: types::StateInstant, balance: UD128) {
+        if self.tracks_balances() {
+            self.last_balance_change = balance.to_signed() - self.balance.to_signed();
+            self.balance = balance;
+            self.instant = instant;
+        }
+    }
+
+    /// Change of the balance by the last [`Self::update_balance`].
+    pub(crate) fn last_balance_change(&self) -> D128 {
+        self.last_balance_change
+    }
+
     pub(crate) fn update_locked_balance(
         &mut self,
         instant: types::StateInstant,
         locked_balance: UD128,
     ) {
-        self.locked_balance = locked_balance;
-        self.instant = instant;
+        if self.tracks_balances() {
+            self.locked_balance = locked_balance;
+            self.instant = instant;
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn track_positions_only(&mut self) {
+        self.tracking = AccountTracking::PositionsOnly;
+        self.balance = UD128::ZERO;
+        self.locked_balance = UD128::ZERO;
     }
 
     pub(crate) fn positions_mut(&mut self) -> &mut HashMap<types::PerpetualId, position::Position> {
         &mut self.positions
     }
+
+    pub(crate) fn insert_position(&mut self, position: Position) {
+        let perp_id = position.perpetual_id();
+        self.positions.insert(perp_id, position);
+        self.refresh_position_margin(perp_id);
+    }
+
+    /// Accounts the change of the position, or its removal, in the margin aggregates.
+    /// Has to be called after every change of the position made via [`Self::positions_mut`].
+    pub(crate) fn refresh_position_margin(&mut self, perpetual_id: types::PerpetualId) {
+        self.margin
+            .update(perpetual_id, self.positions.get(&perpetual_id));
+    }
 }
 
 /// Returns IDs of perpetuals with positions according to [`PositionBitMap`].
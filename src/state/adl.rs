@@ -0,0 +1,244 @@
+//! Auto-deleveraging (ADL) awareness.
+//!
+//! The exchange deleverages a position against the opposing positions provided in
+//! the `autoDeleverage` call, reported with `PositionDeleveraged` events, see
+//! [`super::PositionEventType::Deleveraged`]. Deleveraging attempts that found
+//! no opposing positions are tracked as [`DeleverageFailure`].
+
+use fastnum::{D256, UD64};
+
+use super::{Exchange, Position, PositionType};
+use crate::types;
+
+/// Number of buckets of the ADL indicator, as displayed by centralized venues.
+const ADL_INDICATOR_BUCKETS: usize = 5;
+
+/// Auto-deleveraging attempt rejected by the exchange,
+/// see [`super::Perpetual::last_deleverage_failure`].
+#[derive(Clone, Copy, PartialEq, Eq, derive_more::Debug)]
+pub struct DeleverageFailure {
+    /// Instant of the block the failure was reported in.
+    pub instant: types::StateInstant,
+
+    /// Account of the position to be deleveraged.
+    pub account_id: types::AccountId,
+
+    /// Reason of the failure.
+    pub kind: DeleverageFailureKind,
+}
+
+/// Reason of the auto-deleveraging failure along with the reported details.
+#[derive(Clone, Copy, PartialEq, Eq, derive_more::Debug)]
+pub enum DeleverageFailureKind {
+    /// None of the provided positions oppose the position to be deleveraged
+    /// (`CantDeleverageAgainstOpposingPositions`).
+    NoOpposingPositions {
+        force_close: bool,
+        r#type: PositionType,
+        #[debug("{price}")]
+        price: UD64,
+        /// Number of the provided candidate positions.
+        candidates: usize,
+    },
+
+    /// No candidate positions were provided (`DeleveragePositionListEmpty`).
+    EmptyPositionList,
+}
+
+/// Estimated auto-deleveraging priority of the position among the tracked positions
+/// on the same side of the perpetual contract, see [`Position::adl_rank_estimate`].
+#[derive(Clone, Copy, PartialEq, Eq, derive_more::Debug)]
+pub struct AdlRank {
+    /// Rank of the position, starting from 1 for the first to be deleveraged.
+    pub rank: usize,
+
+    /// Number of the ranked positions.
+    pub of: usize,
+
+    /// Ranking score, PnL ratio multiplied by effective leverage for profitable
+    /// positions and divided by it for losing ones.
+    #[debug("{score}")]
+    pub score: D256,
+
+    /// Risk indicator from 1 (lowest) to 5 (highest), the quintile of the rank.
+    pub indicator: u8,
+}
+
+impl Position {
+    /// Estimates the auto-deleveraging priority of the position, ranking it among
+    /// the positions of the same side tracked by the exchange state.
+    ///
+    /// Uses the ranking of centralized venues, profitable and highly leveraged
+    /// positions being deleveraged first, as the exchange leaves the choice of
+    /// opposing positions to the `autoDeleverage` caller.
+    /// Accurate only if all positions of the perpetual contract are tracked,
+    /// see [`super::SnapshotBuilder::with_all_positions`].
+    ///
+    /// Returns `None` if the perpetual contract is unknown or the position has no equity.
+    pub fn adl_rank_estimate(&self, exchange: &Exchange) -> Option<AdlRank> {
+        let mark_price = exchange
+            .perpetuals()
+            .get(&self.perpetual_id())?
+            .mark_price();
+        let score = adl_score(self, mark_price)?;
+        let (positions, _) = exchange.positions_by_perpetual(self.perpetual_id());
+        let (mut of, mut ahead) = (0, 0);
+        for other in positions.filter(|pos| pos.r#type() == self.r#type()) {
+            let Some(other_score) = adl_score(other, mark_price) else {
+                continue;
+            };
+            of += 1;
+            if other_score > score
+                || (other_score == score && other.account_id() < self.account_id())
+            {
+                ahead += 1;
+            }
+        }
+        // The position itself is not yet indexed if tracked outside the exchange state
+        of = of.max(ahead + 1);
+        Some(AdlRank {
+            rank: ahead + 1,
+            of,
+            score,
+            indicator: (ADL_INDICATOR_BUCKETS - ahead * ADL_INDICATOR_BUCKETS / of) as u8,
+        })
+    }
+}
+
+/// ADL ranking score of the position at the mark price, `None` if the position
+/// has no equity to rank by.
+fn adl_score(position: &Position, mark_price: UD64) -> Option<D256> {
+    let deposit = position.deposit().resize().to_signed();
+    let equity = deposit + position.pnl();
+    if deposit <= D256::ZERO || equity <= D256::ZERO {
+        return None;
+    }
+    let pnl_ratio = position.pnl() / deposit;
+    let notional = (position.size().resize() * mark_price.resize()).to_signed();
+    let leverage = notional / equity;
+    Some(if pnl_ratio >= D256::ZERO {
+        pnl_ratio * leverage
+    } else if leverage > D256::ZERO {
+        pnl_ratio / leverage
+    } else {
+        pnl_ratio
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::{Address, TxHash, U256};
+    use fastnum::udec64;
+
+    use super::*;
+    use crate::{
+        abi::dex::Exchange::{self as Abi, ExchangeEvents},
+        stream,
+        testing::sim::Simulator,
+    };
+
+    fn apply(exchange: &mut Exchange, block: u64, events: Vec<ExchangeEvents>) {
+        let events = events
+            .into_iter()
+            .enumerate()
+            .map(|(i, e)| stream::RawEvent::new(TxHash::ZERO, 0, i as u64, Address::ZERO, e))
+            .collect();
+        exchange
+            .apply_events(&stream::RawBlockEvents::new(
+                types::StateInstant::new(block, block),
+                events,
+            ))
+            .unwrap();
+    }
+
+    fn open(account_id: u64, is_long: bool, deposit: u64) -> ExchangeEvents {
+        ExchangeEvents::PositionOpened(Abi::PositionOpened {
+            perpId: U256::from(1),
+            accountId: U256::from(account_id),
+            positionType: if is_long { 0 } else { 1 },
+            leverageHdths: U256::from(100),
+            depositCNS: U256::from(deposit),
+            pricePNS: U256::from(1000),
+            lotLNS: U256::from(1),
+        })
+    }
+
+    #[test]
+    fn ranks_profitable_leveraged_first() {
+        let mut exchange = Simulator::new(&[1], 4).exchange();
+        apply(
+            &mut exchange,
+            2,
+            vec![
+                open(1, true, 1000),
+                open(2, true, 500),
+                open(3, true, 250),
+                open(4, false, 1000),
+                ExchangeEvents::MarkUpdated(Abi::MarkUpdated {
+                    perpId: U256::from(1),
+                    pricePNS: U256::from(1100),
+                }),
+            ],
+        );
+
+        let rank = |account_id| {
+            exchange.accounts()[&account_id].positions()[&1]
+                .adl_rank_estimate(&exchange)
+                .unwrap()
+        };
+        assert_eq!(
+            [1, 2, 3].map(|id| (rank(id).rank, rank(id).of, rank(id).indicator)),
+            [(3, 3, 2), (2, 3, 4), (1, 3, 5)]
+        );
+        assert!(rank(3).score > rank(2).score);
+
+        // Losing short ranks alone on its side
+        let short = rank(4);
+        assert_eq!((short.rank, short.of), (1, 1));
+        assert!(short.score < D256::ZERO);
+    }
+
+    #[test]
+    fn records_deleverage_failures() {
+        let mut exchange = Simulator::new(&[1], 2).exchange();
+        apply(
+            &mut exchange,
+            2,
+            vec![ExchangeEvents::CantDeleverageAgainstOpposingPositions(
+                Abi::CantDeleverageAgainstOpposingPositions {
+                    perpId: U256::from(1),
+                    accountId: U256::from(1),
+                    forceClose: false,
+                    positionType: 0,
+                    deleveragePricePNS: U256::from(1050),
+                    sortedPositionIds: vec![U256::from(2), U256::from(3)],
+                },
+            )],
+        );
+        let failure = exchange.perpetuals()[&1].last_deleverage_failure().unwrap();
+        assert_eq!(failure.account_id, 1);
+        assert_eq!(
+            failure.kind,
+            DeleverageFailureKind::NoOpposingPositions {
+                force_close: false,
+                r#type: PositionType::Long,
+                price: udec64!(1050),
+                candidates: 2,
+            }
+        );
+
+        apply(
+            &mut exchange,
+            3,
+            vec![ExchangeEvents::DeleveragePositionListEmpty(
+                Abi::DeleveragePositionListEmpty {
+                    perpId: U256::from(1),
+                    accountId: U256::from(2),
+                },
+            )],
+        );
+        let failure = exchange.perpetuals()[&1].last_deleverage_failure().unwrap();
+        assert_eq!(failure.instant.block_number(), 3);
+        assert_eq!(failure.kind, DeleverageFailureKind::EmptyPositionList);
+    }
+}
@@ -0,0 +1,159 @@
+use std::{collections::BTreeMap, io, path::Path};
+
+use alloy::primitives::Address;
+#[cfg(feature = "rpc")]
+use alloy::{providers::Provider, rpc::types::Filter, sol_types::SolEvent};
+
+use crate::types;
+#[cfg(feature = "rpc")]
+use crate::{abi::dex::Exchange::AccountCreated, error::DexError};
+
+/// Number of blocks to scan for `AccountCreated` logs with a single request.
+#[cfg(feature = "rpc")]
+const BLOCKS_PER_SCAN: u64 = 1000;
+
+/// Mapping of account IDs to addresses collected from `AccountCreated` logs,
+/// see [`super::Exchange::backfill_account_addresses`].
+///
+/// Can be persisted with [`Self::save`] and restored with [`Self::load`] to avoid
+/// re-scanning already scanned blocks across restarts.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccountAddresses {
+    addresses: BTreeMap<types::AccountId, Address>,
+    scanned_to_block: Option<u64>,
+}
+
+impl AccountAddresses {
+    /// Address of the account, if known.
+    pub fn get(&self, account_id: types::AccountId) -> Option<Address> {
+        self.addresses.get(&account_id).copied()
+    }
+
+    /// All known account addresses.
+    pub fn iter(&self) -> impl Iterator<Item = (types::AccountId, Address)> + '_ {
+        self.addresses.iter().map(|(id, addr)| (*id, *addr))
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    /// Last block scanned for `AccountCreated` logs, scanning can be
+    /// continued from the next one.
+    pub fn scanned_to_block(&self) -> Option<u64> {
+        self.scanned_to_block
+    }
+
+    /// Merges other mapping into this one.
+    pub fn extend(&mut self, other: AccountAddresses) {
+        self.addresses.extend(other.addresses);
+        self.scanned_to_block = self.scanned_to_block.max(other.scanned_to_block);
+    }
+
+    /// Stores the mapping as text: the last scanned block on the first line,
+    /// then one `<account id> <address>` pair per line.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut text = format!(
+            "{}\n",
+            self.scanned_to_block
+                .map(|b| b.to_string())
+                .unwrap_or_default()
+        );
+        for (id, addr) in &self.addresses {
+            text.push_str(&format!("{id} {addr}\n"));
+        }
+        std::fs::write(path, text)
+    }
+
+    /// Loads the mapping stored with [`Self::save`].
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let invalid = |line: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid account address line: {line}"),
+            )
+        };
+        let text = std::fs::read_to_string(path)?;
+        let mut lines = text.lines();
+        let scanned_to_block = match lines.next().map(str::trim) {
+            None | Some("") => None,
+            Some(line) => Some(line.parse().map_err(|_| invalid(line))?),
+        };
+        let addresses = lines
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let (id, addr) = line.trim().split_once(' ').ok_or_else(|| invalid(line))?;
+                Ok((
+                    id.parse().map_err(|_| invalid(line))?,
+                    addr.parse().map_err(|_| invalid(line))?,
+                ))
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Self {
+            addresses,
+            scanned_to_block,
+        })
+    }
+
+    pub(crate) fn insert(&mut self, account_id: types::AccountId, address: Address) {
+        self.addresses.insert(account_id, address);
+    }
+}
+
+#[cfg(feature = "rpc")]
+/// Scans `AccountCreated` logs of the exchange within the given block range (inclusive),
+/// in chunks of [`BLOCKS_PER_SCAN`] blocks.
+pub(crate) async fn scan_account_addresses<P: Provider>(
+    provider: &P,
+    exchange: Address,
+    from_block: u64,
+    to_block: u64,
+) -> Result<AccountAddresses, DexError> {
+    let mut result = AccountAddresses::default();
+    let mut start = from_block;
+    while start <= to_block {
+        let end = (start + BLOCKS_PER_SCAN - 1).min(to_block);
+        let filter = Filter::new()
+            .address(exchange)
+            .event_signature(AccountCreated::SIGNATURE_HASH)
+            .from_block(start)
+            .to_block(end);
+        for log in provider.get_logs(&filter).await? {
+            let event = AccountCreated::decode_log(&log.inner)
+                .map_err(|err| DexError::Fatal(format!("invalid AccountCreated log: {err}")))?;
+            result.insert(event.id.to(), event.account);
+        }
+        result.scanned_to_block = Some(end);
+        start = end + 1;
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_and_load() {
+        let mut addresses = AccountAddresses::default();
+        addresses.insert(7, Address::repeat_byte(7));
+        addresses.insert(3, Address::repeat_byte(3));
+        addresses.scanned_to_block = Some(1234);
+
+        let path = std::env::temp_dir().join(format!(
+            "dex-sdk-account-addresses-{}.txt",
+            std::process::id()
+        ));
+        addresses.save(&path).unwrap();
+        let loaded = AccountAddresses::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, addresses);
+        assert_eq!(loaded.get(3), Some(Address::repeat_byte(3)));
+        assert_eq!(loaded.scanned_to_block(), Some(1234));
+    }
+}
@@ -0,0 +1,73 @@
+//! Rolling average of the observed block time.
+
+use std::{collections::VecDeque, time::Duration};
+
+use crate::types;
+
+/// Number of most recent blocks the average block time is computed over.
+///
+/// Block timestamps have a resolution of a second, so the window has to span
+/// many blocks for the average to be meaningful with sub-second block times.
+const WINDOW_BLOCKS: usize = 1000;
+
+/// Instants of the recently applied blocks, see [`super::Exchange::observed_block_time`].
+#[derive(Clone, Debug, Default)]
+pub(crate) struct BlockTimes {
+    instants: VecDeque<types::StateInstant>,
+}
+
+impl BlockTimes {
+    pub(crate) fn observe(&mut self, instant: types::StateInstant) {
+        if self.instants.len() == WINDOW_BLOCKS {
+            self.instants.pop_front();
+        }
+        self.instants.push_back(instant);
+    }
+
+    /// Average time between the blocks in the window, `None` until the window spans
+    /// at least a second.
+    pub(crate) fn average(&self) -> Option<Duration> {
+        let (first, last) = (self.instants.front()?, self.instants.back()?);
+        let blocks = last.block_number().checked_sub(first.block_number())?;
+        let seconds = last
+            .block_timestamp()
+            .checked_sub(first.block_timestamp())?;
+        if blocks == 0 || seconds == 0 {
+            return None;
+        }
+        Some(Duration::from_secs(seconds) / blocks as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rolling_average() {
+        let mut times = BlockTimes::default();
+        assert_eq!(times.average(), None);
+
+        // Two and a half blocks per second, same timestamp within a second
+        for block_number in 0..3 {
+            times.observe(types::StateInstant::new(
+                block_number,
+                100 + block_number * 2 / 5,
+            ));
+        }
+        assert_eq!(times.average(), None);
+        for block_number in 3..=10 {
+            times.observe(types::StateInstant::new(
+                block_number,
+                100 + block_number * 2 / 5,
+            ));
+        }
+        assert_eq!(times.average(), Some(Duration::from_millis(400)));
+
+        // Older blocks leave the window
+        for block_number in 11..=(WINDOW_BLOCKS as u64 + 10) {
+            times.observe(types::StateInstant::new(block_number, block_number));
+        }
+        assert_eq!(times.average(), Some(Duration::from_secs(1)));
+    }
+}
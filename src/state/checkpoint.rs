@@ -0,0 +1,164 @@
+//! Bounded ring of exchange state checkpoints.
+
+use std::{collections::VecDeque, sync::Arc};
+
+use super::Exchange;
+use crate::types;
+
+/// Default number of checkpoints to keep.
+pub(crate) const DEFAULT_CHECKPOINTS_CAPACITY: usize = 8;
+
+/// Checkpoints of the exchange state, oldest first.
+///
+/// Checkpoints are shared between clones of the state,
+/// so cloning the [`Exchange`] does not copy them.
+#[derive(Clone, Debug)]
+pub(crate) struct Checkpoints {
+    every_blocks: u64,
+    capacity: usize,
+    ring: VecDeque<Arc<Exchange>>,
+}
+
+impl Checkpoints {
+    pub(crate) fn new(every_blocks: u64, capacity: usize) -> Self {
+        Self {
+            every_blocks,
+            capacity,
+            ring: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Indicates if automatic checkpoint is due at the given instant.
+    pub(crate) fn is_due(&self, instant: types::StateInstant) -> bool {
+        self.every_blocks > 0 && instant.block_number().is_multiple_of(self.every_blocks)
+    }
+
+    pub(crate) fn push(&mut self, state: Exchange) {
+        if self.capacity == 0
+            || self
+                .ring
+                .back()
+                .is_some_and(|last| last.instant() == state.instant())
+        {
+            return;
+        }
+        if self.ring.len() == self.capacity {
+            self.ring.pop_front();
+        }
+        self.ring.push_back(Arc::new(state));
+    }
+
+    /// Removes all checkpoints taken after the latest one at or before the given
+    /// block and returns the latter.
+    pub(crate) fn rewind(&mut self, block_number: u64) -> Option<Arc<Exchange>> {
+        let pos = self
+            .ring
+            .iter()
+            .rposition(|c| c.instant().block_number() <= block_number)?;
+        self.ring.truncate(pos + 1);
+        self.ring.back().cloned()
+    }
+
+    pub(crate) fn instants(&self) -> impl Iterator<Item = types::StateInstant> {
+        self.ring.iter().map(|c| c.instant())
+    }
+}
+
+impl Default for Checkpoints {
+    fn default() -> Self {
+        Self::new(0, DEFAULT_CHECKPOINTS_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{Chain, error::DexError, num, stream};
+
+    fn exchange(every_blocks: u64, capacity: usize) -> Exchange {
+        let mut exchange = Exchange::new(
+            Chain::testnet(),
+            types::StateInstant::new(100, 1000),
+            num::Converter::new(6),
+            1000,
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            HashMap::new(),
+            HashMap::new(),
+            false,
+            false,
+        );
+        exchange.set_checkpoints(Checkpoints::new(every_blocks, capacity));
+        exchange
+    }
+
+    fn advance(exchange: &mut Exchange, to_block: u64) {
+        for block in exchange.instant().block_number() + 1..=to_block {
+            exchange
+                .apply_events(&stream::RawBlockEvents::new(
+                    types::StateInstant::new(block, block * 10),
+                    vec![],
+                ))
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn automatic_checkpoints_are_bounded() {
+        let mut exchange = exchange(10, 3);
+        advance(&mut exchange, 150);
+
+        let blocks = exchange
+            .checkpoints()
+            .map(|i| i.block_number())
+            .collect::<Vec<_>>();
+        assert_eq!(blocks, vec![130, 140, 150]);
+    }
+
+    #[test]
+    fn rollback_restores_latest_checkpoint_before_instant() {
+        let mut exchange = exchange(10, 8);
+        advance(&mut exchange, 135);
+
+        let restored = exchange
+            .rollback_to(types::StateInstant::new(125, 1250))
+            .unwrap();
+        assert_eq!(restored.block_number(), 120);
+        assert_eq!(exchange.instant(), restored);
+        assert_eq!(
+            exchange
+                .checkpoints()
+                .map(|i| i.block_number())
+                .collect::<Vec<_>>(),
+            vec![110, 120]
+        );
+
+        // Events get re-applied on top of the restored state
+        advance(&mut exchange, 130);
+        assert_eq!(exchange.instant().block_number(), 130);
+        assert_eq!(exchange.checkpoints().count(), 3);
+    }
+
+    #[test]
+    fn rollback_without_checkpoint_fails() {
+        let mut exchange = exchange(0, 8);
+        advance(&mut exchange, 105);
+        exchange.checkpoint();
+        advance(&mut exchange, 110);
+
+        assert!(matches!(
+            exchange.rollback_to(types::StateInstant::new(104, 1040)),
+            Err(DexError::CheckpointNotFound(104))
+        ));
+        assert_eq!(
+            exchange
+                .rollback_to(types::StateInstant::new(108, 1080))
+                .unwrap()
+                .block_number(),
+            105
+        );
+    }
+}
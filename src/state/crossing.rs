@@ -0,0 +1,212 @@
+//! Detection of locked and crossed order books.
+//!
+//! The exchange matches crossing orders on placement, so a tracked book with the best bid
+//! at or above the best ask almost always indicates missed or misapplied events,
+//! reported with [`super::StateEvents::BookCrossed`].
+
+use fastnum::UD64;
+
+use super::{IntegrityReport, Order, OrderBook};
+use crate::types;
+
+/// Action taken by [`super::Exchange::apply_events`] on the order book turning locked
+/// or crossed, see [`super::SnapshotBuilder::with_crossed_book_check`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CrossedBookCheck {
+    /// Books are not checked.
+    Disabled,
+
+    /// Emit [`super::StateEvents::BookCrossed`] once the book turns locked or crossed.
+    #[default]
+    Report,
+
+    /// Same as [`Self::Report`], additionally checking the integrity of the book and
+    /// repairing it, see [`OrderBook::repair`].
+    Repair,
+}
+
+/// Order book of the perpetual contract turned locked or crossed.
+///
+/// Crossing rarely heals with further events, so the state is best rebuilt with
+/// a fresh snapshot, e.g. by [`super::SnapshotBuilder`].
+#[derive(Clone, derive_more::Debug)]
+pub struct BookCrossedEvent {
+    /// ID of the perpetual contract of the book.
+    pub perpetual_id: types::PerpetualId,
+
+    /// Best bid price.
+    #[debug("{best_bid}")]
+    pub best_bid: UD64,
+
+    /// Best ask price, at or below the best bid.
+    #[debug("{best_ask}")]
+    pub best_ask: UD64,
+
+    /// Bids at or above the best ask price, in price-time priority.
+    pub bid_orders: Vec<Order>,
+
+    /// Asks at or below the best bid price, in price-time priority.
+    pub ask_orders: Vec<Order>,
+
+    /// Integrity issues of the book detected and repaired with [`CrossedBookCheck::Repair`].
+    pub integrity: Option<IntegrityReport>,
+}
+
+impl OrderBook {
+    /// Whether the best bid is at or above the best ask.
+    pub fn is_crossed(&self) -> bool {
+        self.crossing().is_some()
+    }
+
+    /// Best bid and ask prices if the book is locked or crossed.
+    fn crossing(&self) -> Option<(UD64, UD64)> {
+        let ((bid, _), (ask, _)) = self.best_bid().zip(self.best_ask())?;
+        (bid >= ask).then_some((bid, ask))
+    }
+}
+
+/// Detects the crossing of the book, repairing it with [`CrossedBookCheck::Repair`].
+pub(crate) fn check_book(
+    perpetual_id: types::PerpetualId,
+    book: &mut OrderBook,
+    check: CrossedBookCheck,
+) -> Option<BookCrossedEvent> {
+    let (best_bid, best_ask) = book.crossing()?;
+    let bid_orders = book
+        .bid_orders()
+        .take_while(|o| o.price() >= best_ask)
+        .map(|o| *o.order())
+        .collect();
+    let ask_orders = book
+        .ask_orders()
+        .take_while(|o| o.price() <= best_bid)
+        .map(|o| *o.order())
+        .collect();
+    let integrity = (check == CrossedBookCheck::Repair).then(|| book.repair());
+    Some(BookCrossedEvent {
+        perpetual_id,
+        best_bid,
+        best_ask,
+        bid_orders,
+        ask_orders,
+        integrity,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::{Address, I256, TxHash, U256};
+
+    use super::*;
+    use crate::{
+        abi::dex::Exchange::{self as Abi, ExchangeEvents},
+        state::{Exchange, StateEvents},
+        stream,
+        testing::sim::{Action, Simulator},
+    };
+
+    /// Exchange with a resting bid and ask, and the block placing a bid above the ask.
+    fn crossing_block() -> (Simulator, Exchange, stream::RawBlockEvents) {
+        let mut sim = Simulator::new(&[1], 2);
+        let mut exchange = sim.exchange();
+        let place = |account, is_bid| Action::Place {
+            perpetual: 0,
+            account,
+            is_bid,
+            offset: 1,
+            lots: 10,
+        };
+        exchange
+            .apply_events(&sim.block(&[place(0, true), place(1, false)]))
+            .unwrap();
+
+        let perp = &exchange.perpetuals()[&1];
+        let (ask, _) = perp.l3_book().best_ask().unwrap();
+        let price = perp.price_converter().to_unsigned(ask) + U256::from(1);
+        let events = [
+            ExchangeEvents::OrderRequest(Abi::OrderRequest {
+                perpId: U256::from(1),
+                accountId: U256::from(1),
+                orderDescId: U256::from(100),
+                orderId: U256::ZERO,
+                orderType: 0,
+                pricePNS: price,
+                lotLNS: U256::from(1),
+                expiryBlock: U256::ZERO,
+                postOnly: false,
+                fillOrKill: false,
+                immediateOrCancel: false,
+                maxMatches: U256::ZERO,
+                leverageHdths: U256::from(100),
+                gasLeft: U256::ZERO,
+            }),
+            ExchangeEvents::OrderPlaced(Abi::OrderPlaced {
+                orderId: U256::from(100),
+                lotLNS: U256::from(1),
+                lockedBalanceCNS: U256::ZERO,
+                amountCNS: I256::ZERO,
+                balanceCNS: U256::from(1_000_000),
+            }),
+        ];
+        let block = stream::RawBlockEvents::new(
+            types::StateInstant::new(3, 3),
+            events
+                .into_iter()
+                .enumerate()
+                .map(|(i, e)| stream::RawEvent::new(TxHash::ZERO, 0, i as u64, Address::ZERO, e))
+                .collect(),
+        );
+        (sim, exchange, block)
+    }
+
+    fn crossed(events: &crate::state::StateBlockEvents) -> Vec<&BookCrossedEvent> {
+        events
+            .flat_events()
+            .filter_map(|(_, e)| match e {
+                StateEvents::BookCrossed(e) => Some(e),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reports_crossing_once() {
+        let (mut sim, mut exchange, block) = crossing_block();
+        let events = exchange.apply_events(&block).unwrap().unwrap();
+        assert!(exchange.perpetuals()[&1].l3_book().is_crossed());
+
+        let crossed = crossed(&events);
+        assert_eq!(crossed.len(), 1);
+        assert_eq!(crossed[0].perpetual_id, 1);
+        assert!(crossed[0].best_bid > crossed[0].best_ask);
+        assert_eq!(
+            crossed[0]
+                .bid_orders
+                .iter()
+                .map(|o| o.order_id().get())
+                .collect::<Vec<_>>(),
+            vec![100]
+        );
+        assert_eq!(crossed[0].ask_orders.len(), 1);
+        assert!(crossed[0].integrity.is_none());
+
+        // Still crossed, not reported again; the simulator skips the block applied above
+        sim.block(&[]);
+        let events = exchange.apply_events(&sim.block(&[])).unwrap().unwrap();
+        assert!(self::crossed(&events).is_empty());
+    }
+
+    #[test]
+    fn repairs_crossed_book() {
+        let (_, mut exchange, block) = crossing_block();
+        exchange.set_crossed_book_check(CrossedBookCheck::Repair);
+        let events = exchange.apply_events(&block).unwrap().unwrap();
+        let crossed = crossed(&events);
+        assert!(crossed[0].integrity.as_ref().is_some_and(|r| r.is_ok()));
+
+        let (_, mut exchange, block) = crossing_block();
+        exchange.set_crossed_book_check(CrossedBookCheck::Disabled);
+        let events = exchange.apply_events(&block).unwrap().unwrap();
+        assert!(self::crossed(&events).is_empty());
+    }
+}
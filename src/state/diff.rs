@@ -0,0 +1,317 @@
+//! Structured differences between two exchange states.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+};
+
+use fastnum::{D64, UD64, UD128};
+
+use super::{Account, Exchange, Order, OrderSlab, Perpetual, Position};
+use crate::types;
+
+/// Change of a single state entity between two states.
+#[derive(Clone, Debug)]
+pub enum Change<T> {
+    /// Entity is present only in the other state.
+    Added(T),
+    /// Entity is present only in this state.
+    Removed(T),
+    /// Entity is present in both states, but differs.
+    Changed { before: T, after: T },
+}
+
+/// Difference in an active order of the perpetual contract order book.
+#[derive(Clone, Debug)]
+pub struct OrderDiff {
+    pub perpetual_id: types::PerpetualId,
+    pub order_id: types::OrderId,
+    pub change: Change<Order>,
+}
+
+/// Collateral balances of the account compared by [`Exchange::diff`].
+#[derive(Clone, Copy, PartialEq, Eq, derive_more::Debug)]
+pub struct AccountBalances {
+    #[debug("{balance}")]
+    pub balance: UD128,
+    #[debug("{locked_balance}")]
+    pub locked_balance: UD128,
+    pub frozen: bool,
+}
+
+/// Difference in the account balances.
+#[derive(Clone, Debug)]
+pub struct BalanceDiff {
+    pub account_id: types::AccountId,
+    pub change: Change<AccountBalances>,
+}
+
+/// Difference in an open position.
+#[derive(Clone, Debug)]
+pub struct PositionDiff {
+    pub account_id: types::AccountId,
+    pub perpetual_id: types::PerpetualId,
+    pub change: Change<Position>,
+}
+
+/// Parameters and state of the perpetual contract compared by [`Exchange::diff`].
+#[derive(Clone, Copy, PartialEq, Eq, derive_more::Debug)]
+pub struct PerpetualParams {
+    pub is_paused: bool,
+    #[debug("{maker_fee}")]
+    pub maker_fee: UD64,
+    #[debug("{taker_fee}")]
+    pub taker_fee: UD64,
+    #[debug("{initial_margin}")]
+    pub initial_margin: UD64,
+    #[debug("{maintenance_margin}")]
+    pub maintenance_margin: UD64,
+    #[debug("{tick_size}")]
+    pub tick_size: UD64,
+    #[debug("{lot_size}")]
+    pub lot_size: UD64,
+    #[debug("{mark_price}")]
+    pub mark_price: UD64,
+    #[debug("{oracle_price}")]
+    pub oracle_price: UD64,
+    #[debug("{funding_rate}")]
+    pub funding_rate: D64,
+    pub funding_start_block: u64,
+    #[debug("{open_interest}")]
+    pub open_interest: UD128,
+}
+
+/// Difference in the perpetual contract parameters.
+#[derive(Clone, Debug)]
+pub struct PerpetualDiff {
+    pub perpetual_id: types::PerpetualId,
+    pub change: Change<PerpetualParams>,
+}
+
+/// Differences between two exchange states, see [`Exchange::diff`].
+///
+/// Entries of each kind are ordered by their IDs.
+#[derive(Clone, Debug, Default)]
+pub struct StateDiff {
+    /// Orders added, removed or changed.
+    pub orders: Vec<OrderDiff>,
+
+    /// Accounts with changed balances.
+    pub balances: Vec<BalanceDiff>,
+
+    /// Positions opened, closed or changed.
+    pub positions: Vec<PositionDiff>,
+
+    /// Perpetual contracts with changed parameters.
+    pub perpetuals: Vec<PerpetualDiff>,
+}
+
+impl StateDiff {
+    /// Returns `true` if the states are equivalent.
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+            && self.balances.is_empty()
+            && self.positions.is_empty()
+            && self.perpetuals.is_empty()
+    }
+
+    /// Total number of differences.
+    pub fn len(&self) -> usize {
+        self.orders.len() + self.balances.len() + self.positions.len() + self.perpetuals.len()
+    }
+}
+
+pub(crate) fn diff(this: &Exchange, other: &Exchange) -> StateDiff {
+    let mut result = StateDiff::default();
+
+    for perp_id in keys(this.perpetuals(), other.perpetuals()) {
+        let (before, after) = (
+            this.perpetuals().get(&perp_id).map(Arc::as_ref),
+            other.perpetuals().get(&perp_id).map(Arc::as_ref),
+        );
+        if let Some(change) = compare(
+            before.map(perpetual_params),
+            after.map(perpetual_params),
+            |a, b| a == b,
+        ) {
+            result.perpetuals.push(PerpetualDiff {
+                perpetual_id: perp_id,
+                change,
+            });
+        }
+
+        let empty = OrderSlab::default();
+        let (before, after) = (
+            before.map_or(&empty, |p| p.l3_book().all_orders()),
+            after.map_or(&empty, |p| p.l3_book().all_orders()),
+        );
+        let order_ids = before.keys().chain(after.keys()).collect::<BTreeSet<_>>();
+        for order_id in order_ids {
+            if let Some(change) = compare(
+                before.get(&order_id).map(|o| *o.order()),
+                after.get(&order_id).map(|o| *o.order()),
+                same_order,
+            ) {
+                result.orders.push(OrderDiff {
+                    perpetual_id: perp_id,
+                    order_id,
+                    change,
+                });
+            }
+        }
+    }
+
+    for account_id in keys(this.accounts(), other.accounts()) {
+        let (before, after) = (
+            this.accounts().get(&account_id).map(Arc::as_ref),
+            other.accounts().get(&account_id).map(Arc::as_ref),
+        );
+        if let Some(change) = compare(
+            before.map(account_balances),
+            after.map(account_balances),
+            |a, b| a == b,
+        ) {
+            result.balances.push(BalanceDiff { account_id, change });
+        }
+
+        let empty = HashMap::new();
+        let (before, after) = (
+            before.map_or(&empty, |a| a.positions()),
+            after.map_or(&empty, |a| a.positions()),
+        );
+        for perp_id in keys(before, after) {
+            if let Some(change) = compare(
+                before.get(&perp_id).cloned(),
+                after.get(&perp_id).cloned(),
+                same_position,
+            ) {
+                result.positions.push(PositionDiff {
+                    account_id,
+                    perpetual_id: perp_id,
+                    change,
+                });
+            }
+        }
+    }
+
+    result
+}
+
+fn keys<K: Copy + Ord, A, B>(a: &HashMap<K, A>, b: &HashMap<K, B>) -> BTreeSet<K> {
+    a.keys().chain(b.keys()).copied().collect()
+}
+
+fn compare<T>(
+    before: Option<T>,
+    after: Option<T>,
+    same: impl Fn(&T, &T) -> bool,
+) -> Option<Change<T>> {
+    match (before, after) {
+        (Some(before), Some(after)) => {
+            (!same(&before, &after)).then_some(Change::Changed { before, after })
+        }
+        (Some(before), None) => Some(Change::Removed(before)),
+        (None, Some(after)) => Some(Change::Added(after)),
+        (None, None) => None,
+    }
+}
+
+pub(super) fn perpetual_params(perp: &Perpetual) -> PerpetualParams {
+    PerpetualParams {
+        is_paused: perp.is_paused(),
+        maker_fee: perp.maker_fee(),
+        taker_fee: perp.taker_fee(),
+        initial_margin: perp.initial_margin(),
+        maintenance_margin: perp.maintenance_margin(),
+        tick_size: perp.tick_size(),
+        lot_size: perp.lot_size(),
+        mark_price: perp.mark_price(),
+        oracle_price: perp.oracle_price(),
+        funding_rate: perp.funding_rate(),
+        funding_start_block: perp.funding_start_block(),
+        open_interest: perp.open_interest(),
+    }
+}
+
+pub(super) fn account_balances(account: &Account) -> AccountBalances {
+    AccountBalances {
+        balance: account.balance(),
+        locked_balance: account.locked_balance(),
+        frozen: account.frozen(),
+    }
+}
+
+// Instants, request IDs, order flags and FIFO links are not available from
+// the snapshot or events respectively, so are not compared.
+fn same_order(a: &Order, b: &Order) -> bool {
+    a.r#type() == b.r#type()
+        && a.account_id() == b.account_id()
+        && a.price() == b.price()
+        && a.size() == b.size()
+        && a.expiry_block() == b.expiry_block()
+        && a.leverage() == b.leverage()
+}
+
+// PnL depends on the funding and mark price update timing, so only the
+// on-chain position parameters are compared.
+fn same_position(a: &Position, b: &Position) -> bool {
+    a.r#type() == b.r#type()
+        && a.entry_price() == b.entry_price()
+        && a.size() == b.size()
+        && a.deposit() == b.deposit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::PositionType;
+    use crate::testing::fuzz::{Action, Simulator};
+
+    #[test]
+    fn detects_divergence() {
+        let mut sim = Simulator::new(&[1], 2);
+        let mut applied = sim.exchange();
+        let mut lagging = sim.exchange();
+        assert!(applied.diff(&lagging).is_empty());
+
+        let block = sim.block(&[
+            Action::Place {
+                perpetual: 0,
+                account: 0,
+                is_bid: true,
+                offset: 1,
+                lots: 2,
+            },
+            Action::Open {
+                perpetual: 0,
+                account: 1,
+                is_long: true,
+                lots: 3,
+            },
+        ]);
+        applied.apply_events(&block).unwrap();
+
+        let diff = lagging.diff(&applied);
+        assert_eq!(diff.orders.len(), 1);
+        assert!(matches!(diff.orders[0].change, Change::Added(_)));
+        // Only the order locks the balance
+        assert_eq!(diff.balances.len(), 1);
+        assert_eq!(diff.balances[0].account_id, 1);
+        assert_eq!(diff.positions.len(), 1);
+        assert!(matches!(
+            &diff.positions[0].change,
+            Change::Added(pos) if pos.r#type() == PositionType::Long
+        ));
+        assert!(matches!(
+            diff.perpetuals[0].change,
+            Change::Changed { before, after } if before.open_interest < after.open_interest
+        ));
+
+        let reverse = applied.diff(&lagging);
+        assert_eq!(reverse.len(), diff.len());
+        assert!(matches!(reverse.orders[0].change, Change::Removed(_)));
+
+        lagging.apply_events(&block).unwrap();
+        assert!(applied.diff(&lagging).is_empty());
+    }
+}
@@ -1,7 +1,12 @@
-use alloy::primitives::{B256, U256};
-use fastnum::{D64, D256, UD64, UD128};
+use std::ops::Range;
 
-use super::{account, order, perpetual, position};
+use alloy::primitives::{Address, B256, U256};
+use fastnum::{D64, D128, D256, UD64, UD128};
+
+use super::{
+    account, adl::DeleverageFailure, crossing::BookCrossedEvent, insurance::FundChange, order,
+    perpetual, position, price_band::ToleranceBreach, roles::Role,
+};
 
 use crate::{abi::dex::Exchange::OrderRequest, types};
 
@@ -15,6 +20,9 @@ pub enum StateEvents {
     /// Account state updated.
     Account(AccountEvent),
 
+    /// Tracked order book turned locked or crossed, likely due to missed events.
+    BookCrossed(BookCrossedEvent),
+
     /// Order request processing error.
     Error(OrderError),
 
@@ -51,13 +59,66 @@ pub enum AccountEventType {
     Created(types::AccountId),
 
     /// Account frozen/unfrozen.
-    Frozen(bool),
+    Frozen(types::FreezeStatus),
 
     /// Account balance updated.
     BalanceUpdated(#[debug("{_0}")] UD128),
 
+    /// Account balance changed by the delta for the reason,
+    /// follows the corresponding [`Self::BalanceUpdated`] unless the balance is unchanged.
+    BalanceChanged {
+        #[debug("{delta}")]
+        delta: D128,
+        reason: BalanceChangeReason,
+    },
+
     /// Account locked balance updated.
     LockedBalanceUpdated(#[debug("{_0}")] UD128),
+
+    /// Forwarding of orders by the exchange administrator allowed/disallowed
+    /// by the account, see [`super::Account::order_forwarding`].
+    OrderForwarding(bool),
+}
+
+/// Cause of the account balance change, classified by the raw exchange event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BalanceChangeReason {
+    /// Collateral deposited (`CollateralDeposit`).
+    Deposit,
+
+    /// Collateral withdrawn (`CollateralWithdrawal`).
+    Withdrawal,
+
+    /// Settlement of the order fill, either as maker or taker, including the trading fee,
+    /// the realized PnL and funding of the position, and the margin moved to/from it.
+    Fill,
+
+    /// Recycle fee deposit taken on order posting or change, or returned on cancellation.
+    OrderPosting,
+
+    /// Recycle fee collected for clearing an expired, invalid or unsettleable order.
+    RecycleFee,
+
+    /// Collateral moved to the position (`IncreasePositionCollateral`).
+    PositionCollateral,
+
+    /// Settlement of the liquidated position.
+    Liquidation,
+
+    /// Credit paid for liquidating the position of another account (`AccountLiquidationCredit`).
+    LiquidationCredit,
+
+    /// Settlement of the deleveraged position, including the realized PnL and funding.
+    Deleverage,
+
+    /// Payment for the position unwound by the exchange.
+    Unwind,
+
+    /// Transfer between the account and the protocol.
+    ProtocolTransfer,
+
+    /// Any other exchange event.
+    Other,
 }
 
 /// Order request processing error with corresponding reason
@@ -83,7 +144,7 @@ pub struct OrderError {
 #[derive(Clone, Copy, derive_more::Debug)]
 pub enum OrderErrorType {
     /// Account is frozen.
-    AccountFrozen,
+    AccountFrozen(types::FreezeStatus),
 
     /// Required amount exceeds available balance.
     AmountExceedsAvailableBalance(#[debug("{_0}")] UD128, #[debug("{_1}")] UD128),
@@ -137,8 +198,11 @@ pub enum OrderErrorType {
     /// Order does not exist.
     OrderDoesNotExist,
 
-    /// Order posting failed with status.
-    OrderPostFailed(u16),
+    /// Account does not allow forwarding of its orders.
+    OrderForwardingNotAllowed,
+
+    /// Order posting failed with the result code.
+    OrderPostFailed(types::ResultCode),
 
     /// Settlement of the order will render perpetual contract insolvent.
     OrderSettlementImpliesInsolvent,
@@ -159,11 +223,80 @@ pub enum OrderErrorType {
     WrongAccountForOrder,
 }
 
+impl std::fmt::Display for OrderErrorType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrderErrorType::AccountFrozen(status) => write!(f, "account is {status}"),
+            OrderErrorType::AmountExceedsAvailableBalance(amount, available) => write!(
+                f,
+                "required amount {amount} exceeds available balance {available}"
+            ),
+            OrderErrorType::CancelExistingInvalidCloseOrders => f.write_str(
+                "existing close orders mismatch the position type and need to be cancelled",
+            ),
+            OrderErrorType::CantChangeCloseOrder => f.write_str("close orders can not be changed"),
+            OrderErrorType::ChangeExpiredOrderNeedsNewExpiry => {
+                f.write_str("expired order needs new expiration to be changed")
+            }
+            OrderErrorType::CloseOrderExceedsPosition => {
+                f.write_str("close order size exceeds position size")
+            }
+            OrderErrorType::CloseOrderPositionMismatch => {
+                f.write_str("close order side mismatches position type")
+            }
+            OrderErrorType::ContractIsPaused => f.write_str("perpetual contract is paused"),
+            OrderErrorType::CrossesBook => f.write_str("post-only order crosses the book"),
+            OrderErrorType::ExceedsLastExecutionBlock => {
+                f.write_str("current block exceeds last execution block of the order")
+            }
+            OrderErrorType::ImmediateOrCancelExecuted => {
+                f.write_str("immediate-or-cancel order was not completely filled")
+            }
+            OrderErrorType::InsuficientFundsForRecycleFee => {
+                f.write_str("available balance can not cover recycling fee")
+            }
+            OrderErrorType::InvalidExpiryBlock => {
+                f.write_str("current block exceeds expiration block of the order")
+            }
+            OrderErrorType::InvalidOrderId => f.write_str("order ID is out of range"),
+            OrderErrorType::MakerOrderSettlementFailed => {
+                f.write_str("failed to settle maker order")
+            }
+            OrderErrorType::MaxMatchesReached => {
+                f.write_str("maximum number of matches reached for taker order")
+            }
+            OrderErrorType::MaximumAccountOrders => f.write_str("account reached limit of orders"),
+            OrderErrorType::OrderDoesNotExist => f.write_str("order does not exist"),
+            OrderErrorType::OrderForwardingNotAllowed => {
+                f.write_str("account does not allow order forwarding")
+            }
+            OrderErrorType::OrderPostFailed(code) => write!(f, "order posting failed with {code}"),
+            OrderErrorType::OrderSettlementImpliesInsolvent => {
+                f.write_str("order settlement would render perpetual contract insolvent")
+            }
+            OrderErrorType::OrderSizeExceedsAvailableSize => {
+                f.write_str("close order size exceeds remaining position size")
+            }
+            OrderErrorType::PostOrderUnderMinimum => f.write_str("order is under minimum amount"),
+            OrderErrorType::PriceOutOfRange => f.write_str("order price is out of range"),
+            OrderErrorType::SizeOutOfRange => f.write_str("order size is out of range"),
+            OrderErrorType::WrongAccountForOrder => f.write_str("another account owns the order"),
+        }
+    }
+}
+
 #[derive(Clone, derive_more::Debug)]
 pub enum ExchangeEvent {
+    /// Protocol or insurance fund balance changed, see [`super::Exchange::fund_history`].
+    FundBalanceChanged(FundChange),
+
     /// Exchange halted/unhalted.
     Halted(bool),
 
+    /// Implementation behind the exchange proxy upgraded to the contract at the address,
+    /// see [`super::Exchange::pending_upgrade`].
+    ImplementationUpgraded(Address),
+
     /// Minimal posting amount updated.
     MinPostUpdated(#[debug("{_0}")] UD128),
 
@@ -172,6 +305,29 @@ pub enum ExchangeEvent {
 
     /// Recycling fee updated.
     RecycleFeeUpdated(#[debug("{_0}")] UD128),
+
+    /// Role granted to (`true`) or revoked from (`false`) the address.
+    RoleUpdated(Role, Address, bool),
+
+    /// Account whitelisting enabled/disabled.
+    WhitelistingEnabled(bool),
+
+    /// Withdrawal rate limit reset, regularly (`WithdrawRateLimitReset`) or forcibly
+    /// (`WithdrawRateLimitForceReset`), see [`super::Exchange::withdraw_rate_limit`].
+    WithdrawRateLimitReset(WithdrawRateLimit),
+}
+
+/// Exchange-wide collateral withdrawal rate limit.
+#[derive(Clone, Copy, PartialEq, Eq, derive_more::Debug)]
+pub struct WithdrawRateLimit {
+    /// Block the limit expires at and gets reset.
+    pub expiry_block: u64,
+    /// Amount of collateral that can be withdrawn until the limit expires.
+    #[debug("{limit}")]
+    pub limit: UD128,
+    /// Amount of collateral the allowance grows by with every block.
+    #[debug("{per_block}")]
+    pub per_block: UD128,
 }
 
 /// Order book state mutation event.
@@ -207,6 +363,8 @@ pub enum OrderEventType {
         #[debug("{fee}")]
         fee: UD64, // Precision of SC calculations is limited to 5 decimals.
         is_maker: bool,
+        /// Fill log, see [`types::FillId`].
+        fill_id: types::FillId,
     },
 
     /// Order placed to the book.
@@ -227,6 +385,10 @@ pub enum OrderEventType {
     /// Order removed from the book.
     Removed,
 
+    /// Order of the watched account was not placed or modified for longer than
+    /// the watched interval, see [`super::Exchange::watch_stale_orders`].
+    Stale { age_blocks: u64 },
+
     /// Order in the book updated.
     Updated {
         #[debug("{:?}", price.map(|v| format!("{v}")))]
@@ -285,8 +447,23 @@ pub enum PerpetualEventType {
     /// Perpetual contract paused/unpaused.
     Paused(bool),
 
+    /// Price tolerance updated, see [`super::Perpetual::price_tolerance`].
+    PriceToleranceUpdated(#[debug("{_0}")] UD64),
+
+    /// Price deviation beyond the tolerance prevented the mark price update
+    /// or the funding event.
+    PriceToleranceExceeded(ToleranceBreach),
+
     /// Taker fee updated.
     TakerFeeUpdated(#[debug("{_0}")] UD64),
+
+    /// Auto-deleveraging attempt rejected for the lack of opposing positions,
+    /// see [`super::Perpetual::last_deleverage_failure`].
+    DeleverageFailed(DeleverageFailure),
+
+    /// Price or size of the watched top order book levels changed during the block,
+    /// see [`super::Exchange::watch_top_levels`].
+    TopLevelsChanged,
 }
 
 /// Position state mutation event.
@@ -538,6 +715,72 @@ impl StateEvents {
     }
 }
 
+/// Buffer all state events of a block are pushed into, see [`super::Exchange::apply_events`].
+///
+/// Events pushed since the last [`Self::commit`] form the next context of the block,
+/// which refers to them by range, so the whole block allocates a single list.
+#[derive(Default)]
+pub(crate) struct EventSink {
+    events: Vec<StateEvents>,
+    start: usize,
+}
+
+impl EventSink {
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Self {
+            events: Vec::with_capacity(capacity),
+            start: 0,
+        }
+    }
+
+    pub(crate) fn push(&mut self, event: StateEvents) {
+        self.events.push(event);
+    }
+
+    pub(crate) fn events(&self) -> &[StateEvents] {
+        &self.events
+    }
+
+    pub(crate) fn events_mut(&mut self) -> &mut Vec<StateEvents> {
+        &mut self.events
+    }
+
+    /// Position of the first event pushed since the last commit.
+    pub(crate) fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Keeps only the events pushed since the last commit matching the predicate,
+    /// preserving their order.
+    pub(crate) fn retain(&mut self, mut f: impl FnMut(&StateEvents) -> bool) {
+        let mut kept = self.start;
+        for i in self.start..self.events.len() {
+            if f(&self.events[i]) {
+                self.events.swap(kept, i);
+                kept += 1;
+            }
+        }
+        self.events.truncate(kept);
+    }
+
+    /// Closes the events pushed since the last commit, returning their range if any.
+    pub(crate) fn commit(&mut self) -> Option<Range<usize>> {
+        let range = self.start..self.events.len();
+        self.start = self.events.len();
+        (!range.is_empty()).then_some(range)
+    }
+
+    pub(crate) fn into_events(self) -> Vec<StateEvents> {
+        self.events
+    }
+}
+
+impl Extend<StateEvents> for EventSink {
+    fn extend<T: IntoIterator<Item = StateEvents>>(&mut self, iter: T) {
+        self.events.extend(iter);
+    }
+}
+
 /// Order request context.
 pub(crate) struct OrderContext {
     pub(crate) perpetual_id: types::PerpetualId,
@@ -1,9 +1,51 @@
-use super::*;
-use crate::{Chain, abi::dex::Exchange::ExchangeEvents, stream, types::EventContext};
-use fastnum::{D256, UD64, UD128};
+use super::{
+    block_time::BlockTimes, checkpoint::Checkpoints, crossing, insurance::FundHistory,
+    position_index::PositionIndex, roles::Roles, shard, *,
+};
+use crate::{
+    Chain, abi::dex::Exchange::ExchangeEvents, oracle::IndexPriceSource, stream,
+    types::EventContext,
+};
+use alloy::primitives::B256;
+use fastnum::{D128, D256, UD64, UD128};
 use itertools::chain;
+use std::{
+    collections::{BTreeMap, HashSet},
+    ops::Range,
+    sync::Arc,
+    time::Duration,
+};
 
-pub type StateBlockEvents = types::BlockEvents<types::EventContext<Vec<StateEvents>>>;
+/// State events produced by [`Exchange::apply_events`] from a single block.
+///
+/// Events come in a deterministic order, the same for any state tracking the same
+/// perpetual contracts and accounts:
+/// * events produced from raw events, in the order of their logs within the block;
+/// * changes of the watched top order book levels, by perpetual contract ID;
+/// * events produced by the block commit, e.g. price or funding updates,
+///   by perpetual contract ID, followed by their consequences in the same order;
+/// * orders of the watched accounts turned stale, by account and perpetual contract ID;
+/// * order books turned locked or crossed, by perpetual contract ID.
+///
+/// Each event has a sequence number, strictly increasing across blocks and
+/// derived from the block number and the position of the event within the block
+/// (`block_number << 32 | index`), so persistence layers can use it as an idempotent
+/// ordering key, see [`types::EventContext::sequence_number`] and
+/// [`types::BlockEvents::sequenced_events`].
+pub type StateBlockEvents = types::BlockEvents<types::EventContext<Range<usize>>, StateEvents>;
+
+/// Reason the exchange does not accept orders for the perpetual contract,
+/// see [`Exchange::ensure_available`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum VenueUnavailable {
+    /// Exchange is halted (`ExchangeHalted`).
+    #[error("exchange is halted")]
+    Halted,
+
+    /// Perpetual contract is paused (`ContractPaused`) or removed.
+    #[error("perpetual {0} is paused")]
+    Paused(types::PerpetualId),
+}
 
 /// Exchange state snapshot.
 ///
@@ -22,10 +64,79 @@ pub struct Exchange {
     min_settle: UD128,
     #[debug("{recycle_fee}")]
     recycle_fee: UD128,
-    perpetuals: HashMap<types::PerpetualId, Perpetual>,
-    accounts: HashMap<types::AccountId, Account>,
+    #[debug("{protocol_balance}")]
+    protocol_balance: UD128,
+    #[debug(skip)]
+    fund_history: FundHistory,
+    /// Per-contract and per-account state is shared with the checkpoints and clones
+    /// of the exchange, getting copied on the first mutation after cloning.
+    perpetuals: HashMap<types::PerpetualId, Arc<Perpetual>>,
+    accounts: HashMap<types::AccountId, Arc<Account>>,
+    account_ids: HashMap<Address, types::AccountId>,
+    position_index: PositionIndex,
+    pnl_history_retention: usize,
     is_halted: bool,
+    halt_on_upgrade: bool,
+    pending_upgrade: Option<Address>,
+    withdraw_rate_limit: Option<WithdrawRateLimit>,
+    roles: Roles,
     track_all_accounts: bool,
+    top_levels_watches: BTreeMap<types::PerpetualId, TopLevelsWatch>,
+    stale_orders_watches: BTreeMap<types::AccountId, StaleOrdersWatch>,
+    crossed_book_check: CrossedBookCheck,
+    /// Perpetual contracts with the book reported crossed and not uncrossed since.
+    crossed_books: HashSet<types::PerpetualId>,
+    #[debug(skip)]
+    block_times: BlockTimes,
+    #[debug(skip)]
+    checkpoints: Checkpoints,
+    parallel_apply: bool,
+    pruning: PruningPolicy,
+    last_compaction: u64,
+}
+
+/// Top order book levels last reported via [`PerpetualEventType::TopLevelsChanged`].
+#[derive(Clone, Debug)]
+struct TopLevelsWatch {
+    depth: usize,
+    bids: Vec<(UD64, UD64)>,
+    asks: Vec<(UD64, UD64)>,
+}
+
+/// Orders of the account last reported via [`OrderEventType::Stale`].
+#[derive(Clone, Debug)]
+struct StaleOrdersWatch {
+    older_than_blocks: u64,
+    /// Reported orders with the block they were last modified at,
+    /// so that modified orders get reported again once stale.
+    reported: HashSet<(types::PerpetualId, types::OrderId, u64)>,
+}
+
+impl TopLevelsWatch {
+    fn new(depth: usize, book: &OrderBook) -> Self {
+        Self {
+            depth,
+            bids: book.top_bids(depth),
+            asks: book.top_asks(depth),
+        }
+    }
+
+    /// Updates the watched levels, returns `true` if they changed.
+    fn update(&mut self, book: &OrderBook) -> bool {
+        // Compare in place, allocating only when the levels changed
+        let view = book.view();
+        let same = |watched: &[(UD64, UD64)], levels: &mut dyn Iterator<Item = LevelView>| {
+            watched
+                .iter()
+                .copied()
+                .eq(levels.take(self.depth).map(|l| (l.price(), l.size())))
+        };
+        if same(&self.bids, &mut view.bids()) && same(&self.asks, &mut view.asks()) {
+            return false;
+        }
+        *self = Self::new(self.depth, book);
+        true
+    }
 }
 
 impl Exchange {
@@ -38,11 +149,20 @@ impl Exchange {
         min_post: UD128,
         min_settle: UD128,
         recycle_fee: UD128,
-        perpetuals: HashMap<types::PerpetualId, Perpetual>,
+        mut perpetuals: HashMap<types::PerpetualId, Perpetual>,
         accounts: HashMap<types::AccountId, Account>,
         is_halted: bool,
         track_all_accounts: bool,
     ) -> Self {
+        for perp in perpetuals.values_mut() {
+            perp.set_collateral_converter(collateral_converter);
+        }
+        let account_ids = accounts
+            .values()
+            .filter(|acc| acc.address() != Address::ZERO)
+            .map(|acc| (acc.address(), acc.id()))
+            .collect();
+        let position_index = PositionIndex::new(accounts.values());
         Self {
             chain,
             instant,
@@ -51,10 +171,34 @@ impl Exchange {
             min_post,
             min_settle,
             recycle_fee,
-            perpetuals,
-            accounts,
+            protocol_balance: UD128::ZERO,
+            fund_history: FundHistory::default(),
+            perpetuals: perpetuals
+                .into_iter()
+                .map(|(id, perp)| (id, Arc::new(perp)))
+                .collect(),
+            accounts: accounts
+                .into_iter()
+                .map(|(id, acc)| (id, Arc::new(acc)))
+                .collect(),
+            account_ids,
+            position_index,
+            pnl_history_retention: 0,
             is_halted,
+            halt_on_upgrade: false,
+            pending_upgrade: None,
+            withdraw_rate_limit: None,
+            roles: Roles::default(),
             track_all_accounts,
+            top_levels_watches: BTreeMap::new(),
+            stale_orders_watches: BTreeMap::new(),
+            crossed_book_check: CrossedBookCheck::default(),
+            crossed_books: HashSet::new(),
+            block_times: BlockTimes::default(),
+            checkpoints: Checkpoints::default(),
+            parallel_apply: false,
+            pruning: PruningPolicy::default(),
+            last_compaction: instant.block_number(),
         }
     }
 
@@ -68,6 +212,26 @@ impl Exchange {
         &self.chain
     }
 
+    /// Verifies the exchange contract currently deployed behind the proxy matches the ABI
+    /// revision the SDK was built with, [`crate::abi::DEX_REVISION`], so events do not get
+    /// misinterpreted after a contract upgrade.
+    ///
+    /// The contract exposes no revision marker, so the implementation bytecode is probed
+    /// instead: all ABI function selectors and event signatures have to be present.
+    ///
+    /// Fails with [`DexError::IncompatibleContract`] listing the missing ones otherwise.
+    #[cfg(feature = "rpc")]
+    pub async fn verify_revision<P: Provider>(&self, provider: &P) -> Result<(), DexError> {
+        let implementation = revision::implementation(provider, self.chain.exchange()).await?;
+        let code = provider.get_code_at(implementation).await?;
+        if code.is_empty() {
+            return Err(DexError::InvalidRequest(format!(
+                "no contract deployed at {implementation}"
+            )));
+        }
+        Ok(revision::check_bytecode(implementation, &code)?)
+    }
+
     /// Instant the snapshot is consistent with or was last updated at.
     pub fn instant(&self) -> types::StateInstant {
         self.instant
@@ -79,6 +243,26 @@ impl Exchange {
         self.collateral_converter
     }
 
+    /// Upcoming funding event boundaries of the perpetual contract with timestamps
+    /// estimated from [`Chain::block_time`].
+    pub fn funding_schedule(&self, perpetual_id: types::PerpetualId) -> Option<FundingSchedule> {
+        let perp = self.perpetuals.get(&perpetual_id)?;
+        Some(perp.funding_schedule(
+            self.instant,
+            self.funding_interval_blocks,
+            self.chain.block_time(),
+        ))
+    }
+
+    /// Block number and estimated timestamp of the next funding event boundary
+    /// of the perpetual contract, see [`Self::funding_schedule`].
+    pub fn next_funding_instant(
+        &self,
+        perpetual_id: types::PerpetualId,
+    ) -> Option<types::StateInstant> {
+        self.funding_schedule(perpetual_id)?.next()
+    }
+
     /// Funding interval in blocks.
     ///
     /// Each perpetual contract has own [Perpetual::funding_start_block]  this interval
@@ -106,23 +290,493 @@ impl Exchange {
         self.recycle_fee
     }
 
+    /// Protocol balance of the exchange, collecting the protocol share of fees
+    /// and funding the insurance funds.
+    ///
+    /// Tracked from the snapshot with deposits, withdrawals and transfers reported
+    /// by the events, see [`Self::fund_history`].
+    pub fn protocol_balance(&self) -> UD128 {
+        self.protocol_balance
+    }
+
+    /// Total balance of the insurance funds of the tracked perpetual contracts,
+    /// see [`Perpetual::insurance_balance`] for the per-contract ones and caveats.
+    pub fn insurance_fund_balance(&self) -> UD128 {
+        self.perpetuals
+            .values()
+            .map(|perp| perp.insurance_balance())
+            .fold(UD128::ZERO, |total, balance| total + balance)
+    }
+
+    /// Most recent protocol and insurance fund balance changes, oldest first.
+    ///
+    /// History is bounded by the retention,
+    /// see [`super::SnapshotBuilder::with_fund_history_retention`].
+    pub fn fund_history(&self) -> impl DoubleEndedIterator<Item = &FundChange> {
+        self.fund_history.changes().iter()
+    }
+
+    /// Withdrawal rate limit set by the latest reset observed in the events,
+    /// `None` until the first reset after the snapshot.
+    pub fn withdraw_rate_limit(&self) -> Option<WithdrawRateLimit> {
+        self.withdraw_rate_limit
+    }
+
     /// Perpetual contracts state tracked within the exchange, according to initial
     /// snapshot building configuration.
-    pub fn perpetuals(&self) -> &HashMap<types::PerpetualId, Perpetual> {
+    pub fn perpetuals(&self) -> &HashMap<types::PerpetualId, Arc<Perpetual>> {
         &self.perpetuals
     }
 
     /// Accounts state tracked within the exchange, according to initial
     /// snapshot building configuration.
-    pub fn accounts(&self) -> &HashMap<types::AccountId, Account> {
+    pub fn accounts(&self) -> &HashMap<types::AccountId, Arc<Account>> {
         &self.accounts
     }
 
+    /// ID of the account with the given address.
+    ///
+    /// Known for accounts in the snapshot, accounts created since, and accounts
+    /// with addresses filled by [`Self::apply_account_addresses`], whether tracked or not.
+    pub fn account_id_by_address(&self, address: Address) -> Option<types::AccountId> {
+        self.account_ids.get(&address).copied()
+    }
+
+    /// Tracked account with the given address, see [`Self::account_id_by_address`].
+    pub fn account_by_address(&self, address: Address) -> Option<&Account> {
+        self.accounts
+            .get(&self.account_id_by_address(address)?)
+            .map(Arc::as_ref)
+    }
+
+    /// Tracked positions in the perpetual contract ordered by account ID,
+    /// along with their aggregate statistics.
+    ///
+    /// Aggregates are maintained incrementally as the events get applied,
+    /// so the query does not scan all tracked accounts.
+    pub fn positions_by_perpetual(
+        &self,
+        perpetual_id: types::PerpetualId,
+    ) -> (impl Iterator<Item = &Position>, PositionAggregates) {
+        let positions = self
+            .position_index
+            .account_ids(perpetual_id)
+            .filter_map(move |id| self.accounts.get(&id)?.positions().get(&perpetual_id));
+        (positions, self.position_index.aggregates(perpetual_id))
+    }
+
+    /// Average block time observed over the recently applied blocks,
+    /// `None` until enough blocks are applied.
+    pub fn observed_block_time(&self) -> Option<Duration> {
+        self.block_times.average()
+    }
+
+    /// Block number to expire the order at for it to stay active for the given duration
+    /// from the current instant, e.g. `expiry_block_in(Duration::from_secs(30))`.
+    ///
+    /// Uses the observed block time, falling back to the average block time of the chain,
+    /// see [`Chain::blocks_from_duration`].
+    pub fn expiry_block_in(&self, duration: Duration) -> u64 {
+        let block_time = self
+            .observed_block_time()
+            .unwrap_or(self.chain.block_time());
+        self.instant.block_number() + crate::blocks_from_duration(duration, block_time)
+    }
+
     /// Indicates if exchange is being halted.
     pub fn is_halted(&self) -> bool {
         self.is_halted
     }
 
+    /// Fails if the exchange would reject orders for the perpetual contract, as it is
+    /// halted or the contract is paused. Untracked perpetual contracts are not checked.
+    pub fn ensure_available(
+        &self,
+        perpetual_id: types::PerpetualId,
+    ) -> Result<(), VenueUnavailable> {
+        if self.is_halted {
+            return Err(VenueUnavailable::Halted);
+        }
+        match self.perpetuals.get(&perpetual_id) {
+            Some(perp) if perp.is_paused() => Err(VenueUnavailable::Paused(perpetual_id)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails with [`DexError::VenueUnavailable`] if any of the requests targets
+    /// the unavailable perpetual contract, see [`Self::ensure_available`].
+    pub(crate) fn ensure_requests_available(
+        &self,
+        requests: &[types::OrderRequest],
+    ) -> Result<(), DexError> {
+        requests
+            .iter()
+            .try_for_each(|r| self.ensure_available(r.perp_id()))
+            .map_err(DexError::from)
+    }
+
+    /// Known holders of the administrator role, see [`Self::role_holders`].
+    pub fn administrators(&self) -> &HashSet<Address> {
+        self.roles.holders(Role::Administrator)
+    }
+
+    /// Known holders of the price administrator role, see [`Self::role_holders`].
+    pub fn price_administrators(&self) -> &HashSet<Address> {
+        self.roles.holders(Role::PriceAdministrator)
+    }
+
+    /// Known holders of the position administrator role, see [`Self::role_holders`].
+    pub fn position_administrators(&self) -> &HashSet<Address> {
+        self.roles.holders(Role::PositionAdministrator)
+    }
+
+    /// Known holders of the role.
+    ///
+    /// Exchange smart contract does not allow to enumerate role holders, so only addresses
+    /// checked during snapshot creation with [`SnapshotBuilder::with_role_candidates`]
+    /// and addresses granted the role via events since are known.
+    pub fn role_holders(&self, role: Role) -> &HashSet<Address> {
+        self.roles.holders(role)
+    }
+
+    /// Indicates if only whitelisted addresses can create accounts.
+    pub fn is_whitelisting_enabled(&self) -> bool {
+        self.roles.is_whitelisting_enabled()
+    }
+
+    /// Estimates how the prospective order, if completely filled at its price, changes
+    /// the margin and position of the given account, using tracked perpetual parameters.
+    ///
+    /// Returns `None` if the account or perpetual contract is not tracked, or the request
+    /// does not place an order.
+    pub fn margin_impact(
+        &self,
+        account_id: types::AccountId,
+        request: &types::OrderRequest,
+    ) -> Option<MarginImpact> {
+        margin::margin_impact(
+            self.perpetuals.get(&request.perp_id())?,
+            self.accounts.get(&account_id)?,
+            request,
+        )
+    }
+
+    /// Previews how the prospective order, if completely filled at its price, nets against
+    /// the existing position of the given account: whether the position gets opened,
+    /// increased, decreased, closed or inverted, and the resulting entry price and deposit.
+    ///
+    /// Returns `None` if the account or perpetual contract is not tracked, or the request
+    /// does not place an order.
+    pub fn preview_order_effect(
+        &self,
+        account_id: types::AccountId,
+        request: &types::OrderRequest,
+    ) -> Option<OrderEffect> {
+        margin::order_effect(
+            self.perpetuals.get(&request.perp_id())?,
+            self.accounts.get(&account_id)?,
+            request,
+        )
+    }
+
+    /// Structured differences between this and the other state: orders, account balances,
+    /// positions and perpetual contract parameters present in only one of the states or
+    /// differing between them.
+    ///
+    /// Intended for verification of the state kept up to date with [`Self::apply_events`]
+    /// against a fresh snapshot taken at the same block. Data available only from events,
+    /// like order request IDs or FIFO links available only from snapshot, is not compared.
+    pub fn diff(&self, other: &Exchange) -> StateDiff {
+        diff::diff(self, other)
+    }
+
+    /// Deterministic Keccak-256 hash of the state at the current instant, letting
+    /// independently running indexers detect divergence by comparing hashes per block.
+    ///
+    /// Covers the block number, the halt flag, perpetual contract parameters, resting orders,
+    /// account balances and positions, limited to the data compared by [`Self::diff`],
+    /// so states built from a snapshot and from events hash the same. Matching hashes
+    /// require the same set of tracked accounts.
+    pub fn state_hash(&self) -> B256 {
+        hash::state_hash(self)
+    }
+
+    /// Updates index prices of tracked perpetual contracts from the source,
+    /// see [`Perpetual::index_price`].
+    ///
+    /// Returns the number of perpetual contracts with changed index price.
+    pub fn update_index_prices(&mut self, source: &(impl IndexPriceSource + ?Sized)) -> usize {
+        self.perpetuals
+            .values_mut()
+            .filter_map(|perp| {
+                source
+                    .index_price(perp.id())
+                    .map(|price| Arc::make_mut(perp).update_index_price(price))
+            })
+            .filter(|updated| *updated)
+            .count()
+    }
+
+    /// Registers interest in the top `depth` levels of each side of the perpetual contract
+    /// order book, replacing the previous registration if any.
+    ///
+    /// [`Self::apply_events`] then emits [`PerpetualEventType::TopLevelsChanged`] for the
+    /// perpetual contract once per block in which price or size of any of these levels changed,
+    /// so changes deeper in the book can be ignored.
+    pub fn watch_top_levels(
+        &mut self,
+        perpetual_id: types::PerpetualId,
+        depth: usize,
+    ) -> Result<(), DexError> {
+        let perp = self.perpetuals.get(&perpetual_id).ok_or_else(|| {
+            DexError::InvalidRequest(format!("unknown perpetual: {perpetual_id}"))
+        })?;
+        self.top_levels_watches
+            .insert(perpetual_id, TopLevelsWatch::new(depth, perp.l3_book()));
+        Ok(())
+    }
+
+    /// Removes interest in the top order book levels registered with [`Self::watch_top_levels`].
+    pub fn unwatch_top_levels(&mut self, perpetual_id: types::PerpetualId) {
+        self.top_levels_watches.remove(&perpetual_id);
+    }
+
+    /// Registers interest in the orders of the account lingering in the book, so that
+    /// [`Self::apply_events`] reports [`OrderEventType::Stale`] once per order when it
+    /// was not placed or modified for more than `older_than_blocks` blocks,
+    /// see [`Perpetual::stale_orders`].
+    ///
+    /// Lets quoting systems detect orders not refreshed within the intended interval,
+    /// e.g. with [`crate::notify::Condition::OrderStale`].
+    pub fn watch_stale_orders(&mut self, account_id: types::AccountId, older_than_blocks: u64) {
+        self.stale_orders_watches.insert(
+            account_id,
+            StaleOrdersWatch {
+                older_than_blocks,
+                reported: HashSet::new(),
+            },
+        );
+    }
+
+    /// Removes interest in the stale orders registered with [`Self::watch_stale_orders`].
+    pub fn unwatch_stale_orders(&mut self, account_id: types::AccountId) {
+        self.stale_orders_watches.remove(&account_id);
+    }
+
+    /// Fills missing addresses of tracked accounts, e.g. accounts discovered via positions
+    /// with [`SnapshotBuilder::with_all_positions`], by scanning `AccountCreated` logs
+    /// from the given block up to the snapshot instant.
+    ///
+    /// Returns the mapping of all accounts created within the scanned range, which can be
+    /// persisted and applied with [`Self::apply_account_addresses`] after restart, continuing
+    /// the scan from [`AccountAddresses::scanned_to_block`].
+    #[cfg(feature = "rpc")]
+    pub async fn backfill_account_addresses<P: Provider>(
+        &mut self,
+        provider: &P,
+        from_block: u64,
+    ) -> Result<AccountAddresses, DexError> {
+        let addresses = backfill::scan_account_addresses(
+            provider,
+            self.chain.exchange(),
+            from_block,
+            self.instant.block_number(),
+        )
+        .await?;
+        self.apply_account_addresses(&addresses);
+        Ok(addresses)
+    }
+
+    /// Fills missing addresses of tracked accounts from the known mapping,
+    /// adding all of its entries to the address index, see [`Self::account_id_by_address`].
+    ///
+    /// Returns the number of updated accounts.
+    pub fn apply_account_addresses(&mut self, addresses: &AccountAddresses) -> usize {
+        self.account_ids
+            .extend(addresses.iter().map(|(id, addr)| (addr, id)));
+        self.accounts
+            .values_mut()
+            .filter(|acc| acc.address() == Address::ZERO)
+            .filter_map(|acc| {
+                addresses
+                    .get(acc.id())
+                    .map(|addr| Arc::make_mut(acc).update_address(addr))
+            })
+            .count()
+    }
+
+    /// Takes a checkpoint of the current state, which can be restored later
+    /// with [`Self::rollback_to`].
+    ///
+    /// Only a bounded number of the most recent checkpoints is kept, see
+    /// [`SnapshotBuilder::with_checkpoints`] for the automatic checkpoints configuration.
+    ///
+    /// Checkpoints share the state of perpetual contracts, order books and accounts
+    /// with the current state, each one getting copied on its first mutation after
+    /// the checkpoint, so retained checkpoints only cost the parts changed since.
+    pub fn checkpoint(&mut self) {
+        let mut state = self.clone();
+        state.checkpoints = Checkpoints::new(0, 0);
+        self.checkpoints.push(state);
+    }
+
+    /// Instants of the available checkpoints, oldest first.
+    pub fn checkpoints(&self) -> impl Iterator<Item = types::StateInstant> + '_ {
+        self.checkpoints.instants()
+    }
+
+    /// Rewinds the state to the latest checkpoint taken at or before the given instant,
+    /// discarding newer checkpoints, e.g. when a chain reorganization is detected.
+    ///
+    /// Returns the instant of the restored state, events have to be re-applied
+    /// starting from the next block.
+    pub fn rollback_to(
+        &mut self,
+        instant: types::StateInstant,
+    ) -> Result<types::StateInstant, DexError> {
+        let restored = self
+            .checkpoints
+            .rewind(instant.block_number())
+            .ok_or(DexError::CheckpointNotFound(instant.block_number()))?;
+        let checkpoints = std::mem::take(&mut self.checkpoints);
+        *self = Arc::unwrap_or_clone(restored);
+        self.checkpoints = checkpoints;
+        Ok(self.instant)
+    }
+
+    pub(crate) fn set_checkpoints(&mut self, checkpoints: Checkpoints) {
+        self.checkpoints = checkpoints;
+    }
+
+    /// Enables position PnL recording for the current and future positions.
+    pub(crate) fn set_fund_history_retention(&mut self, retention: usize) {
+        self.fund_history.set_retention(retention);
+    }
+
+    pub(crate) fn set_protocol_balance(&mut self, balance: UD128) {
+        self.protocol_balance = balance;
+    }
+
+    pub(crate) fn set_pnl_history_retention(&mut self, retention: usize) {
+        self.pnl_history_retention = retention;
+        for acc in self.accounts.values_mut().map(Arc::make_mut) {
+            for pos in acc.positions_mut().values_mut() {
+                pos.set_pnl_history_retention(retention);
+            }
+        }
+    }
+
+    /// Address of the implementation the exchange proxy got upgraded to, if event application
+    /// is halted until [`Self::acknowledge_upgrade`], see [`SnapshotBuilder::with_halt_on_upgrade`].
+    pub fn pending_upgrade(&self) -> Option<Address> {
+        self.pending_upgrade
+    }
+
+    /// Resumes event application halted by the upgrade of the exchange implementation,
+    /// returning the address of the acknowledged implementation.
+    ///
+    /// Compatibility of the new implementation can be checked with [`Self::verify_revision`]
+    /// beforehand.
+    pub fn acknowledge_upgrade(&mut self) -> Option<Address> {
+        self.pending_upgrade.take()
+    }
+
+    pub(crate) fn set_halt_on_upgrade(&mut self, halt_on_upgrade: bool) {
+        self.halt_on_upgrade = halt_on_upgrade;
+    }
+
+    pub(crate) fn set_parallel_apply(&mut self, parallel_apply: bool) {
+        self.parallel_apply = parallel_apply;
+    }
+
+    pub(crate) fn set_crossed_book_check(&mut self, check: CrossedBookCheck) {
+        self.crossed_book_check = check;
+    }
+
+    pub(crate) fn set_pruning(&mut self, pruning: PruningPolicy) {
+        self.pruning = pruning;
+    }
+
+    /// Drops the history and idle accounts outside of the pruning policy,
+    /// see [`SnapshotBuilder::with_pruning`], regardless of the automatic compaction
+    /// schedule.
+    pub fn compact(&mut self) -> CompactionReport {
+        let block_number = self.instant.block_number();
+        self.last_compaction = block_number;
+        let mut report = CompactionReport::default();
+        if let Some(blocks) = self.pruning.history_blocks {
+            let before = block_number.saturating_sub(blocks);
+            report.history_records += self.fund_history.prune_before(before);
+            for perp in self.perpetuals.values_mut().map(Arc::make_mut) {
+                report.history_records += perp.prune_history(before);
+            }
+            for acc in self
+                .accounts
+                .values_mut()
+                .filter(|acc| !acc.positions().is_empty())
+                .map(Arc::make_mut)
+            {
+                for pos in acc.positions_mut().values_mut() {
+                    report.history_records += pos.prune_pnl_history(before);
+                }
+            }
+        }
+        if self.track_all_accounts
+            && let Some(blocks) = self.pruning.idle_account_blocks
+        {
+            let before = block_number.saturating_sub(blocks);
+            let with_orders = self
+                .perpetuals
+                .values()
+                .flat_map(|perp| perp.l3_book().all_orders().values())
+                .map(|order| order.account_id())
+                .collect::<HashSet<_>>();
+            let len = self.accounts.len();
+            self.accounts.retain(|id, acc| {
+                !acc.positions().is_empty()
+                    || !acc.locked_balance().is_zero()
+                    || with_orders.contains(id)
+                    || acc.instant().block_number() >= before
+            });
+            report.accounts = len - self.accounts.len();
+            if report.accounts > 0 {
+                self.accounts.shrink_to_fit();
+            }
+        }
+        report
+    }
+
+    pub(crate) fn set_roles(&mut self, roles: Roles) {
+        self.roles = roles;
+    }
+
+    pub(crate) fn tracks_all_accounts(&self) -> bool {
+        self.track_all_accounts
+    }
+
+    /// Replaces the state with the fresh one, keeping checkpoints, top levels watches,
+    /// observed block times, pending upgrade, parallel application mode, crossed book check
+    /// and pruning policy.
+    pub(crate) fn replace_state(&mut self, fresh: Exchange) {
+        let checkpoints = std::mem::take(&mut self.checkpoints);
+        let top_levels_watches = std::mem::take(&mut self.top_levels_watches);
+        let stale_orders_watches = std::mem::take(&mut self.stale_orders_watches);
+        let block_times = std::mem::take(&mut self.block_times);
+        let (halt_on_upgrade, pending_upgrade) = (self.halt_on_upgrade, self.pending_upgrade);
+        let (parallel_apply, crossed_book_check) = (self.parallel_apply, self.crossed_book_check);
+        let pruning = self.pruning;
+        *self = fresh;
+        self.checkpoints = checkpoints;
+        self.top_levels_watches = top_levels_watches;
+        self.stale_orders_watches = stale_orders_watches;
+        self.block_times = block_times;
+        self.halt_on_upgrade = halt_on_upgrade;
+        self.pending_upgrade = pending_upgrade;
+        self.parallel_apply = parallel_apply;
+        self.crossed_book_check = crossed_book_check;
+        self.pruning = pruning;
+    }
+
     /// Updates state snapshot by applying raw exchange events from the
     /// specific block.
     ///
@@ -145,7 +799,8 @@ impl Exchange {
     /// # Returns
     ///
     /// On success, list of state mutation and failure [`StateEvents`] produced from the original raw events,
-    /// filtered as described above and with numeric systems conversion applied.
+    /// filtered as described above and with numeric systems conversion applied, in deterministic order
+    /// and with sequence numbers assigned, see [`StateBlockEvents`].
     ///
     /// [`StateEvents`] are roughly resemble [`crate::abi::dex::Exchange::ExchangeEvents`] so corresponding
     /// smart contract documentation and raw event data for error responses could be helpful with debugging,
@@ -156,6 +811,13 @@ impl Exchange {
     /// On failure, the corresponding [`DexError`], any of which indicates some inconsistency in event sequence or
     /// event handling logic and should not be ignored as it may lead to state inconsistency.
     ///
+    /// The exception is [`DexError::UpgradePending`], returned without applying the block while the upgrade
+    /// of the exchange implementation awaits [`Self::acknowledge_upgrade`], after which the same block
+    /// can be applied again.
+    ///
+    /// With [`SnapshotBuilder::with_parallel_apply`], order flow of untracked accounts in different
+    /// perpetual contracts is applied on separate threads, producing the same state and events.
+    ///
     pub fn apply_events(
         &mut self,
         events: &stream::RawBlockEvents,
@@ -172,44 +834,280 @@ impl Exchange {
                 next_instant.block_number(),
             ));
         }
+        if let Some(implementation) = self.pending_upgrade {
+            return Err(DexError::UpgradePending(implementation));
+        }
+
+        let plan = (self.parallel_apply && !self.track_all_accounts)
+            .then(|| shard::plan(events.events(), |id| self.accounts.contains_key(&id)))
+            .flatten();
+        let mut sink = EventSink::with_capacity(events.events().len());
+        let mut state_events = if let Some(plan) = plan {
+            self.apply_sharded(next_instant, events.events(), plan, &mut sink)?
+        } else {
+            let mut state_events = Vec::with_capacity(events.events().len());
+            self.apply_raw_events(
+                next_instant,
+                events.events().iter().enumerate(),
+                &mut sink,
+                |_, e| state_events.push(e),
+            )?;
+            state_events
+        };
+
+        // Report watched top levels changes once per block
+        for (perp_id, watch) in self.top_levels_watches.iter_mut() {
+            if let Some(perp) = self.perpetuals.get(perp_id)
+                && watch.update(perp.l3_book())
+            {
+                sink.push(StateEvents::perpetual(
+                    perp,
+                    PerpetualEventType::TopLevelsChanged,
+                ));
+                state_events.extend(sink.commit().map(EventContext::empty));
+            }
+        }
+
+        // Commit instant, can produce its own set of events
+        self.instant = events.instant();
+        self.block_times.observe(self.instant);
+        let mut perp_ids = self.perpetuals.keys().copied().collect::<Vec<_>>();
+        perp_ids.sort_unstable();
+        let perp_events_start = state_events.len();
+        for perp_id in &perp_ids {
+            // Contracts not reaching the funding event are left shared with checkpoints
+            let perp = self.perpetuals.get_mut(perp_id).expect("known perpetual");
+            if !perp.is_funding_due(self.instant) {
+                continue;
+            }
+            sink.extend(Arc::make_mut(perp).update_funding_instant(self.instant));
+            state_events.extend(sink.commit().map(EventContext::empty));
+        }
+
+        // Applying produced state events as a second pass, indexing them in place
+        // rather than cloning every perpetual's events beforehand
+        for i in perp_events_start..state_events.len() {
+            for j in state_events[i].event.clone() {
+                let result = self.apply_state_event(self.instant, &sink.events()[j])?;
+                sink.extend(result);
+                state_events.extend(sink.commit().map(EventContext::empty));
+            }
+        }
+
+        // Report orders of watched accounts turned stale, once per order and modification
+        for (account_id, watch) in self.stale_orders_watches.iter_mut() {
+            let mut reported = HashSet::with_capacity(watch.reported.len());
+            for perp_id in &perp_ids {
+                let perp = &self.perpetuals[perp_id];
+                for order in perp.stale_orders(
+                    *account_id,
+                    self.instant.block_number(),
+                    watch.older_than_blocks,
+                ) {
+                    let key = (
+                        *perp_id,
+                        order.order_id(),
+                        order.modified_at().block_number(),
+                    );
+                    if !watch.reported.contains(&key) {
+                        sink.push(StateEvents::order(
+                            perp,
+                            order.order(),
+                            &None,
+                            OrderEventType::Stale {
+                                age_blocks: order
+                                    .blocks_since_modified(self.instant.block_number()),
+                            },
+                        ));
+                    }
+                    reported.insert(key);
+                }
+            }
+            watch.reported = reported;
+        }
+        state_events.extend(sink.commit().map(EventContext::empty));
+
+        sink.extend(self.check_crossed_books(&perp_ids));
+        state_events.extend(sink.commit().map(EventContext::empty));
+
+        self.update_position_aggregates(sink.events());
+
+        if self
+            .pruning
+            .is_due(self.last_compaction, self.instant.block_number())
+        {
+            self.compact();
+        }
+
+        if self.checkpoints.is_due(self.instant) {
+            self.checkpoint();
+        }
+
+        let mut block_events =
+            StateBlockEvents::with_buffer(self.instant, state_events, sink.into_events());
+        block_events.assign_sequence_numbers();
+        Ok(Some(block_events))
+    }
+
+    /// Reports books turned locked or crossed, once until they uncross,
+    /// see [`SnapshotBuilder::with_crossed_book_check`].
+    fn check_crossed_books(&mut self, perp_ids: &[types::PerpetualId]) -> Vec<StateEvents> {
+        if self.crossed_book_check == CrossedBookCheck::Disabled {
+            return vec![];
+        }
+        let mut events = Vec::new();
+        for perp_id in perp_ids {
+            let perp = self.perpetuals.get_mut(perp_id).expect("known perpetual");
+            if !perp.l3_book().is_crossed() {
+                self.crossed_books.remove(perp_id);
+            } else if self.crossed_books.insert(*perp_id)
+                && let Some(event) = crossing::check_book(
+                    *perp_id,
+                    Arc::make_mut(perp).l3_book_mut(),
+                    self.crossed_book_check,
+                )
+            {
+                events.push(StateEvents::BookCrossed(event));
+            }
+        }
+        events
+    }
 
-        // Apply events sequentially and accumulate produced state events,
-        // keeping intermediate context as many order events are incremental
+    /// Applies raw events sequentially passing on produced state events along with the index
+    /// of the raw event, keeping intermediate context as many order events are incremental.
+    fn apply_raw_events<'e>(
+        &mut self,
+        instant: types::StateInstant,
+        events: impl Iterator<Item = (usize, &'e stream::RawEvent)>,
+        sink: &mut EventSink,
+        mut output: impl FnMut(usize, EventContext<Range<usize>>),
+    ) -> Result<(), DexError> {
         let mut order_context: Option<OrderContext> = None;
         let mut prev_tx_index: Option<u64> = None;
-        let mut state_events = vec![];
-        for event in events.events() {
+        for (index, event) in events {
             if prev_tx_index.is_some_and(|idx| idx < event.tx_index()) {
                 // Reset order context at the transaction boundary
                 order_context.take();
             }
-            let result = self.apply_raw_event(next_instant, event, &mut order_context)?;
-            if !result.is_empty() {
-                state_events.push(event.pass(result));
+            self.apply_raw_event(instant, event, &mut order_context, sink)?;
+            sink.retain(|e| !self.is_untracked_balance_event(e));
+            let start = sink.start();
+            self.add_balance_changes(event.event(), sink.events_mut(), start);
+            if let Some(range) = sink.commit() {
+                output(index, event.pass(range));
             }
             prev_tx_index = Some(event.tx_index());
         }
+        Ok(())
+    }
 
-        // Commit instant, can produce its own set of events
-        self.instant = events.instant();
-        let mut perp_events = vec![];
-        for perp in self.perpetuals.values_mut() {
-            let result = perp.update_state_instant(self.instant);
-            if !result.is_empty() {
-                perp_events.push(result.clone());
-                state_events.push(EventContext::empty(result));
-            }
-        }
+    /// Applies lanes of the [`shard::ShardPlan`] in parallel, each to the exchange holding
+    /// only the perpetual contract of the lane, and the serial lane to this one,
+    /// merging produced events into the sink in the order of raw events.
+    fn apply_sharded(
+        &mut self,
+        instant: types::StateInstant,
+        events: &[stream::RawEvent],
+        plan: shard::ShardPlan,
+        sink: &mut EventSink,
+    ) -> Result<Vec<EventContext<Range<usize>>>, DexError> {
+        let mut lanes = plan
+            .lanes
+            .into_iter()
+            .map(|(perp_id, indices)| (self.lane(perp_id), indices))
+            .collect::<Vec<_>>();
+        // Serial lane goes first, each lane pushing into its own sink
+        let mut applied = vec![];
+        let mut serial_sink = EventSink::default();
+        let (serial, lane_results) = std::thread::scope(|s| {
+            let handles = lanes
+                .iter_mut()
+                .enumerate()
+                .map(|(lane_index, (lane, indices))| {
+                    s.spawn(move || {
+                        let mut applied = vec![];
+                        let mut sink = EventSink::default();
+                        lane.apply_raw_events(
+                            instant,
+                            indices.iter().map(|i| (*i, &events[*i])),
+                            &mut sink,
+                            |i, e| applied.push((i, lane_index + 1, e)),
+                        )
+                        .map(|_| (applied, sink))
+                    })
+                })
+                .collect::<Vec<_>>();
+            let serial = self.apply_raw_events(
+                instant,
+                plan.serial.iter().map(|i| (*i, &events[*i])),
+                &mut serial_sink,
+                |i, e| applied.push((i, 0, e)),
+            );
+            let lane_results = handles
+                .into_iter()
+                .map(|h| h.join().expect("lane application does not panic"))
+                .collect::<Vec<_>>();
+            (serial, lane_results)
+        });
 
-        // Applying produced state events as a second pass
-        for event in perp_events.iter().flatten() {
-            let result = self.apply_state_event(self.instant, event)?;
-            if !result.is_empty() {
-                state_events.push(EventContext::empty(result));
-            }
+        // Return perpetual contracts before reporting any failure
+        for (lane, _) in lanes {
+            self.perpetuals.extend(lane.perpetuals);
+        }
+        serial?;
+        let mut lane_events = vec![serial_sink.into_events().into_iter()];
+        for result in lane_results {
+            let (lane_applied, sink) = result?;
+            applied.extend(lane_applied);
+            lane_events.push(sink.into_events().into_iter());
         }
 
-        Ok(Some(StateBlockEvents::new(self.instant, state_events)))
+        // Contexts of each lane come in order, so moving events of the merged contexts
+        // out of the lane sinks one after another keeps them matching
+        applied.sort_unstable_by_key(|(i, _, _)| *i);
+        Ok(applied
+            .into_iter()
+            .filter_map(|(_, lane_index, ctx)| {
+                sink.extend(lane_events[lane_index].by_ref().take(ctx.event.len()));
+                sink.commit().map(|range| ctx.pass(range))
+            })
+            .collect())
+    }
+
+    /// Exchange holding only the perpetual contract taken out of this one and no accounts,
+    /// to apply the order flow of untracked accounts to.
+    fn lane(&mut self, perp_id: types::PerpetualId) -> Exchange {
+        Exchange {
+            chain: self.chain.clone(),
+            instant: self.instant,
+            collateral_converter: self.collateral_converter,
+            funding_interval_blocks: self.funding_interval_blocks,
+            min_post: self.min_post,
+            min_settle: self.min_settle,
+            recycle_fee: self.recycle_fee,
+            protocol_balance: self.protocol_balance,
+            fund_history: FundHistory::default(),
+            perpetuals: self.perpetuals.remove_entry(&perp_id).into_iter().collect(),
+            accounts: HashMap::new(),
+            account_ids: HashMap::new(),
+            position_index: PositionIndex::default(),
+            pnl_history_retention: self.pnl_history_retention,
+            is_halted: self.is_halted,
+            halt_on_upgrade: false,
+            pending_upgrade: None,
+            withdraw_rate_limit: self.withdraw_rate_limit,
+            roles: Roles::default(),
+            track_all_accounts: false,
+            top_levels_watches: BTreeMap::new(),
+            stale_orders_watches: BTreeMap::new(),
+            crossed_book_check: CrossedBookCheck::default(),
+            crossed_books: HashSet::new(),
+            block_times: BlockTimes::default(),
+            checkpoints: Checkpoints::default(),
+            parallel_apply: false,
+            pruning: PruningPolicy::default(),
+            last_compaction: self.last_compaction,
+        }
     }
 
     fn apply_raw_event(
@@ -217,7 +1115,8 @@ impl Exchange {
         instant: types::StateInstant,
         event: &stream::RawEvent,
         ctx: &mut Option<OrderContext>,
-    ) -> Result<Vec<StateEvents>, DexError> {
+        sink: &mut EventSink,
+    ) -> Result<(), DexError> {
         let cc = self.collateral_converter;
 
         let must_ctx = || {
@@ -227,48 +1126,40 @@ impl Exchange {
             ))
         };
 
-        Ok(match event.event() {
+        match event.event() {
             ExchangeEvents::AccountCreated(e) => {
+                self.account_ids.insert(e.account, e.id.to());
                 if self.track_all_accounts {
                     self.accounts.insert(
                         e.id.to(),
-                        Account::from_event(instant, e.id.to(), e.account),
+                        Arc::new(Account::from_event(instant, e.id.to(), e.account)),
                     );
-                    vec![StateEvents::Account(AccountEvent {
+                    sink.push(StateEvents::Account(AccountEvent {
                         account_id: e.id.to(),
                         request_id: None,
                         r#type: AccountEventType::Created(e.id.to()),
-                    })]
-                } else {
-                    vec![]
+                    }));
                 }
             }
-            ExchangeEvents::AccountFreeze(e) => self
-                .account(e.accountId)
-                .map(|acc| {
-                    acc.update_frozen(instant, e.status > 0);
-                    StateEvents::account(acc, ctx, AccountEventType::Frozen(acc.frozen()))
-                })
-                .into_iter()
-                .collect(),
-            ExchangeEvents::AccountFrozen(_) => self
-                .err_ctx(ctx, event)?
-                .map(|ctx| StateEvents::order_error(ctx, OrderErrorType::AccountFrozen))
-                .into_iter()
-                .collect(),
-            ExchangeEvents::AccountLiquidationCredit(e) => self
-                .account(e.accountId)
-                .map(|acc| {
+            ExchangeEvents::AccountFreeze(e) => sink.extend(self.account(e.accountId).map(|acc| {
+                acc.update_freeze_status(instant, e.status.into());
+                StateEvents::account(acc, ctx, AccountEventType::Frozen(acc.freeze_status()))
+            })),
+            ExchangeEvents::AccountFrozen(e) => sink.extend(self.err_ctx(ctx, event)?.map(|ctx| {
+                StateEvents::order_error(ctx, OrderErrorType::AccountFrozen(e.status.into()))
+            })),
+            ExchangeEvents::AccountLiquidationCredit(e) => {
+                sink.extend(self.account(e.accountId).map(|acc| {
                     acc.update_balance(instant, cc.from_unsigned(e.endBalanceCNS));
                     StateEvents::account(acc, ctx, AccountEventType::BalanceUpdated(acc.balance()))
-                })
-                .into_iter()
-                .collect(),
-            ExchangeEvents::AdminChanged(_) => vec![],
-            ExchangeEvents::AdministratorUpdated(_) => vec![],
-            ExchangeEvents::AmountExceedsAvailableBalance(e) => self
-                .err_ctx(ctx, event)?
-                .map(|ctx| {
+                }))
+            }
+            ExchangeEvents::AdminChanged(_) => {}
+            ExchangeEvents::AdministratorUpdated(e) => {
+                sink.extend(self.update_role(Role::Administrator, e.administator, e.added))
+            }
+            ExchangeEvents::AmountExceedsAvailableBalance(e) => {
+                sink.extend(self.err_ctx(ctx, event)?.map(|ctx| {
                     StateEvents::order_error(
                         ctx,
                         OrderErrorType::AmountExceedsAvailableBalance(
@@ -276,43 +1167,52 @@ impl Exchange {
                             cc.from_unsigned(e.availableBalanceCNS),
                         ),
                     )
-                })
-                .into_iter()
-                .collect(),
-            ExchangeEvents::BankruptcyPriceExceedsReferencePrice(_) => vec![],
-            ExchangeEvents::BeaconUpgraded(_) => vec![],
-            ExchangeEvents::BlockStatusChanged(_) => vec![],
-            ExchangeEvents::BorrowMarginNotMetAfterDecCollateral(_) => vec![],
-            ExchangeEvents::BuyToLiquidateSlippageExceeded(_) => vec![],
-            ExchangeEvents::BuyToLiquidateBuyerRestricted(_) => vec![],
-            ExchangeEvents::BuyToLiquidateParamsUpdated(_) => vec![],
-            ExchangeEvents::BuyToLiquidateThresholdUpdated(_) => vec![],
-            ExchangeEvents::BuyToLiquidateRestrictionUpdated(_) => vec![],
-            ExchangeEvents::CancelExistingInvalidCloseOrders(_) => self
-                .err_ctx(ctx, event)?
-                .map(|ctx| {
+                }))
+            }
+            ExchangeEvents::BankruptcyPriceExceedsReferencePrice(_) => {}
+            ExchangeEvents::BeaconUpgraded(_) => {}
+            ExchangeEvents::BlockStatusChanged(_) => {}
+            ExchangeEvents::BorrowMarginNotMetAfterDecCollateral(_) => {}
+            ExchangeEvents::BuyToLiquidateSlippageExceeded(_) => {}
+            ExchangeEvents::BuyToLiquidateBuyerRestricted(_) => {}
+            ExchangeEvents::BuyToLiquidateParamsUpdated(_) => {}
+            ExchangeEvents::BuyToLiquidateThresholdUpdated(_) => {}
+            ExchangeEvents::BuyToLiquidateRestrictionUpdated(_) => {}
+            ExchangeEvents::CancelExistingInvalidCloseOrders(_) => {
+                sink.extend(self.err_ctx(ctx, event)?.map(|ctx| {
                     StateEvents::order_error(ctx, OrderErrorType::CancelExistingInvalidCloseOrders)
-                })
-                .into_iter()
-                .collect(),
-            ExchangeEvents::CannotAdjustEntryPriceToDecCollateral(_) => vec![],
-            ExchangeEvents::CantBuyToLiquidate(_) => vec![],
-            ExchangeEvents::CantChangeCloseOrder(_) => self
-                .err_ctx(ctx, event)?
-                .map(|ctx| StateEvents::order_error(ctx, OrderErrorType::CantChangeCloseOrder))
-                .into_iter()
-                .collect(),
-            ExchangeEvents::CantDeleverageAgainstOpposingPositions(_) => vec![],
-            ExchangeEvents::CantLiquidatePosAboveMMR(_) => vec![],
-            ExchangeEvents::ChangeExpiredOrderNeedsNewExpiry(_) => self
-                .err_ctx(ctx, event)?
-                .map(|ctx| {
+                }))
+            }
+            ExchangeEvents::CannotAdjustEntryPriceToDecCollateral(_) => {}
+            ExchangeEvents::CantBuyToLiquidate(_) => {}
+            ExchangeEvents::CantChangeCloseOrder(_) => sink
+                .extend(self.err_ctx(ctx, event)?.map(|ctx| {
+                    StateEvents::order_error(ctx, OrderErrorType::CantChangeCloseOrder)
+                })),
+            ExchangeEvents::CantDeleverageAgainstOpposingPositions(e) => {
+                sink.extend(self.perpetual(e.perpId).map(|perp| {
+                    let failure = DeleverageFailure {
+                        instant,
+                        account_id: e.accountId.to(),
+                        kind: DeleverageFailureKind::NoOpposingPositions {
+                            force_close: e.forceClose,
+                            r#type: PositionType::from(e.positionType),
+                            price: perp.price_converter().from_unsigned(e.deleveragePricePNS),
+                            candidates: e.sortedPositionIds.len(),
+                        },
+                    };
+                    perp.record_deleverage_failure(failure);
+                    StateEvents::perpetual(perp, PerpetualEventType::DeleverageFailed(failure))
+                }))
+            }
+            ExchangeEvents::CantLiquidatePosAboveMMR(_) => {}
+            ExchangeEvents::ChangeExpiredOrderNeedsNewExpiry(_) => {
+                sink.extend(self.err_ctx(ctx, event)?.map(|ctx| {
                     StateEvents::order_error(ctx, OrderErrorType::ChangeExpiredOrderNeedsNewExpiry)
-                })
-                .into_iter()
-                .collect(),
-            ExchangeEvents::ClearedDecreaseCollatParams(_) => vec![],
-            ExchangeEvents::ClearingExpiredOrder(e) => chain!(
+                }))
+            }
+            ExchangeEvents::ClearedDecreaseCollatParams(_) => {}
+            ExchangeEvents::ClearingExpiredOrder(e) => sink.extend(chain!(
                 if let Some(perp) = self.perpetual(e.perpId) {
                     let order_id = std::num::NonZeroU16::new(e.orderId.to::<u16>())
                         .expect("orderId in event cannot be 0");
@@ -338,9 +1238,8 @@ impl Exchange {
                     acc.update_balance(instant, cc.from_unsigned(e.recyclerBalanceCNS));
                     StateEvents::account(acc, ctx, AccountEventType::BalanceUpdated(acc.balance()))
                 }),
-            )
-            .collect(),
-            ExchangeEvents::ClearingFrozenAccountOrder(e) => chain!(
+            )),
+            ExchangeEvents::ClearingFrozenAccountOrder(e) => sink.extend(chain!(
                 if let Some(perp) = self.perpetual(e.perpId) {
                     let order_id = std::num::NonZeroU16::new(e.orderId.to::<u16>())
                         .expect("orderId in event cannot be 0");
@@ -366,9 +1265,8 @@ impl Exchange {
                     acc.update_balance(instant, cc.from_unsigned(e.recyclerBalanceCNS));
                     StateEvents::account(acc, ctx, AccountEventType::BalanceUpdated(acc.balance()))
                 }),
-            )
-            .collect(),
-            ExchangeEvents::ClearingInvalidCloseOrder(e) => chain!(
+            )),
+            ExchangeEvents::ClearingInvalidCloseOrder(e) => sink.extend(chain!(
                 if let Some(perp) = self.perpetual(e.perpId) {
                     let order_id = std::num::NonZeroU16::new(e.orderId.to::<u16>())
                         .expect("orderId in event cannot be 0");
@@ -394,9 +1292,8 @@ impl Exchange {
                     acc.update_balance(instant, cc.from_unsigned(e.recyclerBalanceCNS));
                     StateEvents::account(acc, ctx, AccountEventType::BalanceUpdated(acc.balance()))
                 }),
-            )
-            .collect(),
-            ExchangeEvents::ClearingSelfMatchingOrder(e) => chain!(
+            )),
+            ExchangeEvents::ClearingSelfMatchingOrder(e) => sink.extend(chain!(
                 if let Some(perp) = self.perpetual(e.perpId) {
                     let order_id = std::num::NonZeroU16::new(e.orderId.to::<u16>())
                         .expect("orderId in event cannot be 0");
@@ -422,48 +1319,39 @@ impl Exchange {
                     acc.update_balance(instant, cc.from_unsigned(e.recyclerBalanceCNS));
                     StateEvents::account(acc, ctx, AccountEventType::BalanceUpdated(acc.balance()))
                 }),
-            )
-            .collect(),
-            ExchangeEvents::CloseOrderExceedsPosition(_) => self
-                .err_ctx(ctx, event)?
-                .map(|ctx| StateEvents::order_error(ctx, OrderErrorType::CloseOrderExceedsPosition))
-                .into_iter()
-                .collect(),
-            ExchangeEvents::CloseOrderPositionMismatch(_) => self
-                .err_ctx(ctx, event)?
-                .map(|ctx| {
+            )),
+            ExchangeEvents::CloseOrderExceedsPosition(_) => {
+                sink.extend(self.err_ctx(ctx, event)?.map(|ctx| {
+                    StateEvents::order_error(ctx, OrderErrorType::CloseOrderExceedsPosition)
+                }))
+            }
+            ExchangeEvents::CloseOrderPositionMismatch(_) => {
+                sink.extend(self.err_ctx(ctx, event)?.map(|ctx| {
                     StateEvents::order_error(ctx, OrderErrorType::CloseOrderPositionMismatch)
-                })
-                .into_iter()
-                .collect(),
-            ExchangeEvents::CollateralDecreaseApproved(_) => vec![],
-            ExchangeEvents::CollateralDecreaseDeclined(_) => vec![],
-            ExchangeEvents::CollateralDecreaseRequested(_) => vec![],
-            ExchangeEvents::CollateralDeposit(e) => self
-                .account(e.accountId)
-                .map(|acc| {
+                }))
+            }
+            ExchangeEvents::CollateralDecreaseApproved(_) => {}
+            ExchangeEvents::CollateralDecreaseDeclined(_) => {}
+            ExchangeEvents::CollateralDecreaseRequested(_) => {}
+            ExchangeEvents::CollateralDeposit(e) => {
+                sink.extend(self.account(e.accountId).map(|acc| {
                     acc.update_balance(instant, cc.from_unsigned(e.balanceCNS));
                     StateEvents::account(acc, ctx, AccountEventType::BalanceUpdated(acc.balance()))
-                })
-                .into_iter()
-                .collect(),
-            ExchangeEvents::CollateralWithdrawal(e) => self
-                .account(e.accountId)
-                .map(|acc| {
+                }))
+            }
+            ExchangeEvents::CollateralWithdrawal(e) => {
+                sink.extend(self.account(e.accountId).map(|acc| {
                     acc.update_balance(instant, cc.from_unsigned(e.balanceCNS));
                     StateEvents::account(acc, ctx, AccountEventType::BalanceUpdated(acc.balance()))
-                })
-                .into_iter()
-                .collect(),
-            ExchangeEvents::ContractAdded(_) => vec![],
-            ExchangeEvents::ContractIsPaused(_) => self
-                .err_ctx(ctx, event)?
-                .map(|ctx| StateEvents::order_error(ctx, OrderErrorType::ContractIsPaused))
-                .into_iter()
-                .collect(),
-            ExchangeEvents::ContractLinkFeedUpdated(e) => self
-                .perpetual(e.perpId)
-                .map(|perp| {
+                }))
+            }
+            ExchangeEvents::ContractAdded(_) => {}
+            ExchangeEvents::ContractIsPaused(_) => sink.extend(
+                self.err_ctx(ctx, event)?
+                    .map(|ctx| StateEvents::order_error(ctx, OrderErrorType::ContractIsPaused)),
+            ),
+            ExchangeEvents::ContractLinkFeedUpdated(e) => {
+                sink.extend(self.perpetual(e.perpId).map(|perp| {
                     perp.update_oracle_feed_id(instant, e.feedId);
                     StateEvents::perpetual(
                         perp,
@@ -472,45 +1360,49 @@ impl Exchange {
                             feed_id: perp.oracle_feed_id(),
                         },
                     )
-                })
-                .into_iter()
-                .collect(),
-            ExchangeEvents::ContractPaused(e) => self
-                .perpetual(e.perpId)
-                .map(|perp| {
+                }))
+            }
+            ExchangeEvents::ContractPaused(e) => {
+                sink.extend(self.perpetual(e.perpId).map(|perp| {
                     perp.update_paused(instant, e.paused);
                     StateEvents::perpetual(perp, PerpetualEventType::Paused(perp.is_paused()))
-                })
-                .into_iter()
-                .collect(),
-            ExchangeEvents::ContractRemoved(e) => self
-                .perpetual(e.perpId)
-                .map(|perp| {
+                }))
+            }
+            ExchangeEvents::ContractRemoved(e) => {
+                sink.extend(self.perpetual(e.perpId).map(|perp| {
                     perp.update_paused(instant, true);
                     StateEvents::perpetual(perp, PerpetualEventType::Paused(perp.is_paused()))
-                })
-                .into_iter()
-                .collect(),
-            ExchangeEvents::CrossesBook(_) => self
-                .err_ctx(ctx, event)?
-                .map(|ctx| StateEvents::order_error(ctx, OrderErrorType::CrossesBook))
-                .into_iter()
-                .collect(),
-            ExchangeEvents::DecreaseCollateralAmountOutOfRange(_) => vec![],
-            ExchangeEvents::DecreaseCollateralParamsExpired(_) => vec![],
-            ExchangeEvents::DecreaseCollateralPriceBeyondReference(_) => vec![],
-            ExchangeEvents::DeleveragePositionListEmpty(_) => vec![],
-            ExchangeEvents::ExceedsLastExecutionBlock(_) => self
-                .err_ctx(ctx, event)?
-                .map(|ctx| StateEvents::order_error(ctx, OrderErrorType::ExceedsLastExecutionBlock))
-                .into_iter()
-                .collect(),
+                }))
+            }
+            ExchangeEvents::CrossesBook(_) => sink.extend(
+                self.err_ctx(ctx, event)?
+                    .map(|ctx| StateEvents::order_error(ctx, OrderErrorType::CrossesBook)),
+            ),
+            ExchangeEvents::DecreaseCollateralAmountOutOfRange(_) => {}
+            ExchangeEvents::DecreaseCollateralParamsExpired(_) => {}
+            ExchangeEvents::DecreaseCollateralPriceBeyondReference(_) => {}
+            ExchangeEvents::DeleveragePositionListEmpty(e) => {
+                sink.extend(self.perpetual(e.perpId).map(|perp| {
+                    let failure = DeleverageFailure {
+                        instant,
+                        account_id: e.accountId.to(),
+                        kind: DeleverageFailureKind::EmptyPositionList,
+                    };
+                    perp.record_deleverage_failure(failure);
+                    StateEvents::perpetual(perp, PerpetualEventType::DeleverageFailed(failure))
+                }))
+            }
+            ExchangeEvents::ExceedsLastExecutionBlock(_) => {
+                sink.extend(self.err_ctx(ctx, event)?.map(|ctx| {
+                    StateEvents::order_error(ctx, OrderErrorType::ExceedsLastExecutionBlock)
+                }))
+            }
             ExchangeEvents::ExchangeHalted(e) => {
                 self.is_halted = e.halted;
-                vec![StateEvents::Exchange(ExchangeEvent::Halted(self.is_halted))]
+                sink.push(StateEvents::Exchange(ExchangeEvent::Halted(self.is_halted)));
             }
-            ExchangeEvents::FeeParamsUpdated(_) => vec![],
-            ExchangeEvents::FundingClampPctUpdated(_) => vec![],
+            ExchangeEvents::FeeParamsUpdated(_) => {}
+            ExchangeEvents::FundingClampPctUpdated(_) => {}
             ExchangeEvents::FundingEventCompleted(e) => {
                 if let Some(perp) = self.perpetual(e.perpId) {
                     perp.update_funding(
@@ -522,14 +1414,26 @@ impl Exchange {
                         e.fundingEventBlock.to(),
                     );
                 }
-                vec![]
-            }
-            ExchangeEvents::FundingEventSetTooEarly(_) => vec![],
-            ExchangeEvents::FundingPriceExceedsTol(_) => vec![],
-            ExchangeEvents::FundingSumAlreadySet(_) => vec![],
-            ExchangeEvents::IgnoreOracleUpdated(e) => self
-                .perpetual(e.perpId)
-                .map(|perp| {
+            }
+            ExchangeEvents::FundingEventSetTooEarly(_) => {}
+            ExchangeEvents::FundingPriceExceedsTol(e) => {
+                sink.extend(self.perpetual(e.perpId).map(|perp| {
+                    let pc = perp.price_converter();
+                    let breach = ToleranceBreach {
+                        instant,
+                        kind: ToleranceBreachKind::Funding {
+                            funding_price: pc.from_unsigned(e.fundingPricePNS),
+                            oracle_price: pc.from_unsigned(e.oraclePNS),
+                        },
+                        tolerance: perp.fee_converter().from_unsigned(e.tolerancePer100k),
+                    };
+                    perp.record_tolerance_breach(breach);
+                    StateEvents::perpetual(perp, PerpetualEventType::PriceToleranceExceeded(breach))
+                }))
+            }
+            ExchangeEvents::FundingSumAlreadySet(_) => {}
+            ExchangeEvents::IgnoreOracleUpdated(e) => {
+                sink.extend(self.perpetual(e.perpId).map(|perp| {
                     perp.update_is_oracle_used(instant, !e.ignOracle);
                     StateEvents::perpetual(
                         perp,
@@ -538,15 +1442,14 @@ impl Exchange {
                             feed_id: perp.oracle_feed_id(),
                         },
                     )
-                })
-                .into_iter()
-                .collect(),
-            ExchangeEvents::ImmediateOrCancelExecuted(_) => self
-                .err_ctx(ctx, event)?
-                .map(|ctx| StateEvents::order_error(ctx, OrderErrorType::ImmediateOrCancelExecuted))
-                .into_iter()
-                .collect(),
-            ExchangeEvents::IncreasePositionCollateral(e) => chain!(
+                }))
+            }
+            ExchangeEvents::ImmediateOrCancelExecuted(_) => {
+                sink.extend(self.err_ctx(ctx, event)?.map(|ctx| {
+                    StateEvents::order_error(ctx, OrderErrorType::ImmediateOrCancelExecuted)
+                }))
+            }
+            ExchangeEvents::IncreasePositionCollateral(e) => sink.extend(chain!(
                 self.position(e.accountId, e.perpId)?.map(|(pos, _)| {
                     pos.update_deposit(instant, cc.from_unsigned(e.positionDepositCNS));
                     StateEvents::position(
@@ -559,12 +1462,10 @@ impl Exchange {
                     acc.update_balance(instant, cc.from_unsigned(e.balanceCNS));
                     StateEvents::account(acc, ctx, AccountEventType::BalanceUpdated(acc.balance()))
                 }),
-            )
-            .collect(),
-            ExchangeEvents::Initialized(_) => vec![],
-            ExchangeEvents::InitialMarginFractionUpdated(e) => self
-                .perpetual(e.perpId)
-                .map(|perp| {
+            )),
+            ExchangeEvents::Initialized(_) => {}
+            ExchangeEvents::InitialMarginFractionUpdated(e) => {
+                sink.extend(self.perpetual(e.perpId).map(|perp| {
                     perp.update_initial_margin(
                         instant,
                         perp.leverage_converter()
@@ -574,42 +1475,41 @@ impl Exchange {
                         perp,
                         PerpetualEventType::InitialMarginFractionUpdated(perp.initial_margin()),
                     )
-                })
-                .into_iter()
-                .collect(),
-            ExchangeEvents::InsolventPositionCannotBeForcedClose(_) => vec![],
-            ExchangeEvents::InsuficientFundsForRecycleFee(_) => self
-                .err_ctx(ctx, event)?
-                .map(|ctx| {
+                }))
+            }
+            ExchangeEvents::InsolventPositionCannotBeForcedClose(_) => {}
+            ExchangeEvents::InsuficientFundsForRecycleFee(_) => {
+                sink.extend(self.err_ctx(ctx, event)?.map(|ctx| {
                     StateEvents::order_error(ctx, OrderErrorType::InsuficientFundsForRecycleFee)
-                })
-                .into_iter()
-                .collect(),
-            ExchangeEvents::InsufficientFundsToDecCollateral(_) => vec![],
-            ExchangeEvents::InsurancePaymentForSettlement(_) => vec![],
-            ExchangeEvents::InvalidAccountFrozenOrder(_) => vec![],
-            ExchangeEvents::InvalidBankruptcyPrice(_) => vec![],
-            ExchangeEvents::InvalidExpiryBlock(_) => self
-                .err_ctx(ctx, event)?
-                .map(|ctx| StateEvents::order_error(ctx, OrderErrorType::InvalidExpiryBlock))
-                .into_iter()
-                .collect(),
-            ExchangeEvents::InvalidLinkReportForContract(_) => vec![],
-            ExchangeEvents::InvalidLinkReportVersion(_) => vec![],
-            ExchangeEvents::InvalidLiquidationPrice(_) => vec![],
-            ExchangeEvents::InvalidOrderId(_) => self
-                .err_ctx(ctx, event)?
-                .map(|ctx| StateEvents::order_error(ctx, OrderErrorType::InvalidOrderId))
-                .into_iter()
-                .collect(),
-            ExchangeEvents::InvalidSynthPerpPrice(_) => vec![],
-            ExchangeEvents::LinkDatastreamConfigured(_) => vec![],
-            ExchangeEvents::LinkDsError_0(_) => vec![],
-            ExchangeEvents::LinkDsError_1(_) => vec![],
-            ExchangeEvents::LinkDsPanic(_) => vec![],
-            ExchangeEvents::LinkPriceUpdated(e) => self
-                .perpetual(e.perpId)
-                .map(|perp| {
+                }))
+            }
+            ExchangeEvents::InsufficientFundsToDecCollateral(_) => {}
+            ExchangeEvents::InsurancePaymentForSettlement(e) => sink.extend(self.change_fund(
+                instant,
+                Fund::Insurance(e.perpId.to()),
+                -cc.from_unsigned::<2>(e.insPaymentCNS).to_signed(),
+                FundChangeReason::SettlementPayment(e.accountId.to()),
+            )),
+            ExchangeEvents::InvalidAccountFrozenOrder(_) => {}
+            ExchangeEvents::InvalidBankruptcyPrice(_) => {}
+            ExchangeEvents::InvalidExpiryBlock(_) => sink.extend(
+                self.err_ctx(ctx, event)?
+                    .map(|ctx| StateEvents::order_error(ctx, OrderErrorType::InvalidExpiryBlock)),
+            ),
+            ExchangeEvents::InvalidLinkReportForContract(_) => {}
+            ExchangeEvents::InvalidLinkReportVersion(_) => {}
+            ExchangeEvents::InvalidLiquidationPrice(_) => {}
+            ExchangeEvents::InvalidOrderId(_) => sink.extend(
+                self.err_ctx(ctx, event)?
+                    .map(|ctx| StateEvents::order_error(ctx, OrderErrorType::InvalidOrderId)),
+            ),
+            ExchangeEvents::InvalidSynthPerpPrice(_) => {}
+            ExchangeEvents::LinkDatastreamConfigured(_) => {}
+            ExchangeEvents::LinkDsError_0(_) => {}
+            ExchangeEvents::LinkDsError_1(_) => {}
+            ExchangeEvents::LinkDsPanic(_) => {}
+            ExchangeEvents::LinkPriceUpdated(e) => {
+                sink.extend(self.perpetual(e.perpId).map(|perp| {
                     perp.update_oracle_price(
                         instant,
                         perp.price_converter().from_unsigned(e.oraclePricePNS),
@@ -618,19 +1518,16 @@ impl Exchange {
                         perp,
                         PerpetualEventType::OraclePriceUpdated(perp.oracle_price()),
                     )
-                })
-                .into_iter()
-                .collect(),
-            ExchangeEvents::LiquidationBuyerUpdated(_) => vec![],
-            ExchangeEvents::LiquidationParamsUpdated(_) => vec![],
-            ExchangeEvents::LotOutOfRange(_) => self
-                .err_ctx(ctx, event)?
-                .map(|ctx| StateEvents::order_error(ctx, OrderErrorType::SizeOutOfRange))
-                .into_iter()
-                .collect(),
-            ExchangeEvents::MaintenanceMarginFractionUpdated(e) => self
-                .perpetual(e.perpId)
-                .map(|perp| {
+                }))
+            }
+            ExchangeEvents::LiquidationBuyerUpdated(_) => {}
+            ExchangeEvents::LiquidationParamsUpdated(_) => {}
+            ExchangeEvents::LotOutOfRange(_) => sink.extend(
+                self.err_ctx(ctx, event)?
+                    .map(|ctx| StateEvents::order_error(ctx, OrderErrorType::SizeOutOfRange)),
+            ),
+            ExchangeEvents::MaintenanceMarginFractionUpdated(e) => {
+                sink.extend(self.perpetual(e.perpId).map(|perp| {
                     perp.update_maintenance_margin(
                         instant,
                         perp.leverage_converter()
@@ -642,12 +1539,10 @@ impl Exchange {
                             perp.maintenance_margin(),
                         ),
                     )
-                })
-                .into_iter()
-                .collect(),
-            ExchangeEvents::MakerFeeUpdated(e) => self
-                .perpetual(e.perpId)
-                .map(|perp| {
+                }))
+            }
+            ExchangeEvents::MakerFeeUpdated(e) => {
+                sink.extend(self.perpetual(e.perpId).map(|perp| {
                     perp.update_maker_fee(
                         instant,
                         perp.fee_converter().from_unsigned(e.makerFeePer100K),
@@ -656,16 +1551,15 @@ impl Exchange {
                         perp,
                         PerpetualEventType::MakerFeeUpdated(perp.maker_fee()),
                     )
-                })
-                .into_iter()
-                .collect(),
-            ExchangeEvents::MakerOrderFilled(e) => chain!(
+                }))
+            }
+            ExchangeEvents::MakerOrderFilled(e) => {
                 if let Some((perp, order)) = self.order(e.perpId, e.orderId)? {
                     let fill_price = perp.price_converter().from_unsigned(e.pricePNS);
                     let fill_size = perp.size_converter().from_unsigned(e.lotLNS);
                     let fee = cc.from_unsigned(e.feeCNS);
                     perp.update_last_price(instant, fill_price);
-                    vec![
+                    sink.extend([
                         if order.size() > fill_size {
                             let new_size = order.size() - fill_size;
                             perp.update_order(order.updated(
@@ -699,31 +1593,35 @@ impl Exchange {
                                 fill_size,
                                 fee,
                                 is_maker: true,
+                                fill_id: event.fill_id(),
                             },
                         ),
                         StateEvents::perpetual(
                             perp,
                             PerpetualEventType::LastPriceUpdated(perp.last_price()),
                         ),
-                    ]
-                } else {
-                    vec![]
-                },
-                self.account(e.accountId).map(|acc| {
-                    acc.update_locked_balance(instant, cc.from_unsigned(e.lockedBalanceCNS));
-                    StateEvents::account(
-                        acc,
-                        ctx,
-                        AccountEventType::LockedBalanceUpdated(acc.locked_balance()),
-                    )
-                }),
-                self.account(e.accountId).map(|acc| {
-                    acc.update_balance(instant, cc.from_unsigned(e.balanceCNS));
-                    StateEvents::account(acc, ctx, AccountEventType::BalanceUpdated(acc.balance()))
-                }),
-            )
-            .collect(),
-            ExchangeEvents::MakerOrderSettlementFailed(e) => chain!(
+                    ]);
+                }
+                sink.extend(chain!(
+                    self.account(e.accountId).map(|acc| {
+                        acc.update_locked_balance(instant, cc.from_unsigned(e.lockedBalanceCNS));
+                        StateEvents::account(
+                            acc,
+                            ctx,
+                            AccountEventType::LockedBalanceUpdated(acc.locked_balance()),
+                        )
+                    }),
+                    self.account(e.accountId).map(|acc| {
+                        acc.update_balance(instant, cc.from_unsigned(e.balanceCNS));
+                        StateEvents::account(
+                            acc,
+                            ctx,
+                            AccountEventType::BalanceUpdated(acc.balance()),
+                        )
+                    }),
+                ));
+            }
+            ExchangeEvents::MakerOrderSettlementFailed(e) => sink.extend(chain!(
                 if let Some(perp) = self.perpetual(e.perpId) {
                     let order_id = std::num::NonZeroU16::new(e.orderId.to::<u16>())
                         .expect("orderId in event cannot be 0");
@@ -758,9 +1656,22 @@ impl Exchange {
                     acc.update_balance(instant, cc.from_unsigned(e.recyclerBalanceCNS));
                     StateEvents::account(acc, ctx, AccountEventType::BalanceUpdated(acc.balance()))
                 }),
-            )
-            .collect(),
-            ExchangeEvents::MarkExceedsTol(_) => vec![],
+            )),
+            ExchangeEvents::MarkExceedsTol(e) => {
+                sink.extend(self.perpetual(e.perpId).map(|perp| {
+                    let pc = perp.price_converter();
+                    let breach = ToleranceBreach {
+                        instant,
+                        kind: ToleranceBreachKind::Mark {
+                            mark_price: pc.from_unsigned(e.markPNS),
+                            synthetic_price: pc.from_unsigned(e.synthPerpPricePNS),
+                        },
+                        tolerance: perp.fee_converter().from_unsigned(e.tolerancePer100k),
+                    };
+                    perp.record_tolerance_breach(breach);
+                    StateEvents::perpetual(perp, PerpetualEventType::PriceToleranceExceeded(breach))
+                }))
+            }
             ExchangeEvents::MarkUpdated(e) => {
                 let perp_mark = self.perpetual(e.perpId).map(|perp| {
                     perp.update_mark_price(
@@ -770,100 +1681,95 @@ impl Exchange {
                     (perp.id(), perp.mark_price())
                 });
                 if let Some((perp_id, mark_price)) = perp_mark {
-                    chain!(
-                        Some(StateEvents::Perpetual(PerpetualEvent {
-                            perpetual_id: perp_id,
-                            r#type: PerpetualEventType::MarkPriceUpdated(mark_price),
-                        })),
-                        // Applying updated mark to all tracked positions
-                        self.accounts.values_mut().filter_map(|acc| {
-                            acc.positions_mut().get_mut(&perp_id).map(|pos| {
-                                pos.apply_mark_price(instant, mark_price);
-                                StateEvents::position(
-                                    pos,
-                                    &None,
-                                    PositionEventType::UnrealizedPnLUpdated {
-                                        pnl: pos.pnl(),
-                                        delta_pnl: pos.delta_pnl(),
-                                        premium_pnl: pos.premium_pnl(),
-                                    },
-                                )
-                            })
-                        }),
-                    )
-                    .collect()
-                } else {
-                    vec![]
+                    sink.push(StateEvents::Perpetual(PerpetualEvent {
+                        perpetual_id: perp_id,
+                        r#type: PerpetualEventType::MarkPriceUpdated(mark_price),
+                    }));
+                    // Applying updated mark to all tracked positions
+                    let start = sink.events_mut().len();
+                    sink.extend(
+                        self.accounts
+                            .values_mut()
+                            .filter(|acc| acc.positions().contains_key(&perp_id))
+                            .map(Arc::make_mut)
+                            .filter_map(|acc| {
+                                acc.positions_mut().get_mut(&perp_id).map(|pos| {
+                                    pos.apply_mark_price(instant, mark_price);
+                                    StateEvents::position(
+                                        pos,
+                                        &None,
+                                        PositionEventType::UnrealizedPnLUpdated {
+                                            pnl: pos.pnl(),
+                                            delta_pnl: pos.delta_pnl(),
+                                            premium_pnl: pos.premium_pnl(),
+                                        },
+                                    )
+                                })
+                            }),
+                    );
+                    sort_by_account(&mut sink.events_mut()[start..]);
                 }
             }
-            ExchangeEvents::MaxMatchesReached(_) => self
-                .err_ctx(ctx, event)?
-                .map(|ctx| StateEvents::order_error(ctx, OrderErrorType::MaxMatchesReached))
-                .into_iter()
-                .collect(),
-            ExchangeEvents::MaxOpenInterestUpdated(_) => vec![],
-            ExchangeEvents::MaximumAccountOrders(_) => self
-                .err_ctx(ctx, event)?
-                .map(|ctx| StateEvents::order_error(ctx, OrderErrorType::MaximumAccountOrders))
-                .into_iter()
-                .collect(),
-            ExchangeEvents::MinAccountOpenAmountUpdated(_) => vec![],
+            ExchangeEvents::MaxMatchesReached(_) => sink.extend(
+                self.err_ctx(ctx, event)?
+                    .map(|ctx| StateEvents::order_error(ctx, OrderErrorType::MaxMatchesReached)),
+            ),
+            ExchangeEvents::MaxOpenInterestUpdated(_) => {}
+            ExchangeEvents::MaximumAccountOrders(_) => sink
+                .extend(self.err_ctx(ctx, event)?.map(|ctx| {
+                    StateEvents::order_error(ctx, OrderErrorType::MaximumAccountOrders)
+                })),
+            ExchangeEvents::MinAccountOpenAmountUpdated(_) => {}
             ExchangeEvents::MinPostUpdated(e) => {
                 self.min_post = cc.from_unsigned(e.minPostCNS);
-                vec![StateEvents::Exchange(ExchangeEvent::MinPostUpdated(
+                sink.push(StateEvents::Exchange(ExchangeEvent::MinPostUpdated(
                     self.min_post,
-                ))]
+                )));
             }
             ExchangeEvents::MinSettleUpdated(e) => {
                 self.min_settle = cc.from_unsigned(e.minSettleCNS);
-                vec![StateEvents::Exchange(ExchangeEvent::MinSettleUpdated(
+                sink.push(StateEvents::Exchange(ExchangeEvent::MinSettleUpdated(
                     self.min_settle,
-                ))]
+                )));
             }
-            ExchangeEvents::OracleAgeExceedsMax(_) => vec![],
-            ExchangeEvents::OracleDisabled(_) => vec![],
+            ExchangeEvents::OracleAgeExceedsMax(_) => {}
+            ExchangeEvents::OracleDisabled(_) => {}
             ExchangeEvents::OrderBatchCompleted(_) => {
                 // Reset context
                 ctx.take();
-                vec![]
             }
             ExchangeEvents::OrderCancelled(e) => {
                 let c = must_ctx()?;
                 let order_id = c.order_id.expect("order_id required for OrderCancelled");
-                chain!(
-                    if let Some(perp) = self.perpetuals.get_mut(&c.perpetual_id) {
-                        let order = perp.remove_order(order_id)?;
-                        Some(StateEvents::order(
-                            perp,
-                            &order,
+                if let Some(perp) = self.perpetuals.get_mut(&c.perpetual_id) {
+                    let perp = Arc::make_mut(perp);
+                    let order = perp.remove_order(order_id)?;
+                    sink.push(StateEvents::order(
+                        perp,
+                        &order,
+                        ctx,
+                        OrderEventType::Removed,
+                    ));
+                }
+                if let Some(acc) = self.accounts.get_mut(&c.account_id) {
+                    let acc = Arc::make_mut(acc);
+                    acc.update_locked_balance(instant, cc.from_unsigned(e.lockedBalanceCNS));
+                    acc.update_balance(instant, cc.from_unsigned(e.balanceCNS));
+                    sink.extend([
+                        StateEvents::account(
+                            acc,
                             ctx,
-                            OrderEventType::Removed,
-                        ))
-                    } else {
-                        None
-                    },
-                    if let Some(acc) = self.accounts.get_mut(&c.account_id) {
-                        acc.update_locked_balance(instant, cc.from_unsigned(e.lockedBalanceCNS));
-                        acc.update_balance(instant, cc.from_unsigned(e.balanceCNS));
-                        vec![
-                            StateEvents::account(
-                                acc,
-                                ctx,
-                                AccountEventType::LockedBalanceUpdated(acc.locked_balance()),
-                            ),
-                            StateEvents::account(
-                                acc,
-                                ctx,
-                                AccountEventType::BalanceUpdated(acc.balance()),
-                            ),
-                        ]
-                    } else {
-                        vec![]
-                    },
-                )
-                .collect()
+                            AccountEventType::LockedBalanceUpdated(acc.locked_balance()),
+                        ),
+                        StateEvents::account(
+                            acc,
+                            ctx,
+                            AccountEventType::BalanceUpdated(acc.balance()),
+                        ),
+                    ]);
+                }
             }
-            ExchangeEvents::OrderCancelledByAdmin(e) => chain!(
+            ExchangeEvents::OrderCancelledByAdmin(e) => sink.extend(chain!(
                 self.order(e.perpId, e.orderId)?.map(|(perp, order)| {
                     perp.remove_order(order.order_id()).expect("order exists");
                     StateEvents::order(perp, &order, ctx, OrderEventType::Removed)
@@ -876,9 +1782,8 @@ impl Exchange {
                         AccountEventType::LockedBalanceUpdated(acc.locked_balance()),
                     )
                 }),
-            )
-            .collect(),
-            ExchangeEvents::OrderCancelledByLiquidator(e) => chain!(
+            )),
+            ExchangeEvents::OrderCancelledByLiquidator(e) => sink.extend(chain!(
                 self.order(e.perpId, e.orderId)?.map(|(perp, order)| {
                     perp.remove_order(order.order_id()).expect("order exists");
                     StateEvents::order(perp, &order, ctx, OrderEventType::Removed)
@@ -891,199 +1796,185 @@ impl Exchange {
                         AccountEventType::LockedBalanceUpdated(acc.locked_balance()),
                     )
                 }),
-            )
-            .collect(),
+            )),
             ExchangeEvents::OrderChanged(e) => {
                 let c = must_ctx()?;
                 let order_id = c.order_id.expect("order_id required for OrderChanged");
-                chain!(
-                    if let Some(perp) = self.perpetuals.get_mut(&c.perpetual_id) {
-                        let order = perp
-                            .get_order(order_id)
-                            .copied()
-                            .ok_or(DexError::OrderNotFound(perp.id(), order_id))?;
-                        let new_price = perp.price_converter().from_unsigned(e.pricePNS);
-                        let new_size = perp.size_converter().from_unsigned(e.lotLNS);
-                        let new_expiry_block = e.expiryBlock.to();
-                        let price_update = if order.price() != new_price {
-                            Some(new_price)
-                        } else {
-                            None
-                        };
-                        let size_update = if order.size() != new_size {
-                            Some(new_size)
-                        } else {
-                            None
-                        };
-                        let expiry_block_update = if order.expiry_block() != new_expiry_block {
-                            Some(new_expiry_block)
-                        } else {
-                            None
-                        };
-                        let updated = order.updated(
-                            instant,
-                            ctx,
-                            price_update,
-                            size_update,
-                            expiry_block_update,
-                        );
-                        perp.update_order(updated)?;
-                        Some(StateEvents::order(
-                            perp,
-                            &order,
-                            ctx,
-                            OrderEventType::Updated {
-                                price: price_update,
-                                size: size_update,
-                                expiry_block: expiry_block_update,
-                            },
-                        ))
+                if let Some(perp) = self.perpetuals.get_mut(&c.perpetual_id) {
+                    let perp = Arc::make_mut(perp);
+                    let order = perp
+                        .get_order(order_id)
+                        .copied()
+                        .ok_or(DexError::OrderNotFound(perp.id(), order_id))?;
+                    let new_price = perp.price_converter().from_unsigned(e.pricePNS);
+                    let new_size = perp.size_converter().from_unsigned(e.lotLNS);
+                    let new_expiry_block = e.expiryBlock.to();
+                    let price_update = if order.price() != new_price {
+                        Some(new_price)
                     } else {
                         None
-                    },
-                    if let Some(acc) = self.accounts.get_mut(&c.account_id) {
-                        acc.update_locked_balance(instant, cc.from_unsigned(e.lockedBalanceCNS));
-                        acc.update_balance(instant, cc.from_unsigned(e.balanceCNS));
-                        vec![
-                            StateEvents::account(
-                                acc,
-                                ctx,
-                                AccountEventType::LockedBalanceUpdated(acc.locked_balance()),
-                            ),
-                            StateEvents::account(
-                                acc,
-                                ctx,
-                                AccountEventType::BalanceUpdated(acc.balance()),
-                            ),
-                        ]
+                    };
+                    let size_update = if order.size() != new_size {
+                        Some(new_size)
                     } else {
-                        vec![]
-                    },
-                )
-                .collect()
+                        None
+                    };
+                    let expiry_block_update = if order.expiry_block() != new_expiry_block {
+                        Some(new_expiry_block)
+                    } else {
+                        None
+                    };
+                    let updated =
+                        order.updated(instant, ctx, price_update, size_update, expiry_block_update);
+                    perp.modify_order(updated)?;
+                    sink.push(StateEvents::order(
+                        perp,
+                        &order,
+                        ctx,
+                        OrderEventType::Updated {
+                            price: price_update,
+                            size: size_update,
+                            expiry_block: expiry_block_update,
+                        },
+                    ));
+                }
+                if let Some(acc) = self.accounts.get_mut(&c.account_id) {
+                    let acc = Arc::make_mut(acc);
+                    acc.update_locked_balance(instant, cc.from_unsigned(e.lockedBalanceCNS));
+                    acc.update_balance(instant, cc.from_unsigned(e.balanceCNS));
+                    sink.extend([
+                        StateEvents::account(
+                            acc,
+                            ctx,
+                            AccountEventType::LockedBalanceUpdated(acc.locked_balance()),
+                        ),
+                        StateEvents::account(
+                            acc,
+                            ctx,
+                            AccountEventType::BalanceUpdated(acc.balance()),
+                        ),
+                    ]);
+                }
+            }
+            ExchangeEvents::OrderDescIdTooLow(_) => {}
+            ExchangeEvents::OrderDoesNotExist(_) => sink.extend(
+                self.err_ctx(ctx, event)?
+                    .map(|ctx| StateEvents::order_error(ctx, OrderErrorType::OrderDoesNotExist)),
+            ),
+            ExchangeEvents::OrderForwardingNotAllowed(_) => {
+                // Forwarding may be rejected before any order request is logged
+                sink.extend(self.err_ctx(ctx, event).ok().flatten().map(|ctx| {
+                    StateEvents::order_error(ctx, OrderErrorType::OrderForwardingNotAllowed)
+                }))
+            }
+            ExchangeEvents::OrderForwardingUpdated(e) => {
+                sink.extend(self.account(e.accountId).map(|acc| {
+                    acc.update_order_forwarding(instant, e.allowed);
+                    StateEvents::account(acc, ctx, AccountEventType::OrderForwarding(e.allowed))
+                }))
             }
-            ExchangeEvents::OrderDescIdTooLow(_) => vec![],
-            ExchangeEvents::OrderDoesNotExist(_) => self
-                .err_ctx(ctx, event)?
-                .map(|ctx| StateEvents::order_error(ctx, OrderErrorType::OrderDoesNotExist))
-                .into_iter()
-                .collect(),
-            ExchangeEvents::OrderForwardingNotAllowed(_) => vec![],
-            ExchangeEvents::OrderForwardingUpdated(_) => vec![],
             ExchangeEvents::OrderPlaced(e) => {
                 let c = must_ctx()?;
                 let order_id = std::num::NonZeroU16::new(e.orderId.to::<u16>())
                     .expect("orderId in OrderPlaced event cannot be 0");
-                chain!(
-                    if let Some(perp) = self.perpetuals.get_mut(&c.perpetual_id) {
-                        let order = Order::placed(
-                            instant,
-                            c,
-                            order_id,
-                            perp.size_converter().from_unsigned(e.lotLNS),
-                            perp.price_converter(),
-                            perp.leverage_converter(),
-                        );
-                        let event = OrderEventType::Placed {
-                            r#type: order.r#type(),
-                            price: order.price(),
-                            size: order.size(),
-                            expiry_block: order.expiry_block(),
-                            leverage: order.leverage(),
-                            post_only: order.post_only().unwrap_or_default(),
-                            fill_or_kill: order.fill_or_kill().unwrap_or_default(),
-                            immediate_or_cancel: order.immediate_or_cancel().unwrap_or_default(),
-                        };
-                        perp.add_order(order)?;
-                        Some(StateEvents::order(perp, &order, ctx, event))
-                    } else {
-                        None
-                    },
-                    if let Some(acc) = self.accounts.get_mut(&c.account_id) {
-                        acc.update_locked_balance(instant, cc.from_unsigned(e.lockedBalanceCNS));
-                        acc.update_balance(instant, cc.from_unsigned(e.balanceCNS));
-                        vec![
-                            StateEvents::account(
-                                acc,
-                                ctx,
-                                AccountEventType::LockedBalanceUpdated(acc.locked_balance()),
-                            ),
-                            StateEvents::account(
-                                acc,
-                                ctx,
-                                AccountEventType::BalanceUpdated(acc.balance()),
-                            ),
-                        ]
-                    } else {
-                        vec![]
-                    },
-                )
-                .collect()
+                if let Some(perp) = self.perpetuals.get_mut(&c.perpetual_id) {
+                    let perp = Arc::make_mut(perp);
+                    let order = Order::placed(
+                        instant,
+                        c,
+                        order_id,
+                        perp.size_converter().from_unsigned(e.lotLNS),
+                        perp.price_converter(),
+                        perp.leverage_converter(),
+                    );
+                    let event = OrderEventType::Placed {
+                        r#type: order.r#type(),
+                        price: order.price(),
+                        size: order.size(),
+                        expiry_block: order.expiry_block(),
+                        leverage: order.leverage(),
+                        post_only: order.post_only().unwrap_or_default(),
+                        fill_or_kill: order.fill_or_kill().unwrap_or_default(),
+                        immediate_or_cancel: order.immediate_or_cancel().unwrap_or_default(),
+                    };
+                    perp.add_order(order)?;
+                    sink.push(StateEvents::order(perp, &order, ctx, event));
+                }
+                if let Some(acc) = self.accounts.get_mut(&c.account_id) {
+                    let acc = Arc::make_mut(acc);
+                    acc.update_locked_balance(instant, cc.from_unsigned(e.lockedBalanceCNS));
+                    acc.update_balance(instant, cc.from_unsigned(e.balanceCNS));
+                    sink.extend([
+                        StateEvents::account(
+                            acc,
+                            ctx,
+                            AccountEventType::LockedBalanceUpdated(acc.locked_balance()),
+                        ),
+                        StateEvents::account(
+                            acc,
+                            ctx,
+                            AccountEventType::BalanceUpdated(acc.balance()),
+                        ),
+                    ]);
+                }
+            }
+            ExchangeEvents::OrderPostFailed(e) => {
+                sink.extend(self.err_ctx(ctx, event)?.map(|ctx| {
+                    StateEvents::order_error(
+                        ctx,
+                        OrderErrorType::OrderPostFailed(e.reason.saturating_to::<u16>().into()),
+                    )
+                }))
             }
-            ExchangeEvents::OrderPostFailed(e) => self
-                .err_ctx(ctx, event)?
-                .map(|ctx| {
-                    StateEvents::order_error(ctx, OrderErrorType::OrderPostFailed(e.reason.to()))
-                })
-                .into_iter()
-                .collect(),
             ExchangeEvents::OrderRequest(e) => {
                 // Store order request context as it is required to handle
                 // future events
                 ctx.replace(OrderContext::from(e));
-                vec![]
             }
-            ExchangeEvents::OrderSettlementImpliesInsolvent(_) => self
-                .err_ctx(ctx, event)?
-                .map(|ctx| {
+            ExchangeEvents::OrderSettlementImpliesInsolvent(_) => {
+                sink.extend(self.err_ctx(ctx, event)?.map(|ctx| {
                     StateEvents::order_error(ctx, OrderErrorType::OrderSettlementImpliesInsolvent)
-                })
-                .into_iter()
-                .collect(),
-            ExchangeEvents::OrderSizeExceedsAvailableSize(_) => self
-                .err_ctx(ctx, event)?
-                .map(|ctx| {
+                }))
+            }
+            ExchangeEvents::OrderSizeExceedsAvailableSize(_) => {
+                sink.extend(self.err_ctx(ctx, event)?.map(|ctx| {
                     StateEvents::order_error(ctx, OrderErrorType::OrderSizeExceedsAvailableSize)
-                })
-                .into_iter()
-                .collect(),
-            ExchangeEvents::OverCollatDescentThreshUpdated(_) => vec![],
-            ExchangeEvents::OwnershipTransferStarted(_) => vec![],
-            ExchangeEvents::OwnershipTransferred(_) => vec![],
-            ExchangeEvents::PermissonedCancelParamsUpdated(_) => vec![],
-            ExchangeEvents::PositionAdministratorUpdated(_) => vec![],
+                }))
+            }
+            ExchangeEvents::OverCollatDescentThreshUpdated(_) => {}
+            ExchangeEvents::OwnershipTransferStarted(_) => {}
+            ExchangeEvents::OwnershipTransferred(_) => {}
+            ExchangeEvents::PermissonedCancelParamsUpdated(_) => {}
+            ExchangeEvents::PositionAdministratorUpdated(e) => sink.extend(self.update_role(
+                Role::PositionAdministrator,
+                e.positionAdministrator,
+                e.added,
+            )),
             ExchangeEvents::PositionClosed(e) => {
                 if let Some((acc, perp)) = self.account_perpetual(e.accountId, e.perpId) {
                     let pos = acc
                         .positions_mut()
                         .remove(&perp.id())
                         .ok_or(DexError::PositionNotFound(acc.id(), perp.id()))?;
-                    chain!(
-                        Some(StateEvents::position(
-                            &pos,
-                            ctx,
-                            PositionEventType::Closed {
-                                r#type: pos.r#type(),
-                                entry_price: pos.entry_price(),
-                                exit_price: perp.price_converter().from_unsigned(e.pricePNS),
-                                size: pos.size(),
-                                delta_pnl: cc.from_signed(e.deltaPnlCNS),
-                                premium_pnl: cc.from_signed(e.fundingCNS),
-                            }
-                        )),
-                        if PositionType::from(e.positionType) == PositionType::Long {
-                            perp.update_open_interest(instant, pos.size(), UD64::ZERO);
-                            Some(StateEvents::perpetual(
-                                perp,
-                                PerpetualEventType::OpenInterestUpdated(perp.open_interest()),
-                            ))
-                        } else {
-                            None
+                    sink.push(StateEvents::position(
+                        &pos,
+                        ctx,
+                        PositionEventType::Closed {
+                            r#type: pos.r#type(),
+                            entry_price: pos.entry_price(),
+                            exit_price: perp.price_converter().from_unsigned(e.pricePNS),
+                            size: pos.size(),
+                            delta_pnl: cc.from_signed(e.deltaPnlCNS),
+                            premium_pnl: cc.from_signed(e.fundingCNS),
                         },
-                    )
-                    .collect()
-                } else {
-                    vec![]
+                    ));
+                    if PositionType::from(e.positionType) == PositionType::Long {
+                        perp.update_open_interest(instant, pos.size(), UD64::ZERO);
+                        sink.push(StateEvents::perpetual(
+                            perp,
+                            PerpetualEventType::OpenInterestUpdated(perp.open_interest()),
+                        ));
+                    }
                 }
             }
             ExchangeEvents::PositionCollateralDecreased(e) => {
@@ -1096,7 +1987,7 @@ impl Exchange {
                     pos.update_deposit(instant, cc.from_unsigned(e.endDepositCNS));
                     pos.apply_mark_price(instant, perp.mark_price());
                     pos.apply_maintenance_margin(instant, perp.maintenance_margin());
-                    vec![StateEvents::position(
+                    sink.push(StateEvents::position(
                         pos,
                         ctx,
                         PositionEventType::CollateralDecreased {
@@ -1104,9 +1995,7 @@ impl Exchange {
                             new_entry_price: pos.entry_price(),
                             deposit: pos.deposit(),
                         },
-                    )]
-                } else {
-                    vec![]
+                    ));
                 }
             }
             ExchangeEvents::PositionDecreased(e) => {
@@ -1120,34 +2009,27 @@ impl Exchange {
                         pos.premium_pnl().sub(cc.from_signed(e.fundingCNS)),
                     );
                     pos.apply_maintenance_margin(instant, perp.maintenance_margin());
-                    chain!(
-                        Some(StateEvents::position(
-                            pos,
-                            ctx,
-                            PositionEventType::Decreased {
-                                prev_size,
-                                new_size: pos.size(),
-                                deposit: pos.deposit(),
-                                delta_pnl: pos.delta_pnl(),
-                                premium_pnl: pos.premium_pnl(),
-                            }
-                        )),
-                        if pos.r#type() == PositionType::Long {
-                            perp.update_open_interest(instant, prev_size, pos.size());
-                            Some(StateEvents::perpetual(
-                                perp,
-                                PerpetualEventType::OpenInterestUpdated(perp.open_interest()),
-                            ))
-                        } else {
-                            None
+                    sink.push(StateEvents::position(
+                        pos,
+                        ctx,
+                        PositionEventType::Decreased {
+                            prev_size,
+                            new_size: pos.size(),
+                            deposit: pos.deposit(),
+                            delta_pnl: pos.delta_pnl(),
+                            premium_pnl: pos.premium_pnl(),
                         },
-                    )
-                    .collect()
-                } else {
-                    vec![]
+                    ));
+                    if pos.r#type() == PositionType::Long {
+                        perp.update_open_interest(instant, prev_size, pos.size());
+                        sink.push(StateEvents::perpetual(
+                            perp,
+                            PerpetualEventType::OpenInterestUpdated(perp.open_interest()),
+                        ));
+                    }
                 }
             }
-            ExchangeEvents::PositionDeleveraged(e) => chain!(
+            ExchangeEvents::PositionDeleveraged(e) => sink.extend(chain!(
                 if let Some((pos, perp)) = self.position(e.accountId, e.perpId)? {
                     let prev_size = pos.size();
                     pos.update_size(instant, perp.size_converter().from_unsigned(e.endLotLNS));
@@ -1198,9 +2080,8 @@ impl Exchange {
                     acc.update_balance(instant, cc.from_unsigned(e.balanceCNS));
                     StateEvents::account(acc, ctx, AccountEventType::BalanceUpdated(acc.balance()))
                 }),
-            )
-            .collect(),
-            ExchangeEvents::PositionDoesNotExist(_) => vec![],
+            )),
+            ExchangeEvents::PositionDoesNotExist(_) => {}
             ExchangeEvents::PositionIncreased(e) => {
                 if let Some((pos, perp)) = self.position(e.accountId, e.perpId)? {
                     let prev_size = pos.size();
@@ -1214,30 +2095,23 @@ impl Exchange {
                     pos.update_premium_pnl(instant, D256::ZERO);
                     pos.apply_maintenance_margin(instant, perp.maintenance_margin());
 
-                    chain!(
-                        Some(StateEvents::position(
-                            pos,
-                            ctx,
-                            PositionEventType::Increased {
-                                entry_price: pos.entry_price(),
-                                prev_size,
-                                new_size: pos.size(),
-                                deposit: pos.deposit(),
-                            }
-                        )),
-                        if pos.r#type() == PositionType::Long {
-                            perp.update_open_interest(instant, prev_size, pos.size());
-                            Some(StateEvents::perpetual(
-                                perp,
-                                PerpetualEventType::OpenInterestUpdated(perp.open_interest()),
-                            ))
-                        } else {
-                            None
+                    sink.push(StateEvents::position(
+                        pos,
+                        ctx,
+                        PositionEventType::Increased {
+                            entry_price: pos.entry_price(),
+                            prev_size,
+                            new_size: pos.size(),
+                            deposit: pos.deposit(),
                         },
-                    )
-                    .collect()
-                } else {
-                    vec![]
+                    ));
+                    if pos.r#type() == PositionType::Long {
+                        perp.update_open_interest(instant, prev_size, pos.size());
+                        sink.push(StateEvents::perpetual(
+                            perp,
+                            PerpetualEventType::OpenInterestUpdated(perp.open_interest()),
+                        ));
+                    }
                 }
             }
             ExchangeEvents::PositionInverted(e) => {
@@ -1260,7 +2134,7 @@ impl Exchange {
                     } else {
                         perp.update_open_interest(instant, prev_size, UD64::ZERO);
                     }
-                    vec![
+                    sink.extend([
                         StateEvents::position(
                             pos,
                             ctx,
@@ -1290,12 +2164,10 @@ impl Exchange {
                             perp,
                             PerpetualEventType::OpenInterestUpdated(perp.open_interest()),
                         ),
-                    ]
-                } else {
-                    vec![]
+                    ]);
                 }
             }
-            ExchangeEvents::PositionLiquidated(e) => chain!(
+            ExchangeEvents::PositionLiquidated(e) => sink.extend(chain!(
                 if let Some((pos, perp)) = self.position(e.posAccountId, e.perpId)? {
                     let prev_size = pos.size();
                     pos.update_size(instant, perp.size_converter().from_unsigned(e.posLotLNS));
@@ -1344,23 +2216,21 @@ impl Exchange {
                     acc.update_balance(instant, cc.from_unsigned(e.accBalanceCNS));
                     StateEvents::account(acc, ctx, AccountEventType::BalanceUpdated(acc.balance()))
                 }),
-            )
-            .collect(),
-            ExchangeEvents::PositionLiquidationCredit(e) => self
-                .position(e.accountId, e.perpId)?
-                .map(|(pos, _)| {
+            )),
+            ExchangeEvents::PositionLiquidationCredit(e) => {
+                sink.extend(self.position(e.accountId, e.perpId)?.map(|(pos, _)| {
                     pos.update_deposit(instant, cc.from_unsigned(e.endDepositCNS));
                     StateEvents::position(
                         pos,
                         ctx,
                         PositionEventType::DepositUpdated(pos.deposit()),
                     )
-                })
-                .into_iter()
-                .collect(),
+                }))
+            }
             ExchangeEvents::PositionOpened(e) => {
+                let pnl_history_retention = self.pnl_history_retention;
                 if let Some((acc, perp)) = self.account_perpetual(e.accountId, e.perpId) {
-                    let pos = Position::opened(
+                    let mut pos = Position::opened(
                         instant,
                         perp.id(),
                         acc.id(),
@@ -1370,35 +2240,28 @@ impl Exchange {
                         cc.from_unsigned(e.depositCNS),
                         perp.maintenance_margin(),
                     );
-                    let events = chain!(
-                        Some(StateEvents::position(
-                            &pos,
-                            ctx,
-                            PositionEventType::Opened {
-                                r#type: pos.r#type(),
-                                entry_price: pos.entry_price(),
-                                size: pos.size(),
-                                deposit: pos.deposit(),
-                            }
-                        )),
-                        if pos.r#type() == PositionType::Long {
-                            perp.update_open_interest(instant, UD64::ZERO, pos.size());
-                            Some(StateEvents::perpetual(
-                                perp,
-                                PerpetualEventType::OpenInterestUpdated(perp.open_interest()),
-                            ))
-                        } else {
-                            None
+                    pos.set_pnl_history_retention(pnl_history_retention);
+                    sink.push(StateEvents::position(
+                        &pos,
+                        ctx,
+                        PositionEventType::Opened {
+                            r#type: pos.r#type(),
+                            entry_price: pos.entry_price(),
+                            size: pos.size(),
+                            deposit: pos.deposit(),
                         },
-                    )
-                    .collect();
+                    ));
+                    if pos.r#type() == PositionType::Long {
+                        perp.update_open_interest(instant, UD64::ZERO, pos.size());
+                        sink.push(StateEvents::perpetual(
+                            perp,
+                            PerpetualEventType::OpenInterestUpdated(perp.open_interest()),
+                        ));
+                    }
                     acc.positions_mut().insert(perp.id(), pos);
-                    events
-                } else {
-                    vec![]
                 }
             }
-            ExchangeEvents::PositionTypeMismatch(_) => vec![],
+            ExchangeEvents::PositionTypeMismatch(_) => {}
             ExchangeEvents::PositionUnwound(e) => {
                 if let Some((acc, perp)) = self.account_perpetual(e.accountId, e.perpId) {
                     let pos = acc
@@ -1406,8 +2269,8 @@ impl Exchange {
                         .remove(&perp.id())
                         .ok_or(DexError::PositionNotFound(acc.id(), perp.id()))?;
                     acc.update_balance(instant, cc.from_unsigned(e.balanceCNS));
-                    chain!(
-                        Some(StateEvents::position(
+                    sink.extend([
+                        StateEvents::position(
                             &pos,
                             ctx,
                             PositionEventType::Unwound {
@@ -1417,26 +2280,21 @@ impl Exchange {
                                 size: pos.size(),
                                 fair_market_value: cc.from_signed(e.positionFmvCNS),
                                 payment: cc.from_unsigned(e.paymentCNS),
-                            }
-                        )),
-                        Some(StateEvents::account(
+                            },
+                        ),
+                        StateEvents::account(
                             acc,
                             ctx,
-                            AccountEventType::BalanceUpdated(acc.balance())
-                        )),
-                        if pos.r#type() == PositionType::Long {
-                            perp.update_open_interest(instant, pos.size(), UD64::ZERO);
-                            Some(StateEvents::perpetual(
-                                perp,
-                                PerpetualEventType::OpenInterestUpdated(perp.open_interest()),
-                            ))
-                        } else {
-                            None
-                        },
-                    )
-                    .collect()
-                } else {
-                    vec![]
+                            AccountEventType::BalanceUpdated(acc.balance()),
+                        ),
+                    ]);
+                    if pos.r#type() == PositionType::Long {
+                        perp.update_open_interest(instant, pos.size(), UD64::ZERO);
+                        sink.push(StateEvents::perpetual(
+                            perp,
+                            PerpetualEventType::OpenInterestUpdated(perp.open_interest()),
+                        ));
+                    }
                 }
             }
             ExchangeEvents::PositionUnwoundWithoutPayment(e) => {
@@ -1445,72 +2303,87 @@ impl Exchange {
                         .positions_mut()
                         .remove(&perp.id())
                         .ok_or(DexError::PositionNotFound(acc.id(), perp.id()))?;
-                    chain!(
-                        Some(StateEvents::position(
-                            &pos,
-                            ctx,
-                            PositionEventType::Unwound {
-                                r#type: pos.r#type(),
-                                entry_price: pos.entry_price(),
-                                exit_price: perp.price_converter().from_unsigned(e.pricePNS),
-                                size: pos.size(),
-                                fair_market_value: cc.from_signed(e.positionFmvCNS),
-                                payment: UD128::ZERO,
-                            }
-                        )),
-                        if pos.r#type() == PositionType::Long {
-                            perp.update_open_interest(instant, pos.size(), UD64::ZERO);
-                            Some(StateEvents::perpetual(
-                                perp,
-                                PerpetualEventType::OpenInterestUpdated(perp.open_interest()),
-                            ))
-                        } else {
-                            None
+                    sink.push(StateEvents::position(
+                        &pos,
+                        ctx,
+                        PositionEventType::Unwound {
+                            r#type: pos.r#type(),
+                            entry_price: pos.entry_price(),
+                            exit_price: perp.price_converter().from_unsigned(e.pricePNS),
+                            size: pos.size(),
+                            fair_market_value: cc.from_signed(e.positionFmvCNS),
+                            payment: UD128::ZERO,
                         },
-                    )
-                    .collect()
-                } else {
-                    vec![]
+                    ));
+                    if pos.r#type() == PositionType::Long {
+                        perp.update_open_interest(instant, pos.size(), UD64::ZERO);
+                        sink.push(StateEvents::perpetual(
+                            perp,
+                            PerpetualEventType::OpenInterestUpdated(perp.open_interest()),
+                        ));
+                    }
                 }
             }
-            ExchangeEvents::PostOrderUnderMinimum(_) => self
-                .err_ctx(ctx, event)?
-                .map(|ctx| StateEvents::order_error(ctx, OrderErrorType::PostOrderUnderMinimum))
-                .into_iter()
-                .collect(),
-            ExchangeEvents::PriceAdministratorUpdated(_) => vec![],
+            ExchangeEvents::PostOrderUnderMinimum(_) => {
+                sink.extend(self.err_ctx(ctx, event)?.map(|ctx| {
+                    StateEvents::order_error(ctx, OrderErrorType::PostOrderUnderMinimum)
+                }))
+            }
+            ExchangeEvents::PriceAdministratorUpdated(e) => sink.extend(self.update_role(
+                Role::PriceAdministrator,
+                e.priceAdministrator,
+                e.added,
+            )),
             ExchangeEvents::PriceMaxAgeUpdated(e) => {
                 if let Some(perp) = self.perpetual(e.perpId) {
                     perp.update_price_max_age_sec(instant, e.maxAgeSec.to());
                 }
-                vec![]
             }
-            ExchangeEvents::PriceOutOfRange(_) => self
-                .err_ctx(ctx, event)
-                .ok() // Used both for orders and mark/oracle prices
-                .flatten()
-                .map(|ctx| StateEvents::order_error(ctx, OrderErrorType::PriceOutOfRange))
-                .into_iter()
-                .collect(),
-            ExchangeEvents::PriceTolUpdated(_) => vec![],
-            ExchangeEvents::ProtocolBalanceDeposit(_) => vec![],
-            ExchangeEvents::ProtocolBalanceWithdraw(_) => vec![],
-            ExchangeEvents::RecycleBalanceInsufficientSevere(_) => vec![],
+            ExchangeEvents::PriceOutOfRange(_) => sink.extend(
+                self.err_ctx(ctx, event)
+                    .ok() // Used both for orders and mark/oracle prices
+                    .flatten()
+                    .map(|ctx| StateEvents::order_error(ctx, OrderErrorType::PriceOutOfRange)),
+            ),
+            ExchangeEvents::PriceTolUpdated(e) => {
+                sink.extend(self.perpetual(e.perpId).map(|perp| {
+                    perp.update_price_tolerance(
+                        instant,
+                        perp.fee_converter().from_unsigned(e.tolPer100k),
+                    );
+                    StateEvents::perpetual(
+                        perp,
+                        PerpetualEventType::PriceToleranceUpdated(perp.price_tolerance()),
+                    )
+                }))
+            }
+            ExchangeEvents::ProtocolBalanceDeposit(e) => sink.extend(self.change_fund(
+                instant,
+                Fund::Protocol,
+                cc.from_unsigned::<2>(e.amountCNS).to_signed(),
+                FundChangeReason::Deposit,
+            )),
+            ExchangeEvents::ProtocolBalanceWithdraw(e) => sink.extend(self.change_fund(
+                instant,
+                Fund::Protocol,
+                -cc.from_unsigned::<2>(e.amountCNS).to_signed(),
+                FundChangeReason::Withdrawal,
+            )),
+            ExchangeEvents::RecycleBalanceInsufficientSevere(_) => {}
             ExchangeEvents::RecycleFeeUpdated(e) => {
                 self.recycle_fee = cc.from_unsigned(e.recycleFeeCNS);
-                vec![StateEvents::Exchange(ExchangeEvent::RecycleFeeUpdated(
+                sink.push(StateEvents::Exchange(ExchangeEvent::RecycleFeeUpdated(
                     self.recycle_fee(),
-                ))]
-            }
-            ExchangeEvents::ReferencePriceAgesExceedMax(_) => vec![],
-            ExchangeEvents::ReportAgeExceedsLastUpdate(_) => vec![],
-            ExchangeEvents::ReportExpiresTooSoon(_) => vec![],
-            ExchangeEvents::ReportFromFuture(_) => vec![],
-            ExchangeEvents::ReportPriceIsNegative(_) => vec![],
-            ExchangeEvents::SyntheticPriceError(_) => vec![],
-            ExchangeEvents::TakerFeeUpdated(e) => self
-                .perpetual(e.perpId)
-                .map(|perp| {
+                )));
+            }
+            ExchangeEvents::ReferencePriceAgesExceedMax(_) => {}
+            ExchangeEvents::ReportAgeExceedsLastUpdate(_) => {}
+            ExchangeEvents::ReportExpiresTooSoon(_) => {}
+            ExchangeEvents::ReportFromFuture(_) => {}
+            ExchangeEvents::ReportPriceIsNegative(_) => {}
+            ExchangeEvents::SyntheticPriceError(_) => {}
+            ExchangeEvents::TakerFeeUpdated(e) => {
+                sink.extend(self.perpetual(e.perpId).map(|perp| {
                     perp.update_taker_fee(
                         instant,
                         perp.fee_converter().from_unsigned(e.takerFeePer100K),
@@ -1519,12 +2392,11 @@ impl Exchange {
                         perp,
                         PerpetualEventType::TakerFeeUpdated(perp.taker_fee()),
                     )
-                })
-                .into_iter()
-                .collect(),
+                }))
+            }
             ExchangeEvents::TakerOrderFilled(e) => {
                 let c = must_ctx()?;
-                chain!(
+                sink.extend(chain!(
                     self.perpetuals
                         .get(&c.perpetual_id)
                         .map(|perp| StateEvents::Order(OrderEvent {
@@ -1537,9 +2409,11 @@ impl Exchange {
                                 fill_size: perp.size_converter().from_unsigned(e.lotLNS),
                                 fee: cc.from_unsigned(e.feeCNS),
                                 is_maker: false,
+                                fill_id: event.fill_id(),
                             },
                         })),
                     self.accounts.get_mut(&c.account_id).map(|acc| {
+                        let acc = Arc::make_mut(acc);
                         acc.update_balance(instant, cc.from_unsigned(e.balanceCNS));
                         StateEvents::account(
                             acc,
@@ -1547,52 +2421,122 @@ impl Exchange {
                             AccountEventType::BalanceUpdated(acc.balance()),
                         )
                     }),
-                )
-                .collect()
+                ));
             }
-            ExchangeEvents::TransferAccountToProtocol(e) => self
-                .account(e.accountId)
-                .map(|acc| {
+            ExchangeEvents::TransferAccountToProtocol(e) => sink.extend(chain!(
+                self.account(e.accountId).map(|acc| {
                     acc.update_balance(instant, cc.from_unsigned(e.balanceCNS));
                     StateEvents::account(acc, ctx, AccountEventType::BalanceUpdated(acc.balance()))
-                })
-                .into_iter()
-                .collect(),
-            ExchangeEvents::TransferPerpInsToProtocol(_) => vec![],
-            ExchangeEvents::TransferProtocolToAccount(e) => self
-                .account(e.accountId)
-                .map(|acc| {
+                }),
+                self.change_fund(
+                    instant,
+                    Fund::Protocol,
+                    cc.from_unsigned::<2>(e.amountCNS).to_signed(),
+                    FundChangeReason::AccountTransfer(e.accountId.to()),
+                ),
+            )),
+            ExchangeEvents::TransferPerpInsToProtocol(e) => {
+                let amount = cc.from_unsigned::<2>(e.amountCNS).to_signed();
+                let reason = FundChangeReason::InsuranceTransfer(e.perpId.to());
+                sink.extend(chain!(
+                    self.change_fund(instant, Fund::Insurance(e.perpId.to()), -amount, reason),
+                    self.change_fund(instant, Fund::Protocol, amount, reason),
+                ))
+            }
+            ExchangeEvents::TransferProtocolToAccount(e) => sink.extend(chain!(
+                self.account(e.accountId).map(|acc| {
                     acc.update_balance(instant, cc.from_unsigned(e.balanceCNS));
                     StateEvents::account(acc, ctx, AccountEventType::BalanceUpdated(acc.balance()))
-                })
-                .into_iter()
-                .collect(),
-            ExchangeEvents::TransferProtocolToPerp(_) => vec![],
-            ExchangeEvents::TransferProtocolToRecycleBal(_) => vec![],
-            ExchangeEvents::UnableToCancelOrder(_) => vec![],
-            ExchangeEvents::UnityDescentThreshUpdated(_) => vec![],
-            ExchangeEvents::UnspecifiedCollateral(_) => vec![],
-            ExchangeEvents::UnsupportedOperation(_) => vec![],
-            ExchangeEvents::UnwindCompleted(_) => vec![],
-            ExchangeEvents::UnwindInitializationCleared(_) => vec![],
-            ExchangeEvents::UnwindInitialized(_) => vec![],
-            ExchangeEvents::UnwindInsufficientBalance(_) => vec![],
-            ExchangeEvents::UnwindIterationCompleted(_) => vec![],
-            ExchangeEvents::UpdateOracleFailed(_) => vec![],
-            ExchangeEvents::Upgraded(_) => vec![],
-            ExchangeEvents::WhitelistAddress(_) => vec![],
-            ExchangeEvents::WhitelistingEnabledChanged(_) => vec![],
-            ExchangeEvents::WithdrawRateLimitBypassSet(_) => vec![],
-            ExchangeEvents::WithdrawRateLimitForceReset(_) => vec![],
-            ExchangeEvents::WRLSMinWithdrawLimitUpdated(_) => vec![],
-            ExchangeEvents::WRLSThousandthsTvlUpdated(_) => vec![],
-            ExchangeEvents::WithdrawRateLimitReset(_) => vec![],
-            ExchangeEvents::WrongAccountForOrder(_) => self
-                .err_ctx(ctx, event)?
-                .map(|ctx| StateEvents::order_error(ctx, OrderErrorType::WrongAccountForOrder))
-                .into_iter()
-                .collect(),
-        })
+                }),
+                self.change_fund(
+                    instant,
+                    Fund::Protocol,
+                    -cc.from_unsigned::<2>(e.amountCNS).to_signed(),
+                    FundChangeReason::AccountTransfer(e.accountId.to()),
+                ),
+            )),
+            ExchangeEvents::TransferProtocolToPerp(e) => {
+                let amount = cc.from_unsigned::<2>(e.amountCNS).to_signed();
+                let reason = FundChangeReason::InsuranceTransfer(e.perpId.to());
+                sink.extend(chain!(
+                    self.change_fund(instant, Fund::Protocol, -amount, reason),
+                    // Transfers to the position balance of the contract are not tracked
+                    e.toInsuranceFund
+                        .then(|| {
+                            self.change_fund(
+                                instant,
+                                Fund::Insurance(e.perpId.to()),
+                                amount,
+                                reason,
+                            )
+                        })
+                        .flatten(),
+                ))
+            }
+            ExchangeEvents::TransferProtocolToRecycleBal(e) => sink.extend(self.change_fund(
+                instant,
+                Fund::Protocol,
+                -cc.from_unsigned::<2>(e.amountCNS).to_signed(),
+                FundChangeReason::RecycleTransfer,
+            )),
+            ExchangeEvents::UnableToCancelOrder(_) => {}
+            ExchangeEvents::UnityDescentThreshUpdated(_) => {}
+            ExchangeEvents::UnspecifiedCollateral(_) => {}
+            ExchangeEvents::UnsupportedOperation(_) => {}
+            ExchangeEvents::UnwindCompleted(_) => {}
+            ExchangeEvents::UnwindInitializationCleared(_) => {}
+            ExchangeEvents::UnwindInitialized(_) => {}
+            ExchangeEvents::UnwindInsufficientBalance(_) => {}
+            ExchangeEvents::UnwindIterationCompleted(_) => {}
+            ExchangeEvents::UpdateOracleFailed(_) => {}
+            ExchangeEvents::Upgraded(e) => {
+                if self.halt_on_upgrade {
+                    self.pending_upgrade = Some(e.implementation);
+                }
+                sink.push(StateEvents::Exchange(
+                    ExchangeEvent::ImplementationUpgraded(e.implementation),
+                ));
+            }
+            ExchangeEvents::WhitelistAddress(_) => {}
+            ExchangeEvents::WhitelistingEnabledChanged(e) => {
+                self.roles.update_whitelisting_enabled(e.enabled);
+                sink.push(StateEvents::Exchange(ExchangeEvent::WhitelistingEnabled(
+                    e.enabled,
+                )));
+            }
+            ExchangeEvents::WithdrawRateLimitBypassSet(_) => {}
+            ExchangeEvents::WithdrawRateLimitForceReset(e) => sink.push(
+                self.reset_withdraw_rate_limit(e.newExpiryBlock, e.newLimitCNS, e.perBlockCNS),
+            ),
+            ExchangeEvents::WRLSMinWithdrawLimitUpdated(_) => {}
+            ExchangeEvents::WRLSThousandthsTvlUpdated(_) => {}
+            ExchangeEvents::WithdrawRateLimitReset(e) => sink.push(self.reset_withdraw_rate_limit(
+                e.newExpiryBlock,
+                e.newLimitCNS,
+                e.perBlockCNS,
+            )),
+            ExchangeEvents::WrongAccountForOrder(_) => sink
+                .extend(self.err_ctx(ctx, event)?.map(|ctx| {
+                    StateEvents::order_error(ctx, OrderErrorType::WrongAccountForOrder)
+                })),
+        }
+        Ok(())
+    }
+
+    fn reset_withdraw_rate_limit(
+        &mut self,
+        expiry_block: U256,
+        limit: U256,
+        per_block: U256,
+    ) -> StateEvents {
+        let cc = self.collateral_converter;
+        let limit = WithdrawRateLimit {
+            expiry_block: expiry_block.to(),
+            limit: cc.from_unsigned(limit),
+            per_block: cc.from_unsigned(per_block),
+        };
+        self.withdraw_rate_limit = Some(limit);
+        StateEvents::Exchange(ExchangeEvent::WithdrawRateLimitReset(limit))
     }
 
     fn apply_state_event(
@@ -1608,46 +2552,51 @@ impl Exchange {
                         payment_per_unit,
                     } => {
                         // Applying funding to all tracked positions
-                        self.accounts
-                            .values_mut()
-                            .filter_map(|acc| {
-                                acc.positions_mut()
-                                    .get_mut(&pe.perpetual_id)
-                                    .and_then(|pos| {
-                                        pos.apply_funding_payment(instant, payment_per_unit).then(
-                                            || {
-                                                StateEvents::position(
-                                                    pos,
-                                                    &None,
-                                                    PositionEventType::UnrealizedPnLUpdated {
-                                                        pnl: pos.pnl(),
-                                                        delta_pnl: pos.delta_pnl(),
-                                                        premium_pnl: pos.premium_pnl(),
-                                                    },
-                                                )
-                                            },
-                                        )
-                                    })
-                            })
-                            .collect()
+                        by_account(
+                            self.accounts
+                                .values_mut()
+                                .filter(|acc| acc.positions().contains_key(&pe.perpetual_id))
+                                .map(Arc::make_mut)
+                                .filter_map(|acc| {
+                                    acc.positions_mut()
+                                        .get_mut(&pe.perpetual_id)
+                                        .and_then(|pos| {
+                                            pos.apply_funding_payment(instant, payment_per_unit)
+                                                .then(|| {
+                                                    StateEvents::position(
+                                                        pos,
+                                                        &None,
+                                                        PositionEventType::UnrealizedPnLUpdated {
+                                                            pnl: pos.pnl(),
+                                                            delta_pnl: pos.delta_pnl(),
+                                                            premium_pnl: pos.premium_pnl(),
+                                                        },
+                                                    )
+                                                })
+                                        })
+                                }),
+                        )
                     }
                     PerpetualEventType::MaintenanceMarginFractionUpdated(maintenance_margin) => {
                         // Applying new maintenance margin to all tracked positions
-                        self.accounts
-                            .values_mut()
-                            .filter_map(|acc| {
-                                acc.positions_mut().get_mut(&pe.perpetual_id).map(|pos| {
-                                    pos.apply_maintenance_margin(instant, maintenance_margin);
-                                    StateEvents::position(
-                                        pos,
-                                        &None,
-                                        PositionEventType::MaintenanceMarginUpdated(
-                                            pos.maintenance_margin_requirement(),
-                                        ),
-                                    )
-                                })
-                            })
-                            .collect()
+                        by_account(
+                            self.accounts
+                                .values_mut()
+                                .filter(|acc| acc.positions().contains_key(&pe.perpetual_id))
+                                .map(Arc::make_mut)
+                                .filter_map(|acc| {
+                                    acc.positions_mut().get_mut(&pe.perpetual_id).map(|pos| {
+                                        pos.apply_maintenance_margin(instant, maintenance_margin);
+                                        StateEvents::position(
+                                            pos,
+                                            &None,
+                                            PositionEventType::MaintenanceMarginUpdated(
+                                                pos.maintenance_margin_requirement(),
+                                            ),
+                                        )
+                                    })
+                                }),
+                        )
                     }
                     _ => vec![],
                 }
@@ -1671,16 +2620,118 @@ impl Exchange {
     fn ensure_account(&mut self, id: U256) {
         let id = id.to::<types::AccountId>();
         if self.track_all_accounts && !self.accounts.contains_key(&id) {
+            // Address is known for accounts dropped by the compaction
+            let address = self
+                .account_ids
+                .iter()
+                .find_map(|(address, acc_id)| (*acc_id == id).then_some(*address))
+                .unwrap_or_default();
             self.accounts.insert(
                 id,
-                Account::from_event(types::StateInstant::default(), id, Address::ZERO),
+                Arc::new(Account::from_event(
+                    types::StateInstant::default(),
+                    id,
+                    address,
+                )),
             );
         }
     }
 
+    /// Balance updates of accounts tracked with [`AccountTracking::PositionsOnly`]
+    /// are not reported.
+    fn is_untracked_balance_event(&self, event: &StateEvents) -> bool {
+        match event {
+            StateEvents::Account(AccountEvent {
+                account_id,
+                r#type:
+                    AccountEventType::BalanceUpdated(_) | AccountEventType::LockedBalanceUpdated(_),
+                ..
+            }) => self
+                .accounts
+                .get(account_id)
+                .is_some_and(|acc| !acc.tracks_balances()),
+            _ => false,
+        }
+    }
+
+    /// Follows each balance update from the given position on with the change classified
+    /// by the raw event.
+    ///
+    /// Relies on the raw events updating the balance of an account at most once.
+    fn add_balance_changes(
+        &self,
+        event: &ExchangeEvents,
+        events: &mut Vec<StateEvents>,
+        start: usize,
+    ) {
+        let mut i = start;
+        while i < events.len() {
+            i += 1;
+            let StateEvents::Account(AccountEvent {
+                account_id,
+                request_id,
+                r#type: AccountEventType::BalanceUpdated(_),
+            }) = events[i - 1]
+            else {
+                continue;
+            };
+            let Some(acc) = self.accounts.get(&account_id) else {
+                continue;
+            };
+            let delta = acc.last_balance_change();
+            if delta.is_zero() {
+                continue;
+            }
+            events.insert(
+                i,
+                StateEvents::Account(AccountEvent {
+                    account_id,
+                    request_id,
+                    r#type: AccountEventType::BalanceChanged {
+                        delta,
+                        reason: balance_change_reason(event),
+                    },
+                }),
+            );
+            i += 1;
+        }
+    }
+
+    /// Refreshes the index and the account margin aggregates for the positions
+    /// touched by the events.
+    fn update_position_aggregates(&mut self, state_events: &[StateEvents]) {
+        let touched = state_events
+            .iter()
+            .filter_map(|e| match e {
+                StateEvents::Position(pe) => Some((pe.perpetual_id, pe.account_id)),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+        for (perp_id, acc_id) in touched {
+            if let Some(acc) = self.accounts.get_mut(&acc_id) {
+                Arc::make_mut(acc).refresh_position_margin(perp_id);
+            }
+            let pos = self
+                .accounts
+                .get(&acc_id)
+                .and_then(|acc| acc.positions().get(&perp_id));
+            self.position_index.update(perp_id, acc_id, pos);
+        }
+    }
+
+    fn update_role(&mut self, role: Role, address: Address, granted: bool) -> Option<StateEvents> {
+        self.roles
+            .update(role, address, granted)
+            .then_some(StateEvents::Exchange(ExchangeEvent::RoleUpdated(
+                role, address, granted,
+            )))
+    }
+
     fn account(&mut self, id: U256) -> Option<&mut Account> {
         self.ensure_account(id);
-        self.accounts.get_mut(&id.to::<types::AccountId>())
+        self.accounts
+            .get_mut(&id.to::<types::AccountId>())
+            .map(Arc::make_mut)
     }
 
     fn order(
@@ -1692,6 +2743,7 @@ impl Exchange {
             .expect("ord_id in order lookup cannot be 0");
         Ok(
             if let Some(perp) = self.perpetuals.get_mut(&perp_id.to::<types::PerpetualId>()) {
+                let perp = Arc::make_mut(perp);
                 let ord = perp
                     .get_order(ord_id)
                     .copied()
@@ -1703,8 +2755,46 @@ impl Exchange {
         )
     }
 
+    /// Applies the change to the fund balance, recording it in the history.
+    /// Insurance funds of untracked perpetual contracts are ignored.
+    fn change_fund(
+        &mut self,
+        instant: types::StateInstant,
+        fund: Fund,
+        delta: D128,
+        reason: FundChangeReason,
+    ) -> Option<StateEvents> {
+        let balance = match fund {
+            Fund::Protocol => {
+                self.protocol_balance = insurance::apply_delta(self.protocol_balance, delta);
+                self.protocol_balance
+            }
+            Fund::Insurance(perp_id) => {
+                let perp = Arc::make_mut(self.perpetuals.get_mut(&perp_id)?);
+                perp.set_insurance_balance(
+                    instant,
+                    insurance::apply_delta(perp.insurance_balance(), delta),
+                );
+                perp.insurance_balance()
+            }
+        };
+        let change = FundChange {
+            instant,
+            fund,
+            delta,
+            balance,
+            reason,
+        };
+        self.fund_history.record(change);
+        Some(StateEvents::Exchange(ExchangeEvent::FundBalanceChanged(
+            change,
+        )))
+    }
+
     fn perpetual(&mut self, id: U256) -> Option<&mut Perpetual> {
-        self.perpetuals.get_mut(&id.to::<types::PerpetualId>())
+        self.perpetuals
+            .get_mut(&id.to::<types::PerpetualId>())
+            .map(Arc::make_mut)
     }
 
     fn account_perpetual(
@@ -1716,6 +2806,7 @@ impl Exchange {
         self.accounts
             .get_mut(&acc_id.to::<types::AccountId>())
             .zip(self.perpetuals.get_mut(&perp_id.to::<types::PerpetualId>()))
+            .map(|(acc, perp)| (Arc::make_mut(acc), Arc::make_mut(perp)))
     }
 
     fn position(
@@ -1727,16 +2818,855 @@ impl Exchange {
         let acc_id = acc_id.to::<types::AccountId>();
         let perp_id = perp_id.to::<types::PerpetualId>();
         Ok(if let Some(acc) = self.accounts.get_mut(&acc_id) {
-            let pos = acc
+            let pos = Arc::make_mut(acc)
                 .positions_mut()
                 .get_mut(&perp_id)
                 .ok_or(DexError::PositionNotFound(acc_id, perp_id))?;
             Some((
                 pos,
-                self.perpetuals.get_mut(&perp_id).expect("perpetual found"),
+                Arc::make_mut(self.perpetuals.get_mut(&perp_id).expect("perpetual found")),
             ))
         } else {
             None
         })
     }
 }
+
+/// Collects position events of multiple accounts ordered by account ID,
+/// as accounts are not kept in a deterministic order.
+fn by_account(events: impl Iterator<Item = StateEvents>) -> Vec<StateEvents> {
+    let mut events = events.collect::<Vec<_>>();
+    sort_by_account(&mut events);
+    events
+}
+
+/// Orders position events of multiple accounts by account ID in place.
+fn sort_by_account(events: &mut [StateEvents]) {
+    // Account IDs are unique within a single fan-out, so no need for a stable sort
+    events.sort_unstable_by_key(|e| match e {
+        StateEvents::Position(pe) => pe.account_id,
+        _ => 0,
+    });
+}
+
+/// Classifies the cause of the balance update by the raw event.
+fn balance_change_reason(event: &ExchangeEvents) -> BalanceChangeReason {
+    match event {
+        ExchangeEvents::CollateralDeposit(_) => BalanceChangeReason::Deposit,
+        ExchangeEvents::CollateralWithdrawal(_) => BalanceChangeReason::Withdrawal,
+        ExchangeEvents::MakerOrderFilled(_) | ExchangeEvents::TakerOrderFilled(_) => {
+            BalanceChangeReason::Fill
+        }
+        ExchangeEvents::OrderPlaced(_)
+        | ExchangeEvents::OrderChanged(_)
+        | ExchangeEvents::OrderCancelled(_) => BalanceChangeReason::OrderPosting,
+        ExchangeEvents::ClearingExpiredOrder(_)
+        | ExchangeEvents::ClearingFrozenAccountOrder(_)
+        | ExchangeEvents::ClearingInvalidCloseOrder(_)
+        | ExchangeEvents::ClearingSelfMatchingOrder(_)
+        | ExchangeEvents::MakerOrderSettlementFailed(_) => BalanceChangeReason::RecycleFee,
+        ExchangeEvents::IncreasePositionCollateral(_) => BalanceChangeReason::PositionCollateral,
+        ExchangeEvents::PositionLiquidated(_) => BalanceChangeReason::Liquidation,
+        ExchangeEvents::AccountLiquidationCredit(_) => BalanceChangeReason::LiquidationCredit,
+        ExchangeEvents::PositionDeleveraged(_) => BalanceChangeReason::Deleverage,
+        ExchangeEvents::PositionUnwound(_) => BalanceChangeReason::Unwind,
+        ExchangeEvents::TransferAccountToProtocol(_)
+        | ExchangeEvents::TransferProtocolToAccount(_) => BalanceChangeReason::ProtocolTransfer,
+        _ => BalanceChangeReason::Other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fastnum::{dec128, udec128};
+
+    use super::*;
+    use crate::testing::fuzz::{Action, Simulator};
+
+    fn top_levels_changed(events: &StateBlockEvents) -> usize {
+        events
+            .flat_events()
+            .filter(|(_, e)| {
+                matches!(
+                    e,
+                    StateEvents::Perpetual(PerpetualEvent {
+                        r#type: PerpetualEventType::TopLevelsChanged,
+                        ..
+                    })
+                )
+            })
+            .count()
+    }
+
+    #[test]
+    fn top_levels_changes() {
+        let mut sim = Simulator::new(&[1], 2);
+        let mut exchange = sim.exchange();
+        exchange.watch_top_levels(1, 1).unwrap();
+        assert!(exchange.watch_top_levels(2, 1).is_err());
+
+        let place = |offset| Action::Place {
+            perpetual: 0,
+            account: 0,
+            is_bid: true,
+            offset,
+            lots: 10,
+        };
+        let mut apply = |actions: &[Action]| {
+            let events = exchange.apply_events(&sim.block(actions)).unwrap().unwrap();
+            top_levels_changed(&events)
+        };
+
+        // Best bid placed, then the deeper one
+        assert_eq!(apply(&[place(0)]), 1);
+        assert_eq!(apply(&[place(5)]), 0);
+        // Size of the best bid changed twice within the block
+        let fill = Action::Fill {
+            order: 0,
+            taker: 1,
+            lots: 3,
+        };
+        assert_eq!(apply(&[fill.clone(), fill]), 1);
+        assert_eq!(apply(&[]), 0);
+
+        exchange.unwatch_top_levels(1);
+        let events = exchange
+            .apply_events(&sim.block(&[Action::Cancel { order: 0 }]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(top_levels_changed(&events), 0);
+    }
+
+    #[test]
+    fn stale_orders_reported_once() {
+        let mut sim = Simulator::new(&[1], 2);
+        let mut exchange = sim.exchange();
+        exchange.watch_stale_orders(1, 2);
+
+        let mut apply = |actions: &[Action]| {
+            let events = exchange.apply_events(&sim.block(actions)).unwrap().unwrap();
+            let stale = events
+                .flat_events()
+                .filter_map(|(_, e)| match e {
+                    StateEvents::Order(OrderEvent {
+                        account_id,
+                        r#type: OrderEventType::Stale { age_blocks },
+                        ..
+                    }) => Some((*account_id, *age_blocks)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            let block_number = exchange.instant().block_number();
+            (
+                stale,
+                exchange.perpetuals()[&1]
+                    .stale_orders(1, block_number, 2)
+                    .count(),
+            )
+        };
+        let place = |account| Action::Place {
+            perpetual: 0,
+            account,
+            is_bid: true,
+            offset: 1,
+            lots: 10,
+        };
+
+        // Orders of the other account are not watched
+        assert_eq!(apply(&[place(0), place(1)]), (vec![], 0));
+        // Partial fill does not refresh the order
+        let fill = Action::Fill {
+            order: 0,
+            taker: 1,
+            lots: 3,
+        };
+        assert_eq!(apply(&[fill]), (vec![], 0));
+        assert_eq!(apply(&[]), (vec![], 0));
+        assert_eq!(apply(&[]), (vec![(1, 3)], 1));
+        assert_eq!(apply(&[]), (vec![], 1));
+
+        // Modification refreshes the order, reported again once stale
+        let modify = Action::Modify {
+            order: 0,
+            offset: 2,
+            lots: 10,
+        };
+        assert_eq!(apply(&[modify]), (vec![], 0));
+        assert_eq!(apply(&[]), (vec![], 0));
+        assert_eq!(apply(&[]), (vec![], 0));
+        assert_eq!(apply(&[]), (vec![(1, 3)], 1));
+    }
+
+    #[test]
+    fn account_margin_aggregates() {
+        let mut sim = Simulator::new(&[1, 2], 1);
+        let mut exchange = sim.exchange();
+        let mut apply = |actions: &[Action]| {
+            exchange.apply_events(&sim.block(actions)).unwrap();
+            let acc = &exchange.accounts()[&1];
+            let recomputed = acc
+                .positions()
+                .values()
+                .map(|pos| pos.maintenance_margin_requirement())
+                .fold(UD128::ZERO, |acc, mm| acc + mm);
+            assert_eq!(acc.maintenance_margin(), recomputed);
+            let equity = acc
+                .positions()
+                .values()
+                .fold(acc.balance().to_signed().resize(), |equity: D256, pos| {
+                    equity + pos.deposit().to_signed().resize() + pos.pnl()
+                });
+            assert_eq!(acc.equity(), equity);
+            (acc.maintenance_margin(), acc.health_ratio())
+        };
+        let open = |perpetual| Action::Open {
+            perpetual,
+            account: 0,
+            is_long: true,
+            lots: 10,
+        };
+
+        let (mm, _) = apply(&[open(0)]);
+        assert!(mm > UD128::ZERO);
+        let (mm_both, healthy) = apply(&[open(1)]);
+        assert!(mm_both > mm);
+
+        // Mark moving against the long positions reduces health
+        let (_, health) = apply(&[Action::Mark {
+            perpetual: 0,
+            price: 900,
+        }]);
+        assert!(health.unwrap() < healthy.unwrap());
+
+        assert_eq!(apply(&[Action::Close { position: 0 }]).0, mm);
+        assert_eq!(apply(&[Action::Close { position: 0 }]), (UD128::ZERO, None));
+    }
+
+    #[test]
+    fn role_updates() {
+        use crate::abi::dex::Exchange as Abi;
+
+        let mut exchange = Simulator::new(&[1], 1).exchange();
+        let admin = Address::with_last_byte(1);
+        let block = |exchange: &Exchange, events: Vec<ExchangeEvents>| {
+            let instant = exchange.instant();
+            stream::RawBlockEvents::new(
+                types::StateInstant::new(instant.block_number() + 1, instant.block_timestamp()),
+                events
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, e)| {
+                        EventContext::new(Default::default(), 0, i as u64, Address::ZERO, e)
+                    })
+                    .collect(),
+            )
+        };
+
+        let events = block(
+            &exchange,
+            vec![
+                ExchangeEvents::AdministratorUpdated(Abi::AdministratorUpdated {
+                    administator: admin,
+                    added: true,
+                }),
+                ExchangeEvents::PriceAdministratorUpdated(Abi::PriceAdministratorUpdated {
+                    priceAdministrator: admin,
+                    added: true,
+                }),
+                ExchangeEvents::WhitelistingEnabledChanged(Abi::WhitelistingEnabledChanged {
+                    enabled: true,
+                }),
+            ],
+        );
+        let result = exchange.apply_events(&events).unwrap().unwrap();
+        assert_eq!(result.flat_events().count(), 3);
+        assert!(exchange.administrators().contains(&admin));
+        assert!(exchange.price_administrators().contains(&admin));
+        assert!(exchange.position_administrators().is_empty());
+        assert!(exchange.is_whitelisting_enabled());
+
+        // Revoking the role not held produces no event
+        let events = block(
+            &exchange,
+            vec![
+                ExchangeEvents::AdministratorUpdated(Abi::AdministratorUpdated {
+                    administator: admin,
+                    added: false,
+                }),
+                ExchangeEvents::PositionAdministratorUpdated(Abi::PositionAdministratorUpdated {
+                    positionAdministrator: admin,
+                    added: false,
+                }),
+            ],
+        );
+        let result = exchange.apply_events(&events).unwrap().unwrap();
+        assert!(matches!(
+            result.flat_events().map(|(_, e)| e).collect::<Vec<_>>()[..],
+            [StateEvents::Exchange(ExchangeEvent::RoleUpdated(
+                Role::Administrator,
+                _,
+                false
+            ))]
+        ));
+        assert!(exchange.role_holders(Role::Administrator).is_empty());
+    }
+
+    #[test]
+    fn account_freeze_suspends_orders() {
+        use crate::abi::dex::Exchange as Abi;
+
+        let mut exchange = Simulator::new(&[1], 1).exchange();
+        let freeze = |exchange: &mut Exchange, block_number, status| {
+            let events = stream::RawBlockEvents::new(
+                types::StateInstant::new(block_number, block_number),
+                vec![EventContext::new(
+                    Default::default(),
+                    0,
+                    0,
+                    Address::ZERO,
+                    ExchangeEvents::AccountFreeze(Abi::AccountFreeze {
+                        accountId: U256::from(1),
+                        status,
+                    }),
+                )],
+            );
+            exchange.apply_events(&events).unwrap();
+        };
+        assert!(exchange.accounts()[&1].ensure_not_frozen().is_ok());
+
+        // Repeated freeze keeps the original instant
+        freeze(&mut exchange, 2, 1);
+        freeze(&mut exchange, 3, 2);
+        let acc = &exchange.accounts()[&1];
+        assert!(acc.frozen());
+        assert_eq!(acc.freeze_status(), types::FreezeStatus::Frozen(2));
+        assert_eq!(acc.freeze_status().to_string(), "frozen with status 2");
+        assert_eq!(acc.frozen_since(), Some(types::StateInstant::new(2, 2)));
+        assert!(matches!(
+            acc.ensure_not_frozen(),
+            Err(DexError::AccountFrozen(1, 2))
+        ));
+
+        freeze(&mut exchange, 4, 0);
+        let acc = &exchange.accounts()[&1];
+        assert_eq!(acc.freeze_status(), types::FreezeStatus::NotFrozen);
+        assert_eq!(acc.frozen_since(), None);
+        assert!(acc.ensure_not_frozen().is_ok());
+    }
+
+    #[test]
+    fn order_forwarding_updates() {
+        use crate::abi::dex::Exchange as Abi;
+
+        let mut exchange = Simulator::new(&[1], 1).exchange();
+        assert_eq!(exchange.accounts()[&1].order_forwarding(), None);
+
+        let events = stream::RawBlockEvents::new(
+            types::StateInstant::new(2, 2),
+            vec![EventContext::new(
+                Default::default(),
+                0,
+                0,
+                Address::ZERO,
+                ExchangeEvents::OrderForwardingUpdated(Abi::OrderForwardingUpdated {
+                    accountId: U256::from(1),
+                    allowed: true,
+                }),
+            )],
+        );
+        let events = exchange.apply_events(&events).unwrap().unwrap();
+        assert_eq!(exchange.accounts()[&1].order_forwarding(), Some(true));
+        assert!(events.flat_events().any(
+            |(_, e)| matches!(e, StateEvents::Account(e) if matches!(e.r#type, AccountEventType::OrderForwarding(true)))
+        ));
+    }
+
+    #[test]
+    fn checkpoint_shares_unchanged_state() {
+        let mut sim = Simulator::new(&[1, 2], 2);
+        let mut exchange = sim.exchange();
+        exchange.checkpoint();
+        exchange.apply_events(&sim.block(&[])).unwrap();
+
+        let checkpoint = exchange.checkpoints.rewind(1).unwrap();
+        assert!(Arc::ptr_eq(
+            &checkpoint.perpetuals()[&1],
+            &exchange.perpetuals()[&1]
+        ));
+        assert!(Arc::ptr_eq(
+            &checkpoint.accounts()[&1],
+            &exchange.accounts()[&1]
+        ));
+
+        exchange
+            .apply_events(&sim.block(&[Action::Place {
+                perpetual: 0,
+                account: 0,
+                is_bid: true,
+                offset: 1,
+                lots: 10,
+            }]))
+            .unwrap();
+
+        let checkpoint = exchange.checkpoints.rewind(1).unwrap();
+        assert!(Arc::ptr_eq(
+            &checkpoint.perpetuals()[&2],
+            &exchange.perpetuals()[&2]
+        ));
+        assert!(Arc::ptr_eq(
+            &checkpoint.accounts()[&2],
+            &exchange.accounts()[&2]
+        ));
+        assert!(!Arc::ptr_eq(
+            &checkpoint.accounts()[&1],
+            &exchange.accounts()[&1]
+        ));
+        assert!(!std::ptr::eq(
+            checkpoint.perpetuals()[&1].l3_book(),
+            exchange.perpetuals()[&1].l3_book()
+        ));
+        assert_eq!(checkpoint.perpetuals()[&1].l3_book().total_orders(), 0);
+    }
+
+    #[test]
+    fn expiry_block_from_duration() {
+        let mut sim = Simulator::new(&[1], 1);
+        let mut exchange = sim.exchange();
+        assert_eq!(exchange.chain().blocks_from_duration(Duration::ZERO), 0);
+        assert_eq!(
+            exchange
+                .chain()
+                .blocks_from_duration(Duration::from_secs(1)),
+            3
+        );
+        // Chain block time until observed
+        assert_eq!(exchange.observed_block_time(), None);
+        assert_eq!(exchange.expiry_block_in(Duration::from_secs(2)), 1 + 5);
+
+        // Simulated blocks are a second apart
+        for _ in 0..3 {
+            exchange.apply_events(&sim.block(&[])).unwrap();
+        }
+        assert_eq!(exchange.observed_block_time(), Some(Duration::from_secs(1)));
+        assert_eq!(exchange.expiry_block_in(Duration::from_secs(30)), 4 + 30);
+        assert_eq!(exchange.expiry_block_in(Duration::from_millis(1500)), 4 + 2);
+    }
+
+    #[test]
+    fn sequenced_events() {
+        let mut sim = Simulator::new(&[1, 2], 3);
+        let (mut first, mut second) = (sim.exchange(), sim.exchange());
+        let open = |perpetual, account, is_long| Action::Open {
+            perpetual,
+            account,
+            is_long,
+            lots: 1,
+        };
+        let place = |perpetual, account| Action::Place {
+            perpetual,
+            account,
+            is_bid: true,
+            offset: 1,
+            lots: 1,
+        };
+        let blocks = [
+            sim.block(&[open(0, 0, true), open(0, 1, false), open(1, 2, true)]),
+            sim.block(&[place(1, 0), place(0, 2), Action::Close { position: 0 }]),
+        ];
+
+        let mut numbers = vec![];
+        for block in &blocks {
+            let events = first.apply_events(block).unwrap().unwrap();
+            let other = second.apply_events(block).unwrap().unwrap();
+            let sequenced = events
+                .sequenced_events()
+                .map(|(n, e)| (n, format!("{e:?}")))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                sequenced,
+                other
+                    .sequenced_events()
+                    .map(|(n, e)| (n, format!("{e:?}")))
+                    .collect::<Vec<_>>()
+            );
+            assert_eq!(sequenced[0].0, block.instant().block_number() << 32);
+            assert_eq!(
+                events.events().first().unwrap().sequence_number(),
+                sequenced[0].0
+            );
+            numbers.extend(sequenced.into_iter().map(|(n, _)| n));
+        }
+        assert!(numbers.is_sorted_by(|a, b| a < b));
+        assert!(numbers.len() > 6);
+    }
+
+    #[test]
+    fn balance_changes_with_reasons() {
+        use crate::abi::dex::Exchange as Abi;
+
+        let mut sim = Simulator::new(&[1], 1);
+        let mut exchange = sim.exchange();
+        let initial = exchange.accounts()[&1].balance();
+        let changes = |events: StateBlockEvents| {
+            events
+                .flat_events()
+                .filter_map(|(_, e)| match e {
+                    StateEvents::Account(AccountEvent {
+                        r#type: AccountEventType::BalanceChanged { delta, reason },
+                        ..
+                    }) => Some((*delta, *reason)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let transfer = |block_number, event| {
+            stream::RawBlockEvents::new(
+                types::StateInstant::new(block_number, block_number),
+                vec![EventContext::new(
+                    Default::default(),
+                    0,
+                    0,
+                    Address::ZERO,
+                    event,
+                )],
+            )
+        };
+        let cc = exchange.collateral_converter();
+        let deposit = |balance| {
+            ExchangeEvents::CollateralDeposit(Abi::CollateralDeposit {
+                accountId: U256::from(1),
+                amountCNS: U256::ZERO,
+                balanceCNS: cc.to_unsigned(balance),
+            })
+        };
+
+        let events = exchange
+            .apply_events(&transfer(2, deposit(initial + udec128!(50))))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            changes(events),
+            vec![(dec128!(50), BalanceChangeReason::Deposit)]
+        );
+        let events = exchange
+            .apply_events(&transfer(
+                3,
+                ExchangeEvents::CollateralWithdrawal(Abi::CollateralWithdrawal {
+                    accountId: U256::from(1),
+                    amountCNS: U256::ZERO,
+                    balanceCNS: cc.to_unsigned(initial),
+                }),
+            ))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            changes(events),
+            vec![(dec128!(-50), BalanceChangeReason::Withdrawal)]
+        );
+        // Unchanged balance is reported without the change
+        let events = exchange
+            .apply_events(&transfer(4, deposit(initial)))
+            .unwrap()
+            .unwrap();
+        assert!(changes(events).is_empty());
+
+        // Order posting follows the balance update
+        for _ in 2..=4 {
+            sim.block(&[]);
+        }
+        let events = exchange
+            .apply_events(&sim.block(&[Action::Place {
+                perpetual: 0,
+                account: 0,
+                is_bid: true,
+                offset: 0,
+                lots: 1,
+            }]))
+            .unwrap()
+            .unwrap();
+        let account_events = events
+            .flat_events()
+            .filter_map(|(_, e)| match e {
+                StateEvents::Account(e) => Some(e.r#type),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            account_events[..],
+            [
+                AccountEventType::LockedBalanceUpdated(_),
+                AccountEventType::BalanceUpdated(_),
+                AccountEventType::BalanceChanged {
+                    reason: BalanceChangeReason::OrderPosting,
+                    ..
+                }
+            ]
+        ));
+    }
+
+    #[test]
+    fn halt_and_pause_suspend_orders() {
+        use crate::abi::dex::Exchange as Abi;
+
+        let mut exchange = Simulator::new(&[1, 2], 1).exchange();
+        let apply = |exchange: &mut Exchange, block_number, event| {
+            let events = stream::RawBlockEvents::new(
+                types::StateInstant::new(block_number, block_number),
+                vec![EventContext::new(
+                    Default::default(),
+                    0,
+                    0,
+                    Address::ZERO,
+                    event,
+                )],
+            );
+            exchange.apply_events(&events).unwrap();
+        };
+        let requests = |perp_id| {
+            [types::OrderRequest::cancel(
+                1,
+                perp_id,
+                types::OrderId::new(1).unwrap(),
+            )]
+        };
+        assert!(exchange.ensure_requests_available(&requests(1)).is_ok());
+
+        apply(
+            &mut exchange,
+            2,
+            ExchangeEvents::ContractPaused(Abi::ContractPaused {
+                perpId: U256::from(2),
+                paused: true,
+            }),
+        );
+        assert_eq!(exchange.ensure_available(1), Ok(()));
+        assert_eq!(
+            exchange.ensure_available(2),
+            Err(VenueUnavailable::Paused(2))
+        );
+        assert!(matches!(
+            exchange.ensure_requests_available(&requests(2)),
+            Err(DexError::VenueUnavailable(VenueUnavailable::Paused(2)))
+        ));
+
+        apply(
+            &mut exchange,
+            3,
+            ExchangeEvents::ExchangeHalted(Abi::ExchangeHalted { halted: true }),
+        );
+        assert_eq!(exchange.ensure_available(1), Err(VenueUnavailable::Halted));
+
+        apply(
+            &mut exchange,
+            4,
+            ExchangeEvents::ExchangeHalted(Abi::ExchangeHalted { halted: false }),
+        );
+        apply(
+            &mut exchange,
+            5,
+            ExchangeEvents::ContractPaused(Abi::ContractPaused {
+                perpId: U256::from(2),
+                paused: false,
+            }),
+        );
+        assert!(exchange.ensure_requests_available(&requests(2)).is_ok());
+        // Untracked perpetual contracts are not checked
+        assert_eq!(exchange.ensure_available(9), Ok(()));
+    }
+
+    #[test]
+    fn withdraw_rate_limit_reset() {
+        use crate::abi::dex::Exchange as Abi;
+
+        let mut exchange = Simulator::new(&[1], 1).exchange();
+        assert_eq!(exchange.withdraw_rate_limit(), None);
+
+        let cc = exchange.collateral_converter();
+        let events = stream::RawBlockEvents::new(
+            types::StateInstant::new(2, 2),
+            vec![EventContext::new(
+                Default::default(),
+                0,
+                0,
+                Address::ZERO,
+                ExchangeEvents::WithdrawRateLimitReset(Abi::WithdrawRateLimitReset {
+                    newExpiryBlock: U256::from(100),
+                    newLimitCNS: cc.to_unsigned(fastnum::udec128!(5000)),
+                    perBlockCNS: cc.to_unsigned(fastnum::udec128!(50)),
+                }),
+            )],
+        );
+        let state_events = exchange.apply_events(&events).unwrap().unwrap();
+        let limit = WithdrawRateLimit {
+            expiry_block: 100,
+            limit: fastnum::udec128!(5000),
+            per_block: fastnum::udec128!(50),
+        };
+        assert_eq!(exchange.withdraw_rate_limit(), Some(limit));
+        assert!(matches!(
+            state_events.events_of(&state_events.events()[0]),
+            [StateEvents::Exchange(ExchangeEvent::WithdrawRateLimitReset(l))] if *l == limit
+        ));
+    }
+
+    #[test]
+    fn upgrade_halts_event_application() {
+        use crate::abi::dex::Exchange as Abi;
+
+        let mut exchange = Simulator::new(&[1], 1).exchange();
+        let implementation = Address::with_last_byte(7);
+        let block = |block_number, events: Vec<ExchangeEvents>| {
+            stream::RawBlockEvents::new(
+                types::StateInstant::new(block_number, block_number),
+                events
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, e)| {
+                        EventContext::new(Default::default(), 0, i as u64, Address::ZERO, e)
+                    })
+                    .collect(),
+            )
+        };
+        let upgraded = |block_number| {
+            block(
+                block_number,
+                vec![ExchangeEvents::Upgraded(Abi::Upgraded { implementation })],
+            )
+        };
+
+        // Reported without halting by default
+        let result = exchange.apply_events(&upgraded(2)).unwrap().unwrap();
+        assert!(matches!(
+            result.flat_events().map(|(_, e)| e).collect::<Vec<_>>()[..],
+            [StateEvents::Exchange(ExchangeEvent::ImplementationUpgraded(addr))] if *addr == implementation
+        ));
+        assert_eq!(exchange.pending_upgrade(), None);
+
+        exchange.set_halt_on_upgrade(true);
+        exchange.apply_events(&upgraded(3)).unwrap();
+        assert_eq!(exchange.pending_upgrade(), Some(implementation));
+        assert!(matches!(
+            exchange.apply_events(&block(4, vec![])),
+            Err(DexError::UpgradePending(addr)) if addr == implementation
+        ));
+        assert_eq!(exchange.instant().block_number(), 3);
+
+        assert_eq!(exchange.acknowledge_upgrade(), Some(implementation));
+        assert!(exchange.apply_events(&block(4, vec![])).unwrap().is_some());
+        assert_eq!(exchange.instant().block_number(), 4);
+    }
+
+    #[test]
+    fn account_address_index() {
+        use crate::abi::dex::Exchange as Abi;
+
+        let mut exchange = Simulator::new(&[1], 2).exchange();
+        let first = Address::with_last_byte(1);
+        assert_eq!(exchange.account_id_by_address(first), Some(1));
+        assert_eq!(exchange.account_by_address(first).unwrap().id(), 1);
+
+        let created = Address::with_last_byte(10);
+        assert!(exchange.account_by_address(created).is_none());
+        let events = stream::RawBlockEvents::new(
+            types::StateInstant::new(exchange.instant().block_number() + 1, 0),
+            vec![EventContext::new(
+                Default::default(),
+                0,
+                0,
+                Address::ZERO,
+                ExchangeEvents::AccountCreated(Abi::AccountCreated {
+                    account: created,
+                    id: U256::from(10),
+                }),
+            )],
+        );
+        exchange.apply_events(&events).unwrap();
+        assert_eq!(exchange.account_id_by_address(created), Some(10));
+        assert_eq!(exchange.account_by_address(created).unwrap().id(), 10);
+    }
+
+    #[test]
+    fn positions_only_accounts() {
+        let mut sim = Simulator::new(&[1], 2);
+        let mut exchange = sim.exchange();
+        Arc::make_mut(exchange.accounts.get_mut(&2).unwrap()).track_positions_only();
+
+        let place = |account| Action::Place {
+            perpetual: 0,
+            account,
+            is_bid: true,
+            offset: 0,
+            lots: 10,
+        };
+        let open = Action::Open {
+            perpetual: 0,
+            account: 1,
+            is_long: true,
+            lots: 1,
+        };
+        let events = exchange
+            .apply_events(&sim.block(&[place(0), place(1), open]))
+            .unwrap()
+            .unwrap();
+        let balance_updates = events
+            .flat_events()
+            .filter_map(|(_, e)| match e {
+                StateEvents::Account(AccountEvent { account_id, .. }) => Some(*account_id),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // Locked balance, balance and its change of the fully tracked account only
+        assert_eq!(balance_updates, vec![1, 1, 1]);
+
+        let acc = &exchange.accounts()[&2];
+        assert_eq!(acc.tracking(), AccountTracking::PositionsOnly);
+        assert_eq!(acc.locked_balance(), UD128::ZERO);
+        assert!(acc.positions().contains_key(&1));
+        assert!(exchange.accounts()[&1].locked_balance() > UD128::ZERO);
+    }
+
+    #[test]
+    fn order_change_linked_to_request() {
+        let mut sim = Simulator::new(&[1], 1);
+        let mut exchange = sim.exchange();
+        let place = Action::Place {
+            perpetual: 0,
+            account: 0,
+            is_bid: false,
+            offset: 0,
+            lots: 10,
+        };
+        exchange.apply_events(&sim.block(&[place])).unwrap();
+
+        // Request IDs are assigned sequentially by the simulator
+        let modify = Action::Modify {
+            order: 0,
+            offset: 4,
+            lots: 5,
+        };
+        let events = exchange
+            .apply_events(&sim.block(&[modify]))
+            .unwrap()
+            .unwrap();
+        let updated = events
+            .flat_events()
+            .find_map(|(_, e)| match e {
+                StateEvents::Order(
+                    oe @ OrderEvent {
+                        r#type: OrderEventType::Updated { .. },
+                        ..
+                    },
+                ) => Some(oe.clone()),
+                _ => None,
+            })
+            .expect("order updated");
+        assert_eq!(updated.request_id, Some(2));
+        let order = exchange.perpetuals()[&1]
+            .get_order(updated.order_id.unwrap())
+            .copied()
+            .unwrap();
+        assert_eq!(order.request_id(), Some(2));
+        assert_eq!(order.price(), fastnum::udec64!(1005));
+        assert_eq!(order.size(), fastnum::udec64!(5));
+    }
+}
@@ -0,0 +1,105 @@
+//! Trading fees computed the way the exchange does.
+
+use alloy::primitives::U256;
+use fastnum::{UD64, UD128};
+
+use crate::num::{self, Rounding};
+
+/// Denominator of the fee rates, the exchange keeps fees in 1/100K of the notional.
+const FEE_DENOMINATOR: u64 = 100_000;
+
+/// Maker and taker fees of the perpetual contract, see [`super::Perpetual::fee_schedule`].
+///
+/// Fee amounts are computed with the integer math of the exchange: the notional value
+/// gets truncated to the collateral token precision and the fee rounded down to it,
+/// so the expected fees match the on-chain amounts exactly.
+#[derive(Clone, Copy, derive_more::Debug)]
+pub struct FeeSchedule {
+    #[debug("{maker_fee}")]
+    maker_fee: UD64,
+    #[debug("{taker_fee}")]
+    taker_fee: UD64,
+    fee_converter: num::Converter,
+    collateral_converter: num::Converter,
+}
+
+impl FeeSchedule {
+    pub(crate) fn new(
+        maker_fee: UD64,
+        taker_fee: UD64,
+        fee_converter: num::Converter,
+        collateral_converter: num::Converter,
+    ) -> Self {
+        Self {
+            maker_fee,
+            taker_fee,
+            fee_converter,
+            collateral_converter,
+        }
+    }
+
+    /// Maker fee, as a fraction of the notional value.
+    pub fn maker_fee(&self) -> UD64 {
+        self.maker_fee
+    }
+
+    /// Taker fee, as a fraction of the notional value.
+    pub fn taker_fee(&self) -> UD64 {
+        self.taker_fee
+    }
+
+    /// Maker fee in collateral tokens for the fill of the size at the price.
+    pub fn maker_fee_for(&self, size: UD64, price: UD64) -> UD128 {
+        self.fee_for(self.maker_fee, size, price)
+    }
+
+    /// Taker fee in collateral tokens for the fill of the size at the price.
+    pub fn taker_fee_for(&self, size: UD64, price: UD64) -> UD128 {
+        self.fee_for(self.taker_fee, size, price)
+    }
+
+    fn fee_for(&self, fee: UD64, size: UD64, price: UD64) -> UD128 {
+        let cc = self.collateral_converter;
+        let notional: UD128 = price.resize() * size.resize();
+        let notional_cns = cc.to_unsigned_rounded(notional, Rounding::Floor);
+        let fee_per_100k = self.fee_converter.to_unsigned(fee);
+        cc.from_unsigned(notional_cns * fee_per_100k / U256::from(FEE_DENOMINATOR))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fastnum::{udec64, udec128};
+
+    use super::*;
+
+    #[test]
+    fn fees_rounded_down() {
+        // 0.035% taker and 0.01% maker fee with 6 decimals collateral
+        let schedule = FeeSchedule::new(
+            udec64!(0.0001),
+            udec64!(0.00035),
+            num::Converter::new(5),
+            num::Converter::new(6),
+        );
+        assert_eq!(
+            schedule.taker_fee_for(udec64!(2), udec64!(1000)),
+            udec128!(0.7)
+        );
+        assert_eq!(
+            schedule.maker_fee_for(udec64!(2), udec64!(1000)),
+            udec128!(0.2)
+        );
+
+        // 0.123456 * 0.00035 = 0.0000432096 -> 0.000043
+        assert_eq!(
+            schedule.taker_fee_for(udec64!(0.1234567), udec64!(1)),
+            udec128!(0.000043)
+        );
+        // Notional gets truncated first: 0.000001 * 0.0001 -> 0
+        assert_eq!(
+            schedule.maker_fee_for(udec64!(0.0000019), udec64!(1)),
+            UD128::ZERO
+        );
+    }
+}
@@ -0,0 +1,295 @@
+//! Schedule and history of perpetual contract funding events.
+
+use std::{collections::VecDeque, time::Duration};
+
+use fastnum::{D64, D128, D256};
+
+use crate::types;
+
+/// Default number of funding events kept per perpetual contract.
+pub(crate) const DEFAULT_FUNDING_HISTORY_RETENTION: usize = 512;
+
+/// Seconds in a (365 days) year, to annualize funding rates.
+const SECONDS_PER_YEAR: u64 = 365 * 24 * 60 * 60;
+
+/// Iterator over the upcoming funding event boundaries of the perpetual contract,
+/// see [`super::Perpetual::funding_schedule`]. Endless unless the funding interval is zero.
+///
+/// Yields instants with the block numbers of the boundaries and timestamps estimated
+/// from the average block time, relative to the instant the schedule was computed at.
+#[derive(Clone, Copy, Debug)]
+pub struct FundingSchedule {
+    from: types::StateInstant,
+    next_block: Option<u64>,
+    interval_blocks: u64,
+    block_time: Duration,
+}
+
+impl FundingSchedule {
+    pub(crate) fn new(
+        from: types::StateInstant,
+        start_block: u64,
+        interval_blocks: u32,
+        block_time: Duration,
+    ) -> Self {
+        let interval_blocks = interval_blocks as u64;
+        let current = from.block_number();
+        let next_block = if current < start_block {
+            Some(start_block)
+        } else {
+            // Funding is not scheduled past the start block without the interval
+            (current - start_block)
+                .checked_div(interval_blocks)
+                .map(|elapsed| start_block + (elapsed + 1) * interval_blocks)
+        };
+        Self {
+            from,
+            next_block,
+            interval_blocks,
+            block_time,
+        }
+    }
+
+    /// Estimated timestamp of the future block.
+    fn estimate_timestamp(&self, block_number: u64) -> u64 {
+        let blocks = block_number.saturating_sub(self.from.block_number()) as u128;
+        let elapsed = (blocks * self.block_time.as_millis() / 1000) as u64;
+        self.from.block_timestamp() + elapsed
+    }
+}
+
+impl Iterator for FundingSchedule {
+    type Item = types::StateInstant;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let block_number = self.next_block?;
+        self.next_block = (self.interval_blocks > 0).then(|| block_number + self.interval_blocks);
+        Some(types::StateInstant::new(
+            block_number,
+            self.estimate_timestamp(block_number),
+        ))
+    }
+}
+
+/// Realized funding event of the perpetual contract,
+/// see [`super::Perpetual::funding_history`].
+#[derive(Clone, Copy, derive_more::Debug, PartialEq, Eq)]
+pub struct FundingRecord {
+    /// Instant of the block the funding event got completed in.
+    pub instant: types::StateInstant,
+
+    /// Funding event block number the rate applies from.
+    pub block_number: u64,
+
+    /// Funding rate, as a fraction of the position notional.
+    #[debug("{rate}")]
+    pub rate: D64,
+
+    /// Funding payment per unit of the position size, paid by longs if positive.
+    #[debug("{payment_per_unit}")]
+    pub payment_per_unit: D256,
+}
+
+/// Most recent funding events, bounded by the retention.
+#[derive(Clone, Debug)]
+pub(crate) struct FundingHistory {
+    retention: usize,
+    records: VecDeque<FundingRecord>,
+}
+
+impl Default for FundingHistory {
+    fn default() -> Self {
+        Self {
+            retention: DEFAULT_FUNDING_HISTORY_RETENTION,
+            records: VecDeque::new(),
+        }
+    }
+}
+
+impl FundingHistory {
+    pub(crate) fn record(&mut self, record: FundingRecord) {
+        if self.retention == 0 {
+            return;
+        }
+        if self.records.len() == self.retention {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+
+    pub(crate) fn set_retention(&mut self, retention: usize) {
+        self.retention = retention;
+        while self.records.len() > retention {
+            self.records.pop_front();
+        }
+    }
+
+    /// Drops records recorded before the block, returning the number dropped.
+    pub(crate) fn prune_before(&mut self, block_number: u64) -> usize {
+        let len = self.records.len();
+        while self
+            .records
+            .front()
+            .is_some_and(|r| r.instant.block_number() < block_number)
+        {
+            self.records.pop_front();
+        }
+        len - self.records.len()
+    }
+
+    /// Records completed within the window before the given timestamp.
+    pub(crate) fn window(&self, now: u64, window: Duration) -> FundingWindow<'_> {
+        let since = now.saturating_sub(window.as_secs());
+        let start = self
+            .records
+            .partition_point(|r| r.instant.block_timestamp() < since);
+        FundingWindow {
+            records: self.records.range(start..).collect(),
+        }
+    }
+}
+
+/// Funding events within the time window, oldest first,
+/// see [`super::Perpetual::funding_history`].
+#[derive(Clone, Debug)]
+pub struct FundingWindow<'a> {
+    records: Vec<&'a FundingRecord>,
+}
+
+impl<'a> FundingWindow<'a> {
+    /// Funding events within the window, oldest first.
+    pub fn records(&self) -> impl DoubleEndedIterator<Item = &'a FundingRecord> + '_ {
+        self.records.iter().copied()
+    }
+
+    /// Number of funding events within the window.
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Mean funding rate per event, `None` if there are no events.
+    pub fn mean_rate(&self) -> Option<D64> {
+        if self.records.is_empty() {
+            return None;
+        }
+        let sum = self
+            .records
+            .iter()
+            .fold(D128::ZERO, |sum, r| sum + r.rate.resize());
+        Some((sum / D128::from(self.records.len() as u64)).resize())
+    }
+
+    /// Mean funding rate scaled to a year by the observed frequency of funding events,
+    /// `None` unless there are at least two events completed at different times.
+    pub fn annualized_rate(&self) -> Option<D64> {
+        let (first, last) = (self.records.first()?, self.records.last()?);
+        let span = last
+            .instant
+            .block_timestamp()
+            .checked_sub(first.instant.block_timestamp())
+            .filter(|span| *span > 0)?;
+        let intervals = (self.records.len() - 1) as u64;
+        let per_year = D128::from(SECONDS_PER_YEAR * intervals) / D128::from(span);
+        Some((self.mean_rate()?.resize() * per_year).resize())
+    }
+
+    /// Sum of funding payments per unit of the position size within the window.
+    pub fn total_payment_per_unit(&self) -> D256 {
+        self.records
+            .iter()
+            .fold(D256::ZERO, |sum, r| sum + r.payment_per_unit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fastnum::{dec64, dec256};
+
+    use super::*;
+
+    #[test]
+    fn schedule_boundaries() {
+        let block_time = Duration::from_millis(400);
+        let from = types::StateInstant::new(1_050, 10_000);
+        let schedule = FundingSchedule::new(from, 1_000, 100, block_time)
+            .take(2)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            schedule,
+            vec![
+                types::StateInstant::new(1_100, 10_020),
+                types::StateInstant::new(1_200, 10_060)
+            ]
+        );
+
+        // Boundary block itself is not upcoming
+        let from = types::StateInstant::new(1_100, 10_000);
+        let next = FundingSchedule::new(from, 1_000, 100, block_time).next();
+        assert_eq!(next.unwrap().block_number(), 1_200);
+
+        // Before the start block
+        let from = types::StateInstant::new(900, 10_000);
+        let next = FundingSchedule::new(from, 1_000, 100, block_time).next();
+        assert_eq!(next, Some(types::StateInstant::new(1_000, 10_040)));
+
+        // No interval, only the start block if still ahead
+        let mut schedule = FundingSchedule::new(from, 1_000, 0, block_time);
+        assert_eq!(schedule.next().unwrap().block_number(), 1_000);
+        assert!(schedule.next().is_none());
+        let from = types::StateInstant::new(1_050, 10_000);
+        assert!(
+            FundingSchedule::new(from, 1_000, 0, block_time)
+                .next()
+                .is_none()
+        );
+    }
+
+    fn record(timestamp: u64, rate: D64) -> FundingRecord {
+        FundingRecord {
+            instant: types::StateInstant::new(timestamp, timestamp),
+            block_number: timestamp,
+            rate,
+            payment_per_unit: rate.resize() * D256::TEN,
+        }
+    }
+
+    #[test]
+    fn history_window_stats() {
+        let mut history = FundingHistory::default();
+        history.set_retention(3);
+        assert!(history.window(0, Duration::MAX).mean_rate().is_none());
+
+        for (timestamp, rate) in [
+            (0, dec64!(0.5)),
+            (3_600, dec64!(0.0001)),
+            (7_200, dec64!(0.0003)),
+            (10_800, dec64!(-0.0001)),
+        ] {
+            history.record(record(timestamp, rate));
+        }
+
+        // Oldest record is evicted by the retention
+        let all = history.window(10_800, Duration::from_secs(86_400));
+        assert_eq!(all.len(), 3);
+        assert_eq!(
+            all.records().next().unwrap().instant.block_timestamp(),
+            3_600
+        );
+        assert_eq!(all.mean_rate(), Some(dec64!(0.0001)));
+        // Hourly funding
+        assert_eq!(all.annualized_rate(), Some(dec64!(0.876)));
+        assert_eq!(all.total_payment_per_unit(), dec256!(0.003));
+
+        let recent = history.window(10_800, Duration::from_secs(3_600));
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent.mean_rate(), Some(dec64!(0.0001)));
+
+        let last = history.window(10_800, Duration::ZERO);
+        assert_eq!(last.len(), 1);
+        assert_eq!(last.annualized_rate(), None);
+    }
+}
@@ -0,0 +1,182 @@
+//! Deterministic hash of the exchange state.
+
+use std::{collections::HashMap, fmt::Display};
+
+use alloy::primitives::{B256, Keccak256};
+use fastnum::{D64, UD64, UD128};
+
+use super::{Exchange, diff};
+use crate::types;
+
+/// Version of the hashed encoding, changed along with it so hashes of
+/// incompatible SDK versions never match.
+const ENCODING_VERSION: u8 = 1;
+
+pub(crate) fn state_hash(exchange: &Exchange) -> B256 {
+    let mut hasher = StateHasher::default();
+    hasher.u8(ENCODING_VERSION);
+    hasher.u64(exchange.instant().block_number());
+    hasher.bool(exchange.is_halted());
+
+    let perpetuals = sorted(exchange.perpetuals());
+    hasher.u64(perpetuals.len() as u64);
+    for (perp_id, perp) in perpetuals {
+        let params = diff::perpetual_params(perp);
+        hasher.u64(perp_id as u64);
+        hasher.bool(params.is_paused);
+        hasher.ud64(params.maker_fee);
+        hasher.ud64(params.taker_fee);
+        hasher.ud64(params.initial_margin);
+        hasher.ud64(params.maintenance_margin);
+        hasher.ud64(params.tick_size);
+        hasher.ud64(params.lot_size);
+        hasher.ud64(params.mark_price);
+        hasher.ud64(params.oracle_price);
+        hasher.d64(params.funding_rate);
+        hasher.u64(params.funding_start_block);
+        hasher.ud128(params.open_interest);
+
+        // Slab iterates in ascending order ID already
+        let orders = perp.l3_book().all_orders();
+        hasher.u64(orders.len() as u64);
+        for (order_id, order) in orders.iter() {
+            let order = order.order();
+            hasher.u64(order_id.get() as u64);
+            hasher.u8(match order.r#type() {
+                types::OrderType::OpenLong => 0,
+                types::OrderType::OpenShort => 1,
+                types::OrderType::CloseLong => 2,
+                types::OrderType::CloseShort => 3,
+            });
+            hasher.u64(order.account_id() as u64);
+            hasher.ud64(order.price());
+            hasher.ud64(order.size());
+            hasher.u64(order.expiry_block());
+            hasher.ud64(order.leverage());
+        }
+    }
+
+    let accounts = sorted(exchange.accounts());
+    hasher.u64(accounts.len() as u64);
+    for (account_id, account) in accounts {
+        let balances = diff::account_balances(account);
+        hasher.u64(account_id as u64);
+        hasher.ud128(balances.balance);
+        hasher.ud128(balances.locked_balance);
+        hasher.bool(balances.frozen);
+
+        let positions = sorted(account.positions());
+        hasher.u64(positions.len() as u64);
+        for (perp_id, position) in positions {
+            hasher.u64(perp_id as u64);
+            hasher.u8(position.r#type() as u8);
+            hasher.ud64(position.entry_price());
+            hasher.ud64(position.size());
+            hasher.ud128(position.deposit());
+        }
+    }
+
+    hasher.finalize()
+}
+
+fn sorted<K: Copy + Ord, V>(map: &HashMap<K, V>) -> Vec<(K, &V)> {
+    let mut entries = map.iter().map(|(k, v)| (*k, v)).collect::<Vec<_>>();
+    entries.sort_unstable_by_key(|(k, _)| *k);
+    entries
+}
+
+/// Keccak-256 over the fixed-width integers and length-prefixed decimals.
+#[derive(Default)]
+struct StateHasher(Keccak256);
+
+impl StateHasher {
+    fn u8(&mut self, value: u8) {
+        self.0.update([value]);
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.0.update(value.to_be_bytes());
+    }
+
+    fn ud64(&mut self, value: UD64) {
+        self.decimal(value.reduce());
+    }
+
+    fn ud128(&mut self, value: UD128) {
+        self.decimal(value.reduce());
+    }
+
+    fn d64(&mut self, value: D64) {
+        self.decimal(value.reduce());
+    }
+
+    /// Decimals are hashed in their shortest form, so equal values of
+    /// different scales, e.g. 1.50 and 1.5, hash the same.
+    fn decimal(&mut self, value: impl Display) {
+        let text = value.to_string();
+        self.u64(text.len() as u64);
+        self.0.update(text.as_bytes());
+    }
+
+    fn finalize(self) -> B256 {
+        self.0.finalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fastnum::udec64;
+
+    use super::*;
+    use crate::testing::fuzz::{Action, Simulator};
+
+    #[test]
+    fn hash_follows_state() {
+        let mut sim = Simulator::new(&[1, 2], 2);
+        let mut applied = sim.exchange();
+        let mut other = sim.exchange();
+        assert_eq!(applied.state_hash(), other.state_hash());
+
+        let block = sim.block(&[
+            Action::Place {
+                perpetual: 1,
+                account: 0,
+                is_bid: false,
+                offset: 2,
+                lots: 1,
+            },
+            Action::Open {
+                perpetual: 0,
+                account: 1,
+                is_long: false,
+                lots: 2,
+            },
+        ]);
+        applied.apply_events(&block).unwrap();
+        assert_ne!(applied.state_hash(), other.state_hash());
+
+        other.apply_events(&block).unwrap();
+        assert_eq!(applied.state_hash(), other.state_hash());
+
+        // Same state at the next block hashes differently
+        let empty = sim.block(&[]);
+        other.apply_events(&empty).unwrap();
+        assert_ne!(applied.state_hash(), other.state_hash());
+    }
+
+    #[test]
+    fn decimals_hash_regardless_of_scale() {
+        let hash = |value: UD64| {
+            let mut hasher = StateHasher::default();
+            hasher.ud64(value);
+            hasher.finalize()
+        };
+        assert_eq!(hash(udec64!(1.50)), hash(udec64!(1.5)));
+        assert_eq!(hash(udec64!(100)), hash(udec64!(100.000)));
+        assert_ne!(hash(udec64!(1.5)), hash(udec64!(15)));
+    }
+}
@@ -0,0 +1,232 @@
+//! Protocol and insurance fund balances of the exchange.
+
+use std::collections::VecDeque;
+
+use fastnum::{D128, UD128};
+
+use crate::types;
+
+/// Default number of fund balance changes kept by the exchange.
+pub(crate) const DEFAULT_FUND_HISTORY_RETENTION: usize = 256;
+
+/// Exchange-level fund holding collateral outside of the accounts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Fund {
+    /// Protocol balance of the exchange, see [`super::Exchange::protocol_balance`].
+    Protocol,
+
+    /// Insurance fund of the perpetual contract, see [`super::Perpetual::insurance_balance`].
+    Insurance(types::PerpetualId),
+}
+
+/// Cause of the fund balance change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FundChangeReason {
+    /// Collateral deposited to the protocol balance (`ProtocolBalanceDeposit`).
+    Deposit,
+
+    /// Collateral withdrawn from the protocol balance (`ProtocolBalanceWithdraw`).
+    Withdrawal,
+
+    /// Transfer between the protocol balance and the account
+    /// (`TransferAccountToProtocol`, `TransferProtocolToAccount`).
+    AccountTransfer(types::AccountId),
+
+    /// Transfer between the protocol balance and the insurance fund
+    /// (`TransferPerpInsToProtocol`, `TransferProtocolToPerp`).
+    InsuranceTransfer(types::PerpetualId),
+
+    /// Transfer from the protocol balance to the balance paying recycling fees
+    /// (`TransferProtocolToRecycleBal`).
+    RecycleTransfer,
+
+    /// Insurance fund covered the settlement of the account (`InsurancePaymentForSettlement`).
+    SettlementPayment(types::AccountId),
+}
+
+/// Single change of the fund balance, see [`super::Exchange::fund_history`].
+#[derive(Clone, Copy, PartialEq, Eq, derive_more::Debug)]
+pub struct FundChange {
+    /// Instant of the block the change occurred in.
+    pub instant: types::StateInstant,
+
+    /// Fund the balance of which changed.
+    pub fund: Fund,
+
+    /// Signed change of the balance.
+    #[debug("{delta}")]
+    pub delta: D128,
+
+    /// Balance after the change.
+    #[debug("{balance}")]
+    pub balance: UD128,
+
+    /// Cause of the change.
+    pub reason: FundChangeReason,
+}
+
+/// Most recent fund balance changes, bounded by the retention.
+#[derive(Clone, Debug)]
+pub(crate) struct FundHistory {
+    retention: usize,
+    changes: VecDeque<FundChange>,
+}
+
+impl Default for FundHistory {
+    fn default() -> Self {
+        Self {
+            retention: DEFAULT_FUND_HISTORY_RETENTION,
+            changes: VecDeque::new(),
+        }
+    }
+}
+
+impl FundHistory {
+    pub(crate) fn changes(&self) -> &VecDeque<FundChange> {
+        &self.changes
+    }
+
+    pub(crate) fn record(&mut self, change: FundChange) {
+        if self.retention == 0 {
+            return;
+        }
+        if self.changes.len() == self.retention {
+            self.changes.pop_front();
+        }
+        self.changes.push_back(change);
+    }
+
+    pub(crate) fn set_retention(&mut self, retention: usize) {
+        self.retention = retention;
+        while self.changes.len() > retention {
+            self.changes.pop_front();
+        }
+    }
+
+    /// Drops changes recorded before the block, returning the number dropped.
+    pub(crate) fn prune_before(&mut self, block_number: u64) -> usize {
+        let len = self.changes.len();
+        while self
+            .changes
+            .front()
+            .is_some_and(|r| r.instant.block_number() < block_number)
+        {
+            self.changes.pop_front();
+        }
+        len - self.changes.len()
+    }
+}
+
+/// Applies the signed delta to the balance, saturating at zero.
+pub(crate) fn apply_delta(balance: UD128, delta: D128) -> UD128 {
+    let balance = balance.to_signed() + delta;
+    if balance.is_negative() {
+        UD128::ZERO
+    } else {
+        balance.unsigned_abs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::{Address, TxHash, U256};
+    use fastnum::{dec128, udec128};
+
+    use super::*;
+    use crate::{
+        abi::dex::Exchange::{self as Abi, ExchangeEvents},
+        stream,
+        testing::sim::Simulator,
+    };
+
+    #[test]
+    fn tracks_fund_balances() {
+        let mut exchange = Simulator::new(&[1, 2], 1).exchange();
+        let events = [
+            ExchangeEvents::ProtocolBalanceDeposit(Abi::ProtocolBalanceDeposit {
+                amountCNS: U256::from(1000),
+            }),
+            ExchangeEvents::TransferProtocolToPerp(Abi::TransferProtocolToPerp {
+                perpId: U256::from(1),
+                amountCNS: U256::from(300),
+                toInsuranceFund: true,
+            }),
+            ExchangeEvents::TransferProtocolToPerp(Abi::TransferProtocolToPerp {
+                perpId: U256::from(2),
+                amountCNS: U256::from(200),
+                toInsuranceFund: false,
+            }),
+            ExchangeEvents::InsurancePaymentForSettlement(Abi::InsurancePaymentForSettlement {
+                perpId: U256::from(1),
+                accountId: U256::from(1),
+                insPaymentCNS: U256::from(100),
+            }),
+            ExchangeEvents::TransferPerpInsToProtocol(Abi::TransferPerpInsToProtocol {
+                perpId: U256::from(1),
+                amountCNS: U256::from(50),
+            }),
+        ];
+        exchange
+            .apply_events(&stream::RawBlockEvents::new(
+                types::StateInstant::new(2, 2),
+                events
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, e)| {
+                        stream::RawEvent::new(TxHash::ZERO, 0, i as u64, Address::ZERO, e)
+                    })
+                    .collect(),
+            ))
+            .unwrap();
+
+        assert_eq!(exchange.protocol_balance(), udec128!(550));
+        assert_eq!(exchange.perpetuals()[&1].insurance_balance(), udec128!(150));
+        assert_eq!(exchange.perpetuals()[&2].insurance_balance(), UD128::ZERO);
+        assert_eq!(exchange.insurance_fund_balance(), udec128!(150));
+
+        let history = exchange
+            .fund_history()
+            .map(|c| (c.fund, c.delta, c.balance))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            history,
+            vec![
+                (Fund::Protocol, dec128!(1000), udec128!(1000)),
+                (Fund::Protocol, dec128!(-300), udec128!(700)),
+                (Fund::Insurance(1), dec128!(300), udec128!(300)),
+                (Fund::Protocol, dec128!(-200), udec128!(500)),
+                (Fund::Insurance(1), dec128!(-100), udec128!(200)),
+                (Fund::Insurance(1), dec128!(-50), udec128!(150)),
+                (Fund::Protocol, dec128!(50), udec128!(550)),
+            ]
+        );
+        assert_eq!(
+            exchange.fund_history().nth(4).unwrap().reason,
+            FundChangeReason::SettlementPayment(1)
+        );
+    }
+
+    #[test]
+    fn history_retention() {
+        let mut history = FundHistory::default();
+        history.set_retention(2);
+        for balance in 1..=3u64 {
+            history.record(FundChange {
+                instant: types::StateInstant::new(balance, balance),
+                fund: Fund::Protocol,
+                delta: D128::ONE,
+                balance: UD128::from(balance),
+                reason: FundChangeReason::Deposit,
+            });
+        }
+        assert_eq!(
+            history
+                .changes()
+                .iter()
+                .map(|c| c.balance)
+                .collect::<Vec<_>>(),
+            vec![udec128!(2), udec128!(3)]
+        );
+        assert_eq!(apply_delta(udec128!(1), dec128!(-2)), UD128::ZERO);
+    }
+}
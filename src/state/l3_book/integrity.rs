@@ -0,0 +1,295 @@
+//! Consistency checks of the order book linked lists.
+
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+};
+
+use fastnum::UD64;
+use thiserror::Error;
+
+use super::{BookLevel, OrderBook};
+use crate::types::{OrderId, OrderSide};
+
+/// Violation of the order book invariants, see [`OrderBook::check_integrity`].
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum IntegrityIssue {
+    /// Level is kept in the book without any orders.
+    #[error("empty {side:?} level at price {price}")]
+    EmptyLevel { side: OrderSide, price: UD64 },
+
+    /// Head order of the level points to a previous order.
+    #[error("head order {order_id} of {side:?} level {price} has previous order")]
+    HeadHasPrev {
+        side: OrderSide,
+        price: UD64,
+        order_id: OrderId,
+    },
+
+    /// Tail order of the level points to a next order.
+    #[error("tail order {order_id} of {side:?} level {price} has next order")]
+    TailHasNext {
+        side: OrderSide,
+        price: UD64,
+        order_id: OrderId,
+    },
+
+    /// Tail of the level is not the last order reached from the head.
+    #[error("tail of {side:?} level {price} is {tail:?}, but traversal ends at {last:?}")]
+    TailMismatch {
+        side: OrderSide,
+        price: UD64,
+        tail: Option<OrderId>,
+        last: Option<OrderId>,
+    },
+
+    /// Link to the order is broken: the order is missing, already traversed,
+    /// or does not point back to the previous one.
+    #[error("broken link to order {order_id} at {side:?} level {price}")]
+    BrokenLink {
+        side: OrderSide,
+        price: UD64,
+        order_id: OrderId,
+    },
+
+    /// Order of another side or price is linked into the level.
+    #[error("order {order_id} is linked into foreign {side:?} level {price}")]
+    ForeignOrder {
+        side: OrderSide,
+        price: UD64,
+        order_id: OrderId,
+    },
+
+    /// Cached number of orders of the level differs from the traversed one.
+    #[error("{side:?} level {price} caches {cached} orders, but has {actual}")]
+    CountMismatch {
+        side: OrderSide,
+        price: UD64,
+        cached: u32,
+        actual: u32,
+    },
+
+    /// Cached size of the level differs from the sum of traversed order sizes.
+    #[error("{side:?} level {price} caches size {cached}, but has {actual}")]
+    SizeMismatch {
+        side: OrderSide,
+        price: UD64,
+        cached: UD64,
+        actual: UD64,
+    },
+
+    /// Order is not reachable from the head of its level.
+    #[error("order {order_id} is not linked into its level")]
+    UnlinkedOrder { order_id: OrderId },
+}
+
+/// Result of the order book consistency check.
+#[derive(Clone, Debug, Default)]
+pub struct IntegrityReport {
+    /// All detected violations, empty if the book is consistent.
+    pub issues: Vec<IntegrityIssue>,
+}
+
+impl IntegrityReport {
+    /// Whether the book is consistent.
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl OrderBook {
+    /// Validates the invariants of the price level lists: head has no previous order,
+    /// tail has no next order, each order points back to the previous one, and the cached
+    /// order count and size match the ones of the traversed orders. Every order must be
+    /// reachable from the head of the level of its side and price.
+    pub fn check_integrity(&self) -> IntegrityReport {
+        let mut issues = Vec::new();
+        let mut linked = HashSet::with_capacity(self.orders.len());
+        for (price, level) in &self.asks {
+            self.check_level(OrderSide::Ask, *price, level, &mut linked, &mut issues);
+        }
+        for (price, level) in &self.bids {
+            self.check_level(OrderSide::Bid, price.0, level, &mut linked, &mut issues);
+        }
+
+        let unlinked = self
+            .orders
+            .keys()
+            .filter(|id| !linked.contains(id))
+            .collect::<Vec<_>>();
+        issues.extend(
+            unlinked
+                .into_iter()
+                .map(|order_id| IntegrityIssue::UnlinkedOrder { order_id }),
+        );
+        IntegrityReport { issues }
+    }
+
+    /// Rebuilds the price levels from the orders if the book is inconsistent.
+    ///
+    /// Time priority is kept for the orders still reachable from the head of their level,
+    /// the rest are queued behind them in order of placement block and ID.
+    ///
+    /// Returns the report of the issues detected before the repair.
+    pub fn repair(&mut self) -> IntegrityReport {
+        let report = self.check_integrity();
+        if report.is_ok() {
+            return report;
+        }
+
+        // Queue of each level: reachable orders in link order first
+        let mut queues: HashMap<(OrderSide, UD64), Vec<OrderId>> = HashMap::new();
+        let mut queued = HashSet::with_capacity(self.orders.len());
+        let levels = self
+            .asks
+            .iter()
+            .map(|(price, level)| (OrderSide::Ask, *price, level))
+            .chain(
+                self.bids
+                    .iter()
+                    .map(|(price, level)| (OrderSide::Bid, price.0, level)),
+            );
+        for (side, price, level) in levels {
+            let mut current = level.head();
+            while let Some(id) = current
+                && let Some(order) = self.orders.get(&id)
+                && order.r#type().side() == side
+                && order.price() == price
+                && queued.insert(id)
+            {
+                queues.entry((side, price)).or_default().push(id);
+                current = order.next();
+            }
+        }
+        let mut rest = self
+            .orders
+            .values()
+            .filter(|o| !queued.contains(&o.order_id()))
+            .map(|o| (o.order().instant().block_number(), o.order_id()))
+            .collect::<Vec<_>>();
+        rest.sort();
+        for (_, id) in rest {
+            let order = &self.orders[&id];
+            queues
+                .entry((order.r#type().side(), order.price()))
+                .or_default()
+                .push(id);
+        }
+
+        self.asks.clear();
+        self.bids.clear();
+        for ((side, price), queue) in queues {
+            let mut level = BookLevel::new();
+            level.set_head(queue.first().copied());
+            level.set_tail(queue.last().copied());
+            for (i, id) in queue.iter().enumerate() {
+                let order = self.orders.get_mut(id).expect("queued order exists");
+                order.set_prev(i.checked_sub(1).map(|p| queue[p]));
+                order.set_next(queue.get(i + 1).copied());
+                level.add_size(order.size());
+            }
+            match side {
+                OrderSide::Ask => self.asks.insert(price, level),
+                OrderSide::Bid => self.bids.insert(Reverse(price), level),
+            };
+        }
+        report
+    }
+
+    fn check_level(
+        &self,
+        side: OrderSide,
+        price: UD64,
+        level: &BookLevel,
+        linked: &mut HashSet<OrderId>,
+        issues: &mut Vec<IntegrityIssue>,
+    ) {
+        if level.is_empty() {
+            issues.push(IntegrityIssue::EmptyLevel { side, price });
+        }
+        if let Some(order_id) = level.head()
+            && self
+                .orders
+                .get(&order_id)
+                .is_some_and(|o| o.prev().is_some())
+        {
+            issues.push(IntegrityIssue::HeadHasPrev {
+                side,
+                price,
+                order_id,
+            });
+        }
+
+        let (mut count, mut size) = (0, UD64::ZERO);
+        let (mut prev, mut current) = (None, level.head());
+        while let Some(order_id) = current {
+            let Some(order) = self
+                .orders
+                .get(&order_id)
+                .filter(|_| linked.insert(order_id))
+            else {
+                issues.push(IntegrityIssue::BrokenLink {
+                    side,
+                    price,
+                    order_id,
+                });
+                break;
+            };
+            if prev.is_some() && order.prev() != prev {
+                issues.push(IntegrityIssue::BrokenLink {
+                    side,
+                    price,
+                    order_id,
+                });
+            }
+            if order.r#type().side() != side || order.price() != price {
+                issues.push(IntegrityIssue::ForeignOrder {
+                    side,
+                    price,
+                    order_id,
+                });
+            }
+            count += 1;
+            size += order.size();
+            prev = current;
+            current = order.next();
+        }
+
+        if level.tail() != prev {
+            issues.push(IntegrityIssue::TailMismatch {
+                side,
+                price,
+                tail: level.tail(),
+                last: prev,
+            });
+        }
+        if let Some(order_id) = level.tail()
+            && self
+                .orders
+                .get(&order_id)
+                .is_some_and(|o| o.next().is_some())
+        {
+            issues.push(IntegrityIssue::TailHasNext {
+                side,
+                price,
+                order_id,
+            });
+        }
+        if level.num_orders() != count {
+            issues.push(IntegrityIssue::CountMismatch {
+                side,
+                price,
+                cached: level.num_orders(),
+                actual: count,
+            });
+        }
+        if level.size() != size {
+            issues.push(IntegrityIssue::SizeMismatch {
+                side,
+                price,
+                cached: level.size(),
+                actual: size,
+            });
+        }
+    }
+}
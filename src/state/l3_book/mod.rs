@@ -4,15 +4,23 @@
 //! at each price level with FIFO time-priority ordering using doubly-linked lists.
 
 mod error;
+mod integrity;
 mod level;
+mod modification;
 mod order;
+mod slab;
+mod view;
 
 #[cfg(test)]
 mod tests;
 
 pub use error::{OrderBookError, OrderBookResult};
+pub use integrity::{IntegrityIssue, IntegrityReport};
 pub use level::BookLevel;
+pub use modification::{ModificationImpact, QueuePriority};
 pub use order::BookOrder;
+pub use slab::OrderSlab;
+pub use view::{BookView, LevelView};
 
 use std::{
     cmp::Reverse,
@@ -20,19 +28,28 @@ use std::{
 };
 
 use fastnum::{UD64, UD128};
-use itertools::{FoldWhile, Itertools};
 
 use crate::{state::Order, types};
 
+/// Order book sides aggregated into price buckets, see [`OrderBook::depth_bucketed`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BucketedDepth {
+    /// Bucket price and total size of the bids, best first.
+    pub bids: Vec<(UD64, UD64)>,
+
+    /// Bucket price and total size of the asks, best first.
+    pub asks: Vec<(UD64, UD64)>,
+}
+
 /// L3 order book with intrusive linked lists.
 ///
-/// Orders are stored in a HashMap keyed by OrderId, with each price level
+/// Orders are stored in an [`OrderSlab`] indexed by OrderId, with each price level
 /// maintaining a doubly-linked list of orders in FIFO (time-priority) order.
 /// Provides both L2 (aggregated price levels) and L3 (individual orders) views.
 #[derive(Clone, Debug, Default)]
 pub struct OrderBook {
-    /// Storage for all orders, keyed by OrderId.
-    orders: HashMap<types::OrderId, BookOrder>,
+    /// Storage for all orders, indexed by OrderId.
+    orders: OrderSlab,
     /// Ask levels sorted by price (ascending, best ask first).
     asks: BTreeMap<UD64, BookLevel>,
     /// Bid levels sorted by price (descending, best bid first).
@@ -44,6 +61,11 @@ impl OrderBook {
         Self::default()
     }
 
+    /// Borrowed view for the allocation-free queries on the hot path.
+    pub fn view(&self) -> BookView<'_> {
+        BookView::new(self)
+    }
+
     // === L2 API ===
 
     /// Asks sorted away from the spread.
@@ -66,14 +88,97 @@ impl OrderBook {
         self.bids.first_key_value().map(|(k, v)| (k.0, v.size()))
     }
 
+    /// Price/size of up to `depth` best ask levels.
+    pub fn top_asks(&self, depth: usize) -> Vec<(UD64, UD64)> {
+        self.asks
+            .iter()
+            .take(depth)
+            .map(|(k, v)| (*k, v.size()))
+            .collect()
+    }
+
+    /// Price/size of up to `depth` best bid levels.
+    pub fn top_bids(&self, depth: usize) -> Vec<(UD64, UD64)> {
+        self.bids
+            .iter()
+            .take(depth)
+            .map(|(k, v)| (k.0, v.size()))
+            .collect()
+    }
+
+    /// Up to `depth` best price buckets of each side, with levels aggregated into buckets
+    /// of the given price width, e.g. for depth charts.
+    ///
+    /// Bids are bucketed down and asks up to the multiple of the bucket size, so bucket
+    /// prices are never better than the prices of their levels. Zero bucket size keeps
+    /// the levels as is.
+    pub fn depth_bucketed(&self, bucket_size: UD64, depth: usize) -> BucketedDepth {
+        let bucket = |price: UD64, round_up: bool| {
+            if bucket_size.is_zero() {
+                return price;
+            }
+            let buckets = price / bucket_size;
+            (if round_up {
+                buckets.ceil()
+            } else {
+                buckets.floor()
+            }) * bucket_size
+        };
+        BucketedDepth {
+            bids: bucket_levels(
+                self.bids
+                    .iter()
+                    .map(|(p, l)| (bucket(p.0, false), l.size())),
+                depth,
+            ),
+            asks: bucket_levels(
+                self.asks.iter().map(|(p, l)| (bucket(*p, true), l.size())),
+                depth,
+            ),
+        }
+    }
+
     /// Ask impact price for the requested size, along with the fillable size and size-averaged price.
     pub fn ask_impact(&self, want_size: UD64) -> Option<(UD64, UD64, UD64)> {
-        Self::impact(self.asks.iter(), want_size)
+        Self::impact(&self.ask_impact_levels(want_size))
     }
 
     /// Bid impact price for the requested size, along with the fillable size and size-averaged price.
     pub fn bid_impact(&self, want_size: UD64) -> Option<(UD64, UD64, UD64)> {
-        Self::impact(self.bids.iter().map(|(k, v)| (&k.0, v)), want_size)
+        Self::impact(&self.bid_impact_levels(want_size))
+    }
+
+    /// Per-level breakdown of the ask impact for the requested size,
+    /// as `(price, size)` pairs consumed in price priority.
+    pub fn ask_impact_levels(&self, want_size: UD64) -> Vec<(UD64, UD64)> {
+        Self::consume(self.asks.iter(), ImpactTarget::Size(want_size))
+    }
+
+    /// Per-level breakdown of the bid impact for the requested size,
+    /// as `(price, size)` pairs consumed in price priority.
+    pub fn bid_impact_levels(&self, want_size: UD64) -> Vec<(UD64, UD64)> {
+        Self::consume(
+            self.bids.iter().map(|(k, v)| (&k.0, v)),
+            ImpactTarget::Size(want_size),
+        )
+    }
+
+    /// Ask impact price for the requested notional (collateral amount spent at
+    /// the book prices), along with the fillable size and size-averaged price.
+    pub fn ask_impact_for_notional(&self, notional: UD128) -> Option<(UD64, UD64, UD64)> {
+        Self::impact(&Self::consume(
+            self.asks.iter(),
+            ImpactTarget::Notional(notional),
+        ))
+    }
+
+    /// Bid impact price for the requested notional (collateral amount received at
+    /// the book prices), along with the fillable size and size-averaged price.
+    pub fn bid_impact_for_notional(&self, notional: UD128) -> Option<(UD64, UD64, UD64)> {
+        Self::impact(&Self::consume(
+            self.bids.iter().map(|(k, v)| (&k.0, v)),
+            ImpactTarget::Notional(notional),
+        ))
     }
 
     // === L3 API ===
@@ -88,7 +193,7 @@ impl OrderBook {
         self.bids.get(&Reverse(price))
     }
 
-    /// Get a specific order by ID (O(1) via HashMap lookup).
+    /// Get a specific order by ID (O(1) via slab indexing).
     pub fn get_order(&self, order_id: types::OrderId) -> Option<&BookOrder> {
         self.orders.get(&order_id)
     }
@@ -98,6 +203,51 @@ impl OrderBook {
         self.get_order(order_id).map(|o| o.order())
     }
 
+    /// Resting size ahead of and behind the given order at its price level,
+    /// `None` if the order is not in the book or the level is inconsistent with its orders.
+    pub fn queue_position(&self, order_id: types::OrderId) -> Option<(UD64, UD64)> {
+        let order = self.orders.get(&order_id)?;
+        let level = self.get_level(order.r#type().side(), order.price())?;
+        let (_, ahead) = self.orders_ahead(level, order)?;
+        let queued = ahead + order.size();
+        (level.size() >= queued).then(|| (ahead, level.size() - queued))
+    }
+
+    /// Number and total size of the orders ahead of the given one at its price level,
+    /// walking the level queue back from the order.
+    ///
+    /// `None` if the queue is broken or longer than the level, e.g. looped.
+    fn orders_ahead(&self, level: &BookLevel, order: &BookOrder) -> Option<(usize, UD64)> {
+        let mut count = 0;
+        let mut size = UD64::ZERO;
+        let mut current = order.prev();
+        while let Some(id) = current {
+            let prev = self.orders.get(&id)?;
+            count += 1;
+            if count >= level.num_orders() as usize {
+                return None;
+            }
+            size += prev.size();
+            current = prev.prev();
+        }
+        Some((count, size))
+    }
+
+    /// Total resting size at the levels with better price than the given one,
+    /// on the given side of the book.
+    pub fn size_ahead_of_level(&self, side: types::OrderSide, price: UD64) -> UD64 {
+        match side {
+            types::OrderSide::Ask => self
+                .asks
+                .range(..price)
+                .fold(UD64::ZERO, |acc, (_, level)| acc + level.size()),
+            types::OrderSide::Bid => self
+                .bids
+                .range(..Reverse(price))
+                .fold(UD64::ZERO, |acc, (_, level)| acc + level.size()),
+        }
+    }
+
     /// Iterator over all L3 orders on the ask side in price-time priority.
     pub fn ask_orders(&self) -> impl Iterator<Item = &BookOrder> {
         self.asks
@@ -113,11 +263,8 @@ impl OrderBook {
     }
 
     /// Iterator over orders at a specific level (follows the linked list).
-    pub(crate) fn level_orders<'a>(&'a self, level: &'a BookLevel) -> LevelOrdersIter<'a> {
-        LevelOrdersIter {
-            orders: &self.orders,
-            current: level.head(),
-        }
+    pub(crate) fn level_orders<'a>(&'a self, level: &'a BookLevel) -> LevelOrders<'a> {
+        LevelOrders::new(&self.orders, level.head())
     }
 
     /// Total number of orders in the book.
@@ -125,11 +272,17 @@ impl OrderBook {
         self.orders.len()
     }
 
-    /// Access to all orders in the book keyed by order ID.
-    pub fn all_orders(&self) -> &HashMap<types::OrderId, BookOrder> {
+    /// Access to all orders in the book indexed by order ID.
+    pub fn all_orders(&self) -> &OrderSlab {
         &self.orders
     }
 
+    /// Number of times an order with the given ID was removed from the book,
+    /// telling apart successive orders reusing the same ID.
+    pub fn order_generation(&self, order_id: types::OrderId) -> u32 {
+        self.orders.generation(&order_id)
+    }
+
     // === Mutation methods ===
 
     /// Add an order to the book (at the back of the queue for its price level).
@@ -173,7 +326,7 @@ impl OrderBook {
         let mut l3_order = BookOrder::new(*order);
         l3_order.set_prev(old_tail);
 
-        // Insert into hashmap
+        // Insert into slab
         self.orders.insert(order_id, l3_order);
 
         // Link at tail
@@ -271,7 +424,7 @@ impl OrderBook {
             self.remove_level(side, price);
         }
 
-        // Remove from hashmap and return the order
+        // Remove from slab and return the order
         let removed = self
             .orders
             .remove(&order_id)
@@ -280,12 +433,25 @@ impl OrderBook {
         Ok(*removed.order())
     }
 
+    /// Set the placement and modification instants of the order, if it exists.
+    pub(crate) fn set_order_times(
+        &mut self,
+        order_id: types::OrderId,
+        placed_at: types::StateInstant,
+        modified_at: types::StateInstant,
+    ) {
+        if let Some(order) = self.orders.get_mut(&order_id) {
+            order.set_times(placed_at, modified_at);
+        }
+    }
+
     /// Move an order to the back of the queue (for size increases).
     ///
     /// # Errors
     ///
     /// Returns an error if:
     /// - The order doesn't exist in the book
+    #[allow(clippy::collapsible_if)]
     pub(crate) fn move_to_back(
         &mut self,
         order: &Order,
@@ -370,6 +536,7 @@ impl OrderBook {
     /// # Errors
     ///
     /// Returns an error if any order has invalid size or price.
+    #[allow(clippy::collapsible_if)]
     pub(crate) fn add_orders_from_snapshot(&mut self, orders: &[Order]) -> OrderBookResult<()> {
         // Collect order IDs for validation
         let order_ids: std::collections::HashSet<types::OrderId> =
@@ -512,6 +679,7 @@ impl OrderBook {
     }
 
     /// Unlink a node from the doubly-linked list by updating its neighbors.
+    #[allow(clippy::collapsible_if)]
     fn unlink_node(&mut self, prev_id: Option<types::OrderId>, next_id: Option<types::OrderId>) {
         // Update prev's next pointer
         if let Some(prev) = prev_id {
@@ -530,6 +698,7 @@ impl OrderBook {
 
     /// Link a new order at the tail of a level.
     /// Takes old_tail to avoid borrowing level while mutating orders.
+    #[allow(clippy::collapsible_if)]
     fn link_at_tail(
         &mut self,
         side: types::OrderSide,
@@ -554,32 +723,52 @@ impl OrderBook {
         level.add_size(size);
     }
 
-    fn impact<'a>(
-        mut side: impl Iterator<Item = (&'a UD64, &'a BookLevel)>,
-        want_size: UD64,
-    ) -> Option<(UD64, UD64, UD64)> {
-        let (price, unfilled, price_size) = side
-            .fold_while(
-                (UD64::ZERO, want_size, UD128::ZERO),
-                |(_, unfilled, price_size), (price, level)| {
-                    let level_size = level.size();
-                    if unfilled > level_size {
-                        FoldWhile::Continue((
-                            *price,
-                            unfilled - level_size,
-                            price_size + (price.resize() * level_size.resize()),
-                        ))
+    fn consume<'a>(
+        side: impl Iterator<Item = (&'a UD64, &'a BookLevel)>,
+        mut target: ImpactTarget,
+    ) -> Vec<(UD64, UD64)> {
+        let mut levels = Vec::new();
+        for (price, level) in side {
+            let level_size = level.size();
+            let take = match &mut target {
+                ImpactTarget::Size(unfilled) => {
+                    let take = level_size.min(*unfilled);
+                    *unfilled -= take;
+                    take
+                }
+                ImpactTarget::Notional(unspent) => {
+                    let level_notional = price.resize() * level_size.resize();
+                    if level_notional <= *unspent {
+                        *unspent -= level_notional;
+                        level_size
                     } else {
-                        FoldWhile::Done((
-                            *price,
-                            UD64::ZERO,
-                            price_size + (price.resize() * unfilled.resize()),
-                        ))
+                        let take: UD64 = (*unspent / price.resize()).resize().min(level_size);
+                        *unspent = UD128::ZERO;
+                        take
                     }
-                },
-            )
-            .into_inner();
-        let filled = want_size - unfilled;
+                }
+            };
+            if take > UD64::ZERO {
+                levels.push((*price, take));
+            }
+            if target.is_exhausted() {
+                break;
+            }
+        }
+        levels
+    }
+
+    fn impact(levels: &[(UD64, UD64)]) -> Option<(UD64, UD64, UD64)> {
+        let (price, filled, price_size) = levels.iter().fold(
+            (UD64::ZERO, UD64::ZERO, UD128::ZERO),
+            |(_, filled, price_size), (price, size)| {
+                (
+                    *price,
+                    filled + *size,
+                    price_size + (price.resize() * size.resize()),
+                )
+            },
+        );
         if filled > UD64::ZERO {
             Some((price, filled, (price_size / filled.resize()).resize()))
         } else {
@@ -588,13 +777,35 @@ impl OrderBook {
     }
 }
 
-/// Iterator over orders at a price level (follows linked list).
-pub(crate) struct LevelOrdersIter<'a> {
-    orders: &'a HashMap<types::OrderId, BookOrder>,
+/// Amount of liquidity to consume when calculating impact.
+enum ImpactTarget {
+    Size(UD64),
+    Notional(UD128),
+}
+
+impl ImpactTarget {
+    fn is_exhausted(&self) -> bool {
+        match self {
+            ImpactTarget::Size(unfilled) => unfilled.is_zero(),
+            ImpactTarget::Notional(unspent) => unspent.is_zero(),
+        }
+    }
+}
+
+/// Iterator over orders at a price level in time priority (follows linked list).
+#[derive(Clone, Debug)]
+pub struct LevelOrders<'a> {
+    orders: &'a OrderSlab,
     current: Option<types::OrderId>,
 }
 
-impl<'a> Iterator for LevelOrdersIter<'a> {
+impl<'a> LevelOrders<'a> {
+    fn new(orders: &'a OrderSlab, current: Option<types::OrderId>) -> Self {
+        Self { orders, current }
+    }
+}
+
+impl<'a> Iterator for LevelOrders<'a> {
     type Item = &'a BookOrder;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -604,3 +815,21 @@ impl<'a> Iterator for LevelOrdersIter<'a> {
         Some(order)
     }
 }
+
+/// Sums sizes of the consecutive levels falling into the same bucket,
+/// keeping up to `depth` buckets.
+fn bucket_levels(levels: impl Iterator<Item = (UD64, UD64)>, depth: usize) -> Vec<(UD64, UD64)> {
+    let mut buckets: Vec<(UD64, UD64)> = Vec::with_capacity(depth);
+    for (price, size) in levels {
+        if let Some((last, total)) = buckets.last_mut()
+            && *last == price
+        {
+            *total += size;
+        } else if buckets.len() == depth {
+            break;
+        } else {
+            buckets.push((price, size));
+        }
+    }
+    buckets
+}
@@ -0,0 +1,113 @@
+//! Queue priority effect of the order modification.
+
+use fastnum::UD64;
+
+use super::OrderBook;
+use crate::types;
+
+/// Effect of the modification on the queue priority of the order,
+/// following the matching engine rules.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueuePriority {
+    /// Same price with the size decreased or unchanged, the order keeps its place.
+    Kept,
+
+    /// Size increased at the same price, the order moves to the back of its level.
+    LostSizeIncrease,
+
+    /// Price changed, the order joins the back of the new level.
+    NewLevel,
+}
+
+/// Queue position of the order after the modification,
+/// see [`OrderBook::simulate_modification`].
+#[derive(Clone, Copy, PartialEq, Eq, derive_more::Debug)]
+pub struct ModificationImpact {
+    pub priority: QueuePriority,
+
+    /// Number of orders ahead at the resulting level.
+    pub orders_ahead: usize,
+
+    /// Resting size ahead at the resulting level.
+    #[debug("{size_ahead}")]
+    pub size_ahead: UD64,
+
+    /// Resting size at the levels with better price than the resulting one.
+    #[debug("{size_ahead_of_level}")]
+    pub size_ahead_of_level: UD64,
+
+    /// Resting size ahead at the current level before the modification, for comparison
+    /// with [`Self::size_ahead`].
+    #[debug("{current_size_ahead}")]
+    pub current_size_ahead: UD64,
+}
+
+impl OrderBook {
+    /// Simulates the modification of the resting order to the new price and size,
+    /// reporting whether it keeps its queue priority and where it would end up.
+    ///
+    /// The new price is assumed to rest, matching against the opposite side
+    /// is not simulated.
+    ///
+    /// Returns `None` if the order is not in the book or the new size is zero.
+    pub fn simulate_modification(
+        &self,
+        order_id: types::OrderId,
+        new_price: UD64,
+        new_size: UD64,
+    ) -> Option<ModificationImpact> {
+        if new_size.is_zero() {
+            return None;
+        }
+        let order = self.orders.get(&order_id)?;
+        let side = order.r#type().side();
+        let level = self.get_level(side, order.price())?;
+
+        let (current_orders_ahead, current_size_ahead) = self.orders_ahead(level, order)?;
+
+        let (priority, orders_ahead, size_ahead, size_ahead_of_level) =
+            if new_price != order.price() {
+                let (orders_ahead, size_ahead) = self
+                    .get_level(side, new_price)
+                    .map(|l| (l.num_orders() as usize, l.size()))
+                    .unwrap_or_default();
+                // The order leaves its current level, which may be a better one
+                let mut size_ahead_of_level = self.size_ahead_of_level(side, new_price);
+                let is_better = match side {
+                    types::OrderSide::Ask => order.price() < new_price,
+                    types::OrderSide::Bid => order.price() > new_price,
+                };
+                if is_better {
+                    size_ahead_of_level -= order.size();
+                }
+                (
+                    QueuePriority::NewLevel,
+                    orders_ahead,
+                    size_ahead,
+                    size_ahead_of_level,
+                )
+            } else if new_size > order.size() {
+                (
+                    QueuePriority::LostSizeIncrease,
+                    level.num_orders() as usize - 1,
+                    level.size() - order.size(),
+                    self.size_ahead_of_level(side, new_price),
+                )
+            } else {
+                (
+                    QueuePriority::Kept,
+                    current_orders_ahead,
+                    current_size_ahead,
+                    self.size_ahead_of_level(side, new_price),
+                )
+            };
+
+        Some(ModificationImpact {
+            priority,
+            orders_ahead,
+            size_ahead,
+            size_ahead_of_level,
+            current_size_ahead,
+        })
+    }
+}
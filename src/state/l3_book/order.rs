@@ -14,6 +14,10 @@ pub struct BookOrder {
     prev: Option<types::OrderId>,
     /// Next order in queue (toward tail). None if this is the tail.
     next: Option<types::OrderId>,
+    /// Instant the order was placed at.
+    placed_at: types::StateInstant,
+    /// Instant the order was last modified by its owner at.
+    modified_at: types::StateInstant,
 }
 
 impl BookOrder {
@@ -23,6 +27,8 @@ impl BookOrder {
             order,
             prev: None,
             next: None,
+            placed_at: order.instant(),
+            modified_at: order.instant(),
         }
     }
 
@@ -56,6 +62,28 @@ impl BookOrder {
         self.order.r#type()
     }
 
+    /// Instant the order was placed at.
+    /// For orders from the snapshot, it is the snapshot instant.
+    pub fn placed_at(&self) -> types::StateInstant {
+        self.placed_at
+    }
+
+    /// Instant the order was last placed or modified by its owner at,
+    /// fills do not count as modifications.
+    pub fn modified_at(&self) -> types::StateInstant {
+        self.modified_at
+    }
+
+    /// Number of blocks since the order was placed, as of the given block.
+    pub fn blocks_since_placed(&self, block_number: u64) -> u64 {
+        block_number.saturating_sub(self.placed_at.block_number())
+    }
+
+    /// Number of blocks since the order was last placed or modified, as of the given block.
+    pub fn blocks_since_modified(&self, block_number: u64) -> u64 {
+        block_number.saturating_sub(self.modified_at.block_number())
+    }
+
     /// Previous order in the FIFO queue (toward head).
     pub(crate) fn prev(&self) -> Option<types::OrderId> {
         self.prev
@@ -71,6 +99,16 @@ impl BookOrder {
         self.order = order;
     }
 
+    /// Set the placement and modification instants.
+    pub(crate) fn set_times(
+        &mut self,
+        placed_at: types::StateInstant,
+        modified_at: types::StateInstant,
+    ) {
+        self.placed_at = placed_at;
+        self.modified_at = modified_at;
+    }
+
     /// Set the previous order pointer.
     pub(crate) fn set_prev(&mut self, prev: Option<types::OrderId>) {
         self.prev = prev;
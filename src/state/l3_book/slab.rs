@@ -0,0 +1,117 @@
+//! Order storage indexed directly by order ID.
+
+use std::ops::Index;
+
+use super::BookOrder;
+use crate::types;
+
+/// Slot of the order ID, tagged with the number of times it was vacated.
+#[derive(Clone, Debug, Default)]
+struct Slot {
+    generation: u32,
+    order: Option<BookOrder>,
+}
+
+/// Orders of the book in slots indexed by order ID.
+///
+/// Order IDs are 16-bit and recycled by the exchange, so the slab spans at most
+/// 65535 slots and lookups are plain indexing with no hashing involved. Slots are
+/// allocated up to the highest order ID seen, and iteration goes in ascending order ID.
+///
+/// Each slot carries a generation, incremented every time its order is removed,
+/// distinguishing successive orders reusing the same ID.
+#[derive(Clone, Debug, Default)]
+pub struct OrderSlab {
+    slots: Vec<Slot>,
+    len: usize,
+}
+
+impl OrderSlab {
+    /// Number of orders.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether there are no orders.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Order by ID.
+    pub fn get(&self, order_id: &types::OrderId) -> Option<&BookOrder> {
+        self.slots
+            .get(Self::index(order_id))
+            .and_then(|s| s.order.as_ref())
+    }
+
+    /// Whether the order is present.
+    pub fn contains_key(&self, order_id: &types::OrderId) -> bool {
+        self.get(order_id).is_some()
+    }
+
+    /// Generation of the order ID slot, the number of times an order with this ID was removed.
+    pub fn generation(&self, order_id: &types::OrderId) -> u32 {
+        self.slots
+            .get(Self::index(order_id))
+            .map_or(0, |s| s.generation)
+    }
+
+    /// Orders with their IDs, in ascending order ID.
+    pub fn iter(&self) -> impl Iterator<Item = (types::OrderId, &BookOrder)> {
+        self.values().map(|o| (o.order_id(), o))
+    }
+
+    /// Order IDs in ascending order.
+    pub fn keys(&self) -> impl Iterator<Item = types::OrderId> + '_ {
+        self.values().map(|o| o.order_id())
+    }
+
+    /// Orders in ascending order ID.
+    pub fn values(&self) -> impl Iterator<Item = &BookOrder> {
+        self.slots.iter().filter_map(|s| s.order.as_ref())
+    }
+
+    pub(crate) fn get_mut(&mut self, order_id: &types::OrderId) -> Option<&mut BookOrder> {
+        self.slots
+            .get_mut(Self::index(order_id))
+            .and_then(|s| s.order.as_mut())
+    }
+
+    /// Places the order into its slot, returning the order previously stored there.
+    pub(crate) fn insert(
+        &mut self,
+        order_id: types::OrderId,
+        order: BookOrder,
+    ) -> Option<BookOrder> {
+        let index = Self::index(&order_id);
+        if index >= self.slots.len() {
+            self.slots.resize_with(index + 1, Slot::default);
+        }
+        let prev = self.slots[index].order.replace(order);
+        if prev.is_none() {
+            self.len += 1;
+        }
+        prev
+    }
+
+    /// Vacates the slot of the order, advancing its generation.
+    pub(crate) fn remove(&mut self, order_id: &types::OrderId) -> Option<BookOrder> {
+        let slot = self.slots.get_mut(Self::index(order_id))?;
+        let order = slot.order.take()?;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.len -= 1;
+        Some(order)
+    }
+
+    fn index(order_id: &types::OrderId) -> usize {
+        order_id.get() as usize - 1
+    }
+}
+
+impl Index<&types::OrderId> for OrderSlab {
+    type Output = BookOrder;
+
+    fn index(&self, order_id: &types::OrderId) -> &BookOrder {
+        self.get(order_id).expect("order exists")
+    }
+}
@@ -2,7 +2,7 @@
 
 use std::num::NonZeroU16;
 
-use fastnum::udec64;
+use fastnum::{udec64, udec128};
 
 use super::*;
 use crate::state::Order;
@@ -227,6 +227,98 @@ fn l3_book_bid_impact() {
     );
 }
 
+#[test]
+fn l3_book_impact_levels() {
+    // Per-level breakdown of consumed liquidity.
+    let mut book = OrderBook::new();
+    book.add_order(&ask!(100, 1.0, 1, 1, 1)).unwrap();
+    book.add_order(&ask!(110, 2.0, 2, 2, 2)).unwrap();
+    book.add_order(&ask!(120, 3.0, 3, 3, 3)).unwrap();
+    book.add_order(&bid!(90, 3.0, 4, 4, 4)).unwrap();
+
+    assert_eq!(
+        book.ask_impact_levels(udec64!(2.5)),
+        vec![(udec64!(100), udec64!(1.0)), (udec64!(110), udec64!(1.5))]
+    );
+    // Requesting more than available consumes the whole side.
+    assert_eq!(
+        book.ask_impact_levels(udec64!(10)),
+        vec![
+            (udec64!(100), udec64!(1.0)),
+            (udec64!(110), udec64!(2.0)),
+            (udec64!(120), udec64!(3.0))
+        ]
+    );
+    assert_eq!(
+        book.bid_impact_levels(udec64!(1)),
+        vec![(udec64!(90), udec64!(1))]
+    );
+    assert!(book.bid_impact_levels(UD64::ZERO).is_empty());
+}
+
+#[test]
+fn l3_book_impact_for_notional() {
+    // Impact sized by notional rather than contract size.
+    let mut book = OrderBook::new();
+    book.add_order(&ask!(100, 1.0, 1, 1, 1)).unwrap();
+    book.add_order(&ask!(110, 2.0, 2, 2, 2)).unwrap();
+    book.add_order(&bid!(100, 2.0, 3, 3, 3)).unwrap();
+    book.add_order(&bid!(90, 3.0, 4, 4, 4)).unwrap();
+
+    // 265 = 1.0@100 + 1.5@110
+    assert_eq!(
+        book.ask_impact_for_notional(udec128!(265)),
+        Some((udec64!(110), udec64!(2.5), udec64!(265) / udec64!(2.5)))
+    );
+    // Exactly the first level.
+    assert_eq!(
+        book.ask_impact_for_notional(udec128!(100)),
+        Some((udec64!(100), udec64!(1.0), udec64!(100)))
+    );
+    // More than the side holds: 100 + 220 = 320 available.
+    assert_eq!(
+        book.ask_impact_for_notional(udec128!(1000)),
+        Some((udec64!(110), udec64!(3.0), udec64!(320) / udec64!(3.0)))
+    );
+    // 290 = 2.0@100 + 1.0@90
+    assert_eq!(
+        book.bid_impact_for_notional(udec128!(290)),
+        Some((udec64!(90), udec64!(3.0), udec64!(290) / udec64!(3.0)))
+    );
+    assert_eq!(book.bid_impact_for_notional(UD128::ZERO), None);
+}
+
+#[test]
+fn l3_book_depth_bucketed() {
+    // Levels aggregated into price buckets, bids rounded down and asks up.
+    let mut book = OrderBook::new();
+    book.add_order(&ask!(101, 1.0, 1, 1, 1)).unwrap();
+    book.add_order(&ask!(109, 2.0, 2, 2, 2)).unwrap();
+    book.add_order(&ask!(110, 3.0, 3, 3, 3)).unwrap();
+    book.add_order(&ask!(125, 4.0, 4, 4, 4)).unwrap();
+    book.add_order(&bid!(99.5, 1.0, 5, 5, 5)).unwrap();
+    book.add_order(&bid!(90, 2.0, 6, 6, 6)).unwrap();
+    book.add_order(&bid!(85, 3.0, 7, 7, 7)).unwrap();
+
+    let depth = book.depth_bucketed(udec64!(10), 2);
+    assert_eq!(
+        depth.asks,
+        vec![(udec64!(110), udec64!(6.0)), (udec64!(130), udec64!(4.0))]
+    );
+    assert_eq!(
+        depth.bids,
+        vec![(udec64!(90), udec64!(3.0)), (udec64!(80), udec64!(3.0))]
+    );
+
+    // Depth limits the number of buckets, not levels.
+    let depth = book.depth_bucketed(udec64!(10), 1);
+    assert_eq!(depth.asks, vec![(udec64!(110), udec64!(6.0))]);
+
+    // Zero bucket size keeps the levels.
+    assert_eq!(book.depth_bucketed(UD64::ZERO, 2).bids, book.top_bids(2));
+    assert_eq!(book.depth_bucketed(UD64::ZERO, 0), BucketedDepth::default());
+}
+
 // ============================================================================
 // L3BOOK TESTS - L3 API
 // ============================================================================
@@ -280,6 +372,121 @@ fn l3_book_level_accessors() {
     assert!(book.bid_level(udec64!(91)).is_none());
 }
 
+#[test]
+fn l3_book_queue_position() {
+    // Size ahead/behind within the level, other levels are not counted.
+    let mut book = OrderBook::new();
+    book.add_order(&ask!(100, 1.0, 1, 1, 1)).unwrap();
+    book.add_order(&ask!(100, 2.0, 2, 2, 2)).unwrap();
+    book.add_order(&ask!(100, 3.0, 3, 3, 3)).unwrap();
+    book.add_order(&ask!(99, 4.0, 4, 4, 4)).unwrap();
+
+    assert_eq!(
+        book.queue_position(oid(1)),
+        Some((udec64!(0), udec64!(5.0)))
+    );
+    assert_eq!(
+        book.queue_position(oid(2)),
+        Some((udec64!(1.0), udec64!(3.0)))
+    );
+    assert_eq!(
+        book.queue_position(oid(3)),
+        Some((udec64!(3.0), udec64!(0)))
+    );
+    assert_eq!(book.queue_position(oid(5)), None);
+    assert_eq!(
+        book.size_ahead_of_level(types::OrderSide::Ask, udec64!(100)),
+        udec64!(4.0)
+    );
+}
+
+#[test]
+fn l3_book_queue_position_looped_queue() {
+    // Corrupted queue linking back to the order does not hang the walk.
+    let mut book = OrderBook::new();
+    book.add_order(&ask!(100, 1.0, 1, 1, 1)).unwrap();
+    book.add_order(&ask!(100, 2.0, 2, 2, 2)).unwrap();
+    book.orders.get_mut(&oid(1)).unwrap().set_prev(Some(oid(2)));
+
+    assert_eq!(book.queue_position(oid(2)), None);
+    assert!(
+        book.simulate_modification(oid(2), udec64!(100), udec64!(1.0))
+            .is_none()
+    );
+}
+
+#[test]
+fn l3_book_queue_position_after_move_to_back() {
+    // Size increase loses priority, so the order ends up behind the rest.
+    let mut book = OrderBook::new();
+    book.add_order(&bid!(100, 1.0, 1, 1, 1)).unwrap();
+    book.add_order(&bid!(100, 2.0, 2, 2, 2)).unwrap();
+    book.add_order(&bid!(101, 5.0, 3, 3, 3)).unwrap();
+
+    let prev = *book.get_order_data(oid(1)).unwrap();
+    book.move_to_back(&prev.with_size(udec64!(1.5)), &prev)
+        .unwrap();
+
+    assert_eq!(
+        book.queue_position(oid(1)),
+        Some((udec64!(2.0), udec64!(0)))
+    );
+    assert_eq!(
+        book.size_ahead_of_level(types::OrderSide::Bid, udec64!(100)),
+        udec64!(5.0)
+    );
+}
+
+#[test]
+fn l3_book_simulate_modification() {
+    let mut book = OrderBook::new();
+    book.add_order(&bid!(100, 1.0, 1, 1, 1)).unwrap();
+    book.add_order(&bid!(100, 2.0, 2, 2, 2)).unwrap();
+    book.add_order(&bid!(100, 3.0, 3, 3, 3)).unwrap();
+    book.add_order(&bid!(99, 4.0, 4, 4, 4)).unwrap();
+    let simulate = |order_id, price, size| {
+        book.simulate_modification(oid(order_id), price, size)
+            .unwrap()
+    };
+
+    // Size decrease keeps the place
+    let impact = simulate(2, udec64!(100), udec64!(1.0));
+    assert_eq!(impact.priority, QueuePriority::Kept);
+    assert_eq!(impact.orders_ahead, 1);
+    assert_eq!(impact.size_ahead, udec64!(1.0));
+    assert_eq!(impact.current_size_ahead, udec64!(1.0));
+
+    // Size increase moves behind the rest of the level
+    let impact = simulate(1, udec64!(100), udec64!(1.5));
+    assert_eq!(impact.priority, QueuePriority::LostSizeIncrease);
+    assert_eq!(impact.orders_ahead, 2);
+    assert_eq!(impact.size_ahead, udec64!(5.0));
+    assert_eq!(impact.current_size_ahead, udec64!(0));
+
+    // Worse price joins the back of the existing level, leaving the current one
+    let impact = simulate(1, udec64!(99), udec64!(1.0));
+    assert_eq!(impact.priority, QueuePriority::NewLevel);
+    assert_eq!(impact.orders_ahead, 1);
+    assert_eq!(impact.size_ahead, udec64!(4.0));
+    assert_eq!(impact.size_ahead_of_level, udec64!(5.0));
+
+    // Better price opens a new level
+    let impact = simulate(3, udec64!(101), udec64!(3.0));
+    assert_eq!(impact.priority, QueuePriority::NewLevel);
+    assert_eq!(impact.orders_ahead, 0);
+    assert_eq!(impact.size_ahead, udec64!(0));
+    assert_eq!(impact.size_ahead_of_level, udec64!(0));
+
+    assert!(
+        book.simulate_modification(oid(5), udec64!(100), udec64!(1))
+            .is_none()
+    );
+    assert!(
+        book.simulate_modification(oid(1), udec64!(100), udec64!(0))
+            .is_none()
+    );
+}
+
 // ============================================================================
 // L3BOOK TESTS - MUTATIONS
 // ============================================================================
@@ -409,6 +616,35 @@ fn l3_book_order_id_reuse() {
 
     assert_order!(book, 42 => { price: 110, size: 2.0, account_id: 2 });
     assert_eq!(book.total_orders(), 1);
+    assert_eq!(book.order_generation(oid(42)), 1);
+    assert_eq!(book.order_generation(oid(7)), 0);
+}
+
+#[test]
+fn l3_book_order_slab() {
+    // Orders are iterated in ascending ID regardless of insertion order.
+    let mut book = OrderBook::new();
+    book.add_order(&ask!(100, 1.0, 1, 300, 1)).unwrap();
+    book.add_order(&bid!(90, 1.0, 1, 2, 1)).unwrap();
+    book.add_order(&ask!(101, 1.0, 1, u16::MAX, 1)).unwrap();
+    book.add_order(&bid!(91, 1.0, 1, 1, 1)).unwrap();
+
+    let orders = book.all_orders();
+    assert_eq!(orders.len(), 4);
+    assert_eq!(
+        orders.keys().collect::<Vec<_>>(),
+        vec![oid(1), oid(2), oid(300), oid(u16::MAX)]
+    );
+    assert!(!orders.contains_key(&oid(3)));
+    assert_eq!(orders[&oid(300)].price(), udec64!(100));
+
+    book.remove_order_by_id(oid(u16::MAX)).unwrap();
+    book.remove_order_by_id(oid(1)).unwrap();
+    assert_eq!(book.all_orders().len(), 2);
+    assert_eq!(book.order_generation(oid(u16::MAX)), 1);
+    assert!(book.get_order(oid(u16::MAX)).is_none());
+    assert!(book.remove_order_by_id(oid(1)).is_err());
+    assert_eq!(book.order_generation(oid(1)), 1);
 }
 
 #[test]
@@ -958,6 +1194,113 @@ fn move_nonexistent_order() {
     ));
 }
 
+#[test]
+fn book_view_matches_owned_accessors() {
+    let mut book = OrderBook::new();
+    book.add_order(&ask!(101, 1.0, 1, 1, 1)).unwrap();
+    book.add_order(&ask!(100, 2.0, 2, 2, 2)).unwrap();
+    book.add_order(&ask!(100, 0.5, 3, 3, 3)).unwrap();
+    book.add_order(&bid!(98, 1.5, 4, 4, 4)).unwrap();
+    book.add_order(&bid!(99, 3.0, 5, 5, 5)).unwrap();
+
+    let view = book.view();
+    let levels = |side| {
+        view.levels(side)
+            .map(|l| (l.price(), l.size()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(levels(types::OrderSide::Ask), book.top_asks(10));
+    assert_eq!(levels(types::OrderSide::Bid), book.top_bids(10));
+    assert_eq!(view.best_bid().unwrap().price(), udec64!(99));
+    assert_eq!(view.spread(), Some(udec64!(1)));
+
+    let level = view.level(types::OrderSide::Ask, udec64!(100)).unwrap();
+    assert_eq!(level.num_orders(), 2);
+    assert_eq!(
+        level.orders().map(|o| o.order_id()).collect::<Vec<_>>(),
+        vec![oid(2), oid(3)]
+    );
+    assert!(view.level(types::OrderSide::Bid, udec64!(100)).is_none());
+}
+
+#[test]
+fn integrity_check_and_repair() {
+    let mut book = OrderBook::new();
+    book.add_order(&ask!(100, 1.0, 1, 1, 1)).unwrap();
+    book.add_order(&ask!(100, 2.0, 2, 2, 2)).unwrap();
+    book.add_order(&ask!(100, 0.5, 3, 3, 3)).unwrap();
+    book.add_order(&bid!(99, 3.0, 4, 4, 4)).unwrap();
+    assert!(book.check_integrity().is_ok());
+    assert!(book.repair().is_ok());
+
+    // Cut the list after the second order and skew the bid level size
+    book.orders.get_mut(&oid(2)).unwrap().set_next(None);
+    book.bids
+        .get_mut(&Reverse(udec64!(99)))
+        .unwrap()
+        .add_size(udec64!(1.0));
+    let price = udec64!(100);
+    let side = types::OrderSide::Ask;
+    let issues = book.check_integrity().issues;
+    assert!(issues.contains(&IntegrityIssue::TailMismatch {
+        side,
+        price,
+        tail: ooid(3),
+        last: ooid(2),
+    }));
+    assert!(issues.contains(&IntegrityIssue::CountMismatch {
+        side,
+        price,
+        cached: 3,
+        actual: 2,
+    }));
+    assert!(issues.contains(&IntegrityIssue::SizeMismatch {
+        side,
+        price,
+        cached: udec64!(3.5),
+        actual: udec64!(3.0),
+    }));
+    assert!(issues.contains(&IntegrityIssue::SizeMismatch {
+        side: types::OrderSide::Bid,
+        price: udec64!(99),
+        cached: udec64!(4.0),
+        actual: udec64!(3.0),
+    }));
+    assert!(issues.contains(&IntegrityIssue::UnlinkedOrder { order_id: oid(3) }));
+
+    // Repair reports the same issues and restores the queue
+    assert_eq!(book.repair().issues, issues);
+    assert!(book.check_integrity().is_ok());
+    assert_fifo!(book, ask @ 100 => [1, 2, 3]);
+    assert_level!(book, ask @ 100 => (3.5, 3));
+    assert_level!(book, bid @ 99 => (3.0, 1));
+
+    // Cycle and foreign order: unreachable orders are queued by placement block
+    book.add_order(&ask!(101, 1.0, 5, 5, 5)).unwrap();
+    book.orders.get_mut(&oid(3)).unwrap().set_next(ooid(1));
+    let issues = book.check_integrity().issues;
+    assert!(issues.contains(&IntegrityIssue::BrokenLink {
+        side,
+        price,
+        order_id: oid(1),
+    }));
+    book.orders.get_mut(&oid(3)).unwrap().set_next(None);
+    book.orders.get_mut(&oid(2)).unwrap().set_next(ooid(5));
+    assert!(
+        book.check_integrity()
+            .issues
+            .contains(&IntegrityIssue::ForeignOrder {
+                side,
+                price,
+                order_id: oid(5),
+            })
+    );
+    assert!(!book.repair().is_ok());
+    assert!(book.check_integrity().is_ok());
+    assert_fifo!(book, ask @ 100 => [1, 2, 3]);
+    assert_fifo!(book, ask @ 101 => [5]);
+}
+
 // ============================================================================
 // STATE INCONSISTENCY TESTS (LevelNotFound)
 // ============================================================================
@@ -0,0 +1,116 @@
+//! Borrowed views of the order book for allocation-free queries.
+
+use std::cmp::Reverse;
+
+use fastnum::UD64;
+use itertools::Either;
+
+use super::{BookLevel, LevelOrders, OrderBook, OrderSlab};
+use crate::types;
+
+/// Borrowed view of the [`OrderBook`], see [`OrderBook::view`].
+///
+/// All queries walk the book in place: neither orders are copied,
+/// nor intermediate collections are allocated.
+#[derive(Clone, Copy, derive_more::Debug)]
+#[debug("BookView({} orders)", book.total_orders())]
+pub struct BookView<'a> {
+    book: &'a OrderBook,
+}
+
+impl<'a> BookView<'a> {
+    pub(crate) fn new(book: &'a OrderBook) -> Self {
+        Self { book }
+    }
+
+    /// Best ask level.
+    pub fn best_ask(&self) -> Option<LevelView<'a>> {
+        self.asks().next()
+    }
+
+    /// Best bid level.
+    pub fn best_bid(&self) -> Option<LevelView<'a>> {
+        self.bids().next()
+    }
+
+    /// Ask levels sorted away from the spread.
+    pub fn asks(&self) -> impl DoubleEndedIterator<Item = LevelView<'a>> + 'a {
+        let orders = &self.book.orders;
+        self.book
+            .asks
+            .iter()
+            .map(move |(price, level)| LevelView::new(*price, level, orders))
+    }
+
+    /// Bid levels sorted away from the spread.
+    pub fn bids(&self) -> impl DoubleEndedIterator<Item = LevelView<'a>> + 'a {
+        let orders = &self.book.orders;
+        self.book
+            .bids
+            .iter()
+            .map(move |(price, level)| LevelView::new(price.0, level, orders))
+    }
+
+    /// Levels of the given side sorted away from the spread.
+    pub fn levels(&self, side: types::OrderSide) -> impl Iterator<Item = LevelView<'a>> + 'a {
+        match side {
+            types::OrderSide::Ask => Either::Left(self.asks()),
+            types::OrderSide::Bid => Either::Right(self.bids()),
+        }
+    }
+
+    /// Level of the given side at the price.
+    pub fn level(&self, side: types::OrderSide, price: UD64) -> Option<LevelView<'a>> {
+        let level = match side {
+            types::OrderSide::Ask => self.book.asks.get(&price),
+            types::OrderSide::Bid => self.book.bids.get(&Reverse(price)),
+        }?;
+        Some(LevelView::new(price, level, &self.book.orders))
+    }
+
+    /// Difference between the best ask and bid prices, if both sides are present.
+    pub fn spread(&self) -> Option<UD64> {
+        let ask = self.best_ask()?.price();
+        let bid = self.best_bid()?.price();
+        Some(if ask > bid { ask - bid } else { UD64::ZERO })
+    }
+}
+
+/// Borrowed view of the price level along with its orders.
+#[derive(Clone, Copy, derive_more::Debug)]
+#[debug("{} @ {price} ({} orders)", level.size(), level.num_orders())]
+pub struct LevelView<'a> {
+    price: UD64,
+    level: &'a BookLevel,
+    orders: &'a OrderSlab,
+}
+
+impl<'a> LevelView<'a> {
+    fn new(price: UD64, level: &'a BookLevel, orders: &'a OrderSlab) -> Self {
+        Self {
+            price,
+            level,
+            orders,
+        }
+    }
+
+    /// Price of the level.
+    pub fn price(&self) -> UD64 {
+        self.price
+    }
+
+    /// Total resting size of the level.
+    pub fn size(&self) -> UD64 {
+        self.level.size()
+    }
+
+    /// Number of orders at the level.
+    pub fn num_orders(&self) -> u32 {
+        self.level.num_orders()
+    }
+
+    /// Orders of the level in time priority.
+    pub fn orders(&self) -> LevelOrders<'a> {
+        LevelOrders::new(self.orders, self.level.head())
+    }
+}
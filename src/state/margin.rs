@@ -0,0 +1,562 @@
+//! Margin requirements and position effects of hypothetical orders.
+
+use fastnum::{UD64, UD128};
+
+use super::{Account, Perpetual, Position, PositionType};
+use crate::types::{self, RequestType};
+
+/// Effect of a prospective order on the account margin and position,
+/// assuming the order gets completely filled at its limit price.
+///
+/// Computed from the tracked state, so it is an estimate which does not
+/// account for fees, funding and the actual fill prices.
+#[derive(Clone, Copy, derive_more::Debug)]
+pub struct MarginImpact {
+    /// Collateral locked by the order for the part increasing the position exposure.
+    #[debug("{locked_balance_change}")]
+    pub locked_balance_change: UD128,
+    /// Collateral available for new orders before the order placement.
+    #[debug("{available_balance}")]
+    pub available_balance: UD128,
+    /// Notional value of the position at entry price before the fill.
+    #[debug("{notional_before}")]
+    pub notional_before: UD128,
+    /// Notional value of the position at entry price after the fill.
+    #[debug("{notional_after}")]
+    pub notional_after: UD128,
+    /// Type of the position after the fill, `None` if the position gets closed.
+    pub position_type_after: Option<PositionType>,
+    /// Size of the position after the fill.
+    #[debug("{size_after}")]
+    pub size_after: UD64,
+    /// Position leverage before the fill.
+    #[debug("{leverage_before}")]
+    pub leverage_before: UD64,
+    /// Position leverage after the fill.
+    #[debug("{leverage_after}")]
+    pub leverage_after: UD64,
+    /// Liquidation price of the position after the fill.
+    pub liquidation_price_after: Option<UD64>,
+    /// Distance between the mark price and the liquidation price after the fill,
+    /// as a fraction of the mark price.
+    pub liquidation_distance_after: Option<UD64>,
+}
+
+impl MarginImpact {
+    /// Indicates the order fits the available balance of the account.
+    pub fn fits_available_balance(&self) -> bool {
+        self.locked_balance_change <= self.available_balance
+    }
+}
+
+/// Position parameters before/after the fill.
+#[derive(Clone, Copy)]
+struct PositionParams {
+    r#type: Option<PositionType>,
+    entry_price: UD64,
+    size: UD64,
+    deposit: UD128,
+}
+
+impl PositionParams {
+    fn closed() -> Self {
+        Self {
+            r#type: None,
+            entry_price: UD64::ZERO,
+            size: UD64::ZERO,
+            deposit: UD128::ZERO,
+        }
+    }
+
+    fn notional(&self) -> UD128 {
+        notional(self.entry_price, self.size)
+    }
+
+    fn leverage(&self) -> UD64 {
+        if self.deposit > UD128::ZERO {
+            (self.notional() / self.deposit).resize()
+        } else {
+            UD64::ZERO
+        }
+    }
+}
+
+/// How a complete fill of the order changes the existing position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PositionChange {
+    /// New position gets opened.
+    Open,
+    /// Position of the same type gets increased.
+    Increase,
+    /// Opposing position gets partially reduced.
+    Decrease,
+    /// Opposing position gets closed in full.
+    Close,
+    /// Opposing position gets closed, and the rest of the order opens
+    /// a position of the other type.
+    Invert,
+    /// Position is not affected, e.g. by a close order without a position to close,
+    /// or if neither the order nor the perpetual contract specifies the leverage.
+    Unchanged,
+}
+
+/// Position resulting from a prospective order, assuming the order gets completely
+/// filled at its limit price.
+///
+/// Follows the same netting rules as the exchange, but does not account for fees,
+/// funding and the actual fill prices.
+#[derive(Clone, Copy, derive_more::Debug)]
+pub struct OrderEffect {
+    /// Change of the position.
+    pub change: PositionChange,
+    /// Type of the position before the fill, `None` if there is no position.
+    pub position_type_before: Option<PositionType>,
+    /// Entry price of the position before the fill.
+    #[debug("{entry_price_before}")]
+    pub entry_price_before: UD64,
+    /// Size of the position before the fill.
+    #[debug("{size_before}")]
+    pub size_before: UD64,
+    /// Deposit of the position before the fill.
+    #[debug("{deposit_before}")]
+    pub deposit_before: UD128,
+    /// Type of the position after the fill, `None` if the position gets closed.
+    pub position_type_after: Option<PositionType>,
+    /// Entry price of the position after the fill.
+    #[debug("{entry_price_after}")]
+    pub entry_price_after: UD64,
+    /// Size of the position after the fill.
+    #[debug("{size_after}")]
+    pub size_after: UD64,
+    /// Deposit of the position after the fill.
+    #[debug("{deposit_after}")]
+    pub deposit_after: UD128,
+}
+
+/// Position netting of the order at full fill.
+struct Netting {
+    change: PositionChange,
+    before: PositionParams,
+    after: PositionParams,
+    /// Collateral locked for the part increasing the position exposure.
+    locked: UD128,
+}
+
+fn netting(perp: &Perpetual, account: &Account, request: &types::OrderRequest) -> Option<Netting> {
+    let (order_position_type, reduce_only) = match request.r#type() {
+        RequestType::OpenLong => (PositionType::Long, false),
+        RequestType::OpenShort => (PositionType::Short, false),
+        RequestType::CloseLong => (PositionType::Short, true),
+        RequestType::CloseShort => (PositionType::Long, true),
+        _ => return None,
+    };
+    let (price, size) = (request.price(), request.size());
+
+    let position = account.positions().get(&perp.id());
+    let leverage = if request.leverage() > UD64::ZERO {
+        request.leverage()
+    } else {
+        // Tier of the position size the order can end up with
+        let exposure = match position {
+            Some(p) if p.r#type() != order_position_type => {
+                if size > p.size() {
+                    size - p.size()
+                } else {
+                    UD64::ZERO
+                }
+            }
+            Some(p) => p.size() + size,
+            None => size,
+        };
+        perp.initial_margin_for_size(exposure)
+    };
+    let before = position
+        .map(|p| PositionParams {
+            r#type: Some(p.r#type()),
+            entry_price: p.entry_price(),
+            size: p.size(),
+            deposit: p.deposit(),
+        })
+        .unwrap_or_else(PositionParams::closed);
+
+    let (change, after, locked) = if before.r#type.is_none_or(|t| t == order_position_type) {
+        if reduce_only || leverage == UD64::ZERO {
+            (PositionChange::Unchanged, before, UD128::ZERO)
+        } else {
+            // Opening or increasing the position
+            let margin = notional(price, size) / leverage.resize();
+            let size_after = before.size + size;
+            let after = PositionParams {
+                r#type: Some(order_position_type),
+                entry_price: ((before.notional() + notional(price, size)) / size_after.resize())
+                    .resize(),
+                size: size_after,
+                deposit: before.deposit + margin,
+            };
+            let change = if before.r#type.is_some() {
+                PositionChange::Increase
+            } else {
+                PositionChange::Open
+            };
+            (change, after, margin)
+        }
+    } else if size < before.size {
+        // Decreasing the position, deposit gets released proportionally
+        let size_after = before.size - size;
+        let after = PositionParams {
+            deposit: before.deposit * size_after.resize() / before.size.resize(),
+            size: size_after,
+            ..before
+        };
+        (PositionChange::Decrease, after, UD128::ZERO)
+    } else if size == before.size || reduce_only || leverage == UD64::ZERO {
+        (PositionChange::Close, PositionParams::closed(), UD128::ZERO)
+    } else {
+        // Inverting the position
+        let size_after = size - before.size;
+        let margin = notional(price, size_after) / leverage.resize();
+        let after = PositionParams {
+            r#type: Some(order_position_type),
+            entry_price: price,
+            size: size_after,
+            deposit: margin,
+        };
+        (PositionChange::Invert, after, margin)
+    };
+    Some(Netting {
+        change,
+        before,
+        after,
+        locked,
+    })
+}
+
+pub(crate) fn order_effect(
+    perp: &Perpetual,
+    account: &Account,
+    request: &types::OrderRequest,
+) -> Option<OrderEffect> {
+    let Netting {
+        change,
+        before,
+        after,
+        ..
+    } = netting(perp, account, request)?;
+    Some(OrderEffect {
+        change,
+        position_type_before: before.r#type,
+        entry_price_before: before.entry_price,
+        size_before: before.size,
+        deposit_before: before.deposit,
+        position_type_after: after.r#type,
+        entry_price_after: after.entry_price,
+        size_after: after.size,
+        deposit_after: after.deposit,
+    })
+}
+
+pub(crate) fn margin_impact(
+    perp: &Perpetual,
+    account: &Account,
+    request: &types::OrderRequest,
+) -> Option<MarginImpact> {
+    let Netting {
+        before,
+        after,
+        locked,
+        ..
+    } = netting(perp, account, request)?;
+    let position = account.positions().get(&perp.id());
+
+    let liquidation_price_after = after
+        .r#type
+        .filter(|_| after.size > UD64::ZERO && perp.maintenance_margin() > UD64::ZERO)
+        .map(|r#type| {
+            let mut hypothetical = Position::opened(
+                account.instant(),
+                perp.id(),
+                account.id(),
+                r#type,
+                after.entry_price,
+                after.size,
+                after.deposit,
+                perp.maintenance_margin(),
+            );
+            if let Some(p) = position.filter(|p| p.r#type() == r#type) {
+                hypothetical.update_premium_pnl(p.instant(), p.premium_pnl());
+            }
+            hypothetical.liquidation_price()
+        });
+    let mark_price = perp.mark_price();
+    let liquidation_distance_after = liquidation_price_after
+        .filter(|_| mark_price > UD64::ZERO)
+        .map(|liq| {
+            let distance = if liq > mark_price {
+                liq - mark_price
+            } else {
+                mark_price - liq
+            };
+            distance / mark_price
+        });
+
+    let available_balance = if account.balance() > account.locked_balance() {
+        account.balance() - account.locked_balance()
+    } else {
+        UD128::ZERO
+    };
+
+    Some(MarginImpact {
+        locked_balance_change: locked,
+        available_balance,
+        notional_before: before.notional(),
+        notional_after: after.notional(),
+        position_type_after: after.r#type,
+        size_after: after.size,
+        leverage_before: before.leverage(),
+        leverage_after: after.leverage(),
+        liquidation_price_after,
+        liquidation_distance_after,
+    })
+}
+
+fn notional(price: UD64, size: UD64) -> UD128 {
+    price.resize() * size.resize()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::{Address, U256};
+    use fastnum::{udec64, udec128};
+
+    use super::*;
+    use crate::types::StateInstant;
+
+    fn perp() -> Perpetual {
+        let mut perp = Perpetual::for_testing(1);
+        perp.update_initial_margin(StateInstant::default(), udec64!(10));
+        perp.update_maintenance_margin(StateInstant::default(), udec64!(20));
+        perp.update_mark_price(StateInstant::default(), udec64!(100));
+        perp
+    }
+
+    fn account(position: Option<(PositionType, UD64, UD64, UD128)>) -> Account {
+        let mut account = Account::from_event(StateInstant::default(), 7, Address::ZERO);
+        account.update_balance(StateInstant::default(), udec128!(1000));
+        account.update_locked_balance(StateInstant::default(), udec128!(100));
+        if let Some((r#type, entry_price, size, deposit)) = position {
+            account.positions_mut().insert(
+                1,
+                Position::opened(
+                    StateInstant::default(),
+                    1,
+                    7,
+                    r#type,
+                    entry_price,
+                    size,
+                    deposit,
+                    udec64!(20),
+                ),
+            );
+        }
+        account
+    }
+
+    fn request(
+        r#type: RequestType,
+        price: UD64,
+        size: UD64,
+        leverage: UD64,
+    ) -> types::OrderRequest {
+        types::OrderRequest::new(
+            1, 1, r#type, None, price, size, None, false, false, false, None, leverage, None, None,
+        )
+    }
+
+    #[test]
+    fn opening_position() {
+        let impact = margin_impact(
+            &perp(),
+            &account(None),
+            &request(RequestType::OpenLong, udec64!(100), udec64!(5), udec64!(5)),
+        )
+        .unwrap();
+
+        assert_eq!(impact.locked_balance_change, udec128!(100));
+        assert_eq!(impact.available_balance, udec128!(900));
+        assert!(impact.fits_available_balance());
+        assert_eq!(impact.notional_before, UD128::ZERO);
+        assert_eq!(impact.notional_after, udec128!(500));
+        assert_eq!(impact.position_type_after, Some(PositionType::Long));
+        assert_eq!(impact.leverage_before, UD64::ZERO);
+        assert_eq!(impact.leverage_after, udec64!(5));
+        // 100 + (25 - 100) / 5
+        assert_eq!(impact.liquidation_price_after, Some(udec64!(85)));
+        assert_eq!(impact.liquidation_distance_after, Some(udec64!(0.15)));
+    }
+
+    #[test]
+    fn increasing_position() {
+        let impact = margin_impact(
+            &perp(),
+            &account(Some((
+                PositionType::Long,
+                udec64!(80),
+                udec64!(5),
+                udec128!(100),
+            ))),
+            &request(RequestType::OpenLong, udec64!(120), udec64!(5), udec64!(6)),
+        )
+        .unwrap();
+
+        assert_eq!(impact.locked_balance_change, udec128!(100));
+        assert_eq!(impact.notional_before, udec128!(400));
+        assert_eq!(impact.notional_after, udec128!(1000));
+        assert_eq!(impact.size_after, udec64!(10));
+        assert_eq!(impact.leverage_before, udec64!(4));
+        assert_eq!(impact.leverage_after, udec64!(5));
+    }
+
+    #[test]
+    fn decreasing_and_inverting_position() {
+        let perp = perp();
+        let account = account(Some((
+            PositionType::Short,
+            udec64!(100),
+            udec64!(4),
+            udec128!(80),
+        )));
+
+        let decrease = margin_impact(
+            &perp,
+            &account,
+            &request(RequestType::OpenLong, udec64!(100), udec64!(1), udec64!(5)),
+        )
+        .unwrap();
+        assert_eq!(decrease.locked_balance_change, UD128::ZERO);
+        assert_eq!(decrease.position_type_after, Some(PositionType::Short));
+        assert_eq!(decrease.size_after, udec64!(3));
+        assert_eq!(decrease.leverage_after, udec64!(5));
+
+        let close = margin_impact(
+            &perp,
+            &account,
+            &request(
+                RequestType::CloseShort,
+                udec64!(100),
+                udec64!(10),
+                UD64::ZERO,
+            ),
+        )
+        .unwrap();
+        assert_eq!(close.position_type_after, None);
+        assert_eq!(close.notional_after, UD128::ZERO);
+        assert_eq!(close.liquidation_price_after, None);
+
+        let invert = margin_impact(
+            &perp,
+            &account,
+            &request(RequestType::OpenLong, udec64!(100), udec64!(6), udec64!(5)),
+        )
+        .unwrap();
+        assert_eq!(invert.locked_balance_change, udec128!(40));
+        assert_eq!(invert.position_type_after, Some(PositionType::Long));
+        assert_eq!(invert.size_after, udec64!(2));
+        assert_eq!(invert.notional_after, udec128!(200));
+    }
+
+    #[test]
+    fn default_leverage_by_size_tier() {
+        let mut perp = perp();
+        perp.set_margin_tiers(&[
+            (U256::from(0), U256::from(1000)),
+            (U256::from(10), U256::from(500)),
+        ]);
+        let account = account(Some((
+            PositionType::Long,
+            udec64!(100),
+            udec64!(8),
+            udec128!(80),
+        )));
+        let open = |size| {
+            margin_impact(
+                &perp,
+                &account,
+                &request(RequestType::OpenLong, udec64!(100), size, UD64::ZERO),
+            )
+            .unwrap()
+        };
+        assert_eq!(open(udec64!(1)).locked_balance_change, udec128!(10));
+        // Position of 10 falls into the second tier
+        assert_eq!(open(udec64!(2)).locked_balance_change, udec128!(40));
+    }
+
+    #[test]
+    fn order_effects() {
+        let perp = perp();
+        let account = account(Some((
+            PositionType::Short,
+            udec64!(100),
+            udec64!(4),
+            udec128!(80),
+        )));
+        let effect = |r#type, price, size| {
+            order_effect(&perp, &account, &request(r#type, price, size, UD64::ZERO)).unwrap()
+        };
+
+        let increase = effect(RequestType::OpenShort, udec64!(110), udec64!(1));
+        assert_eq!(increase.change, PositionChange::Increase);
+        assert_eq!(increase.position_type_before, Some(PositionType::Short));
+        assert_eq!(increase.entry_price_after, udec64!(102));
+        assert_eq!(increase.size_after, udec64!(5));
+        assert_eq!(increase.deposit_after, udec128!(91));
+
+        let decrease = effect(RequestType::CloseShort, udec64!(90), udec64!(1));
+        assert_eq!(decrease.change, PositionChange::Decrease);
+        assert_eq!(decrease.entry_price_after, udec64!(100));
+        assert_eq!(decrease.size_after, udec64!(3));
+        assert_eq!(decrease.deposit_after, udec128!(60));
+
+        let close = effect(RequestType::CloseShort, udec64!(90), udec64!(6));
+        assert_eq!(close.change, PositionChange::Close);
+        assert_eq!(close.position_type_after, None);
+        assert_eq!(close.deposit_after, UD128::ZERO);
+
+        let invert = effect(RequestType::OpenLong, udec64!(90), udec64!(6));
+        assert_eq!(invert.change, PositionChange::Invert);
+        assert_eq!(invert.position_type_after, Some(PositionType::Long));
+        assert_eq!(invert.entry_price_after, udec64!(90));
+        assert_eq!(invert.size_after, udec64!(2));
+        assert_eq!(invert.deposit_after, udec128!(18));
+
+        let open = order_effect(
+            &perp,
+            &Account::from_event(StateInstant::default(), 8, Address::ZERO),
+            &request(RequestType::OpenLong, udec64!(100), udec64!(1), UD64::ZERO),
+        )
+        .unwrap();
+        assert_eq!(open.change, PositionChange::Open);
+        assert_eq!(open.size_before, UD64::ZERO);
+        assert_eq!(open.deposit_after, udec128!(10));
+
+        let unchanged = order_effect(
+            &perp,
+            &Account::from_event(StateInstant::default(), 8, Address::ZERO),
+            &request(RequestType::CloseLong, udec64!(100), udec64!(1), UD64::ZERO),
+        )
+        .unwrap();
+        assert_eq!(unchanged.change, PositionChange::Unchanged);
+        assert_eq!(unchanged.position_type_after, None);
+    }
+
+    #[test]
+    fn non_order_requests_have_no_impact() {
+        assert!(
+            margin_impact(
+                &perp(),
+                &account(None),
+                &request(RequestType::Cancel, UD64::ZERO, UD64::ZERO, UD64::ZERO),
+            )
+            .is_none()
+        );
+    }
+}
@@ -12,46 +12,136 @@
 //! access methods explicitly covers such cases.
 
 mod account;
+mod adl;
+mod backfill;
+mod block_time;
+mod checkpoint;
+mod crossing;
+mod diff;
 mod event;
 mod exchange;
+mod fee;
+mod funding;
+mod hash;
+mod insurance;
 mod l3_book;
+mod margin;
 mod order;
+mod param_history;
+mod partial;
 mod perpetual;
+mod pnl_history;
+mod portfolio;
 mod position;
+mod position_index;
+mod price_band;
+mod pruning;
+mod revision;
+mod roles;
+mod shard;
+mod shared;
 
+#[cfg(feature = "rpc")]
 use crate::{
     Chain,
     abi::dex::{self, Exchange::getExchangeInfoReturn},
-    error::DexError,
-    num, types,
-};
-use alloy::{
-    eips::BlockId,
-    primitives::{Address, U256},
-    providers::Provider,
 };
+use crate::{error::DexError, num, types};
+use alloy::primitives::{Address, U256};
+#[cfg(feature = "rpc")]
+use alloy::{eips::BlockId, providers::Provider};
+#[cfg(feature = "rpc")]
 use itertools::Itertools;
-use std::collections::{HashMap, hash_map};
+use std::collections::HashMap;
+#[cfg(feature = "rpc")]
+use std::collections::{HashSet, hash_map};
+#[cfg(feature = "rpc")]
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+#[cfg(feature = "rpc")]
+use tokio_util::sync::CancellationToken;
 
 // Public re-exports
 pub use account::*;
+pub use adl::{AdlRank, DeleverageFailure, DeleverageFailureKind};
+pub use backfill::AccountAddresses;
+pub use crossing::{BookCrossedEvent, CrossedBookCheck};
+pub use diff::{
+    AccountBalances, BalanceDiff, Change, OrderDiff, PerpetualDiff, PerpetualParams, PositionDiff,
+    StateDiff,
+};
 pub use event::*;
 pub use exchange::*;
+pub use fee::FeeSchedule;
+pub use funding::{FundingRecord, FundingSchedule, FundingWindow};
+pub use insurance::{Fund, FundChange, FundChangeReason};
 pub use l3_book::*;
+pub use margin::{MarginImpact, OrderEffect, PositionChange};
 pub use order::*;
+pub use param_history::{ParamChange, PerpetualParam};
+pub use partial::PartialSnapshot;
 pub use perpetual::*;
+pub use pnl_history::PnlPoint;
+pub use portfolio::{
+    AccountSummary, Exposure, MultiAccountView, PerpetualNetting, PortfolioSummary,
+};
 pub use position::*;
+pub use position_index::PositionAggregates;
+pub use price_band::{ToleranceBreach, ToleranceBreachKind};
+pub use pruning::{CompactionReport, PruningPolicy};
+pub use revision::IncompatibleContract;
+pub use roles::Role;
+pub use shared::{ExchangeWriter, SharedExchange};
 
+#[cfg(feature = "rpc")]
 /// Default number of orders to fetch via single call.
 /// Assuming Monad's 8100 gas per storage slot access and 30M gas limit of `eth_call`,
 /// plus some buffer.
 const DEFAULT_ORDERS_PER_BATCH: usize = 3000;
 
+#[cfg(feature = "rpc")]
 /// Default number of positions to fetch via single call.
 /// Assuming Monad's 8100 gas per storage slot access and 30M gas limit of `eth_call`,
 /// plus some buffer.
 const DEFAULT_POSITIONS_PER_BATCH: usize = 3000;
 
+#[cfg(feature = "rpc")]
+/// Default number of position sizes to sample the initial margin fraction at,
+/// see [`SnapshotBuilder::with_margin_tier_samples`].
+const DEFAULT_MARGIN_TIER_SAMPLES: usize = 8;
+
+/// Progress of the snapshot building reported by [`SnapshotBuilder::build`],
+/// see [`SnapshotBuilder::with_progress`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SnapshotProgress {
+    /// Fetching global exchange parameters and state.
+    ExchangeInfo,
+    /// Fetching parameters and state of the perpetual contracts.
+    Perpetuals { total: usize },
+    /// Fetching active orders of the perpetual contract.
+    Orders {
+        perpetual_id: types::PerpetualId,
+        fetched: usize,
+        total: usize,
+    },
+    /// Fetching requested accounts along with their positions.
+    Accounts { fetched: usize, total: usize },
+    /// Scanning positions of the perpetual contract, by account IDs.
+    Positions {
+        perpetual_id: types::PerpetualId,
+        fetched: usize,
+        total: usize,
+    },
+    /// Snapshot is complete.
+    Done,
+}
+
+#[cfg(feature = "rpc")]
+type ProgressCallback = Arc<dyn Fn(SnapshotProgress) + Send + Sync>;
+
+#[cfg(feature = "rpc")]
 /// Builds a consistent snapshot of the exchange state
 /// that can be then kept up-to-date by the data from [`crate::stream::raw`].
 pub struct SnapshotBuilder<P> {
@@ -60,12 +150,27 @@ pub struct SnapshotBuilder<P> {
     provider: P,
     block_id: BlockId,
     perpetuals: Vec<types::PerpetualId>,
-    accounts: Vec<Address>,
+    accounts: Vec<(Address, AccountTracking)>,
     all_positions: bool,
+    role_candidates: Vec<Address>,
     orders_per_batch: usize,
     positions_per_batch: usize,
+    margin_tier_samples: usize,
+    checkpoints: checkpoint::Checkpoints,
+    param_history_retention: usize,
+    funding_history_retention: usize,
+    pnl_history_retention: usize,
+    fund_history_retention: usize,
+    halt_on_upgrade: bool,
+    parallel_apply: bool,
+    crossed_book_check: CrossedBookCheck,
+    pruning: PruningPolicy,
+    progress: Option<ProgressCallback>,
+    cancellation: Option<CancellationToken>,
+    fetched: partial::Fetched,
 }
 
+#[cfg(feature = "rpc")]
 impl<P: Provider + Clone> SnapshotBuilder<P> {
     /// Creates a new [`SnapshotBuilder`] which fetches the full exchange state
     /// at the latest block.
@@ -78,8 +183,22 @@ impl<P: Provider + Clone> SnapshotBuilder<P> {
             perpetuals: chain.perpetuals.clone(),
             accounts: vec![],
             all_positions: false,
+            role_candidates: vec![],
             orders_per_batch: DEFAULT_ORDERS_PER_BATCH,
             positions_per_batch: DEFAULT_POSITIONS_PER_BATCH,
+            margin_tier_samples: DEFAULT_MARGIN_TIER_SAMPLES,
+            checkpoints: checkpoint::Checkpoints::default(),
+            param_history_retention: param_history::DEFAULT_PARAM_HISTORY_RETENTION,
+            funding_history_retention: funding::DEFAULT_FUNDING_HISTORY_RETENTION,
+            pnl_history_retention: 0,
+            fund_history_retention: insurance::DEFAULT_FUND_HISTORY_RETENTION,
+            halt_on_upgrade: false,
+            parallel_apply: false,
+            crossed_book_check: CrossedBookCheck::default(),
+            pruning: PruningPolicy::default(),
+            progress: None,
+            cancellation: None,
+            fetched: partial::Fetched::default(),
         }
     }
 
@@ -97,18 +216,47 @@ impl<P: Provider + Clone> SnapshotBuilder<P> {
         self
     }
 
-    /// Sets the list of addresses to fetch the state of exchange accounts for.
+    /// Sets the list of addresses to fetch the state of exchange accounts for,
+    /// with [`AccountTracking::Full`] tracking.
     /// Assumes accounts already exist, snapshot creation will fail otherwise.
     ///
     /// # Panics
     ///
     /// If [`Self::with_all_positions`] was called before.
     pub fn with_accounts(mut self, accounts: Vec<Address>) -> Self {
+        self.accounts.clear();
+        self.with_accounts_full(accounts)
+    }
+
+    /// Adds addresses of exchange accounts to track balances and positions of,
+    /// see [`AccountTracking::Full`].
+    /// Assumes accounts already exist, snapshot creation will fail otherwise.
+    ///
+    /// # Panics
+    ///
+    /// If [`Self::with_all_positions`] was called before.
+    pub fn with_accounts_full(self, accounts: Vec<Address>) -> Self {
+        self.add_accounts(accounts, AccountTracking::Full)
+    }
+
+    /// Adds addresses of exchange accounts to track only positions of, skipping
+    /// balance updates processing, see [`AccountTracking::PositionsOnly`].
+    /// Assumes accounts already exist, snapshot creation will fail otherwise.
+    ///
+    /// # Panics
+    ///
+    /// If [`Self::with_all_positions`] was called before.
+    pub fn with_accounts_positions_only(self, accounts: Vec<Address>) -> Self {
+        self.add_accounts(accounts, AccountTracking::PositionsOnly)
+    }
+
+    fn add_accounts(mut self, accounts: Vec<Address>, tracking: AccountTracking) -> Self {
         assert!(
             !self.all_positions,
             "simultaneous tracking of all positions and specific accounts is not supported"
         );
-        self.accounts = accounts;
+        self.accounts
+            .extend(accounts.into_iter().map(|addr| (addr, tracking)));
         self
     }
 
@@ -128,6 +276,12 @@ impl<P: Provider + Clone> SnapshotBuilder<P> {
         self
     }
 
+    /// Sets the addresses to check for the exchange roles, see [`Exchange::role_holders`].
+    pub fn with_role_candidates(mut self, addresses: Vec<Address>) -> Self {
+        self.role_candidates = addresses;
+        self
+    }
+
     /// Sets the number of orders to fetch in a single batch via multicall (default: 3000).
     /// Use if default does not fit node/provider gas and response size limits.
     pub fn with_orders_per_batch(mut self, orders_per_batch: usize) -> Self {
@@ -142,12 +296,144 @@ impl<P: Provider + Clone> SnapshotBuilder<P> {
         self
     }
 
+    /// Sets the number of position sizes, evenly spaced up to the maximal open interest,
+    /// to sample the initial margin fraction at to find the size tiers (default: 8),
+    /// see [`Perpetual::margin_tiers`]. Zero disables tiers.
+    pub fn with_margin_tier_samples(mut self, samples: usize) -> Self {
+        self.margin_tier_samples = samples;
+        self
+    }
+
+    /// Enables automatic checkpoints of the state at every block divisible by `every_blocks`,
+    /// keeping up to `capacity` most recent ones (default: no automatic checkpoints, 8 kept).
+    /// See [`Exchange::rollback_to`].
+    pub fn with_checkpoints(mut self, every_blocks: u64, capacity: usize) -> Self {
+        self.checkpoints = checkpoint::Checkpoints::new(every_blocks, capacity);
+        self
+    }
+
+    /// Sets the number of parameter changes kept per perpetual contract (default: 64),
+    /// see [`Perpetual::param_history`].
+    pub fn with_param_history_retention(mut self, retention: usize) -> Self {
+        self.param_history_retention = retention;
+        self
+    }
+
+    /// Sets the number of funding events kept per perpetual contract (default: 512),
+    /// see [`Perpetual::funding_history`].
+    pub fn with_funding_history_retention(mut self, retention: usize) -> Self {
+        self.funding_history_retention = retention;
+        self
+    }
+
+    /// Enables recording of position PnL, keeping up to the given number of points
+    /// per position (default: disabled), see [`Position::pnl_history`].
+    pub fn with_pnl_history_retention(mut self, retention: usize) -> Self {
+        self.pnl_history_retention = retention;
+        self
+    }
+
+    /// Sets the number of protocol and insurance fund balance changes to keep
+    /// (default: 256), see [`Exchange::fund_history`].
+    pub fn with_fund_history_retention(mut self, retention: usize) -> Self {
+        self.fund_history_retention = retention;
+        self
+    }
+
+    /// Halts event application after the upgrade of the exchange implementation until
+    /// it gets acknowledged (default: disabled), see [`Exchange::pending_upgrade`].
+    pub fn with_halt_on_upgrade(mut self) -> Self {
+        self.halt_on_upgrade = true;
+        self
+    }
+
+    /// Applies order flow of untracked accounts to different perpetual contracts
+    /// in parallel (default: disabled), reducing the block application latency for
+    /// exchanges with many actively traded contracts, see [`Exchange::apply_events`].
+    ///
+    /// Has no effect when tracking all accounts.
+    pub fn with_parallel_apply(mut self) -> Self {
+        self.parallel_apply = true;
+        self
+    }
+
+    /// Sets the action taken when a tracked order book turns locked or crossed
+    /// (default: [`CrossedBookCheck::Report`]), see [`StateEvents::BookCrossed`].
+    pub fn with_crossed_book_check(mut self, check: CrossedBookCheck) -> Self {
+        self.crossed_book_check = check;
+        self
+    }
+
+    /// Bounds the history and idle accounts kept over long uptimes on top of the
+    /// history retention (default: no pruning), see [`Exchange::compact`].
+    pub fn with_pruning(mut self, pruning: PruningPolicy) -> Self {
+        self.pruning = pruning;
+        self
+    }
+
+    /// Sets the callback to report building progress to.
+    /// Gets called from the building task, so should not block.
+    pub fn with_progress(
+        mut self,
+        progress: impl Fn(SnapshotProgress) + Send + Sync + 'static,
+    ) -> Self {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+
+    /// Sets the token to abort building with, in which case [`Self::build`]
+    /// returns [`DexError::Cancelled`] without waiting for the pending calls.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Resumes building from the state fetched by the failed attempt, at the same block,
+    /// see [`Self::build_resumable`].
+    ///
+    /// Batches fetched with the batch sizes different from the ones in effect
+    /// at the time of building get fetched again.
+    pub fn resume_from(mut self, partial: PartialSnapshot) -> Self {
+        let fetched = partial.into_fetched();
+        if let Some(instant) = fetched.instant {
+            self.block_id = BlockId::number(instant.block_number());
+        }
+        self.fetched = fetched;
+        self
+    }
+
     /// Build the snapshot
-    pub async fn build(mut self) -> Result<Exchange, DexError> {
+    pub async fn build(self) -> Result<Exchange, DexError> {
+        self.build_resumable()
+            .await
+            .map_err(|partial| partial.into_error())
+    }
+
+    /// Build the snapshot, returning the state fetched so far on failure (rate limit,
+    /// timeout, cancellation), so that the retry with [`Self::resume_from`]
+    /// fetches only the remainder.
+    pub async fn build_resumable(mut self) -> Result<Exchange, Box<PartialSnapshot>> {
+        let recorder = partial::Recorder::new(
+            std::mem::take(&mut self.fetched)
+                .for_batches(self.orders_per_batch, self.positions_per_batch),
+        );
+        let result = match self.cancellation.take() {
+            Some(token) => tokio::select! {
+                biased;
+                _ = token.cancelled() => Err(DexError::Cancelled),
+                result = self.build_snapshot(&recorder) => result,
+            },
+            None => self.build_snapshot(&recorder).await,
+        };
+        result.map_err(|error| Box::new(PartialSnapshot::new(error, recorder.into_inner())))
+    }
+
+    async fn build_snapshot(mut self, recorder: &partial::Recorder) -> Result<Exchange, DexError> {
         // Normalize block ID to fetch consistent state
-        let instant = self.normalize_block().await?;
+        let instant = self.normalize_block(recorder).await?;
 
         // Global exchange parameters and state
+        self.report(SnapshotProgress::ExchangeInfo);
         let (
             exchange_info,
             funding_interval,
@@ -156,15 +442,36 @@ impl<P: Provider + Clone> SnapshotBuilder<P> {
             recycle_fee,
             is_halted,
             num_of_accounts,
-        ) = self.exchange_info().await?;
+        ) = match recorder.with(|f| f.exchange_info.clone()) {
+            Some(exchange_info) => exchange_info,
+            None => {
+                let exchange_info = self.exchange_info().await?;
+                recorder.with(|f| f.exchange_info = Some(exchange_info.clone()));
+                exchange_info
+            }
+        };
         let collateral_converter = num::Converter::new(exchange_info.collateralDecimals.to());
+        let roles = match recorder.with(|f| f.roles.clone()) {
+            Some(roles) => roles,
+            None => {
+                let roles = self.roles().await?;
+                recorder.with(|f| f.roles = Some(roles.clone()));
+                roles
+            }
+        };
 
         // Perpetual contracts parameters, state and active orders
-        let perpetuals = self.perpetuals(instant).await?;
+        let mut perpetuals = self
+            .perpetuals(instant, collateral_converter, recorder)
+            .await?;
+        for perp in perpetuals.values_mut() {
+            perp.set_param_history_retention(self.param_history_retention);
+            perp.set_funding_history_retention(self.funding_history_retention);
+        }
 
         let accounts = if !self.accounts.is_empty() {
             // Accounts parameters, state and open positions if specific accounts requested
-            self.accounts(instant, &perpetuals, collateral_converter)
+            self.accounts(instant, &perpetuals, collateral_converter, recorder)
                 .await?
         } else if self.all_positions {
             // All positions with corresponding accounts without parameters and balance snapshot
@@ -173,13 +480,14 @@ impl<P: Provider + Clone> SnapshotBuilder<P> {
                 &perpetuals,
                 num_of_accounts.to(),
                 collateral_converter,
+                recorder,
             )
             .await?
         } else {
             HashMap::new()
         };
 
-        Ok(Exchange::new(
+        let mut exchange = Exchange::new(
             self.chain.clone(),
             instant,
             collateral_converter,
@@ -191,10 +499,38 @@ impl<P: Provider + Clone> SnapshotBuilder<P> {
             accounts,
             is_halted,
             self.all_positions,
-        ))
+        );
+        self.report(SnapshotProgress::Done);
+        exchange.set_roles(roles);
+        exchange.set_checkpoints(self.checkpoints);
+        exchange.set_pnl_history_retention(self.pnl_history_retention);
+        exchange.set_fund_history_retention(self.fund_history_retention);
+        exchange.set_protocol_balance(
+            collateral_converter.from_unsigned(exchange_info.protocolBalanceCNS),
+        );
+        exchange.set_halt_on_upgrade(self.halt_on_upgrade);
+        exchange.set_parallel_apply(self.parallel_apply);
+        exchange.set_crossed_book_check(self.crossed_book_check);
+        exchange.set_pruning(self.pruning);
+        Ok(exchange)
+    }
+
+    fn report(&self, progress: SnapshotProgress) {
+        if let Some(callback) = &self.progress {
+            callback(progress);
+        }
     }
 
-    async fn normalize_block(&mut self) -> Result<types::StateInstant, DexError> {
+    async fn normalize_block(
+        &mut self,
+        recorder: &partial::Recorder,
+    ) -> Result<types::StateInstant, DexError> {
+        // Resumed building keeps the block of the original attempt
+        if let Some(instant) = recorder.with(|f| f.instant) {
+            self.block_id = BlockId::number(instant.block_number());
+            return Ok(instant);
+        }
+
         // Transform provided block ID to fixed number block ID and use if for all calls
         // to retrieve consistent state
         let block_header = self
@@ -205,10 +541,9 @@ impl<P: Provider + Clone> SnapshotBuilder<P> {
             .map(|b| b.into_header())
             .ok_or(DexError::InvalidRequest("block not found".to_string()))?;
         self.block_id = BlockId::number(block_header.number);
-        Ok(types::StateInstant::new(
-            block_header.number,
-            block_header.timestamp,
-        ))
+        let instant = types::StateInstant::new(block_header.number, block_header.timestamp);
+        recorder.with(|f| f.instant = Some(instant));
+        Ok(instant)
     }
 
     async fn exchange_info(
@@ -243,11 +578,59 @@ impl<P: Provider + Clone> SnapshotBuilder<P> {
         .map_err(DexError::from)
     }
 
+    async fn roles(&self) -> Result<roles::Roles, DexError> {
+        let is_whitelisting_enabled = self
+            .instance
+            .whitelistingEnabled()
+            .block(self.block_id)
+            .call()
+            .await?;
+        let role_futs = self.role_candidates.iter().map(|addr| async {
+            let (admin_call, price_admin_call, position_admin_call) = (
+                self.instance.isAdministrator(*addr).block(self.block_id),
+                self.instance
+                    .isPriceAdministrator(*addr)
+                    .block(self.block_id),
+                self.instance
+                    .isPositionAdministrator(*addr)
+                    .block(self.block_id),
+            );
+            futures::try_join!(
+                admin_call.call().into_future(),
+                price_admin_call.call().into_future(),
+                position_admin_call.call().into_future(),
+            )
+            .map(|granted| (*addr, granted))
+        });
+
+        let mut roles = roles::Roles::new(is_whitelisting_enabled);
+        for (addr, (admin, price_admin, position_admin)) in
+            futures::future::try_join_all(role_futs).await?
+        {
+            roles.update(Role::Administrator, addr, admin);
+            roles.update(Role::PriceAdministrator, addr, price_admin);
+            roles.update(Role::PositionAdministrator, addr, position_admin);
+        }
+        Ok(roles)
+    }
+
     async fn perpetuals(
         &self,
         instant: types::StateInstant,
+        collateral_converter: num::Converter,
+        recorder: &partial::Recorder,
     ) -> Result<HashMap<types::PerpetualId, perpetual::Perpetual>, DexError> {
-        let perpetual_futs = self.perpetuals.iter().map(|perp_id| async {
+        self.report(SnapshotProgress::Perpetuals {
+            total: self.perpetuals.len(),
+        });
+        let remaining = recorder.with(|f| {
+            self.perpetuals
+                .iter()
+                .filter(|perp_id| !f.perpetuals.contains_key(perp_id))
+                .copied()
+                .collect::<Vec<_>>()
+        });
+        let perpetual_futs = remaining.iter().map(|perp_id| async {
             let pid = U256::from(*perp_id);
             let (perp_info_call, maker_fee_call, taker_fee_call, margins_call) = (
                 self.instance.getPerpetualInfo(pid).block(self.block_id),
@@ -258,81 +641,143 @@ impl<P: Provider + Clone> SnapshotBuilder<P> {
                     .block(self.block_id),
             );
 
-            futures::try_join!(
+            let (perp_info, maker_fee, taker_fee, margins) = futures::try_join!(
                 perp_info_call.call().into_future(),
                 maker_fee_call.call().into_future(),
                 taker_fee_call.call().into_future(),
                 margins_call.call().into_future(),
-            )
-            .map(|(perp_info, maker_fee, taker_fee, margins)| {
-                (*perp_id, perp_info, maker_fee, taker_fee, margins)
-            })
+            )?;
+            let margin_tiers = self
+                .margin_tiers(pid, margins.dynamicInitMarginFracHdths, margins.oiMaxLNS)
+                .await?;
+            let mut perp = Perpetual::new(
+                instant,
+                *perp_id,
+                &perp_info,
+                maker_fee,
+                taker_fee,
+                margins.perpInitMarginFracHdths,
+                margins.perpMaintMarginFracHdths,
+            );
+            perp.set_margin_tiers(&margin_tiers);
+            perp.set_insurance_balance(
+                instant,
+                collateral_converter.from_unsigned(perp_info.insuranceBalanceCNS),
+            );
+            recorder.with(|f| f.perpetuals.insert(*perp_id, perp));
+            Ok::<_, DexError>(())
         });
-
-        let mut perpetuals = futures::future::try_join_all(perpetual_futs)
-            .await?
-            .into_iter()
-            .map(|(perp_id, perp_info, maker_fee, taker_fee, margins)| {
-                let perp = Perpetual::new(
-                    instant,
-                    perp_id,
-                    &perp_info,
-                    maker_fee,
-                    taker_fee,
-                    margins.perpInitMarginFracHdths,
-                    margins.perpMaintMarginFracHdths,
-                );
-                (perp_id, perp)
-            })
-            .collect::<HashMap<_, _>>();
+        futures::future::try_join_all(perpetual_futs).await?;
 
         // Fetching orders one perp at a time to bound parallel requests
-        for perp in perpetuals.values_mut() {
-            self.perpetual_orders(perp).await?;
+        let mut perpetuals = HashMap::with_capacity(self.perpetuals.len());
+        for perp_id in &self.perpetuals {
+            let mut perp = recorder
+                .with(|f| f.perpetuals.get(perp_id).cloned())
+                .expect("perpetual fetched");
+            self.perpetual_orders(&mut perp, recorder).await?;
+            perpetuals.insert(*perp_id, perp);
         }
 
         Ok(perpetuals)
     }
 
-    async fn perpetual_orders(&self, perp: &mut perpetual::Perpetual) -> Result<(), DexError> {
-        let pid = U256::from(perp.id());
-        let order_id_index = self
-            .instance
-            .getOrderIdIndex(pid)
-            .block(self.block_id)
-            .call()
-            .await?;
+    /// Initial margin fractions sampled at the position sizes evenly spaced up to
+    /// the maximal open interest, starting with the one at zero size.
+    async fn margin_tiers(
+        &self,
+        pid: U256,
+        base_fraction: U256,
+        max_open_interest: U256,
+    ) -> Result<Vec<(U256, U256)>, DexError> {
+        if self.margin_tier_samples == 0 || max_open_interest.is_zero() {
+            return Ok(vec![]);
+        }
+        let samples = U256::from(self.margin_tier_samples);
+        let sample_futs = (1..=self.margin_tier_samples).map(|i| async move {
+            let lots = max_open_interest * U256::from(i) / samples;
+            self.instance
+                .getMarginFractions(pid, lots)
+                .block(self.block_id)
+                .call()
+                .await
+                .map(|margins| (lots, margins.dynamicInitMarginFracHdths))
+        });
+        let mut tiers = vec![(U256::ZERO, base_fraction)];
+        tiers.extend(futures::future::try_join_all(sample_futs).await?);
+        Ok(tiers)
+    }
 
-        let order_ids = order_id_index
-            .leaves
-            .into_iter()
-            .enumerate()
-            .flat_map(|(leaf, bitmap)| {
-                // Skip the first bit of the first leaf slot (_NULL_ORDER_ID)
-                // All remaining IDs are guaranteed non-zero since we start at bit 1
-                ((if leaf == 0 { 1 } else { 0 })..U256::BITS)
-                    .filter(move |bit| bitmap.bit(*bit))
-                    .map(move |bit| {
-                        let id = (leaf * U256::BITS + bit) as u16;
-                        // Safety: we skip bit 0 of leaf 0, so id is always >= 1
-                        std::num::NonZeroU16::new(id).expect("order id from bitmap cannot be 0")
-                    })
-            })
-            .collect::<Vec<_>>();
+    async fn perpetual_orders(
+        &self,
+        perp: &mut perpetual::Perpetual,
+        recorder: &partial::Recorder,
+    ) -> Result<(), DexError> {
+        let (pid, perpetual_id) = (U256::from(perp.id()), perp.id());
+        let order_ids = match recorder.with(|f| f.order_ids.get(&perpetual_id).cloned()) {
+            Some(order_ids) => order_ids,
+            None => {
+                let order_ids = self.order_ids(pid).await?;
+                recorder.with(|f| f.order_ids.insert(perpetual_id, order_ids.clone()));
+                order_ids
+            }
+        };
 
-        let order_batch_futs = order_ids.chunks(self.orders_per_batch).map(|chunk| {
-            let multicall = self
-                .provider
-                .multicall()
-                .block(self.block_id)
-                .dynamic()
-                .extend(
-                    chunk
-                        .iter()
-                        .map(|oid| self.instance.getOrder(pid, U256::from(oid.get()))),
-                );
-            async move { multicall.aggregate().await }
+        let completed = recorder.with(|f| {
+            f.order_batches
+                .get(&perpetual_id)
+                .map(|batches| batches.keys().copied().collect::<HashSet<_>>())
+                .unwrap_or_default()
+        });
+        let total = order_ids.len();
+        let fetched = AtomicUsize::new(
+            order_ids
+                .chunks(self.orders_per_batch)
+                .enumerate()
+                .filter(|(batch, _)| completed.contains(batch))
+                .map(|(_, chunk)| chunk.len())
+                .sum(),
+        );
+        self.report(SnapshotProgress::Orders {
+            perpetual_id,
+            fetched: fetched.load(Ordering::Relaxed),
+            total,
         });
+        let order_batch_futs = order_ids
+            .chunks(self.orders_per_batch)
+            .enumerate()
+            .filter(|(batch, _)| !completed.contains(batch))
+            .map(|(batch, chunk)| {
+                let fetched = &fetched;
+                let multicall = self
+                    .provider
+                    .multicall()
+                    .block(self.block_id)
+                    .dynamic()
+                    .extend(
+                        chunk
+                            .iter()
+                            .map(|oid| self.instance.getOrder(pid, U256::from(oid.get()))),
+                    );
+                async move {
+                    let orders = multicall.aggregate().await?;
+                    recorder.with(|f| {
+                        f.order_batches
+                            .entry(perpetual_id)
+                            .or_default()
+                            .insert(batch, orders)
+                    });
+                    self.report(SnapshotProgress::Orders {
+                        perpetual_id,
+                        fetched: fetched.fetch_add(chunk.len(), Ordering::Relaxed) + chunk.len(),
+                        total,
+                    });
+                    Ok::<_, alloy::providers::MulticallError>(())
+                }
+            });
+        futures::future::try_join_all(order_batch_futs)
+            .await
+            .map_err(DexError::from)?;
 
         let (instant, base_price, price_converter, size_converter, leverage_converter) = (
             perp.instant(),
@@ -343,11 +788,14 @@ impl<P: Provider + Clone> SnapshotBuilder<P> {
         );
 
         // Collect all orders first, then add via snapshot method to preserve FIFO ordering
-        let orders: Vec<Order> = futures::future::try_join_all(order_batch_futs)
-            .await
-            .map_err(DexError::from)?
+        let orders: Vec<Order> = recorder
+            .with(|f| {
+                f.order_batches
+                    .get(&perpetual_id)
+                    .map(|batches| batches.values().flatten().cloned().collect::<Vec<_>>())
+                    .unwrap_or_default()
+            })
             .into_iter()
-            .flatten()
             .map(|ord| {
                 Order::new(
                     instant,
@@ -363,13 +811,53 @@ impl<P: Provider + Clone> SnapshotBuilder<P> {
         perp.add_orders_from_snapshot(orders)
     }
 
+    async fn order_ids(&self, pid: U256) -> Result<Vec<std::num::NonZeroU16>, DexError> {
+        let order_id_index = self
+            .instance
+            .getOrderIdIndex(pid)
+            .block(self.block_id)
+            .call()
+            .await?;
+
+        Ok(order_id_index
+            .leaves
+            .into_iter()
+            .enumerate()
+            .flat_map(|(leaf, bitmap)| {
+                // Skip the first bit of the first leaf slot (_NULL_ORDER_ID)
+                // All remaining IDs are guaranteed non-zero since we start at bit 1
+                ((if leaf == 0 { 1 } else { 0 })..U256::BITS)
+                    .filter(move |bit| bitmap.bit(*bit))
+                    .map(move |bit| {
+                        let id = (leaf * U256::BITS + bit) as u16;
+                        // Safety: we skip bit 0 of leaf 0, so id is always >= 1
+                        std::num::NonZeroU16::new(id).expect("order id from bitmap cannot be 0")
+                    })
+            })
+            .collect())
+    }
+
     async fn accounts(
         &self,
         instant: types::StateInstant,
         perpetuals: &HashMap<types::PerpetualId, perpetual::Perpetual>,
         collateral_converter: num::Converter,
+        recorder: &partial::Recorder,
     ) -> Result<HashMap<types::AccountId, Account>, DexError> {
-        let account_futs = self.accounts.iter().map(|acc_addr| async {
+        let total = self.accounts.len();
+        let remaining = recorder.with(|f| {
+            self.accounts
+                .iter()
+                .filter(|(acc_addr, _)| !f.accounts.contains_key(acc_addr))
+                .map(|(acc_addr, _)| *acc_addr)
+                .collect::<Vec<_>>()
+        });
+        let fetched = AtomicUsize::new(total - remaining.len());
+        self.report(SnapshotProgress::Accounts {
+            fetched: fetched.load(Ordering::Relaxed),
+            total,
+        });
+        let account_futs = remaining.iter().map(|acc_addr| async {
             let acc_info = self
                 .instance
                 .getAccountByAddr(*acc_addr)
@@ -386,43 +874,56 @@ impl<P: Provider + Clone> SnapshotBuilder<P> {
                     .map(|pos_info| (*perp_id, pos_info))
             });
             let positions = futures::future::try_join_all(position_futs).await?;
-            Ok::<_, DexError>((acc_info.accountId, acc_info, positions))
+            recorder.with(|f| f.accounts.insert(*acc_addr, (acc_info, positions)));
+            self.report(SnapshotProgress::Accounts {
+                fetched: fetched.fetch_add(1, Ordering::Relaxed) + 1,
+                total,
+            });
+            Ok::<_, DexError>(())
         });
+        futures::future::try_join_all(account_futs).await?;
 
-        Ok(futures::future::try_join_all(account_futs)
-            .await?
-            .into_iter()
-            .map(|(acc_id, acc_info, positions)| {
-                (
-                    acc_id.to(),
-                    Account::new(
-                        instant,
-                        acc_id.to(),
-                        &acc_info,
-                        positions
-                            .into_iter()
-                            .filter_map(|(perp_id, pos_info)| {
-                                perpetuals.get(&perp_id).map(|perp| {
-                                    (
-                                        perp_id,
-                                        Position::new(
-                                            instant,
-                                            perp_id,
-                                            &pos_info.positionInfo,
-                                            collateral_converter,
-                                            perp.price_converter(),
-                                            perp.size_converter(),
-                                            perp.maintenance_margin(),
-                                        ),
-                                    )
+        Ok(recorder.with(|f| {
+            self.accounts
+                .iter()
+                .filter_map(|(acc_addr, tracking)| {
+                    f.accounts
+                        .get(acc_addr)
+                        .map(|(acc_info, positions)| (acc_info, positions, *tracking))
+                })
+                .map(|(acc_info, positions, tracking)| {
+                    (
+                        acc_info.accountId.to(),
+                        Account::new(
+                            instant,
+                            acc_info.accountId.to(),
+                            acc_info,
+                            positions
+                                .iter()
+                                .filter_map(|(perp_id, pos_info)| {
+                                    perpetuals.get(perp_id).map(|perp| {
+                                        (
+                                            *perp_id,
+                                            Position::new(
+                                                instant,
+                                                *perp_id,
+                                                &pos_info.positionInfo,
+                                                collateral_converter,
+                                                perp.price_converter(),
+                                                perp.size_converter(),
+                                                perp.maintenance_margin(),
+                                            ),
+                                        )
+                                    })
                                 })
-                            })
-                            .collect(),
-                        collateral_converter,
-                    ),
-                )
-            })
-            .collect())
+                                .collect(),
+                            collateral_converter,
+                            tracking,
+                        ),
+                    )
+                })
+                .collect()
+        }))
     }
 
     async fn position_accounts(
@@ -431,47 +932,94 @@ impl<P: Provider + Clone> SnapshotBuilder<P> {
         perpetuals: &HashMap<types::PerpetualId, perpetual::Perpetual>,
         num_accounts: usize,
         collateral_converter: num::Converter,
+        recorder: &partial::Recorder,
     ) -> Result<HashMap<types::AccountId, Account>, DexError> {
         let mut accounts: HashMap<types::AccountId, Account> = HashMap::new();
         for (perp_id, perp) in perpetuals {
             let pid = U256::from(*perp_id);
-            let account_id_chunks = (1..num_accounts + 1).chunks(self.positions_per_batch);
-            let pos_batch_futs = account_id_chunks.into_iter().map(|chunk| {
-                let multicall = self
-                    .provider
-                    .multicall()
-                    .block(self.block_id)
-                    .dynamic()
-                    .extend(chunk.map(|aid| self.instance.getPosition(pid, U256::from(aid))));
-                async move { multicall.aggregate().await }
+            let completed = recorder.with(|f| {
+                f.position_batches
+                    .get(perp_id)
+                    .map(|batches| batches.keys().copied().collect::<HashSet<_>>())
+                    .unwrap_or_default()
             });
+            let fetched = AtomicUsize::new(
+                (1..num_accounts + 1)
+                    .chunks(self.positions_per_batch)
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(batch, _)| completed.contains(batch))
+                    .map(|(_, chunk)| chunk.count())
+                    .sum(),
+            );
+            self.report(SnapshotProgress::Positions {
+                perpetual_id: *perp_id,
+                fetched: fetched.load(Ordering::Relaxed),
+                total: num_accounts,
+            });
+            let account_id_chunks = (1..num_accounts + 1).chunks(self.positions_per_batch);
+            let pos_batch_futs = account_id_chunks
+                .into_iter()
+                .enumerate()
+                .filter(|(batch, _)| !completed.contains(batch))
+                .map(|(batch, chunk)| {
+                    let fetched = &fetched;
+                    let multicall = self
+                        .provider
+                        .multicall()
+                        .block(self.block_id)
+                        .dynamic()
+                        .extend(chunk.map(|aid| self.instance.getPosition(pid, U256::from(aid))));
+                    async move {
+                        let positions = multicall.aggregate().await?;
+                        let count = positions.len();
+                        recorder.with(|f| {
+                            f.position_batches
+                                .entry(*perp_id)
+                                .or_default()
+                                .insert(batch, positions)
+                        });
+                        self.report(SnapshotProgress::Positions {
+                            perpetual_id: *perp_id,
+                            fetched: fetched.fetch_add(count, Ordering::Relaxed) + count,
+                            total: num_accounts,
+                        });
+                        Ok::<_, alloy::providers::MulticallError>(())
+                    }
+                })
+                .collect::<Vec<_>>();
 
             futures::future::try_join_all(pos_batch_futs)
                 .await
-                .map_err(DexError::from)?
-                .into_iter()
-                .flatten()
-                .for_each(|pos| {
-                    if !pos.positionInfo.lotLNS.is_zero() {
-                        let position = Position::new(
-                            instant,
-                            *perp_id,
-                            &pos.positionInfo,
-                            collateral_converter,
-                            perp.price_converter(),
-                            perp.size_converter(),
-                            perp.maintenance_margin(),
-                        );
-                        match accounts.entry(pos.positionInfo.accountId.to()) {
-                            hash_map::Entry::Occupied(mut e) => {
-                                e.get_mut().positions_mut().insert(*perp_id, position);
-                            }
-                            hash_map::Entry::Vacant(e) => {
-                                e.insert(Account::from_position(instant, position));
+                .map_err(DexError::from)?;
+
+            recorder.with(|f| {
+                f.position_batches
+                    .get(perp_id)
+                    .into_iter()
+                    .flat_map(|batches| batches.values().flatten())
+                    .for_each(|pos| {
+                        if !pos.positionInfo.lotLNS.is_zero() {
+                            let position = Position::new(
+                                instant,
+                                *perp_id,
+                                &pos.positionInfo,
+                                collateral_converter,
+                                perp.price_converter(),
+                                perp.size_converter(),
+                                perp.maintenance_margin(),
+                            );
+                            match accounts.entry(pos.positionInfo.accountId.to()) {
+                                hash_map::Entry::Occupied(mut e) => {
+                                    e.get_mut().insert_position(position);
+                                }
+                                hash_map::Entry::Vacant(e) => {
+                                    e.insert(Account::from_position(instant, position));
+                                }
                             }
                         }
-                    }
-                });
+                    })
+            });
         }
 
         Ok(accounts)
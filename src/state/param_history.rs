@@ -0,0 +1,118 @@
+//! Audit trail of perpetual contract parameter changes.
+
+use std::collections::VecDeque;
+
+use alloy::primitives::B256;
+use fastnum::UD64;
+
+use crate::types;
+
+/// Default number of parameter changes kept per perpetual contract.
+pub(crate) const DEFAULT_PARAM_HISTORY_RETENTION: usize = 64;
+
+/// Value of the administratively set perpetual contract parameter.
+#[derive(Clone, Copy, PartialEq, Eq, derive_more::Debug)]
+pub enum PerpetualParam {
+    /// Trading paused.
+    Paused(bool),
+
+    /// Maker fee.
+    MakerFee(#[debug("{_0}")] UD64),
+
+    /// Taker fee.
+    TakerFee(#[debug("{_0}")] UD64),
+
+    /// Initial margin requirement, as maximal leverage.
+    InitialMargin(#[debug("{_0}")] UD64),
+
+    /// Maintenance margin requirement, as maximal leverage.
+    MaintenanceMargin(#[debug("{_0}")] UD64),
+
+    /// Price feed ID of the oracle.
+    OracleFeedId(B256),
+
+    /// Oracle price is used.
+    OracleUsed(bool),
+
+    /// Maximal age of the reference price, in seconds.
+    PriceMaxAge(u64),
+
+    /// Maximal relative deviation of the checked prices.
+    PriceTolerance(#[debug("{_0}")] UD64),
+}
+
+/// Single change of the perpetual contract parameter,
+/// see [`super::Perpetual::param_history`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParamChange {
+    /// Instant of the block the change occurred in.
+    pub instant: types::StateInstant,
+
+    /// Value before the change.
+    pub previous: PerpetualParam,
+
+    /// Value after the change.
+    pub current: PerpetualParam,
+}
+
+/// Most recent parameter changes, bounded by the retention.
+#[derive(Clone, Debug)]
+pub(crate) struct ParamHistory {
+    retention: usize,
+    changes: VecDeque<ParamChange>,
+}
+
+impl Default for ParamHistory {
+    fn default() -> Self {
+        Self {
+            retention: DEFAULT_PARAM_HISTORY_RETENTION,
+            changes: VecDeque::new(),
+        }
+    }
+}
+
+impl ParamHistory {
+    pub(crate) fn changes(&self) -> &VecDeque<ParamChange> {
+        &self.changes
+    }
+
+    /// Records the change unless the value stays the same.
+    pub(crate) fn record(
+        &mut self,
+        instant: types::StateInstant,
+        previous: PerpetualParam,
+        current: PerpetualParam,
+    ) {
+        if previous == current || self.retention == 0 {
+            return;
+        }
+        if self.changes.len() == self.retention {
+            self.changes.pop_front();
+        }
+        self.changes.push_back(ParamChange {
+            instant,
+            previous,
+            current,
+        });
+    }
+
+    pub(crate) fn set_retention(&mut self, retention: usize) {
+        self.retention = retention;
+        while self.changes.len() > retention {
+            self.changes.pop_front();
+        }
+    }
+
+    /// Drops changes recorded before the block, returning the number dropped.
+    pub(crate) fn prune_before(&mut self, block_number: u64) -> usize {
+        let len = self.changes.len();
+        while self
+            .changes
+            .front()
+            .is_some_and(|r| r.instant.block_number() < block_number)
+        {
+            self.changes.pop_front();
+        }
+        len - self.changes.len()
+    }
+}
@@ -0,0 +1,196 @@
+//! State of the interrupted snapshot building to resume from.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    num::NonZeroU16,
+    sync::Mutex,
+};
+
+use alloy::primitives::{Address, U256};
+
+use super::{perpetual::Perpetual, roles::Roles};
+use crate::{
+    abi::dex::Exchange::{AccountInfo, Order, getExchangeInfoReturn, getPositionReturn},
+    error::DexError,
+    types,
+};
+
+/// Global exchange parameters and state fetched at the start of the snapshot.
+pub(super) type ExchangeInfo = (getExchangeInfoReturn, U256, U256, U256, U256, bool, U256);
+
+/// Account fetched along with its positions.
+pub(super) type FetchedAccount = (AccountInfo, Vec<(types::PerpetualId, getPositionReturn)>);
+
+/// Exchange state fetched by the [`super::SnapshotBuilder`] before building failed,
+/// returned by [`super::SnapshotBuilder::build_resumable`].
+///
+/// Passing it to [`super::SnapshotBuilder::resume_from`] makes the retry fetch
+/// only the remaining perpetual contracts, order batches, accounts and position batches,
+/// at the same block as the original attempt.
+#[derive(Debug)]
+pub struct PartialSnapshot {
+    error: DexError,
+    fetched: Fetched,
+}
+
+impl PartialSnapshot {
+    pub(super) fn new(error: DexError, fetched: Fetched) -> Self {
+        Self { error, fetched }
+    }
+
+    /// Error building the snapshot failed with.
+    pub fn error(&self) -> &DexError {
+        &self.error
+    }
+
+    /// Consumes the partial snapshot returning the error building failed with.
+    pub fn into_error(self) -> DexError {
+        self.error
+    }
+
+    /// Block number the snapshot is being built at, if it was resolved before the failure.
+    pub fn block_number(&self) -> Option<u64> {
+        self.fetched.instant.map(|instant| instant.block_number())
+    }
+
+    /// Perpetual contracts with parameters and all active orders fetched, in ascending order.
+    pub fn completed_perpetuals(&self) -> Vec<types::PerpetualId> {
+        let mut completed = self
+            .fetched
+            .perpetuals
+            .keys()
+            .filter(|perp_id| self.fetched.orders_complete(**perp_id))
+            .copied()
+            .collect::<Vec<_>>();
+        completed.sort_unstable();
+        completed
+    }
+
+    /// Number of order batches fetched for the perpetual contract.
+    pub fn order_batches(&self, perpetual_id: types::PerpetualId) -> usize {
+        self.fetched
+            .order_batches
+            .get(&perpetual_id)
+            .map_or(0, BTreeMap::len)
+    }
+
+    /// Number of position batches fetched for the perpetual contract,
+    /// when all positions are requested, see [`super::SnapshotBuilder::with_all_positions`].
+    pub fn position_batches(&self, perpetual_id: types::PerpetualId) -> usize {
+        self.fetched
+            .position_batches
+            .get(&perpetual_id)
+            .map_or(0, BTreeMap::len)
+    }
+
+    /// Number of requested accounts fetched along with their positions.
+    pub fn fetched_accounts(&self) -> usize {
+        self.fetched.accounts.len()
+    }
+
+    pub(super) fn into_fetched(self) -> Fetched {
+        self.fetched
+    }
+}
+
+/// Raw state fetched so far, keyed by the unit of work it was fetched in.
+#[derive(Debug, Default)]
+pub(super) struct Fetched {
+    pub instant: Option<types::StateInstant>,
+    pub exchange_info: Option<ExchangeInfo>,
+    pub roles: Option<Roles>,
+    /// Perpetual contracts with parameters but without orders.
+    pub perpetuals: HashMap<types::PerpetualId, Perpetual>,
+    pub order_ids: HashMap<types::PerpetualId, Vec<NonZeroU16>>,
+    pub orders_per_batch: usize,
+    pub order_batches: HashMap<types::PerpetualId, BTreeMap<usize, Vec<Order>>>,
+    pub accounts: HashMap<Address, FetchedAccount>,
+    pub positions_per_batch: usize,
+    pub position_batches: HashMap<types::PerpetualId, BTreeMap<usize, Vec<getPositionReturn>>>,
+}
+
+impl Fetched {
+    /// Prepares the fetched state for building with the given batch sizes,
+    /// discarding batches fetched with different ones as their bounds no longer match.
+    pub fn for_batches(mut self, orders_per_batch: usize, positions_per_batch: usize) -> Self {
+        if self.orders_per_batch != orders_per_batch {
+            self.order_batches.clear();
+            self.orders_per_batch = orders_per_batch;
+        }
+        if self.positions_per_batch != positions_per_batch {
+            self.position_batches.clear();
+            self.positions_per_batch = positions_per_batch;
+        }
+        self
+    }
+
+    fn orders_complete(&self, perp_id: types::PerpetualId) -> bool {
+        self.order_ids.get(&perp_id).is_some_and(|order_ids| {
+            order_ids.len().div_ceil(self.orders_per_batch.max(1)) == self.order_batches(perp_id)
+        })
+    }
+
+    fn order_batches(&self, perp_id: types::PerpetualId) -> usize {
+        self.order_batches.get(&perp_id).map_or(0, BTreeMap::len)
+    }
+}
+
+/// Fetched state shared between concurrent fetches of the snapshot,
+/// so every completed unit of work gets recorded even if others fail.
+#[derive(Debug)]
+pub(super) struct Recorder(Mutex<Fetched>);
+
+impl Recorder {
+    pub fn new(fetched: Fetched) -> Self {
+        Self(Mutex::new(fetched))
+    }
+
+    pub fn with<R>(&self, f: impl FnOnce(&mut Fetched) -> R) -> R {
+        f(&mut self.0.lock().expect("snapshot recorder lock"))
+    }
+
+    pub fn into_inner(self) -> Fetched {
+        self.0.into_inner().expect("snapshot recorder lock")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batches_tracking() {
+        let ids = |n: u16| (1..=n).filter_map(NonZeroU16::new).collect::<Vec<_>>();
+        let mut fetched = Fetched::default().for_batches(2, 10);
+        fetched.perpetuals.insert(1, Perpetual::for_testing(1));
+        fetched.perpetuals.insert(2, Perpetual::for_testing(2));
+        fetched.order_ids.insert(1, ids(3));
+        fetched.order_ids.insert(2, ids(0));
+        fetched
+            .order_batches
+            .insert(1, BTreeMap::from([(0, vec![])]));
+        fetched
+            .position_batches
+            .insert(1, BTreeMap::from([(0, vec![])]));
+
+        let partial = PartialSnapshot::new(DexError::Cancelled, fetched);
+        assert_eq!(partial.completed_perpetuals(), vec![2]);
+        assert_eq!(partial.order_batches(1), 1);
+
+        // Same batch sizes keep the progress
+        let mut fetched = partial.into_fetched().for_batches(2, 10);
+        fetched.order_batches.get_mut(&1).unwrap().insert(1, vec![]);
+        let partial = PartialSnapshot::new(DexError::Cancelled, fetched);
+        assert_eq!(partial.completed_perpetuals(), vec![1, 2]);
+        assert_eq!(partial.position_batches(1), 1);
+
+        // Different batch sizes discard fetched batches
+        let partial = PartialSnapshot::new(
+            DexError::Cancelled,
+            partial.into_fetched().for_batches(3, 20),
+        );
+        assert_eq!(partial.completed_perpetuals(), vec![2]);
+        assert_eq!(partial.order_batches(1), 0);
+        assert_eq!(partial.position_batches(1), 0);
+    }
+}
@@ -1,11 +1,25 @@
-use super::*;
-use crate::{abi::dex::Exchange::PerpetualInfo, types};
+use super::{funding::FundingHistory, param_history::ParamHistory, *};
+use crate::{abi::dex::Exchange::PerpetualInfo, oracle::IndexPrice, types};
 use alloy::primitives::{B256, I256, U256};
 use fastnum::{D64, D256, UD64, UD128};
+use std::{sync::Arc, time::Duration};
 
-const FEE_SCALE: u8 = 5;
-const FUNDING_RATE_SCALE: u8 = 5;
-const LEVERAGE_SCALE: u8 = 2;
+pub(crate) const FEE_SCALE: u8 = 5;
+pub(crate) const FUNDING_RATE_SCALE: u8 = 5;
+pub(crate) const LEVERAGE_SCALE: u8 = 2;
+
+/// Initial margin fraction applied to positions from the size on,
+/// see [`Perpetual::margin_tiers`].
+#[derive(Clone, Copy, PartialEq, Eq, derive_more::Debug)]
+pub struct MarginTier {
+    /// Smallest position size the tier applies to.
+    #[debug("{min_size}")]
+    pub min_size: UD64,
+
+    /// Initial margin fraction of the tier.
+    #[debug("{initial_margin}")]
+    pub initial_margin: UD64,
+}
 
 /// Perpetual contract tradeable at the exchange.
 ///
@@ -14,7 +28,9 @@ const LEVERAGE_SCALE: u8 = 2;
 #[derive(Clone, derive_more::Debug)]
 pub struct Perpetual {
     instant: types::StateInstant,
-    state_instant: types::StateInstant,
+    /// Instant the funding state was last brought up to, advanced only on reaching
+    /// the funding event block so that untouched contracts stay shared with checkpoints.
+    funding_instant: types::StateInstant,
     id: types::PerpetualId,
     name: String,
     symbol: String,
@@ -25,6 +41,7 @@ pub struct Perpetual {
     leverage_converter: num::Converter,
     fee_converter: num::Converter,
     funding_rate_converter: num::Converter,
+    collateral_converter: num::Converter,
     #[debug("{base_price}")]
     base_price: UD64, // SC allocates 32 bits
 
@@ -34,6 +51,7 @@ pub struct Perpetual {
     taker_fee: UD64, // SC allocates 16 bits
     #[debug("{initial_margin}")]
     initial_margin: UD64, // SC allocates 16 bits
+    margin_tiers: Vec<MarginTier>,
     #[debug("{maintenance_margin}")]
     maintenance_margin: UD64, // SC allocates 16 bits
 
@@ -52,6 +70,8 @@ pub struct Perpetual {
     oracle_price_block: Option<u64>,
     oracle_price_timestamp: u64,
 
+    index_price: Option<IndexPrice>,
+
     #[debug("{prev_funding_rate}")]
     prev_funding_rate: D64, // SC allocates 16 bits of precision
     #[debug("{:?}", next_funding_rate.map(|v| format!("{v}")))]
@@ -64,11 +84,21 @@ pub struct Perpetual {
     oracle_feed_id: B256,
     is_oracle_used: bool,
     price_max_age_sec: u64,
+    #[debug("{price_tolerance}")]
+    price_tolerance: UD64,
+    last_tolerance_breach: Option<ToleranceBreach>,
+    last_deleverage_failure: Option<DeleverageFailure>,
 
-    l3_book: OrderBook,
+    /// Shared with the clones of the contract state until either one gets mutated.
+    l3_book: Arc<OrderBook>,
 
     #[debug("{open_interest}")]
     open_interest: UD128,
+    #[debug("{insurance_balance}")]
+    insurance_balance: UD128,
+
+    param_history: ParamHistory,
+    funding_history: FundingHistory,
 }
 
 impl Perpetual {
@@ -89,7 +119,7 @@ impl Perpetual {
         let funding_rate_converter = num::Converter::new(FUNDING_RATE_SCALE);
         Self {
             instant,
-            state_instant: instant,
+            funding_instant: instant,
             id,
             name: info.name.clone(),
             symbol: info.symbol.clone(),
@@ -100,12 +130,15 @@ impl Perpetual {
             leverage_converter,
             fee_converter,
             funding_rate_converter,
+            // Collateral precision is set by the exchange state holding the contract
+            collateral_converter: num::Converter::default(),
             base_price: price_converter.from_unsigned(info.basePricePNS),
 
             maker_fee: fee_converter.from_unsigned(maker_fee), // Fees are per 100K
             taker_fee: fee_converter.from_unsigned(taker_fee), // Fees are per 100K
             // Margins are in hundredths
             initial_margin: leverage_converter.from_unsigned(initial_margin),
+            margin_tiers: vec![],
             // Margins are in hundredths
             maintenance_margin: leverage_converter.from_unsigned(maintenance_margin),
 
@@ -121,6 +154,8 @@ impl Perpetual {
             oracle_price_block: None,
             oracle_price_timestamp: info.oracleTimestampSec.to(),
 
+            index_price: None,
+
             prev_funding_rate: funding_rate_converter
                 .from_signed(I256::try_from(info.fundingRatePct100k).unwrap()),
             next_funding_rate: None,
@@ -131,10 +166,18 @@ impl Perpetual {
             oracle_feed_id: info.linkFeedId,
             is_oracle_used: !info.ignOracle,
             price_max_age_sec: info.refPriceMaxAgeSec.to(),
+            price_tolerance: fee_converter.from_unsigned(info.priceTolPer100K), // Per 100K
+            last_tolerance_breach: None,
+            last_deleverage_failure: None,
 
-            l3_book: OrderBook::new(),
+            l3_book: Arc::new(OrderBook::new()),
 
             open_interest: size_converter.from_unsigned(info.longOpenInterestLNS),
+            // Collateral precision is not known yet, see `set_insurance_balance`
+            insurance_balance: UD128::ZERO,
+
+            param_history: ParamHistory::default(),
+            funding_history: FundingHistory::default(),
         }
     }
 
@@ -198,11 +241,53 @@ impl Perpetual {
         self.taker_fee
     }
 
+    /// Maker and taker fees with the collateral precision, to compute fee amounts
+    /// matching the on-chain ones.
+    pub fn fee_schedule(&self) -> FeeSchedule {
+        FeeSchedule::new(
+            self.maker_fee,
+            self.taker_fee,
+            self.fee_converter,
+            self.collateral_converter,
+        )
+    }
+
+    /// Maker fee in collateral tokens for the fill of the size at the price,
+    /// see [`FeeSchedule::maker_fee_for`].
+    pub fn maker_fee_for(&self, size: UD64, price: UD64) -> UD128 {
+        self.fee_schedule().maker_fee_for(size, price)
+    }
+
+    /// Taker fee in collateral tokens for the fill of the size at the price,
+    /// see [`FeeSchedule::taker_fee_for`].
+    pub fn taker_fee_for(&self, size: UD64, price: UD64) -> UD128 {
+        self.fee_schedule().taker_fee_for(size, price)
+    }
+
     /// Minimal initial margin fraction required to open a position.
     pub fn initial_margin(&self) -> UD64 {
         self.initial_margin
     }
 
+    /// Initial margin fractions by position size reported by the contract at the snapshot,
+    /// ordered by size, empty if the contract does not tier them by size.
+    /// See [`super::SnapshotBuilder::with_margin_tier_samples`].
+    ///
+    /// Tiers are dropped on the initial margin update, as events do not report them.
+    pub fn margin_tiers(&self) -> &[MarginTier] {
+        &self.margin_tiers
+    }
+
+    /// Initial margin fraction of the position of the given size: the one of the largest tier
+    /// starting at or below the size, or [`Self::initial_margin`] if not tiered.
+    pub fn initial_margin_for_size(&self, size: UD64) -> UD64 {
+        self.margin_tiers
+            .iter()
+            .take_while(|tier| tier.min_size <= size)
+            .last()
+            .map_or(self.initial_margin, |tier| tier.initial_margin)
+    }
+
     /// Minimal maintenance margin fraction required to keep a position.
     pub fn maintenance_margin(&self) -> UD64 {
         self.maintenance_margin
@@ -268,10 +353,34 @@ impl Perpetual {
         self.oracle_price_timestamp + self.price_max_age_sec <= self.instant.block_timestamp()
     }
 
+    /// Index price from the external reference, see [`crate::oracle`].
+    /// Available only after [`Exchange::update_index_prices`].
+    pub fn index_price(&self) -> Option<IndexPrice> {
+        self.index_price
+    }
+
+    /// Reference price for basis and funding estimations: the oracle price if the contract
+    /// uses the on-chain oracle and the price is not obsolete, the index price otherwise,
+    /// falling back to the known oracle price.
+    pub fn reference_price(&self) -> Option<UD64> {
+        let oracle_price = (self.oracle_price > UD64::ZERO).then_some(self.oracle_price);
+        if self.is_oracle_used && !self.is_oracle_price_obsolete() {
+            return oracle_price;
+        }
+        self.index_price.map(|p| p.price).or(oracle_price)
+    }
+
+    /// Relative difference of the mark price from the [`Self::reference_price`].
+    pub fn basis(&self) -> Option<D64> {
+        let reference = self.reference_price()?;
+        (self.mark_price > UD64::ZERO)
+            .then(|| (self.mark_price.to_signed() - reference.to_signed()) / reference.to_signed())
+    }
+
     /// The funding rate applied at the previous funding event.
     pub fn funding_rate(&self) -> D64 {
         if let Some((next, bl)) = self.next_funding_rate.zip(self.next_funding_event_block)
-            && bl <= self.state_instant.block_number()
+            && bl <= self.funding_instant.block_number()
         {
             next
         } else {
@@ -284,7 +393,7 @@ impl Perpetual {
         self.next_funding_rate.is_some()
             && self
                 .next_funding_event_block
-                .is_some_and(|bl| bl > self.state_instant.block_number())
+                .is_some_and(|bl| bl > self.funding_instant.block_number())
     }
 
     /// Starting block number of funding intervals.
@@ -293,6 +402,23 @@ impl Perpetual {
         self.funding_start_block
     }
 
+    /// Upcoming funding event boundaries after the given instant, given the funding
+    /// interval of the exchange and the average block time to estimate their timestamps,
+    /// see [`Exchange::funding_schedule`].
+    pub fn funding_schedule(
+        &self,
+        instant: types::StateInstant,
+        funding_interval_blocks: u32,
+        block_time: Duration,
+    ) -> FundingSchedule {
+        FundingSchedule::new(
+            instant,
+            self.funding_start_block,
+            funding_interval_blocks,
+            block_time,
+        )
+    }
+
     /// Feed ID of ChainLink DataStreams price oracle.
     pub fn oracle_feed_id(&self) -> B256 {
         self.oracle_feed_id
@@ -308,6 +434,117 @@ impl Perpetual {
         self.price_max_age_sec
     }
 
+    /// Maximal relative deviation of the prices checked by the exchange, such as
+    /// order prices from the mark price, or the mark price from the synthetic
+    /// perpetual price.
+    pub fn price_tolerance(&self) -> UD64 {
+        self.price_tolerance
+    }
+
+    /// Range of order prices within the [`Self::price_tolerance`] around the mark price,
+    /// inclusive. Orders priced outside of it get rejected with
+    /// [`OrderErrorType::PriceOutOfRange`].
+    ///
+    /// Returns `None` if the mark price is unknown or the tolerance is not set.
+    pub fn acceptable_price_range(&self) -> Option<(UD64, UD64)> {
+        price_band::price_band(self.mark_price, self.price_tolerance)
+    }
+
+    /// Indicates the order price is within [`Self::acceptable_price_range`],
+    /// or the range is unknown.
+    pub fn is_price_acceptable(&self, price: UD64) -> bool {
+        self.acceptable_price_range()
+            .is_none_or(|(min, max)| (min..=max).contains(&price))
+    }
+
+    /// The most recent price deviation beyond the tolerance reported by the exchange,
+    /// which prevented the mark price update or the funding event.
+    /// Available only from real-time events, not from the initial snapshot.
+    pub fn last_tolerance_breach(&self) -> Option<ToleranceBreach> {
+        self.last_tolerance_breach
+    }
+
+    /// The most recent auto-deleveraging attempt rejected by the exchange for the lack
+    /// of opposing positions. Available only from real-time events, not from the initial snapshot.
+    pub fn last_deleverage_failure(&self) -> Option<DeleverageFailure> {
+        self.last_deleverage_failure
+    }
+
+    /// Most recent changes of the administratively set parameters, oldest first.
+    ///
+    /// Only changes observed via events since the snapshot are available, bounded by
+    /// the retention, see [`super::SnapshotBuilder::with_param_history_retention`].
+    pub fn param_history(&self) -> impl DoubleEndedIterator<Item = &ParamChange> {
+        self.param_history.changes().iter()
+    }
+
+    /// Funding events completed within the time window before the given instant,
+    /// usually [`Exchange::instant`], along with their summary statistics.
+    ///
+    /// Only funding events observed via events since the snapshot are available, bounded by
+    /// the retention, see [`super::SnapshotBuilder::with_funding_history_retention`].
+    pub fn funding_history(
+        &self,
+        instant: types::StateInstant,
+        window: Duration,
+    ) -> FundingWindow<'_> {
+        self.funding_history
+            .window(instant.block_timestamp(), window)
+    }
+
+    /// Minimal price increment of the contract.
+    pub fn tick_size(&self) -> UD64 {
+        self.price_converter.from_u64(1)
+    }
+
+    /// Minimal size increment of the contract.
+    pub fn lot_size(&self) -> UD64 {
+        self.size_converter.from_u64(1)
+    }
+
+    /// Minimal size of an order at the given price satisfying
+    /// the exchange minimal posted order value, see [`Exchange::min_post`].
+    ///
+    /// Returns [`Self::lot_size`] if the price is zero.
+    pub fn min_order_size(&self, min_post: UD128, price: UD64) -> UD64 {
+        if price == UD64::ZERO {
+            return self.lot_size();
+        }
+        let size: UD64 = (min_post / price.resize()).resize();
+        self.size_converter
+            .from_unsigned(
+                self.size_converter
+                    .to_unsigned_rounded(size, num::Rounding::Ceil),
+            )
+            .max(self.lot_size())
+    }
+
+    /// Up to `depth` best price buckets of each side of the order book, every
+    /// `tick_multiple` ticks wide, see [`OrderBook::depth_bucketed`].
+    pub fn depth_bucketed(&self, tick_multiple: u64, depth: usize) -> BucketedDepth {
+        self.l3_book
+            .depth_bucketed(self.price_converter.from_u64(tick_multiple), depth)
+    }
+
+    /// Snaps the price to a valid tick in the direction safe for the order side:
+    /// down for bids and up for asks, so the order never gets more aggressive.
+    pub fn round_price(&self, price: UD64, side: types::OrderSide) -> UD64 {
+        let rounding = match side {
+            types::OrderSide::Bid => num::Rounding::Floor,
+            types::OrderSide::Ask => num::Rounding::Ceil,
+        };
+        self.price_converter
+            .from_unsigned(self.price_converter.to_unsigned_rounded(price, rounding))
+    }
+
+    /// Snaps the size down to a valid lot, so the order never exceeds the intended size.
+    pub fn round_size(&self, size: UD64) -> UD64 {
+        self.size_converter.from_unsigned(
+            self.size_converter
+                .to_unsigned_rounded(size, num::Rounding::Floor),
+        )
+    }
+
     /// Get a specific order by ID.
     pub fn get_order(&self, order_id: types::OrderId) -> Option<&Order> {
         self.l3_book.get_order_data(order_id)
@@ -323,20 +560,99 @@ impl Perpetual {
         &self.l3_book
     }
 
+    pub(crate) fn l3_book_mut(&mut self) -> &mut OrderBook {
+        Arc::make_mut(&mut self.l3_book)
+    }
+
+    /// Orders of the account not placed or modified for more than `older_than_blocks`
+    /// blocks as of the given block, usually the one of [`Exchange::instant`],
+    /// see [`BookOrder::blocks_since_modified`].
+    ///
+    /// Orders from the snapshot are aged from the snapshot instant.
+    pub fn stale_orders(
+        &self,
+        account_id: types::AccountId,
+        block_number: u64,
+        older_than_blocks: u64,
+    ) -> impl Iterator<Item = &BookOrder> {
+        self.l3_book.all_orders().values().filter(move |order| {
+            order.account_id() == account_id
+                && order.blocks_since_modified(block_number) > older_than_blocks
+        })
+    }
+
+    /// Estimated time until the given order gets completely filled, assuming the
+    /// resting size ahead of it, including better price levels, and the order
+    /// itself get consumed at the given `trade_rate` (size per second).
+    ///
+    /// Returns `None` if the order is not in the book, the trade rate is zero
+    /// or too small for the estimate to fit [`Duration`].
+    pub fn estimated_time_to_fill(
+        &self,
+        order_id: types::OrderId,
+        trade_rate: UD64,
+    ) -> Option<Duration> {
+        if trade_rate == UD64::ZERO {
+            return None;
+        }
+        let order = self.l3_book.get_order(order_id)?;
+        let (ahead, _) = self.l3_book.queue_position(order_id)?;
+        let queue = self
+            .l3_book
+            .size_ahead_of_level(order.r#type().side(), order.price())
+            + ahead
+            + order.size();
+        Duration::try_from_secs_f64((queue / trade_rate).to_f64()).ok()
+    }
+
+    /// Depth-weighted fair price: the midpoint of the size-averaged prices of both sides
+    /// of the book, each walked away from the spread until `depth_notional` is reached.
+    ///
+    /// Less sensitive to a single thin level than the last trade or the top of the book.
+    /// Returns `None` if either side of the book is empty or the notional is zero.
+    pub fn fair_price(&self, depth_notional: UD128) -> Option<UD64> {
+        let bids = self.l3_book.bids().iter().map(|(k, v)| (k.0, v.size()));
+        let asks = self.l3_book.asks().iter().map(|(k, v)| (*k, v.size()));
+        let bid = depth_weighted_price(bids, depth_notional)?;
+        let ask = depth_weighted_price(asks, depth_notional)?;
+        Some(((bid.resize() + ask.resize()) / UD128::TWO).resize())
+    }
+
     /// Open interest in the perpetual contract.
     pub fn open_interest(&self) -> UD128 {
         self.open_interest
     }
 
+    /// Balance of the insurance fund of the perpetual contract, covering settlements
+    /// the positions can not pay for.
+    ///
+    /// Tracked from the snapshot with transfers and settlement payments reported
+    /// by the events, see [`super::Exchange::fund_history`]. Shares of trading fees
+    /// and liquidation proceeds credited to the fund are not reported by the exchange,
+    /// so the tracked balance underestimates the actual one until the next snapshot.
+    pub fn insurance_balance(&self) -> UD128 {
+        self.insurance_balance
+    }
+
     pub(crate) fn base_price(&self) -> UD64 {
         self.base_price
     }
 
-    pub(crate) fn update_state_instant(
+    /// Whether the block brings the contract to its next funding event,
+    /// see [`Self::update_funding_instant`].
+    pub(crate) fn is_funding_due(&self, instant: types::StateInstant) -> bool {
+        self.next_funding_event_block.is_some_and(|bl| {
+            bl > self.funding_instant.block_number() && bl <= instant.block_number()
+        })
+    }
+
+    /// Brings the funding state up to the committed block, reporting the funding event
+    /// taking place at the block.
+    pub(crate) fn update_funding_instant(
         &mut self,
         instant: types::StateInstant,
     ) -> Vec<StateEvents> {
-        self.state_instant = instant;
+        self.funding_instant = instant;
         if let Some(payment) = self.next_funding_payment
             && self
                 .next_funding_event_block
@@ -355,7 +671,7 @@ impl Perpetual {
     }
 
     pub(crate) fn add_order(&mut self, order: Order) -> Result<(), DexError> {
-        self.l3_book.add_order(&order)?;
+        self.l3_book_mut().add_order(&order)?;
         Ok(())
     }
 
@@ -364,54 +680,86 @@ impl Perpetual {
     /// Uses the `prev_order_id`/`next_order_id` fields from the snapshot to determine
     /// the correct queue position within each price level.
     pub(crate) fn add_orders_from_snapshot(&mut self, orders: Vec<Order>) -> Result<(), DexError> {
-        self.l3_book.add_orders_from_snapshot(&orders)?;
+        self.l3_book_mut().add_orders_from_snapshot(&orders)?;
         Ok(())
     }
 
+    /// Updates the order on fills, keeping its modification instant.
     pub(crate) fn update_order(&mut self, order: Order) -> Result<(), DexError> {
-        let prev = self
+        self.replace_order(order, false)
+    }
+
+    /// Updates the order modified by its owner.
+    pub(crate) fn modify_order(&mut self, order: Order) -> Result<(), DexError> {
+        self.replace_order(order, true)
+    }
+
+    fn replace_order(&mut self, order: Order, modified: bool) -> Result<(), DexError> {
+        let (prev, placed_at, modified_at) = self
             .l3_book
-            .get_order_data(order.order_id())
-            .copied()
+            .get_order(order.order_id())
+            .map(|o| (*o.order(), o.placed_at(), o.modified_at()))
             .ok_or(DexError::OrderNotFound(self.id, order.order_id()))?;
 
+        let book = self.l3_book_mut();
         if prev.price() != order.price() {
             // Price changed: remove from old level, add to new level (back of queue)
-            self.l3_book.remove_order_by_id(order.order_id())?;
-            self.l3_book.add_order(&order)?;
+            book.remove_order_by_id(order.order_id())?;
+            book.add_order(&order)?;
         } else if order.size() > prev.size() {
             // Size INCREASED at same price: move to back of queue (loses priority)
-            self.l3_book.move_to_back(&order, &prev)?;
+            book.move_to_back(&order, &prev)?;
         } else if prev.expiry_block() > 0
             && prev.expiry_block() < order.instant().block_number()
             && prev.expiry_block() != order.expiry_block()
         {
             // Expired order got new expiry: move to back of queue (loses priority)
-            self.l3_book.move_to_back(&order, &prev)?;
+            book.move_to_back(&order, &prev)?;
         } else {
             // Size decreased or unchanged: keep queue position
-            self.l3_book.update_order(&order, &prev)?;
+            book.update_order(&order, &prev)?;
         }
+        let modified_at = if modified {
+            order.instant()
+        } else {
+            modified_at
+        };
+        book.set_order_times(order.order_id(), placed_at, modified_at);
         Ok(())
     }
 
     pub(crate) fn remove_order(&mut self, order_id: types::OrderId) -> Result<Order, DexError> {
-        self.l3_book
+        self.l3_book_mut()
             .remove_order_by_id(order_id)
             .map_err(|_| DexError::OrderNotFound(self.id, order_id))
     }
 
     pub(crate) fn update_paused(&mut self, instant: types::StateInstant, paused: bool) {
+        self.param_history.record(
+            instant,
+            PerpetualParam::Paused(self.is_paused),
+            PerpetualParam::Paused(paused),
+        );
         self.is_paused = paused;
         self.instant = instant;
     }
 
     pub(crate) fn update_maker_fee(&mut self, instant: types::StateInstant, maker_fee: UD64) {
+        self.param_history.record(
+            instant,
+            PerpetualParam::MakerFee(self.maker_fee),
+            PerpetualParam::MakerFee(maker_fee),
+        );
         self.maker_fee = maker_fee;
         self.instant = instant;
     }
 
     pub(crate) fn update_taker_fee(&mut self, instant: types::StateInstant, taker_fee: UD64) {
+        self.param_history.record(
+            instant,
+            PerpetualParam::TakerFee(self.taker_fee),
+            PerpetualParam::TakerFee(taker_fee),
+        );
         self.taker_fee = taker_fee;
         self.instant = instant;
     }
@@ -421,15 +769,49 @@ impl Perpetual {
         instant: types::StateInstant,
         initial_margin: UD64,
     ) {
+        self.param_history.record(
+            instant,
+            PerpetualParam::InitialMargin(self.initial_margin),
+            PerpetualParam::InitialMargin(initial_margin),
+        );
         self.initial_margin = initial_margin;
+        self.margin_tiers.clear();
         self.instant = instant;
     }
 
+    /// Sets the margin tiers from the contract initial margin fractions sampled at the sizes,
+    /// ordered by size and starting at zero, merging tiers with the same fraction.
+    pub(crate) fn set_margin_tiers(&mut self, samples: &[(U256, U256)]) {
+        let mut tiers: Vec<MarginTier> = vec![];
+        for (lots, fraction) in samples {
+            let initial_margin = self.leverage_converter.from_unsigned(*fraction);
+            if tiers
+                .last()
+                .is_none_or(|t| t.initial_margin != initial_margin)
+            {
+                tiers.push(MarginTier {
+                    min_size: self.size_converter.from_unsigned(*lots),
+                    initial_margin,
+                });
+            }
+        }
+        // Single tier means the fraction does not depend on the size
+        if tiers.len() < 2 {
+            tiers.clear();
+        }
+        self.margin_tiers = tiers;
+    }
+
     pub(crate) fn update_maintenance_margin(
         &mut self,
         instant: types::StateInstant,
         maintenance_margin: UD64,
     ) {
+        self.param_history.record(
+            instant,
+            PerpetualParam::MaintenanceMargin(self.maintenance_margin),
+            PerpetualParam::MaintenanceMargin(maintenance_margin),
+        );
         self.maintenance_margin = maintenance_margin;
         self.instant = instant;
     }
@@ -455,6 +837,13 @@ impl Perpetual {
         self.instant = instant;
     }
 
+    /// Returns `true` if the price differs from the current one.
+    pub(crate) fn update_index_price(&mut self, index_price: IndexPrice) -> bool {
+        let updated = self.index_price != Some(index_price);
+        self.index_price = Some(index_price);
+        updated
+    }
+
     pub(crate) fn update_funding(
         &mut self,
         instant: types::StateInstant,
@@ -473,6 +862,12 @@ impl Perpetual {
         self.next_funding_rate = Some(funding_rate);
         self.next_funding_payment = Some(funding_payment);
         self.next_funding_event_block = Some(block_num);
+        self.funding_history.record(FundingRecord {
+            instant,
+            block_number: block_num,
+            rate: funding_rate,
+            payment_per_unit: funding_payment,
+        });
         self.instant = instant;
     }
 
@@ -481,6 +876,11 @@ impl Perpetual {
         instant: types::StateInstant,
         oracle_feed_id: B256,
     ) {
+        self.param_history.record(
+            instant,
+            PerpetualParam::OracleFeedId(self.oracle_feed_id),
+            PerpetualParam::OracleFeedId(oracle_feed_id),
+        );
         self.oracle_feed_id = oracle_feed_id;
         self.instant = instant;
     }
@@ -490,6 +890,11 @@ impl Perpetual {
         instant: types::StateInstant,
         is_oracle_used: bool,
     ) {
+        self.param_history.record(
+            instant,
+            PerpetualParam::OracleUsed(self.is_oracle_used),
+            PerpetualParam::OracleUsed(is_oracle_used),
+        );
         self.is_oracle_used = is_oracle_used;
         self.instant = instant;
     }
@@ -499,10 +904,59 @@ impl Perpetual {
         instant: types::StateInstant,
         price_max_age_sec: u64,
     ) {
+        self.param_history.record(
+            instant,
+            PerpetualParam::PriceMaxAge(self.price_max_age_sec),
+            PerpetualParam::PriceMaxAge(price_max_age_sec),
+        );
         self.price_max_age_sec = price_max_age_sec;
         self.instant = instant;
     }
 
+    pub(crate) fn update_price_tolerance(&mut self, instant: types::StateInstant, tolerance: UD64) {
+        self.param_history.record(
+            instant,
+            PerpetualParam::PriceTolerance(self.price_tolerance),
+            PerpetualParam::PriceTolerance(tolerance),
+        );
+        self.price_tolerance = tolerance;
+        self.instant = instant;
+    }
+
+    pub(crate) fn record_tolerance_breach(&mut self, breach: ToleranceBreach) {
+        self.last_tolerance_breach = Some(breach);
+        self.instant = breach.instant;
+    }
+
+    pub(crate) fn record_deleverage_failure(&mut self, failure: DeleverageFailure) {
+        self.last_deleverage_failure = Some(failure);
+        self.instant = failure.instant;
+    }
+
+    pub(crate) fn set_insurance_balance(&mut self, instant: types::StateInstant, balance: UD128) {
+        self.insurance_balance = balance;
+        self.instant = instant;
+    }
+
+    pub(crate) fn set_collateral_converter(&mut self, collateral_converter: num::Converter) {
+        self.collateral_converter = collateral_converter;
+    }
+
+    pub(crate) fn set_param_history_retention(&mut self, retention: usize) {
+        self.param_history.set_retention(retention);
+    }
+
+    pub(crate) fn set_funding_history_retention(&mut self, retention: usize) {
+        self.funding_history.set_retention(retention);
+    }
+
+    /// Drops parameter and funding history recorded before the block,
+    /// returning the number of records dropped.
+    pub(crate) fn prune_history(&mut self, block_number: u64) -> usize {
+        self.param_history.prune_before(block_number)
+            + self.funding_history.prune_before(block_number)
+    }
+
     pub(crate) fn update_open_interest(
         &mut self,
         instant: types::StateInstant,
@@ -515,11 +969,10 @@ impl Perpetual {
     }
 
     /// Create a minimal Perpetual for testing purposes.
-    #[cfg(test)]
     pub(crate) fn for_testing(id: types::PerpetualId) -> Self {
         Self {
             instant: types::StateInstant::new(0, 0),
-            state_instant: types::StateInstant::new(0, 0),
+            funding_instant: types::StateInstant::new(0, 0),
             id,
             name: "TEST".to_string(),
             symbol: "TEST".to_string(),
@@ -529,10 +982,12 @@ impl Perpetual {
             leverage_converter: num::Converter::new(2),
             fee_converter: num::Converter::new(5),
             funding_rate_converter: num::Converter::new(5),
+            collateral_converter: num::Converter::new(6),
             base_price: UD64::ZERO,
             maker_fee: UD64::ZERO,
             taker_fee: UD64::ZERO,
             initial_margin: UD64::ZERO,
+            margin_tiers: vec![],
             maintenance_margin: UD64::ZERO,
             last_price: UD64::ZERO,
             last_price_block: None,
@@ -543,6 +998,7 @@ impl Perpetual {
             oracle_price: UD64::ZERO,
             oracle_price_block: None,
             oracle_price_timestamp: 0,
+            index_price: None,
             prev_funding_rate: D64::ZERO,
             next_funding_rate: None,
             next_funding_payment: None,
@@ -551,16 +1007,44 @@ impl Perpetual {
             oracle_feed_id: B256::ZERO,
             is_oracle_used: false,
             price_max_age_sec: 0,
-            l3_book: OrderBook::new(),
+            price_tolerance: UD64::ZERO,
+            last_tolerance_breach: None,
+            last_deleverage_failure: None,
+            l3_book: Arc::new(OrderBook::new()),
             open_interest: UD128::ZERO,
+            insurance_balance: UD128::ZERO,
+            param_history: ParamHistory::default(),
+            funding_history: FundingHistory::default(),
         }
     }
 }
 
+/// Size-averaged price of the levels, taken in order until the notional is reached.
+fn depth_weighted_price(
+    levels: impl Iterator<Item = (UD64, UD64)>,
+    depth_notional: UD128,
+) -> Option<UD64> {
+    let mut notional = UD128::ZERO;
+    let mut size = UD128::ZERO;
+    for (price, level_size) in levels {
+        let remaining = depth_notional - notional;
+        let level_notional = price.resize() * level_size.resize();
+        if level_notional < remaining {
+            notional += level_notional;
+            size += level_size.resize();
+        } else {
+            notional += remaining;
+            size += remaining / price.resize();
+            break;
+        }
+    }
+    (size > UD128::ZERO).then(|| (notional / size).resize())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use fastnum::udec64;
+    use fastnum::{udec64, udec128};
     use std::num::NonZeroU16;
 
     fn oid(n: u16) -> types::OrderId {
@@ -677,4 +1161,217 @@ mod tests {
             "Non-expired order should keep position"
         );
     }
+
+    #[test]
+    fn estimated_time_to_fill_counts_better_levels_and_queue() {
+        let mut perp = Perpetual::for_testing(1);
+        perp.add_order(Order::for_l3_testing(
+            types::OrderType::OpenShort,
+            udec64!(99),
+            udec64!(2.0),
+            50,
+            oid(1),
+            101,
+        ))
+        .unwrap();
+        perp.add_order(Order::for_l3_testing(
+            types::OrderType::OpenShort,
+            udec64!(100),
+            udec64!(3.0),
+            50,
+            oid(2),
+            102,
+        ))
+        .unwrap();
+        perp.add_order(Order::for_l3_testing(
+            types::OrderType::OpenShort,
+            udec64!(100),
+            udec64!(5.0),
+            51,
+            oid(3),
+            103,
+        ))
+        .unwrap();
+
+        // 2.0 at better level + 3.0 ahead + 5.0 own size at 2.0 per second
+        assert_eq!(
+            perp.estimated_time_to_fill(oid(3), udec64!(2.0)),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(perp.estimated_time_to_fill(oid(3), UD64::ZERO), None);
+        assert_eq!(
+            perp.estimated_time_to_fill(oid(3), udec64!(0.0000000000000000001)),
+            None
+        );
+        assert_eq!(perp.estimated_time_to_fill(oid(4), udec64!(2.0)), None);
+    }
+
+    #[test]
+    fn increments_and_rounding() {
+        let mut perp = Perpetual::for_testing(1);
+        perp.price_converter = num::Converter::new(1);
+        perp.size_converter = num::Converter::new(3);
+
+        assert_eq!(perp.tick_size(), udec64!(0.1));
+        assert_eq!(perp.lot_size(), udec64!(0.001));
+
+        assert_eq!(
+            perp.round_price(udec64!(100.27), types::OrderSide::Bid),
+            udec64!(100.2)
+        );
+        assert_eq!(
+            perp.round_price(udec64!(100.21), types::OrderSide::Ask),
+            udec64!(100.3)
+        );
+        assert_eq!(
+            perp.round_price(udec64!(100.2), types::OrderSide::Ask),
+            udec64!(100.2)
+        );
+        assert_eq!(perp.round_size(udec64!(1.2349)), udec64!(1.234));
+
+        // 10 / 3000 = 0.00333.. rounded up to the lot
+        assert_eq!(
+            perp.min_order_size(udec128!(10), udec64!(3000)),
+            udec64!(0.004)
+        );
+        assert_eq!(
+            perp.min_order_size(udec128!(10), udec64!(2000)),
+            udec64!(0.005)
+        );
+        assert_eq!(
+            perp.min_order_size(UD128::ZERO, udec64!(2000)),
+            udec64!(0.001)
+        );
+        assert_eq!(
+            perp.min_order_size(udec128!(10), UD64::ZERO),
+            udec64!(0.001)
+        );
+    }
+
+    #[test]
+    fn param_history_retention() {
+        let mut perp = Perpetual::for_testing(1);
+        perp.set_param_history_retention(2);
+        let at = |block| types::StateInstant::new(block, block);
+
+        perp.update_maker_fee(at(1), udec64!(0.0001));
+        // Unchanged value is not recorded
+        perp.update_paused(at(2), false);
+        perp.update_paused(at(3), true);
+        perp.update_maker_fee(at(4), udec64!(0.0002));
+
+        let history = perp.param_history().collect::<Vec<_>>();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].instant, at(3));
+        assert_eq!(history[0].previous, PerpetualParam::Paused(false));
+        assert_eq!(history[0].current, PerpetualParam::Paused(true));
+        assert_eq!(
+            history[1].previous,
+            PerpetualParam::MakerFee(udec64!(0.0001))
+        );
+        assert_eq!(
+            history[1].current,
+            PerpetualParam::MakerFee(udec64!(0.0002))
+        );
+    }
+
+    #[test]
+    fn margin_tiers_by_size() {
+        let mut perp = Perpetual::for_testing(1);
+        perp.update_initial_margin(types::StateInstant::default(), udec64!(10));
+        perp.set_margin_tiers(&[(U256::from(0), U256::from(1000))]);
+        assert!(perp.margin_tiers().is_empty());
+        assert_eq!(perp.initial_margin_for_size(udec64!(100)), udec64!(10));
+
+        perp.set_margin_tiers(&[
+            (U256::from(0), U256::from(1000)),
+            (U256::from(50), U256::from(1000)),
+            (U256::from(100), U256::from(500)),
+            (U256::from(150), U256::from(250)),
+        ]);
+        assert_eq!(
+            perp.margin_tiers(),
+            &[
+                MarginTier {
+                    min_size: udec64!(0),
+                    initial_margin: udec64!(10)
+                },
+                MarginTier {
+                    min_size: udec64!(100),
+                    initial_margin: udec64!(5)
+                },
+                MarginTier {
+                    min_size: udec64!(150),
+                    initial_margin: udec64!(2.5)
+                },
+            ]
+        );
+        assert_eq!(perp.initial_margin_for_size(udec64!(99)), udec64!(10));
+        assert_eq!(perp.initial_margin_for_size(udec64!(100)), udec64!(5));
+        assert_eq!(perp.initial_margin_for_size(udec64!(1000)), udec64!(2.5));
+
+        perp.update_initial_margin(types::StateInstant::default(), udec64!(20));
+        assert!(perp.margin_tiers().is_empty());
+        assert_eq!(perp.initial_margin_for_size(udec64!(1000)), udec64!(20));
+    }
+
+    #[test]
+    fn fair_price_weighs_depth() {
+        let mut perp = Perpetual::for_testing(1);
+        assert_eq!(perp.fair_price(udec128!(1000)), None);
+
+        let orders = [
+            (types::OrderType::OpenLong, udec64!(100), udec64!(1)),
+            (types::OrderType::OpenLong, udec64!(80), udec64!(1)),
+            (types::OrderType::OpenShort, udec64!(110), udec64!(1)),
+            (types::OrderType::OpenShort, udec64!(140), udec64!(2)),
+        ];
+        for (i, (r#type, price, size)) in orders.into_iter().enumerate() {
+            perp.add_order(Order::for_l3_testing(
+                r#type,
+                price,
+                size,
+                1,
+                oid(i as u16 + 1),
+                101,
+            ))
+            .unwrap();
+        }
+
+        // Within the best levels
+        assert_eq!(perp.fair_price(udec128!(50)), Some(udec64!(105)));
+        // Bids: 100 + 80 -> 90, asks: 110 + 70 at 140 -> 120
+        assert_eq!(perp.fair_price(udec128!(180)), Some(udec64!(105)));
+        // Both sides exhausted, asks: 110 + 280 -> 130
+        assert_eq!(perp.fair_price(udec128!(1000)), Some(udec64!(110)));
+        assert_eq!(perp.fair_price(UD128::ZERO), None);
+
+        perp.remove_order(oid(1)).unwrap();
+        perp.remove_order(oid(2)).unwrap();
+        assert_eq!(perp.fair_price(udec128!(180)), None);
+    }
+
+    #[test]
+    fn funding_history_from_events() {
+        let mut perp = Perpetual::for_testing(1);
+        perp.update_funding(
+            types::StateInstant::new(10, 1_000),
+            D64::from(1) / D64::from(10_000),
+            D256::ONE,
+            12,
+        );
+        let instant = types::StateInstant::new(20, 1_010);
+        assert!(perp.is_funding_due(instant));
+        perp.update_funding_instant(instant);
+
+        let history = perp.funding_history(instant, Duration::from_secs(60));
+        assert_eq!(history.len(), 1);
+        let record = history.records().next().unwrap();
+        assert_eq!(record.block_number, 12);
+        assert_eq!(record.payment_per_unit, D256::ONE);
+        assert!(
+            perp.funding_history(instant, Duration::from_secs(5))
+                .is_empty()
+        );
+    }
 }
@@ -0,0 +1,78 @@
+//! Time series of position PnL.
+
+use std::collections::VecDeque;
+
+use fastnum::D256;
+
+use crate::types;
+
+/// Unrealized PnL of the position as of the end of the block,
+/// see [`super::Position::pnl_history`].
+#[derive(Clone, Copy, PartialEq, Eq, derive_more::Debug)]
+pub struct PnlPoint {
+    /// Instant of the block the PnL was updated in.
+    pub instant: types::StateInstant,
+
+    /// Unrealized PnL, the sum of delta and premium PnL.
+    #[debug("{pnl}")]
+    pub pnl: D256,
+
+    /// Unrealized delta PnL.
+    #[debug("{delta_pnl}")]
+    pub delta_pnl: D256,
+
+    /// Unrealized premium PnL.
+    #[debug("{premium_pnl}")]
+    pub premium_pnl: D256,
+}
+
+/// Most recent PnL points, one per block, bounded by the retention.
+/// Recording is disabled with zero retention.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PnlHistory {
+    retention: usize,
+    points: VecDeque<PnlPoint>,
+}
+
+impl PnlHistory {
+    pub(crate) fn points(&self) -> &VecDeque<PnlPoint> {
+        &self.points
+    }
+
+    /// Records the point, replacing the previous one of the same block.
+    pub(crate) fn record(&mut self, point: PnlPoint) {
+        if self.retention == 0 {
+            return;
+        }
+        if let Some(last) = self.points.back_mut()
+            && last.instant.block_number() == point.instant.block_number()
+        {
+            *last = point;
+            return;
+        }
+        if self.points.len() == self.retention {
+            self.points.pop_front();
+        }
+        self.points.push_back(point);
+    }
+
+    pub(crate) fn set_retention(&mut self, retention: usize) {
+        self.retention = retention;
+        while self.points.len() > retention {
+            self.points.pop_front();
+        }
+    }
+
+    /// Drops points recorded before the block, returning the number dropped.
+    pub(crate) fn prune_before(&mut self, block_number: u64) -> usize {
+        let len = self.points.len();
+        while self
+            .points
+            .front()
+            .is_some_and(|r| r.instant.block_number() < block_number)
+        {
+            self.points.pop_front();
+        }
+        len - self.points.len()
+    }
+}
@@ -0,0 +1,348 @@
+//! Margin summary aggregated across multiple accounts.
+
+use std::collections::{HashMap, HashSet};
+
+use fastnum::{D64, D256, UD64, UD128};
+
+use super::{
+    Account, Exchange, PerpetualEvent, PerpetualEventType, PositionType, StateBlockEvents,
+    StateEvents,
+};
+use crate::types;
+
+/// Exposure of a single account position.
+#[derive(Clone, Copy, derive_more::Debug)]
+pub struct Exposure {
+    /// Type of the position.
+    pub r#type: PositionType,
+
+    /// Size of the position.
+    #[debug("{size}")]
+    pub size: UD64,
+
+    /// Notional value of the position at mark price, or at entry price
+    /// if the mark price is not known.
+    #[debug("{notional}")]
+    pub notional: UD128,
+
+    /// Unrealized PnL of the position.
+    #[debug("{pnl}")]
+    pub pnl: D256,
+}
+
+/// Margin summary of a single account.
+#[derive(Clone, derive_more::Debug)]
+pub struct AccountSummary {
+    /// ID of the account.
+    pub account_id: types::AccountId,
+
+    /// Collateral balance, not including positions.
+    #[debug("{balance}")]
+    pub balance: UD128,
+
+    /// Collateral locked by orders.
+    #[debug("{locked_balance}")]
+    pub locked_balance: UD128,
+
+    /// Balance plus deposits and unrealized PnL of all positions.
+    #[debug("{equity}")]
+    pub equity: D256,
+
+    /// Total notional value of all positions.
+    #[debug("{notional}")]
+    pub notional: UD128,
+
+    /// Total unrealized PnL of all positions.
+    #[debug("{unrealized_pnl}")]
+    pub unrealized_pnl: D256,
+
+    /// Total maintenance margin requirement of all positions.
+    #[debug("{maintenance_margin}")]
+    pub maintenance_margin: UD128,
+
+    /// Exposure per perpetual contract the account has position in.
+    pub exposures: HashMap<types::PerpetualId, Exposure>,
+}
+
+impl AccountSummary {
+    fn new(exchange: &Exchange, account: &Account) -> Self {
+        let mut summary = Self {
+            account_id: account.id(),
+            balance: account.balance(),
+            locked_balance: account.locked_balance(),
+            equity: account.balance().to_signed().resize(),
+            notional: UD128::ZERO,
+            unrealized_pnl: D256::ZERO,
+            maintenance_margin: UD128::ZERO,
+            exposures: HashMap::new(),
+        };
+        for (perp_id, pos) in account.positions() {
+            let price = exchange
+                .perpetuals()
+                .get(perp_id)
+                .map(|perp| perp.mark_price())
+                .filter(|price| *price > UD64::ZERO)
+                .unwrap_or(pos.entry_price());
+            let exposure = Exposure {
+                r#type: pos.r#type(),
+                size: pos.size(),
+                notional: price.resize() * pos.size().resize(),
+                pnl: pos.pnl(),
+            };
+            summary.equity += pos.deposit().to_signed().resize() + pos.pnl();
+            summary.notional += exposure.notional;
+            summary.unrealized_pnl += exposure.pnl;
+            summary.maintenance_margin += pos.maintenance_margin_requirement();
+            summary.exposures.insert(*perp_id, exposure);
+        }
+        summary
+    }
+}
+
+/// Netting statistics of positions in a single perpetual contract across accounts.
+#[derive(Clone, Copy, Default, derive_more::Debug)]
+pub struct PerpetualNetting {
+    /// Total size of long positions.
+    #[debug("{long_size}")]
+    pub long_size: UD64,
+
+    /// Total size of short positions.
+    #[debug("{short_size}")]
+    pub short_size: UD64,
+
+    /// Total notional value of long and short positions.
+    #[debug("{gross_notional}")]
+    pub gross_notional: UD128,
+
+    /// Notional value of long positions minus notional value of short positions.
+    #[debug("{net_notional}")]
+    pub net_notional: D256,
+}
+
+impl PerpetualNetting {
+    /// Size of long positions minus size of short positions.
+    pub fn net_size(&self) -> D64 {
+        self.long_size.to_signed() - self.short_size.to_signed()
+    }
+}
+
+/// Margin summary aggregated across accounts of [`MultiAccountView`].
+#[derive(Clone, derive_more::Debug)]
+pub struct PortfolioSummary {
+    /// Total equity of all accounts.
+    #[debug("{equity}")]
+    pub equity: D256,
+
+    /// Total notional value of all positions.
+    #[debug("{notional}")]
+    pub notional: UD128,
+
+    /// Total unrealized PnL of all positions.
+    #[debug("{unrealized_pnl}")]
+    pub unrealized_pnl: D256,
+
+    /// Total maintenance margin requirement of all positions.
+    #[debug("{maintenance_margin}")]
+    pub maintenance_margin: UD128,
+
+    /// Netting statistics per perpetual contract.
+    pub perpetuals: HashMap<types::PerpetualId, PerpetualNetting>,
+}
+
+impl PortfolioSummary {
+    /// Share of the equity consumed by the maintenance margin requirement,
+    /// `None` if the equity is not positive.
+    pub fn margin_usage(&self) -> Option<UD64> {
+        (self.equity > D256::ZERO).then(|| {
+            (self.maintenance_margin.to_signed().resize() / self.equity)
+                .unsigned_abs()
+                .resize()
+        })
+    }
+}
+
+/// Margin summary of a set of tracked accounts, e.g. sub-accounts per strategy.
+///
+/// Per-account summaries are recomputed only for accounts affected by state events
+/// passed to [`Self::apply`], aggregation is done on request with [`Self::summary`].
+#[derive(Clone, Debug)]
+pub struct MultiAccountView {
+    accounts: HashMap<types::AccountId, Option<AccountSummary>>,
+}
+
+impl MultiAccountView {
+    /// Create view of the given accounts, accounts not tracked by the exchange
+    /// are included once they appear in the exchange state.
+    pub fn new(
+        exchange: &Exchange,
+        account_ids: impl IntoIterator<Item = types::AccountId>,
+    ) -> Self {
+        let mut view = Self {
+            accounts: HashMap::new(),
+        };
+        for id in account_ids {
+            view.add_account(exchange, id);
+        }
+        view
+    }
+
+    /// Add the account to the view.
+    pub fn add_account(&mut self, exchange: &Exchange, account_id: types::AccountId) {
+        self.accounts.insert(
+            account_id,
+            exchange
+                .accounts()
+                .get(&account_id)
+                .map(|acc| AccountSummary::new(exchange, acc)),
+        );
+    }
+
+    /// Remove the account from the view.
+    pub fn remove_account(&mut self, account_id: types::AccountId) {
+        self.accounts.remove(&account_id);
+    }
+
+    /// IDs of accounts in the view.
+    pub fn account_ids(&self) -> impl Iterator<Item = types::AccountId> + '_ {
+        self.accounts.keys().copied()
+    }
+
+    /// Summary of the account, if it is in the view and tracked by the exchange.
+    pub fn account(&self, account_id: types::AccountId) -> Option<&AccountSummary> {
+        self.accounts.get(&account_id)?.as_ref()
+    }
+
+    /// Refreshes summaries of accounts affected by the events, which must be
+    /// the result of the latest [`Exchange::apply_events`] call.
+    ///
+    /// Returns the number of refreshed accounts.
+    pub fn apply(&mut self, exchange: &Exchange, events: &StateBlockEvents) -> usize {
+        let mut affected = HashSet::new();
+        let mut repriced = HashSet::new();
+        for (_, event) in events.flat_events() {
+            match event {
+                StateEvents::Account(e) => {
+                    affected.insert(e.account_id);
+                }
+                StateEvents::Position(e) => {
+                    affected.insert(e.account_id);
+                }
+                StateEvents::Perpetual(PerpetualEvent {
+                    perpetual_id,
+                    r#type:
+                        PerpetualEventType::MarkPriceUpdated(_)
+                        | PerpetualEventType::FundingEvent { .. }
+                        | PerpetualEventType::MaintenanceMarginFractionUpdated(_),
+                }) => {
+                    repriced.insert(*perpetual_id);
+                }
+                _ => {}
+            }
+        }
+        for (id, summary) in &self.accounts {
+            if summary
+                .as_ref()
+                .is_some_and(|s| s.exposures.keys().any(|perp_id| repriced.contains(perp_id)))
+            {
+                affected.insert(*id);
+            }
+        }
+
+        let mut refreshed = 0;
+        for id in affected {
+            if let Some(summary) = self.accounts.get_mut(&id) {
+                *summary = exchange
+                    .accounts()
+                    .get(&id)
+                    .map(|acc| AccountSummary::new(exchange, acc));
+                refreshed += 1;
+            }
+        }
+        refreshed
+    }
+
+    /// Aggregated summary of all accounts in the view.
+    pub fn summary(&self) -> PortfolioSummary {
+        let mut summary = PortfolioSummary {
+            equity: D256::ZERO,
+            notional: UD128::ZERO,
+            unrealized_pnl: D256::ZERO,
+            maintenance_margin: UD128::ZERO,
+            perpetuals: HashMap::new(),
+        };
+        for acc in self.accounts.values().flatten() {
+            summary.equity += acc.equity;
+            summary.notional += acc.notional;
+            summary.unrealized_pnl += acc.unrealized_pnl;
+            summary.maintenance_margin += acc.maintenance_margin;
+            for (perp_id, exposure) in &acc.exposures {
+                let netting: &mut PerpetualNetting =
+                    summary.perpetuals.entry(*perp_id).or_default();
+                netting.gross_notional += exposure.notional;
+                match exposure.r#type {
+                    PositionType::Long => {
+                        netting.long_size += exposure.size;
+                        netting.net_notional += exposure.notional.to_signed().resize();
+                    }
+                    PositionType::Short => {
+                        netting.short_size += exposure.size;
+                        netting.net_notional -= exposure.notional.to_signed().resize();
+                    }
+                }
+            }
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fastnum::{dec64, dec256, udec128};
+
+    use super::*;
+    use crate::testing::fuzz::{Action, Simulator};
+
+    fn open(account: usize, is_long: bool, lots: u64) -> Action {
+        Action::Open {
+            perpetual: 0,
+            account,
+            is_long,
+            lots,
+        }
+    }
+
+    #[test]
+    fn aggregates_and_refreshes() {
+        let mut sim = Simulator::new(&[1], 3);
+        let mut exchange = sim.exchange();
+        let mut view = MultiAccountView::new(&exchange, [1, 2]);
+
+        let events = exchange
+            .apply_events(&sim.block(&[open(0, true, 5), open(1, false, 3), open(2, true, 7)]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(view.apply(&exchange, &events), 2);
+
+        let summary = view.summary();
+        // Entry price 1000 as mark price is not known
+        assert_eq!(summary.notional, udec128!(8000));
+        assert_eq!(summary.equity, dec256!(2000008000));
+        assert_eq!(summary.maintenance_margin, udec128!(400));
+        let netting = summary.perpetuals[&1];
+        assert_eq!(netting.net_size(), dec64!(2));
+        assert_eq!(netting.net_notional, dec256!(2000));
+        assert_eq!(netting.gross_notional, udec128!(8000));
+        assert_eq!(
+            view.account(2).unwrap().exposures[&1].size,
+            fastnum::udec64!(3)
+        );
+
+        let events = exchange
+            .apply_events(&sim.block(&[Action::Close { position: 0 }]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(view.apply(&exchange, &events), 1);
+        assert!(view.account(1).unwrap().exposures.is_empty());
+        assert_eq!(view.summary().perpetuals[&1].net_size(), dec64!(-3));
+    }
+}
@@ -1,6 +1,9 @@
 use fastnum::{D64, D256, UD64, UD128};
 
-use super::num;
+use super::{
+    num,
+    pnl_history::{PnlHistory, PnlPoint},
+};
 use crate::{abi::dex::Exchange::PositionInfo, types};
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -29,6 +32,8 @@ pub struct Position {
     premium_pnl: D256, // SC calculations and ABI use 256 bits
     #[debug("{maintenance_margin_requirement}")]
     maintenance_margin_requirement: UD128,
+    #[debug(skip)]
+    pnl_history: PnlHistory,
 }
 
 impl Position {
@@ -56,6 +61,7 @@ impl Position {
             premium_pnl: collateral_converter.from_signed(info.premiumPnlCNS),
             maintenance_margin_requirement: entry_price.resize() * size.resize()
                 / maintenance_margin.resize(),
+            pnl_history: PnlHistory::default(),
         }
     }
 
@@ -83,6 +89,7 @@ impl Position {
             premium_pnl: D256::ZERO,
             maintenance_margin_requirement: entry_price.resize() * size.resize()
                 / maintenance_margin.resize(),
+            pnl_history: PnlHistory::default(),
         }
     }
 
@@ -141,6 +148,26 @@ impl Position {
         self.maintenance_margin_requirement
     }
 
+    /// Unrealized PnL of the position at the end of each block it changed in, oldest first,
+    /// bounded by the retention, see [`super::SnapshotBuilder::with_pnl_history_retention`].
+    ///
+    /// Empty unless the history is enabled.
+    pub fn pnl_history(&self) -> impl DoubleEndedIterator<Item = &PnlPoint> {
+        self.pnl_history.points().iter()
+    }
+
+    /// Largest decline of the unrealized PnL from its preceding peak within the history.
+    pub fn max_drawdown(&self) -> D256 {
+        let mut peak = None::<D256>;
+        let mut drawdown = D256::ZERO;
+        for point in self.pnl_history() {
+            let peak = peak.get_or_insert(point.pnl);
+            *peak = (*peak).max(point.pnl);
+            drawdown = drawdown.max(*peak - point.pnl);
+        }
+        drawdown
+    }
+
     /// Liquidation price of the position.
     pub fn liquidation_price(&self) -> UD64 {
         let side = if self.r#type.is_long() {
@@ -195,6 +222,7 @@ impl Position {
         self.premium_pnl = premium_pnl;
         self.instant = instant;
         self.funding_instant = instant;
+        self.record_pnl();
     }
 
     pub(crate) fn apply_mark_price(&mut self, instant: types::StateInstant, mark_price: UD64) {
@@ -207,6 +235,7 @@ impl Position {
             * (mark_price.resize().to_signed() - self.entry_price.resize().to_signed())
             * self.size.resize().to_signed();
         self.instant = instant;
+        self.record_pnl();
     }
 
     pub(crate) fn apply_funding_payment(
@@ -228,6 +257,7 @@ impl Position {
         self.premium_pnl += sign * payment_per_unit * self.size.resize().to_signed();
         self.instant = instant;
         self.funding_instant = instant;
+        self.record_pnl();
         true
     }
 
@@ -240,6 +270,26 @@ impl Position {
             self.entry_price.resize() * self.size.resize() / maintenance_margin.resize();
         self.instant = instant;
     }
+
+    /// Sets the number of PnL points kept, recording the current PnL if enabled.
+    pub(crate) fn set_pnl_history_retention(&mut self, retention: usize) {
+        self.pnl_history.set_retention(retention);
+        self.record_pnl();
+    }
+
+    /// Drops PnL points recorded before the block, returning the number dropped.
+    pub(crate) fn prune_pnl_history(&mut self, block_number: u64) -> usize {
+        self.pnl_history.prune_before(block_number)
+    }
+
+    fn record_pnl(&mut self) {
+        self.pnl_history.record(PnlPoint {
+            instant: self.instant,
+            pnl: self.pnl(),
+            delta_pnl: self.delta_pnl,
+            premium_pnl: self.premium_pnl,
+        });
+    }
 }
 
 impl PositionType {
@@ -473,4 +523,42 @@ mod tests {
         assert!(pos.apply_funding_payment(i1, dec256!(-5)));
         assert_eq!(pos.bankruptcy_price(), udec64!(105));
     }
+
+    #[test]
+    fn test_pnl_history() {
+        let instant = |n| StateInstant::new(n, n);
+        let mut pos = Position::opened(
+            instant(1),
+            1,
+            1,
+            PositionType::Long,
+            udec64!(100),
+            udec64!(10),
+            udec128!(100),
+            udec64!(20),
+        );
+        // Disabled by default
+        pos.apply_mark_price(instant(1), udec64!(110));
+        assert_eq!(pos.pnl_history().count(), 0);
+
+        pos.set_pnl_history_retention(3);
+        pos.apply_mark_price(instant(2), udec64!(90));
+        // Latest update of the block wins
+        pos.apply_mark_price(instant(2), udec64!(95));
+        assert!(pos.apply_funding_payment(instant(3), dec256!(1)));
+        let pnl = pos.pnl_history().map(|p| p.pnl).collect::<Vec<_>>();
+        assert_eq!(pnl, vec![dec256!(100), dec256!(-50), dec256!(-60)]);
+        let last = pos.pnl_history().last().unwrap();
+        assert_eq!(
+            (last.delta_pnl, last.premium_pnl),
+            (dec256!(-50), dec256!(-10))
+        );
+        assert_eq!(pos.max_drawdown(), dec256!(160));
+
+        // Oldest point falls out of the retention
+        pos.apply_mark_price(instant(4), udec64!(120));
+        assert_eq!(pos.pnl_history().count(), 3);
+        assert_eq!(pos.pnl_history().next().unwrap().instant, instant(2));
+        assert_eq!(pos.max_drawdown(), dec256!(10));
+    }
 }
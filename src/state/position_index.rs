@@ -0,0 +1,162 @@
+//! Positions of tracked accounts indexed by perpetual contract.
+
+use std::collections::{BTreeMap, HashMap};
+
+use fastnum::{UD64, UD128};
+
+use super::{Account, Position, PositionType};
+use crate::types;
+
+/// Aggregate statistics of tracked positions in the perpetual contract,
+/// see [`super::Exchange::positions_by_perpetual`].
+#[derive(Clone, Copy, Default, PartialEq, Eq, derive_more::Debug)]
+pub struct PositionAggregates {
+    /// Number of positions.
+    pub positions: usize,
+
+    /// Total size of long positions.
+    #[debug("{long_size}")]
+    pub long_size: UD128,
+
+    /// Total size of short positions.
+    #[debug("{short_size}")]
+    pub short_size: UD128,
+
+    /// Total notional value of long positions at their entry prices.
+    #[debug("{long_entry_notional}")]
+    pub long_entry_notional: UD128,
+
+    /// Total notional value of short positions at their entry prices.
+    #[debug("{short_entry_notional}")]
+    pub short_entry_notional: UD128,
+
+    /// Total collateral deposited to the positions.
+    #[debug("{total_deposit}")]
+    pub total_deposit: UD128,
+}
+
+impl PositionAggregates {
+    /// Size-weighted average entry price of long positions, if any.
+    pub fn long_entry_price(&self) -> Option<UD64> {
+        (self.long_size > UD128::ZERO).then(|| (self.long_entry_notional / self.long_size).resize())
+    }
+
+    /// Size-weighted average entry price of short positions, if any.
+    pub fn short_entry_price(&self) -> Option<UD64> {
+        (self.short_size > UD128::ZERO)
+            .then(|| (self.short_entry_notional / self.short_size).resize())
+    }
+
+    fn add(&mut self, c: &Contribution) {
+        self.positions += 1;
+        let notional = c.entry_price.resize() * c.size.resize();
+        match c.r#type {
+            PositionType::Long => {
+                self.long_size += c.size.resize();
+                self.long_entry_notional += notional;
+            }
+            PositionType::Short => {
+                self.short_size += c.size.resize();
+                self.short_entry_notional += notional;
+            }
+        }
+        self.total_deposit += c.deposit;
+    }
+
+    fn remove(&mut self, c: &Contribution) {
+        self.positions -= 1;
+        let notional = c.entry_price.resize() * c.size.resize();
+        match c.r#type {
+            PositionType::Long => {
+                self.long_size -= c.size.resize();
+                self.long_entry_notional -= notional;
+            }
+            PositionType::Short => {
+                self.short_size -= c.size.resize();
+                self.short_entry_notional -= notional;
+            }
+        }
+        self.total_deposit -= c.deposit;
+    }
+}
+
+/// Position parameters accounted in the aggregates.
+#[derive(Clone, Copy, Debug)]
+struct Contribution {
+    r#type: PositionType,
+    size: UD64,
+    entry_price: UD64,
+    deposit: UD128,
+}
+
+impl From<&Position> for Contribution {
+    fn from(pos: &Position) -> Self {
+        Self {
+            r#type: pos.r#type(),
+            size: pos.size(),
+            entry_price: pos.entry_price(),
+            deposit: pos.deposit(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct PerpetualPositions {
+    aggregates: PositionAggregates,
+    contributions: BTreeMap<types::AccountId, Contribution>,
+}
+
+/// Accounts holding positions per perpetual contract along with the aggregates,
+/// updated for each position touched by the applied events.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PositionIndex {
+    perpetuals: HashMap<types::PerpetualId, PerpetualPositions>,
+}
+
+impl PositionIndex {
+    pub(crate) fn new<'a>(accounts: impl IntoIterator<Item = &'a Account>) -> Self {
+        let mut index = Self::default();
+        for acc in accounts {
+            for pos in acc.positions().values() {
+                index.update(pos.perpetual_id(), acc.id(), Some(pos));
+            }
+        }
+        index
+    }
+
+    /// IDs of the accounts holding positions in the perpetual contract, in ascending order.
+    pub(crate) fn account_ids(
+        &self,
+        perpetual_id: types::PerpetualId,
+    ) -> impl Iterator<Item = types::AccountId> + '_ {
+        self.perpetuals
+            .get(&perpetual_id)
+            .into_iter()
+            .flat_map(|perp| perp.contributions.keys().copied())
+    }
+
+    pub(crate) fn aggregates(&self, perpetual_id: types::PerpetualId) -> PositionAggregates {
+        self.perpetuals
+            .get(&perpetual_id)
+            .map(|perp| perp.aggregates)
+            .unwrap_or_default()
+    }
+
+    /// Replaces the contribution of the account position, `None` if it has no position.
+    pub(crate) fn update(
+        &mut self,
+        perpetual_id: types::PerpetualId,
+        account_id: types::AccountId,
+        position: Option<&Position>,
+    ) {
+        let perp = self.perpetuals.entry(perpetual_id).or_default();
+        if let Some(prev) = perp.contributions.remove(&account_id) {
+            perp.aggregates.remove(&prev);
+        }
+        if let Some(pos) = position.filter(|pos| pos.size() > UD64::ZERO) {
+            let contribution = Contribution::from(pos);
+            perp.aggregates.add(&contribution);
+            perp.contributions.insert(account_id, contribution);
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! Price tolerance checks of the exchange.
+
+use fastnum::UD64;
+
+use crate::types;
+
+/// Price deviation beyond the tolerance of the perpetual contract reported by the exchange,
+/// see [`super::Perpetual::last_tolerance_breach`].
+#[derive(Clone, Copy, PartialEq, Eq, derive_more::Debug)]
+pub struct ToleranceBreach {
+    /// Instant of the block the breach was reported in.
+    pub instant: types::StateInstant,
+
+    /// Which price exceeded the tolerance.
+    pub kind: ToleranceBreachKind,
+
+    /// Tolerance in effect, as a fraction of the reference price.
+    #[debug("{tolerance}")]
+    pub tolerance: UD64,
+}
+
+/// Kind of the price tolerance breach along with the compared prices.
+#[derive(Clone, Copy, PartialEq, Eq, derive_more::Debug)]
+pub enum ToleranceBreachKind {
+    /// Proposed mark price deviates from the synthetic perpetual price (`MarkExceedsTol`).
+    Mark {
+        #[debug("{mark_price}")]
+        mark_price: UD64,
+        #[debug("{synthetic_price}")]
+        synthetic_price: UD64,
+    },
+
+    /// Funding price deviates from the oracle price (`FundingPriceExceedsTol`).
+    Funding {
+        #[debug("{funding_price}")]
+        funding_price: UD64,
+        #[debug("{oracle_price}")]
+        oracle_price: UD64,
+    },
+}
+
+/// Band of prices within the tolerance around the reference price, inclusive.
+///
+/// Returns `None` if the reference price is unknown or the tolerance is not set.
+pub(crate) fn price_band(reference: UD64, tolerance: UD64) -> Option<(UD64, UD64)> {
+    if reference == UD64::ZERO || tolerance == UD64::ZERO {
+        return None;
+    }
+    let deviation = reference * tolerance;
+    Some((
+        if deviation < reference {
+            reference - deviation
+        } else {
+            UD64::ZERO
+        },
+        reference + deviation,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use fastnum::udec64;
+
+    use super::*;
+
+    #[test]
+    fn band_around_reference() {
+        assert_eq!(
+            price_band(udec64!(2000), udec64!(0.05)),
+            Some((udec64!(1900), udec64!(2100)))
+        );
+        assert_eq!(
+            price_band(udec64!(100), udec64!(1.5)),
+            Some((UD64::ZERO, udec64!(250)))
+        );
+        assert_eq!(price_band(UD64::ZERO, udec64!(0.05)), None);
+        assert_eq!(price_band(udec64!(100), UD64::ZERO), None);
+    }
+}
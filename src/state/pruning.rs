@@ -0,0 +1,157 @@
+//! Pruning of the state accumulated by long-running trackers.
+
+/// Bounds of the history and idle state kept by the exchange on top of the count-based
+/// retention of the histories, see [`super::SnapshotBuilder::with_pruning`].
+///
+/// Pruning runs every [`Self::every_blocks`] blocks after applying the block,
+/// or on demand with [`super::Exchange::compact`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PruningPolicy {
+    /// Number of blocks between automatic compactions, zero disables them.
+    pub every_blocks: u64,
+
+    /// Fund, funding, parameter and position PnL history recorded more than
+    /// this many blocks ago gets dropped.
+    pub history_blocks: Option<u64>,
+
+    /// Accounts left without positions, orders and locked balance, and not updated
+    /// for this many blocks, get dropped.
+    ///
+    /// Only applies when tracking all accounts, in which case dropped accounts are
+    /// tracked again from their next balance or position event, same as accounts
+    /// absent from the snapshot.
+    pub idle_account_blocks: Option<u64>,
+}
+
+impl PruningPolicy {
+    /// Whether the compaction is due after applying the block.
+    pub(crate) fn is_due(&self, last_compaction: u64, block_number: u64) -> bool {
+        self.every_blocks > 0 && block_number >= last_compaction + self.every_blocks
+    }
+}
+
+/// State dropped by [`super::Exchange::compact`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CompactionReport {
+    /// Number of history records dropped.
+    pub history_records: usize,
+
+    /// Number of idle accounts dropped.
+    pub accounts: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::{Address, TxHash, U256};
+
+    use super::*;
+    use crate::{
+        abi::dex::Exchange::{self as Abi, ExchangeEvents},
+        stream,
+        testing::sim::{Action, Simulator},
+    };
+
+    /// Next block of the simulator with the single event.
+    fn event_block(sim: &mut Simulator, event: ExchangeEvents) -> stream::RawBlockEvents {
+        stream::RawBlockEvents::new(
+            sim.block(&[]).instant(),
+            vec![stream::RawEvent::new(
+                TxHash::ZERO,
+                0,
+                0,
+                Address::ZERO,
+                event,
+            )],
+        )
+    }
+
+    /// Next block with the protocol balance deposit recorded in the fund history.
+    fn deposit_block(sim: &mut Simulator) -> stream::RawBlockEvents {
+        event_block(
+            sim,
+            ExchangeEvents::ProtocolBalanceDeposit(Abi::ProtocolBalanceDeposit {
+                amountCNS: U256::from(100),
+            }),
+        )
+    }
+
+    fn place(account: usize) -> Action {
+        Action::Place {
+            perpetual: 0,
+            account,
+            is_bid: true,
+            offset: 1,
+            lots: 10,
+        }
+    }
+
+    #[test]
+    fn prunes_history_and_idle_accounts() {
+        let mut sim = Simulator::new(&[1], 3);
+        let mut exchange = sim.exchange();
+        exchange.set_pruning(PruningPolicy {
+            every_blocks: 0,
+            history_blocks: Some(2),
+            idle_account_blocks: Some(2),
+        });
+        exchange.apply_events(&deposit_block(&mut sim)).unwrap();
+        exchange
+            .apply_events(&sim.block(&[place(0), place(1)]))
+            .unwrap();
+        exchange
+            .apply_events(&sim.block(&[Action::Cancel { order: 1 }]))
+            .unwrap();
+        exchange.apply_events(&sim.block(&[])).unwrap();
+
+        // Deposit at block 2 and the maintenance margin set at the snapshot are older
+        // than 2 blocks, account 3 has not been updated since the snapshot,
+        // account 2 cancelled its order at block 4, account 1 has the order
+        assert_eq!(exchange.instant().block_number(), 5);
+        assert_eq!(
+            exchange.compact(),
+            CompactionReport {
+                history_records: 2,
+                accounts: 1,
+            }
+        );
+        assert_eq!(exchange.fund_history().count(), 0);
+        assert_eq!(exchange.perpetuals()[&1].param_history().count(), 0);
+        let mut accounts = exchange.accounts().keys().copied().collect::<Vec<_>>();
+        accounts.sort();
+        assert_eq!(accounts, vec![1, 2]);
+
+        // Dropped account is tracked again from its next event, keeping the address
+        let deposit = ExchangeEvents::CollateralDeposit(Abi::CollateralDeposit {
+            accountId: U256::from(3),
+            amountCNS: U256::from(100),
+            balanceCNS: U256::from(100),
+        });
+        exchange
+            .apply_events(&event_block(&mut sim, deposit))
+            .unwrap();
+        assert_eq!(
+            exchange.accounts()[&3].address(),
+            Address::with_last_byte(3)
+        );
+        assert!(!exchange.accounts()[&3].balance().is_zero());
+    }
+
+    #[test]
+    fn compacts_automatically() {
+        let mut sim = Simulator::new(&[1], 1);
+        let mut exchange = sim.exchange();
+        exchange.set_pruning(PruningPolicy {
+            every_blocks: 3,
+            history_blocks: Some(0),
+            idle_account_blocks: None,
+        });
+        exchange.apply_events(&deposit_block(&mut sim)).unwrap();
+        exchange.apply_events(&sim.block(&[])).unwrap();
+        assert_eq!(exchange.fund_history().count(), 1);
+
+        // Due at block 4, three blocks after the snapshot
+        exchange.apply_events(&sim.block(&[])).unwrap();
+        assert_eq!(exchange.fund_history().count(), 0);
+        assert_eq!(exchange.accounts().len(), 1);
+    }
+}
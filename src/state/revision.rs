@@ -0,0 +1,144 @@
+//! Compatibility of the deployed exchange contract with the bundled ABI.
+
+use alloy::{
+    primitives::{Address, B256},
+    sol_types::SolEvent,
+};
+#[cfg(feature = "rpc")]
+use alloy::{
+    primitives::{U256, b256},
+    providers::Provider,
+};
+
+use crate::abi::{
+    self,
+    dex::Exchange::{self, ExchangeCalls, ExchangeEvents},
+};
+#[cfg(feature = "rpc")]
+use crate::error::DexError;
+
+#[cfg(feature = "rpc")]
+/// Storage slot of the implementation address behind the ERC-1967 proxy.
+const IMPLEMENTATION_SLOT: B256 =
+    b256!("0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc");
+
+/// Events declared by the ERC-1967 utilities, emitted only by proxies with
+/// an admin or a beacon, never by the implementation.
+const PROXY_EVENTS: [B256; 2] = [
+    Exchange::AdminChanged::SIGNATURE_HASH,
+    Exchange::BeaconUpgraded::SIGNATURE_HASH,
+];
+
+/// Deployed exchange contract not matching the ABI revision the SDK was built with,
+/// see [`super::Exchange::verify_revision`].
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[error(
+    "exchange implementation {implementation} does not match revision {expected_revision}, \
+     missing functions: {missing_functions:?}, missing events: {missing_events:?}"
+)]
+pub struct IncompatibleContract {
+    /// Revision of the bundled ABI, [`abi::DEX_REVISION`].
+    pub expected_revision: &'static str,
+
+    /// Address of the detected implementation contract.
+    pub implementation: Address,
+
+    /// Signatures of the ABI functions not dispatched by the implementation.
+    pub missing_functions: Vec<&'static str>,
+
+    /// Signatures of the ABI events never emitted by the implementation.
+    pub missing_events: Vec<&'static str>,
+}
+
+#[cfg(feature = "rpc")]
+/// Address of the implementation behind the exchange proxy, or the exchange itself
+/// if it is not a proxy.
+pub(crate) async fn implementation<P: Provider>(
+    provider: &P,
+    exchange: Address,
+) -> Result<Address, DexError> {
+    let slot = provider
+        .get_storage_at(exchange, IMPLEMENTATION_SLOT.into())
+        .await?;
+    Ok(if slot == U256::ZERO {
+        exchange
+    } else {
+        Address::from_word(slot.into())
+    })
+}
+
+/// Compares the implementation bytecode with the bundled ABI: every function selector
+/// compared against by the dispatcher and every event signature hash logged have to be
+/// present as constants, either pushed by the code or copied from its data section.
+pub(crate) fn check_bytecode(
+    implementation: Address,
+    code: &[u8],
+) -> Result<(), Box<IncompatibleContract>> {
+    let missing_functions = ExchangeCalls::SELECTORS
+        .iter()
+        .zip(ExchangeCalls::SIGNATURES)
+        // Leading zero bytes of the selector constant are dropped by the compiler
+        .filter(|(selector, _)| !contains(code, significant_bytes(*selector)))
+        .map(|(_, signature)| *signature)
+        .collect::<Vec<_>>();
+    let missing_events = ExchangeEvents::SELECTORS
+        .iter()
+        .zip(ExchangeEvents::SIGNATURES)
+        .filter(|(topic, _)| {
+            !PROXY_EVENTS.contains(&B256::from(**topic)) && !contains(code, *topic)
+        })
+        .map(|(_, signature)| *signature)
+        .collect::<Vec<_>>();
+    if missing_functions.is_empty() && missing_events.is_empty() {
+        return Ok(());
+    }
+    Err(Box::new(IncompatibleContract {
+        expected_revision: abi::DEX_REVISION,
+        implementation,
+        missing_functions,
+        missing_events,
+    }))
+}
+
+fn significant_bytes(selector: &[u8]) -> &[u8] {
+    let zeros = selector.iter().take_while(|b| **b == 0).count();
+    &selector[zeros.min(selector.len() - 1)..]
+}
+
+fn contains(code: &[u8], constant: &[u8]) -> bool {
+    code.windows(constant.len()).any(|w| w == constant)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bytecode of the implementation built with the bundled ABI.
+    fn deployed_bytecode() -> Vec<u8> {
+        let artifact = include_str!("../../abi/dex/Exchange.json");
+        let start = artifact.find("\"deployedBytecode\"").unwrap();
+        let hex = artifact[start..].split('"').nth(5).unwrap();
+        alloy::hex::decode(hex).unwrap()
+    }
+
+    #[test]
+    fn bytecode_compatibility() {
+        let implementation = Address::with_last_byte(1);
+        let code = deployed_bytecode();
+        assert!(check_bytecode(implementation, &code).is_ok());
+
+        // Changed signature of the event
+        let topic = Exchange::OrderPlaced::SIGNATURE_HASH;
+        let at = code.windows(32).position(|w| w == topic).unwrap();
+        let mut changed = code.clone();
+        changed[at] ^= 0xff;
+        let err = check_bytecode(implementation, &changed).unwrap_err();
+        assert_eq!(err.expected_revision, abi::DEX_REVISION);
+        assert_eq!(err.implementation, implementation);
+        assert!(err.missing_functions.is_empty());
+        assert_eq!(err.missing_events, vec![Exchange::OrderPlaced::SIGNATURE]);
+
+        assert_eq!(significant_bytes(&[0, 0, 1, 2]), &[1, 2]);
+        assert_eq!(significant_bytes(&[0, 0, 0, 0]), &[0]);
+    }
+}
@@ -0,0 +1,70 @@
+//! Exchange roles and permissions.
+
+use std::collections::HashSet;
+
+use alloy::primitives::Address;
+
+/// Administrative role granted by the exchange owner.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Role {
+    /// Manages exchange and perpetual contracts configuration.
+    Administrator,
+
+    /// Updates prices of perpetual contracts.
+    PriceAdministrator,
+
+    /// Manages positions, e.g. liquidations and deleveraging.
+    PositionAdministrator,
+}
+
+/// Holders of the exchange roles along with the whitelisting status.
+///
+/// Exchange smart contract does not allow to enumerate role holders, so the sets
+/// contain addresses checked during snapshot creation, see
+/// [`super::SnapshotBuilder::with_role_candidates`], and addresses updated via events since.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Roles {
+    administrators: HashSet<Address>,
+    price_administrators: HashSet<Address>,
+    position_administrators: HashSet<Address>,
+    is_whitelisting_enabled: bool,
+}
+
+impl Roles {
+    pub(crate) fn new(is_whitelisting_enabled: bool) -> Self {
+        Self {
+            is_whitelisting_enabled,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn holders(&self, role: Role) -> &HashSet<Address> {
+        match role {
+            Role::Administrator => &self.administrators,
+            Role::PriceAdministrator => &self.price_administrators,
+            Role::PositionAdministrator => &self.position_administrators,
+        }
+    }
+
+    pub(crate) fn is_whitelisting_enabled(&self) -> bool {
+        self.is_whitelisting_enabled
+    }
+
+    /// Grants or revokes the role, returns `true` if the holders changed.
+    pub(crate) fn update(&mut self, role: Role, address: Address, granted: bool) -> bool {
+        let holders = match role {
+            Role::Administrator => &mut self.administrators,
+            Role::PriceAdministrator => &mut self.price_administrators,
+            Role::PositionAdministrator => &mut self.position_administrators,
+        };
+        if granted {
+            holders.insert(address)
+        } else {
+            holders.remove(&address)
+        }
+    }
+
+    pub(crate) fn update_whitelisting_enabled(&mut self, enabled: bool) {
+        self.is_whitelisting_enabled = enabled;
+    }
+}
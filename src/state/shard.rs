@@ -0,0 +1,243 @@
+//! Partitioning of the block events by perpetual contract for the parallel application,
+//! see [`super::SnapshotBuilder::with_parallel_apply`].
+//!
+//! Raw events are partitioned at transaction granularity, as the order context spans
+//! the transaction. A transaction goes to the lane of the perpetual contract if all its
+//! events are part of the order flow of that single contract, and all the accounts
+//! involved are not tracked. Handlers of such events mutate only the order book and
+//! the market data of the contract: account, position and order error updates are
+//! applied to tracked accounts only, which are not present.
+//!
+//! Every other transaction goes to the serial lane along with all transactions of the
+//! contracts it touches, so each contract is mutated by a single lane, in the original
+//! order. Serial lane never touches contracts of other lanes, and lanes never touch
+//! accounts, so the lanes mutate disjoint state and can be applied in any order,
+//! producing the same state and events as the serial application. Blocks with events
+//! of unknown footprint, e.g. exchange-wide parameter updates, are not partitioned.
+
+use std::collections::{BTreeMap, HashSet};
+
+use alloy::primitives::U256;
+
+use crate::{abi::dex::Exchange::ExchangeEvents, stream, types};
+
+/// Indices of the block events assigned to each lane, in the original order.
+#[derive(Debug, Default)]
+pub(crate) struct ShardPlan {
+    pub(crate) lanes: BTreeMap<types::PerpetualId, Vec<usize>>,
+    pub(crate) serial: Vec<usize>,
+}
+
+/// State the raw event touches, as far as the partitioning is concerned.
+enum Footprint {
+    /// Order flow of the perpetual contract and the account, or the ones of the order context.
+    Order {
+        perpetual: Option<U256>,
+        account: Option<U256>,
+    },
+    /// Perpetual contract state beyond its order flow, or orders of tracked accounts.
+    Perpetual(U256),
+    /// Account state only.
+    Account,
+    /// Exchange-wide or unknown state.
+    Global,
+}
+
+/// Transaction being partitioned.
+#[derive(Default)]
+struct Transaction {
+    events: Vec<usize>,
+    perpetuals: HashSet<types::PerpetualId>,
+    serial: bool,
+}
+
+/// Partitions the block events, `None` if the block should be applied serially as a whole
+/// or there are less than two lanes to apply in parallel.
+pub(crate) fn plan(
+    events: &[stream::RawEvent],
+    is_tracked: impl Fn(types::AccountId) -> bool,
+) -> Option<ShardPlan> {
+    let mut txs: Vec<Transaction> = vec![];
+    let mut ctx: Option<(U256, U256)> = None;
+    for (index, event) in events.iter().enumerate() {
+        if index == 0 || events[index - 1].tx_index() < event.tx_index() {
+            txs.push(Transaction::default());
+            ctx = None;
+        }
+        let tx = txs.last_mut().expect("transaction started");
+        tx.events.push(index);
+        match footprint(event.event()) {
+            Footprint::Order { perpetual, account } => {
+                if let ExchangeEvents::OrderRequest(e) = event.event() {
+                    ctx = Some((e.perpId, e.accountId));
+                } else if let ExchangeEvents::OrderBatchCompleted(_) = event.event() {
+                    ctx = None;
+                    continue;
+                }
+                // Order flow events without the order context fail to apply
+                let (perp_id, acc_id) = match (perpetual, account) {
+                    (Some(perp_id), Some(acc_id)) => (perp_id, acc_id),
+                    _ => ctx?,
+                };
+                tx.perpetuals.insert(perp_id.to());
+                tx.serial |= is_tracked(acc_id.to());
+            }
+            Footprint::Perpetual(perp_id) => {
+                tx.perpetuals.insert(perp_id.to());
+                tx.serial = true;
+            }
+            Footprint::Account => tx.serial = true,
+            Footprint::Global => return None,
+        }
+    }
+
+    let pinned = txs
+        .iter()
+        .filter(|tx| tx.serial || tx.perpetuals.len() > 1)
+        .flat_map(|tx| tx.perpetuals.iter().copied())
+        .collect::<HashSet<_>>();
+    let mut plan = ShardPlan::default();
+    for tx in txs {
+        match tx.perpetuals.iter().next() {
+            Some(perp_id) if tx.perpetuals.len() == 1 && !pinned.contains(perp_id) => {
+                plan.lanes.entry(*perp_id).or_default().extend(tx.events)
+            }
+            _ => plan.serial.extend(tx.events),
+        }
+    }
+    (plan.lanes.len() > 1).then_some(plan)
+}
+
+fn footprint(event: &ExchangeEvents) -> Footprint {
+    use ExchangeEvents as E;
+
+    let order = |perpetual, account| Footprint::Order {
+        perpetual: Some(perpetual),
+        account: Some(account),
+    };
+    let context = Footprint::Order {
+        perpetual: None,
+        account: None,
+    };
+    match event {
+        E::OrderRequest(e) => order(e.perpId, e.accountId),
+        E::MakerOrderFilled(e) => order(e.perpId, e.accountId),
+        E::IncreasePositionCollateral(e) => order(e.perpId, e.accountId),
+        E::PositionClosed(e) => order(e.perpId, e.accountId),
+        E::PositionCollateralDecreased(e) => order(e.perpId, e.accountId),
+        E::PositionDecreased(e) => order(e.perpId, e.accountId),
+        E::PositionIncreased(e) => order(e.perpId, e.accountId),
+        E::PositionInverted(e) => order(e.perpId, e.accountId),
+        E::PositionOpened(e) => order(e.perpId, e.accountId),
+        E::PositionUnwound(e) => order(e.perpId, e.accountId),
+        E::PositionUnwoundWithoutPayment(e) => order(e.perpId, e.accountId),
+        E::OrderBatchCompleted(_)
+        | E::OrderCancelled(_)
+        | E::OrderChanged(_)
+        | E::OrderPlaced(_)
+        | E::TakerOrderFilled(_)
+        // Order request errors
+        | E::AccountFrozen(_)
+        | E::AmountExceedsAvailableBalance(_)
+        | E::CancelExistingInvalidCloseOrders(_)
+        | E::CantChangeCloseOrder(_)
+        | E::ChangeExpiredOrderNeedsNewExpiry(_)
+        | E::CloseOrderExceedsPosition(_)
+        | E::CloseOrderPositionMismatch(_)
+        | E::ContractIsPaused(_)
+        | E::CrossesBook(_)
+        | E::ExceedsLastExecutionBlock(_)
+        | E::ImmediateOrCancelExecuted(_)
+        | E::InsuficientFundsForRecycleFee(_)
+        | E::InvalidExpiryBlock(_)
+        | E::InvalidOrderId(_)
+        | E::LotOutOfRange(_)
+        | E::MaxMatchesReached(_)
+        | E::MaximumAccountOrders(_)
+        | E::OrderDoesNotExist(_)
+        | E::OrderPostFailed(_)
+        | E::OrderSettlementImpliesInsolvent(_)
+        | E::OrderSizeExceedsAvailableSize(_)
+        | E::PostOrderUnderMinimum(_)
+        | E::PriceOutOfRange(_)
+        | E::WrongAccountForOrder(_) => context,
+        E::CantDeleverageAgainstOpposingPositions(e) => Footprint::Perpetual(e.perpId),
+        E::ClearingExpiredOrder(e) => Footprint::Perpetual(e.perpId),
+        E::ClearingFrozenAccountOrder(e) => Footprint::Perpetual(e.perpId),
+        E::ClearingInvalidCloseOrder(e) => Footprint::Perpetual(e.perpId),
+        E::ClearingSelfMatchingOrder(e) => Footprint::Perpetual(e.perpId),
+        E::ContractLinkFeedUpdated(e) => Footprint::Perpetual(e.perpId),
+        E::ContractPaused(e) => Footprint::Perpetual(e.perpId),
+        E::ContractRemoved(e) => Footprint::Perpetual(e.perpId),
+        E::DeleveragePositionListEmpty(e) => Footprint::Perpetual(e.perpId),
+        E::FundingEventCompleted(e) => Footprint::Perpetual(e.perpId),
+        E::FundingPriceExceedsTol(e) => Footprint::Perpetual(e.perpId),
+        E::IgnoreOracleUpdated(e) => Footprint::Perpetual(e.perpId),
+        E::InitialMarginFractionUpdated(e) => Footprint::Perpetual(e.perpId),
+        E::LinkPriceUpdated(e) => Footprint::Perpetual(e.perpId),
+        E::MaintenanceMarginFractionUpdated(e) => Footprint::Perpetual(e.perpId),
+        E::MakerFeeUpdated(e) => Footprint::Perpetual(e.perpId),
+        E::MakerOrderSettlementFailed(e) => Footprint::Perpetual(e.perpId),
+        E::MarkExceedsTol(e) => Footprint::Perpetual(e.perpId),
+        E::MarkUpdated(e) => Footprint::Perpetual(e.perpId),
+        E::OrderCancelledByAdmin(e) => Footprint::Perpetual(e.perpId),
+        E::OrderCancelledByLiquidator(e) => Footprint::Perpetual(e.perpId),
+        E::PositionDeleveraged(e) => Footprint::Perpetual(e.perpId),
+        E::PositionLiquidated(e) => Footprint::Perpetual(e.perpId),
+        E::PriceMaxAgeUpdated(e) => Footprint::Perpetual(e.perpId),
+        E::PriceTolUpdated(e) => Footprint::Perpetual(e.perpId),
+        E::TakerFeeUpdated(e) => Footprint::Perpetual(e.perpId),
+        E::AccountFreeze(_)
+        | E::AccountLiquidationCredit(_)
+        | E::CollateralDeposit(_)
+        | E::CollateralWithdrawal(_) => Footprint::Account,
+        _ => Footprint::Global,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::sim::{Action, Simulator};
+
+    fn place(perpetual: usize, account: usize) -> Action {
+        Action::Place {
+            perpetual,
+            account,
+            is_bid: true,
+            offset: 1,
+            lots: 10,
+        }
+    }
+
+    #[test]
+    fn plan_lanes_by_perpetual() {
+        let mut sim = Simulator::new(&[1, 2, 3], 4);
+        let block = sim.block(&[place(0, 2), place(1, 3), place(0, 3), place(2, 0)]);
+        let plan = plan(block.events(), |id| id == 1).expect("parallel plan");
+
+        // Account 1 is tracked, pinning perpetual contract 3 to the serial lane
+        assert_eq!(plan.lanes.keys().copied().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(plan.lanes[&1], vec![0, 1, 4, 5]);
+        assert_eq!(plan.lanes[&2], vec![2, 3]);
+        assert_eq!(plan.serial, vec![6, 7]);
+    }
+
+    #[test]
+    fn plan_pins_perpetual_of_serial_transaction() {
+        let mut sim = Simulator::new(&[1, 2], 4);
+        let block = sim.block(&[
+            place(0, 2),
+            Action::Mark {
+                perpetual: 0,
+                price: 1000,
+            },
+            place(1, 3),
+        ]);
+        assert!(plan(block.events(), |_| false).is_none());
+
+        let block = sim.block(&[place(0, 2), place(1, 3)]);
+        let plan = plan(block.events(), |_| false).expect("parallel plan");
+        assert!(plan.serial.is_empty());
+    }
+}
@@ -0,0 +1,175 @@
+//! Exchange state shared between the task applying events and its consumers.
+
+use std::sync::{Arc, RwLock};
+
+use super::{Exchange, StateBlockEvents};
+use crate::{error::DexError, stream::RawBlockEvents};
+
+/// Cheaply cloneable read handle of the exchange state kept up to date
+/// by the [`ExchangeWriter`].
+///
+/// Readers get immutable snapshots which never wait for the writer applying the events
+/// and stay consistent as of the block they were loaded at, no matter how long they are held.
+///
+/// # Example
+///
+/// ```ignore
+/// use dex_sdk::state::SharedExchange;
+///
+/// let (shared, mut writer) = SharedExchange::new(snapshot);
+/// tokio::spawn(async move {
+///     while let Some(block_events) = stream.next().await {
+///         writer.apply_events(&block_events?)?;
+///     }
+///     Ok::<_, DexError>(())
+/// });
+///
+/// // Any number of consumers, e.g. request handlers
+/// let exchange = shared.load();
+/// println!("{:?}", exchange.perpetuals()[&btc].mark_price());
+/// ```
+#[derive(Clone, Debug)]
+pub struct SharedExchange {
+    current: Arc<RwLock<Arc<Exchange>>>,
+}
+
+impl SharedExchange {
+    /// Shares the exchange state, returning the read handle along with
+    /// the single writer to keep it up to date.
+    pub fn new(exchange: Exchange) -> (Self, ExchangeWriter) {
+        let exchange = Arc::new(exchange);
+        let shared = Self {
+            current: Arc::new(RwLock::new(exchange.clone())),
+        };
+        let writer = ExchangeWriter {
+            shared: shared.clone(),
+            exchange,
+        };
+        (shared, writer)
+    }
+
+    /// Snapshot of the latest published state.
+    pub fn load(&self) -> Arc<Exchange> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Swaps the published state, holding the lock only for the pointer swap.
+    fn store(&self, exchange: Arc<Exchange>) {
+        let previous = std::mem::replace(&mut *self.current.write().unwrap(), exchange);
+        // Released outside of the lock in case it is the last reference
+        drop(previous);
+    }
+}
+
+/// The only handle able to update the [`SharedExchange`], intended to be owned
+/// by the task consuming the event stream.
+#[derive(Debug)]
+pub struct ExchangeWriter {
+    shared: SharedExchange,
+    /// Latest published state.
+    exchange: Arc<Exchange>,
+}
+
+impl ExchangeWriter {
+    /// Read handle of the state updated by this writer.
+    pub fn shared(&self) -> &SharedExchange {
+        &self.shared
+    }
+
+    /// Applies the block events to the copy of the latest state and publishes it,
+    /// see [`Exchange::apply_events`].
+    ///
+    /// Events are applied without holding the lock, readers only wait for the swap of
+    /// the published state. The copy shares perpetual contracts, order books and accounts
+    /// with the published state, only the ones changed by the block get copied.
+    ///
+    /// On failure the copy is discarded, leaving the state of the previous block published.
+    pub fn apply_events(
+        &mut self,
+        events: &RawBlockEvents,
+    ) -> Result<Option<StateBlockEvents>, DexError> {
+        let (exchange, state_events) = self.apply(events)?;
+        self.publish(exchange);
+        Ok(state_events)
+    }
+
+    fn apply(
+        &self,
+        events: &RawBlockEvents,
+    ) -> Result<(Arc<Exchange>, Option<StateBlockEvents>), DexError> {
+        let mut exchange = self.exchange.clone();
+        let state_events = Arc::make_mut(&mut exchange).apply_events(events)?;
+        Ok((exchange, state_events))
+    }
+
+    fn publish(&mut self, exchange: Arc<Exchange>) {
+        self.exchange = exchange.clone();
+        self.shared.store(exchange);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fuzz::{Action, Simulator};
+
+    #[test]
+    fn snapshots_stay_consistent() {
+        let mut sim = Simulator::new(&[1], 1);
+        let (shared, mut writer) = SharedExchange::new(sim.exchange());
+        let before = shared.load();
+
+        writer
+            .apply_events(&sim.block(&[Action::Open {
+                perpetual: 0,
+                account: 0,
+                is_long: true,
+                lots: 1,
+            }]))
+            .unwrap();
+        let after = shared.clone().load();
+        assert!(before.accounts()[&1].positions().is_empty());
+        assert_eq!(after.accounts()[&1].positions().len(), 1);
+        assert_eq!(
+            after.instant().block_number(),
+            before.instant().block_number() + 1
+        );
+
+        // Skipped block fails to apply and the published state is kept
+        sim.block(&[]);
+        assert!(writer.apply_events(&sim.block(&[])).is_err());
+        assert_eq!(writer.shared().load().instant(), after.instant());
+    }
+
+    #[test]
+    fn readers_do_not_wait_for_apply() {
+        let mut sim = Simulator::new(&[1, 2], 1);
+        let (shared, mut writer) = SharedExchange::new(sim.exchange());
+        let before = shared.load();
+
+        // Events are applied to the copy with the lock free
+        let (exchange, _) = writer
+            .apply(&sim.block(&[Action::Open {
+                perpetual: 0,
+                account: 0,
+                is_long: true,
+                lots: 1,
+            }]))
+            .unwrap();
+        assert!(shared.current.try_read().is_ok());
+        assert!(Arc::ptr_eq(&shared.load(), &before));
+
+        // Only the changed parts of the state are copied
+        assert!(Arc::ptr_eq(
+            &exchange.perpetuals()[&2],
+            &before.perpetuals()[&2]
+        ));
+        assert!(!Arc::ptr_eq(
+            &exchange.accounts()[&1],
+            &before.accounts()[&1]
+        ));
+
+        writer.publish(exchange.clone());
+        assert!(Arc::ptr_eq(&shared.load(), &exchange));
+    }
+}
@@ -1,13 +1,237 @@
-use std::time::Duration;
+use std::sync::Arc;
+#[cfg(feature = "rpc")]
+use std::{collections::HashSet, time::Duration};
 
-use alloy::{eips::BlockId, providers::Provider, rpc::types::Filter, sol_types::SolEventInterface};
-use futures::{Stream, stream};
+use alloy::primitives::B256;
+#[cfg(feature = "rpc")]
+use alloy::{
+    eips::BlockId,
+    primitives::Log,
+    providers::Provider,
+    rpc::types::Filter,
+    sol_types::{self, SolEvent, SolEventInterface},
+};
+use futures::Stream;
+#[cfg(feature = "rpc")]
+use futures::stream;
 
-use crate::{Chain, abi::dex::Exchange::ExchangeEvents, error::DexError, types};
+#[cfg(feature = "rpc")]
+use crate::{Chain, abi::dex::Exchange};
+use crate::{abi::dex::Exchange::ExchangeEvents, error::DexError, types};
 
 pub type RawEvent = types::EventContext<ExchangeEvents>;
 pub type RawBlockEvents = types::BlockEvents<RawEvent>;
+pub type UnknownRawEvent = types::EventContext<types::UnknownEvent>;
 
+#[cfg(feature = "rpc")]
+/// Exchange log decoded by [`decode_log`].
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum DecodedLog {
+    Known(ExchangeEvents),
+    Unknown(types::UnknownEvent),
+}
+
+#[cfg(feature = "rpc")]
+/// Decode the exchange log, reporting logs with unknown signature as such
+/// unless in strict mode. Logs with known signature failing to decode are errors.
+pub(crate) fn decode_log(log: &Log, strict: bool) -> Result<DecodedLog, sol_types::Error> {
+    let known = log
+        .topics()
+        .first()
+        .is_some_and(|topic| ExchangeEvents::SELECTORS.binary_search(&topic.0).is_ok());
+    if known || strict {
+        return Ok(DecodedLog::Known(ExchangeEvents::decode_log(log)?.data));
+    }
+    Ok(DecodedLog::Unknown(types::UnknownEvent {
+        topics: log.topics().to_vec(),
+        data: log.data.data.clone(),
+    }))
+}
+
+#[cfg(feature = "rpc")]
+/// Filter of raw events returned by [`raw_filtered`].
+///
+/// Event type filtering is applied by the RPC node via log topics, reducing bandwidth
+/// and decoding work. Exchange events do not index account IDs, so account filtering
+/// is applied locally after decoding.
+///
+/// Filtered streams are not suitable for [`crate::state::Exchange::apply_events`],
+/// which requires the complete event sequence.
+#[derive(Clone, Debug, Default)]
+pub struct EventFilter {
+    signatures: Option<Vec<B256>>,
+    accounts: Option<HashSet<types::AccountId>>,
+    strict: bool,
+}
+
+#[cfg(feature = "rpc")]
+impl EventFilter {
+    /// Filter passing all events.
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Maker and taker fills, along with order requests providing
+    /// the taker context.
+    pub fn fills() -> Self {
+        Self::default().with_events([
+            Exchange::OrderRequest::SIGNATURE_HASH,
+            Exchange::MakerOrderFilled::SIGNATURE_HASH,
+            Exchange::TakerOrderFilled::SIGNATURE_HASH,
+        ])
+    }
+
+    /// Order book mutations and order requests they originate from.
+    pub fn orders() -> Self {
+        Self::default().with_events([
+            Exchange::OrderRequest::SIGNATURE_HASH,
+            Exchange::OrderBatchCompleted::SIGNATURE_HASH,
+            Exchange::OrderPlaced::SIGNATURE_HASH,
+            Exchange::OrderChanged::SIGNATURE_HASH,
+            Exchange::OrderCancelled::SIGNATURE_HASH,
+            Exchange::OrderCancelledByAdmin::SIGNATURE_HASH,
+            Exchange::OrderCancelledByLiquidator::SIGNATURE_HASH,
+            Exchange::MakerOrderFilled::SIGNATURE_HASH,
+            Exchange::TakerOrderFilled::SIGNATURE_HASH,
+            Exchange::MakerOrderSettlementFailed::SIGNATURE_HASH,
+            Exchange::ClearingExpiredOrder::SIGNATURE_HASH,
+            Exchange::ClearingFrozenAccountOrder::SIGNATURE_HASH,
+            Exchange::ClearingInvalidCloseOrder::SIGNATURE_HASH,
+            Exchange::ClearingSelfMatchingOrder::SIGNATURE_HASH,
+        ])
+    }
+
+    /// Pass events with the given signatures (topic 0) in addition to already
+    /// selected ones, e.g. [`Exchange::PositionOpened::SIGNATURE_HASH`].
+    pub fn with_events(mut self, signatures: impl IntoIterator<Item = B256>) -> Self {
+        self.signatures.get_or_insert_default().extend(signatures);
+        self
+    }
+
+    /// Pass only events touching the given accounts: events referring the account directly,
+    /// or following the order request of the account within the same transaction.
+    /// Unknown events are dropped, as they can not be attributed to accounts.
+    pub fn with_accounts(mut self, accounts: impl IntoIterator<Item = types::AccountId>) -> Self {
+        self.accounts.get_or_insert_default().extend(accounts);
+        self
+    }
+
+    /// Fail on logs not matching any known event, instead of reporting them
+    /// as [`types::BlockEvents::unknown_events`].
+    ///
+    /// By default unknown logs, e.g. of events added by a contract upgrade, are passed
+    /// through, so consumers keep running across minor contract changes.
+    pub fn with_strict_decoding(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+
+    fn log_filter(&self, chain: &Chain, block_num: u64) -> Filter {
+        let filter = Filter::new()
+            .address(chain.exchange())
+            .from_block(block_num)
+            .to_block(block_num);
+        match &self.signatures {
+            Some(signatures) => filter.event_signature(signatures.clone()),
+            None => filter,
+        }
+    }
+
+    fn retain(&self, events: &mut Vec<RawEvent>) {
+        let Some(accounts) = &self.accounts else {
+            return;
+        };
+        let mut request_account: Option<(u64, types::AccountId)> = None;
+        events.retain(|event| {
+            if let ExchangeEvents::OrderRequest(e) = event.event() {
+                request_account = Some((event.tx_index(), e.accountId.to()));
+            }
+            let ids = event_account_ids(event.event());
+            if ids.is_empty() {
+                request_account
+                    .is_some_and(|(tx, id)| tx == event.tx_index() && accounts.contains(&id))
+            } else {
+                ids.iter().any(|id| accounts.contains(id))
+            }
+        });
+    }
+}
+
+#[cfg(feature = "rpc")]
+/// Account IDs the event refers to directly.
+fn event_account_ids(event: &ExchangeEvents) -> Vec<types::AccountId> {
+    macro_rules! account_ids {
+        ($($variant:ident => $($field:ident),+;)*) => {
+            match event {
+                $(ExchangeEvents::$variant(e) => vec![$(e.$field.to()),+],)*
+                _ => vec![],
+            }
+        };
+    }
+    account_ids! {
+        AccountCreated => id;
+        AccountFreeze => accountId;
+        AccountLiquidationCredit => accountId;
+        BankruptcyPriceExceedsReferencePrice => accountId;
+        BorrowMarginNotMetAfterDecCollateral => accountId;
+        CannotAdjustEntryPriceToDecCollateral => accountId;
+        CantChangeCloseOrder => accountId;
+        CantDeleverageAgainstOpposingPositions => accountId;
+        ChangeExpiredOrderNeedsNewExpiry => accountId;
+        ClearedDecreaseCollatParams => accountId;
+        CollateralDecreaseApproved => accountId;
+        CollateralDecreaseDeclined => accountId;
+        CollateralDecreaseRequested => accountId;
+        CollateralDeposit => accountId;
+        CollateralWithdrawal => accountId;
+        DecreaseCollateralAmountOutOfRange => accountId;
+        DecreaseCollateralParamsExpired => accountId;
+        DecreaseCollateralPriceBeyondReference => accountId;
+        DeleveragePositionListEmpty => accountId;
+        IncreasePositionCollateral => accountId;
+        InsufficientFundsToDecCollateral => accountId;
+        InsuficientFundsForRecycleFee => accountId;
+        InsurancePaymentForSettlement => accountId;
+        InvalidBankruptcyPrice => accountId;
+        InvalidLiquidationPrice => accountId;
+        LiquidationBuyerUpdated => accountId;
+        MakerOrderFilled => accountId;
+        MaximumAccountOrders => accountId;
+        OrderCancelledByAdmin => accountId;
+        OrderCancelledByLiquidator => accountId;
+        OrderForwardingUpdated => accountId;
+        OrderRequest => accountId;
+        OrderSettlementImpliesInsolvent => accountId;
+        PositionClosed => accountId;
+        PositionCollateralDecreased => accountId;
+        PositionDecreased => accountId;
+        PositionDeleveraged => accountId;
+        PositionDoesNotExist => accountId;
+        PositionIncreased => accountId;
+        PositionInverted => accountId;
+        PositionLiquidationCredit => accountId;
+        PositionOpened => accountId;
+        PositionTypeMismatch => accountId;
+        PositionUnwound => accountId;
+        PositionUnwoundWithoutPayment => accountId;
+        RecycleBalanceInsufficientSevere => accountId;
+        TransferAccountToProtocol => accountId;
+        TransferProtocolToAccount => accountId;
+        UnwindInsufficientBalance => accountId;
+        BuyToLiquidateSlippageExceeded => posAccountId;
+        CantBuyToLiquidate => posAccountId;
+        CantLiquidatePosAboveMMR => posAccountId;
+        InsolventPositionCannotBeForcedClose => posAccountId;
+        PositionLiquidated => posAccountId;
+        ClearingExpiredOrder => accountId, recyclerAccountId;
+        ClearingFrozenAccountOrder => accountId, recyclerAccountId;
+        ClearingInvalidCloseOrder => accountId, recyclerAccountId;
+        ClearingSelfMatchingOrder => accountId, recyclerAccountId;
+        MakerOrderSettlementFailed => accountId, recyclerAccountId;
+    }
+}
+
+#[cfg(feature = "rpc")]
 /// Returns stream of raw events emitted by the DEX smart contract,
 /// batched per block, starting from the specified block.
 ///
@@ -19,6 +243,12 @@ pub type RawBlockEvents = types::BlockEvents<RawEvent>;
 /// and/or [`alloy::transports::layers::RetryBackoffLayer`].
 ///
 /// See [`crate::abi::dex::Exchange::ExchangeEvents`] for the list of possible events and corresponding details.
+/// Logs not matching any of them are reported as [`types::BlockEvents::unknown_events`],
+/// see [`EventFilter::with_strict_decoding`] to fail on them instead.
+///
+/// The stream includes `Upgraded` events of the exchange proxy, reported as
+/// [`crate::state::ExchangeEvent::ImplementationUpgraded`] once applied, see
+/// [`crate::state::SnapshotBuilder::with_halt_on_upgrade`].
 ///
 pub fn raw<P, S, SFut>(
     chain: &Chain,
@@ -31,60 +261,480 @@ where
     S: Fn(Duration) -> SFut + Copy,
     SFut: Future<Output = ()>,
 {
+    raw_filtered(chain, provider, from, EventFilter::all(), sleep)
+}
+
+#[cfg(feature = "rpc")]
+/// Same as [`raw`], but returns only events passing the given [`EventFilter`].
+///
+/// Blocks are still produced continuously, including ones without matching events.
+pub fn raw_filtered<P, S, SFut>(
+    chain: &Chain,
+    provider: P,
+    from: types::StateInstant,
+    event_filter: EventFilter,
+    sleep: S,
+) -> impl Stream<Item = Result<RawBlockEvents, DexError>>
+where
+    P: Provider,
+    S: Fn(Duration) -> SFut + Copy,
+    SFut: Future<Output = ()>,
+{
+    let event_filter = Arc::new(event_filter);
     stream::unfold(
         (provider, from.block_number()),
-        move |(provider, mut block_num)| async move {
-            let filter = Filter::new()
-                .address(chain.exchange())
-                .from_block(block_num)
-                .to_block(block_num);
-            loop {
-                // Anvil node, and maybe some RPC providers, produce empty response instead of
-                // error in case the block in the filter does not exist yet,
-                // so checking the block presence explicitly
-                let result = futures::try_join!(
-                    provider.get_block(BlockId::number(block_num)).into_future(),
-                    provider.get_logs(&filter)
-                )
-                .map_err(DexError::from)
-                .and_then(|(block, logs)| {
-                    let block_header = block
-                        .ok_or(DexError::InvalidRequest(
-                            "block is not available yet".to_string(),
-                        ))?
-                        .header;
-                    let mut events = Vec::with_capacity(logs.len());
-                    for log in &logs {
-                        events.push(RawEvent::new(
-                            log.transaction_hash.unwrap_or_default(),
-                            log.transaction_index.unwrap_or_default(),
-                            log.log_index.unwrap_or_default(),
-                            ExchangeEvents::decode_log(&log.inner)
-                                .map_err(DexError::from)?
-                                .data,
-                        ));
-                    }
-                    Ok(RawBlockEvents::new(
-                        types::StateInstant::new(block_num, block_header.timestamp),
-                        events,
-                    ))
-                });
-                if result.is_ok() {
+        move |(provider, block_num)| {
+            let event_filter = event_filter.clone();
+            async move {
+                let result = next_block(chain, &provider, &event_filter, block_num, sleep).await;
+                let next = block_num + u64::from(result.is_ok());
+                Some((result.map(|(events, _)| events), (provider, next)))
+            }
+        },
+    )
+}
+
+/// Position in the event stream to resume from after restart, see [`resume`].
+///
+/// Tokens produced by [`resume`] cover whole blocks, tokens pointing at an event
+/// within the block can be created to resume after a partially processed block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ResumeToken {
+    /// Block number the last processed event belongs to.
+    pub block_number: u64,
+
+    /// Log index of the last processed event within the block,
+    /// `None` if the whole block is processed.
+    pub log_index: Option<u64>,
+
+    /// Hash of the block to check it is still canonical on resumption,
+    /// `None` to skip the check.
+    pub block_hash: Option<B256>,
+}
+
+impl ResumeToken {
+    pub fn new(block_number: u64, log_index: Option<u64>, block_hash: Option<B256>) -> Self {
+        Self {
+            block_number,
+            log_index,
+            block_hash,
+        }
+    }
+
+    /// Token resuming after the block of the state instant, e.g. of the snapshot.
+    pub fn from_instant(instant: types::StateInstant) -> Self {
+        Self::new(instant.block_number(), None, None)
+    }
+}
+
+/// Formatted as `block_number:log_index:block_hash`, with `-` for missing values.
+impl std::fmt::Display for ResumeToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:", self.block_number)?;
+        match self.log_index {
+            Some(log_index) => write!(f, "{log_index}:")?,
+            None => f.write_str("-:")?,
+        }
+        match self.block_hash {
+            Some(hash) => write!(f, "{hash}"),
+            None => f.write_str("-"),
+        }
+    }
+}
+
+impl std::str::FromStr for ResumeToken {
+    type Err = DexError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || DexError::InvalidRequest(format!("invalid resume token: {s}"));
+        let mut parts = s.split(':');
+        let (Some(block), Some(log), Some(hash), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        Ok(Self {
+            block_number: block.parse().map_err(|_| invalid())?,
+            log_index: match log {
+                "-" => None,
+                log => Some(log.parse().map_err(|_| invalid())?),
+            },
+            block_hash: match hash {
+                "-" => None,
+                hash => Some(hash.parse().map_err(|_| invalid())?),
+            },
+        })
+    }
+}
+
+#[cfg(feature = "rpc")]
+/// Same as [`raw`], but resumes right after the position of the token and produces
+/// the token of each block along with its events, so persisting the token together
+/// with the state the block is applied to guarantees each event gets applied exactly once
+/// across restarts.
+///
+/// Fails with [`DexError::ResumeTokenMismatch`] if the hash of the token block differs
+/// from the canonical one, i.e. the block got reorganized since the token was produced.
+pub fn resume<P, S, SFut>(
+    chain: &Chain,
+    provider: P,
+    token: ResumeToken,
+    sleep: S,
+) -> impl Stream<Item = Result<(RawBlockEvents, ResumeToken), DexError>>
+where
+    P: Provider,
+    S: Fn(Duration) -> SFut + Copy,
+    SFut: Future<Output = ()>,
+{
+    let event_filter = EventFilter::all();
+    stream::unfold(
+        (provider, token.block_number, Some(token)),
+        move |(provider, mut block_num, mut resumed)| {
+            let event_filter = event_filter.clone();
+            async move {
+                loop {
+                    let result =
+                        next_block(chain, &provider, &event_filter, block_num, sleep).await;
+                    let (block_events, block_hash) = match result {
+                        Ok(block) => block,
+                        Err(err) => return Some((Err(err), (provider, block_num, resumed))),
+                    };
                     block_num += 1;
-                    return Some((result, (provider, block_num)));
+                    let block_events = match resumed.take() {
+                        Some(token) => {
+                            if token.block_hash.is_some_and(|hash| hash != block_hash) {
+                                let err = DexError::ResumeTokenMismatch(token.block_number);
+                                return Some((Err(err), (provider, block_num - 1, Some(token))));
+                            }
+                            let Some(log_index) = token.log_index else {
+                                // Whole block processed already
+                                continue;
+                            };
+                            let instant = block_events.instant();
+                            let (mut events, mut unknown) = block_events.into_parts();
+                            events.retain(|e| e.log_index() > log_index);
+                            unknown.retain(|e| e.log_index() > log_index);
+                            RawBlockEvents::new(instant, events).with_unknown_events(unknown)
+                        }
+                        None => block_events,
+                    };
+                    let token = ResumeToken::new(
+                        block_events.instant().block_number(),
+                        None,
+                        Some(block_hash),
+                    );
+                    return Some((Ok((block_events, token)), (provider, block_num, None)));
                 }
-                if matches!(result, Err(DexError::InvalidRequest(_))) {
-                    // Block is not available yet
-                    sleep(provider.client().poll_interval()).await;
-                    continue;
+            }
+        },
+    )
+}
+
+#[cfg(feature = "rpc")]
+/// Fetch events of the block along with its hash, waiting for the block
+/// to become available.
+async fn next_block<P, S, SFut>(
+    chain: &Chain,
+    provider: &P,
+    event_filter: &EventFilter,
+    block_num: u64,
+    sleep: S,
+) -> Result<(RawBlockEvents, B256), DexError>
+where
+    P: Provider,
+    S: Fn(Duration) -> SFut + Copy,
+    SFut: Future<Output = ()>,
+{
+    let filter = event_filter.log_filter(chain, block_num);
+    loop {
+        // Anvil node, and maybe some RPC providers, produce empty response instead of
+        // error in case the block in the filter does not exist yet,
+        // so checking the block presence explicitly
+        let result = futures::try_join!(
+            provider.get_block(BlockId::number(block_num)).into_future(),
+            provider.get_logs(&filter)
+        )
+        .map_err(DexError::from)
+        .and_then(|(block, logs)| {
+            let block_header = block
+                .ok_or(DexError::InvalidRequest(
+                    "block is not available yet".to_string(),
+                ))?
+                .header;
+            let mut events = Vec::with_capacity(logs.len());
+            let mut unknown = vec![];
+            for log in &logs {
+                let tx_hash = log.transaction_hash.unwrap_or_default();
+                let tx_index = log.transaction_index.unwrap_or_default();
+                let log_index = log.log_index.unwrap_or_default();
+                match decode_log(&log.inner, event_filter.strict)? {
+                    DecodedLog::Known(event) => events.push(RawEvent::new(
+                        tx_hash,
+                        tx_index,
+                        log_index,
+                        log.address(),
+                        event,
+                    )),
+                    DecodedLog::Unknown(event) => unknown.push(UnknownRawEvent::new(
+                        tx_hash,
+                        tx_index,
+                        log_index,
+                        log.address(),
+                        event,
+                    )),
                 }
-                return Some((result, (provider, block_num)));
             }
+            event_filter.retain(&mut events);
+            if event_filter.accounts.is_some() {
+                // Unknown events can not be attributed to accounts
+                unknown.clear();
+            }
+            Ok((
+                RawBlockEvents::new(
+                    types::StateInstant::new(block_num, block_header.timestamp),
+                    events,
+                )
+                .with_unknown_events(unknown),
+                block_header.hash,
+            ))
+        });
+        if matches!(result, Err(DexError::InvalidRequest(_))) {
+            // Block is not available yet
+            sleep(provider.client().poll_interval()).await;
+            continue;
+        }
+        return result;
+    }
+}
+
+/// Policy applied by the [`channel`] sender when the buffer is full,
+/// i.e. the consumer falls behind the stream.
+pub enum Backpressure<T> {
+    /// Wait for the consumer to catch up, pausing the stream polling.
+    Block,
+
+    /// Drop the oldest buffered item, so the consumer receives
+    /// [`Delivery::Resync`] in place of the dropped items and can rebuild its state,
+    /// e.g. take a fresh snapshot.
+    DropOldest,
+
+    /// Merge the new item into the newest buffered one with the given function.
+    /// Suitable for items superseding the previous ones, like book or price updates.
+    Coalesce(fn(&mut T, T)),
+}
+
+impl<T> Clone for Backpressure<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Backpressure<T> {}
+
+impl<T> std::fmt::Debug for Backpressure<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Block => f.write_str("Block"),
+            Self::DropOldest => f.write_str("DropOldest"),
+            Self::Coalesce(_) => f.write_str("Coalesce"),
+        }
+    }
+}
+
+/// Item received from the [`channel`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Delivery<T> {
+    /// Next item of the stream.
+    Item(T),
+
+    /// Number of items dropped by [`Backpressure::DropOldest`] since the previous delivery.
+    Resync { dropped: u64 },
+}
+
+#[derive(Debug)]
+struct ChannelState<T> {
+    queue: std::collections::VecDeque<T>,
+    /// Items dropped before the first queued one.
+    dropped: u64,
+    sender_closed: bool,
+    receiver_closed: bool,
+}
+
+#[derive(Debug)]
+struct Channel<T> {
+    state: std::sync::Mutex<ChannelState<T>>,
+    capacity: usize,
+    policy: Backpressure<T>,
+    items: tokio::sync::Notify,
+    space: tokio::sync::Notify,
+}
+
+impl<T> Channel<T> {
+    fn state(&self) -> std::sync::MutexGuard<'_, ChannelState<T>> {
+        self.state.lock().expect("channel lock")
+    }
+}
+
+/// Sending half of the [`channel`].
+#[derive(Debug)]
+pub struct Sender<T> {
+    channel: Arc<Channel<T>>,
+}
+
+/// Receiving half of the [`channel`].
+#[derive(Debug)]
+pub struct Receiver<T> {
+    channel: Arc<Channel<T>>,
+}
+
+/// Create a channel buffering up to `capacity` items, applying the given policy
+/// when the buffer is full.
+///
+/// Decouples stream polling from the consumer, see [`forward`], with the memory bounded
+/// by the capacity and the consumer explicitly signaled if it falls behind:
+///
+/// ```ignore
+/// let (tx, mut rx) = stream::channel(64, stream::Backpressure::DropOldest);
+/// tokio::spawn(async move {
+///     stream::forward(stream::raw(&chain, provider, from, tokio::time::sleep), tx).await
+/// });
+///
+/// while let Some(delivery) = rx.recv().await {
+///     match delivery {
+///         stream::Delivery::Item(block_events) => exchange.apply_events(&block_events?)?,
+///         stream::Delivery::Resync { .. } => exchange = rebuild_snapshot().await?,
+///     };
+/// }
+/// ```
+///
+/// # Panics
+///
+/// If the capacity is zero.
+pub fn channel<T>(capacity: usize, policy: Backpressure<T>) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "channel capacity must be non-zero");
+    let channel = Arc::new(Channel {
+        state: std::sync::Mutex::new(ChannelState {
+            queue: std::collections::VecDeque::with_capacity(capacity),
+            dropped: 0,
+            sender_closed: false,
+            receiver_closed: false,
+        }),
+        capacity,
+        policy,
+        items: tokio::sync::Notify::new(),
+        space: tokio::sync::Notify::new(),
+    });
+    (
+        Sender {
+            channel: channel.clone(),
         },
+        Receiver { channel },
     )
 }
 
-#[cfg(test)]
+/// Forward all items of the stream to the channel, until the stream ends
+/// or the receiver is dropped.
+pub async fn forward<St: Stream>(stream: St, sender: Sender<St::Item>) {
+    futures::pin_mut!(stream);
+    while let Some(item) = futures::StreamExt::next(&mut stream).await {
+        if sender.send(item).await.is_err() {
+            // Receiver dropped, graceful shutdown
+            break;
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    /// Send the item, applying the backpressure policy if the buffer is full.
+    ///
+    /// Returns the item back if the receiver is dropped.
+    pub async fn send(&self, item: T) -> Result<(), T> {
+        let channel = &self.channel;
+        loop {
+            // Registering interest before checking the state to not miss notifications
+            let space = channel.space.notified();
+            {
+                let mut state = channel.state();
+                if state.receiver_closed {
+                    return Err(item);
+                }
+                if state.queue.len() < channel.capacity {
+                    state.queue.push_back(item);
+                    break;
+                }
+                match channel.policy {
+                    Backpressure::Block => {}
+                    Backpressure::DropOldest => {
+                        state.queue.pop_front();
+                        state.dropped += 1;
+                        state.queue.push_back(item);
+                        break;
+                    }
+                    Backpressure::Coalesce(merge) => {
+                        merge(state.queue.back_mut().expect("full queue"), item);
+                        break;
+                    }
+                }
+            }
+            space.await;
+        }
+        channel.items.notify_one();
+        Ok(())
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.channel.state().sender_closed = true;
+        self.channel.items.notify_one();
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Receive the next delivery, or `None` once the sender is dropped
+    /// and all buffered items are received.
+    pub async fn recv(&mut self) -> Option<Delivery<T>> {
+        let channel = &self.channel;
+        loop {
+            let items = channel.items.notified();
+            {
+                let mut state = channel.state();
+                if state.dropped > 0 {
+                    return Some(Delivery::Resync {
+                        dropped: std::mem::take(&mut state.dropped),
+                    });
+                }
+                if let Some(item) = state.queue.pop_front() {
+                    channel.space.notify_one();
+                    return Some(Delivery::Item(item));
+                }
+                if state.sender_closed {
+                    return None;
+                }
+            }
+            items.await;
+        }
+    }
+
+    /// Number of buffered items.
+    pub fn len(&self) -> usize {
+        self.channel.state().queue.len()
+    }
+
+    /// Indicates if there are no buffered items.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.channel.state().receiver_closed = true;
+        self.channel.space.notify_one();
+    }
+}
+
+#[cfg(all(test, feature = "rpc"))]
 mod tests {
     use alloy::{
         providers::ProviderBuilder, rpc::client::RpcClient, transports::layers::RetryBackoffLayer,
@@ -92,7 +742,116 @@ mod tests {
     use futures::StreamExt;
 
     use super::*;
-    use crate::Chain;
+    use crate::{
+        Chain,
+        testing::fuzz::{Action, Simulator},
+    };
+    use alloy::primitives::Address;
+
+    #[test]
+    fn unknown_logs() {
+        let log = |topic| {
+            Log::new(
+                Address::ZERO,
+                vec![topic, B256::repeat_byte(1)],
+                vec![1, 2, 3].into(),
+            )
+            .unwrap()
+        };
+
+        let unknown = log(B256::repeat_byte(0xab));
+        let Ok(DecodedLog::Unknown(event)) = decode_log(&unknown, false) else {
+            panic!("expected unknown event");
+        };
+        assert_eq!(event.topics, unknown.topics());
+        assert_eq!(event.data, unknown.data.data);
+        assert!(decode_log(&unknown, true).is_err());
+
+        // Known signature with malformed data is an error regardless of the mode
+        let malformed = log(Exchange::OrderPlaced::SIGNATURE_HASH);
+        assert!(decode_log(&malformed, false).is_err());
+    }
+
+    #[test]
+    fn account_filter() {
+        let place = |account| Action::Place {
+            perpetual: 0,
+            account,
+            is_bid: true,
+            offset: 0,
+            lots: 1,
+        };
+        let open = Action::Open {
+            perpetual: 0,
+            account: 1,
+            is_long: true,
+            lots: 1,
+        };
+        let events = || {
+            Simulator::new(&[1], 2)
+                .block(&[place(0), place(1), open.clone()])
+                .into_parts()
+                .0
+        };
+
+        // Order request and placement of the first account
+        let mut filtered = events();
+        EventFilter::all().with_accounts([1]).retain(&mut filtered);
+        assert_eq!(
+            filtered.iter().map(|e| e.tx_index()).collect::<Vec<_>>(),
+            vec![0, 0]
+        );
+
+        // Order request, placement and position opening of the second account
+        let mut filtered = events();
+        EventFilter::fills()
+            .with_accounts([2])
+            .retain(&mut filtered);
+        assert_eq!(
+            filtered.iter().map(|e| e.tx_index()).collect::<Vec<_>>(),
+            vec![1, 1, 2]
+        );
+    }
+
+    #[tokio::test]
+    async fn backpressure_policies() {
+        let numbers = || futures::stream::iter(1..=5u64);
+
+        let (tx, mut rx) = channel(2, Backpressure::DropOldest);
+        forward(numbers(), tx).await;
+        assert_eq!(rx.recv().await, Some(Delivery::Resync { dropped: 3 }));
+        assert_eq!(rx.recv().await, Some(Delivery::Item(4)));
+        assert_eq!(rx.recv().await, Some(Delivery::Item(5)));
+        assert_eq!(rx.recv().await, None);
+
+        let (tx, mut rx) = channel(2, Backpressure::Coalesce(|last, next| *last += next));
+        forward(numbers(), tx).await;
+        assert_eq!(rx.recv().await, Some(Delivery::Item(1)));
+        assert_eq!(rx.recv().await, Some(Delivery::Item(14)));
+        assert_eq!(rx.recv().await, None);
+
+        // Blocked sender resumes as the consumer catches up, stops once it is dropped
+        let (tx, mut rx) = channel(2, Backpressure::Block);
+        let sender = tokio::spawn(forward(numbers(), tx));
+        tokio::task::yield_now().await;
+        assert_eq!(rx.len(), 2);
+        assert_eq!(rx.recv().await, Some(Delivery::Item(1)));
+        assert_eq!(rx.recv().await, Some(Delivery::Item(2)));
+        assert_eq!(rx.recv().await, Some(Delivery::Item(3)));
+        drop(rx);
+        sender.await.unwrap();
+    }
+
+    #[test]
+    fn resume_token_format() {
+        let token = ResumeToken::new(12, Some(3), Some(B256::repeat_byte(0xab)));
+        assert_eq!(token.to_string().parse::<ResumeToken>().unwrap(), token);
+        let token = ResumeToken::from_instant(types::StateInstant::new(7, 70));
+        assert_eq!(token.to_string(), "7:-:-");
+        assert_eq!("7:-:-".parse::<ResumeToken>().unwrap(), token);
+        assert!("7:-".parse::<ResumeToken>().is_err());
+        assert!("7:x:-".parse::<ResumeToken>().is_err());
+    }
 
     #[tokio::test]
     async fn test_stream_recent_blocks() {
@@ -0,0 +1,297 @@
+//! Sweeping of free collateral to a cold wallet.
+//!
+//! [`run`] periodically withdraws the free collateral of the client account above
+//! the target buffer and transfers it to the cold wallet address. Free collateral is the
+//! account balance not locked by resting orders, deposits of open positions are never swept.
+//!
+//! Withdrawals respect the exchange-wide rate limit: every sweep is capped by the remaining
+//! withdrawal allowance, and once it is exhausted the sweeper stays idle until the limit
+//! expires or gets reset, as observed via [`state::ExchangeEvent::WithdrawRateLimitReset`].
+//!
+//! # Example
+//!
+//! ```ignore
+//! use dex_sdk::sweep::{self, SweepConfig, SweepReport};
+//!
+//! let mut client = DexClient::signing(chain, provider, address);
+//! client
+//!     .track(client.snapshot().with_accounts(vec![address]), tokio::time::sleep)
+//!     .await?;
+//!
+//! let config = SweepConfig::new(cold_wallet, udec128!(1000)).with_min_sweep(udec128!(100));
+//! let (mut reports, _) = sweep::run(client, config, tokio::time::sleep);
+//! while let Some(report) = reports.recv().await {
+//!     if let Ok(SweepReport::Swept { amount, .. }) = report {
+//!         println!("swept {amount}");
+//!     }
+//! }
+//! ```
+
+use std::time::Duration;
+
+use alloy::{
+    network::Ethereum,
+    primitives::{Address, TxHash, U256},
+    providers::{PendingTransactionBuilder, Provider},
+};
+use fastnum::UD128;
+use tokio::sync::mpsc;
+
+use crate::{
+    abi::dex::Exchange::ExchangeInstance,
+    client::{DexClient, Signing},
+    error::{DexError, RevertReason},
+    state,
+};
+
+/// Default interval between sweeps.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(60);
+
+const DEFAULT_CHANNEL_SIZE: usize = 16;
+
+/// Configuration of the sweeper.
+#[derive(Clone, Copy, derive_more::Debug)]
+pub struct SweepConfig {
+    cold_wallet: Address,
+    #[debug("{target_buffer}")]
+    target_buffer: UD128,
+    #[debug("{min_sweep}")]
+    min_sweep: UD128,
+    interval: Duration,
+}
+
+impl SweepConfig {
+    /// Sweep free collateral above the target buffer to the cold wallet.
+    pub fn new(cold_wallet: Address, target_buffer: UD128) -> Self {
+        Self {
+            cold_wallet,
+            target_buffer,
+            min_sweep: UD128::ZERO,
+            interval: DEFAULT_INTERVAL,
+        }
+    }
+
+    /// Minimal amount worth a sweep, smaller excess stays in the account (default: any).
+    pub fn with_min_sweep(mut self, min_sweep: UD128) -> Self {
+        self.min_sweep = min_sweep;
+        self
+    }
+
+    /// Interval between sweeps (default: 60s).
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn cold_wallet(&self) -> Address {
+        self.cold_wallet
+    }
+
+    pub fn target_buffer(&self) -> UD128 {
+        self.target_buffer
+    }
+
+    pub fn min_sweep(&self) -> UD128 {
+        self.min_sweep
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+/// Outcome of a single sweep.
+#[derive(Clone, derive_more::Debug)]
+pub enum SweepReport {
+    /// Free collateral above the target buffer is below the minimal sweep amount.
+    Idle {
+        #[debug("{sweepable}")]
+        sweepable: UD128,
+    },
+
+    /// Withdrawal allowance is below the minimal sweep amount until the rate limit expires
+    /// at the block, or gets reset earlier.
+    RateLimited {
+        #[debug("{sweepable}")]
+        sweepable: UD128,
+        until_block: u64,
+    },
+
+    /// Collateral withdrawn and transferred to the cold wallet.
+    Swept {
+        #[debug("{amount}")]
+        amount: UD128,
+        withdraw_tx: TxHash,
+        transfer_tx: TxHash,
+    },
+}
+
+/// Free collateral of the account above the target buffer, zero for frozen accounts.
+pub fn sweepable(account: &state::Account, target_buffer: UD128) -> UD128 {
+    let reserved = account.locked_balance() + target_buffer;
+    if account.frozen() || account.balance() <= reserved {
+        UD128::ZERO
+    } else {
+        account.balance() - reserved
+    }
+}
+
+/// Start the sweeping job of the client account.
+///
+/// The client has to track the exchange state including the account, see [`DexClient::track`].
+/// Failed sweeps get reported as errors and retried after the interval.
+///
+/// Job stops once the report receiver is dropped.
+pub fn run<P, S, SFut>(
+    client: DexClient<P, Signing>,
+    config: SweepConfig,
+    sleep: S,
+) -> (
+    mpsc::Receiver<Result<SweepReport, DexError>>,
+    tokio::task::JoinHandle<()>,
+)
+where
+    P: Provider + Clone + Send + Sync + 'static,
+    S: Fn(Duration) -> SFut + Send + 'static,
+    SFut: Future<Output = ()> + Send,
+{
+    let mut sweeper = Sweeper {
+        client,
+        config,
+        blocked: None,
+    };
+    let (tx, rx) = mpsc::channel(DEFAULT_CHANNEL_SIZE);
+    let handle = tokio::spawn(async move {
+        loop {
+            sleep(config.interval).await;
+            let result = sweeper.sweep().await;
+            if tx.send(result).await.is_err() {
+                // Receiver dropped, graceful shutdown
+                break;
+            }
+        }
+    });
+    (rx, handle)
+}
+
+/// Exhausted withdrawal allowance, with the rate limit known at the time.
+#[derive(Clone, Copy, Debug)]
+struct Blocked {
+    until_block: u64,
+    rate_limit: Option<state::WithdrawRateLimit>,
+}
+
+struct Sweeper<P> {
+    client: DexClient<P, Signing>,
+    config: SweepConfig,
+    blocked: Option<Blocked>,
+}
+
+impl<P: Provider + Clone> Sweeper<P> {
+    async fn sweep(&mut self) -> Result<SweepReport, DexError> {
+        let address = self.client.address();
+        let (block_number, sweepable, rate_limit, converter) = {
+            let exchange = self
+                .client
+                .state()
+                .ok_or_else(|| {
+                    DexError::InvalidRequest(
+                        "exchange state is not tracked by the client".to_string(),
+                    )
+                })?
+                .read()
+                .await;
+            let account = exchange.account_by_address(address).ok_or_else(|| {
+                DexError::InvalidRequest(format!("account of {address} is not tracked"))
+            })?;
+            (
+                exchange.instant().block_number(),
+                sweepable(account, self.config.target_buffer),
+                exchange.withdraw_rate_limit(),
+                exchange.collateral_converter(),
+            )
+        };
+        if sweepable.is_zero() || sweepable < self.config.min_sweep {
+            return Ok(SweepReport::Idle { sweepable });
+        }
+
+        // Query the allowance again only once the exhausted limit expires or gets reset
+        if let Some(blocked) = self.blocked
+            && block_number < blocked.until_block
+            && rate_limit == blocked.rate_limit
+        {
+            return Ok(SweepReport::RateLimited {
+                sweepable,
+                until_block: blocked.until_block,
+            });
+        }
+        self.blocked = None;
+
+        let allowance =
+            ExchangeInstance::new(self.client.chain().exchange(), self.client.provider())
+                .getWithdrawAllowanceData(U256::from(block_number))
+                .call()
+                .await?;
+        let amount = sweepable.min(converter.from_unsigned(allowance.allowanceCNS));
+        if amount.is_zero() || amount < self.config.min_sweep {
+            let blocked = Blocked {
+                until_block: allowance.expiryBlock.to(),
+                rate_limit,
+            };
+            self.blocked = Some(blocked);
+            return Ok(SweepReport::RateLimited {
+                sweepable,
+                until_block: blocked.until_block,
+            });
+        }
+
+        let withdraw_tx = confirm("withdrawal", self.client.withdraw(amount).await?).await?;
+        let transfer_tx = confirm(
+            "transfer",
+            self.client
+                .transfer_collateral(self.config.cold_wallet, amount)
+                .await?,
+        )
+        .await?;
+        Ok(SweepReport::Swept {
+            amount,
+            withdraw_tx,
+            transfer_tx,
+        })
+    }
+}
+
+/// Waits for the transaction to get included, failing if it reverted.
+async fn confirm(
+    what: &str,
+    pending: PendingTransactionBuilder<Ethereum>,
+) -> Result<TxHash, DexError> {
+    let receipt = pending.get_receipt().await?;
+    if !receipt.status() {
+        return Err(DexError::Reverted(Box::new(RevertReason::Generic(
+            format!("{what} {} failed", receipt.transaction_hash),
+        ))));
+    }
+    Ok(receipt.transaction_hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use fastnum::udec128;
+
+    use super::*;
+    use crate::types::StateInstant;
+
+    #[test]
+    fn sweepable_above_buffer() {
+        let instant = StateInstant::new(1, 0);
+        let mut account = state::Account::from_event(instant, 1, Address::ZERO);
+        account.update_balance(instant, udec128!(1000));
+        assert_eq!(sweepable(&account, udec128!(300)), udec128!(700));
+        assert_eq!(sweepable(&account, udec128!(1000)), UD128::ZERO);
+        assert_eq!(sweepable(&account, udec128!(1500)), UD128::ZERO);
+
+        account.update_locked_balance(instant, udec128!(200));
+        assert_eq!(sweepable(&account, udec128!(300)), udec128!(500));
+    }
+}
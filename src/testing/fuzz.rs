@@ -0,0 +1,217 @@
+//! Property testing harness for [`Exchange::apply_events`].
+//!
+//! [`blocks`] generates randomized [`Action`]s, which [`Simulator`] turns into
+//! contract-consistent raw event blocks.
+//!
+//! [`check_invariants`] verifies the exchange state consistency after each block.
+//!
+//! # Example
+//!
+//! ```ignore
+//! proptest! {
+//!     #[test]
+//!     fn my_property(blocks in fuzz::blocks(20)) {
+//!         let mut sim = Simulator::new(&[1, 2], 3);
+//!         let mut exchange = sim.exchange();
+//!         for actions in &blocks {
+//!             exchange.apply_events(&sim.block(actions)).unwrap();
+//!             fuzz::check_invariants(&exchange).unwrap();
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::collections::HashMap;
+
+use fastnum::{UD64, UD128};
+use proptest::prelude::*;
+
+use super::sim::MID_PRICE;
+pub use super::sim::{Action, Simulator};
+use crate::{
+    state::{Exchange, PositionAggregates, PositionType},
+    types,
+};
+
+/// Strategy generating a single [`Action`].
+pub fn action() -> impl Strategy<Value = Action> {
+    prop_oneof![
+        3 => (any::<usize>(), any::<usize>(), any::<bool>(), 0..MID_PRICE, 1..100u64).prop_map(
+            |(perpetual, account, is_bid, offset, lots)| Action::Place {
+                perpetual,
+                account,
+                is_bid,
+                offset,
+                lots,
+            }
+        ),
+        2 => (any::<usize>(), any::<usize>(), 1..100u64)
+            .prop_map(|(order, taker, lots)| Action::Fill { order, taker, lots }),
+        1 => any::<usize>().prop_map(|order| Action::Cancel { order }),
+        1 => (any::<usize>(), 0..MID_PRICE, 1..100u64)
+            .prop_map(|(order, offset, lots)| Action::Modify { order, offset, lots }),
+        1 => (any::<usize>(), any::<usize>(), any::<bool>(), 1..100u64).prop_map(
+            |(perpetual, account, is_long, lots)| Action::Open {
+                perpetual,
+                account,
+                is_long,
+                lots,
+            }
+        ),
+        1 => any::<usize>().prop_map(|position| Action::Close { position }),
+    ]
+}
+
+/// Strategy generating up to `max_blocks` blocks of up to 8 actions each.
+pub fn blocks(max_blocks: usize) -> impl Strategy<Value = Vec<Vec<Action>>> {
+    prop::collection::vec(prop::collection::vec(action(), 0..8), 1..=max_blocks)
+}
+
+/// Check exchange state invariants:
+/// * Aggregated book level sizes and order counts match L3 orders.
+/// * Locked balance of each account matches collateral locked by its resting orders,
+///   so it can never become negative.
+/// * Open interest of each perpetual contract matches the sum of long positions.
+/// * Positions indexed by perpetual contract and their aggregates match tracked positions.
+///
+/// Locked collateral is calculated as `price * size` of each order, as produced by [`Simulator`].
+pub fn check_invariants(exchange: &Exchange) -> Result<(), String> {
+    let mut locked = HashMap::<types::AccountId, UD128>::new();
+    for perp in exchange.perpetuals().values() {
+        let book = perp.l3_book();
+        let mut levels = HashMap::<(bool, UD64), (UD64, u32)>::new();
+        for order in book.all_orders().values() {
+            let level = levels
+                .entry((
+                    order.r#type().side() == types::OrderSide::Bid,
+                    order.price(),
+                ))
+                .or_default();
+            level.0 += order.size();
+            level.1 += 1;
+            *locked.entry(order.account_id()).or_default() +=
+                (order.price() * order.size()).resize();
+        }
+        let book_levels = book
+            .bids()
+            .iter()
+            .map(|(p, l)| ((true, p.0), l))
+            .chain(book.asks().iter().map(|(p, l)| ((false, *p), l)))
+            .map(|(key, level)| (key, (level.size(), level.num_orders())))
+            .collect::<Vec<_>>();
+        if book_levels.len() != levels.len() {
+            return Err(format!(
+                "perpetual {}: {} book levels, {} levels of L3 orders",
+                perp.id(),
+                book_levels.len(),
+                levels.len()
+            ));
+        }
+        for (key, (size, count)) in book_levels {
+            if levels.get(&key) != Some(&(size, count)) {
+                return Err(format!(
+                    "perpetual {}: level {key:?} has size {size} with {count} orders, L3 orders {:?}",
+                    perp.id(),
+                    levels.get(&key)
+                ));
+            }
+        }
+        let integrity = book.check_integrity();
+        if !integrity.is_ok() {
+            return Err(format!(
+                "perpetual {}: inconsistent book {:?}",
+                perp.id(),
+                integrity.issues
+            ));
+        }
+
+        let long_size = exchange
+            .accounts()
+            .values()
+            .filter_map(|acc| acc.positions().get(&perp.id()))
+            .filter(|pos| pos.r#type() == PositionType::Long)
+            .fold(UD128::ZERO, |acc, pos| acc + pos.size().resize());
+        if perp.open_interest() != long_size {
+            return Err(format!(
+                "perpetual {}: open interest {}, long positions {long_size}",
+                perp.id(),
+                perp.open_interest()
+            ));
+        }
+
+        let mut expected = PositionAggregates::default();
+        let mut expected_ids = Vec::new();
+        for acc in exchange.accounts().values() {
+            let Some(pos) = acc.positions().get(&perp.id()) else {
+                continue;
+            };
+            expected_ids.push(acc.id());
+            expected.positions += 1;
+            let notional = pos.entry_price().resize() * pos.size().resize();
+            if pos.r#type() == PositionType::Long {
+                expected.long_size += pos.size().resize();
+                expected.long_entry_notional += notional;
+            } else {
+                expected.short_size += pos.size().resize();
+                expected.short_entry_notional += notional;
+            }
+            expected.total_deposit += pos.deposit();
+        }
+        expected_ids.sort();
+        let (positions, aggregates) = exchange.positions_by_perpetual(perp.id());
+        let ids = positions.map(|pos| pos.account_id()).collect::<Vec<_>>();
+        if ids != expected_ids || aggregates != expected {
+            return Err(format!(
+                "perpetual {}: indexed positions {ids:?} {aggregates:?}, tracked {expected_ids:?} {expected:?}",
+                perp.id()
+            ));
+        }
+    }
+
+    for acc in exchange.accounts().values() {
+        let expected = locked.get(&acc.id()).copied().unwrap_or_default();
+        if acc.locked_balance() != expected {
+            return Err(format!(
+                "account {}: locked balance {}, locked by orders {expected}",
+                acc.id(),
+                acc.locked_balance()
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::DexError;
+
+    proptest! {
+        #[test]
+        fn apply_events_keeps_invariants(blocks in blocks(30)) {
+            let mut sim = Simulator::new(&[1, 2], 3);
+            let mut exchange = sim.exchange();
+            for actions in &blocks {
+                let events = sim.block(actions);
+                prop_assert!(exchange.apply_events(&events).map_err(|e| TestCaseError::fail(e.to_string()))?.is_some());
+                check_invariants(&exchange).map_err(TestCaseError::fail)?;
+            }
+        }
+
+        #[test]
+        fn parallel_apply_matches_serial(blocks in blocks(20)) {
+            let mut sim = Simulator::new(&[1, 2, 3, 4], 6);
+            let mut serial = sim.exchange_with_accounts(&[1, 2]);
+            let mut parallel = serial.clone();
+            parallel.set_parallel_apply(true);
+            for actions in &blocks {
+                let events = sim.block(actions);
+                let fail = |e: DexError| TestCaseError::fail(e.to_string());
+                let expected = serial.apply_events(&events).map_err(fail)?;
+                let actual = parallel.apply_events(&events).map_err(fail)?;
+                prop_assert_eq!(format!("{expected:?}"), format!("{actual:?}"));
+                prop_assert_eq!(serial.state_hash(), parallel.state_hash());
+            }
+        }
+    }
+}
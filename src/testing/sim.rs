@@ -0,0 +1,454 @@
+//! Synthetic exchange activity without a node.
+//!
+//! [`Simulator`] models a minimal exchange smart contract and turns [`Action`]s into
+//! contract-consistent raw event blocks: order placement, fills, changes and cancellation,
+//! opening and closing of positions, as well as mark price updates. Actions that are
+//! not applicable to the current simulated state are skipped, so any sequence produces
+//! a valid event stream.
+//!
+//! Intended for tests and benchmarks of the state tracking, see also `fuzz` module
+//! for randomized action sequences.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use dex_sdk::testing::sim::{Action, Simulator};
+//!
+//! let mut sim = Simulator::new(&[1], 2);
+//! let mut exchange = sim.exchange();
+//! let place = Action::Place { perpetual: 0, account: 0, is_bid: true, offset: 1, lots: 10 };
+//! exchange.apply_events(&sim.block(&[place]))?;
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+
+use alloy::primitives::{Address, I256, TxHash, U256};
+use fastnum::{UD64, UD128};
+
+use crate::{
+    Chain,
+    abi::dex::Exchange::{
+        ExchangeEvents, MakerOrderFilled, MarkUpdated, OrderCancelled, OrderChanged, OrderPlaced,
+        OrderRequest, PositionClosed, PositionOpened, TakerOrderFilled,
+    },
+    num,
+    state::{Account, Exchange, Perpetual},
+    stream, types,
+};
+
+/// Collateral balance of each simulated account at the start.
+const INITIAL_BALANCE: u64 = 1_000_000_000;
+
+/// Maintenance margin of simulated perpetual contracts, as maximal leverage.
+const MAINTENANCE_MARGIN: UD64 = fastnum::udec64!(20);
+
+/// Highest bid price, asks are always posted above it so the book never crosses.
+pub(crate) const MID_PRICE: u64 = 1_000;
+
+/// Single simulated user action, resulting in a single transaction.
+///
+/// Indices of accounts, perpetuals, orders and positions are taken modulo
+/// the number of currently available ones.
+#[derive(Clone, Debug)]
+pub enum Action {
+    /// Post a resting order `offset` ticks away from the mid price.
+    Place {
+        perpetual: usize,
+        account: usize,
+        is_bid: bool,
+        offset: u64,
+        lots: u64,
+    },
+    /// Take up to `lots` from the resting order.
+    Fill {
+        order: usize,
+        taker: usize,
+        lots: u64,
+    },
+    /// Cancel the resting order.
+    Cancel { order: usize },
+    /// Change price and size of the resting order, keeping its side.
+    Modify {
+        order: usize,
+        offset: u64,
+        lots: u64,
+    },
+    /// Open a position if the account has none in the perpetual contract.
+    Open {
+        perpetual: usize,
+        account: usize,
+        is_long: bool,
+        lots: u64,
+    },
+    /// Close the open position.
+    Close { position: usize },
+    /// Update the mark price of the perpetual contract, applied to all its positions.
+    Mark { perpetual: usize, price: u64 },
+}
+
+#[derive(Clone, Copy, Debug)]
+struct SimOrder {
+    account_id: types::AccountId,
+    is_bid: bool,
+    price: u64,
+    lots: u64,
+}
+
+/// Minimal model of the exchange smart contract producing raw events.
+#[derive(Clone, Debug)]
+pub struct Simulator {
+    perpetuals: Vec<types::PerpetualId>,
+    accounts: Vec<types::AccountId>,
+    instant: types::StateInstant,
+    balances: HashMap<types::AccountId, (u64, u64)>,
+    orders: BTreeMap<(types::PerpetualId, u16), SimOrder>,
+    next_order_id: HashMap<types::PerpetualId, u16>,
+    positions: BTreeMap<(types::AccountId, types::PerpetualId), (bool, u64)>,
+    next_request_id: u64,
+}
+
+impl Simulator {
+    /// Create a simulator of the given perpetual contracts and number of accounts,
+    /// with IDs starting from 1.
+    pub fn new(perpetuals: &[types::PerpetualId], accounts: u32) -> Self {
+        let accounts = (1..=accounts as types::AccountId).collect::<Vec<_>>();
+        Self {
+            perpetuals: perpetuals.to_vec(),
+            balances: accounts
+                .iter()
+                .map(|id| (*id, (INITIAL_BALANCE, 0)))
+                .collect(),
+            accounts,
+            instant: types::StateInstant::new(1, 1),
+            orders: BTreeMap::new(),
+            next_order_id: perpetuals.iter().map(|id| (*id, 1)).collect(),
+            positions: BTreeMap::new(),
+            next_request_id: 1,
+        }
+    }
+
+    /// Exchange state consistent with the simulator initial state,
+    /// tracking all perpetual contracts and accounts.
+    pub fn exchange(&self) -> Exchange {
+        self.exchange_with_accounts(&self.accounts)
+    }
+
+    /// Exchange state consistent with the simulator initial state,
+    /// tracking all perpetual contracts and only the given accounts.
+    pub fn exchange_with_accounts(&self, tracked: &[types::AccountId]) -> Exchange {
+        let cc = num::Converter::new(0);
+        Exchange::new(
+            Chain::custom(0, Address::ZERO, 0, Address::ZERO, self.perpetuals.clone()),
+            self.instant,
+            cc,
+            100,
+            UD128::ZERO,
+            UD128::ZERO,
+            UD128::ZERO,
+            self.perpetuals
+                .iter()
+                .map(|id| {
+                    let mut perp = Perpetual::for_testing(*id);
+                    perp.update_maintenance_margin(self.instant, MAINTENANCE_MARGIN);
+                    (*id, perp)
+                })
+                .collect(),
+            tracked
+                .iter()
+                .map(|id| {
+                    let mut acc =
+                        Account::from_event(self.instant, *id, Address::with_last_byte(*id as u8));
+                    acc.update_balance(self.instant, cc.from_u64(INITIAL_BALANCE));
+                    (*id, acc)
+                })
+                .collect(),
+            false,
+            true,
+        )
+    }
+
+    /// Produce the next block with events of the given actions.
+    pub fn block(&mut self, actions: &[Action]) -> stream::RawBlockEvents {
+        self.instant = types::StateInstant::new(
+            self.instant.block_number() + 1,
+            self.instant.block_timestamp() + 1,
+        );
+        let mut events = vec![];
+        for (tx_index, action) in actions.iter().enumerate() {
+            for e in self.apply(action) {
+                events.push(stream::RawEvent::new(
+                    TxHash::with_last_byte(tx_index as u8),
+                    tx_index as u64,
+                    events.len() as u64,
+                    Address::ZERO,
+                    e,
+                ));
+            }
+        }
+        stream::RawBlockEvents::new(self.instant, events)
+    }
+
+    fn apply(&mut self, action: &Action) -> Vec<ExchangeEvents> {
+        match *action {
+            Action::Place {
+                perpetual,
+                account,
+                is_bid,
+                offset,
+                lots,
+            } => {
+                let perp_id = self.perpetuals[perpetual % self.perpetuals.len()];
+                let account_id = self.accounts[account % self.accounts.len()];
+                let next_id = self
+                    .next_order_id
+                    .get_mut(&perp_id)
+                    .expect("known perpetual");
+                let order_id = *next_id;
+                *next_id += 1;
+                let price = price(is_bid, offset);
+                self.orders.insert(
+                    (perp_id, order_id),
+                    SimOrder {
+                        account_id,
+                        is_bid,
+                        price,
+                        lots,
+                    },
+                );
+                let amount = price * lots;
+                let (balance, locked) = self.move_balance(account_id, amount, true);
+                vec![
+                    self.request(
+                        perp_id,
+                        account_id,
+                        0,
+                        if is_bid { 0 } else { 1 },
+                        price,
+                        lots,
+                    ),
+                    ExchangeEvents::OrderPlaced(OrderPlaced {
+                        orderId: U256::from(order_id),
+                        lotLNS: U256::from(lots),
+                        lockedBalanceCNS: U256::from(locked),
+                        amountCNS: I256::try_from(amount).expect("fits") * I256::MINUS_ONE,
+                        balanceCNS: U256::from(balance),
+                    }),
+                ]
+            }
+            Action::Fill { order, taker, lots } => {
+                let Some((&(perp_id, order_id), &maker)) = self.nth_order(order) else {
+                    return vec![];
+                };
+                let taker_id = self.accounts[taker % self.accounts.len()];
+                let lots = lots.min(maker.lots);
+                if maker.lots == lots {
+                    self.orders.remove(&(perp_id, order_id));
+                } else {
+                    self.orders
+                        .get_mut(&(perp_id, order_id))
+                        .expect("order exists")
+                        .lots -= lots;
+                }
+                let amount = maker.price * lots;
+                let (maker_balance, maker_locked) =
+                    self.move_balance(maker.account_id, amount, false);
+                let (taker_balance, _) = self.balances[&taker_id];
+                vec![
+                    self.request(
+                        perp_id,
+                        taker_id,
+                        0,
+                        if maker.is_bid { 1 } else { 0 },
+                        maker.price,
+                        lots,
+                    ),
+                    ExchangeEvents::MakerOrderFilled(MakerOrderFilled {
+                        perpId: U256::from(perp_id),
+                        accountId: U256::from(maker.account_id),
+                        orderId: U256::from(order_id),
+                        pricePNS: U256::from(maker.price),
+                        lotLNS: U256::from(lots),
+                        feeCNS: U256::ZERO,
+                        lockedBalanceCNS: U256::from(maker_locked),
+                        amountCNS: I256::try_from(amount).expect("fits"),
+                        balanceCNS: U256::from(maker_balance),
+                    }),
+                    ExchangeEvents::TakerOrderFilled(TakerOrderFilled {
+                        pricePNS: U256::from(maker.price),
+                        lotLNS: U256::from(lots),
+                        feeCNS: U256::ZERO,
+                        amountCNS: I256::ZERO,
+                        balanceCNS: U256::from(taker_balance),
+                    }),
+                ]
+            }
+            Action::Cancel { order } => {
+                let Some((&(perp_id, order_id), _)) = self.nth_order(order) else {
+                    return vec![];
+                };
+                let order = self
+                    .orders
+                    .remove(&(perp_id, order_id))
+                    .expect("order exists");
+                let amount = order.price * order.lots;
+                let (balance, locked) = self.move_balance(order.account_id, amount, false);
+                vec![
+                    self.request(perp_id, order.account_id, order_id, 4, 0, 0),
+                    ExchangeEvents::OrderCancelled(OrderCancelled {
+                        lockedBalanceCNS: U256::from(locked),
+                        amountCNS: I256::try_from(amount).expect("fits"),
+                        balanceCNS: U256::from(balance),
+                    }),
+                ]
+            }
+            Action::Modify {
+                order,
+                offset,
+                lots,
+            } => {
+                let Some((&(perp_id, order_id), &prev)) = self.nth_order(order) else {
+                    return vec![];
+                };
+                let price = price(prev.is_bid, offset);
+                self.orders.insert(
+                    (perp_id, order_id),
+                    SimOrder {
+                        price,
+                        lots,
+                        ..prev
+                    },
+                );
+                self.move_balance(prev.account_id, prev.price * prev.lots, false);
+                let (balance, locked) = self.move_balance(prev.account_id, price * lots, true);
+                vec![
+                    self.request(perp_id, prev.account_id, order_id, 6, price, lots),
+                    ExchangeEvents::OrderChanged(OrderChanged {
+                        orderId: U256::from(order_id),
+                        pricePNS: U256::from(price),
+                        lotLNS: U256::from(lots),
+                        expiryBlock: U256::ZERO,
+                        lockedBalanceCNS: U256::from(locked),
+                        balanceCNS: U256::from(balance),
+                    }),
+                ]
+            }
+            Action::Open {
+                perpetual,
+                account,
+                is_long,
+                lots,
+            } => {
+                let perp_id = self.perpetuals[perpetual % self.perpetuals.len()];
+                let account_id = self.accounts[account % self.accounts.len()];
+                if self.positions.contains_key(&(account_id, perp_id)) {
+                    return vec![];
+                }
+                self.positions
+                    .insert((account_id, perp_id), (is_long, lots));
+                vec![ExchangeEvents::PositionOpened(PositionOpened {
+                    perpId: U256::from(perp_id),
+                    accountId: U256::from(account_id),
+                    positionType: position_type(is_long),
+                    leverageHdths: U256::from(100),
+                    depositCNS: U256::from(MID_PRICE * lots),
+                    pricePNS: U256::from(MID_PRICE),
+                    lotLNS: U256::from(lots),
+                })]
+            }
+            Action::Close { position } => {
+                if self.positions.is_empty() {
+                    return vec![];
+                }
+                let key = *self
+                    .positions
+                    .keys()
+                    .nth(position % self.positions.len())
+                    .expect("index in range");
+                let (is_long, _) = self.positions.remove(&key).expect("position exists");
+                vec![ExchangeEvents::PositionClosed(PositionClosed {
+                    perpId: U256::from(key.1),
+                    accountId: U256::from(key.0),
+                    positionType: position_type(is_long),
+                    pricePNS: U256::from(MID_PRICE),
+                    deltaPnlCNS: I256::ZERO,
+                    fundingCNS: I256::ZERO,
+                })]
+            }
+            Action::Mark { perpetual, price } => {
+                let perp_id = self.perpetuals[perpetual % self.perpetuals.len()];
+                vec![ExchangeEvents::MarkUpdated(MarkUpdated {
+                    perpId: U256::from(perp_id),
+                    pricePNS: U256::from(price),
+                })]
+            }
+        }
+    }
+
+    fn nth_order(&self, index: usize) -> Option<(&(types::PerpetualId, u16), &SimOrder)> {
+        if self.orders.is_empty() {
+            return None;
+        }
+        self.orders.iter().nth(index % self.orders.len())
+    }
+
+    /// Move the amount between available and locked balance of the account,
+    /// returning the resulting `(balance, locked)` pair.
+    fn move_balance(
+        &mut self,
+        account_id: types::AccountId,
+        amount: u64,
+        lock: bool,
+    ) -> (u64, u64) {
+        let (balance, locked) = self.balances.get_mut(&account_id).expect("known account");
+        if lock {
+            *balance -= amount;
+            *locked += amount;
+        } else {
+            *balance += amount;
+            *locked -= amount;
+        }
+        (*balance, *locked)
+    }
+
+    fn request(
+        &mut self,
+        perp_id: types::PerpetualId,
+        account_id: types::AccountId,
+        order_id: u16,
+        order_type: u8,
+        price: u64,
+        lots: u64,
+    ) -> ExchangeEvents {
+        let request_id = self.next_request_id;
+        self.next_request_id += 1;
+        ExchangeEvents::OrderRequest(OrderRequest {
+            perpId: U256::from(perp_id),
+            accountId: U256::from(account_id),
+            orderDescId: U256::from(request_id),
+            orderId: U256::from(order_id),
+            orderType: order_type,
+            pricePNS: U256::from(price),
+            lotLNS: U256::from(lots),
+            expiryBlock: U256::ZERO,
+            postOnly: false,
+            fillOrKill: false,
+            immediateOrCancel: false,
+            maxMatches: U256::ZERO,
+            leverageHdths: U256::from(100),
+            gasLeft: U256::ZERO,
+        })
+    }
+}
+
+/// Price `offset` ticks away from the mid price, asks are posted above the highest bid.
+fn price(is_bid: bool, offset: u64) -> u64 {
+    if is_bid {
+        MID_PRICE - offset
+    } else {
+        MID_PRICE + 1 + offset
+    }
+}
+
+fn position_type(is_long: bool) -> u8 {
+    if is_long { 0 } else { 1 }
+}
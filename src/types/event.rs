@@ -1,10 +1,33 @@
-use alloy::primitives::TxHash;
+use std::ops::Range;
+
+use alloy::primitives::{Address, B256, Bytes, TxHash};
+
+/// Bits of the sequence number holding the index of the event within its block,
+/// see [`crate::state::StateBlockEvents`].
+const SEQUENCE_BLOCK_SHIFT: u32 = 32;
 
 /// Events from a specific block.
+///
+/// Contexts produced from a single log can hold more than one event, in which case
+/// events of all contexts are kept in one list and each context refers to its events
+/// by range, see [`Self::events_of`].
 #[derive(Debug)]
-pub struct BlockEvents<T> {
+pub struct BlockEvents<T, E = ()> {
     instant: super::StateInstant,
     events: Vec<T>,
+    buffer: Vec<E>,
+    unknown: Vec<EventContext<UnknownEvent>>,
+}
+
+/// Log of the exchange not matching any known event, e.g. an event added
+/// by a contract upgrade not supported by this version of the SDK yet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownEvent {
+    /// Topics of the log, the first one being the event signature hash.
+    pub topics: Vec<B256>,
+
+    /// ABI-encoded non-indexed event fields.
+    pub data: Bytes,
 }
 
 /// Event along with transaction context.
@@ -13,14 +36,76 @@ pub struct EventContext<T> {
     pub(crate) tx_hash: TxHash,
     pub(crate) tx_index: u64,
     pub(crate) log_index: u64,
+    pub(crate) address: Address,
     pub(crate) event: T,
+    pub(crate) sequence_number: u64,
+}
+
+/// Provenance of an event: the log it was decoded from.
+///
+/// Along with the block number, log index uniquely identifies the event
+/// and can be used for deduplication.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LogMeta {
+    /// Hash of the transaction emitted the log.
+    pub tx_hash: TxHash,
+
+    /// Index of the transaction within the block.
+    pub tx_index: u64,
+
+    /// Index of the log within the block.
+    pub log_index: u64,
+
+    /// Address of the contract emitted the log.
+    pub address: Address,
+}
+
+/// Identifier of a fill: the log of the maker or taker fill event.
+///
+/// Shared by [`crate::state::OrderEventType::Filled`] and [`crate::fill::MakerFill`]/
+/// [`crate::fill::TakerTrade`], so outputs of both pipelines can be joined.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FillId {
+    /// Hash of the transaction emitted the fill log.
+    pub tx_hash: TxHash,
+
+    /// Index of the fill log within the block.
+    pub log_index: u64,
+}
+
+impl FillId {
+    pub fn new(tx_hash: TxHash, log_index: u64) -> Self {
+        Self { tx_hash, log_index }
+    }
+}
+
+impl std::fmt::Display for FillId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.tx_hash, self.log_index)
+    }
 }
 
 impl<T> BlockEvents<T> {
     pub(crate) fn new(instant: super::StateInstant, events: Vec<T>) -> Self {
-        Self { instant, events }
+        Self {
+            instant,
+            events,
+            buffer: vec![],
+            unknown: vec![],
+        }
     }
 
+    pub(crate) fn with_unknown_events(mut self, unknown: Vec<EventContext<UnknownEvent>>) -> Self {
+        self.unknown = unknown;
+        self
+    }
+
+    pub(crate) fn into_parts(self) -> (Vec<T>, Vec<EventContext<UnknownEvent>>) {
+        (self.events, self.unknown)
+    }
+}
+
+impl<T, E> BlockEvents<T, E> {
     /// Instant the events produced at.
     pub fn instant(&self) -> super::StateInstant {
         self.instant
@@ -30,15 +115,81 @@ impl<T> BlockEvents<T> {
     pub fn events(&self) -> &[T] {
         &self.events
     }
+
+    /// Logs of the block not matching any known event, in order.
+    ///
+    /// Only produced by raw event streams not in strict decoding mode,
+    /// see [`crate::stream::EventFilter::with_strict_decoding`].
+    pub fn unknown_events(&self) -> &[EventContext<UnknownEvent>] {
+        &self.unknown
+    }
+}
+
+impl<T> BlockEvents<EventContext<Range<usize>>, T> {
+    /// Wraps contexts referring to their events in the given list by range.
+    pub(crate) fn with_buffer(
+        instant: super::StateInstant,
+        events: Vec<EventContext<Range<usize>>>,
+        buffer: Vec<T>,
+    ) -> Self {
+        Self {
+            instant,
+            events,
+            buffer,
+            unknown: vec![],
+        }
+    }
+
+    /// Events of the context, which must be one of [`Self::events`].
+    pub fn events_of(&self, ctx: &EventContext<Range<usize>>) -> &[T] {
+        &self.buffer[ctx.event.clone()]
+    }
+
+    /// Iterates over all events of the block along with the metadata
+    /// of the logs they originate from.
+    pub fn flat_events(&self) -> impl Iterator<Item = (LogMeta, &T)> {
+        self.events
+            .iter()
+            .flat_map(|ctx| self.events_of(ctx).iter().map(move |e| (ctx.meta(), e)))
+    }
+
+    /// Iterates over all events of the block along with their sequence numbers,
+    /// see [`crate::state::StateBlockEvents`].
+    pub fn sequenced_events(&self) -> impl Iterator<Item = (u64, &T)> {
+        self.events.iter().flat_map(|ctx| {
+            self.events_of(ctx)
+                .iter()
+                .enumerate()
+                .map(move |(i, e)| (ctx.sequence_number + i as u64, e))
+        })
+    }
+
+    /// Numbers the events consecutively, starting from the first sequence number
+    /// of the block, see [`crate::state::StateBlockEvents`].
+    pub(crate) fn assign_sequence_numbers(&mut self) {
+        let mut next = self.instant.block_number() << SEQUENCE_BLOCK_SHIFT;
+        for ctx in self.events.iter_mut() {
+            ctx.sequence_number = next;
+            next += ctx.event.len() as u64;
+        }
+    }
 }
 
 impl<T> EventContext<T> {
-    pub(crate) fn new(tx_hash: TxHash, tx_index: u64, log_index: u64, event: T) -> Self {
+    pub(crate) fn new(
+        tx_hash: TxHash,
+        tx_index: u64,
+        log_index: u64,
+        address: Address,
+        event: T,
+    ) -> Self {
         Self {
             tx_hash,
             tx_index,
             log_index,
+            address,
             event,
+            sequence_number: 0,
         }
     }
 
@@ -47,7 +198,9 @@ impl<T> EventContext<T> {
             tx_hash: TxHash::ZERO,
             tx_index: 0,
             log_index: 0,
+            address: Address::ZERO,
             event,
+            sequence_number: 0,
         }
     }
 
@@ -63,16 +216,44 @@ impl<T> EventContext<T> {
         self.log_index
     }
 
+    /// Address of the contract emitted the log.
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// Identifier of the fill, for maker and taker fill events.
+    pub fn fill_id(&self) -> FillId {
+        FillId::new(self.tx_hash, self.log_index)
+    }
+
+    /// Metadata of the log the event originates from.
+    pub fn meta(&self) -> LogMeta {
+        LogMeta {
+            tx_hash: self.tx_hash,
+            tx_index: self.tx_index,
+            log_index: self.log_index,
+            address: self.address,
+        }
+    }
+
     pub fn event(&self) -> &T {
         &self.event
     }
 
+    /// Sequence number of the (first) state event in the context, strictly increasing
+    /// across blocks, see [`crate::state::StateBlockEvents`]. Zero for raw events.
+    pub fn sequence_number(&self) -> u64 {
+        self.sequence_number
+    }
+
     pub(crate) fn pass<O>(&self, other: O) -> EventContext<O> {
         EventContext {
             tx_hash: self.tx_hash,
             tx_index: self.tx_index,
             log_index: self.log_index,
+            address: self.address,
             event: other,
+            sequence_number: self.sequence_number,
         }
     }
 }
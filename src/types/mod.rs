@@ -1,10 +1,17 @@
 mod event;
 mod order;
+mod preset;
 mod request;
+mod status;
 
 pub use event::*;
 pub use order::{OrderSide, OrderType};
-pub use request::{OrderRequest, RequestType};
+pub use preset::{
+    Execution, FillOrKill, GoodTillCancel, ImmediateOrCancel, Matching, OrderBuilder,
+    OrderDefaults, OrderPresets, PostOnly, Resting,
+};
+pub use request::{ModificationEffect, ModificationError, OrderRequest, RequestType};
+pub use status::{FreezeStatus, ResultCode};
 
 /// ID of perpetual contract.
 pub type PerpetualId = u32;
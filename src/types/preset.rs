@@ -0,0 +1,405 @@
+use std::{collections::HashMap, marker::PhantomData};
+
+use fastnum::UD64;
+
+use super::*;
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Execution policy of the order placed with [`OrderBuilder`].
+pub trait Execution: sealed::Sealed {
+    #[doc(hidden)]
+    const POST_ONLY: bool;
+    #[doc(hidden)]
+    const FILL_OR_KILL: bool;
+    #[doc(hidden)]
+    const IMMEDIATE_OR_CANCEL: bool;
+    #[doc(hidden)]
+    const RESTING: bool;
+}
+
+/// Execution policies letting the order rest on the book, so it can have an expiry.
+pub trait Resting: Execution {}
+
+/// Execution policies letting the order match on placement, so matches can be limited.
+pub trait Matching: Execution {}
+
+/// Order matches on placement and the rest of it stays on the book until cancelled
+/// or expired.
+#[derive(Clone, Copy, Debug)]
+pub struct GoodTillCancel;
+
+/// Order must not match on placement, see [`OrderRequest::limit_post_only`].
+#[derive(Clone, Copy, Debug)]
+pub struct PostOnly;
+
+/// Unfilled part of the order gets cancelled, see [`OrderRequest::ioc`].
+#[derive(Clone, Copy, Debug)]
+pub struct ImmediateOrCancel;
+
+/// Order gets completely filled on placement or rejected, see [`OrderRequest::fok`].
+#[derive(Clone, Copy, Debug)]
+pub struct FillOrKill;
+
+macro_rules! execution {
+    ($type:ty, $post_only:expr, $fill_or_kill:expr, $immediate_or_cancel:expr, $resting:expr) => {
+        impl sealed::Sealed for $type {}
+        impl Execution for $type {
+            const POST_ONLY: bool = $post_only;
+            const FILL_OR_KILL: bool = $fill_or_kill;
+            const IMMEDIATE_OR_CANCEL: bool = $immediate_or_cancel;
+            const RESTING: bool = $resting;
+        }
+    };
+}
+
+execution!(GoodTillCancel, false, false, false, true);
+execution!(PostOnly, true, false, false, true);
+execution!(ImmediateOrCancel, false, false, true, false);
+execution!(FillOrKill, false, true, false, false);
+
+impl Resting for GoodTillCancel {}
+impl Resting for PostOnly {}
+impl Matching for GoodTillCancel {}
+impl Matching for ImmediateOrCancel {}
+impl Matching for FillOrKill {}
+
+/// Default leverage and expiry of orders in a perpetual contract,
+/// applied by [`OrderBuilder::build_with`] to parameters not set explicitly,
+/// along with the execution policy of [`OrderPresets::buy`] and [`OrderPresets::sell`].
+#[derive(Clone, Copy, derive_more::Debug)]
+pub struct OrderDefaults {
+    #[debug("{leverage}")]
+    leverage: UD64,
+    expiry_blocks: Option<u64>,
+    post_only: bool,
+}
+
+impl OrderDefaults {
+    /// Create defaults with the given leverage and resting orders never expiring.
+    pub fn new(leverage: UD64) -> Self {
+        Self {
+            leverage,
+            expiry_blocks: None,
+            post_only: false,
+        }
+    }
+
+    /// Shorthand orders are post-only instead of good-till-cancel.
+    pub fn with_post_only(mut self, post_only: bool) -> Self {
+        self.post_only = post_only;
+        self
+    }
+
+    /// Resting orders expire the given number of blocks after the block they are built at.
+    pub fn with_expiry_blocks(mut self, expiry_blocks: u64) -> Self {
+        self.expiry_blocks = Some(expiry_blocks);
+        self
+    }
+
+    /// Default leverage.
+    pub fn leverage(&self) -> UD64 {
+        self.leverage
+    }
+
+    /// Default lifetime of resting orders in blocks.
+    pub fn expiry_blocks(&self) -> Option<u64> {
+        self.expiry_blocks
+    }
+
+    /// Indicates shorthand orders are post-only.
+    pub fn post_only(&self) -> bool {
+        self.post_only
+    }
+}
+
+/// [`OrderDefaults`] configured per perpetual contract, with the fallback
+/// for the rest of them.
+#[derive(Clone, Debug)]
+pub struct OrderPresets {
+    fallback: OrderDefaults,
+    perpetuals: HashMap<PerpetualId, OrderDefaults>,
+}
+
+impl OrderPresets {
+    /// Create presets applying the given defaults to all perpetual contracts.
+    pub fn new(fallback: OrderDefaults) -> Self {
+        Self {
+            fallback,
+            perpetuals: HashMap::new(),
+        }
+    }
+
+    /// Override the defaults for the perpetual contract.
+    pub fn with_perpetual(mut self, perp_id: PerpetualId, defaults: OrderDefaults) -> Self {
+        self.perpetuals.insert(perp_id, defaults);
+        self
+    }
+
+    /// Defaults of the perpetual contract.
+    pub fn get(&self, perp_id: PerpetualId) -> &OrderDefaults {
+        self.perpetuals.get(&perp_id).unwrap_or(&self.fallback)
+    }
+
+    /// Limit order request opening/increasing a long position, filled in with
+    /// the defaults of the perpetual contract as of the given block number.
+    pub fn buy(
+        &self,
+        request_id: RequestId,
+        perp_id: PerpetualId,
+        price: UD64,
+        size: UD64,
+        block_number: u64,
+    ) -> OrderRequest {
+        self.order(
+            request_id,
+            perp_id,
+            OrderType::OpenLong,
+            price,
+            size,
+            block_number,
+        )
+    }
+
+    /// Limit order request opening/increasing a short position, filled in with
+    /// the defaults of the perpetual contract as of the given block number.
+    pub fn sell(
+        &self,
+        request_id: RequestId,
+        perp_id: PerpetualId,
+        price: UD64,
+        size: UD64,
+        block_number: u64,
+    ) -> OrderRequest {
+        self.order(
+            request_id,
+            perp_id,
+            OrderType::OpenShort,
+            price,
+            size,
+            block_number,
+        )
+    }
+
+    fn order(
+        &self,
+        request_id: RequestId,
+        perp_id: PerpetualId,
+        r#type: OrderType,
+        price: UD64,
+        size: UD64,
+        block_number: u64,
+    ) -> OrderRequest {
+        if self.get(perp_id).post_only {
+            OrderRequest::limit_post_only(request_id, perp_id, r#type, price, size)
+                .build_with(self, block_number)
+        } else {
+            OrderRequest::limit(request_id, perp_id, r#type, price, size)
+                .build_with(self, block_number)
+        }
+    }
+}
+
+impl Default for OrderPresets {
+    /// Presets equivalent to [`OrderBuilder::build`]: zero leverage, no expiry,
+    /// good-till-cancel shorthand orders.
+    fn default() -> Self {
+        Self::new(OrderDefaults::new(UD64::ZERO))
+    }
+}
+
+/// Typed builder of order placement requests, created with [`OrderRequest::limit`],
+/// [`OrderRequest::limit_post_only`], [`OrderRequest::ioc`] or [`OrderRequest::fok`].
+///
+/// Execution policy is a part of the builder type and is chosen once by the constructor,
+/// so invalid combinations of order flags and parameters do not compile:
+///
+/// ```compile_fail
+/// # use dex_sdk::types::{OrderRequest, OrderType};
+/// # use fastnum::udec64;
+/// // Immediate-or-cancel orders never rest on the book, so can not have an expiry
+/// OrderRequest::ioc(1, 1, OrderType::OpenLong, udec64!(100), udec64!(1)).gtd(100);
+/// ```
+#[derive(Clone, Copy, derive_more::Debug)]
+pub struct OrderBuilder<E: Execution> {
+    request_id: RequestId,
+    perp_id: PerpetualId,
+    r#type: OrderType,
+    #[debug("{price}")]
+    price: UD64,
+    #[debug("{size}")]
+    size: UD64,
+    expiry_block: Option<u64>,
+    max_matches: Option<u32>,
+    #[debug("{:?}", leverage.map(|v| format!("{v}")))]
+    leverage: Option<UD64>,
+    last_exec_block: Option<u64>,
+    #[debug(skip)]
+    execution: PhantomData<E>,
+}
+
+impl<E: Execution> OrderBuilder<E> {
+    pub(crate) fn new(
+        request_id: RequestId,
+        perp_id: PerpetualId,
+        r#type: OrderType,
+        price: UD64,
+        size: UD64,
+    ) -> Self {
+        Self {
+            request_id,
+            perp_id,
+            r#type,
+            price,
+            size,
+            expiry_block: None,
+            max_matches: None,
+            leverage: None,
+            last_exec_block: None,
+            execution: PhantomData,
+        }
+    }
+
+    /// Leverage of the position the order opens/increases.
+    pub fn with_leverage(mut self, leverage: UD64) -> Self {
+        self.leverage = Some(leverage);
+        self
+    }
+
+    /// Last block the request can be executed at.
+    pub fn with_last_exec_block(mut self, last_exec_block: u64) -> Self {
+        self.last_exec_block = Some(last_exec_block);
+        self
+    }
+
+    /// Build the request, with zero leverage unless set explicitly.
+    pub fn build(self) -> OrderRequest {
+        let (leverage, expiry_block) = (self.leverage.unwrap_or_default(), self.expiry_block);
+        self.build_request(leverage, expiry_block)
+    }
+
+    /// Build the request, applying defaults of the perpetual contract to the leverage
+    /// and expiry not set explicitly. Expiry is relative to the given block number,
+    /// and applies to resting orders only.
+    pub fn build_with(self, presets: &OrderPresets, block_number: u64) -> OrderRequest {
+        let defaults = presets.get(self.perp_id);
+        let expiry_block = self.expiry_block.or(defaults
+            .expiry_blocks
+            .filter(|_| E::RESTING)
+            .map(|blocks| block_number + blocks));
+        let leverage = self.leverage.unwrap_or(defaults.leverage);
+        self.build_request(leverage, expiry_block)
+    }
+
+    fn build_request(self, leverage: UD64, expiry_block: Option<u64>) -> OrderRequest {
+        OrderRequest::new(
+            self.request_id,
+            self.perp_id,
+            self.r#type.into(),
+            None,
+            self.price,
+            self.size,
+            expiry_block,
+            E::POST_ONLY,
+            E::FILL_OR_KILL,
+            E::IMMEDIATE_OR_CANCEL,
+            self.max_matches,
+            leverage,
+            self.last_exec_block,
+            None,
+        )
+    }
+}
+
+impl<E: Resting> OrderBuilder<E> {
+    /// Good till the given block: the order expires at it.
+    pub fn gtd(mut self, expiry_block: u64) -> Self {
+        self.expiry_block = Some(expiry_block);
+        self
+    }
+}
+
+impl<E: Matching> OrderBuilder<E> {
+    /// Maximal number of matches against resting orders.
+    pub fn with_max_matches(mut self, max_matches: u32) -> Self {
+        self.max_matches = Some(max_matches);
+        self
+    }
+}
+
+impl<E: Execution> From<OrderBuilder<E>> for OrderRequest {
+    fn from(builder: OrderBuilder<E>) -> Self {
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fastnum::udec64;
+
+    use super::*;
+
+    #[test]
+    fn presets() {
+        let presets = OrderPresets::new(OrderDefaults::new(udec64!(5)))
+            .with_perpetual(2, OrderDefaults::new(udec64!(10)).with_expiry_blocks(100));
+
+        let req =
+            OrderRequest::limit_post_only(1, 2, OrderType::OpenLong, udec64!(100), udec64!(1))
+                .build_with(&presets, 1000);
+        assert!(req.post_only() && !req.immediate_or_cancel() && !req.fill_or_kill());
+        assert_eq!(req.leverage(), udec64!(10));
+        assert_eq!(req.expiry_block(), Some(1100));
+
+        // Explicit parameters take precedence
+        let req = OrderRequest::limit(2, 2, OrderType::OpenShort, udec64!(100), udec64!(1))
+            .gtd(1050)
+            .with_leverage(udec64!(2))
+            .build_with(&presets, 1000);
+        assert_eq!(req.expiry_block(), Some(1050));
+        assert_eq!(req.leverage(), udec64!(2));
+
+        // No expiry for orders not resting on the book
+        let req = OrderRequest::ioc(3, 2, OrderType::CloseLong, udec64!(100), udec64!(1))
+            .with_max_matches(3)
+            .build_with(&presets, 1000);
+        assert!(req.immediate_or_cancel());
+        assert_eq!(req.expiry_block(), None);
+        assert_eq!(req.max_matches(), Some(3));
+
+        let req: OrderRequest =
+            OrderRequest::fok(4, 1, OrderType::OpenLong, udec64!(100), udec64!(1)).into();
+        assert!(req.fill_or_kill());
+        assert_eq!(req.leverage(), UD64::ZERO);
+        assert_eq!(
+            OrderRequest::fok(4, 1, OrderType::OpenLong, udec64!(100), udec64!(1))
+                .build_with(&presets, 1000)
+                .leverage(),
+            udec64!(5)
+        );
+    }
+
+    #[test]
+    fn shorthand_orders() {
+        let presets = OrderPresets::default().with_perpetual(
+            2,
+            OrderDefaults::new(udec64!(10))
+                .with_expiry_blocks(100)
+                .with_post_only(true),
+        );
+
+        let buy = presets.buy(1, 2, udec64!(100), udec64!(1), 1000);
+        assert!(matches!(buy.r#type(), RequestType::OpenLong));
+        assert!(buy.post_only());
+        assert_eq!(buy.leverage(), udec64!(10));
+        assert_eq!(buy.expiry_block(), Some(1100));
+
+        let sell = presets.sell(2, 1, udec64!(100), udec64!(1), 1000);
+        assert!(matches!(sell.r#type(), RequestType::OpenShort));
+        assert!(!sell.post_only());
+        assert_eq!(sell.leverage(), UD64::ZERO);
+        assert_eq!(sell.expiry_block(), None);
+    }
+}
@@ -1,5 +1,6 @@
 use alloy::primitives::U256;
 use fastnum::{UD64, UD128};
+use thiserror::Error;
 
 use crate::{abi::dex::Exchange::OrderDesc, num, state};
 
@@ -33,6 +34,44 @@ pub enum RequestType {
     Change,
 }
 
+/// Effect of the order modification request on the tracked order,
+/// see [`OrderRequest::check_modification`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ModificationEffect {
+    /// Price of the order changes.
+    pub price_changed: bool,
+
+    /// Size of the order changes.
+    pub size_changed: bool,
+
+    /// Expiry block of the order changes.
+    pub expiry_changed: bool,
+
+    /// Order moves to the back of the queue at its price level:
+    /// on price change, size increase or renewal of the expired order.
+    pub loses_priority: bool,
+}
+
+/// Order modification request rejected by local validation,
+/// see [`OrderRequest::check_modification`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error)]
+pub enum ModificationError {
+    #[error("not an order change request")]
+    NotChange,
+
+    #[error("request targets another order")]
+    OrderMismatch,
+
+    #[error("close orders can not be changed")]
+    CloseOrder,
+
+    #[error("expired order needs new expiry")]
+    ExpiredNeedsNewExpiry,
+
+    #[error("request does not change the order")]
+    NoChange,
+}
+
 /// Request to post/modify an order.
 #[derive(Clone, derive_more::Debug)]
 pub struct OrderRequest {
@@ -95,6 +134,213 @@ impl OrderRequest {
         }
     }
 
+    /// Start building a limit order request: matches on placement and the rest
+    /// of it stays on the book.
+    pub fn limit(
+        request_id: RequestId,
+        perp_id: PerpetualId,
+        r#type: OrderType,
+        price: UD64,
+        size: UD64,
+    ) -> OrderBuilder<GoodTillCancel> {
+        OrderBuilder::new(request_id, perp_id, r#type, price, size)
+    }
+
+    /// Start building a post-only order request: rejected if it would match on placement.
+    pub fn limit_post_only(
+        request_id: RequestId,
+        perp_id: PerpetualId,
+        r#type: OrderType,
+        price: UD64,
+        size: UD64,
+    ) -> OrderBuilder<PostOnly> {
+        OrderBuilder::new(request_id, perp_id, r#type, price, size)
+    }
+
+    /// Start building an immediate-or-cancel order request: matches on placement
+    /// and the unfilled part gets cancelled.
+    pub fn ioc(
+        request_id: RequestId,
+        perp_id: PerpetualId,
+        r#type: OrderType,
+        price: UD64,
+        size: UD64,
+    ) -> OrderBuilder<ImmediateOrCancel> {
+        OrderBuilder::new(request_id, perp_id, r#type, price, size)
+    }
+
+    /// Start building a fill-or-kill order request: completely filled on placement
+    /// or rejected.
+    pub fn fok(
+        request_id: RequestId,
+        perp_id: PerpetualId,
+        r#type: OrderType,
+        price: UD64,
+        size: UD64,
+    ) -> OrderBuilder<FillOrKill> {
+        OrderBuilder::new(request_id, perp_id, r#type, price, size)
+    }
+
+    /// Create a request cancelling the existing order.
+    pub fn cancel(request_id: RequestId, perp_id: PerpetualId, order_id: OrderId) -> Self {
+        Self::new(
+            request_id,
+            perp_id,
+            RequestType::Cancel,
+            Some(order_id),
+            UD64::ZERO,
+            UD64::ZERO,
+            None,
+            false,
+            false,
+            false,
+            None,
+            UD64::ZERO,
+            None,
+            None,
+        )
+    }
+
+    /// Create a request changing price, size and expiry of the existing order.
+    ///
+    /// All parameters are set to the new values, unchanged ones have to repeat
+    /// the current values of the order. Use [`Self::check_modification`] to validate
+    /// the request against the tracked order before sending.
+    pub fn modify(
+        request_id: RequestId,
+        perp_id: PerpetualId,
+        order_id: OrderId,
+        new_price: UD64,
+        new_size: UD64,
+        new_expiry: Option<u64>,
+    ) -> Self {
+        Self::new(
+            request_id,
+            perp_id,
+            RequestType::Change,
+            Some(order_id),
+            new_price,
+            new_size,
+            new_expiry,
+            false,
+            false,
+            false,
+            None,
+            UD64::ZERO,
+            None,
+            None,
+        )
+    }
+
+    /// Validates the order change request against the tracked order at the given block,
+    /// applying the exchange rules locally, and returns the expected effect.
+    ///
+    /// Note that the request is sent in a later block than the check is made at, so
+    /// the order can expire or be changed by then.
+    pub fn check_modification(
+        &self,
+        order: &state::Order,
+        block_number: u64,
+    ) -> Result<ModificationEffect, ModificationError> {
+        if !matches!(self.r#type, RequestType::Change) {
+            return Err(ModificationError::NotChange);
+        }
+        if self.order_id != Some(order.order_id()) {
+            return Err(ModificationError::OrderMismatch);
+        }
+        if matches!(order.r#type(), OrderType::CloseLong | OrderType::CloseShort) {
+            return Err(ModificationError::CloseOrder);
+        }
+
+        let expiry_block = self.expiry_block.unwrap_or_default();
+        let is_expired = order.expiry_block() > 0 && order.expiry_block() < block_number;
+        let effect = ModificationEffect {
+            price_changed: self.price != order.price(),
+            size_changed: self.size != order.size(),
+            expiry_changed: expiry_block != order.expiry_block(),
+            loses_priority: self.price != order.price()
+                || self.size > order.size()
+                || (is_expired && expiry_block != order.expiry_block()),
+        };
+        if is_expired && !effect.expiry_changed {
+            return Err(ModificationError::ExpiredNeedsNewExpiry);
+        }
+        if !effect.price_changed && !effect.size_changed && !effect.expiry_changed {
+            return Err(ModificationError::NoChange);
+        }
+        Ok(effect)
+    }
+
+    /// Client-assigned ID of the request.
+    pub fn request_id(&self) -> RequestId {
+        self.request_id
+    }
+
+    /// ID of the perpetual contract.
+    pub fn perp_id(&self) -> PerpetualId {
+        self.perp_id
+    }
+
+    /// Type of the request.
+    pub fn r#type(&self) -> RequestType {
+        self.r#type
+    }
+
+    /// ID of the order to cancel/change.
+    pub fn order_id(&self) -> Option<OrderId> {
+        self.order_id
+    }
+
+    /// Limit price of the order.
+    pub fn price(&self) -> UD64 {
+        self.price
+    }
+
+    /// Size of the order.
+    pub fn size(&self) -> UD64 {
+        self.size
+    }
+
+    /// Block the order expires at.
+    pub fn expiry_block(&self) -> Option<u64> {
+        self.expiry_block
+    }
+
+    /// Indicates the order must not match on placement.
+    pub fn post_only(&self) -> bool {
+        self.post_only
+    }
+
+    /// Indicates the order must be completely filled on placement or rejected.
+    pub fn fill_or_kill(&self) -> bool {
+        self.fill_or_kill
+    }
+
+    /// Indicates the unfilled part of the order must not rest on the book.
+    pub fn immediate_or_cancel(&self) -> bool {
+        self.immediate_or_cancel
+    }
+
+    /// Maximal number of matches against resting orders.
+    pub fn max_matches(&self) -> Option<u32> {
+        self.max_matches
+    }
+
+    /// Leverage of the position the order opens/increases.
+    pub fn leverage(&self) -> UD64 {
+        self.leverage
+    }
+
+    /// Last block the request can be executed at.
+    pub fn last_exec_block(&self) -> Option<u64> {
+        self.last_exec_block
+    }
+
+    /// Amount of collateral for collateral operations.
+    pub fn amount(&self) -> Option<UD128> {
+        self.amount
+    }
+
     /// Prepare order request to execution.
     pub fn prepare(&self, exchange: &state::Exchange) -> OrderDesc {
         let perp = exchange
@@ -168,6 +414,17 @@ impl RequestType {
     }
 }
 
+impl From<OrderType> for RequestType {
+    fn from(value: OrderType) -> Self {
+        match value {
+            OrderType::OpenLong => RequestType::OpenLong,
+            OrderType::OpenShort => RequestType::OpenShort,
+            OrderType::CloseLong => RequestType::CloseLong,
+            OrderType::CloseShort => RequestType::CloseShort,
+        }
+    }
+}
+
 impl From<RequestType> for OrderType {
     fn from(value: RequestType) -> Self {
         match value {
@@ -179,3 +436,64 @@ impl From<RequestType> for OrderType {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use fastnum::udec64;
+
+    use super::*;
+
+    fn oid(n: u16) -> OrderId {
+        OrderId::new(n).expect("test order id must be non-zero")
+    }
+
+    fn order(r#type: OrderType) -> state::Order {
+        state::Order::for_l3_testing(r#type, udec64!(100), udec64!(2), 10, oid(1), 1)
+            .with_expiry_block(50)
+    }
+
+    #[test]
+    fn modification_checks() {
+        let modify = |price, size, expiry| OrderRequest::modify(1, 1, oid(1), price, size, expiry);
+        let open = order(OrderType::OpenLong);
+
+        // Size decrease keeps priority, increase loses it
+        let effect = modify(udec64!(100), udec64!(1), Some(50))
+            .check_modification(&open, 20)
+            .unwrap();
+        assert!(effect.size_changed && !effect.loses_priority);
+        let effect = modify(udec64!(100), udec64!(3), Some(50))
+            .check_modification(&open, 20)
+            .unwrap();
+        assert!(effect.loses_priority);
+        let effect = modify(udec64!(101), udec64!(2), Some(50))
+            .check_modification(&open, 20)
+            .unwrap();
+        assert!(effect.price_changed && effect.loses_priority);
+
+        assert_eq!(
+            modify(udec64!(100), udec64!(2), Some(50)).check_modification(&open, 20),
+            Err(ModificationError::NoChange)
+        );
+        assert_eq!(
+            modify(udec64!(100), udec64!(1), Some(50)).check_modification(&open, 60),
+            Err(ModificationError::ExpiredNeedsNewExpiry)
+        );
+        assert!(
+            modify(udec64!(100), udec64!(2), Some(100))
+                .check_modification(&open, 60)
+                .unwrap()
+                .loses_priority
+        );
+        assert_eq!(
+            modify(udec64!(100), udec64!(1), Some(50))
+                .check_modification(&order(OrderType::CloseShort), 20),
+            Err(ModificationError::CloseOrder)
+        );
+        assert_eq!(
+            OrderRequest::modify(1, 1, oid(2), udec64!(100), udec64!(1), Some(50))
+                .check_modification(&open, 20),
+            Err(ModificationError::OrderMismatch)
+        );
+    }
+}
@@ -0,0 +1,74 @@
+use std::fmt;
+
+/// Freeze status of the account (`FreezeStatusEnum` of the exchange).
+///
+/// The exchange rejects orders of frozen accounts and lets the administrator
+/// close their positions, see [`crate::state::Account::frozen_since`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum FreezeStatus {
+    /// Account is not frozen.
+    #[default]
+    NotFrozen,
+
+    /// Account is frozen with the non-zero status code set by the administrator.
+    Frozen(u8),
+}
+
+impl FreezeStatus {
+    pub fn is_frozen(&self) -> bool {
+        matches!(self, FreezeStatus::Frozen(_))
+    }
+}
+
+impl From<u8> for FreezeStatus {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => FreezeStatus::NotFrozen,
+            status => FreezeStatus::Frozen(status),
+        }
+    }
+}
+
+impl From<FreezeStatus> for u8 {
+    fn from(value: FreezeStatus) -> Self {
+        match value {
+            FreezeStatus::NotFrozen => 0,
+            FreezeStatus::Frozen(status) => status,
+        }
+    }
+}
+
+impl fmt::Display for FreezeStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FreezeStatus::NotFrozen => f.write_str("not frozen"),
+            FreezeStatus::Frozen(status) => write!(f, "frozen with status {status}"),
+        }
+    }
+}
+
+/// Result code of the order book operation reported by the exchange,
+/// e.g. with `OrderPostFailed`.
+///
+/// The exchange ABI does not publish the meaning of the codes, so they are kept
+/// as reported, compare against the ones observed for the deployed contract revision.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ResultCode(pub u16);
+
+impl ResultCode {
+    pub fn code(&self) -> u16 {
+        self.0
+    }
+}
+
+impl From<u16> for ResultCode {
+    fn from(value: u16) -> Self {
+        ResultCode(value)
+    }
+}
+
+impl fmt::Display for ResultCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "result code {}", self.0)
+    }
+}
@@ -0,0 +1,182 @@
+use alloy::{primitives::U256, providers::Provider};
+use dex_sdk::{
+    client::DexClient,
+    collateral::ApprovalPolicy,
+    error::DexError,
+    execution::AccountSigners,
+    state, testing,
+    types::{OrderRequest, OrderType},
+};
+use fastnum::{udec64, udec128};
+
+/// Tests the client tracking the state and submitting orders against it.
+#[tokio::test]
+async fn test_client_tracking() {
+    let exchange = testing::TestExchange::new().await;
+    let maker = exchange.account(0, 1_000_000).await;
+    let btc_perp = exchange.btc_perp().await;
+
+    let mut client = DexClient::signing(exchange.chain(), exchange.provider.clone(), maker.address);
+    assert!(client.book(btc_perp.id).await.is_err());
+
+    let snapshot = client.snapshot().with_accounts(vec![maker.address]);
+    let handle = client.track(snapshot, tokio::time::sleep).await.unwrap();
+    let mut events = client.events().unwrap();
+    assert_eq!(
+        client.account(maker.address).await.unwrap().unwrap().id(),
+        maker.id
+    );
+
+    let request = OrderRequest::limit_post_only(
+        1,
+        btc_perp.id,
+        OrderType::OpenLong,
+        udec64!(99000),
+        udec64!(0.1),
+    )
+    .with_leverage(udec64!(10))
+    .build();
+    client
+        .submit_orders(&[request])
+        .await
+        .unwrap()
+        .get_receipt()
+        .await
+        .unwrap();
+
+    while client.book(btc_perp.id).await.unwrap().best_bid().is_none() {
+        events.recv().await.unwrap();
+    }
+    assert_eq!(
+        client.book(btc_perp.id).await.unwrap().best_bid(),
+        Some((udec64!(99000), udec64!(0.1)))
+    );
+
+    // Tracking stops with the client
+    drop(client);
+    exchange.advance_blocks(2).await;
+    handle.await.unwrap().unwrap();
+}
+
+/// Tests routing of order batches to the signers of their accounts.
+#[tokio::test]
+async fn test_account_signers() {
+    let exchange = testing::TestExchange::new().await;
+    let alice = exchange.account(0, 1_000_000).await;
+    let bob = exchange.account(1, 1_000_000).await;
+    let btc_perp = exchange.btc_perp().await;
+
+    let snapshot = state::SnapshotBuilder::new(&exchange.chain(), exchange.provider.clone())
+        .with_accounts(vec![alice.address, bob.address])
+        .build()
+        .await
+        .unwrap();
+    // Anvil wallet of the test provider signs for all test accounts
+    let signers = AccountSigners::new(exchange.chain())
+        .with_signer(alice.id, alice.address, exchange.provider.clone())
+        .with_signer(bob.id, bob.address, exchange.provider.clone());
+    let bid = |id, price| {
+        OrderRequest::limit_post_only(id, btc_perp.id, OrderType::OpenLong, price, udec64!(0.1))
+            .with_leverage(udec64!(10))
+            .build()
+    };
+
+    let results = signers
+        .send_batches(
+            &snapshot,
+            vec![
+                (alice.id, vec![bid(1, udec64!(99000))]),
+                (bob.id, vec![bid(2, udec64!(98000))]),
+                (alice.id, vec![bid(3, udec64!(97000))]),
+            ],
+        )
+        .await;
+    let mut senders = vec![];
+    for (account_id, result) in results {
+        let receipt = result.unwrap().get_receipt().await.unwrap();
+        assert!(receipt.status());
+        senders.push((account_id, receipt.from));
+    }
+    assert_eq!(
+        senders,
+        vec![
+            (alice.id, alice.address),
+            (bob.id, bob.address),
+            (alice.id, alice.address)
+        ]
+    );
+    for account in [&alice, &bob] {
+        let sent = exchange
+            .provider
+            .get_transaction_count(account.address)
+            .await
+            .unwrap();
+        assert_eq!(signers.nonce(account.id).await, Some(sent));
+    }
+
+    assert!(matches!(
+        signers.send_orders(&snapshot, 99, &[]).await,
+        Err(DexError::InvalidRequest(_))
+    ));
+}
+
+/// Tests collateral approvals sent along with account creation and deposits.
+#[tokio::test]
+async fn test_collateral_approvals() {
+    let exchange = testing::TestExchange::new().await;
+    // Owner holds collateral tokens but has no account yet
+    let owner = exchange.owner;
+    let spender = *exchange.exchange.address();
+
+    let client = DexClient::signing(exchange.chain(), exchange.provider.clone(), owner);
+    client
+        .create_account(udec128!(1000))
+        .await
+        .unwrap()
+        .get_receipt()
+        .await
+        .unwrap();
+    // Exact approval is used up by the deposit
+    assert_eq!(client.allowances().cached(owner), Some(U256::ZERO));
+    assert_eq!(
+        exchange
+            .token
+            .allowance(owner, spender)
+            .call()
+            .await
+            .unwrap(),
+        U256::ZERO
+    );
+
+    let client = client.with_approval_policy(ApprovalPolicy::Infinite);
+    for _ in 0..2 {
+        client
+            .deposit(udec128!(250))
+            .await
+            .unwrap()
+            .get_receipt()
+            .await
+            .unwrap();
+    }
+    assert_eq!(client.allowances().cached(owner), Some(U256::MAX));
+    assert_eq!(
+        exchange
+            .token
+            .allowance(owner, spender)
+            .call()
+            .await
+            .unwrap(),
+        U256::MAX
+    );
+
+    let snapshot = client
+        .snapshot()
+        .with_accounts(vec![owner])
+        .build()
+        .await
+        .unwrap();
+    assert_eq!(
+        snapshot.account_by_address(owner).unwrap().balance(),
+        udec128!(1500)
+    );
+}
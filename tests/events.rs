@@ -1,6 +1,10 @@
+#![allow(clippy::bool_assert_comparison, clippy::map_flatten)]
+
 use std::{num::NonZeroU16, pin::pin, sync::Arc};
 
+use alloy::primitives::B256;
 use dex_sdk::{
+    error::DexError,
     state::{
         self, AccountEvent, AccountEventType, OrderEvent, OrderEventType, PositionEvent,
         PositionEventType,
@@ -154,7 +158,12 @@ async fn test_snapshot_and_events() {
     // Collect and (partially) validate produced events
     while let Some(block_events) = results_rx.recv().await {
         if let Some(block_events) = block_events {
-            for event in block_events.events().iter().map(|e| e.event()).flatten() {
+            for event in block_events
+                .events()
+                .iter()
+                .map(|e| block_events.events_of(e))
+                .flatten()
+            {
                 match event {
                     state::StateEvents::Account(AccountEvent {
                         account_id: 1,
@@ -199,6 +208,7 @@ async fn test_snapshot_and_events() {
                                 fill_size,
                                 fee,
                                 is_maker,
+                                ..
                             },
                     }) if *order_id == oid(1) => {
                         assert_eq!(*fill_price, udec64!(100100));
@@ -252,3 +262,65 @@ async fn test_snapshot_and_events() {
         assert_eq!(taker.positions().len(), 0);
     }
 }
+
+/// Tests resuming the event stream after the positions of resume tokens.
+#[tokio::test]
+async fn test_stream_resume() {
+    let exchange = testing::TestExchange::new().await;
+    let maker = exchange.account(0, 1_000_000).await;
+    let btc_perp = exchange.btc_perp().await;
+    let from = exchange.advance_blocks(1).await;
+
+    let request = types::OrderRequest::limit_post_only(
+        1,
+        btc_perp.id,
+        types::OrderType::OpenLong,
+        udec64!(99000),
+        udec64!(0.1),
+    )
+    .with_leverage(udec64!(10))
+    .build();
+    let receipt = btc_perp
+        .order(maker.id, request)
+        .await
+        .get_receipt()
+        .await
+        .unwrap();
+    let order_block = receipt.block_number.unwrap();
+    exchange.advance_blocks(1).await;
+
+    let chain = exchange.chain();
+    let resume =
+        |token| stream::resume(&chain, exchange.provider.clone(), token, tokio::time::sleep);
+
+    let mut blocks = pin!(resume(stream::ResumeToken::new(from, None, None)));
+    let (events, token) = loop {
+        let (events, token) = blocks.next().await.unwrap().unwrap();
+        if events.instant().block_number() == order_block {
+            break (events, token);
+        }
+    };
+    assert!(events.events().len() > 1);
+    assert_eq!(token.block_number, order_block);
+
+    // Resuming after the first event of the block yields the rest of it
+    let partial = stream::ResumeToken::new(
+        order_block,
+        Some(events.events()[0].log_index()),
+        token.block_hash,
+    );
+    let (rest, rest_token) = pin!(resume(partial)).next().await.unwrap().unwrap();
+    assert_eq!(rest.events().len(), events.events().len() - 1);
+    assert_eq!(rest_token, token);
+
+    // Whole block token resumes from the next block
+    let (next, _) = pin!(resume(token)).next().await.unwrap().unwrap();
+    assert_eq!(next.instant().block_number(), order_block + 1);
+
+    // Token of the reorganized block is rejected
+    let stale = stream::ResumeToken::new(order_block, None, Some(B256::repeat_byte(1)));
+    assert!(matches!(
+        pin!(resume(stale)).next().await.unwrap(),
+        Err(DexError::ResumeTokenMismatch(block)) if block == order_block
+    ));
+}
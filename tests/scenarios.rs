@@ -0,0 +1,213 @@
+use alloy::{eips::BlockId, providers::Provider, rpc::types::Filter, sol_types::SolEvent};
+use dex_sdk::{
+    abi::dex::Exchange::FundingEventCompleted,
+    state, testing,
+    types::{self, OrderSide::*},
+};
+use fastnum::{D64, udec64};
+
+/// Tests building a simple market scenario with the scenario drivers.
+#[tokio::test]
+async fn test_scenario_drivers() {
+    let exchange = testing::TestExchange::new().await;
+    let maker = exchange.account(0, 1_000_000).await;
+    let taker = exchange.account(1, 100_000).await;
+    let btc_perp = exchange.btc_perp().await;
+
+    exchange
+        .fill_book(
+            &btc_perp,
+            maker.id,
+            &[
+                (Ask, udec64!(100100), udec64!(0.1)),
+                (Ask, udec64!(100200), udec64!(0.1)),
+                (Bid, udec64!(99900), udec64!(0.1)),
+                (Bid, udec64!(99800), udec64!(0.1)),
+            ],
+        )
+        .await;
+    exchange
+        .cross(&btc_perp, taker.id, types::OrderSide::Bid, udec64!(0.15))
+        .await;
+
+    let snapshot = state::SnapshotBuilder::new(&exchange.chain(), exchange.provider.clone())
+        .with_all_positions()
+        .build()
+        .await
+        .unwrap();
+
+    let perp = snapshot.perpetuals().get(&btc_perp.id).unwrap();
+    assert_eq!(
+        perp.l3_book().best_ask(),
+        Some((udec64!(100200), udec64!(0.05)))
+    );
+    assert_eq!(
+        perp.l3_book().best_bid(),
+        Some((udec64!(99900), udec64!(0.1)))
+    );
+
+    let taker_pos = snapshot
+        .accounts()
+        .get(&taker.id)
+        .unwrap()
+        .positions()
+        .get(&btc_perp.id)
+        .unwrap();
+    assert_eq!(taker_pos.r#type(), state::PositionType::Long);
+    assert_eq!(taker_pos.size(), udec64!(0.15));
+
+    let block = snapshot.instant().block_number();
+    assert!(exchange.advance_blocks(10).await >= block + 10);
+}
+
+/// Tests deterministic block production control.
+#[tokio::test]
+async fn test_block_control() {
+    let exchange = testing::TestExchange::new().await;
+    exchange.set_mining_mode(testing::MiningMode::Manual).await;
+
+    let block = exchange.mine_block().await;
+    let timestamp = exchange
+        .provider
+        .get_block(BlockId::number(block))
+        .await
+        .unwrap()
+        .unwrap()
+        .header
+        .timestamp;
+
+    exchange.set_next_timestamp(timestamp + 100).await;
+    assert_eq!(exchange.mine_block().await, block + 1);
+    let next_timestamp = exchange
+        .provider
+        .get_block(BlockId::number(block + 1))
+        .await
+        .unwrap()
+        .unwrap()
+        .header
+        .timestamp;
+    assert_eq!(next_timestamp, timestamp + 100);
+
+    assert_eq!(exchange.advance_blocks(5).await, block + 6);
+    assert_eq!(
+        exchange.provider.get_block_number().await.unwrap(),
+        block + 6
+    );
+}
+
+/// Tests consecutive funding cycles driven by the perpetual contract helper.
+#[tokio::test]
+async fn test_funding_cycles() {
+    let exchange = testing::TestExchange::new().await;
+    let maker = exchange.account(0, 1_000_000).await;
+    let taker = exchange.account(1, 100_000).await;
+    let btc_perp = exchange.btc_perp().await;
+    exchange
+        .fill_book(&btc_perp, maker.id, &[(Ask, udec64!(100100), udec64!(0.1))])
+        .await;
+    exchange
+        .cross(&btc_perp, taker.id, types::OrderSide::Bid, udec64!(0.1))
+        .await;
+
+    let first = btc_perp.advance_funding_cycle(100).await;
+    assert!(exchange.provider.get_block_number().await.unwrap() >= first.event_block);
+    assert!(first.rate > D64::ZERO);
+
+    // Next cycle gets scheduled for the following funding event
+    let second = btc_perp.advance_funding_cycle(-50).await;
+    assert!(second.event_block > first.event_block);
+    assert!(second.rate < D64::ZERO);
+    assert!(exchange.provider.get_block_number().await.unwrap() >= second.event_block);
+
+    let snapshot = state::SnapshotBuilder::new(&exchange.chain(), exchange.provider.clone())
+        .build()
+        .await
+        .unwrap();
+    let perp = snapshot.perpetuals().get(&btc_perp.id).unwrap();
+    assert_eq!(perp.funding_rate(), second.rate);
+}
+
+/// Tests liquidation of the underwater position by the scenario driver.
+#[tokio::test]
+async fn test_liquidate() {
+    let exchange = testing::TestExchange::new().await;
+    let maker = exchange.account(0, 1_000_000).await;
+    let taker = exchange.account(1, 100_000).await;
+    let liquidator = exchange.account(2, 1_000_000).await;
+    let btc_perp = exchange.btc_perp().await;
+    exchange
+        .fill_book(&btc_perp, maker.id, &[(Ask, udec64!(100100), udec64!(0.1))])
+        .await;
+    exchange
+        .cross(&btc_perp, taker.id, types::OrderSide::Bid, udec64!(0.1))
+        .await;
+
+    // Mark price well below the liquidation price of the 10x long position
+    let snapshot = state::SnapshotBuilder::new(&exchange.chain(), exchange.provider.clone())
+        .with_all_positions()
+        .build()
+        .await
+        .unwrap();
+    let taker_pos = &snapshot.accounts()[&taker.id].positions()[&btc_perp.id];
+    assert!(taker_pos.liquidation_price() > udec64!(85000));
+    btc_perp.set_mark_price(udec64!(85000)).await;
+
+    exchange.liquidate(liquidator.id, taker.id).await;
+
+    let snapshot = state::SnapshotBuilder::new(&exchange.chain(), exchange.provider.clone())
+        .with_all_positions()
+        .build()
+        .await
+        .unwrap();
+    assert!(
+        snapshot
+            .accounts()
+            .get(&taker.id)
+            .is_none_or(|acc| !acc.positions().contains_key(&btc_perp.id))
+    );
+}
+
+/// Tests the funding event triggered by the scenario driver.
+#[tokio::test]
+async fn test_trigger_funding() {
+    let exchange = testing::TestExchange::new().await;
+    let maker = exchange.account(0, 1_000_000).await;
+    let taker = exchange.account(1, 100_000).await;
+    let btc_perp = exchange.btc_perp().await;
+    exchange
+        .fill_book(&btc_perp, maker.id, &[(Ask, udec64!(100100), udec64!(0.1))])
+        .await;
+    exchange
+        .cross(&btc_perp, taker.id, types::OrderSide::Bid, udec64!(0.1))
+        .await;
+    let start_block = exchange.provider.get_block_number().await.unwrap();
+
+    let event_block = exchange.trigger_funding(&btc_perp, 100).await;
+    assert!(exchange.provider.get_block_number().await.unwrap() >= event_block);
+
+    let logs = exchange
+        .provider
+        .get_logs(
+            &Filter::new()
+                .address(exchange.chain().exchange())
+                .event_signature(FundingEventCompleted::SIGNATURE_HASH)
+                .from_block(start_block + 1),
+        )
+        .await
+        .unwrap();
+    assert_eq!(logs.len(), 1);
+    let completed = logs[0].log_decode::<FundingEventCompleted>().unwrap().inner;
+    assert_eq!(completed.perpId.to::<types::PerpetualId>(), btc_perp.id);
+    assert_eq!(completed.fundingEventBlock.to::<u64>(), event_block);
+
+    let snapshot = state::SnapshotBuilder::new(&exchange.chain(), exchange.provider.clone())
+        .build()
+        .await
+        .unwrap();
+    let perp = snapshot.perpetuals().get(&btc_perp.id).unwrap();
+    let rate = perp
+        .funding_rate_converter()
+        .from_signed(completed.actualRatePct100k);
+    assert!(rate > D64::ZERO);
+    assert_eq!(perp.funding_rate(), rate);
+}
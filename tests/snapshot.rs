@@ -1,7 +1,25 @@
-use std::time::Instant;
+#![allow(
+    clippy::bool_assert_comparison,
+    clippy::into_iter_on_ref,
+    clippy::map_flatten
+)]
 
-use dex_sdk::{state, testing, types};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use alloy::{providers::Provider, sol_types::SolEvent};
+use dex_sdk::{
+    abi::dex::Exchange::AccountCreated,
+    error::DexError,
+    reconcile::{self, ReconcileConfig},
+    state, stream, testing, types,
+};
 use fastnum::{UD64, udec64, udec128};
+use futures::StreamExt;
+use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
 
 /// Tests the creation of exchange snapshot when perpetual order book is full.
 #[tokio::test]
@@ -194,3 +212,200 @@ async fn test_full_book_snapshot() {
         Some((udec64!(98990), udec64!(1), udec64!(99489.5)))
     );
 }
+
+/// Tests snapshot building progress reporting and cancellation.
+#[tokio::test]
+async fn test_snapshot_progress_and_cancellation() {
+    let exchange = testing::TestExchange::new().await;
+    let maker = exchange.account(0, 1_000_000).await;
+    let btc_perp = exchange.btc_perp().await;
+    exchange
+        .fill_book(
+            &btc_perp,
+            maker.id,
+            &[
+                (types::OrderSide::Ask, udec64!(100100), udec64!(0.1)),
+                (types::OrderSide::Bid, udec64!(99900), udec64!(0.1)),
+            ],
+        )
+        .await;
+
+    let progress = Arc::new(Mutex::new(vec![]));
+    let reported = progress.clone();
+    state::SnapshotBuilder::new(&exchange.chain(), exchange.provider.clone())
+        .with_orders_per_batch(1)
+        .with_all_positions()
+        .with_progress(move |p| reported.lock().unwrap().push(p))
+        .build()
+        .await
+        .unwrap();
+
+    let progress = progress.lock().unwrap().clone();
+    assert_eq!(
+        progress.first(),
+        Some(&state::SnapshotProgress::ExchangeInfo)
+    );
+    assert_eq!(progress.last(), Some(&state::SnapshotProgress::Done));
+    assert!(progress.contains(&state::SnapshotProgress::Perpetuals { total: 1 }));
+    assert!(progress.contains(&state::SnapshotProgress::Orders {
+        perpetual_id: btc_perp.id,
+        fetched: 2,
+        total: 2,
+    }));
+
+    let token = CancellationToken::new();
+    token.cancel();
+    let result = state::SnapshotBuilder::new(&exchange.chain(), exchange.provider.clone())
+        .with_cancellation(token)
+        .build()
+        .await;
+    assert!(matches!(result, Err(DexError::Cancelled)));
+}
+
+/// Tests resuming of the interrupted snapshot building at the original block.
+#[tokio::test]
+async fn test_snapshot_resume() {
+    let exchange = testing::TestExchange::new().await;
+    let maker = exchange.account(0, 1_000_000).await;
+    let btc_perp = exchange.btc_perp().await;
+    exchange
+        .fill_book(
+            &btc_perp,
+            maker.id,
+            &[
+                (types::OrderSide::Ask, udec64!(100100), udec64!(0.1)),
+                (types::OrderSide::Ask, udec64!(100200), udec64!(0.1)),
+                (types::OrderSide::Bid, udec64!(99900), udec64!(0.1)),
+            ],
+        )
+        .await;
+
+    // Interrupt building after the first batch of orders
+    let token = CancellationToken::new();
+    let cancel = token.clone();
+    let partial = state::SnapshotBuilder::new(&exchange.chain(), exchange.provider.clone())
+        .with_orders_per_batch(1)
+        .with_progress(move |p| {
+            if let state::SnapshotProgress::Orders { fetched: 1, .. } = p {
+                cancel.cancel();
+            }
+        })
+        .with_cancellation(token)
+        .build_resumable()
+        .await
+        .unwrap_err();
+    assert!(matches!(partial.error(), DexError::Cancelled));
+    assert!(partial.completed_perpetuals().is_empty());
+    assert!(partial.order_batches(btc_perp.id) >= 1);
+    let block_number = partial.block_number().unwrap();
+
+    // Orders placed after the interruption are not part of the resumed snapshot
+    exchange
+        .fill_book(
+            &btc_perp,
+            maker.id,
+            &[(types::OrderSide::Bid, udec64!(99800), udec64!(0.1))],
+        )
+        .await;
+
+    let progress = Arc::new(Mutex::new(vec![]));
+    let reported = progress.clone();
+    let resumed = state::SnapshotBuilder::new(&exchange.chain(), exchange.provider.clone())
+        .with_orders_per_batch(1)
+        .with_progress(move |p| reported.lock().unwrap().push(p))
+        .resume_from(*partial)
+        .build()
+        .await
+        .unwrap();
+    assert_eq!(resumed.instant().block_number(), block_number);
+    let book = resumed.perpetuals()[&btc_perp.id].l3_book();
+    assert_eq!(book.ask_orders().count(), 2);
+    assert_eq!(book.bid_orders().count(), 1);
+
+    // Already fetched batches are not fetched again
+    let progress = progress.lock().unwrap().clone();
+    assert!(!progress.contains(&state::SnapshotProgress::Orders {
+        perpetual_id: btc_perp.id,
+        fetched: 0,
+        total: 3,
+    }));
+}
+
+/// Tests the compatibility check of the deployed exchange with the bundled ABI.
+#[tokio::test]
+async fn test_verify_revision() {
+    let exchange = testing::TestExchange::new().await;
+    let snap = state::SnapshotBuilder::new(&exchange.chain(), exchange.provider.clone())
+        .build()
+        .await
+        .unwrap();
+    snap.verify_revision(&exchange.provider).await.unwrap();
+}
+
+/// Tests detection and hot swap of the diverged state by the reconciliation job.
+#[tokio::test]
+async fn test_reconcile_hot_swap() {
+    let exchange = testing::TestExchange::new().await;
+    let maker = exchange.account(0, 1_000_000).await;
+    let btc_perp = exchange.btc_perp().await;
+
+    let mut live = state::SnapshotBuilder::new(&exchange.chain(), exchange.provider.clone())
+        .with_accounts(vec![maker.address])
+        .build()
+        .await
+        .unwrap();
+    exchange
+        .fill_book(
+            &btc_perp,
+            maker.id,
+            &[(types::OrderSide::Ask, udec64!(100100), udec64!(0.1))],
+        )
+        .await;
+
+    // Catch up skipping order events, so the live state misses the placed order
+    let latest = exchange.provider.get_block_number().await.unwrap();
+    let chain = exchange.chain();
+    let events = stream::raw_filtered(
+        &chain,
+        exchange.provider.clone(),
+        types::StateInstant::new(live.instant().block_number() + 1, 0),
+        stream::EventFilter::all().with_events([AccountCreated::SIGNATURE_HASH]),
+        tokio::time::sleep,
+    );
+    futures::pin_mut!(events);
+    while live.instant().block_number() < latest {
+        live.apply_events(&events.next().await.unwrap().unwrap())
+            .unwrap();
+    }
+    let live = Arc::new(RwLock::new(live));
+
+    let config = ReconcileConfig::new(Duration::from_millis(10)).with_hot_swap();
+    let (mut reports, handle) = reconcile::run(
+        live.clone(),
+        exchange.provider.clone(),
+        config,
+        tokio::time::sleep,
+    );
+
+    let report = reports.recv().await.unwrap().unwrap();
+    assert_eq!(report.instant.block_number(), latest);
+    assert_eq!(report.diff.orders.len(), 1);
+    assert!(matches!(
+        report.diff.orders[0].change,
+        state::Change::Added(order) if order.account_id() == maker.id
+    ));
+    assert_eq!(report.swapped_at.map(|i| i.block_number()), Some(latest));
+    assert_eq!(
+        live.read().await.perpetuals()[&btc_perp.id]
+            .l3_book()
+            .best_ask(),
+        Some((udec64!(100100), udec64!(0.1)))
+    );
+
+    let report = reports.recv().await.unwrap().unwrap();
+    assert!(report.diff.is_empty());
+    assert!(report.swapped_at.is_none());
+
+    drop(reports);
+    handle.await.unwrap();
+}